@@ -0,0 +1,223 @@
+#ifndef RIPSET_H
+#define RIPSET_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The set stores CIDR blocks and address ranges
+#define RIPSET_NFT_INTERVAL 1
+
+// Elements keep packet and byte counters
+#define RIPSET_NFT_COUNTERS (1 << 1)
+
+// Adjacent and overlapping ranges are merged on insertion
+#define RIPSET_NFT_AUTO_MERGE (1 << 2)
+
+// Error codes returned by the C functions. The values are stable.
+typedef enum RipsetErrorCode {
+  // A null pointer, text that is not UTF-8, or an invalid entry, type,
+  // family or option
+  RIPSET_ERROR_CODE_INVALID_ARGUMENT = -1,
+  // An invalid set or table name
+  RIPSET_ERROR_CODE_INVALID_NAME = -2,
+  // The set or table does not exist
+  RIPSET_ERROR_CODE_SET_NOT_FOUND = -3,
+  // The set is referenced and cannot be destroyed
+  RIPSET_ERROR_CODE_SET_IN_USE = -4,
+  // The set exists with a different definition
+  RIPSET_ERROR_CODE_SET_MISMATCH = -5,
+  // The entry is not in the set
+  RIPSET_ERROR_CODE_ENTRY_NOT_FOUND = -6,
+  // The entry is already in the set
+  RIPSET_ERROR_CODE_ENTRY_EXISTS = -7,
+  // The set is full
+  RIPSET_ERROR_CODE_SET_FULL = -8,
+  // CAP_NET_ADMIN is required
+  RIPSET_ERROR_CODE_PERMISSION_DENIED = -9,
+  // The entry does not fit the type of the set
+  RIPSET_ERROR_CODE_TYPE_MISMATCH = -10,
+  // The kernel lacks a feature the request needs
+  RIPSET_ERROR_CODE_KERNEL_UNSUPPORTED = -11,
+  // The table is owned by another process
+  RIPSET_ERROR_CODE_TABLE_OWNED = -12,
+  // The kernel did not answer in time
+  RIPSET_ERROR_CODE_TIMEOUT = -13,
+  // The kernel refused the request, see `ripset_last_errno`
+  RIPSET_ERROR_CODE_KERNEL = -14,
+  // The netlink socket failed
+  RIPSET_ERROR_CODE_IO = -15,
+  // The kernel answered with an unexpected message
+  RIPSET_ERROR_CODE_PROTOCOL = -16,
+  // The platform is not Linux
+  RIPSET_ERROR_CODE_UNSUPPORTED_PLATFORM = -17,
+  // Notifications were lost
+  RIPSET_ERROR_CODE_EVENTS_LOST = -18,
+  // A bug in ripset
+  RIPSET_ERROR_CODE_INTERNAL = -19,
+} RipsetErrorCode;
+
+// Called with each entry of a listed set, as text, and the `ctx` pointer
+// given to the listing function. The text is only valid during the call.
+typedef void (*RipsetEntryCallback)(const char *entry, void *ctx);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Message of the last failure on the calling thread, or NULL if nothing
+// failed yet. The string stays valid until the next failure on the thread.
+const char *ripset_last_error(void);
+
+// The errno the kernel answered the last failed request of the calling
+// thread with, or 0 if it was not a kernel error.
+int ripset_last_errno(void);
+
+// Create an ipset. `options` holds the type and the options as in
+// `ipset create`, e.g. `hash:net family inet6 timeout 300`.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_ipset_create(const char *setname, const char *options);
+
+// Destroy an ipset.
+//
+// # Safety
+//
+// `setname` must be a NUL-terminated string.
+int ripset_ipset_destroy(const char *setname);
+
+// Remove all entries from an ipset.
+//
+// # Safety
+//
+// `setname` must be a NUL-terminated string.
+int ripset_ipset_flush(const char *setname);
+
+// Exchange the contents of two ipsets.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_ipset_swap(const char *setname1, const char *setname2);
+
+// Add an entry to an ipset. `entry` is written as in `ipset add`, e.g.
+// `192.0.2.0/24,tcp:80 timeout 60`.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_ipset_add(const char *setname, const char *entry);
+
+// Delete an entry from an ipset.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_ipset_del(const char *setname, const char *entry);
+
+// Test whether an entry is in an ipset: 1 if it is, 0 if not.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_ipset_test(const char *setname, const char *entry);
+
+// Call `callback` with each entry of an ipset. Returns the number of
+// entries.
+//
+// # Safety
+//
+// `setname` must be a NUL-terminated string, and `callback` must be safe
+// to call with `ctx`.
+int ripset_ipset_list(const char *setname, RipsetEntryCallback callback, void *ctx);
+
+// Create an nftables table. `family` is `ip`, `ip6`, `inet`, `arp`,
+// `bridge` or `netdev`.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_create_table(const char *family, const char *table);
+
+// Delete an nftables table with everything in it.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_delete_table(const char *family, const char *table);
+
+// Create an nftables set. `set_type` is an nft type such as `ipv4_addr`
+// or `ipv4_addr . inet_service`, `flags` a combination of the
+// `RIPSET_NFT_*` flags, and `timeout` the default element timeout in
+// seconds, or 0 for none.
+//
+// # Safety
+//
+// The string arguments must be NUL-terminated strings.
+int ripset_nft_create_set(const char *family,
+                          const char *table,
+                          const char *setname,
+                          const char *set_type,
+                          uint32_t flags,
+                          uint32_t timeout);
+
+// Delete an nftables set.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_delete_set(const char *family, const char *table, const char *setname);
+
+// Remove all elements from an nftables set.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_flush(const char *family, const char *table, const char *setname);
+
+// Add an element to an nftables set. `entry` is written as in nft, e.g.
+// `192.0.2.0/24` or `192.0.2.1 . 80`.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_add(const char *family, const char *table, const char *setname, const char *entry);
+
+// Delete an element from an nftables set.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_del(const char *family, const char *table, const char *setname, const char *entry);
+
+// Test whether an element is in an nftables set: 1 if it is, 0 if not.
+//
+// # Safety
+//
+// The arguments must be NUL-terminated strings.
+int ripset_nft_test(const char *family, const char *table, const char *setname, const char *entry);
+
+// Call `callback` with each element of an nftables set. Returns the number
+// of elements.
+//
+// # Safety
+//
+// The string arguments must be NUL-terminated strings, and `callback` must
+// be safe to call with `ctx`.
+int ripset_nft_list(const char *family,
+                    const char *table,
+                    const char *setname,
+                    RipsetEntryCallback callback,
+                    void *ctx);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RIPSET_H */
@@ -0,0 +1,1229 @@
+//! Async versions of the set operations for the tokio runtime.
+//!
+//! Each `*_async` function returns an [`Operation`], a future resolving to
+//! the result of the matching blocking function, and [`AsyncIpsetSession`]
+//! and [`AsyncNftSession`] mirror the blocking sessions. The netlink sockets
+//! of an operation are non-blocking and wait for answers on the reactor of
+//! the runtime, so they never stall the tasks of an async daemon.
+//! [`AsyncNftMonitor`] is a `Stream` of nftables changes.
+//!
+//! An operation starts when its future is first polled. Dropping the future
+//! cancels it and closes its sockets; a change already sent may or may not
+//! have been applied by then. Answers left on the socket of a session by a
+//! cancelled operation are skipped by the next one.
+//!
+//! [`with_timeout`](crate::with_timeout) and [`with_netns`](crate::with_netns)
+//! apply to the operations created within them, whichever thread polls them.
+//! The operations must be polled within a tokio runtime with IO enabled;
+//! polled outside of a runtime, they fail with an error.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::net::IpAddr;
+//! use ripset::{ipset_add_async, ipset_test_async};
+//!
+//! # async fn example() -> ripset::Result<()> {
+//! let addr: IpAddr = "192.168.1.1".parse().unwrap();
+//! if !ipset_test_async("myset", addr).await? {
+//!     ipset_add_async("myset", addr).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+// The operations are not supported off Linux, where their arguments go unused
+#![cfg_attr(not(target_os = "linux"), allow(unused))]
+
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+#[cfg(unix)]
+use std::task::ready;
+use std::task::{Context, Poll};
+
+#[cfg(unix)]
+use futures_core::Stream;
+#[cfg(unix)]
+use tokio::io::Interest;
+#[cfg(unix)]
+use tokio::io::unix::AsyncFd;
+use tokio::sync::Mutex;
+
+#[cfg(target_os = "linux")]
+use crate::netlink::IoContext;
+use crate::{
+    BackendCapabilities, Entry, IpEntry, IpSetCreateOptions, IpSetInfo, IpSetProtocol,
+    IpSetTestOptions, IpSetType, IpSetTypeRevisions, IpsetSession, NftEvent, NftFamily,
+    NftMapConflict, NftMonitor, NftOwnedTable, NftSession, NftSetCreateOptions, NftSetElement,
+    NftSetInfo, NftSetReference, NftSetSummary, NftTransaction, Result, RipsetError,
+};
+
+/// A set operation waiting for the kernel on the reactor of the tokio
+/// runtime.
+pub struct Operation<T> {
+    /// The namespace and deadline the operation was created with.
+    #[cfg(target_os = "linux")]
+    context: IoContext,
+    future: Pin<Box<dyn Future<Output = Result<T>> + Send>>,
+}
+
+impl<T> Operation<T> {
+    fn new(future: impl Future<Output = Result<T>> + Send + 'static) -> Self {
+        #[cfg(target_os = "linux")]
+        match IoContext::current() {
+            Ok(context) => Self {
+                context,
+                future: Box::pin(future),
+            },
+            Err(e) => Self {
+                context: IoContext::default(),
+                future: Box::pin(async move { Err(e.into()) }),
+            },
+        }
+        #[cfg(not(target_os = "linux"))]
+        Self {
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<T> Future for Operation<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        #[cfg(target_os = "linux")]
+        return this.context.enter(|| this.future.as_mut().poll(cx));
+        #[cfg(not(target_os = "linux"))]
+        this.future.as_mut().poll(cx)
+    }
+}
+
+/// Await `$op`, the async version of a blocking function, which only
+/// exists on Linux; other platforms fail like the blocking stubs.
+macro_rules! run {
+    ($op:expr) => {{
+        #[cfg(target_os = "linux")]
+        let result = $op.await;
+        #[cfg(not(target_os = "linux"))]
+        let result = Err(RipsetError::UnsupportedPlatform);
+        result
+    }};
+}
+
+/// Register `inner` with the reactor of the current tokio runtime, which
+/// must have IO enabled.
+#[cfg(unix)]
+pub(crate) fn reactor<T: AsRawFd>(inner: T) -> io::Result<AsyncFd<T>> {
+    tokio::runtime::Handle::try_current().map_err(io::Error::other)?;
+    AsyncFd::with_interest(inner, Interest::READABLE)
+}
+
+// ipset operations
+
+/// Create an ipset, like [`ipset_create`](crate::ipset_create).
+pub fn ipset_create_async(setname: &str, options: &IpSetCreateOptions) -> Operation<()> {
+    let (setname, options) = (setname.to_string(), options.clone());
+    Operation::new(async move { run!(crate::ipset::ipset_create_async(&setname, &options)) })
+}
+
+/// Create an ipset unless it exists, like
+/// [`ipset_create_exist`](crate::ipset_create_exist).
+pub fn ipset_create_exist_async(setname: &str, options: &IpSetCreateOptions) -> Operation<()> {
+    let (setname, options) = (setname.to_string(), options.clone());
+    Operation::new(async move { run!(crate::ipset::ipset_create_exist_async(&setname, &options)) })
+}
+
+/// Make sure an ipset exists with the given options, like
+/// [`ipset_ensure_set`](crate::ipset_ensure_set).
+pub fn ipset_ensure_set_async(setname: &str, options: &IpSetCreateOptions) -> Operation<()> {
+    let (setname, options) = (setname.to_string(), options.clone());
+    Operation::new(async move { run!(crate::ipset::ipset_ensure_set_async(&setname, &options)) })
+}
+
+/// Destroy an ipset, like [`ipset_destroy`](crate::ipset_destroy).
+pub fn ipset_destroy_async(setname: &str) -> Operation<()> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_destroy_async(&setname)) })
+}
+
+/// Destroy all ipsets, like [`ipset_destroy_all`](crate::ipset_destroy_all).
+pub fn ipset_destroy_all_async() -> Operation<()> {
+    Operation::new(async { run!(crate::ipset::ipset_destroy_all_async()) })
+}
+
+/// Remove all entries from an ipset, like [`ipset_flush`](crate::ipset_flush).
+pub fn ipset_flush_async(setname: &str) -> Operation<()> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_flush_async(&setname)) })
+}
+
+/// Remove all entries from all ipsets, like
+/// [`ipset_flush_all`](crate::ipset_flush_all).
+pub fn ipset_flush_all_async() -> Operation<()> {
+    Operation::new(async { run!(crate::ipset::ipset_flush_all_async()) })
+}
+
+/// Swap the contents of two ipsets, like [`ipset_swap`](crate::ipset_swap).
+pub fn ipset_swap_async(setname1: &str, setname2: &str) -> Operation<()> {
+    let (setname1, setname2) = (setname1.to_string(), setname2.to_string());
+    Operation::new(async move { run!(crate::ipset::ipset_swap_async(&setname1, &setname2)) })
+}
+
+/// Replace the contents of an ipset, like
+/// [`ipset_replace`](crate::ipset_replace).
+pub fn ipset_replace_async<I, E>(
+    setname: &str,
+    entries: I,
+    options: &IpSetCreateOptions,
+) -> Operation<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let (setname, options) = (setname.to_string(), options.clone());
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move {
+        run!(crate::ipset::ipset_replace_async(
+            &setname, entries, &options
+        ))
+    })
+}
+
+/// Add an entry to an ipset, like [`ipset_add`](crate::ipset_add).
+pub fn ipset_add_async<E: Into<Entry>>(setname: &str, entry: E) -> Operation<()> {
+    let (setname, entry) = (setname.to_string(), entry.into());
+    Operation::new(async move { run!(crate::ipset::ipset_add_async(&setname, entry)) })
+}
+
+/// Add or refresh an entry of an ipset, like
+/// [`ipset_add_exist`](crate::ipset_add_exist).
+pub fn ipset_add_exist_async<E: Into<Entry>>(setname: &str, entry: E) -> Operation<()> {
+    let (setname, entry) = (setname.to_string(), entry.into());
+    Operation::new(async move { run!(crate::ipset::ipset_add_exist_async(&setname, entry)) })
+}
+
+/// Add many entries to an ipset, like [`ipset_add_many`](crate::ipset_add_many).
+pub fn ipset_add_many_async<I, E>(setname: &str, entries: I) -> Operation<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let setname = setname.to_string();
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move { run!(crate::ipset::ipset_add_many_async(&setname, entries)) })
+}
+
+/// Make sure an entry is in an ipset, like
+/// [`ipset_ensure_entry`](crate::ipset_ensure_entry).
+pub fn ipset_ensure_entry_async<E: Into<Entry>>(setname: &str, entry: E) -> Operation<bool> {
+    let (setname, entry) = (setname.to_string(), entry.into());
+    Operation::new(async move { run!(crate::ipset::ipset_ensure_entry_async(&setname, entry)) })
+}
+
+/// Delete an entry from an ipset, like [`ipset_del`](crate::ipset_del).
+pub fn ipset_del_async<E: Into<Entry>>(setname: &str, entry: E) -> Operation<()> {
+    let (setname, entry) = (setname.to_string(), entry.into());
+    Operation::new(async move { run!(crate::ipset::ipset_del_async(&setname, entry)) })
+}
+
+/// Delete many entries from an ipset, like [`ipset_del_many`](crate::ipset_del_many).
+pub fn ipset_del_many_async<I, E>(setname: &str, entries: I) -> Operation<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let setname = setname.to_string();
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move { run!(crate::ipset::ipset_del_many_async(&setname, entries)) })
+}
+
+/// Test if an entry exists in an ipset, like [`ipset_test`](crate::ipset_test).
+pub fn ipset_test_async<E: Into<Entry>>(setname: &str, entry: E) -> Operation<bool> {
+    let (setname, entry) = (setname.to_string(), entry.into());
+    Operation::new(async move { run!(crate::ipset::ipset_test_async(&setname, entry)) })
+}
+
+/// Test an entry of an ipset with options, like
+/// [`ipset_test_with_options`](crate::ipset_test_with_options).
+pub fn ipset_test_with_options_async<E: Into<Entry>>(
+    setname: &str,
+    entry: E,
+    options: &IpSetTestOptions,
+) -> Operation<bool> {
+    let (setname, entry, options) = (setname.to_string(), entry.into(), options.clone());
+    Operation::new(async move {
+        run!(crate::ipset::ipset_test_with_options_async(
+            &setname, entry, &options
+        ))
+    })
+}
+
+/// Test many entries of an ipset, like [`ipset_test_many`](crate::ipset_test_many).
+pub fn ipset_test_many_async<I, E>(setname: &str, entries: I) -> Operation<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let setname = setname.to_string();
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move { run!(crate::ipset::ipset_test_many_async(&setname, entries)) })
+}
+
+/// Find the entry of an ipset matching an address, like
+/// [`ipset_test_match`](crate::ipset_test_match).
+pub fn ipset_test_match_async(setname: &str, addr: IpAddr) -> Operation<Option<IpEntry>> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_test_match_async(&setname, addr)) })
+}
+
+/// Check if an ipset exists, like [`ipset_exists`](crate::ipset_exists).
+pub fn ipset_exists_async(setname: &str) -> Operation<bool> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_exists_async(&setname)) })
+}
+
+/// List the addresses in an ipset, like [`ipset_list`](crate::ipset_list).
+pub fn ipset_list_async(setname: &str) -> Operation<Vec<IpAddr>> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_list_async(&setname)) })
+}
+
+/// List the networks in an ipset, like
+/// [`ipset_list_nets`](crate::ipset_list_nets).
+#[cfg(feature = "ipnet")]
+pub fn ipset_list_nets_async(setname: &str) -> Operation<Vec<ipnet::IpNet>> {
+    let setname = setname.to_string();
+    Operation::new(async move {
+        run!(crate::ipset::ipset_list_entries_async(&setname)).map(crate::net::entry_nets)
+    })
+}
+
+/// List the entries in an ipset, like
+/// [`ipset_list_entries`](crate::ipset_list_entries).
+pub fn ipset_list_entries_async(setname: &str) -> Operation<Vec<Entry>> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_list_entries_async(&setname)) })
+}
+
+/// List the headers of all ipsets, like
+/// [`ipset_list_sets`](crate::ipset_list_sets).
+pub fn ipset_list_sets_async() -> Operation<Vec<IpSetInfo>> {
+    Operation::new(async { run!(crate::ipset::ipset_list_sets_async()) })
+}
+
+/// Zero the counters of every entry of an ipset, like
+/// [`ipset_reset_counters`](crate::ipset_reset_counters).
+pub fn ipset_reset_counters_async(setname: &str) -> Operation<Vec<Entry>> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_reset_counters_async(&setname)) })
+}
+
+/// Zero the counters of one entry of an ipset, like
+/// [`ipset_reset_entry_counters`](crate::ipset_reset_entry_counters).
+pub fn ipset_reset_entry_counters_async<E: Into<Entry>>(setname: &str, entry: E) -> Operation<()> {
+    let (setname, entry) = (setname.to_string(), entry.into());
+    Operation::new(async move {
+        run!(crate::ipset::ipset_reset_entry_counters_async(
+            &setname, entry
+        ))
+    })
+}
+
+/// Count the entries in an ipset, like [`ipset_count`](crate::ipset_count).
+pub fn ipset_count_async(setname: &str) -> Operation<u32> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_count_async(&setname)) })
+}
+
+/// Get information about an ipset, like [`ipset_info`](crate::ipset_info).
+pub fn ipset_info_async(setname: &str) -> Operation<IpSetInfo> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_info_async(&setname)) })
+}
+
+/// Count the references to an ipset, like
+/// [`ipset_references`](crate::ipset_references).
+pub fn ipset_references_async(setname: &str) -> Operation<u32> {
+    let setname = setname.to_string();
+    Operation::new(async move { run!(crate::ipset::ipset_references_async(&setname)) })
+}
+
+/// Query the ipset protocol versions, like [`ipset_protocol`](crate::ipset_protocol).
+pub fn ipset_protocol_async() -> Operation<IpSetProtocol> {
+    Operation::new(async { run!(crate::ipset::ipset_protocol_async()) })
+}
+
+/// List the set types the kernel supports, like
+/// [`ipset_supported_types`](crate::ipset_supported_types).
+pub fn ipset_supported_types_async() -> Operation<Vec<IpSetTypeRevisions>> {
+    Operation::new(async { run!(crate::ipset::ipset_supported_types_async()) })
+}
+
+/// Query the revisions of a set type, like
+/// [`ipset_type_revisions`](crate::ipset_type_revisions).
+pub fn ipset_type_revisions_async(set_type: IpSetType) -> Operation<Option<IpSetTypeRevisions>> {
+    Operation::new(async move { run!(crate::ipset::ipset_type_revisions_async(set_type)) })
+}
+
+/// Ask which optional features ipsets support, like
+/// [`ipset_capabilities`](crate::ipset_capabilities).
+pub fn ipset_capabilities_async() -> Operation<BackendCapabilities> {
+    Operation::new(async { run!(crate::ipset::ipset_capabilities_async()) })
+}
+
+// nftables operations
+
+/// Create an nftables table, like [`nftset_create_table`](crate::nftset_create_table).
+pub fn nftset_create_table_async(family: NftFamily, table: &str) -> Operation<()> {
+    let table = table.to_string();
+    Operation::new(async move { run!(crate::nftset::nftset_create_table_async(family, &table)) })
+}
+
+/// Create an nftables table owned by a socket, like
+/// [`nftset_create_table_owned`](crate::nftset_create_table_owned).
+pub fn nftset_create_table_owned_async(family: NftFamily, table: &str) -> Operation<NftOwnedTable> {
+    let table = table.to_string();
+    Operation::new(async move {
+        run!(crate::nftset::nftset_create_table_owned_async(
+            family, &table
+        ))
+    })
+}
+
+/// Make sure an nftables table exists, like
+/// [`nftset_ensure_table`](crate::nftset_ensure_table).
+pub fn nftset_ensure_table_async(family: NftFamily, table: &str) -> Operation<()> {
+    let table = table.to_string();
+    Operation::new(async move { run!(crate::nftset::nftset_ensure_table_async(family, &table)) })
+}
+
+/// Delete an nftables table, like [`nftset_delete_table`](crate::nftset_delete_table).
+pub fn nftset_delete_table_async(family: NftFamily, table: &str) -> Operation<()> {
+    let table = table.to_string();
+    Operation::new(async move { run!(crate::nftset::nftset_delete_table_async(family, &table)) })
+}
+
+/// Flush all sets of an nftables table, like
+/// [`nftset_flush_table`](crate::nftset_flush_table).
+pub fn nftset_flush_table_async(family: NftFamily, table: &str) -> Operation<()> {
+    let table = table.to_string();
+    Operation::new(async move { run!(crate::nftset::nftset_flush_table_async(family, &table)) })
+}
+
+/// Find the socket owning an nftables table, like
+/// [`nftset_table_owner`](crate::nftset_table_owner).
+pub fn nftset_table_owner_async(family: NftFamily, table: &str) -> Operation<Option<u32>> {
+    let table = table.to_string();
+    Operation::new(async move { run!(crate::nftset::nftset_table_owner_async(family, &table)) })
+}
+
+/// List the tables of a family, like [`nftset_list_tables`](crate::nftset_list_tables).
+pub fn nftset_list_tables_async(family: NftFamily) -> Operation<Vec<String>> {
+    Operation::new(async move { run!(crate::nftset::nftset_list_tables_async(family)) })
+}
+
+/// List the tables of all families, like
+/// [`nftset_list_tables_all`](crate::nftset_list_tables_all).
+pub fn nftset_list_tables_all_async() -> Operation<Vec<(NftFamily, String)>> {
+    Operation::new(async { run!(crate::nftset::nftset_list_tables_all_async()) })
+}
+
+/// List the sets of an nftables table, like
+/// [`nftset_list_sets`](crate::nftset_list_sets).
+pub fn nftset_list_sets_async(family: NftFamily, table: &str) -> Operation<Vec<NftSetSummary>> {
+    let table = table.to_string();
+    Operation::new(async move { run!(crate::nftset::nftset_list_sets_async(family, &table)) })
+}
+
+/// Create an nftables set, like [`nftset_create_set`](crate::nftset_create_set).
+pub fn nftset_create_set_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Operation<()> {
+    let (table, setname, options) = (table.to_string(), setname.to_string(), options.clone());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_create_set_async(
+            family, &table, &setname, &options
+        ))
+    })
+}
+
+/// Make sure an nftables set exists with the given options, like
+/// [`nftset_ensure_set`](crate::nftset_ensure_set).
+pub fn nftset_ensure_set_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Operation<()> {
+    let (table, setname, options) = (table.to_string(), setname.to_string(), options.clone());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_ensure_set_async(
+            family, &table, &setname, &options
+        ))
+    })
+}
+
+/// Delete an nftables set, like [`nftset_delete_set`](crate::nftset_delete_set).
+pub fn nftset_delete_set_async(family: NftFamily, table: &str, setname: &str) -> Operation<()> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_delete_set_async(
+            family, &table, &setname
+        ))
+    })
+}
+
+/// Remove all elements from an nftables set, like [`nftset_flush`](crate::nftset_flush).
+pub fn nftset_flush_async(family: NftFamily, table: &str, setname: &str) -> Operation<()> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move { run!(crate::nftset::nftset_flush_async(family, &table, &setname)) })
+}
+
+/// Rename an nftables set, like [`nftset_rename`](crate::nftset_rename).
+pub fn nftset_rename_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    newname: &str,
+) -> Operation<()> {
+    let (table, setname, newname) = (table.to_string(), setname.to_string(), newname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_rename_async(
+            family, &table, &setname, &newname
+        ))
+    })
+}
+
+/// Swap the contents of two nftables sets, like [`nftset_swap`](crate::nftset_swap).
+pub fn nftset_swap_async(
+    family: NftFamily,
+    table: &str,
+    setname1: &str,
+    setname2: &str,
+) -> Operation<()> {
+    let (table, setname1, setname2) = (
+        table.to_string(),
+        setname1.to_string(),
+        setname2.to_string(),
+    );
+    Operation::new(async move {
+        run!(crate::nftset::nftset_swap_async(
+            family, &table, &setname1, &setname2
+        ))
+    })
+}
+
+/// Check if an nftables set exists, like [`nftset_exists`](crate::nftset_exists).
+pub fn nftset_exists_async(family: NftFamily, table: &str, setname: &str) -> Operation<bool> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(
+        async move { run!(crate::nftset::nftset_exists_async(family, &table, &setname)) },
+    )
+}
+
+/// Get information about an nftables set, like [`nftset_info`](crate::nftset_info).
+pub fn nftset_info_async(family: NftFamily, table: &str, setname: &str) -> Operation<NftSetInfo> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move { run!(crate::nftset::nftset_info_async(family, &table, &setname)) })
+}
+
+/// List the rules referring to an nftables set, like
+/// [`nftset_references`](crate::nftset_references).
+pub fn nftset_references_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Operation<Vec<NftSetReference>> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_references_async(
+            family, &table, &setname
+        ))
+    })
+}
+
+/// Add an entry to an nftables set, like [`nftset_add`](crate::nftset_add).
+pub fn nftset_add_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Operation<()> {
+    let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_add_async(
+            family, &table, &setname, entry
+        ))
+    })
+}
+
+/// Add many entries to an nftables set, like [`nftset_add_many`](crate::nftset_add_many).
+pub fn nftset_add_many_async<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Operation<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let (table, setname) = (table.to_string(), setname.to_string());
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move {
+        run!(crate::nftset::nftset_add_many_async(
+            family, &table, &setname, entries
+        ))
+    })
+}
+
+/// Make sure an entry is in an nftables set, like
+/// [`nftset_ensure_entry`](crate::nftset_ensure_entry).
+pub fn nftset_ensure_entry_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Operation<bool> {
+    let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_ensure_entry_async(
+            family, &table, &setname, entry
+        ))
+    })
+}
+
+/// Delete an entry from an nftables set, like [`nftset_del`](crate::nftset_del).
+pub fn nftset_del_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Operation<()> {
+    let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_del_async(
+            family, &table, &setname, entry
+        ))
+    })
+}
+
+/// Delete many entries from an nftables set, like
+/// [`nftset_del_many`](crate::nftset_del_many).
+pub fn nftset_del_many_async<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Operation<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let (table, setname) = (table.to_string(), setname.to_string());
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move {
+        run!(crate::nftset::nftset_del_many_async(
+            family, &table, &setname, entries
+        ))
+    })
+}
+
+/// Test if an entry exists in an nftables set, like [`nftset_test`](crate::nftset_test).
+pub fn nftset_test_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Operation<bool> {
+    let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_test_async(
+            family, &table, &setname, entry
+        ))
+    })
+}
+
+/// Test many entries of an nftables set, like
+/// [`nftset_test_many`](crate::nftset_test_many).
+pub fn nftset_test_many_async<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Operation<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let (table, setname) = (table.to_string(), setname.to_string());
+    let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+    Operation::new(async move {
+        run!(crate::nftset::nftset_test_many_async(
+            family, &table, &setname, entries
+        ))
+    })
+}
+
+/// List the addresses in an nftables set, like [`nftset_list`](crate::nftset_list).
+pub fn nftset_list_async(family: NftFamily, table: &str, setname: &str) -> Operation<Vec<IpAddr>> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move { run!(crate::nftset::nftset_list_async(family, &table, &setname)) })
+}
+
+/// List the networks in an nftables set, like
+/// [`nftset_list_nets`](crate::nftset_list_nets).
+#[cfg(feature = "ipnet")]
+pub fn nftset_list_nets_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Operation<Vec<ipnet::IpNet>> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_list_entries_async(
+            family, &table, &setname
+        ))
+        .map(crate::net::entry_nets)
+    })
+}
+
+/// List the entries in an nftables set, like
+/// [`nftset_list_entries`](crate::nftset_list_entries).
+pub fn nftset_list_entries_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Operation<Vec<Entry>> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_list_entries_async(
+            family, &table, &setname
+        ))
+    })
+}
+
+/// List the elements of an nftables set with their state, like
+/// [`nftset_list_elements`](crate::nftset_list_elements).
+pub fn nftset_list_elements_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Operation<Vec<NftSetElement>> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_list_elements_async(
+            family, &table, &setname
+        ))
+    })
+}
+
+/// Zero the counters of every element of an nftables set, like
+/// [`nftset_reset_counters`](crate::nftset_reset_counters).
+pub fn nftset_reset_counters_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Operation<Vec<NftSetElement>> {
+    let (table, setname) = (table.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_reset_counters_async(
+            family, &table, &setname
+        ))
+    })
+}
+
+/// Zero the counters of one element of an nftables set, like
+/// [`nftset_reset_entry_counters`](crate::nftset_reset_entry_counters).
+pub fn nftset_reset_entry_counters_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Operation<()> {
+    let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_reset_entry_counters_async(
+            family, &table, &setname, entry
+        ))
+    })
+}
+
+/// Add a mapping to an nftables map, like [`nftset_map_add`](crate::nftset_map_add).
+pub fn nftset_map_add_async<K, V>(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+    key: K,
+    value: V,
+) -> Operation<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    let (table, mapname) = (table.to_string(), mapname.to_string());
+    let (key, value) = (key.into(), value.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_map_add_async(
+            family, &table, &mapname, key, value
+        ))
+    })
+}
+
+/// Add a mapping to an nftables map, choosing what happens when the key is
+/// already mapped, like [`nftset_map_add_with`](crate::nftset_map_add_with).
+pub fn nftset_map_add_with_async<K, V>(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+    key: K,
+    value: V,
+    on_conflict: NftMapConflict,
+) -> Operation<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    let (table, mapname) = (table.to_string(), mapname.to_string());
+    let (key, value) = (key.into(), value.into());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_map_add_with_async(
+            family,
+            &table,
+            &mapname,
+            key,
+            value,
+            on_conflict
+        ))
+    })
+}
+
+/// List the mappings of an nftables map, like [`nftset_map_list`](crate::nftset_map_list).
+pub fn nftset_map_list_async(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+) -> Operation<Vec<(Entry, Entry)>> {
+    let (table, mapname) = (table.to_string(), mapname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nftset_map_list_async(
+            family, &table, &mapname
+        ))
+    })
+}
+
+/// Add a rule dropping packets from the addresses of a set, like
+/// [`nft_ensure_drop_rule`](crate::nft_ensure_drop_rule).
+pub fn nft_ensure_drop_rule_async(
+    family: NftFamily,
+    table: &str,
+    chain: &str,
+    setname: &str,
+) -> Operation<()> {
+    let (table, chain, setname) = (table.to_string(), chain.to_string(), setname.to_string());
+    Operation::new(async move {
+        run!(crate::nftset::nft_ensure_drop_rule_async(
+            family, &table, &chain, &setname
+        ))
+    })
+}
+
+/// Ask which optional features nftables sets support, like
+/// [`nftset_capabilities`](crate::nftset_capabilities).
+pub fn nftset_capabilities_async(family: NftFamily) -> Operation<BackendCapabilities> {
+    Operation::new(async move { run!(crate::nftset::nftset_capabilities_async(family)) })
+}
+
+/// Send the changes queued in a transaction, like [`NftTransaction::commit`].
+pub fn nft_commit_async(tx: NftTransaction) -> Operation<()> {
+    Operation::new(async move { run!(tx.commit_async()) })
+}
+
+// Sessions
+
+// The async sessions lock the socket of a session, which unlike the session
+// itself can be borrowed by the futures of tasks moving between threads
+#[cfg(target_os = "linux")]
+type IpsetSessionInner = crate::ipset::IpsetSessionInner;
+#[cfg(not(target_os = "linux"))]
+type IpsetSessionInner = IpsetSession;
+#[cfg(target_os = "linux")]
+type NftSessionInner = crate::nftset::NftSessionInner;
+#[cfg(not(target_os = "linux"))]
+type NftSessionInner = NftSession;
+
+/// An [`IpsetSession`] for async code.
+///
+/// Clones share the socket; their operations run one at a time.
+#[derive(Clone)]
+pub struct AsyncIpsetSession {
+    inner: Arc<Mutex<IpsetSessionInner>>,
+}
+
+impl AsyncIpsetSession {
+    /// Open the netlink socket of a new session.
+    pub fn new() -> Result<Self> {
+        IpsetSession::new()?.try_into()
+    }
+
+    /// Open a session managing the ipsets of the network namespace `netns`,
+    /// like [`IpsetSession::in_netns`].
+    pub fn in_netns(netns: impl AsRef<Path>) -> Result<Self> {
+        IpsetSession::in_netns(netns)?.try_into()
+    }
+
+    /// Open a session managing the ipsets of the network namespace referred
+    /// to by the file descriptor `netns`, like [`IpsetSession::in_netns_fd`].
+    #[cfg(unix)]
+    pub fn in_netns_fd(netns: BorrowedFd<'_>) -> Result<Self> {
+        IpsetSession::in_netns_fd(netns)?.try_into()
+    }
+
+    /// Add an entry to an ipset, like [`IpsetSession::add`].
+    pub fn add<E: Into<Entry>>(&self, setname: &str, entry: E) -> Operation<()> {
+        let (inner, setname, entry) = (self.inner.clone(), setname.to_string(), entry.into());
+        Operation::new(async move { run!(inner.lock().await.add_async(&setname, entry)) })
+    }
+
+    /// Add or refresh an entry of an ipset, like [`IpsetSession::add_exist`].
+    pub fn add_exist<E: Into<Entry>>(&self, setname: &str, entry: E) -> Operation<()> {
+        let (inner, setname, entry) = (self.inner.clone(), setname.to_string(), entry.into());
+        Operation::new(async move { run!(inner.lock().await.add_exist_async(&setname, entry)) })
+    }
+
+    /// Add many entries to an ipset, like [`IpsetSession::add_many`].
+    pub fn add_many<I, E>(&self, setname: &str, entries: I) -> Operation<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let (inner, setname) = (self.inner.clone(), setname.to_string());
+        let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+        Operation::new(async move { run!(inner.lock().await.add_many_async(&setname, entries)) })
+    }
+
+    /// Test many entries of an ipset, like [`IpsetSession::test_many`].
+    pub fn test_many<I, E>(&self, setname: &str, entries: I) -> Operation<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let (inner, setname) = (self.inner.clone(), setname.to_string());
+        let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+        Operation::new(async move { run!(inner.lock().await.test_many_async(&setname, entries)) })
+    }
+
+    /// Delete many entries from an ipset, like [`IpsetSession::del_many`].
+    pub fn del_many<I, E>(&self, setname: &str, entries: I) -> Operation<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let (inner, setname) = (self.inner.clone(), setname.to_string());
+        let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+        Operation::new(async move { run!(inner.lock().await.del_many_async(&setname, entries)) })
+    }
+
+    /// Delete an entry from an ipset, like [`IpsetSession::del`].
+    pub fn del<E: Into<Entry>>(&self, setname: &str, entry: E) -> Operation<()> {
+        let (inner, setname, entry) = (self.inner.clone(), setname.to_string(), entry.into());
+        Operation::new(async move { run!(inner.lock().await.del_async(&setname, entry)) })
+    }
+
+    /// Test if an entry exists in an ipset, like [`IpsetSession::test`].
+    pub fn test<E: Into<Entry>>(&self, setname: &str, entry: E) -> Operation<bool> {
+        let (inner, setname, entry) = (self.inner.clone(), setname.to_string(), entry.into());
+        Operation::new(async move { run!(inner.lock().await.test_async(&setname, entry)) })
+    }
+}
+
+impl TryFrom<IpsetSession> for AsyncIpsetSession {
+    type Error = RipsetError;
+
+    /// Make the socket of `session` wait on the reactor of the current
+    /// tokio runtime, failing outside of one.
+    fn try_from(session: IpsetSession) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let session = {
+            let mut inner = session.into_inner();
+            inner.register()?;
+            inner
+        };
+        Ok(Self {
+            inner: Arc::new(Mutex::new(session)),
+        })
+    }
+}
+
+/// An [`NftSession`] for async code.
+///
+/// Clones share the socket; their operations run one at a time.
+#[derive(Clone)]
+pub struct AsyncNftSession {
+    inner: Arc<Mutex<NftSessionInner>>,
+}
+
+impl AsyncNftSession {
+    /// Open the netlink socket of a new session.
+    pub fn new() -> Result<Self> {
+        NftSession::new()?.try_into()
+    }
+
+    /// Open a session managing the tables of the network namespace `netns`,
+    /// like [`NftSession::in_netns`].
+    pub fn in_netns(netns: impl AsRef<Path>) -> Result<Self> {
+        NftSession::in_netns(netns)?.try_into()
+    }
+
+    /// Open a session managing the tables of the network namespace referred
+    /// to by the file descriptor `netns`, like [`NftSession::in_netns_fd`].
+    #[cfg(unix)]
+    pub fn in_netns_fd(netns: BorrowedFd<'_>) -> Result<Self> {
+        NftSession::in_netns_fd(netns)?.try_into()
+    }
+
+    /// Add an entry to an nftables set, like [`NftSession::add`].
+    pub fn add<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Operation<()> {
+        let inner = self.inner.clone();
+        let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+        Operation::new(async move {
+            run!(
+                inner
+                    .lock()
+                    .await
+                    .add_async(family, &table, &setname, entry)
+            )
+        })
+    }
+
+    /// Add many entries to an nftables set, like [`NftSession::add_many`].
+    pub fn add_many<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Operation<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let inner = self.inner.clone();
+        let (table, setname) = (table.to_string(), setname.to_string());
+        let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+        Operation::new(async move {
+            run!(
+                inner
+                    .lock()
+                    .await
+                    .add_many_async(family, &table, &setname, entries)
+            )
+        })
+    }
+
+    /// Delete many entries from an nftables set, like [`NftSession::del_many`].
+    pub fn del_many<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Operation<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let inner = self.inner.clone();
+        let (table, setname) = (table.to_string(), setname.to_string());
+        let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+        Operation::new(async move {
+            run!(
+                inner
+                    .lock()
+                    .await
+                    .del_many_async(family, &table, &setname, entries)
+            )
+        })
+    }
+
+    /// Delete an entry from an nftables set, like [`NftSession::del`].
+    pub fn del<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Operation<()> {
+        let inner = self.inner.clone();
+        let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+        Operation::new(async move {
+            run!(
+                inner
+                    .lock()
+                    .await
+                    .del_async(family, &table, &setname, entry)
+            )
+        })
+    }
+
+    /// Test if an entry exists in an nftables set, like [`NftSession::test`].
+    pub fn test<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Operation<bool> {
+        let inner = self.inner.clone();
+        let (table, setname, entry) = (table.to_string(), setname.to_string(), entry.into());
+        Operation::new(async move {
+            run!(
+                inner
+                    .lock()
+                    .await
+                    .test_async(family, &table, &setname, entry)
+            )
+        })
+    }
+
+    /// Test many entries of an nftables set, like [`NftSession::test_many`].
+    pub fn test_many<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Operation<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let inner = self.inner.clone();
+        let (table, setname) = (table.to_string(), setname.to_string());
+        let entries: Vec<Entry> = entries.into_iter().map(Into::into).collect();
+        Operation::new(async move {
+            run!(
+                inner
+                    .lock()
+                    .await
+                    .test_many_async(family, &table, &setname, entries)
+            )
+        })
+    }
+
+    /// Send the changes queued in `tx`, like [`NftSession::commit`].
+    pub fn commit(&self, tx: NftTransaction) -> Operation<()> {
+        let inner = self.inner.clone();
+        Operation::new(async move { run!(inner.lock().await.commit_async(tx)) })
+    }
+}
+
+impl TryFrom<NftSession> for AsyncNftSession {
+    type Error = RipsetError;
+
+    /// Make the socket of `session` wait on the reactor of the current
+    /// tokio runtime, failing outside of one.
+    fn try_from(session: NftSession) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let session = {
+            let mut inner = session.into_inner();
+            inner.register()?;
+            inner
+        };
+        Ok(Self {
+            inner: Arc::new(Mutex::new(session)),
+        })
+    }
+}
+
+/// An [`NftMonitor`] for async code, yielding the changes made to nftables
+/// as a `Stream`.
+///
+/// The monitor waits for notifications on the runtime's reactor. Set
+/// properties it has to look up are read directly over netlink, which the
+/// kernel answers at once.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{AsyncNftMonitor, NftEvent};
+///
+/// # async fn example() -> ripset::Result<()> {
+/// let mut monitor = AsyncNftMonitor::new()?;
+/// loop {
+///     if let NftEvent::ElementAdded { set, entry, .. } = monitor.next_event().await? {
+///         println!("{set}: +{entry:?}");
+///     }
+/// }
+/// # }
+/// ```
+#[cfg(unix)]
+pub struct AsyncNftMonitor {
+    inner: AsyncFd<NftMonitor>,
+}
+
+#[cfg(unix)]
+impl AsyncNftMonitor {
+    /// Subscribe to the nftables changes, like [`NftMonitor::new`]. Must be
+    /// called within a tokio runtime with IO enabled.
+    pub fn new() -> Result<Self> {
+        NftMonitor::new()?.try_into()
+    }
+
+    /// Wait for the next change, like [`NftMonitor::next_event`].
+    pub async fn next_event(&mut self) -> Result<NftEvent> {
+        std::future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Result<NftEvent>> {
+        loop {
+            if let Some(event) = self.inner.get_mut().pop_event() {
+                return Poll::Ready(Ok(event));
+            }
+            let mut guard = ready!(self.inner.poll_read_ready_mut(cx))?;
+            match guard.try_io(|inner| inner.get_mut().recv()) {
+                Ok(Ok(len)) => guard.get_inner_mut().handle(len)?,
+                Ok(Err(e)) => return Poll::Ready(Err(guard.get_inner_mut().recv_failed(e))),
+                // Readiness was cleared, wait for the next notification
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl TryFrom<NftMonitor> for AsyncNftMonitor {
+    type Error = RipsetError;
+
+    fn try_from(monitor: NftMonitor) -> Result<Self> {
+        monitor.set_nonblocking()?;
+        Ok(Self {
+            inner: reactor(monitor)?,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Stream for AsyncNftMonitor {
+    type Item = Result<NftEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_event(cx).map(Some)
+    }
+}
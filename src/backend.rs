@@ -0,0 +1,951 @@
+//! Backend-agnostic set operations.
+//!
+//! This module provides the [`SetBackend`] trait, implemented for ipset by
+//! [`IpsetBackend`] and for the sets of one nftables table by
+//! [`NftBackend`], so that code managing sets can work with either.
+
+use std::collections::HashSet;
+
+use crate::{
+    BackendCapabilities, Entry, IpSetCreateOptions, IpSetFamily, IpSetInfo, NftFamily,
+    NftSetCreateOptions, NftSetType, Result, RipsetError, SetRef, ipset_add, ipset_add_many,
+    ipset_capabilities, ipset_create, ipset_del, ipset_destroy, ipset_flush, ipset_info,
+    ipset_list_entries, ipset_list_iter, ipset_protocol, ipset_swap, ipset_test, ipset_test_many,
+    nftset_add, nftset_add_many, nftset_capabilities, nftset_create_set, nftset_del,
+    nftset_delete_set, nftset_exists, nftset_flush, nftset_list_entries, nftset_list_iter,
+    nftset_list_tables_all, nftset_swap, nftset_test, nftset_test_many,
+};
+
+mod dry_run;
+pub use dry_run::{DryRunBackend, SetOperation};
+
+mod dual_stack;
+pub use dual_stack::DualStackSet;
+
+/// Operations common to ipset and nftables sets.
+///
+/// Sets are named by a single string; the backend supplies everything else,
+/// such as the nftables family and table or the options new sets are
+/// created with.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{IpsetBackend, NftBackend, NftFamily, SetBackend};
+///
+/// fn block(backend: &dyn SetBackend, addr: IpAddr) -> ripset::Result<()> {
+///     if !backend.test("blocklist", addr.into())? {
+///         backend.add("blocklist", addr.into())?;
+///     }
+///     Ok(())
+/// }
+///
+/// let addr: IpAddr = "10.0.0.1".parse().unwrap();
+/// block(&IpsetBackend::new(), addr).unwrap();
+/// block(&NftBackend::new(NftFamily::Inet, "filter"), addr).unwrap();
+/// ```
+pub trait SetBackend {
+    /// Create a set with the backend's creation options.
+    fn create(&self, setname: &str) -> Result<()>;
+
+    /// Destroy a set.
+    fn destroy(&self, setname: &str) -> Result<()>;
+
+    /// Add an entry to a set.
+    fn add(&self, setname: &str, entry: Entry) -> Result<()>;
+
+    /// Add many entries to a set. Entries already in the set are not an
+    /// error.
+    ///
+    /// The default implementation adds the entries one by one.
+    fn add_many(&self, setname: &str, entries: Vec<Entry>) -> Result<()> {
+        for entry in entries {
+            match self.add(setname, entry) {
+                Ok(()) | Err(RipsetError::EntryExists) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete an entry from a set.
+    fn del(&self, setname: &str, entry: Entry) -> Result<()>;
+
+    /// Test whether an entry is in a set.
+    fn test(&self, setname: &str, entry: Entry) -> Result<bool>;
+
+    /// Test many entries at once, returning whether each is in the set.
+    ///
+    /// The default implementation tests the entries one by one.
+    fn test_many(&self, setname: &str, entries: Vec<Entry>) -> Result<Vec<bool>> {
+        entries
+            .into_iter()
+            .map(|entry| self.test(setname, entry))
+            .collect()
+    }
+
+    /// List the entries of a set.
+    fn list(&self, setname: &str) -> Result<Vec<Entry>>;
+
+    /// Iterate over the entries of a set as they are received, so that
+    /// large sets can be gone through in little memory.
+    ///
+    /// The default implementation lists the whole set first.
+    fn list_iter(&self, setname: &str) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
+        Ok(Box::new(self.list(setname)?.into_iter().map(Ok)))
+    }
+
+    /// Remove all entries from a set.
+    fn flush(&self, setname: &str) -> Result<()>;
+
+    /// Atomically exchange the contents of two sets.
+    fn swap(&self, setname1: &str, setname2: &str) -> Result<()>;
+
+    /// Ask the kernel which optional features the backend supports, to
+    /// avoid relying on ones that would fail at runtime.
+    ///
+    /// The default implementation reports none.
+    fn capabilities(&self) -> Result<BackendCapabilities> {
+        Ok(BackendCapabilities::default())
+    }
+
+    /// The kernel set a name refers to, which tells whether two backends
+    /// name the same set.
+    ///
+    /// The default implementation returns `None`, and sets are then only
+    /// known to be the same when named by the same backend.
+    fn set_ref(&self, setname: &str) -> Option<SetRef> {
+        let _ = setname;
+        None
+    }
+}
+
+/// The ipset backend.
+#[derive(Clone, Debug, Default)]
+pub struct IpsetBackend {
+    /// Options for the sets created by [`SetBackend::create`]
+    pub create_options: IpSetCreateOptions,
+}
+
+impl IpsetBackend {
+    /// Use ipset, creating sets with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create sets with `options` instead of the defaults.
+    pub fn with_create_options(mut self, options: IpSetCreateOptions) -> Self {
+        self.create_options = options;
+        self
+    }
+}
+
+impl SetBackend for IpsetBackend {
+    fn create(&self, setname: &str) -> Result<()> {
+        ipset_create(setname, &self.create_options)
+    }
+
+    fn destroy(&self, setname: &str) -> Result<()> {
+        ipset_destroy(setname)
+    }
+
+    fn add(&self, setname: &str, entry: Entry) -> Result<()> {
+        ipset_add(setname, entry)
+    }
+
+    fn add_many(&self, setname: &str, entries: Vec<Entry>) -> Result<()> {
+        ipset_add_many(setname, entries)
+    }
+
+    fn del(&self, setname: &str, entry: Entry) -> Result<()> {
+        ipset_del(setname, entry)
+    }
+
+    fn test(&self, setname: &str, entry: Entry) -> Result<bool> {
+        ipset_test(setname, entry)
+    }
+
+    fn test_many(&self, setname: &str, entries: Vec<Entry>) -> Result<Vec<bool>> {
+        ipset_test_many(setname, entries)
+    }
+
+    fn list(&self, setname: &str) -> Result<Vec<Entry>> {
+        ipset_list_entries(setname)
+    }
+
+    fn list_iter(&self, setname: &str) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
+        Ok(Box::new(ipset_list_iter(setname)?))
+    }
+
+    fn flush(&self, setname: &str) -> Result<()> {
+        ipset_flush(setname)
+    }
+
+    fn swap(&self, setname1: &str, setname2: &str) -> Result<()> {
+        ipset_swap(setname1, setname2)
+    }
+
+    fn capabilities(&self) -> Result<BackendCapabilities> {
+        ipset_capabilities()
+    }
+
+    fn set_ref(&self, setname: &str) -> Option<SetRef> {
+        Some(SetRef::ipset(setname))
+    }
+}
+
+/// The nftables backend, managing the sets of one table.
+#[derive(Clone, Debug)]
+pub struct NftBackend {
+    /// The address family of the table
+    pub family: NftFamily,
+    /// The table holding the sets
+    pub table: String,
+    /// Options for the sets created by [`SetBackend::create`]
+    pub create_options: NftSetCreateOptions,
+}
+
+impl NftBackend {
+    /// Use the sets of an nftables table, creating sets with the default
+    /// options. The table itself must already exist.
+    pub fn new(family: NftFamily, table: &str) -> Self {
+        Self {
+            family,
+            table: table.to_string(),
+            create_options: NftSetCreateOptions::default(),
+        }
+    }
+
+    /// Create sets with `options` instead of the defaults.
+    pub fn with_create_options(mut self, options: NftSetCreateOptions) -> Self {
+        self.create_options = options;
+        self
+    }
+}
+
+impl SetBackend for NftBackend {
+    fn create(&self, setname: &str) -> Result<()> {
+        nftset_create_set(self.family, &self.table, setname, &self.create_options)
+    }
+
+    fn destroy(&self, setname: &str) -> Result<()> {
+        nftset_delete_set(self.family, &self.table, setname)
+    }
+
+    fn add(&self, setname: &str, entry: Entry) -> Result<()> {
+        nftset_add(self.family, &self.table, setname, entry)
+    }
+
+    fn add_many(&self, setname: &str, entries: Vec<Entry>) -> Result<()> {
+        nftset_add_many(self.family, &self.table, setname, entries)
+    }
+
+    fn del(&self, setname: &str, entry: Entry) -> Result<()> {
+        nftset_del(self.family, &self.table, setname, entry)
+    }
+
+    fn test(&self, setname: &str, entry: Entry) -> Result<bool> {
+        nftset_test(self.family, &self.table, setname, entry)
+    }
+
+    fn test_many(&self, setname: &str, entries: Vec<Entry>) -> Result<Vec<bool>> {
+        nftset_test_many(self.family, &self.table, setname, entries)
+    }
+
+    fn list(&self, setname: &str) -> Result<Vec<Entry>> {
+        nftset_list_entries(self.family, &self.table, setname)
+    }
+
+    fn list_iter(&self, setname: &str) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
+        Ok(Box::new(nftset_list_iter(
+            self.family,
+            &self.table,
+            setname,
+        )?))
+    }
+
+    fn flush(&self, setname: &str) -> Result<()> {
+        nftset_flush(self.family, &self.table, setname)
+    }
+
+    fn swap(&self, setname1: &str, setname2: &str) -> Result<()> {
+        nftset_swap(self.family, &self.table, setname1, setname2)
+    }
+
+    fn capabilities(&self) -> Result<BackendCapabilities> {
+        nftset_capabilities(self.family)
+    }
+
+    fn set_ref(&self, setname: &str) -> Option<SetRef> {
+        Some(SetRef::nft(self.family, &self.table, setname))
+    }
+}
+
+/// What [`sync_set`] changed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncSummary {
+    /// Desired entries that were missing and have been added
+    pub added: Vec<Entry>,
+    /// Entries that were not desired and have been deleted, as listed
+    pub removed: Vec<Entry>,
+}
+
+impl SyncSummary {
+    /// Whether the set already held exactly the desired entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A set backend, as recommended by [`detect_backend`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    /// ipset
+    Ipset,
+    /// nftables
+    Nftables,
+}
+
+/// Which backends can be used, as found by [`detect_backend`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BackendSupport {
+    /// nf_tables answers requests
+    pub nftables: bool,
+    /// ip_set answers requests
+    pub ipset: bool,
+}
+
+impl BackendSupport {
+    /// The backend to use: nftables when it is usable, as the successor of
+    /// ipset, otherwise ipset. `None` if neither is usable.
+    pub fn recommended(&self) -> Option<BackendKind> {
+        if self.nftables {
+            Some(BackendKind::Nftables)
+        } else if self.ipset {
+            Some(BackendKind::Ipset)
+        } else {
+            None
+        }
+    }
+}
+
+/// Probe whether nftables and ipset are usable in the current kernel and
+/// network namespace.
+///
+/// Each backend is asked for something harmless: the list of nftables
+/// tables and the ipset protocol version. A backend is unusable when the
+/// kernel lacks it, the module cannot be loaded, or the process lacks
+/// `CAP_NET_ADMIN`.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{BackendKind, IpsetBackend, NftBackend, NftFamily, SetBackend, detect_backend};
+///
+/// let backend: Box<dyn SetBackend> = match detect_backend().recommended() {
+///     Some(BackendKind::Nftables) => Box::new(NftBackend::new(NftFamily::Inet, "filter")),
+///     Some(BackendKind::Ipset) => Box::new(IpsetBackend::new()),
+///     None => panic!("no set backend available"),
+/// };
+/// ```
+pub fn detect_backend() -> BackendSupport {
+    BackendSupport {
+        nftables: nftset_list_tables_all().is_ok(),
+        ipset: ipset_protocol().is_ok(),
+    }
+}
+
+/// Difference between the contents of a set and a desired collection of
+/// entries, computed by [`diff_set`] or [`diff_entries`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SetDiff {
+    /// Desired entries missing from the set
+    pub added: Vec<Entry>,
+    /// Entries of the set that are not desired, as listed
+    pub removed: Vec<Entry>,
+    /// Entries of the set that are also desired, as listed
+    pub unchanged: Vec<Entry>,
+}
+
+impl SetDiff {
+    /// Whether the set already holds exactly the desired entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Compare the contents of a set with the `desired` entries, without
+/// changing anything.
+///
+/// This is what [`sync_set`] would apply, so changes can be previewed or
+/// logged first. Entries are compared as described there.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{IpsetBackend, diff_set};
+///
+/// let desired: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap()];
+/// let diff = diff_set(&IpsetBackend::new(), "allowlist", desired).unwrap();
+/// for entry in &diff.removed {
+///     println!("would remove {entry:?}");
+/// }
+/// ```
+pub fn diff_set<I, E>(backend: &dyn SetBackend, setname: &str, desired: I) -> Result<SetDiff>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Ok(diff_entries(backend.list(setname)?, desired))
+}
+
+/// Compare the `current` entries of a set with the `desired` ones.
+///
+/// To compare two sets, pass the listed entries of both.
+pub fn diff_entries<C, I, E>(current: C, desired: I) -> SetDiff
+where
+    C: IntoIterator<Item = Entry>,
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let mut desired_keys = HashSet::new();
+    let desired: Vec<Entry> = desired
+        .into_iter()
+        .map(Into::into)
+        .filter(|entry| desired_keys.insert(sync_key(entry)))
+        .collect();
+
+    let mut diff = SetDiff::default();
+    let mut present = HashSet::new();
+    for entry in current {
+        let key = sync_key(&entry);
+        if !desired_keys.contains(&key) {
+            diff.removed.push(entry);
+        } else if present.insert(key) {
+            diff.unchanged.push(entry);
+        }
+    }
+    diff.added = desired
+        .into_iter()
+        .filter(|entry| !present.contains(&sync_key(entry)))
+        .collect();
+    diff
+}
+
+/// Bring the contents of a set to exactly the `desired` entries.
+///
+/// The set is listed once and only the difference is applied: entries that
+/// are not desired are deleted first, then the missing ones are added.
+/// Entries are compared without their timeouts and counters, and a prefix
+/// covering a single address is the same as the plain address. Ranges are
+/// listed as CIDR blocks, so desired ranges should be given that way too or
+/// they are replaced on every call.
+///
+/// The changes are not atomic: on error, the ones already applied are kept.
+/// Use [`SetBackend::swap`] with a freshly filled set to replace a set's
+/// contents in one step instead.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{IpsetBackend, sync_set};
+///
+/// let desired: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+/// let summary = sync_set(&IpsetBackend::new(), "allowlist", desired).unwrap();
+/// println!("added {}, removed {}", summary.added.len(), summary.removed.len());
+/// ```
+pub fn sync_set<I, E>(backend: &dyn SetBackend, setname: &str, desired: I) -> Result<SyncSummary>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let diff = diff_set(backend, setname, desired)?;
+
+    let mut summary = SyncSummary::default();
+    for entry in diff.removed {
+        match backend.del(setname, sync_key(&entry)) {
+            // Expired since it was listed
+            Ok(()) | Err(RipsetError::EntryNotFound) => summary.removed.push(entry),
+            Err(e) => return Err(e),
+        }
+    }
+    for entry in diff.added {
+        backend.add(setname, entry.clone())?;
+        summary.added.push(entry);
+    }
+    Ok(summary)
+}
+
+/// Copy the entries of one set into another, possibly of the other backend.
+///
+/// The destination set must already exist; entries it already holds are
+/// kept. Address entries carrying a port, MAC address, mark or interface are
+/// copied as a concatenation of those fields, which nftables sets need and
+/// ipset turns back into the same entry. Packet and byte counters are not
+/// copied. The source is read and added in chunks rather than listed whole,
+/// so it can be large. Returns the number of entries copied.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpsetBackend, NftBackend, NftFamily, copy_set};
+///
+/// // Move an ipset blocklist into an existing nftables set
+/// let copied = copy_set(
+///     &IpsetBackend::new(),
+///     "blocklist",
+///     &NftBackend::new(NftFamily::Inet, "filter"),
+///     "blocklist",
+/// )
+/// .unwrap();
+/// println!("copied {copied} entries");
+/// ```
+pub fn copy_set(
+    src: &dyn SetBackend,
+    src_set: &str,
+    dst: &dyn SetBackend,
+    dst_set: &str,
+) -> Result<usize> {
+    let mut count = 0;
+    for_each_chunk(src.list_iter(src_set)?, |chunk| {
+        count += chunk.len();
+        dst.add_many(dst_set, chunk.into_iter().map(copy_entry).collect())
+    })?;
+    Ok(count)
+}
+
+/// Fill a set with the entries of two sets, possibly of different
+/// backends.
+///
+/// The destination ends up holding exactly the union, so it must already
+/// exist and may be one of the two sets. The sets are read in chunks rather
+/// than listed whole: the entries of the destination that are in neither
+/// set are deleted first, then the entries of the two sets missing from it
+/// are added. A set that is also the destination, as told by
+/// [`SetBackend::set_ref`], is listed whole before it is written to. Entries are looked up with [`SetBackend::test_many`], so one
+/// covered by a network or range of the other set counts as present.
+/// Entries are written without their timeouts and counters, and otherwise
+/// converted as by [`copy_set`]. Returns the changes made to the
+/// destination.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpsetBackend, set_union};
+///
+/// let ipset = IpsetBackend::new();
+/// set_union(&ipset, "blocklist_a", &ipset, "blocklist_b", &ipset, "blocklist").unwrap();
+/// ```
+pub fn set_union(
+    a: &dyn SetBackend,
+    set_a: &str,
+    b: &dyn SetBackend,
+    set_b: &str,
+    dst: &dyn SetBackend,
+    dst_set: &str,
+) -> Result<SyncSummary> {
+    combine_sets(SetOp::Union, a, set_a, b, set_b, dst, dst_set)
+}
+
+/// Fill a set with the entries of the first set that are also in the
+/// second.
+///
+/// The second set is not read: the entries of the first are looked up in
+/// it with [`SetBackend::test_many`], so the second set can be large, and an
+/// address of the first set covered by a network or range of the second
+/// counts as in both. The destination is filled as by [`set_union`].
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpsetBackend, set_intersection};
+///
+/// // Requests from allowed addresses that are also reported as abusive
+/// let ipset = IpsetBackend::new();
+/// set_intersection(&ipset, "allowlist", &ipset, "abusers", &ipset, "review").unwrap();
+/// ```
+pub fn set_intersection(
+    a: &dyn SetBackend,
+    set_a: &str,
+    b: &dyn SetBackend,
+    set_b: &str,
+    dst: &dyn SetBackend,
+    dst_set: &str,
+) -> Result<SyncSummary> {
+    combine_sets(SetOp::Intersection, a, set_a, b, set_b, dst, dst_set)
+}
+
+/// Fill a set with the entries of the first set that are not in the
+/// second.
+///
+/// Entries are looked up in the second set like by [`set_intersection`],
+/// and the destination is filled as by [`set_union`].
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftBackend, NftFamily, set_difference};
+///
+/// // Block the listed addresses, except the allowed ones
+/// let nft = NftBackend::new(NftFamily::Inet, "filter");
+/// set_difference(&nft, "blocklist", &nft, "allowlist", &nft, "effective_blocklist").unwrap();
+/// ```
+pub fn set_difference(
+    a: &dyn SetBackend,
+    set_a: &str,
+    b: &dyn SetBackend,
+    set_b: &str,
+    dst: &dyn SetBackend,
+    dst_set: &str,
+) -> Result<SyncSummary> {
+    combine_sets(SetOp::Difference, a, set_a, b, set_b, dst, dst_set)
+}
+
+/// Entries read, tested or added per request by the functions streaming
+/// sets.
+const CHUNK_SIZE: usize = 1024;
+
+/// Call `f` with the entries in chunks of [`CHUNK_SIZE`].
+fn for_each_chunk(
+    entries: impl Iterator<Item = Result<Entry>>,
+    mut f: impl FnMut(Vec<Entry>) -> Result<()>,
+) -> Result<()> {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    for entry in entries {
+        chunk.push(entry?);
+        if chunk.len() == CHUNK_SIZE {
+            f(std::mem::replace(
+                &mut chunk,
+                Vec::with_capacity(CHUNK_SIZE),
+            ))?;
+        }
+    }
+    if !chunk.is_empty() {
+        f(chunk)?;
+    }
+    Ok(())
+}
+
+/// A set operation, deciding which entries of two sets the result holds.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SetOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl SetOp {
+    /// Whether an entry belongs to the result, given whether it is in the
+    /// first and in the second set.
+    fn keeps(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            SetOp::Union => in_a || in_b,
+            SetOp::Intersection => in_a && in_b,
+            SetOp::Difference => in_a && !in_b,
+        }
+    }
+}
+
+/// Bring the destination to the result of `op` on sets `a` and `b`,
+/// reading all three in chunks.
+fn combine_sets(
+    op: SetOp,
+    a: &dyn SetBackend,
+    set_a: &str,
+    b: &dyn SetBackend,
+    set_b: &str,
+    dst: &dyn SetBackend,
+    dst_set: &str,
+) -> Result<SyncSummary> {
+    let mut summary = SyncSummary::default();
+
+    // Deleting from the destination while it is being dumped could skip
+    // entries, so only the unwanted ones are collected first
+    let mut unwanted = Vec::new();
+    for_each_chunk(dst.list_iter(dst_set)?, |chunk| {
+        let entries: Vec<Entry> = chunk.iter().map(set_entry).collect();
+        let in_a = a.test_many(set_a, entries.clone())?;
+        let in_b = b.test_many(set_b, entries)?;
+        unwanted.extend(
+            chunk
+                .into_iter()
+                .zip(in_a.into_iter().zip(in_b))
+                .filter(|&(_, (in_a, in_b))| !op.keeps(in_a, in_b))
+                .map(|(entry, _)| entry),
+        );
+        Ok(())
+    })?;
+    for entry in unwanted {
+        match dst.del(dst_set, sync_key(&entry)) {
+            // Expired since it was listed
+            Ok(()) | Err(RipsetError::EntryNotFound) => summary.removed.push(entry),
+            Err(e) => return Err(e),
+        }
+    }
+
+    for_each_chunk(source_iter(a, set_a, dst, dst_set)?, |chunk| {
+        let mut entries: Vec<Entry> = chunk.iter().map(set_entry).collect();
+        if op != SetOp::Union {
+            let in_b = b.test_many(set_b, entries.clone())?;
+            entries = entries
+                .into_iter()
+                .zip(in_b)
+                .filter(|&(_, in_b)| op.keeps(true, in_b))
+                .map(|(entry, _)| entry)
+                .collect();
+        }
+        add_missing(dst, dst_set, entries, &mut summary)
+    })?;
+    if op == SetOp::Union {
+        for_each_chunk(source_iter(b, set_b, dst, dst_set)?, |chunk| {
+            let entries = chunk.iter().map(set_entry).collect();
+            add_missing(dst, dst_set, entries, &mut summary)
+        })?;
+    }
+    Ok(summary)
+}
+
+/// Iterate over the entries of a source set that the destination is filled
+/// from meanwhile. Adding to a set while it is being dumped could skip
+/// entries just like deleting, so a source that is the destination is
+/// listed whole first.
+fn source_iter<'a>(
+    src: &'a dyn SetBackend,
+    src_set: &str,
+    dst: &dyn SetBackend,
+    dst_set: &str,
+) -> Result<Box<dyn Iterator<Item = Result<Entry>> + 'a>> {
+    let same = match (src.set_ref(src_set), dst.set_ref(dst_set)) {
+        (Some(src_ref), Some(dst_ref)) => src_ref == dst_ref,
+        _ => std::ptr::addr_eq(src, dst) && src_set == dst_set,
+    };
+    if same {
+        Ok(Box::new(src.list(src_set)?.into_iter().map(Ok)))
+    } else {
+        src.list_iter(src_set)
+    }
+}
+
+/// Add the entries a set does not hold yet, recording them in `summary`.
+fn add_missing(
+    dst: &dyn SetBackend,
+    dst_set: &str,
+    entries: Vec<Entry>,
+    summary: &mut SyncSummary,
+) -> Result<()> {
+    let present = dst.test_many(dst_set, entries.clone())?;
+    let missing: Vec<Entry> = entries
+        .into_iter()
+        .zip(present)
+        .filter(|&(_, present)| !present)
+        .map(|(entry, _)| entry)
+        .collect();
+    if !missing.is_empty() {
+        dst.add_many(dst_set, missing.clone())?;
+        summary.added.extend(missing);
+    }
+    Ok(())
+}
+
+/// Move an ipset into nftables: create a set of the same name and an
+/// equivalent type in an nftables table, then copy all the entries into it.
+///
+/// The new set has the default timeout and the counters of the ipset, and
+/// is an interval set for the `net` types. Ports keep their number but not
+/// their protocol, as nftables sets match ports of any protocol. If the nft
+/// set already exists, the entries are added to it; otherwise the new set is
+/// deleted again when the entries cannot be copied. Returns the number of
+/// entries copied.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, migrate_ipset_to_nft};
+///
+/// let copied = migrate_ipset_to_nft("blocklist", NftFamily::Inet, "filter").unwrap();
+/// println!("migrated {copied} entries");
+/// ```
+pub fn migrate_ipset_to_nft(setname: &str, family: NftFamily, table: &str) -> Result<usize> {
+    let options = nft_options_for_ipset(&ipset_info(setname)?)?;
+    let nft = NftBackend::new(family, table).with_create_options(options);
+    let existed = nftset_exists(family, table, setname)?;
+    nft.create(setname)?;
+    copy_set(&IpsetBackend::new(), setname, &nft, setname).inspect_err(|_| {
+        if !existed {
+            let _ = nft.destroy(setname);
+        }
+    })
+}
+
+/// nftables set options equivalent to the header of an ipset.
+fn nft_options_for_ipset(info: &IpSetInfo) -> Result<NftSetCreateOptions> {
+    let addr = match info.family {
+        Some(IpSetFamily::Inet6) => NftSetType::Ipv6Addr,
+        _ => NftSetType::Ipv4Addr,
+    };
+    let concat = |field| NftSetType::Concat(vec![addr.clone(), field]);
+    let (set_type, interval) = match info.type_name.as_str() {
+        "hash:ip" | "bitmap:ip" => (addr.clone(), false),
+        "hash:net" => (addr.clone(), true),
+        "hash:mac" => (NftSetType::EtherAddr, false),
+        "bitmap:port" => (NftSetType::InetService, false),
+        "hash:ip,port" => (concat(NftSetType::InetService), false),
+        "hash:net,port" => (concat(NftSetType::InetService), true),
+        "hash:ip,mac" | "bitmap:ip,mac" => (concat(NftSetType::EtherAddr), false),
+        "hash:ip,mark" => (concat(NftSetType::Mark), false),
+        "hash:net,iface" => (concat(NftSetType::Ifname), true),
+        type_name => {
+            return Err(RipsetError::InvalidOption(format!(
+                "ipset type {type_name} has no nftables equivalent"
+            )));
+        }
+    };
+    Ok(NftSetCreateOptions {
+        set_type,
+        interval,
+        timeout: info.timeout,
+        counters: info.counters,
+        ..Default::default()
+    })
+}
+
+/// Translate a listed entry into a form both backends accept.
+fn copy_entry(entry: Entry) -> Entry {
+    let Entry::Ip(mut ip) = entry else {
+        return entry;
+    };
+    ip.packets = None;
+    ip.bytes = None;
+
+    let mut parts = Vec::new();
+    if let Some(port) = ip.port.take() {
+        parts.push(Entry::Port {
+            port,
+            port_to: ip.port_to.take(),
+        });
+    }
+    if let Some(mac) = ip.mac.take() {
+        parts.push(Entry::Mac(mac));
+    }
+    if let Some(mark) = ip.mark.take() {
+        parts.push(Entry::Mark(mark));
+    }
+    if let Some(iface) = ip.iface.take() {
+        parts.push(Entry::Iface(iface));
+    }
+    if parts.is_empty() {
+        return Entry::Ip(ip);
+    }
+    parts.insert(0, Entry::Ip(ip));
+    Entry::Concat(parts)
+}
+
+/// A listed entry as written by the set operations: without its timeout
+/// and counters, in a form both backends accept.
+fn set_entry(entry: &Entry) -> Entry {
+    copy_entry(sync_key(entry))
+}
+
+/// The part of an entry that identifies it in a set.
+fn sync_key(entry: &Entry) -> Entry {
+    match entry {
+        Entry::Ip(ip) => {
+            let mut ip = ip.clone();
+            let bits = if ip.addr.is_ipv4() { 32 } else { 128 };
+            if ip.cidr == Some(bits) {
+                ip.cidr = None;
+            }
+            ip.timeout = None;
+            ip.packets = None;
+            ip.bytes = None;
+            Entry::Ip(ip)
+        }
+        Entry::Concat(parts) => Entry::Concat(parts.iter().map(sync_key).collect()),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::*;
+    use crate::IpEntry;
+
+    fn addr(s: &str) -> Entry {
+        s.parse::<IpAddr>().unwrap().into()
+    }
+
+    #[test]
+    fn test_diff_entries() {
+        let mut listed = IpEntry::with_cidr("10.0.0.2".parse().unwrap(), 32);
+        listed.timeout = Some(30);
+        listed.packets = Some(1);
+        let current = vec![addr("10.0.0.1"), Entry::Ip(listed.clone())];
+
+        let diff = diff_entries(
+            current,
+            vec![addr("10.0.0.2"), addr("10.0.0.3"), addr("10.0.0.3")],
+        );
+        assert_eq!(diff.added, vec![addr("10.0.0.3")]);
+        assert_eq!(diff.removed, vec![addr("10.0.0.1")]);
+        assert_eq!(diff.unchanged, vec![Entry::Ip(listed)]);
+        assert!(!diff.is_empty());
+
+        let diff = diff_entries(vec![addr("10.0.0.1")], vec![addr("10.0.0.1")]);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_set_ref() {
+        let nft = NftBackend::new(NftFamily::Inet, "filter");
+        assert_eq!(IpsetBackend::new().set_ref("a"), Some(SetRef::ipset("a")));
+        assert_eq!(
+            nft.set_ref("a"),
+            Some(SetRef::nft(NftFamily::Inet, "filter", "a"))
+        );
+        assert_ne!(
+            nft.set_ref("a"),
+            NftBackend::new(NftFamily::Ip, "filter").set_ref("a")
+        );
+        assert_eq!(
+            DryRunBackend::new(nft.clone()).set_ref("a"),
+            nft.set_ref("a")
+        );
+    }
+
+    #[test]
+    fn test_recommended_backend() {
+        let support = |nftables, ipset| BackendSupport { nftables, ipset };
+        assert_eq!(
+            support(true, true).recommended(),
+            Some(BackendKind::Nftables)
+        );
+        assert_eq!(support(false, true).recommended(), Some(BackendKind::Ipset));
+        assert_eq!(support(false, false).recommended(), None);
+    }
+
+    #[test]
+    fn test_copy_entry() {
+        assert_eq!(copy_entry(addr("10.0.0.1")), addr("10.0.0.1"));
+
+        let mut ip = IpEntry::new("10.0.0.1".parse().unwrap());
+        ip.port = Some(443);
+        ip.proto = Some(17);
+        ip.iface = Some("eth0".to_string());
+        ip.packets = Some(5);
+        let mut first = IpEntry::new("10.0.0.1".parse().unwrap());
+        first.proto = Some(17);
+        assert_eq!(
+            copy_entry(Entry::Ip(ip)),
+            Entry::Concat(vec![
+                Entry::Ip(first),
+                443u16.into(),
+                Entry::Iface("eth0".to_string()),
+            ])
+        );
+    }
+}
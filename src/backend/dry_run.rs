@@ -0,0 +1,496 @@
+//! A backend recording changes instead of making them.
+
+use std::sync::{Mutex, MutexGuard};
+
+use super::*;
+use crate::{IpEntry, IpSetRange, NftSetPolicy};
+
+/// A change recorded by a [`DryRunBackend`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetOperation {
+    /// Create a set with the options of the backend
+    Create { setname: String },
+    /// Destroy a set
+    Destroy { setname: String },
+    /// Add an entry, failing if it is already in the set
+    Add { setname: String, entry: Entry },
+    /// Add entries, keeping those already in the set
+    AddMany {
+        setname: String,
+        entries: Vec<Entry>,
+    },
+    /// Delete an entry
+    Del { setname: String, entry: Entry },
+    /// Remove all entries
+    Flush { setname: String },
+    /// Exchange the contents of two sets
+    Swap { setname1: String, setname2: String },
+}
+
+/// A backend wrapper recording the changes it is asked to make, without
+/// making them.
+///
+/// Changes succeed without reaching the kernel, while tests and listings
+/// are answered by the wrapped backend: they see the sets as they are, not
+/// as the recorded changes would leave them. This is enough to preview what
+/// [`sync_set`] or [`copy_set`] would do, or to keep an audit trail of the
+/// changes before applying them for real.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{DryRunBackend, IpsetBackend, sync_set};
+///
+/// let dry_run = DryRunBackend::new(IpsetBackend::new());
+/// let desired: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap()];
+/// sync_set(&dry_run, "allowlist", desired).unwrap();
+/// for command in dry_run.commands() {
+///     println!("{command}");
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DryRunBackend<B> {
+    inner: B,
+    operations: Mutex<Vec<SetOperation>>,
+}
+
+impl<B> DryRunBackend<B> {
+    /// Record the changes meant for `inner`, reading sets through it.
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            operations: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The wrapped backend.
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// The changes recorded so far, in order.
+    pub fn operations(&self) -> Vec<SetOperation> {
+        self.lock().clone()
+    }
+
+    /// Take the changes recorded so far, starting a new recording.
+    pub fn take_operations(&self) -> Vec<SetOperation> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn record(&self, operation: SetOperation) -> Result<()> {
+        self.lock().push(operation);
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<SetOperation>> {
+        self.operations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl DryRunBackend<IpsetBackend> {
+    /// The `ipset` commands making the recorded changes.
+    pub fn commands(&self) -> Vec<String> {
+        let options = &self.inner.create_options;
+        let mut commands = Vec::new();
+        for operation in self.lock().iter() {
+            match operation {
+                SetOperation::Create { setname } => {
+                    commands.push(format!("ipset create {setname} {}", ipset_options(options)))
+                }
+                SetOperation::Destroy { setname } => {
+                    commands.push(format!("ipset destroy {setname}"))
+                }
+                SetOperation::Add { setname, entry } => {
+                    commands.push(format!("ipset add {setname} {}", ipset_entry(entry, true)))
+                }
+                SetOperation::AddMany { setname, entries } => {
+                    commands.extend(entries.iter().map(|entry| {
+                        format!("ipset add -exist {setname} {}", ipset_entry(entry, true))
+                    }))
+                }
+                SetOperation::Del { setname, entry } => {
+                    commands.push(format!("ipset del {setname} {}", ipset_entry(entry, false)))
+                }
+                SetOperation::Flush { setname } => commands.push(format!("ipset flush {setname}")),
+                SetOperation::Swap { setname1, setname2 } => {
+                    commands.push(format!("ipset swap {setname1} {setname2}"))
+                }
+            }
+        }
+        commands
+    }
+}
+
+impl DryRunBackend<NftBackend> {
+    /// The `nft` commands making the recorded changes.
+    ///
+    /// nftables cannot swap sets, so a swap is described by a comment.
+    pub fn commands(&self) -> Vec<String> {
+        let table = format!("{} {}", self.inner.family, self.inner.table);
+        let options = &self.inner.create_options;
+        let elements = |entries: &[Entry], with_options| {
+            let elements: Vec<String> = entries
+                .iter()
+                .map(|entry| nft_element(entry, with_options))
+                .collect();
+            format!("'{{ {} }}'", elements.join(", "))
+        };
+        self.lock()
+            .iter()
+            .map(|operation| match operation {
+                SetOperation::Create { setname } => {
+                    let kind = if options.data_type.is_some() {
+                        "map"
+                    } else {
+                        "set"
+                    };
+                    format!("nft add {kind} {table} {setname} {}", nft_options(options))
+                }
+                SetOperation::Destroy { setname } => format!("nft delete set {table} {setname}"),
+                SetOperation::Add { setname, entry } => format!(
+                    "nft create element {table} {setname} {}",
+                    elements(std::slice::from_ref(entry), true)
+                ),
+                SetOperation::AddMany { setname, entries } => format!(
+                    "nft add element {table} {setname} {}",
+                    elements(entries, true)
+                ),
+                SetOperation::Del { setname, entry } => format!(
+                    "nft delete element {table} {setname} {}",
+                    elements(std::slice::from_ref(entry), false)
+                ),
+                SetOperation::Flush { setname } => format!("nft flush set {table} {setname}"),
+                SetOperation::Swap { setname1, setname2 } => {
+                    format!("# exchange the elements of {table} {setname1} and {setname2}")
+                }
+            })
+            .collect()
+    }
+}
+
+impl<B: SetBackend> SetBackend for DryRunBackend<B> {
+    fn create(&self, setname: &str) -> Result<()> {
+        self.record(SetOperation::Create {
+            setname: setname.to_string(),
+        })
+    }
+
+    fn destroy(&self, setname: &str) -> Result<()> {
+        self.record(SetOperation::Destroy {
+            setname: setname.to_string(),
+        })
+    }
+
+    fn add(&self, setname: &str, entry: Entry) -> Result<()> {
+        self.record(SetOperation::Add {
+            setname: setname.to_string(),
+            entry,
+        })
+    }
+
+    fn add_many(&self, setname: &str, entries: Vec<Entry>) -> Result<()> {
+        self.record(SetOperation::AddMany {
+            setname: setname.to_string(),
+            entries,
+        })
+    }
+
+    fn del(&self, setname: &str, entry: Entry) -> Result<()> {
+        self.record(SetOperation::Del {
+            setname: setname.to_string(),
+            entry,
+        })
+    }
+
+    fn test(&self, setname: &str, entry: Entry) -> Result<bool> {
+        self.inner.test(setname, entry)
+    }
+
+    fn test_many(&self, setname: &str, entries: Vec<Entry>) -> Result<Vec<bool>> {
+        self.inner.test_many(setname, entries)
+    }
+
+    fn list(&self, setname: &str) -> Result<Vec<Entry>> {
+        self.inner.list(setname)
+    }
+
+    fn list_iter(&self, setname: &str) -> Result<Box<dyn Iterator<Item = Result<Entry>> + '_>> {
+        self.inner.list_iter(setname)
+    }
+
+    fn flush(&self, setname: &str) -> Result<()> {
+        self.record(SetOperation::Flush {
+            setname: setname.to_string(),
+        })
+    }
+
+    fn swap(&self, setname1: &str, setname2: &str) -> Result<()> {
+        self.record(SetOperation::Swap {
+            setname1: setname1.to_string(),
+            setname2: setname2.to_string(),
+        })
+    }
+
+    fn capabilities(&self) -> Result<BackendCapabilities> {
+        self.inner.capabilities()
+    }
+
+    fn set_ref(&self, setname: &str) -> Option<SetRef> {
+        self.inner.set_ref(setname)
+    }
+}
+
+/// The type and options of an `ipset create` command.
+fn ipset_options(options: &IpSetCreateOptions) -> String {
+    let mut line = options.set_type.to_string();
+    if options.family == IpSetFamily::Inet6 {
+        line.push_str(" family inet6");
+    }
+    if let Some(hashsize) = options.hashsize {
+        line.push_str(&format!(" hashsize {hashsize}"));
+    }
+    if let Some(maxelem) = options.maxelem {
+        line.push_str(&format!(" maxelem {maxelem}"));
+    }
+    if let Some(bucketsize) = options.bucketsize {
+        line.push_str(&format!(" bucketsize {bucketsize}"));
+    }
+    if let Some(initval) = options.initval {
+        line.push_str(&format!(" initval {initval:#x}"));
+    }
+    if let Some(timeout) = options.timeout {
+        line.push_str(&format!(" timeout {timeout}"));
+    }
+    match options.range {
+        Some(IpSetRange::FromTo(from, to)) => line.push_str(&format!(" range {from}-{to}")),
+        Some(IpSetRange::Cidr(addr, prefix)) => line.push_str(&format!(" range {addr}/{prefix}")),
+        Some(IpSetRange::Ports(from, to)) => line.push_str(&format!(" range {from}-{to}")),
+        None => {}
+    }
+    for (enabled, flag) in [
+        (options.counters, "counters"),
+        (options.comment, "comment"),
+        (options.skbinfo, "skbinfo"),
+        (options.forceadd, "forceadd"),
+    ] {
+        if enabled {
+            line.push(' ');
+            line.push_str(flag);
+        }
+    }
+    line
+}
+
+/// An entry as `ipset add` and `ipset del` take it, with the extensions of
+/// an added entry.
+fn ipset_entry(entry: &Entry, with_options: bool) -> String {
+    match entry {
+        Entry::Ip(ip) if with_options => ip.to_string(),
+        _ => ipset_element(entry),
+    }
+}
+
+/// The element part of an ipset entry, its fields separated by commas.
+fn ipset_element(entry: &Entry) -> String {
+    match entry {
+        Entry::Ip(ip) => IpEntry {
+            timeout: None,
+            packets: None,
+            bytes: None,
+            comment: None,
+            skbmark: None,
+            skbprio: None,
+            skbqueue: None,
+            nomatch: false,
+            ..ip.clone()
+        }
+        .to_string(),
+        Entry::Iface(iface) => iface.clone(),
+        Entry::Concat(parts) => parts
+            .iter()
+            .map(ipset_element)
+            .collect::<Vec<_>>()
+            .join(","),
+        entry => entry.to_string(),
+    }
+}
+
+/// The set definition of an `nft add set` command.
+fn nft_options(options: &NftSetCreateOptions) -> String {
+    let mut definition = match &options.data_type {
+        Some(data_type) => format!("type {} : {data_type};", options.set_type),
+        None => format!("type {};", options.set_type),
+    };
+    let flags: Vec<&str> = [
+        (options.interval, "interval"),
+        (
+            options.timeout.is_some() || options.gc_interval.is_some(),
+            "timeout",
+        ),
+        (options.dynamic, "dynamic"),
+        (options.constant, "constant"),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, flag)| enabled.then_some(flag))
+    .collect();
+    if !flags.is_empty() {
+        definition.push_str(&format!(" flags {};", flags.join(",")));
+    }
+    if let Some(timeout) = options.timeout {
+        definition.push_str(&format!(" timeout {timeout}s;"));
+    }
+    if let Some(gc_interval) = options.gc_interval {
+        definition.push_str(&format!(" gc-interval {gc_interval}s;"));
+    }
+    if let Some(size) = options.size {
+        definition.push_str(&format!(" size {size};"));
+    }
+    match options.policy {
+        Some(NftSetPolicy::Performance) => definition.push_str(" policy performance;"),
+        Some(NftSetPolicy::Memory) => definition.push_str(" policy memory;"),
+        None => {}
+    }
+    if options.counters {
+        definition.push_str(" counter;");
+    }
+    if options.auto_merge {
+        definition.push_str(" auto-merge;");
+    }
+    if let Some(comment) = &options.comment {
+        definition.push_str(&format!(" comment \"{comment}\";"));
+    }
+    format!("'{{ {definition} }}'")
+}
+
+/// An element as nft writes it, with the timeout and comment of an added
+/// entry. Addresses with extra fields become concatenations.
+fn nft_element(entry: &Entry, with_options: bool) -> String {
+    let mut line = nft_key(&copy_entry(entry.clone()));
+    let ip = match entry {
+        Entry::Ip(ip) => ip,
+        Entry::Concat(parts) => match parts.first() {
+            Some(Entry::Ip(ip)) => ip,
+            _ => return line,
+        },
+        _ => return line,
+    };
+    if with_options {
+        if let Some(timeout) = ip.timeout {
+            line.push_str(&format!(" timeout {timeout}s"));
+        }
+        if let Some(comment) = &ip.comment {
+            line.push_str(&format!(" comment \"{comment}\""));
+        }
+    }
+    line
+}
+
+/// The key of an nftables element, its fields separated by dots.
+fn nft_key(entry: &Entry) -> String {
+    match entry {
+        Entry::Ip(ip) => ip_range(ip),
+        Entry::Port { port, port_to } => port_range(*port, *port_to),
+        Entry::Mac(mac) => mac.to_string(),
+        Entry::Mark(mark) => format!("{mark:#x}"),
+        Entry::Iface(iface) => format!("\"{iface}\""),
+        Entry::SetName(name) => name.clone(),
+        Entry::Verdict(verdict) => verdict.to_string(),
+        Entry::Concat(parts) => parts.iter().map(nft_key).collect::<Vec<_>>().join(" . "),
+    }
+}
+
+/// An address, network or address range.
+fn ip_range(ip: &IpEntry) -> String {
+    match (ip.cidr, ip.ip_to) {
+        (_, Some(ip_to)) => format!("{}-{ip_to}", ip.addr),
+        (Some(cidr), None) => format!("{}/{cidr}", ip.addr),
+        (None, None) => ip.addr.to_string(),
+    }
+}
+
+fn port_range(port: u16, port_to: Option<u16>) -> String {
+    match port_to {
+        Some(port_to) => format!("{port}-{port_to}"),
+        None => port.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::*;
+    use crate::IpSetType;
+
+    fn addr(s: &str) -> Entry {
+        s.parse::<IpAddr>().unwrap().into()
+    }
+
+    #[test]
+    fn test_dry_run_ipset_commands() {
+        let dry_run =
+            DryRunBackend::new(IpsetBackend::new().with_create_options(IpSetCreateOptions {
+                set_type: IpSetType::HashNetPort,
+                timeout: Some(300),
+                counters: true,
+                ..Default::default()
+            }));
+        let mut net = IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 24);
+        net.port = Some(443);
+        net.timeout = Some(60);
+        dry_run.create("web").unwrap();
+        dry_run.add("web", Entry::Ip(net.clone())).unwrap();
+        dry_run
+            .add_many("web", vec![addr("10.0.1.1"), addr("10.0.1.2")])
+            .unwrap();
+        dry_run.del("web", Entry::Ip(net)).unwrap();
+        dry_run.swap("web", "web_new").unwrap();
+
+        assert_eq!(
+            dry_run.commands(),
+            vec![
+                "ipset create web hash:net,port timeout 300 counters",
+                "ipset add web 10.0.0.0/24,tcp:443 timeout 60",
+                "ipset add -exist web 10.0.1.1",
+                "ipset add -exist web 10.0.1.2",
+                "ipset del web 10.0.0.0/24,tcp:443",
+                "ipset swap web web_new",
+            ]
+        );
+        assert_eq!(dry_run.take_operations().len(), 5);
+        assert!(dry_run.operations().is_empty());
+    }
+
+    #[test]
+    fn test_dry_run_nft_commands() {
+        let dry_run = DryRunBackend::new(
+            NftBackend::new(NftFamily::Inet, "filter").with_create_options(NftSetCreateOptions {
+                set_type: NftSetType::Concat(vec![NftSetType::Ipv4Addr, NftSetType::Ifname]),
+                interval: true,
+                timeout: Some(300),
+                ..Default::default()
+            }),
+        );
+        let mut ip = IpEntry::with_range("10.0.0.1".parse().unwrap(), "10.0.0.9".parse().unwrap());
+        ip.iface = Some("eth0".to_string());
+        ip.timeout = Some(60);
+        dry_run.create("lan").unwrap();
+        dry_run.add("lan", Entry::Ip(ip.clone())).unwrap();
+        dry_run.del("lan", Entry::Ip(ip)).unwrap();
+        dry_run.flush("lan").unwrap();
+        dry_run.destroy("lan").unwrap();
+
+        assert_eq!(
+            dry_run.commands(),
+            vec![
+                "nft add set inet filter lan '{ type ipv4_addr . ifname; flags interval,timeout; timeout 300s; }'",
+                "nft create element inet filter lan '{ 10.0.0.1-10.0.0.9 . \"eth0\" timeout 60s }'",
+                "nft delete element inet filter lan '{ 10.0.0.1-10.0.0.9 . \"eth0\" }'",
+                "nft flush set inet filter lan",
+                "nft delete set inet filter lan",
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! A pair of sets holding the IPv4 and the IPv6 entries of one list.
+
+use std::net::IpAddr;
+
+use super::*;
+
+/// An IPv4 set and an IPv6 set used as one.
+///
+/// ipset sets and most nftables sets hold addresses of a single family, so
+/// a dual-stack list is kept as two sets. Entries are routed to the set of
+/// their address family, and whole-set operations apply to both.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{DualStackSet, IpSetCreateOptions};
+///
+/// // The ipsets blocklist_v4 and blocklist_v6
+/// let blocklist = DualStackSet::ipset("blocklist", IpSetCreateOptions::default());
+/// blocklist.create().unwrap();
+/// for addr in ["192.0.2.1", "2001:db8::1"] {
+///     blocklist.add(addr.parse::<IpAddr>().unwrap()).unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DualStackSet<B> {
+    v4: B,
+    name_v4: String,
+    v6: B,
+    name_v6: String,
+}
+
+impl DualStackSet<IpsetBackend> {
+    /// The ipsets `<base>_v4` and `<base>_v6`, created with `options` and
+    /// the family of each.
+    pub fn ipset(base: &str, options: IpSetCreateOptions) -> Self {
+        let backend = |family| {
+            IpsetBackend::new().with_create_options(IpSetCreateOptions {
+                family,
+                ..options.clone()
+            })
+        };
+        Self::new(
+            backend(IpSetFamily::Inet),
+            &format!("{base}_v4"),
+            backend(IpSetFamily::Inet6),
+            &format!("{base}_v6"),
+        )
+    }
+}
+
+impl DualStackSet<NftBackend> {
+    /// The nftables sets `<base>_v4` and `<base>_v6` of a table, created
+    /// with `options`. The addresses in the key type of `options`, alone or
+    /// in a concatenation, are made IPv4 or IPv6 addresses for each set.
+    pub fn nft(family: NftFamily, table: &str, base: &str, options: NftSetCreateOptions) -> Self {
+        let backend = |addr: &NftSetType| {
+            NftBackend::new(family, table).with_create_options(NftSetCreateOptions {
+                set_type: with_address_type(&options.set_type, addr),
+                ..options.clone()
+            })
+        };
+        Self::new(
+            backend(&NftSetType::Ipv4Addr),
+            &format!("{base}_v4"),
+            backend(&NftSetType::Ipv6Addr),
+            &format!("{base}_v6"),
+        )
+    }
+}
+
+impl<B: SetBackend> DualStackSet<B> {
+    /// Pair the set `name_v4` of `v4` with the set `name_v6` of `v6`.
+    pub fn new(v4: B, name_v4: &str, v6: B, name_v6: &str) -> Self {
+        Self {
+            v4,
+            name_v4: name_v4.to_string(),
+            v6,
+            name_v6: name_v6.to_string(),
+        }
+    }
+
+    /// The backend and the name of the IPv4 set.
+    pub fn v4(&self) -> (&B, &str) {
+        (&self.v4, &self.name_v4)
+    }
+
+    /// The backend and the name of the IPv6 set.
+    pub fn v6(&self) -> (&B, &str) {
+        (&self.v6, &self.name_v6)
+    }
+
+    /// Create both sets.
+    pub fn create(&self) -> Result<()> {
+        self.v4.create(&self.name_v4)?;
+        self.v6.create(&self.name_v6)
+    }
+
+    /// Destroy both sets. The IPv6 set is destroyed even if destroying the
+    /// IPv4 set fails, and the first error is returned.
+    pub fn destroy(&self) -> Result<()> {
+        let v4 = self.v4.destroy(&self.name_v4);
+        let v6 = self.v6.destroy(&self.name_v6);
+        v4.and(v6)
+    }
+
+    /// Add an entry to the set of its address family.
+    pub fn add<E: Into<Entry>>(&self, entry: E) -> Result<()> {
+        let entry = entry.into();
+        let (backend, setname) = self.route(&entry)?;
+        backend.add(setname, entry)
+    }
+
+    /// Add many entries, each to the set of its address family. Entries
+    /// already in the sets are not an error.
+    pub fn add_many<I, E>(&self, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let (v4, v6) = self.split(entries)?;
+        if !v4.is_empty() {
+            self.v4.add_many(&self.name_v4, v4)?;
+        }
+        if !v6.is_empty() {
+            self.v6.add_many(&self.name_v6, v6)?;
+        }
+        Ok(())
+    }
+
+    /// Delete an entry from the set of its address family.
+    pub fn del<E: Into<Entry>>(&self, entry: E) -> Result<()> {
+        let entry = entry.into();
+        let (backend, setname) = self.route(&entry)?;
+        backend.del(setname, entry)
+    }
+
+    /// Test whether an entry is in the set of its address family.
+    pub fn test<E: Into<Entry>>(&self, entry: E) -> Result<bool> {
+        let entry = entry.into();
+        let (backend, setname) = self.route(&entry)?;
+        backend.test(setname, entry)
+    }
+
+    /// List the entries of both sets, the IPv4 ones first.
+    pub fn list(&self) -> Result<Vec<Entry>> {
+        let mut entries = self.v4.list(&self.name_v4)?;
+        entries.extend(self.v6.list(&self.name_v6)?);
+        Ok(entries)
+    }
+
+    /// Remove all entries from both sets.
+    pub fn flush(&self) -> Result<()> {
+        self.v4.flush(&self.name_v4)?;
+        self.v6.flush(&self.name_v6)
+    }
+
+    /// Bring the contents of both sets to exactly the `desired` entries, as
+    /// [`sync_set`] does for one set. Returns the changes of both sets.
+    pub fn sync<I, E>(&self, desired: I) -> Result<SyncSummary>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let (v4, v6) = self.split(desired)?;
+        let mut summary = sync_set(&self.v4, &self.name_v4, v4)?;
+        let v6 = sync_set(&self.v6, &self.name_v6, v6)?;
+        summary.added.extend(v6.added);
+        summary.removed.extend(v6.removed);
+        Ok(summary)
+    }
+
+    /// The backend and set holding entries of the family of `entry`.
+    fn route(&self, entry: &Entry) -> Result<(&B, &str)> {
+        match entry_addr(entry) {
+            Some(IpAddr::V4(_)) => Ok(self.v4()),
+            Some(IpAddr::V6(_)) => Ok(self.v6()),
+            None => Err(RipsetError::InvalidAddressFamily),
+        }
+    }
+
+    /// Split entries into the IPv4 and the IPv6 ones.
+    fn split<I, E>(&self, entries: I) -> Result<(Vec<Entry>, Vec<Entry>)>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let (mut v4, mut v6) = (Vec::new(), Vec::new());
+        for entry in entries {
+            let entry = entry.into();
+            match entry_addr(&entry) {
+                Some(IpAddr::V4(_)) => v4.push(entry),
+                Some(IpAddr::V6(_)) => v6.push(entry),
+                None => return Err(RipsetError::InvalidAddressFamily),
+            }
+        }
+        Ok((v4, v6))
+    }
+}
+
+/// The address deciding the family of an entry: its own, or that of the
+/// first part of a concatenation.
+fn entry_addr(entry: &Entry) -> Option<IpAddr> {
+    match entry {
+        Entry::Ip(ip) => Some(ip.addr),
+        Entry::Concat(parts) => parts.first().and_then(entry_addr),
+        _ => None,
+    }
+}
+
+/// `set_type` with its address types replaced by `addr`.
+fn with_address_type(set_type: &NftSetType, addr: &NftSetType) -> NftSetType {
+    match set_type {
+        NftSetType::Ipv4Addr | NftSetType::Ipv6Addr => addr.clone(),
+        NftSetType::Concat(types) => NftSetType::Concat(
+            types
+                .iter()
+                .map(|set_type| with_address_type(set_type, addr))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DryRunBackend;
+
+    #[test]
+    fn test_dual_stack_routing() {
+        let ipset = DualStackSet::ipset("blocklist", IpSetCreateOptions::default());
+        let (v4, v6) = (ipset.v4(), ipset.v6());
+        let dual = DualStackSet::new(
+            DryRunBackend::new(v4.0.clone()),
+            v4.1,
+            DryRunBackend::new(v6.0.clone()),
+            v6.1,
+        );
+        dual.create().unwrap();
+        dual.add("192.0.2.1".parse::<IpAddr>().unwrap()).unwrap();
+        dual.add_many(["2001:db8::1", "2001:db8::2"].map(|s| s.parse::<IpAddr>().unwrap()))
+            .unwrap();
+        dual.del("2001:db8::1".parse::<IpAddr>().unwrap()).unwrap();
+        assert!(matches!(
+            dual.add(Entry::from("other")),
+            Err(RipsetError::InvalidAddressFamily)
+        ));
+
+        assert_eq!(
+            dual.v4().0.commands(),
+            vec![
+                "ipset create blocklist_v4 hash:ip",
+                "ipset add blocklist_v4 192.0.2.1",
+            ]
+        );
+        assert_eq!(
+            dual.v6().0.commands(),
+            vec![
+                "ipset create blocklist_v6 hash:ip family inet6",
+                "ipset add -exist blocklist_v6 2001:db8::1",
+                "ipset add -exist blocklist_v6 2001:db8::2",
+                "ipset del blocklist_v6 2001:db8::1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dual_stack_nft_types() {
+        let options = NftSetCreateOptions {
+            set_type: NftSetType::Concat(vec![NftSetType::Ipv4Addr, NftSetType::InetService]),
+            ..Default::default()
+        };
+        let nft = DualStackSet::nft(NftFamily::Inet, "filter", "web", options);
+        assert_eq!(nft.v4().1, "web_v4");
+        assert_eq!(
+            nft.v6().0.create_options.set_type,
+            NftSetType::Concat(vec![NftSetType::Ipv6Addr, NftSetType::InetService])
+        );
+    }
+}
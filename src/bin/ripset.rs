@@ -4,12 +4,17 @@
 
 use clap::{Parser, Subcommand, ValueEnum};
 use ripset::{
-    IpSetCreateOptions, IpSetFamily, IpSetType, NftSetCreateOptions, NftSetType, ipset_add,
-    ipset_create, ipset_del, ipset_destroy, ipset_flush, ipset_list, nftset_add, nftset_create_set,
-    nftset_create_table, nftset_del, nftset_delete_set, nftset_delete_table, nftset_list,
+    BackendKind, Entry, IpEntry, IpSetCreateOptions, IpSetFamily, IpSetInfo, IpSetRange, IpSetType,
+    IpsetBackend, MacAddr, NftBackend, NftFamily, NftMapConflict, NftSetCreateOptions,
+    NftSetElement, NftSetInfo, NftSetPolicy, NftSetSummary, NftSetType, NftVerdict, SetBackend,
+    detect_backend, ipset_add, ipset_add_exist, ipset_create, ipset_create_exist, ipset_del,
+    ipset_info, ipset_list_entries, nftset_add, nftset_create_set, nftset_create_table, nftset_del,
+    nftset_delete_table, nftset_flush_table, nftset_info, nftset_list_elements, nftset_list_sets,
+    nftset_map_add_with, nftset_map_list, with_timeout,
 };
 use std::net::IpAddr;
 use std::process::ExitCode;
+use std::time::Duration;
 
 /// Parse a set name that may contain a table prefix in the format `<table>.<set>`.
 /// Returns (table_name, set_name) where table_name is Some if a dot separator was found.
@@ -41,6 +46,22 @@ enum Backend {
     #[default]
     #[value(alias("nft"))]
     Nftables,
+    /// Use nftables if the kernel supports it, ipset otherwise
+    Auto,
+}
+
+impl Backend {
+    /// The backend to use, probing the kernel for `auto`.
+    fn resolve(self) -> Result<BackendKind, String> {
+        match self {
+            Backend::Ipset => Ok(BackendKind::Ipset),
+            Backend::Nftables => Ok(BackendKind::Nftables),
+            Backend::Auto => detect_backend().recommended().ok_or_else(|| {
+                "Neither nftables nor ipset is usable (missing kernel support or privileges)"
+                    .to_string()
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -67,6 +88,10 @@ struct Cli {
     #[arg(short, long, value_enum, default_value_t = Backend::Nftables)]
     backend: Backend,
 
+    /// Give up when the kernel does not answer within this many seconds
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -77,25 +102,32 @@ enum Commands {
     Add {
         /// Name of the set (use <table>.<set> syntax for nftables)
         set_name: String,
-        /// IP address entry to add
-        entry: IpAddr,
+        /// IP address, <addr>/<cidr> or <from>-<to> range to add (or a set name for ipset list:set)
+        entry: String,
         /// Table name (required for nftables backend)
         #[arg(short, long)]
         table: Option<String>,
-        /// Address family for nftables (inet, ip, ip6)
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
+        /// Add the network as an exception entry (ipset hash:net only)
+        #[arg(long)]
+        nomatch: bool,
+        /// Update the entry instead of failing if it already exists (ipset, and
+        /// nftables maps where the value is replaced)
+        #[arg(long)]
+        exist: bool,
     },
     /// Delete an entry from a set
     Del {
         /// Name of the set (use <table>.<set> syntax for nftables)
         set_name: String,
-        /// IP address entry to delete
-        entry: IpAddr,
+        /// IP address, <addr>/<cidr> or <from>-<to> range to delete (or a set name for ipset list:set)
+        entry: String,
         /// Table name (required for nftables backend)
         #[arg(short, long)]
         table: Option<String>,
-        /// Address family for nftables (inet, ip, ip6)
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
     },
@@ -106,7 +138,7 @@ enum Commands {
         /// Table name (required for nftables backend)
         #[arg(short, long)]
         table: Option<String>,
-        /// Address family for nftables (inet, ip, ip6)
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
     },
@@ -117,11 +149,11 @@ enum Commands {
         /// Table name (required for nftables backend)
         #[arg(short, long)]
         table: Option<String>,
-        /// Address family for nftables (inet, ip, ip6)
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
     },
-    /// Manage sets (create, delete)
+    /// Manage sets (create, delete, info)
     Set {
         #[command(subcommand)]
         command: SetCommands,
@@ -142,12 +174,69 @@ enum SetCommands {
         /// Table name (required for nftables backend)
         #[arg(short, long)]
         table: Option<String>,
-        /// Address family (inet, inet6 for ipset; inet, ip, ip6 for nftables)
+        /// Address family (inet, inet6 for ipset; inet, ip, ip6, arp, bridge, netdev for nftables)
         #[arg(short, long, default_value = "inet")]
         family: String,
-        /// Set type (hash-ip, hash-net for ipset; ipv4, ipv6 for nftables)
+        /// Set type (hash-ip, hash-net, bitmap-ip, bitmap-ip-mac, list-set, hash-ip-port, hash-net-port, bitmap-port, hash-mac, hash-ip-mark, hash-net-iface for ipset; ipv4, ipv6, ether_addr, mark, ifname, inet_service or a concatenation such as "ipv4_addr . inet_service" for nftables)
         #[arg(long, default_value = "hash-ip")]
         r#type: String,
+        /// Range for bitmap types (<from>-<to> or <addr>/<cidr>; ports for bitmap-port)
+        #[arg(long)]
+        range: Option<String>,
+        /// Initial hash table size for hash types (ipset only)
+        #[arg(long)]
+        hashsize: Option<u32>,
+        /// Maximal number of elements for hash types (ipset only)
+        #[arg(long)]
+        maxelem: Option<u32>,
+        /// Maximal number of elements per hash bucket (ipset only)
+        #[arg(long)]
+        bucketsize: Option<u8>,
+        /// Hash seed for hash types (ipset only)
+        #[arg(long)]
+        initval: Option<u32>,
+        /// Keep per-entry packet and byte counters
+        #[arg(long)]
+        counters: bool,
+        /// Allow a comment on each entry (ipset only)
+        #[arg(long)]
+        comment: bool,
+        /// Allow skbmark/skbprio/skbqueue values on each entry (ipset only)
+        #[arg(long)]
+        skbinfo: bool,
+        /// Evict a random entry when a hash set is full (ipset only)
+        #[arg(long)]
+        forceadd: bool,
+        /// Don't fail if an identical set already exists (ipset only)
+        #[arg(long)]
+        exist: bool,
+        /// Store CIDR blocks and address ranges (nftables only)
+        #[arg(long)]
+        interval: bool,
+        /// Merge adjacent and overlapping ranges, implies --interval (nftables only)
+        #[arg(long)]
+        auto_merge: bool,
+        /// Create a map with values of this type, e.g. mark, ipv4_addr or verdict (nftables only)
+        #[arg(long)]
+        map: Option<String>,
+        /// Lookup policy: performance or memory (nftables only)
+        #[arg(long)]
+        policy: Option<String>,
+        /// Maximal number of elements (nftables only)
+        #[arg(long)]
+        size: Option<u32>,
+        /// Seconds between removals of expired elements (nftables only)
+        #[arg(long)]
+        gc_interval: Option<u32>,
+        /// Allow rules to update the set from the packet path (nftables only)
+        #[arg(long)]
+        dynamic: bool,
+        /// Disallow changes once the set is in use (nftables only)
+        #[arg(long)]
+        constant: bool,
+        /// Comment describing the set (nftables only)
+        #[arg(long)]
+        description: Option<String>,
     },
     /// Delete a set
     Del {
@@ -156,7 +245,31 @@ enum SetCommands {
         /// Table name (required for nftables backend)
         #[arg(short, long)]
         table: Option<String>,
-        /// Address family for nftables (inet, ip, ip6)
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
+        #[arg(short, long, default_value = "inet")]
+        family: String,
+    },
+    /// Show the properties of a set
+    Info {
+        /// Name of the set (use <table>.<set> syntax for nftables)
+        set_name: String,
+        /// Table name (required for nftables backend)
+        #[arg(short, long)]
+        table: Option<String>,
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
+        #[arg(short, long, default_value = "inet")]
+        family: String,
+    },
+    /// Atomically exchange two sets (nftables: their elements, in the same table)
+    Swap {
+        /// Name of the first set (use <table>.<set> syntax for nftables)
+        set_name: String,
+        /// Name of the second set
+        other_set_name: String,
+        /// Table name (required for nftables backend)
+        #[arg(short, long)]
+        table: Option<String>,
+        /// Address family for nftables (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
     },
@@ -168,7 +281,7 @@ enum TableCommands {
     New {
         /// Name of the table to create
         table_name: String,
-        /// Address family (inet, ip, ip6)
+        /// Address family (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
     },
@@ -176,7 +289,15 @@ enum TableCommands {
     Del {
         /// Name of the table to delete
         table_name: String,
-        /// Address family (inet, ip, ip6)
+        /// Address family (inet, ip, ip6, arp, bridge, netdev)
+        #[arg(short, long, default_value = "inet")]
+        family: String,
+    },
+    /// Remove all rules and set elements from an nftables table
+    Flush {
+        /// Name of the table to flush
+        table_name: String,
+        /// Address family (inet, ip, ip6, arp, bridge, netdev)
         #[arg(short, long, default_value = "inet")]
         family: String,
     },
@@ -184,33 +305,49 @@ enum TableCommands {
 
 fn main() -> ExitCode {
     let cli = Cli::parse();
+    match cli.timeout {
+        Some(seconds) => with_timeout(Duration::from_secs(seconds), || run(cli)),
+        None => run(cli),
+    }
+}
 
-    let result = match cli.command {
+fn run(cli: Cli) -> ExitCode {
+    let result = cli.backend.resolve().and_then(|backend| match cli.command {
         Commands::Add {
             set_name,
             entry,
             table,
             family,
-        } => handle_add(cli.backend, &set_name, entry, table.as_deref(), &family),
+            nomatch,
+            exist,
+        } => handle_add(
+            backend,
+            &set_name,
+            &entry,
+            table.as_deref(),
+            &family,
+            nomatch,
+            exist,
+        ),
         Commands::Del {
             set_name,
             entry,
             table,
             family,
-        } => handle_del(cli.backend, &set_name, entry, table.as_deref(), &family),
+        } => handle_del(backend, &set_name, &entry, table.as_deref(), &family),
         Commands::List {
             set_name,
             table,
             family,
-        } => handle_list(cli.backend, &set_name, table.as_deref(), &family),
+        } => handle_list(backend, &set_name, table.as_deref(), &family),
         Commands::Flush {
             set_name,
             table,
             family,
-        } => handle_flush(cli.backend, &set_name, table.as_deref(), &family),
-        Commands::Set { command } => handle_set_command(cli.backend, command),
-        Commands::Table { command } => handle_table_command(cli.backend, command),
-    };
+        } => handle_flush(backend, &set_name, table.as_deref(), &family),
+        Commands::Set { command } => handle_set_command(backend, command),
+        Commands::Table { command } => handle_table_command(backend, command),
+    });
 
     match result {
         Ok(()) => ExitCode::SUCCESS,
@@ -222,29 +359,67 @@ fn main() -> ExitCode {
 }
 
 fn handle_add(
-    backend: Backend,
+    backend: BackendKind,
     set_name: &str,
-    entry: IpAddr,
+    entry: &str,
     table: Option<&str>,
     family: &str,
+    nomatch: bool,
+    exist: bool,
 ) -> Result<(), String> {
     let (parsed_table, actual_set_name) = parse_table_set_name(set_name);
     let resolved_table = resolve_table(parsed_table, table);
 
     match backend {
-        Backend::Ipset => ipset_add(actual_set_name, entry).map_err(|e| e.to_string()),
-        Backend::Nftables => {
+        BackendKind::Ipset => {
+            let mut entry = parse_ipset_entry(entry)?;
+            if let Entry::Ip(ip_entry) = &mut entry {
+                ip_entry.nomatch = nomatch;
+            }
+            if exist {
+                ipset_add_exist(actual_set_name, entry).map_err(|e| e.to_string())
+            } else {
+                ipset_add(actual_set_name, entry).map_err(|e| e.to_string())
+            }
+        }
+        BackendKind::Nftables => {
             let table = resolved_table
                 .ok_or("Table name is required for nftables backend (use -t/--table or <table>.<set> syntax)")?;
+            let family = parse_nft_family(family)?;
+            let set = nftset_summary(family, table, actual_set_name);
+            let set_type = set.as_ref().and_then(|set| set.set_type.as_ref());
+            if let Some(set) = set.as_ref().filter(|set| set.map) {
+                // Map elements are written as in nft: "<key> : <value>"
+                let (key, value) = entry
+                    .split_once(" : ")
+                    .ok_or("Map elements must be given as \"<key> : <value>\"")?;
+                let key = parse_nftset_entry(key.trim(), set_type)?;
+                let value = parse_nftset_entry(value.trim(), set.data_type.as_ref())?;
+                let on_conflict = if exist {
+                    NftMapConflict::Replace
+                } else {
+                    NftMapConflict::Fail
+                };
+                return nftset_map_add_with(
+                    family,
+                    table,
+                    actual_set_name,
+                    key,
+                    value,
+                    on_conflict,
+                )
+                .map_err(|e| e.to_string());
+            }
+            let entry = parse_nftset_entry(entry, set_type)?;
             nftset_add(family, table, actual_set_name, entry).map_err(|e| e.to_string())
         }
     }
 }
 
 fn handle_del(
-    backend: Backend,
+    backend: BackendKind,
     set_name: &str,
-    entry: IpAddr,
+    entry: &str,
     table: Option<&str>,
     family: &str,
 ) -> Result<(), String> {
@@ -252,17 +427,25 @@ fn handle_del(
     let resolved_table = resolve_table(parsed_table, table);
 
     match backend {
-        Backend::Ipset => ipset_del(actual_set_name, entry).map_err(|e| e.to_string()),
-        Backend::Nftables => {
+        BackendKind::Ipset => {
+            ipset_del(actual_set_name, parse_ipset_entry(entry)?).map_err(|e| e.to_string())
+        }
+        BackendKind::Nftables => {
             let table = resolved_table
                 .ok_or("Table name is required for nftables backend (use -t/--table or <table>.<set> syntax)")?;
+            let family = parse_nft_family(family)?;
+            let set = nftset_summary(family, table, actual_set_name);
+            let set_type = set.as_ref().and_then(|set| set.set_type.as_ref());
+            // Map elements are deleted by key alone
+            let key = entry.split_once(" : ").map_or(entry, |(key, _)| key.trim());
+            let entry = parse_nftset_entry(key, set_type)?;
             nftset_del(family, table, actual_set_name, entry).map_err(|e| e.to_string())
         }
     }
 }
 
 fn handle_list(
-    backend: Backend,
+    backend: BackendKind,
     set_name: &str,
     table: Option<&str>,
     family: &str,
@@ -270,12 +453,30 @@ fn handle_list(
     let (parsed_table, actual_set_name) = parse_table_set_name(set_name);
     let resolved_table = resolve_table(parsed_table, table);
 
-    let entries = match backend {
-        Backend::Ipset => ipset_list(actual_set_name).map_err(|e| e.to_string())?,
-        Backend::Nftables => {
+    let entries: Vec<String> = match backend {
+        BackendKind::Ipset => ipset_list_entries(actual_set_name)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(format_entry)
+            .collect(),
+        BackendKind::Nftables => {
             let table = resolved_table
                 .ok_or("Table name is required for nftables backend (use -t/--table or <table>.<set> syntax)")?;
-            nftset_list(family, table, actual_set_name).map_err(|e| e.to_string())?
+            let family = parse_nft_family(family)?;
+            let is_map = nftset_summary(family, table, actual_set_name).is_some_and(|set| set.map);
+            if is_map {
+                nftset_map_list(family, table, actual_set_name)
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(|(key, value)| format!("{} : {}", format_entry(key), format_entry(value)))
+                    .collect()
+            } else {
+                nftset_list_elements(family, table, actual_set_name)
+                    .map_err(|e| e.to_string())?
+                    .into_iter()
+                    .map(format_nftset_element)
+                    .collect()
+            }
         }
     };
 
@@ -287,7 +488,7 @@ fn handle_list(
 }
 
 fn handle_flush(
-    backend: Backend,
+    backend: BackendKind,
     set_name: &str,
     table: Option<&str>,
     family: &str,
@@ -295,54 +496,105 @@ fn handle_flush(
     let (parsed_table, actual_set_name) = parse_table_set_name(set_name);
     let resolved_table = resolve_table(parsed_table, table);
 
+    set_backend(backend, resolved_table, family)?
+        .flush(actual_set_name)
+        .map_err(|e| e.to_string())
+}
+
+/// Build the library backend for the commands that work the same way on both.
+fn set_backend(
+    backend: BackendKind,
+    table: Option<&str>,
+    family: &str,
+) -> Result<Box<dyn SetBackend>, String> {
     match backend {
-        Backend::Ipset => ipset_flush(actual_set_name).map_err(|e| e.to_string()),
-        Backend::Nftables => {
-            let table = resolved_table
+        BackendKind::Ipset => Ok(Box::new(IpsetBackend::new())),
+        BackendKind::Nftables => {
+            let table = table
                 .ok_or("Table name is required for nftables backend (use -t/--table or <table>.<set> syntax)")?;
-            // nftables doesn't have a direct flush command, so we list and delete all
-            let entries =
-                nftset_list(family, table, actual_set_name).map_err(|e| e.to_string())?;
-            for entry in entries {
-                nftset_del(family, table, actual_set_name, entry).map_err(|e| e.to_string())?;
-            }
-            Ok(())
+            Ok(Box::new(NftBackend::new(parse_nft_family(family)?, table)))
         }
     }
 }
 
-fn handle_set_command(backend: Backend, command: SetCommands) -> Result<(), String> {
+fn handle_set_command(backend: BackendKind, command: SetCommands) -> Result<(), String> {
     match command {
         SetCommands::New {
             set_name,
             table,
             family,
             r#type,
+            range,
+            hashsize,
+            maxelem,
+            bucketsize,
+            initval,
+            counters,
+            comment,
+            skbinfo,
+            forceadd,
+            exist,
+            interval,
+            auto_merge,
+            map,
+            policy,
+            size,
+            gc_interval,
+            dynamic,
+            constant,
+            description,
         } => {
             let (parsed_table, actual_set_name) = parse_table_set_name(&set_name);
             let resolved_table = resolve_table(parsed_table, table.as_deref());
 
             match backend {
-                Backend::Ipset => {
+                BackendKind::Ipset => {
                     let set_type = parse_ipset_type(&r#type)?;
                     let ip_family = parse_ipset_family(&family)?;
+                    let range = range.as_deref().map(parse_ipset_range).transpose()?;
                     let options = IpSetCreateOptions {
                         set_type,
                         family: ip_family,
+                        range,
+                        hashsize,
+                        maxelem,
+                        bucketsize,
+                        initval,
+                        counters,
+                        comment,
+                        skbinfo,
+                        forceadd,
                         ..Default::default()
                     };
-                    ipset_create(actual_set_name, &options).map_err(|e| e.to_string())
+                    if exist {
+                        ipset_create_exist(actual_set_name, &options).map_err(|e| e.to_string())
+                    } else {
+                        ipset_create(actual_set_name, &options).map_err(|e| e.to_string())
+                    }
                 }
-                Backend::Nftables => {
+                BackendKind::Nftables => {
                     let table = resolved_table.ok_or(
                         "Table name is required for nftables backend (use -t/--table or <table>.<set> syntax)",
                     )?;
-                    let nft_type = parse_nftset_type(&r#type, &family)?;
+                    let family = parse_nft_family(&family)?;
+                    let nft_type = parse_nftset_type(&r#type, family)?;
+                    let data_type = map.as_deref().map(parse_nftset_field_type).transpose()?;
+                    let policy = policy.as_deref().map(parse_nftset_policy).transpose()?;
                     let options = NftSetCreateOptions {
                         set_type: nft_type,
+                        interval: interval || auto_merge,
+                        auto_merge,
+                        data_type,
+                        policy,
+                        size,
+                        gc_interval,
+                        dynamic,
+                        constant,
+                        comment: description,
+                        counters,
                         ..Default::default()
                     };
-                    nftset_create_set(&family, table, actual_set_name, &options)
+                    nftset_create_set(family, table, actual_set_name, &options)
                         .map_err(|e| e.to_string())
                 }
             }
@@ -355,43 +607,369 @@ fn handle_set_command(backend: Backend, command: SetCommands) -> Result<(), Stri
             let (parsed_table, actual_set_name) = parse_table_set_name(&set_name);
             let resolved_table = resolve_table(parsed_table, table.as_deref());
 
+            set_backend(backend, resolved_table, &family)?
+                .destroy(actual_set_name)
+                .map_err(|e| e.to_string())
+        }
+        SetCommands::Info {
+            set_name,
+            table,
+            family,
+        } => {
+            let (parsed_table, actual_set_name) = parse_table_set_name(&set_name);
+            let resolved_table = resolve_table(parsed_table, table.as_deref());
+
             match backend {
-                Backend::Ipset => ipset_destroy(actual_set_name).map_err(|e| e.to_string()),
-                Backend::Nftables => {
+                BackendKind::Ipset => {
+                    let info = ipset_info(actual_set_name).map_err(|e| e.to_string())?;
+                    print_ipset_info(&info);
+                    Ok(())
+                }
+                BackendKind::Nftables => {
                     let table = resolved_table.ok_or(
                         "Table name is required for nftables backend (use -t/--table or <table>.<set> syntax)",
                     )?;
-                    nftset_delete_set(&family, table, actual_set_name).map_err(|e| e.to_string())
+                    let family = parse_nft_family(&family)?;
+                    let info =
+                        nftset_info(family, table, actual_set_name).map_err(|e| e.to_string())?;
+                    print_nftset_info(&info);
+                    Ok(())
                 }
             }
         }
+        SetCommands::Swap {
+            set_name,
+            other_set_name,
+            table,
+            family,
+        } => {
+            let (parsed_table, actual_set_name) = parse_table_set_name(&set_name);
+            let (other_table, other_set_name) = parse_table_set_name(&other_set_name);
+            let resolved_table = resolve_table(parsed_table, table.as_deref());
+
+            let sets = set_backend(backend, resolved_table, &family)?;
+            if matches!(backend, BackendKind::Nftables)
+                && other_table.is_some_and(|other_table| Some(other_table) != resolved_table)
+            {
+                return Err("Both sets must be in the same table".to_string());
+            }
+            sets.swap(actual_set_name, other_set_name)
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn print_ipset_info(info: &IpSetInfo) {
+    println!("Name: {}", info.name);
+    println!("Type: {}", info.type_name);
+    println!("Revision: {}", info.revision);
+    let mut header = Vec::new();
+    match info.family {
+        Some(IpSetFamily::Inet) => header.push("family inet".to_string()),
+        Some(IpSetFamily::Inet6) => header.push("family inet6".to_string()),
+        None => {}
+    }
+    match info.range {
+        Some(IpSetRange::FromTo(from, to)) => header.push(format!("range {from}-{to}")),
+        Some(IpSetRange::Cidr(addr, cidr)) => header.push(format!("range {addr}/{cidr}")),
+        Some(IpSetRange::Ports(from, to)) => header.push(format!("range {from}-{to}")),
+        None => {}
+    }
+    if let Some(hashsize) = info.hashsize {
+        header.push(format!("hashsize {hashsize}"));
+    }
+    if let Some(maxelem) = info.maxelem {
+        header.push(format!("maxelem {maxelem}"));
+    }
+    if let Some(bucketsize) = info.bucketsize {
+        header.push(format!("bucketsize {bucketsize}"));
+    }
+    if let Some(initval) = info.initval {
+        header.push(format!("initval {initval:#x}"));
+    }
+    if let Some(size) = info.size {
+        header.push(format!("size {size}"));
+    }
+    if let Some(timeout) = info.timeout {
+        header.push(format!("timeout {timeout}"));
+    }
+    for (flag, enabled) in [
+        ("counters", info.counters),
+        ("comment", info.comment),
+        ("skbinfo", info.skbinfo),
+        ("forceadd", info.forceadd),
+    ] {
+        if enabled {
+            header.push(flag.to_string());
+        }
+    }
+    println!("Header: {}", header.join(" "));
+    println!("Size in memory: {}", info.memsize);
+    println!("References: {}", info.references);
+    println!("Number of entries: {}", info.entries);
+}
+
+fn print_nftset_info(info: &NftSetInfo) {
+    println!("Name: {}", info.name);
+    let key_type = match &info.set_type {
+        Some(set_type) => set_type.to_string(),
+        None => format!("{:#x} ({} bytes)", info.key_type, info.key_len),
+    };
+    match (&info.data_type, info.map) {
+        (Some(data_type), true) => println!("Type: map {key_type} : {data_type}"),
+        (None, true) => println!("Type: map {key_type}"),
+        _ => println!("Type: {key_type}"),
+    }
+    let mut flags = Vec::new();
+    for (flag, enabled) in [
+        ("constant", info.constant),
+        ("interval", info.interval),
+        ("auto-merge", info.auto_merge),
+        ("timeout", info.with_timeout),
+        ("dynamic", info.dynamic),
+        ("counter", info.counters),
+    ] {
+        if enabled {
+            flags.push(flag);
+        }
+    }
+    if !flags.is_empty() {
+        println!("Flags: {}", flags.join(","));
+    }
+    if let Some(timeout) = info.timeout {
+        println!("Timeout: {timeout}s");
+    }
+    if let Some(gc_interval) = info.gc_interval {
+        println!("GC interval: {gc_interval}s");
+    }
+    match info.policy {
+        Some(NftSetPolicy::Performance) => println!("Policy: performance"),
+        Some(NftSetPolicy::Memory) => println!("Policy: memory"),
+        None => {}
     }
+    if let Some(size) = info.size {
+        println!("Size: {size}");
+    }
+    if let Some(comment) = &info.comment {
+        println!("Comment: {comment}");
+    }
+    println!("Number of entries: {}", info.entries);
 }
 
-fn handle_table_command(backend: Backend, command: TableCommands) -> Result<(), String> {
+fn handle_table_command(backend: BackendKind, command: TableCommands) -> Result<(), String> {
     match backend {
-        Backend::Ipset => Err("Table commands are only available for nftables backend".to_string()),
-        Backend::Nftables => match command {
+        BackendKind::Ipset => {
+            Err("Table commands are only available for nftables backend".to_string())
+        }
+        BackendKind::Nftables => match command {
             TableCommands::New { table_name, family } => {
-                nftset_create_table(&family, &table_name).map_err(|e| e.to_string())
+                nftset_create_table(parse_nft_family(&family)?, &table_name)
+                    .map_err(|e| e.to_string())
             }
             TableCommands::Del { table_name, family } => {
-                nftset_delete_table(&family, &table_name).map_err(|e| e.to_string())
+                nftset_delete_table(parse_nft_family(&family)?, &table_name)
+                    .map_err(|e| e.to_string())
+            }
+            TableCommands::Flush { table_name, family } => {
+                nftset_flush_table(parse_nft_family(&family)?, &table_name)
+                    .map_err(|e| e.to_string())
             }
         },
     }
 }
 
+/// Format a listed entry; concatenated values are joined with " . ".
+fn format_entry(entry: Entry) -> String {
+    match entry {
+        Entry::Ip(ip) => ip.to_string(),
+        Entry::SetName(name) => name,
+        Entry::Port {
+            port,
+            port_to: Some(port_to),
+        } => format!("{port}-{port_to}"),
+        Entry::Port { port, .. } => port.to_string(),
+        Entry::Mac(mac) => mac.to_string(),
+        Entry::Mark(mark) => format!("{mark:#x}"),
+        Entry::Iface(iface) => iface,
+        Entry::Verdict(verdict) => verdict.to_string(),
+        Entry::Concat(parts) => parts
+            .into_iter()
+            .map(format_entry)
+            .collect::<Vec<_>>()
+            .join(" . "),
+    }
+}
+
+/// Format an nftables set element with its timeouts and counters, similar to
+/// `nft list set`.
+fn format_nftset_element(element: NftSetElement) -> String {
+    let mut entry = element.entry;
+    if let Entry::Ip(ip) = &mut entry {
+        ip.timeout = None;
+        ip.packets = None;
+        ip.bytes = None;
+    }
+    let mut line = format_entry(entry);
+    if let Some(timeout) = element.timeout {
+        line.push_str(&format!(" timeout {timeout}s"));
+    }
+    if let Some(expiration) = element.expiration {
+        line.push_str(&format!(" expires {expiration}s"));
+    }
+    if let (Some(packets), Some(bytes)) = (element.packets, element.bytes) {
+        line.push_str(&format!(" counter packets {packets} bytes {bytes}"));
+    }
+    line
+}
+
+/// Parse an ipset entry: an IP address, a MAC address (hash:mac), or the
+/// name of a set for list:set.
+fn parse_ipset_entry(entry: &str) -> Result<Entry, String> {
+    if let Ok(mac) = entry.parse::<MacAddr>() {
+        return Ok(Entry::Mac(mac));
+    }
+    let looks_like_range = entry
+        .split_once('-')
+        .is_some_and(|(from, _)| from.parse::<IpAddr>().is_ok());
+    match entry.parse::<IpEntry>() {
+        Ok(ip_entry) => Ok(ip_entry.into()),
+        Err(e) if entry.contains('/') || looks_like_range => Err(e.to_string()),
+        // Anything that doesn't look like an address is a set name (list:set)
+        Err(_) => Ok(Entry::from(entry)),
+    }
+}
+
+/// Look up the key and value types of an nftables set.
+fn nftset_summary(family: NftFamily, table: &str, set_name: &str) -> Option<NftSetSummary> {
+    nftset_list_sets(family, table)
+        .ok()?
+        .into_iter()
+        .find(|set| set.name == set_name)
+}
+
+/// Parse an nftables element for a set of the given key type. Concatenated
+/// values are separated by " . ", as in nft.
+///
+/// Without a known type, the element is a MAC address, an IP address,
+/// <addr>/<cidr>, <from>-<to>, a mark (decimal or 0x-prefixed) or an
+/// interface name.
+fn parse_nftset_entry(entry: &str, set_type: Option<&NftSetType>) -> Result<Entry, String> {
+    match set_type {
+        Some(NftSetType::Ipv4Addr | NftSetType::Ipv6Addr) => entry
+            .parse::<IpEntry>()
+            .map(Entry::from)
+            .map_err(|e| e.to_string()),
+        Some(NftSetType::EtherAddr) => entry
+            .parse::<MacAddr>()
+            .map(Entry::Mac)
+            .map_err(|e| e.to_string()),
+        Some(NftSetType::Mark) => parse_mark(entry).map(Entry::Mark),
+        Some(NftSetType::Ifname) => Ok(Entry::Iface(entry.to_string())),
+        Some(NftSetType::Verdict) => entry
+            .parse::<NftVerdict>()
+            .map(Entry::Verdict)
+            .map_err(|e| e.to_string()),
+        Some(NftSetType::InetService) => parse_port_entry(entry),
+        Some(NftSetType::Concat(fields)) => {
+            let parts: Vec<&str> = entry.split(" . ").map(str::trim).collect();
+            if parts.len() != fields.len() {
+                return Err(format!(
+                    "Expected {} values separated by \" . \": {entry}",
+                    fields.len()
+                ));
+            }
+            parts
+                .into_iter()
+                .zip(fields)
+                .map(|(part, field)| parse_nftset_entry(part, Some(field)))
+                .collect::<Result<_, _>>()
+                .map(Entry::Concat)
+        }
+        None => {
+            if let Ok(mac) = entry.parse::<MacAddr>() {
+                return Ok(Entry::Mac(mac));
+            }
+            if let Ok(mark) = parse_mark(entry) {
+                return Ok(Entry::Mark(mark));
+            }
+            let looks_like_address =
+                entry.starts_with(|c: char| c.is_ascii_digit()) || entry.contains([':', '/']);
+            match entry.parse::<IpEntry>() {
+                Ok(ip_entry) => Ok(ip_entry.into()),
+                Err(e) if looks_like_address => Err(e.to_string()),
+                // Anything else is an interface name
+                Err(_) => Ok(Entry::Iface(entry.to_string())),
+            }
+        }
+    }
+}
+
+/// Parse a mark, in decimal or 0x-prefixed hexadecimal.
+/// Parse a port or an inclusive `<from>-<to>` port range.
+fn parse_port_entry(entry: &str) -> Result<Entry, String> {
+    let parse = |port: &str| {
+        port.trim()
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid port: {entry}"))
+    };
+    match entry.split_once('-') {
+        Some((from, to)) => Ok(Entry::Port {
+            port: parse(from)?,
+            port_to: Some(parse(to)?),
+        }),
+        None => parse(entry).map(Entry::from),
+    }
+}
+
+fn parse_mark(mark: &str) -> Result<u32, String> {
+    match mark.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => mark.parse(),
+    }
+    .map_err(|_| format!("Invalid mark: {mark}"))
+}
+
 fn parse_ipset_type(type_str: &str) -> Result<IpSetType, String> {
     match type_str.to_lowercase().as_str() {
         "hash-ip" | "hash:ip" | "haship" => Ok(IpSetType::HashIp),
         "hash-net" | "hash:net" | "hashnet" => Ok(IpSetType::HashNet),
+        "bitmap-ip" | "bitmap:ip" | "bitmapip" => Ok(IpSetType::BitmapIp),
+        "bitmap-ip-mac" | "bitmap:ip,mac" | "bitmapipmac" => Ok(IpSetType::BitmapIpMac),
+        "list-set" | "list:set" | "listset" => Ok(IpSetType::ListSet),
+        "hash-ip-port" | "hash:ip,port" | "hashipport" => Ok(IpSetType::HashIpPort),
+        "hash-net-port" | "hash:net,port" | "hashnetport" => Ok(IpSetType::HashNetPort),
+        "bitmap-port" | "bitmap:port" | "bitmapport" => Ok(IpSetType::BitmapPort),
+        "hash-mac" | "hash:mac" | "hashmac" => Ok(IpSetType::HashMac),
+        "hash-ip-mark" | "hash:ip,mark" | "hashipmark" => Ok(IpSetType::HashIpMark),
+        "hash-net-iface" | "hash:net,iface" | "hashnetiface" => Ok(IpSetType::HashNetIface),
         _ => Err(format!(
-            "Unknown ipset type: {type_str}. Valid types: hash-ip, hash-net"
+            "Unknown ipset type: {type_str}. Valid types: hash-ip, hash-net, bitmap-ip, bitmap-ip-mac, list-set, hash-ip-port, hash-net-port, bitmap-port, hash-mac, hash-ip-mark, hash-net-iface"
         )),
     }
 }
 
+fn parse_ipset_range(range_str: &str) -> Result<IpSetRange, String> {
+    let invalid = || format!("Invalid range: {range_str}. Expected <from>-<to> or <addr>/<cidr>");
+    if let Some((from, to)) = range_str.split_once('-')
+        && let (Ok(from), Ok(to)) = (from.trim().parse(), to.trim().parse())
+    {
+        Ok(IpSetRange::Ports(from, to))
+    } else if let Some((from, to)) = range_str.split_once('-') {
+        let from = from.trim().parse().map_err(|_| invalid())?;
+        let to = to.trim().parse().map_err(|_| invalid())?;
+        Ok(IpSetRange::FromTo(from, to))
+    } else if let Some((addr, cidr)) = range_str.split_once('/') {
+        let addr = addr.trim().parse().map_err(|_| invalid())?;
+        let cidr: u8 = cidr.trim().parse().map_err(|_| invalid())?;
+        if cidr > 32 {
+            return Err(invalid());
+        }
+        Ok(IpSetRange::Cidr(addr, cidr))
+    } else {
+        Err(invalid())
+    }
+}
+
 fn parse_ipset_family(family_str: &str) -> Result<IpSetFamily, String> {
     match family_str.to_lowercase().as_str() {
         "inet" | "ip" | "ipv4" => Ok(IpSetFamily::Inet),
@@ -402,17 +980,57 @@ fn parse_ipset_family(family_str: &str) -> Result<IpSetFamily, String> {
     }
 }
 
-fn parse_nftset_type(type_str: &str, family: &str) -> Result<NftSetType, String> {
+fn parse_nft_family(family_str: &str) -> Result<NftFamily, String> {
+    family_str.parse().map_err(|_| {
+        format!("Unknown family: {family_str}. Valid families: inet, ip, ip6, arp, bridge, netdev")
+    })
+}
+
+fn parse_nftset_type(type_str: &str, family: NftFamily) -> Result<NftSetType, String> {
+    // Concatenations are written as in nft: "ipv4_addr . inet_service"
+    if type_str.contains('.') {
+        return type_str
+            .split('.')
+            .map(|field| parse_nftset_field_type(field.trim()))
+            .collect::<Result<_, _>>()
+            .map(NftSetType::Concat);
+    }
     // For nftables, we can infer from type string or family
     match type_str.to_lowercase().as_str() {
         "ipv4" | "ipv4_addr" | "hash-ip" | "hash:ip" => Ok(NftSetType::Ipv4Addr),
         "ipv6" | "ipv6_addr" => Ok(NftSetType::Ipv6Addr),
+        "mac" | "ether_addr" => Ok(NftSetType::EtherAddr),
+        "mark" => Ok(NftSetType::Mark),
+        "ifname" | "iface" => Ok(NftSetType::Ifname),
+        "port" | "inet_service" => Ok(NftSetType::InetService),
         _ => {
             // Try to infer from family
-            match family.to_lowercase().as_str() {
-                "ip6" | "ipv6" => Ok(NftSetType::Ipv6Addr),
+            match family {
+                NftFamily::Ip6 => Ok(NftSetType::Ipv6Addr),
                 _ => Ok(NftSetType::Ipv4Addr),
             }
         }
     }
 }
+
+/// Parse one field of a concatenated nftables set type.
+fn parse_nftset_field_type(type_str: &str) -> Result<NftSetType, String> {
+    match type_str.to_lowercase().as_str() {
+        "ipv4" | "ipv4_addr" => Ok(NftSetType::Ipv4Addr),
+        "ipv6" | "ipv6_addr" => Ok(NftSetType::Ipv6Addr),
+        "mac" | "ether_addr" => Ok(NftSetType::EtherAddr),
+        "mark" => Ok(NftSetType::Mark),
+        "ifname" | "iface" => Ok(NftSetType::Ifname),
+        "port" | "inet_service" => Ok(NftSetType::InetService),
+        "verdict" => Ok(NftSetType::Verdict),
+        _ => Err(format!("Invalid nftables type: {type_str}")),
+    }
+}
+
+fn parse_nftset_policy(policy_str: &str) -> Result<NftSetPolicy, String> {
+    match policy_str.to_lowercase().as_str() {
+        "performance" => Ok(NftSetPolicy::Performance),
+        "memory" => Ok(NftSetPolicy::Memory),
+        _ => Err(format!("Invalid set policy: {policy_str}")),
+    }
+}
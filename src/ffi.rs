@@ -0,0 +1,515 @@
+//! C interface, built into a shared library with the `ffi` feature:
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib`.
+//!
+//! The functions take NUL-terminated UTF-8 strings and return an `int`:
+//! zero or a positive value on success, and on failure one of the negative
+//! [`RipsetErrorCode`] codes, whose values never change. The message of the
+//! last failure on the calling thread is available from
+//! [`ripset_last_error`]. Sets are created from text options and entries
+//! are passed as text, in the syntax of the `ipset` and `nft` tools.
+//!
+//! `include/ripset.h` declares these functions for C and C++; it is
+//! generated with
+//! `cbindgen --config cbindgen.toml --output include/ripset.h src/ffi.rs`.
+//! Panics are caught and reported as [`RipsetErrorCode::Internal`], so they
+//! never unwind into C code.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::save::{parse_create_options, parse_entry};
+use crate::{
+    Entry, NftFamily, NftSetCreateOptions, Result, RipsetError, ipset_add, ipset_create, ipset_del,
+    ipset_destroy, ipset_flush, ipset_list_entries, ipset_swap, ipset_test, nftset_add,
+    nftset_create_set, nftset_create_table, nftset_del, nftset_delete_set, nftset_delete_table,
+    nftset_flush, nftset_list_entries, nftset_test,
+};
+
+/// Error codes returned by the C functions. The values are stable.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RipsetErrorCode {
+    /// A null pointer, text that is not UTF-8, or an invalid entry, type,
+    /// family or option
+    InvalidArgument = -1,
+    /// An invalid set or table name
+    InvalidName = -2,
+    /// The set or table does not exist
+    SetNotFound = -3,
+    /// The set is referenced and cannot be destroyed
+    SetInUse = -4,
+    /// The set exists with a different definition
+    SetMismatch = -5,
+    /// The entry is not in the set
+    EntryNotFound = -6,
+    /// The entry is already in the set
+    EntryExists = -7,
+    /// The set is full
+    SetFull = -8,
+    /// CAP_NET_ADMIN is required
+    PermissionDenied = -9,
+    /// The entry does not fit the type of the set
+    TypeMismatch = -10,
+    /// The kernel lacks a feature the request needs
+    KernelUnsupported = -11,
+    /// The table is owned by another process
+    TableOwned = -12,
+    /// The kernel did not answer in time
+    Timeout = -13,
+    /// The kernel refused the request, see `ripset_last_errno`
+    Kernel = -14,
+    /// The netlink socket failed
+    Io = -15,
+    /// The kernel answered with an unexpected message
+    Protocol = -16,
+    /// The platform is not Linux
+    UnsupportedPlatform = -17,
+    /// Notifications were lost
+    EventsLost = -18,
+    /// A bug in ripset
+    Internal = -19,
+}
+
+impl From<&RipsetError> for RipsetErrorCode {
+    fn from(error: &RipsetError) -> Self {
+        match error {
+            RipsetError::InvalidOption(_)
+            | RipsetError::InvalidAddress(_)
+            | RipsetError::InvalidMacAddress(_)
+            | RipsetError::InvalidAddressFamily => RipsetErrorCode::InvalidArgument,
+            RipsetError::InvalidSetName(_) | RipsetError::InvalidTableName(_) => {
+                RipsetErrorCode::InvalidName
+            }
+            RipsetError::SetNotFound(_) => RipsetErrorCode::SetNotFound,
+            RipsetError::SetInUse(..) => RipsetErrorCode::SetInUse,
+            RipsetError::SetMismatch(..) => RipsetErrorCode::SetMismatch,
+            RipsetError::EntryNotFound => RipsetErrorCode::EntryNotFound,
+            RipsetError::EntryExists => RipsetErrorCode::EntryExists,
+            RipsetError::SetFull(_) => RipsetErrorCode::SetFull,
+            RipsetError::PermissionDenied => RipsetErrorCode::PermissionDenied,
+            RipsetError::TypeMismatch(_) => RipsetErrorCode::TypeMismatch,
+            RipsetError::KernelUnsupported(_) => RipsetErrorCode::KernelUnsupported,
+            RipsetError::TableOwned(..) => RipsetErrorCode::TableOwned,
+            RipsetError::Timeout => RipsetErrorCode::Timeout,
+            RipsetError::NetlinkError(_) | RipsetError::KernelError { .. } => {
+                RipsetErrorCode::Kernel
+            }
+            RipsetError::SocketError(_) | RipsetError::SendRecvError => RipsetErrorCode::Io,
+            RipsetError::ProtocolError => RipsetErrorCode::Protocol,
+            RipsetError::UnsupportedPlatform => RipsetErrorCode::UnsupportedPlatform,
+            RipsetError::EventsLost => RipsetErrorCode::EventsLost,
+        }
+    }
+}
+
+/// The set stores CIDR blocks and address ranges
+pub const RIPSET_NFT_INTERVAL: u32 = 1;
+/// Elements keep packet and byte counters
+pub const RIPSET_NFT_COUNTERS: u32 = 1 << 1;
+/// Adjacent and overlapping ranges are merged on insertion
+pub const RIPSET_NFT_AUTO_MERGE: u32 = 1 << 2;
+
+/// Called with each entry of a listed set, as text, and the `ctx` pointer
+/// given to the listing function. The text is only valid during the call.
+pub type RipsetEntryCallback = Option<unsafe extern "C" fn(entry: *const c_char, ctx: *mut c_void)>;
+
+thread_local! {
+    /// Message and errno of the last failure on this thread.
+    static LAST_ERROR: RefCell<(Option<CString>, c_int)> = const { RefCell::new((None, 0)) };
+}
+
+/// Run `op`, turning its error or panic into an error code.
+fn call(op: impl FnOnce() -> Result<c_int>) -> c_int {
+    let (code, message, errno) = match panic::catch_unwind(AssertUnwindSafe(op)) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(e)) => (
+            RipsetErrorCode::from(&e),
+            e.to_string(),
+            e.errno().unwrap_or(0),
+        ),
+        Err(_) => (RipsetErrorCode::Internal, "internal error".to_string(), 0),
+    };
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = (message, errno));
+    code as c_int
+}
+
+/// Borrow a string argument.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string that outlives
+/// the call.
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(RipsetError::InvalidOption("null pointer".to_string()));
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| RipsetError::InvalidOption("string is not UTF-8".to_string()))
+}
+
+/// Pass each entry to `callback`, returning the number of entries.
+fn list_to(entries: Vec<Entry>, callback: RipsetEntryCallback, ctx: *mut c_void) -> Result<c_int> {
+    let callback =
+        callback.ok_or_else(|| RipsetError::InvalidOption("null callback".to_string()))?;
+    for entry in &entries {
+        let text =
+            CString::new(entry.to_string().replace('\0', " ")).expect("NUL bytes are replaced");
+        unsafe { callback(text.as_ptr(), ctx) };
+    }
+    Ok(c_int::try_from(entries.len()).unwrap_or(c_int::MAX))
+}
+
+/// Message of the last failure on the calling thread, or NULL if nothing
+/// failed yet. The string stays valid until the next failure on the thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn ripset_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .0
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// The errno the kernel answered the last failed request of the calling
+/// thread with, or 0 if it was not a kernel error.
+#[unsafe(no_mangle)]
+pub extern "C" fn ripset_last_errno() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().1)
+}
+
+/// Create an ipset. `options` holds the type and the options as in
+/// `ipset create`, e.g. `hash:net family inet6 timeout 300`.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_create(
+    setname: *const c_char,
+    options: *const c_char,
+) -> c_int {
+    call(|| {
+        let options = parse_create_options(unsafe { str_arg(options)? })?;
+        ipset_create(unsafe { str_arg(setname)? }, &options).map(|()| 0)
+    })
+}
+
+/// Destroy an ipset.
+///
+/// # Safety
+///
+/// `setname` must be a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_destroy(setname: *const c_char) -> c_int {
+    call(|| ipset_destroy(unsafe { str_arg(setname)? }).map(|()| 0))
+}
+
+/// Remove all entries from an ipset.
+///
+/// # Safety
+///
+/// `setname` must be a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_flush(setname: *const c_char) -> c_int {
+    call(|| ipset_flush(unsafe { str_arg(setname)? }).map(|()| 0))
+}
+
+/// Exchange the contents of two ipsets.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_swap(
+    setname1: *const c_char,
+    setname2: *const c_char,
+) -> c_int {
+    call(|| ipset_swap(unsafe { str_arg(setname1)? }, unsafe { str_arg(setname2)? }).map(|()| 0))
+}
+
+/// Add an entry to an ipset. `entry` is written as in `ipset add`, e.g.
+/// `192.0.2.0/24,tcp:80 timeout 60`.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_add(setname: *const c_char, entry: *const c_char) -> c_int {
+    call(|| {
+        let entry = parse_entry(unsafe { str_arg(entry)? })?;
+        ipset_add(unsafe { str_arg(setname)? }, entry).map(|()| 0)
+    })
+}
+
+/// Delete an entry from an ipset.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_del(setname: *const c_char, entry: *const c_char) -> c_int {
+    call(|| {
+        let entry = parse_entry(unsafe { str_arg(entry)? })?;
+        ipset_del(unsafe { str_arg(setname)? }, entry).map(|()| 0)
+    })
+}
+
+/// Test whether an entry is in an ipset: 1 if it is, 0 if not.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_test(setname: *const c_char, entry: *const c_char) -> c_int {
+    call(|| {
+        let entry = parse_entry(unsafe { str_arg(entry)? })?;
+        ipset_test(unsafe { str_arg(setname)? }, entry).map(c_int::from)
+    })
+}
+
+/// Call `callback` with each entry of an ipset. Returns the number of
+/// entries.
+///
+/// # Safety
+///
+/// `setname` must be a NUL-terminated string, and `callback` must be safe
+/// to call with `ctx`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_ipset_list(
+    setname: *const c_char,
+    callback: RipsetEntryCallback,
+    ctx: *mut c_void,
+) -> c_int {
+    call(|| {
+        list_to(
+            ipset_list_entries(unsafe { str_arg(setname)? })?,
+            callback,
+            ctx,
+        )
+    })
+}
+
+/// Create an nftables table. `family` is `ip`, `ip6`, `inet`, `arp`,
+/// `bridge` or `netdev`.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_create_table(
+    family: *const c_char,
+    table: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        nftset_create_table(family, unsafe { str_arg(table)? }).map(|()| 0)
+    })
+}
+
+/// Delete an nftables table with everything in it.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_delete_table(
+    family: *const c_char,
+    table: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        nftset_delete_table(family, unsafe { str_arg(table)? }).map(|()| 0)
+    })
+}
+
+/// Create an nftables set. `set_type` is an nft type such as `ipv4_addr`
+/// or `ipv4_addr . inet_service`, `flags` a combination of the
+/// `RIPSET_NFT_*` flags, and `timeout` the default element timeout in
+/// seconds, or 0 for none.
+///
+/// # Safety
+///
+/// The string arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_create_set(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+    set_type: *const c_char,
+    flags: u32,
+    timeout: u32,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let options = NftSetCreateOptions {
+            set_type: unsafe { str_arg(set_type)? }.parse()?,
+            interval: flags & RIPSET_NFT_INTERVAL != 0,
+            counters: flags & RIPSET_NFT_COUNTERS != 0,
+            auto_merge: flags & RIPSET_NFT_AUTO_MERGE != 0,
+            timeout: (timeout != 0).then_some(timeout),
+            ..Default::default()
+        };
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        nftset_create_set(family, table, setname, &options).map(|()| 0)
+    })
+}
+
+/// Delete an nftables set.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_delete_set(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        nftset_delete_set(family, table, setname).map(|()| 0)
+    })
+}
+
+/// Remove all elements from an nftables set.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_flush(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        nftset_flush(family, table, setname).map(|()| 0)
+    })
+}
+
+/// Add an element to an nftables set. `entry` is written as in nft, e.g.
+/// `192.0.2.0/24` or `192.0.2.1 . 80`.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_add(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+    entry: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let entry: Entry = unsafe { str_arg(entry)? }.parse()?;
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        nftset_add(family, table, setname, entry).map(|()| 0)
+    })
+}
+
+/// Delete an element from an nftables set.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_del(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+    entry: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let entry: Entry = unsafe { str_arg(entry)? }.parse()?;
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        nftset_del(family, table, setname, entry).map(|()| 0)
+    })
+}
+
+/// Test whether an element is in an nftables set: 1 if it is, 0 if not.
+///
+/// # Safety
+///
+/// The arguments must be NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_test(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+    entry: *const c_char,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let entry: Entry = unsafe { str_arg(entry)? }.parse()?;
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        nftset_test(family, table, setname, entry).map(c_int::from)
+    })
+}
+
+/// Call `callback` with each element of an nftables set. Returns the number
+/// of elements.
+///
+/// # Safety
+///
+/// The string arguments must be NUL-terminated strings, and `callback` must
+/// be safe to call with `ctx`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ripset_nft_list(
+    family: *const c_char,
+    table: *const c_char,
+    setname: *const c_char,
+    callback: RipsetEntryCallback,
+    ctx: *mut c_void,
+) -> c_int {
+    call(|| {
+        let family: NftFamily = unsafe { str_arg(family)? }.parse()?;
+        let (table, setname) = unsafe { (str_arg(table)?, str_arg(setname)?) };
+        list_to(nftset_list_entries(family, table, setname)?, callback, ctx)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(
+            RipsetErrorCode::from(&RipsetError::SetNotFound("x".to_string())) as c_int,
+            -3
+        );
+        assert_eq!(
+            RipsetErrorCode::from(&RipsetError::NetlinkError(22)),
+            RipsetErrorCode::Kernel
+        );
+
+        // Argument errors are reported without reaching the kernel
+        let code = unsafe { ripset_ipset_add(std::ptr::null(), c"10.0.0.1".as_ptr()) };
+        assert_eq!(code, RipsetErrorCode::InvalidArgument as c_int);
+        let message = unsafe { CStr::from_ptr(ripset_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "Invalid option: null pointer");
+
+        let code = unsafe { ripset_ipset_create(c"myset".as_ptr(), c"hash:nothing".as_ptr()) };
+        assert_eq!(code, RipsetErrorCode::InvalidArgument as c_int);
+        assert_eq!(ripset_last_errno(), 0);
+
+        let code = unsafe {
+            ripset_nft_add(
+                c"nofamily".as_ptr(),
+                c"t".as_ptr(),
+                c"s".as_ptr(),
+                c"1".as_ptr(),
+            )
+        };
+        assert_eq!(code, RipsetErrorCode::InvalidArgument as c_int);
+    }
+}
@@ -0,0 +1,100 @@
+//! Lookup of an address across all sets.
+//!
+//! [`find_ip`] answers "which sets hold this address?" by testing it against
+//! every ipset, and optionally every nftables set, that stores plain
+//! addresses or networks.
+
+use std::net::IpAddr;
+
+use crate::{
+    IpSetFamily, IpSetInfo, IpSetRange, NftSetType, Result, RipsetError, SetRef, ipset_list_sets,
+    ipset_test, nftset_list_sets, nftset_list_tables_all, nftset_test,
+};
+
+/// Find every set that matches an address.
+///
+/// Each ipset of type `hash:ip`, `hash:net` or `bitmap:ip` of the address
+/// family is tested, so an address inside a stored network counts as a
+/// match and `nomatch` entries are honoured like the kernel does. With
+/// `include_nft`, every nftables set and map keyed by addresses of the
+/// family is tested as well, in all tables of all families, anonymous sets
+/// of rules included.
+///
+/// Sets whose entries combine the address with other fields, such as
+/// `hash:ip,port` or concatenations, are not searched. Sets deleted while
+/// the search runs are skipped.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{SetRef, find_ip};
+///
+/// for set in find_ip("192.0.2.1".parse().unwrap(), true).unwrap() {
+///     match set {
+///         SetRef::Ipset(name) => println!("ipset {name}"),
+///         SetRef::Nft { family, table, name } => println!("nft {family} {table} {name}"),
+///     }
+/// }
+/// ```
+pub fn find_ip(addr: IpAddr, include_nft: bool) -> Result<Vec<SetRef>> {
+    let mut found = Vec::new();
+
+    for info in ipset_list_sets()? {
+        if !ipset_searchable(&info, addr) {
+            continue;
+        }
+        match ipset_test(&info.name, addr) {
+            Ok(true) => found.push(SetRef::Ipset(info.name)),
+            Ok(false) | Err(RipsetError::SetNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    if include_nft {
+        let key_type = match addr {
+            IpAddr::V4(_) => NftSetType::Ipv4Addr,
+            IpAddr::V6(_) => NftSetType::Ipv6Addr,
+        };
+        for (family, table) in nftset_list_tables_all()? {
+            let sets = match nftset_list_sets(family, &table) {
+                Ok(sets) => sets,
+                Err(RipsetError::SetNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            for set in sets {
+                if set.set_type.as_ref() != Some(&key_type) {
+                    continue;
+                }
+                match nftset_test(family, &table, &set.name, addr) {
+                    Ok(true) => found.push(SetRef::nft(family, &table, &set.name)),
+                    Ok(false) | Err(RipsetError::SetNotFound(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Whether an ipset holds entries made of an address alone, of the family
+/// of `addr` and, for bitmaps, with `addr` in its range.
+fn ipset_searchable(info: &IpSetInfo, addr: IpAddr) -> bool {
+    let family = info.family.unwrap_or(IpSetFamily::Inet);
+    let same_family = matches!(
+        (family, addr),
+        (IpSetFamily::Inet, IpAddr::V4(_)) | (IpSetFamily::Inet6, IpAddr::V6(_))
+    );
+    match info.type_name.as_str() {
+        "hash:ip" | "hash:net" => same_family,
+        "bitmap:ip" => match (addr, &info.range) {
+            (IpAddr::V4(addr), Some(IpSetRange::FromTo(from, to))) => (*from..=*to).contains(&addr),
+            (IpAddr::V4(addr), Some(IpSetRange::Cidr(net, prefix))) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                u32::from(addr) & mask == u32::from(*net) & mask
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -3,42 +3,98 @@
 //! This module provides functions to add, test, and delete IP addresses
 //! from Linux ipset using the netlink protocol.
 
-use std::net::IpAddr;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::io::{AsFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use crate::netlink::{
-    MsgBuffer, NFNL_SUBSYS_IPSET, NLA_F_NESTED, NLM_F_ACK, NLM_F_DUMP, NLM_F_REQUEST,
-    NetlinkSocket, NfGenMsg, NlAttr, NlMsgHdr, is_nlmsg_done, nla_align, parse_nlmsg_error,
+    DumpStream, MsgBuffer, NFNL_SUBSYS_IPSET, NLM_F_ACK, NLM_F_DUMP, NLM_F_EXCL, NLM_F_REQUEST,
+    NetlinkSocket, NfGenMsg, NlAttrIter, NlMsgHdr, block_on, dump_retry_async, errno_error,
+    nla_get_str, nla_get_u16_be, nla_get_u32_be, nla_get_u64_be, parse_nlmsg_error,
+    with_msg_buffer, with_netns_fd, with_netns_fd_async,
 };
-use crate::{IpEntry, IpSetError, Result};
+use crate::{BackendCapabilities, Entry, IpEntry, MacAddr, Result, RipsetError};
 
 // ipset protocol constants
 const IPSET_PROTOCOL: u8 = 7;
+const IPSET_PROTOCOL_MIN: u8 = 6;
 const IPSET_MAXNAMELEN: usize = 32;
 
 // ipset commands
+const IPSET_CMD_PROTOCOL: u8 = 1;
 const IPSET_CMD_CREATE: u8 = 2;
 const IPSET_CMD_DESTROY: u8 = 3;
 const IPSET_CMD_FLUSH: u8 = 4;
+const IPSET_CMD_SWAP: u8 = 6;
 const IPSET_CMD_LIST: u8 = 7;
 const IPSET_CMD_ADD: u8 = 9;
 const IPSET_CMD_DEL: u8 = 10;
 const IPSET_CMD_TEST: u8 = 11;
+const IPSET_CMD_TYPE: u8 = 13;
 
 // ipset attributes at command level
 const IPSET_ATTR_PROTOCOL: u16 = 1;
 const IPSET_ATTR_SETNAME: u16 = 2;
 const IPSET_ATTR_TYPENAME: u16 = 3;
+const IPSET_ATTR_SETNAME2: u16 = IPSET_ATTR_TYPENAME;
 const IPSET_ATTR_REVISION: u16 = 4;
 const IPSET_ATTR_FAMILY: u16 = 5;
+const IPSET_ATTR_FLAGS: u16 = 6;
 const IPSET_ATTR_DATA: u16 = 7;
 const IPSET_ATTR_LINENO: u16 = 9;
+const IPSET_ATTR_PROTOCOL_MIN: u16 = 10;
+const IPSET_ATTR_REVISION_MIN: u16 = IPSET_ATTR_PROTOCOL_MIN;
 
 // ipset CADT attributes (inside IPSET_ATTR_DATA)
 const IPSET_ATTR_IP: u16 = 1;
+const IPSET_ATTR_IP_TO: u16 = 2;
+const IPSET_ATTR_CIDR: u16 = 3;
+const IPSET_ATTR_PORT: u16 = 4;
+const IPSET_ATTR_PORT_TO: u16 = 5;
 const IPSET_ATTR_TIMEOUT: u16 = 6;
+const IPSET_ATTR_PROTO: u16 = 7;
+const IPSET_ATTR_CADT_FLAGS: u16 = 8;
+const IPSET_ATTR_MARK: u16 = 10;
 const IPSET_ATTR_CADT_MAX: u16 = 16;
+const IPSET_ATTR_INITVAL: u16 = IPSET_ATTR_CADT_MAX + 1; // 17
 const IPSET_ATTR_HASHSIZE: u16 = IPSET_ATTR_CADT_MAX + 2; // 18
 const IPSET_ATTR_MAXELEM: u16 = IPSET_ATTR_CADT_MAX + 3; // 19
+const IPSET_ATTR_BUCKETSIZE: u16 = IPSET_ATTR_CADT_MAX + 5; // 21
+const IPSET_ATTR_SIZE: u16 = IPSET_ATTR_CADT_MAX + 7; // 23
+const IPSET_ATTR_ELEMENTS: u16 = IPSET_ATTR_CADT_MAX + 8; // 24
+const IPSET_ATTR_REFERENCES: u16 = IPSET_ATTR_CADT_MAX + 9; // 25
+const IPSET_ATTR_MEMSIZE: u16 = IPSET_ATTR_CADT_MAX + 10; // 26
+
+// ipset ADT attributes (inside IPSET_ATTR_DATA of add/del/test)
+const IPSET_ATTR_ETHER: u16 = IPSET_ATTR_CADT_MAX + 1; // 17
+const IPSET_ATTR_NAME: u16 = IPSET_ATTR_CADT_MAX + 2; // 18
+const IPSET_ATTR_IFACE: u16 = IPSET_ATTR_CADT_MAX + 7; // 23
+const IPSET_ATTR_BYTES: u16 = IPSET_ATTR_CADT_MAX + 8; // 24
+const IPSET_ATTR_PACKETS: u16 = IPSET_ATTR_CADT_MAX + 9; // 25
+const IPSET_ATTR_COMMENT: u16 = IPSET_ATTR_CADT_MAX + 10; // 26
+const IPSET_ATTR_SKBMARK: u16 = IPSET_ATTR_CADT_MAX + 11; // 27
+const IPSET_ATTR_SKBPRIO: u16 = IPSET_ATTR_CADT_MAX + 12; // 28
+const IPSET_ATTR_SKBQUEUE: u16 = IPSET_ATTR_CADT_MAX + 13; // 29
+
+// ipset command flags (IPSET_ATTR_FLAGS)
+const IPSET_FLAG_LIST_HEADER: u32 = 1 << 2;
+
+// ipset CADT flags (IPSET_ATTR_CADT_FLAGS)
+const IPSET_FLAG_NOMATCH: u32 = 1 << 2;
+const IPSET_FLAG_WITH_COUNTERS: u32 = 1 << 3;
+const IPSET_FLAG_WITH_COMMENT: u32 = 1 << 4;
+const IPSET_FLAG_WITH_FORCEADD: u32 = 1 << 5;
+const IPSET_FLAG_WITH_SKBINFO: u32 = 1 << 6;
 
 // ipset ADT attributes (for element lists)
 const IPSET_ATTR_ADT: u16 = 8;
@@ -49,112 +105,747 @@ const IPSET_ATTR_IPADDR_IPV6: u16 = 2;
 
 const BUFF_SZ: usize = 1024;
 
+/// Longest entry comment the kernel keeps, in bytes.
+const IPSET_MAX_COMMENT_SIZE: usize = 255;
+
+/// Size at which a bulk request is closed. Requests stay well below the
+/// 64 KiB limit of the IPSET_ATTR_ADT attribute, since the kernel echoes a
+/// failed request in its error message.
+const IPSET_BULK_MSG_LEN: usize = 8192;
+
+/// Number of bulk requests sent together with a single `sendmsg`.
+const IPSET_BULK_MSGS_PER_SEND: usize = 16;
+
+/// Number of requests an [`IpsetPipeline`] sends together, few enough for
+/// their answers to fit into the socket receive buffer.
+const IPSET_PIPELINE_WINDOW: usize = 128;
+
 /// Build the netlink message type for ipset commands.
 fn ipset_msg_type(cmd: u8) -> u16 {
     ((NFNL_SUBSYS_IPSET as u16) << 8) | (cmd as u16)
 }
 
-/// Internal function to perform ipset operations.
-fn ipset_operate(setname: &str, entry: &IpEntry, cmd: u8) -> Result<()> {
-    // Validate setname
-    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
-    }
-
-    // Determine address family
-    let (family, addr_type, addr_bytes): (u8, u16, Vec<u8>) = match entry.addr {
-        IpAddr::V4(v4) => (
-            libc::AF_INET as u8,
-            IPSET_ATTR_IPADDR_IPV4,
-            v4.octets().to_vec(),
-        ),
-        IpAddr::V6(v6) => (
-            libc::AF_INET6 as u8,
-            IPSET_ATTR_IPADDR_IPV6,
-            v6.octets().to_vec(),
-        ),
-    };
+/// Protocol versions supported by the kernel ipset subsystem
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpSetProtocol {
+    /// Protocol version spoken by the kernel
+    pub version: u8,
+    /// Oldest protocol version accepted by the kernel
+    pub min_version: u8,
+}
+
+/// Query the ipset protocol version of the kernel.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_protocol;
+///
+/// let protocol = ipset_protocol().unwrap();
+/// println!("kernel speaks ipset protocol {}", protocol.version);
+/// ```
+pub fn ipset_protocol() -> Result<IpSetProtocol> {
+    block_on(ipset_protocol_async())
+}
 
-    // Build the netlink message
+/// [`ipset_protocol`] for async code.
+pub(crate) async fn ipset_protocol_async() -> Result<IpSetProtocol> {
     let mut buf = MsgBuffer::new(BUFF_SZ);
 
-    // Netlink message header
-    buf.put_nlmsghdr(ipset_msg_type(cmd), NLM_F_REQUEST | NLM_F_ACK, 0);
+    buf.put_nlmsghdr(ipset_msg_type(IPSET_CMD_PROTOCOL), NLM_F_REQUEST, 0);
+    buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
+
+    // Ask with the oldest version we speak so that older kernels answer too
+    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, IPSET_PROTOCOL_MIN);
 
-    // Netfilter generic message header
-    buf.put_nfgenmsg(family, 0, 0);
+    buf.finalize_nlmsg();
 
-    // IPSET_ATTR_PROTOCOL
-    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, IPSET_PROTOCOL);
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
 
-    // IPSET_ATTR_SETNAME
-    buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+    if recv_len < NlMsgHdr::SIZE + NfGenMsg::SIZE {
+        return Err(RipsetError::ProtocolError);
+    }
+
+    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
+        return Err(ipset_errno_error(-error, &recv_buf[..recv_len]));
+    }
+
+    let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+    let msg_end = (hdr.nlmsg_len as usize).min(recv_len);
+    let mut version = None;
+    let mut min_version = None;
+    for (attr_type, payload) in NlAttrIter::new(&recv_buf[NlMsgHdr::SIZE + NfGenMsg::SIZE..msg_end])
+    {
+        match attr_type {
+            IPSET_ATTR_PROTOCOL => version = payload.first().copied(),
+            IPSET_ATTR_PROTOCOL_MIN => min_version = payload.first().copied(),
+            _ => {}
+        }
+    }
+
+    let version = version.ok_or(RipsetError::ProtocolError)?;
+    Ok(IpSetProtocol {
+        version,
+        // Kernels before protocol 7 don't report a minimum
+        min_version: min_version.unwrap_or(version),
+    })
+}
+
+/// Protocol version used for requests: the newest version both we and the
+/// kernel speak, negotiated once per process.
+fn protocol_version() -> u8 {
+    static VERSION: OnceLock<u8> = OnceLock::new();
+    *VERSION.get_or_init(|| match ipset_protocol() {
+        Ok(protocol) => protocol.version.clamp(IPSET_PROTOCOL_MIN, IPSET_PROTOCOL),
+        // Requests will report the underlying error themselves
+        Err(_) => IPSET_PROTOCOL,
+    })
+}
+
+/// Add a nested IP address attribute (e.g. IPSET_ATTR_IP, IPSET_ATTR_IP_TO).
+fn put_ip_attr(buf: &mut MsgBuffer, attr_type: u16, addr: &IpAddr) {
+    let offset = buf.start_nested(attr_type);
+
+    // IP address (IPv4 or IPv6), always in network byte order
+    let flags = crate::netlink::NLA_F_NET_BYTEORDER;
+    match addr {
+        IpAddr::V4(v4) => buf.put_attr_bytes(IPSET_ATTR_IPADDR_IPV4 | flags, &v4.octets()),
+        IpAddr::V6(v6) => buf.put_attr_bytes(IPSET_ATTR_IPADDR_IPV6 | flags, &v6.octets()),
+    }
+
+    buf.end_nested(offset);
+}
+
+/// Split the address range `from..=to` into the CIDR blocks covering it.
+fn range_to_cidrs(from: IpAddr, to: IpAddr) -> Result<Vec<(IpAddr, u8)>> {
+    let (start, end, bits) = match (from, to) {
+        (IpAddr::V4(from), IpAddr::V4(to)) => (u32::from(from) as u128, u32::from(to) as u128, 32),
+        (IpAddr::V6(from), IpAddr::V6(to)) => (u128::from(from), u128::from(to), 128),
+        _ => return Err(RipsetError::InvalidAddressFamily),
+    };
+    if start > end {
+        return Err(RipsetError::InvalidOption(format!(
+            "invalid range {from}-{to}"
+        )));
+    }
+
+    let to_addr = |value: u128| match bits {
+        32 => IpAddr::V4((value as u32).into()),
+        _ => IpAddr::V6(value.into()),
+    };
+
+    // Offset of the last address in a block of 2^size_bits addresses
+    let last_offset = |size_bits: u32| match size_bits {
+        128 => u128::MAX,
+        _ => (1u128 << size_bits) - 1,
+    };
+
+    let mut blocks = Vec::new();
+    let mut current = start;
+    loop {
+        // Largest block aligned at `current` that doesn't go past `end`
+        let mut size_bits = current.trailing_zeros().min(bits);
+        while last_offset(size_bits) > end - current {
+            size_bits -= 1;
+        }
+        blocks.push((to_addr(current), (bits - size_bits) as u8));
+
+        let last = current + last_offset(size_bits);
+        if last >= end {
+            break;
+        }
+        current = last + 1;
+    }
+    Ok(blocks)
+}
+
+/// Check an add, delete or test entry and bring it into the form sent to
+/// the kernel.
+fn ipset_adt_entries(entry: &Entry, cmd: u8) -> Result<Cow<'_, [Entry]>> {
+    if let Entry::SetName(name) = entry
+        && (name.is_empty() || name.len() >= IPSET_MAXNAMELEN)
+    {
+        return Err(RipsetError::InvalidSetName(name.to_string()));
+    }
+
+    // Concatenations map onto the fields of an address entry
+    let entry = match entry {
+        Entry::Concat(parts) => Cow::Owned(Entry::Ip(concat_to_ip_entry(parts)?)),
+        Entry::Mark(_) | Entry::Iface(_) => {
+            return Err(RipsetError::InvalidOption(
+                "marks and interfaces must follow an address in ipset entries".to_string(),
+            ));
+        }
+        Entry::Verdict(_) => {
+            return Err(RipsetError::InvalidOption(
+                "verdicts are not supported by ipset".to_string(),
+            ));
+        }
+        entry => Cow::Borrowed(entry),
+    };
+
+    // ipset save writes comments between quotes it can't escape
+    if let Entry::Ip(IpEntry {
+        comment: Some(comment),
+        ..
+    }) = &*entry
+        && (comment.len() > IPSET_MAX_COMMENT_SIZE || comment.contains(['"', '\n', '\0']))
+    {
+        return Err(RipsetError::InvalidOption(format!(
+            "invalid comment: {comment:?}"
+        )));
+    }
+
+    // Port ranges are only handled by the kernel when adding or deleting
+    let port_range = match &*entry {
+        Entry::Ip(ip) => ip.port_to.is_some(),
+        Entry::Port { port_to, .. } => port_to.is_some(),
+        _ => false,
+    };
+    if port_range && cmd == IPSET_CMD_TEST {
+        return Err(RipsetError::InvalidOption(
+            "port ranges can only be added or deleted".to_string(),
+        ));
+    }
+
+    // Range entries are sent as the CIDR blocks covering them, which all
+    // set types accept (IPv6 ranges only for net types)
+    if let Entry::Ip(ip) = &*entry
+        && let Some(ip_to) = ip.ip_to
+    {
+        if cmd == IPSET_CMD_TEST || ip.cidr.is_some() {
+            return Err(RipsetError::InvalidOption(
+                "ranges can only be added or deleted".to_string(),
+            ));
+        }
+        let blocks = range_to_cidrs(ip.addr, ip_to)?
+            .into_iter()
+            .map(|(addr, cidr)| {
+                Entry::Ip(IpEntry {
+                    addr,
+                    cidr: Some(cidr),
+                    ip_to: None,
+                    ..ip.clone()
+                })
+            })
+            .collect();
+        return Ok(Cow::Owned(blocks));
+    }
+
+    // Most entries are sent as they are, without a copy
+    Ok(match entry {
+        Cow::Borrowed(entry) => Cow::Borrowed(std::slice::from_ref(entry)),
+        Cow::Owned(entry) => Cow::Owned(vec![entry]),
+    })
+}
 
-    // IPSET_ATTR_DATA (nested)
+/// Address family of the request carrying an entry.
+fn ipset_entry_family(entry: &Entry) -> u8 {
+    match entry {
+        Entry::Ip(IpEntry {
+            addr: IpAddr::V6(_),
+            ..
+        }) => libc::AF_INET6 as u8,
+        _ => libc::AF_INET as u8,
+    }
+}
+
+/// Add an entry checked by [`ipset_adt_entries`] as an IPSET_ATTR_DATA
+/// attribute. A non-zero `lineno` is reported back if the kernel refuses
+/// the entry of an element list.
+fn put_adt_data(buf: &mut MsgBuffer, entry: &Entry, lineno: u32) {
     let data_offset = buf.start_nested(IPSET_ATTR_DATA);
 
-    // IPSET_ATTR_IP (nested)
-    let ip_offset = buf.start_nested(IPSET_ATTR_IP);
+    match entry {
+        Entry::Ip(entry) => {
+            // IPSET_ATTR_IP (nested)
+            put_ip_attr(buf, IPSET_ATTR_IP, &entry.addr);
 
-    // IP address (IPv4 or IPv6)
-    let len = crate::netlink::NlAttr::SIZE + addr_bytes.len();
-    buf.put_u16(len as u16);
-    buf.put_u16(addr_type | crate::netlink::NLA_F_NET_BYTEORDER);
-    buf.put_bytes(&addr_bytes);
-    buf.align();
+            // IPSET_ATTR_CIDR (optional, for net types)
+            if let Some(cidr) = entry.cidr {
+                buf.put_attr_u8(IPSET_ATTR_CIDR, cidr);
+            }
 
-    buf.end_nested(ip_offset);
+            // Protocol and port (optional, for ip,port types)
+            if let Some(port) = entry.port {
+                buf.put_attr_u16_be(IPSET_ATTR_PORT, port);
+                if let Some(port_to) = entry.port_to {
+                    buf.put_attr_u16_be(IPSET_ATTR_PORT_TO, port_to);
+                }
+                let proto = entry.proto.unwrap_or(libc::IPPROTO_TCP as u8);
+                buf.put_attr_u8(IPSET_ATTR_PROTO, proto);
+            }
 
-    // IPSET_ATTR_TIMEOUT (optional)
-    if let Some(timeout) = entry.timeout {
-        buf.put_attr_u32_be(IPSET_ATTR_TIMEOUT, timeout);
+            // IPSET_ATTR_ETHER (optional, for ip,mac types)
+            if let Some(mac) = entry.mac {
+                buf.put_attr_bytes(IPSET_ATTR_ETHER, &mac.0);
+            }
+
+            // IPSET_ATTR_MARK (optional, for ip,mark types)
+            if let Some(mark) = entry.mark {
+                buf.put_attr_u32_be(IPSET_ATTR_MARK, mark);
+            }
+
+            // IPSET_ATTR_IFACE (optional, for net,iface types)
+            if let Some(iface) = &entry.iface {
+                buf.put_attr_str(IPSET_ATTR_IFACE, iface);
+            }
+
+            // IPSET_ATTR_TIMEOUT (optional)
+            if let Some(timeout) = entry.timeout {
+                buf.put_attr_u32_be(IPSET_ATTR_TIMEOUT, timeout);
+            }
+
+            // Initial counter values (optional, for sets with counters)
+            if let Some(packets) = entry.packets {
+                buf.put_attr_u64_be(IPSET_ATTR_PACKETS, packets);
+            }
+            if let Some(bytes) = entry.bytes {
+                buf.put_attr_u64_be(IPSET_ATTR_BYTES, bytes);
+            }
+
+            // IPSET_ATTR_COMMENT (optional, for sets with comments)
+            if let Some(comment) = &entry.comment {
+                buf.put_attr_str(IPSET_ATTR_COMMENT, comment);
+            }
+
+            // skbinfo values (optional, for sets with skbinfo)
+            if let Some((mark, mask)) = entry.skbmark {
+                buf.put_attr_u64_be(IPSET_ATTR_SKBMARK, ((mark as u64) << 32) | mask as u64);
+            }
+            if let Some(prio) = entry.skbprio {
+                buf.put_attr_u32_be(IPSET_ATTR_SKBPRIO, prio);
+            }
+            if let Some(queue) = entry.skbqueue {
+                buf.put_attr_u16_be(IPSET_ATTR_SKBQUEUE, queue);
+            }
+
+            // Exception entry (hash:net types). For a test, the flag asks
+            // the kernel whether the address hits a nomatch entry instead.
+            if entry.nomatch {
+                buf.put_attr_u32_be(IPSET_ATTR_CADT_FLAGS, IPSET_FLAG_NOMATCH);
+            }
+        }
+        Entry::SetName(name) => {
+            // IPSET_ATTR_NAME (member of a list:set)
+            buf.put_attr_str(IPSET_ATTR_NAME, name);
+        }
+        Entry::Port { port, port_to } => {
+            // IPSET_ATTR_PORT and IPSET_ATTR_PORT_TO (bitmap:port)
+            buf.put_attr_u16_be(IPSET_ATTR_PORT, *port);
+            if let Some(port_to) = port_to {
+                buf.put_attr_u16_be(IPSET_ATTR_PORT_TO, *port_to);
+            }
+        }
+        Entry::Mac(mac) => {
+            // IPSET_ATTR_ETHER (hash:mac)
+            buf.put_attr_bytes(IPSET_ATTR_ETHER, &mac.0);
+        }
+        Entry::Mark(_) | Entry::Iface(_) | Entry::Verdict(_) | Entry::Concat(_) => unreachable!(),
     }
 
     // IPSET_ATTR_LINENO (required for some operations)
-    buf.put_attr_u32(IPSET_ATTR_LINENO, 0);
+    buf.put_attr_u32(IPSET_ATTR_LINENO, lineno);
 
     buf.end_nested(data_offset);
+}
 
-    // Finalize message length
-    buf.finalize_nlmsg();
+/// Translate an error code the kernel answered an ipset request with in the
+/// error response `msg`.
+fn ipset_errno_error(error: i32, msg: &[u8]) -> RipsetError {
+    match error {
+        libc::IPSET_ERR_FIND_TYPE => {
+            RipsetError::KernelUnsupported("set type or revision".to_string())
+        }
+        libc::IPSET_ERR_TYPE_MISMATCH => {
+            RipsetError::TypeMismatch("sets have different types".to_string())
+        }
+        libc::IPSET_ERR_IPADDR_IPV4 => {
+            RipsetError::TypeMismatch("the set holds IPv4 addresses".to_string())
+        }
+        libc::IPSET_ERR_IPADDR_IPV6 => {
+            RipsetError::TypeMismatch("the set holds IPv6 addresses".to_string())
+        }
+        error => errno_error(error, msg),
+    }
+}
 
-    // Create socket and send/receive
-    let socket = NetlinkSocket::new()?;
-    let mut recv_buf = [0u8; BUFF_SZ];
-    let recv_len = socket.send_recv(buf.as_slice(), &mut recv_buf)?;
+/// Translate the error the kernel returned for an add, delete or test.
+fn ipset_adt_error(error: i32, setname: &str, cmd: u8, msg: &[u8]) -> RipsetError {
+    match error {
+        libc::ENOENT => {
+            if cmd == IPSET_CMD_TEST {
+                return RipsetError::EntryNotFound;
+            }
+            RipsetError::SetNotFound(setname.to_string())
+        }
+        libc::EEXIST => RipsetError::EntryExists,
+        libc::IPSET_ERR_EXIST => {
+            if cmd == IPSET_CMD_TEST || cmd == IPSET_CMD_DEL {
+                // For TEST and DEL commands, IPSET_ERR_EXIST means element NOT in set
+                return RipsetError::EntryNotFound;
+            }
+            // For ADD command, this means element already exists
+            RipsetError::EntryExists
+        }
+        libc::IPSET_ERR_TYPE_SPECIFIC | libc::IPSET_ERR_LIST_FULL if cmd == IPSET_CMD_ADD => {
+            let full = match ipset_info(setname) {
+                Ok(info) if info.type_name == "list:set" => error == libc::IPSET_ERR_LIST_FULL,
+                Ok(info) => {
+                    info.type_name.starts_with("hash:") && error == libc::IPSET_ERR_TYPE_SPECIFIC
+                }
+                Err(_) => false,
+            };
+            if full {
+                RipsetError::SetFull(setname.to_string())
+            } else {
+                ipset_errno_error(error, msg)
+            }
+        }
+        error => ipset_errno_error(error, msg),
+    }
+}
 
-    // Parse response
-    if recv_len < NlMsgHdr::SIZE {
-        return Err(IpSetError::ProtocolError);
+/// Internal function to perform ipset operations.
+async fn ipset_operate(
+    session: &IpsetSessionInner,
+    setname: &str,
+    entry: &Entry,
+    cmd: u8,
+    flags: u16,
+) -> Result<()> {
+    // Validate setname
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
     }
 
-    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-        if error == 0 {
-            return Ok(());
+    for entry in ipset_adt_entries(entry, cmd)?.iter() {
+        let seq = session.next_seq();
+        with_msg_buffer(|buf| {
+            // Netlink message header
+            buf.put_nlmsghdr(ipset_msg_type(cmd), NLM_F_REQUEST | NLM_F_ACK | flags, seq);
+
+            // Netfilter generic message header
+            buf.put_nfgenmsg(ipset_entry_family(entry), 0, 0);
+
+            // IPSET_ATTR_PROTOCOL
+            buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+
+            // IPSET_ATTR_SETNAME
+            buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+
+            // IPSET_ATTR_DATA (nested)
+            put_adt_data(buf, entry, 0);
+
+            // Finalize message length
+            buf.finalize_nlmsg();
+
+            // Send over the session socket
+            session.socket.send_retry(buf.as_slice())
+        })?;
+        let mut recv_buf = [0u8; BUFF_SZ];
+        let recv_len = session.socket.answer_async(seq, &mut recv_buf).await?;
+
+        // Parse response
+        if recv_len < NlMsgHdr::SIZE {
+            return Err(RipsetError::ProtocolError);
         }
 
-        // Handle specific errors
-        match -error {
-            libc::ENOENT => {
-                if cmd == IPSET_CMD_TEST {
-                    return Err(IpSetError::ElementNotFound);
-                }
-                return Err(IpSetError::SetNotFound(setname.to_string()));
+        match parse_nlmsg_error(&recv_buf[..recv_len]) {
+            Some(0) => {}
+            Some(error) => {
+                return Err(ipset_adt_error(-error, setname, cmd, &recv_buf[..recv_len]));
+            }
+            None => return Err(RipsetError::ProtocolError),
+        }
+    }
+
+    Ok(())
+}
+
+/// Perform an operation on many entries, packing them into IPSET_ATTR_ADT
+/// lists of as few requests as possible.
+async fn ipset_operate_many<I>(
+    session: &IpsetSessionInner,
+    setname: &str,
+    entries: I,
+    cmd: u8,
+    flags: u16,
+) -> Result<()>
+where
+    I: IntoIterator<Item = Entry>,
+{
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    let mut buf = MsgBuffer::new(IPSET_BULK_MSG_LEN * IPSET_BULK_MSGS_PER_SEND);
+    let mut msgs = 0;
+    let mut first_seq = session.seq.load(Ordering::Relaxed).wrapping_add(1);
+    // Start of the open request and of its IPSET_ATTR_ADT attribute
+    let mut open: Option<(usize, usize)> = None;
+
+    for entry in entries {
+        for entry in ipset_adt_entries(&entry, cmd)?.iter() {
+            if open.is_none() {
+                let start = buf.len();
+                buf.put_nlmsghdr(
+                    ipset_msg_type(cmd),
+                    NLM_F_REQUEST | NLM_F_ACK | flags,
+                    session.next_seq(),
+                );
+                buf.put_nfgenmsg(ipset_entry_family(entry), 0, 0);
+                buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+                buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+                // The kernel requires a line number along with an element list
+                buf.put_attr_u32(IPSET_ATTR_LINENO, 0);
+                open = Some((start, buf.start_nested(IPSET_ATTR_ADT)));
             }
-            libc::EEXIST => return Err(IpSetError::ElementExists),
-            libc::IPSET_ERR_EXIST => {
-                if cmd == IPSET_CMD_TEST {
-                    // For TEST command, IPSET_ERR_EXIST means element NOT in set
-                    return Err(IpSetError::ElementNotFound);
+            put_adt_data(&mut buf, entry, 0);
+
+            if let Some((start, adt_offset)) = open
+                && buf.len() - start >= IPSET_BULK_MSG_LEN
+            {
+                buf.end_nested(adt_offset);
+                buf.finalize_nlmsg_at(start);
+                open = None;
+                msgs += 1;
+                if msgs == IPSET_BULK_MSGS_PER_SEND {
+                    ipset_send_bulk(session, &buf, first_seq, setname, cmd).await?;
+                    buf = MsgBuffer::new(IPSET_BULK_MSG_LEN * IPSET_BULK_MSGS_PER_SEND);
+                    msgs = 0;
+                    first_seq = session.seq.load(Ordering::Relaxed).wrapping_add(1);
                 }
-                // For ADD command, this means element already exists
-                return Err(IpSetError::ElementExists);
             }
-            _ => return Err(IpSetError::NetlinkError(-error)),
         }
     }
 
-    Err(IpSetError::ProtocolError)
+    if let Some((start, adt_offset)) = open {
+        buf.end_nested(adt_offset);
+        buf.finalize_nlmsg_at(start);
+    }
+    if !buf.is_empty() {
+        ipset_send_bulk(session, &buf, first_seq, setname, cmd).await?;
+    }
+    Ok(())
+}
+
+/// Send bulk requests numbered from `first_seq` on and wait for all their
+/// answers, returning the first error.
+async fn ipset_send_bulk(
+    session: &IpsetSessionInner,
+    buf: &MsgBuffer,
+    first_seq: u32,
+    setname: &str,
+    cmd: u8,
+) -> Result<()> {
+    session.socket.send(buf.as_slice())?;
+
+    let last_seq = session.seq.load(Ordering::Relaxed);
+    let mut result = Ok(());
+    let mut recv_buf = [0u8; BUFF_SZ];
+    loop {
+        // Errors echo the failed request, of which only the start is needed
+        let recv_len = session.socket.recv_async(&mut recv_buf).await?;
+        if recv_len < NlMsgHdr::SIZE {
+            return Err(RipsetError::ProtocolError);
+        }
+        let hdr: NlMsgHdr =
+            unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+        // Skip answers left over from earlier requests
+        if hdr.nlmsg_seq.wrapping_sub(first_seq) > last_seq.wrapping_sub(first_seq) {
+            continue;
+        }
+        if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len])
+            && error != 0
+            && result.is_ok()
+        {
+            result = Err(ipset_adt_error(-error, setname, cmd, &recv_buf[..recv_len]));
+        }
+        if hdr.nlmsg_seq == last_seq {
+            return result;
+        }
+    }
+}
+
+/// Test many entries, sending the requests in groups instead of waiting
+/// for each answer.
+async fn ipset_test_entries<I>(
+    session: &IpsetSessionInner,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = Entry>,
+{
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    let mut pipeline = session.pipeline();
+    for entry in entries {
+        pipeline.queue(setname, &entry, IPSET_CMD_TEST, 0).await?;
+    }
+    pipeline
+        .finish_async()
+        .await?
+        .into_iter()
+        .map(|result| match result {
+            Ok(()) => Ok(true),
+            Err(RipsetError::EntryNotFound) => Ok(false),
+            Err(e) => Err(e),
+        })
+        .collect()
+}
+
+/// Delete many entries, reporting the entries the kernel refuses.
+///
+/// The kernel stops at the first refused element of a list and tells its
+/// line number, so the rest of that list is sent again.
+async fn ipset_del_entries<I>(
+    session: &IpsetSessionInner,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = Entry>,
+{
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    // Entries as sent to the kernel, with the index of the entry they are from
+    let mut failures = Vec::new();
+    let mut items = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        match ipset_adt_entries(&entry, IPSET_CMD_DEL) {
+            Ok(expanded) => items.extend(expanded.iter().map(|entry| (index, entry.clone()))),
+            Err(e) => failures.push((index, e)),
+        }
+    }
+
+    // Ranges of items still to be sent
+    let mut pending = VecDeque::from([(0, items.len())]);
+    while !pending.is_empty() {
+        let mut buf = MsgBuffer::new(IPSET_BULK_MSG_LEN * IPSET_BULK_MSGS_PER_SEND);
+        // Sequence number and range of items of each request
+        let mut msgs = Vec::new();
+        while msgs.len() < IPSET_BULK_MSGS_PER_SEND
+            && let Some((first, end)) = pending.pop_front()
+        {
+            if first == end {
+                continue;
+            }
+            let start = buf.len();
+            let seq = session.next_seq();
+            buf.put_nlmsghdr(
+                ipset_msg_type(IPSET_CMD_DEL),
+                NLM_F_REQUEST | NLM_F_ACK | NLM_F_EXCL,
+                seq,
+            );
+            buf.put_nfgenmsg(ipset_entry_family(&items[first].1), 0, 0);
+            buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+            buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+            buf.put_attr_u32(IPSET_ATTR_LINENO, 0);
+            let adt_offset = buf.start_nested(IPSET_ATTR_ADT);
+            let mut next = first;
+            while next < end && buf.len() - start < IPSET_BULK_MSG_LEN {
+                put_adt_data(&mut buf, &items[next].1, next as u32 + 1);
+                next += 1;
+            }
+            buf.end_nested(adt_offset);
+            buf.finalize_nlmsg_at(start);
+            if next < end {
+                pending.push_front((next, end));
+            }
+            msgs.push((seq, first, next));
+        }
+        if msgs.is_empty() {
+            break;
+        }
+
+        session.socket.send(buf.as_slice())?;
+        let mut recv_buf = [0u8; BUFF_SZ];
+        let mut answered = 0;
+        while answered < msgs.len() {
+            let recv_len = session.socket.recv_async(&mut recv_buf).await?;
+            if recv_len < NlMsgHdr::SIZE {
+                return Err(RipsetError::ProtocolError);
+            }
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+            // Skip answers left over from earlier requests
+            let Some(&(_, first, end)) = msgs.iter().find(|msg| msg.0 == hdr.nlmsg_seq) else {
+                continue;
+            };
+            answered += 1;
+
+            let error = match parse_nlmsg_error(&recv_buf[..recv_len]) {
+                Some(0) => continue,
+                Some(error) => {
+                    ipset_adt_error(-error, setname, IPSET_CMD_DEL, &recv_buf[..recv_len])
+                }
+                None => return Err(RipsetError::ProtocolError),
+            };
+            // Errors without a line number concern the whole request
+            let refused = ipset_error_lineno(&recv_buf[..recv_len])
+                .map(|lineno| lineno as usize - 1)
+                .filter(|item| (first..end).contains(item));
+            let Some(refused) = refused else {
+                return Err(error);
+            };
+            failures.push((items[refused].0, error));
+            pending.push_back((refused + 1, end));
+        }
+    }
+
+    // A range fails once even if several of its blocks are refused
+    failures.sort_by_key(|(index, _)| *index);
+    failures.dedup_by_key(|(index, _)| *index);
+    Ok(failures)
+}
+
+/// The line number the kernel puts into its error for a refused element,
+/// replacing the IPSET_ATTR_LINENO of the echoed request.
+fn ipset_error_lineno(msg: &[u8]) -> Option<u32> {
+    let attrs = msg.get(NlMsgHdr::SIZE + 4 + NlMsgHdr::SIZE + NfGenMsg::SIZE..)?;
+    NlAttrIter::new(attrs)
+        .find(|(attr_type, _)| *attr_type == IPSET_ATTR_LINENO)
+        .and_then(|(_, payload)| Some(u32::from_ne_bytes(payload.get(..4)?.try_into().ok()?)))
+        .filter(|lineno| *lineno != 0)
+}
+
+/// Fold a concatenation such as `addr . port` into a single address entry.
+fn concat_to_ip_entry(parts: &[Entry]) -> Result<IpEntry> {
+    let invalid = || {
+        RipsetError::InvalidOption(
+            "ipset concatenations must be an address followed by a port, MAC, mark or interface"
+                .to_string(),
+        )
+    };
+
+    let (first, rest) = parts.split_first().ok_or_else(invalid)?;
+    let Entry::Ip(first) = first else {
+        return Err(invalid());
+    };
+    let mut entry = first.clone();
+    for part in rest {
+        match part {
+            Entry::Port { port, port_to } if entry.port.is_none() => {
+                entry.port = Some(*port);
+                entry.port_to = *port_to;
+            }
+            Entry::Mac(mac) if entry.mac.is_none() => entry.mac = Some(*mac),
+            Entry::Mark(mark) if entry.mark.is_none() => entry.mark = Some(*mark),
+            Entry::Iface(iface) if entry.iface.is_none() => entry.iface = Some(iface.clone()),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(entry)
 }
 
 // Custom error codes for ipset (from kernel include/uapi/linux/netfilter/ipset/ip_set.h)
@@ -162,139 +853,795 @@ mod libc {
     pub use ::libc::*;
     // IPSET_ERR_PRIVATE = 4096, then PROTOCOL=4097, FIND_TYPE=4098, MAX_SETS=4099,
     // BUSY=4100, EXIST_SETNAME2=4101, TYPE_MISMATCH=4102, EXIST=4103
+    pub const IPSET_ERR_FIND_TYPE: i32 = 4098;
+    pub const IPSET_ERR_BUSY: i32 = 4100;
+    pub const IPSET_ERR_EXIST_SETNAME2: i32 = 4101;
+    pub const IPSET_ERR_TYPE_MISMATCH: i32 = 4102;
     pub const IPSET_ERR_EXIST: i32 = 4103;
+    pub const IPSET_ERR_IPADDR_IPV4: i32 = 4109;
+    pub const IPSET_ERR_IPADDR_IPV6: i32 = 4110;
+    // Codes from 4352 on depend on the set type: a full hash, an element
+    // outside a bitmap's range, a missing list:set member
+    pub const IPSET_ERR_TYPE_SPECIFIC: i32 = 4352;
+    pub const IPSET_ERR_LIST_FULL: i32 = 4353;
 }
 
 /// ipset type for hash:ip sets
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IpSetType {
     /// hash:ip - stores IP addresses
+    #[cfg_attr(feature = "serde", serde(rename = "hash:ip"))]
     HashIp,
     /// hash:net - stores network addresses (CIDR)
+    #[cfg_attr(feature = "serde", serde(rename = "hash:net"))]
     HashNet,
+    /// bitmap:ip - stores IPv4 addresses from a fixed range (requires `range`)
+    #[cfg_attr(feature = "serde", serde(rename = "bitmap:ip"))]
+    BitmapIp,
+    /// bitmap:ip,mac - stores IPv4 and MAC address pairs from a fixed range (requires `range`)
+    #[cfg_attr(feature = "serde", serde(rename = "bitmap:ip,mac"))]
+    BitmapIpMac,
+    /// list:set - stores names of other sets
+    #[cfg_attr(feature = "serde", serde(rename = "list:set"))]
+    ListSet,
+    /// hash:ip,port - stores IP address, protocol and port triples
+    #[cfg_attr(feature = "serde", serde(rename = "hash:ip,port"))]
+    HashIpPort,
+    /// hash:net,port - stores network address, protocol and port triples
+    #[cfg_attr(feature = "serde", serde(rename = "hash:net,port"))]
+    HashNetPort,
+    /// bitmap:port - stores ports from a fixed range (requires a port `range`)
+    #[cfg_attr(feature = "serde", serde(rename = "bitmap:port"))]
+    BitmapPort,
+    /// hash:mac - stores MAC addresses
+    #[cfg_attr(feature = "serde", serde(rename = "hash:mac"))]
+    HashMac,
+    /// hash:ip,mark - stores IP address and packet mark pairs
+    #[cfg_attr(feature = "serde", serde(rename = "hash:ip,mark"))]
+    HashIpMark,
+    /// hash:net,iface - stores network address and interface name pairs
+    #[cfg_attr(feature = "serde", serde(rename = "hash:net,iface"))]
+    HashNetIface,
 }
 
 impl IpSetType {
+    /// Every set type known to this library.
+    const ALL: [IpSetType; 11] = [
+        IpSetType::HashIp,
+        IpSetType::HashNet,
+        IpSetType::BitmapIp,
+        IpSetType::BitmapIpMac,
+        IpSetType::ListSet,
+        IpSetType::HashIpPort,
+        IpSetType::HashNetPort,
+        IpSetType::BitmapPort,
+        IpSetType::HashMac,
+        IpSetType::HashIpMark,
+        IpSetType::HashNetIface,
+    ];
+
     fn as_str(&self) -> &'static str {
         match self {
             IpSetType::HashIp => "hash:ip",
             IpSetType::HashNet => "hash:net",
+            IpSetType::BitmapIp => "bitmap:ip",
+            IpSetType::BitmapIpMac => "bitmap:ip,mac",
+            IpSetType::ListSet => "list:set",
+            IpSetType::HashIpPort => "hash:ip,port",
+            IpSetType::HashNetPort => "hash:net,port",
+            IpSetType::BitmapPort => "bitmap:port",
+            IpSetType::HashMac => "hash:mac",
+            IpSetType::HashIpMark => "hash:ip,mark",
+            IpSetType::HashNetIface => "hash:net,iface",
         }
     }
 
     fn revision(&self) -> u8 {
-        // Use revision 4 which is widely supported across kernel versions
-        // (5.10+ kernels support revision 4 for hash:ip and hash:net)
-        // Higher revisions (5, 6) require newer kernels
+        // Use the skbinfo revisions, which are widely supported across kernel
+        // versions (available since 3.19); higher revisions require newer kernels
         match self {
             IpSetType::HashIp => 4,
-            IpSetType::HashNet => 4,
+            IpSetType::HashNet => 6,
+            IpSetType::HashIpPort => 5,
+            IpSetType::HashNetPort => 7,
+            IpSetType::HashMac => 0,
+            IpSetType::HashIpMark => 2,
+            IpSetType::HashNetIface => 6,
+            // bitmap revision 3 (skbinfo) has been available since 3.19
+            IpSetType::BitmapIp | IpSetType::BitmapIpMac | IpSetType::BitmapPort => 3,
+            // list:set revision 3 (skbinfo) has been available since 3.19
+            IpSetType::ListSet => 3,
         }
     }
-}
-
-/// Address family for ipset
-#[derive(Clone, Copy, Debug)]
-pub enum IpSetFamily {
-    /// IPv4 addresses
-    Inet,
-    /// IPv6 addresses
-    Inet6,
-}
 
-impl IpSetFamily {
-    fn as_u8(&self) -> u8 {
+    /// Revision that accepts the `bucketsize` and `initval` create options
+    /// (Linux 5.11+), if the type supports them.
+    fn tuning_revision(&self) -> Option<u8> {
         match self {
-            IpSetFamily::Inet => libc::AF_INET as u8,
-            IpSetFamily::Inet6 => libc::AF_INET6 as u8,
+            IpSetType::HashIp => Some(5),
+            IpSetType::HashNet => Some(7),
+            IpSetType::HashIpPort => Some(6),
+            IpSetType::HashNetPort => Some(8),
+            IpSetType::HashMac => Some(1),
+            IpSetType::HashIpMark => Some(3),
+            IpSetType::HashNetIface => Some(8),
+            _ => None,
         }
     }
-}
 
-/// Options for creating an ipset
-#[derive(Clone, Debug)]
-pub struct IpSetCreateOptions {
-    pub set_type: IpSetType,
-    pub family: IpSetFamily,
-    pub hashsize: Option<u32>,
-    pub maxelem: Option<u32>,
-    pub timeout: Option<u32>,
-}
+    /// Oldest revision creating sets with the extensions `options` asks
+    /// for, used on kernels that lack [`Self::revision`].
+    fn fallback_revision(&self, options: &IpSetCreateOptions) -> u8 {
+        // The base revision keeps the entry syntax of the usual revision
+        // (nomatch for networks, SCTP and UDPLITE ports); the others add
+        // counters, comments, forceadd and skbinfo
+        let (base, counters, comment, forceadd, skbinfo) = match self {
+            IpSetType::HashIp => (0, 1, 2, 3, 4),
+            IpSetType::HashNet | IpSetType::HashNetIface => (2, 3, 4, 5, 6),
+            IpSetType::HashIpPort => (1, 2, 3, 4, 5),
+            IpSetType::HashNetPort => (3, 4, 5, 6, 7),
+            IpSetType::HashMac => (0, 0, 0, 0, 0),
+            IpSetType::HashIpMark => (0, 0, 0, 1, 2),
+            IpSetType::BitmapIp
+            | IpSetType::BitmapIpMac
+            | IpSetType::BitmapPort
+            | IpSetType::ListSet => (0, 1, 2, 0, 3),
+        };
+        [
+            (options.counters, counters),
+            (options.comment, comment),
+            (options.forceadd, forceadd),
+            (options.skbinfo, skbinfo),
+        ]
+        .iter()
+        .filter(|(wanted, _)| *wanted)
+        .fold(base, |revision, (_, needed)| revision.max(*needed))
+    }
 
-impl Default for IpSetCreateOptions {
-    fn default() -> Self {
-        Self {
-            set_type: IpSetType::HashIp,
-            family: IpSetFamily::Inet,
-            hashsize: None,
-            maxelem: None,
-            timeout: None,
-        }
+    /// Whether this set type needs a `range` on creation.
+    fn is_bitmap(&self) -> bool {
+        matches!(
+            self,
+            IpSetType::BitmapIp | IpSetType::BitmapIpMac | IpSetType::BitmapPort
+        )
+    }
+
+    /// Whether this set type is backed by a hash table.
+    fn is_hash(&self) -> bool {
+        matches!(
+            self,
+            IpSetType::HashIp
+                | IpSetType::HashNet
+                | IpSetType::HashIpPort
+                | IpSetType::HashNetPort
+                | IpSetType::HashMac
+                | IpSetType::HashIpMark
+                | IpSetType::HashNetIface
+        )
     }
 }
 
-/// Create an ipset.
-///
-/// # Arguments
+/// Range of revisions of a set type supported by the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpSetTypeRevisions {
+    pub set_type: IpSetType,
+    /// Oldest supported revision
+    pub min_revision: u8,
+    /// Newest supported revision
+    pub max_revision: u8,
+}
+
+/// Ask the kernel which revisions of a set type it supports.
 ///
-/// * `setname` - The name of the ipset to create
-/// * `options` - Creation options (type, family, etc.)
+/// Returns `Ok(None)` if the kernel doesn't support the type at all.
 ///
 /// # Example
 ///
 /// ```no_run
-/// use ruhop_ipset::ipset::{ipset_create, IpSetCreateOptions, IpSetType, IpSetFamily};
+/// use ripset::{IpSetType, ipset_type_revisions};
 ///
-/// let opts = IpSetCreateOptions {
-///     set_type: IpSetType::HashIp,
-///     family: IpSetFamily::Inet,
-///     ..Default::default()
-/// };
-/// ipset_create("myset", &opts).unwrap();
+/// if let Some(revisions) = ipset_type_revisions(IpSetType::HashNet).unwrap() {
+///     println!("hash:net revisions {}-{}", revisions.min_revision, revisions.max_revision);
+/// }
 /// ```
-pub fn ipset_create(setname: &str, options: &IpSetCreateOptions) -> Result<()> {
-    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
-    }
+pub fn ipset_type_revisions(set_type: IpSetType) -> Result<Option<IpSetTypeRevisions>> {
+    block_on(ipset_type_revisions_async(set_type))
+}
+
+/// [`ipset_type_revisions`] for async code.
+pub(crate) async fn ipset_type_revisions_async(
+    set_type: IpSetType,
+) -> Result<Option<IpSetTypeRevisions>> {
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+
+    buf.put_nlmsghdr(ipset_msg_type(IPSET_CMD_TYPE), NLM_F_REQUEST, 0);
+    buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
+
+    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+    buf.put_attr_str(IPSET_ATTR_TYPENAME, set_type.as_str());
+    // Every type supports IPv4 (or is family independent)
+    buf.put_attr_u8(IPSET_ATTR_FAMILY, libc::AF_INET as u8);
+
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
+
+    if recv_len < NlMsgHdr::SIZE + NfGenMsg::SIZE {
+        return Err(RipsetError::ProtocolError);
+    }
+
+    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
+        return match -error {
+            libc::IPSET_ERR_FIND_TYPE => Ok(None),
+            error => Err(ipset_errno_error(error, &recv_buf[..recv_len])),
+        };
+    }
+
+    let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+    let msg_end = (hdr.nlmsg_len as usize).min(recv_len);
+    let mut max_revision = None;
+    let mut min_revision = None;
+    for (attr_type, payload) in NlAttrIter::new(&recv_buf[NlMsgHdr::SIZE + NfGenMsg::SIZE..msg_end])
+    {
+        match attr_type {
+            IPSET_ATTR_REVISION => max_revision = payload.first().copied(),
+            IPSET_ATTR_REVISION_MIN => min_revision = payload.first().copied(),
+            _ => {}
+        }
+    }
+
+    let max_revision = max_revision.ok_or(RipsetError::ProtocolError)?;
+    Ok(Some(IpSetTypeRevisions {
+        set_type,
+        min_revision: min_revision.unwrap_or(max_revision),
+        max_revision,
+    }))
+}
+
+/// List the set types supported by the kernel, with their revisions.
+///
+/// Only types known to this library are queried; types the kernel lacks
+/// (e.g. because the module isn't built) are left out.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_supported_types;
+///
+/// for revisions in ipset_supported_types().unwrap() {
+///     println!("{:?}: up to revision {}", revisions.set_type, revisions.max_revision);
+/// }
+/// ```
+pub fn ipset_supported_types() -> Result<Vec<IpSetTypeRevisions>> {
+    block_on(ipset_supported_types_async())
+}
+
+/// [`ipset_supported_types`] for async code.
+pub(crate) async fn ipset_supported_types_async() -> Result<Vec<IpSetTypeRevisions>> {
+    let mut supported = Vec::new();
+    for set_type in IpSetType::ALL {
+        if let Some(revisions) = ipset_type_revisions_async(set_type).await? {
+            supported.push(revisions);
+        }
+    }
+    Ok(supported)
+}
+
+/// Ask the kernel which optional features ipsets support.
+///
+/// A feature is supported when the kernel has the revision of the set type
+/// this library creates for it: `hash:ip` for timeouts, counters and
+/// comments, `hash:net` for CIDR blocks and `hash:mac` for MAC addresses.
+/// Swapping is part of every ipset version.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpEntry, ipset_add, ipset_capabilities};
+///
+/// let addr = "10.0.0.1".parse().unwrap();
+/// let entry = if ipset_capabilities().unwrap().timeouts {
+///     IpEntry::with_timeout(addr, 300)
+/// } else {
+///     IpEntry::new(addr)
+/// };
+/// ipset_add("myset", entry).unwrap();
+/// ```
+pub fn ipset_capabilities() -> Result<BackendCapabilities> {
+    block_on(ipset_capabilities_async())
+}
+
+/// [`ipset_capabilities`] for async code.
+pub(crate) async fn ipset_capabilities_async() -> Result<BackendCapabilities> {
+    let hash_ip = ipset_type_usable(IpSetType::HashIp).await?;
+    Ok(BackendCapabilities {
+        swap: true,
+        timeouts: hash_ip,
+        counters: hash_ip,
+        intervals: ipset_type_usable(IpSetType::HashNet).await?,
+        comments: hash_ip,
+        mac_keys: ipset_type_usable(IpSetType::HashMac).await?,
+    })
+}
+
+/// Whether the kernel has the revision of `set_type` this library creates.
+async fn ipset_type_usable(set_type: IpSetType) -> Result<bool> {
+    Ok(ipset_type_revisions_async(set_type)
+        .await?
+        .is_some_and(|revisions| {
+            (revisions.min_revision..=revisions.max_revision).contains(&set_type.revision())
+        }))
+}
+
+impl fmt::Display for IpSetType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for IpSetType {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        IpSetType::ALL
+            .into_iter()
+            .find(|set_type| set_type.as_str() == s)
+            .ok_or_else(|| RipsetError::InvalidOption(format!("unknown set type: {s}")))
+    }
+}
+
+/// Address range for bitmap set types
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IpSetRange {
+    /// Inclusive range from the first to the last address
+    FromTo(Ipv4Addr, Ipv4Addr),
+    /// Network address with prefix length
+    Cidr(Ipv4Addr, u8),
+    /// Inclusive port range (bitmap:port)
+    Ports(u16, u16),
+}
+
+/// Address family for ipset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum IpSetFamily {
+    /// IPv4 addresses
+    Inet,
+    /// IPv6 addresses
+    Inet6,
+}
+
+impl IpSetFamily {
+    fn as_u8(&self) -> u8 {
+        match self {
+            IpSetFamily::Inet => libc::AF_INET as u8,
+            IpSetFamily::Inet6 => libc::AF_INET6 as u8,
+        }
+    }
+
+    fn from_u8(family: u8) -> Option<Self> {
+        match family as i32 {
+            libc::AF_INET => Some(IpSetFamily::Inet),
+            libc::AF_INET6 => Some(IpSetFamily::Inet6),
+            _ => None,
+        }
+    }
+}
+
+/// Options for creating an ipset
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct IpSetCreateOptions {
+    pub set_type: IpSetType,
+    pub family: IpSetFamily,
+    /// Initial hash table size for hash types (rounded up to a power of two by the kernel)
+    pub hashsize: Option<u32>,
+    /// Maximal number of elements for hash types (kernel default is 65536)
+    pub maxelem: Option<u32>,
+    /// Maximal number of elements per hash bucket (ignored on kernels older than 5.11)
+    pub bucketsize: Option<u8>,
+    /// Hash seed for reproducible hashing (ignored on kernels older than 5.11)
+    pub initval: Option<u32>,
+    pub timeout: Option<u32>,
+    /// Address range, required for bitmap types
+    pub range: Option<IpSetRange>,
+    /// Keep per-entry packet and byte counters
+    pub counters: bool,
+    /// Allow a comment on each entry
+    pub comment: bool,
+    /// Allow skbmark/skbprio/skbqueue values on each entry
+    pub skbinfo: bool,
+    /// Evict a random entry instead of failing when a hash set is full
+    pub forceadd: bool,
+    /// Raw attributes appended to the `IPSET_ATTR_DATA` block of the create
+    /// request, as `(type, payload)` pairs, for kernel options not modeled
+    /// above. The type carries its own flags, such as
+    /// `NLA_F_NET_BYTEORDER`, and the payload is sent as is.
+    pub extra_attrs: Vec<(u16, Vec<u8>)>,
+}
+
+impl Default for IpSetCreateOptions {
+    fn default() -> Self {
+        Self {
+            set_type: IpSetType::HashIp,
+            family: IpSetFamily::Inet,
+            hashsize: None,
+            maxelem: None,
+            bucketsize: None,
+            initval: None,
+            timeout: None,
+            range: None,
+            counters: false,
+            comment: false,
+            skbinfo: false,
+            forceadd: false,
+            extra_attrs: Vec::new(),
+        }
+    }
+}
+
+/// Comparison of an entry counter against a threshold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterMatch {
+    /// Counter equals the value
+    Eq(u64),
+    /// Counter differs from the value
+    Ne(u64),
+    /// Counter is less than the value
+    Lt(u64),
+    /// Counter is greater than the value
+    Gt(u64),
+}
+
+impl CounterMatch {
+    fn matches(&self, counter: u64) -> bool {
+        match *self {
+            CounterMatch::Eq(value) => counter == value,
+            CounterMatch::Ne(value) => counter != value,
+            CounterMatch::Lt(value) => counter < value,
+            CounterMatch::Gt(value) => counter > value,
+        }
+    }
+}
+
+/// Options for testing an entry in an ipset
+#[derive(Clone, Debug, Default)]
+pub struct IpSetTestOptions {
+    /// Only match if the entry's packet counter satisfies the comparison
+    pub packets: Option<CounterMatch>,
+    /// Only match if the entry's byte counter satisfies the comparison
+    pub bytes: Option<CounterMatch>,
+}
+
+/// Create an ipset.
+///
+/// Returns [`RipsetError::EntryExists`] if a set with this name already
+/// exists; use [`ipset_create_exist`] to accept an identical existing set.
+///
+/// On kernels without the usual revision of the set type, the set is
+/// created with the oldest revision that has the extensions asked for, and
+/// `bucketsize` and `initval` are dropped before Linux 5.11. Both fallbacks
+/// log a warning with the `tracing` feature. Returns
+/// [`RipsetError::KernelUnsupported`] naming the type revision needed when
+/// the kernel has none of them.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset to create
+/// * `options` - Creation options (type, family, etc.)
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset::{ipset_create, IpSetCreateOptions, IpSetType, IpSetFamily};
+///
+/// let opts = IpSetCreateOptions {
+///     set_type: IpSetType::HashIp,
+///     family: IpSetFamily::Inet,
+///     ..Default::default()
+/// };
+/// ipset_create("myset", &opts).unwrap();
+/// ```
+pub fn ipset_create(setname: &str, options: &IpSetCreateOptions) -> Result<()> {
+    block_on(ipset_create_async(setname, options))
+}
+
+/// [`ipset_create`] for async code.
+pub(crate) async fn ipset_create_async(setname: &str, options: &IpSetCreateOptions) -> Result<()> {
+    ipset_create_flags(setname, options, NLM_F_EXCL).await
+}
+
+/// Create an ipset unless an identical one already exists
+/// (`ipset create -exist`).
+///
+/// Creating a set whose name is taken by a set of a different type or with
+/// different options still fails with [`RipsetError::EntryExists`].
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset to create
+/// * `options` - Creation options (type, family, etc.)
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpSetCreateOptions, ipset_create_exist};
+///
+/// let opts = IpSetCreateOptions::default();
+/// ipset_create_exist("myset", &opts).unwrap();
+/// // Creating it again is not an error
+/// ipset_create_exist("myset", &opts).unwrap();
+/// ```
+pub fn ipset_create_exist(setname: &str, options: &IpSetCreateOptions) -> Result<()> {
+    block_on(ipset_create_exist_async(setname, options))
+}
+
+/// [`ipset_create_exist`] for async code.
+pub(crate) async fn ipset_create_exist_async(
+    setname: &str,
+    options: &IpSetCreateOptions,
+) -> Result<()> {
+    ipset_create_flags(setname, options, 0).await
+}
+
+/// Make sure an ipset exists with the given options.
+///
+/// The set is created if it is missing. An existing set is left as it is,
+/// entries included, if its type, family, timeout, maximal number of
+/// elements and extensions match `options`; otherwise
+/// [`RipsetError::SetMismatch`] names the first property that differs.
+/// Options the kernel may adjust, such as the hash size, are not compared.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `options` - Creation options (type, family, etc.)
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpSetCreateOptions, ipset_ensure_set};
+///
+/// let opts = IpSetCreateOptions {
+///     timeout: Some(300),
+///     ..Default::default()
+/// };
+/// ipset_ensure_set("recently_seen", &opts).unwrap();
+/// ```
+pub fn ipset_ensure_set(setname: &str, options: &IpSetCreateOptions) -> Result<()> {
+    block_on(ipset_ensure_set_async(setname, options))
+}
+
+/// [`ipset_ensure_set`] for async code.
+pub(crate) async fn ipset_ensure_set_async(
+    setname: &str,
+    options: &IpSetCreateOptions,
+) -> Result<()> {
+    let info = match ipset_info_async(setname).await {
+        Ok(info) => info,
+        // Another process creating the same set in between is fine
+        Err(RipsetError::SetNotFound(_)) => {
+            return ipset_create_exist_async(setname, options).await;
+        }
+        Err(e) => return Err(e),
+    };
+
+    let mismatch = if info.type_name != options.set_type.as_str() {
+        Some("type")
+    } else if info.family.is_some_and(|family| family != options.family) {
+        Some("family")
+    } else if info.timeout != options.timeout {
+        Some("timeout")
+    } else if options.maxelem.is_some() && info.maxelem != options.maxelem {
+        Some("maxelem")
+    } else if info.counters != options.counters {
+        Some("counters option")
+    } else if info.comment != options.comment {
+        Some("comment option")
+    } else if info.skbinfo != options.skbinfo {
+        Some("skbinfo option")
+    } else if info.forceadd != options.forceadd {
+        Some("forceadd option")
+    } else {
+        None
+    };
+    match mismatch {
+        Some(property) => Err(RipsetError::SetMismatch(
+            setname.to_string(),
+            property.to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+async fn ipset_create_flags(setname: &str, options: &IpSetCreateOptions, flags: u16) -> Result<()> {
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    if options.set_type.is_bitmap() {
+        if !matches!(options.family, IpSetFamily::Inet) {
+            return Err(RipsetError::InvalidAddressFamily);
+        }
+        let port_type = matches!(options.set_type, IpSetType::BitmapPort);
+        match options.range {
+            Some(IpSetRange::Ports(..)) if port_type => {}
+            Some(IpSetRange::FromTo(..) | IpSetRange::Cidr(..)) if !port_type => {}
+            _ => {
+                return Err(RipsetError::InvalidOption(format!(
+                    "{} requires {} range",
+                    options.set_type.as_str(),
+                    if port_type { "a port" } else { "an address" }
+                )));
+            }
+        }
+    } else if options.range.is_some() {
+        return Err(RipsetError::InvalidOption(format!(
+            "range is not supported by {}",
+            options.set_type.as_str()
+        )));
+    }
+
+    if !options.set_type.is_hash() {
+        let hash_only = [
+            ("hashsize", options.hashsize.is_some()),
+            ("maxelem", options.maxelem.is_some()),
+            ("bucketsize", options.bucketsize.is_some()),
+            ("initval", options.initval.is_some()),
+            ("forceadd", options.forceadd),
+        ];
+        if let Some((name, _)) = hash_only.iter().find(|(_, set)| *set) {
+            return Err(RipsetError::InvalidOption(format!(
+                "{name} is not supported by {}",
+                options.set_type.as_str()
+            )));
+        }
+    }
+
+    let tuning = options.bucketsize.is_some() || options.initval.is_some();
+    if let Some(revision) = options.set_type.tuning_revision().filter(|_| tuning) {
+        match ipset_create_revision(setname, options, revision, true, flags).await {
+            // Older kernels don't know this revision: create the set without tuning
+            Err(RipsetError::KernelUnsupported(_)) => {
+                warn_fallback(setname, options, "bucketsize and initval are ignored");
+            }
+            result => return result,
+        }
+    }
+
+    let revision = options.set_type.revision();
+    match ipset_create_revision(setname, options, revision, false, flags).await {
+        Err(RipsetError::KernelUnsupported(_)) => {}
+        result => return result,
+    }
+
+    // Kernels before 3.19 lack the skbinfo revisions: use the oldest
+    // revision with the extensions the set needs
+    let fallback = options.set_type.fallback_revision(options);
+    if fallback < revision {
+        warn_fallback(setname, options, "created with an older set type revision");
+        match ipset_create_revision(setname, options, fallback, false, flags).await {
+            Err(RipsetError::KernelUnsupported(_)) => {}
+            result => return result,
+        }
+    }
+    Err(RipsetError::KernelUnsupported(format!(
+        "{} revision {fallback}",
+        options.set_type.as_str()
+    )))
+}
+
+/// Log that a set is created differently than asked because of the kernel,
+/// when the `tracing` feature is enabled.
+fn warn_fallback(setname: &str, options: &IpSetCreateOptions, message: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(setname, set_type = options.set_type.as_str(), "{message}");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (setname, options, message);
+}
 
+/// Send a single create request for the given type revision.
+async fn ipset_create_revision(
+    setname: &str,
+    options: &IpSetCreateOptions,
+    revision: u8,
+    tuning: bool,
+    flags: u16,
+) -> Result<()> {
     let mut buf = MsgBuffer::new(BUFF_SZ);
 
     buf.put_nlmsghdr(
         ipset_msg_type(IPSET_CMD_CREATE),
-        NLM_F_REQUEST | NLM_F_ACK,
+        NLM_F_REQUEST | NLM_F_ACK | flags,
         0,
     );
     buf.put_nfgenmsg(options.family.as_u8(), 0, 0);
 
-    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, IPSET_PROTOCOL);
+    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
     buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
     buf.put_attr_str(IPSET_ATTR_TYPENAME, options.set_type.as_str());
-    buf.put_attr_u8(IPSET_ATTR_REVISION, options.set_type.revision());
+    buf.put_attr_u8(IPSET_ATTR_REVISION, revision);
     buf.put_attr_u8(IPSET_ATTR_FAMILY, options.family.as_u8());
 
     // Data attributes (nested)
     let data_offset = buf.start_nested(IPSET_ATTR_DATA);
 
+    match options.range {
+        Some(IpSetRange::FromTo(from, to)) => {
+            put_ip_attr(&mut buf, IPSET_ATTR_IP, &IpAddr::V4(from));
+            put_ip_attr(&mut buf, IPSET_ATTR_IP_TO, &IpAddr::V4(to));
+        }
+        Some(IpSetRange::Cidr(addr, cidr)) => {
+            put_ip_attr(&mut buf, IPSET_ATTR_IP, &IpAddr::V4(addr));
+            buf.put_attr_u8(IPSET_ATTR_CIDR, cidr);
+        }
+        Some(IpSetRange::Ports(from, to)) => {
+            buf.put_attr_u16_be(IPSET_ATTR_PORT, from);
+            buf.put_attr_u16_be(IPSET_ATTR_PORT_TO, to);
+        }
+        None => {}
+    }
+
+    // hashsize and maxelem must be in network byte order, like timeout
     if let Some(hashsize) = options.hashsize {
-        buf.put_attr_u32(IPSET_ATTR_HASHSIZE, hashsize);
+        buf.put_attr_u32_be(IPSET_ATTR_HASHSIZE, hashsize);
     }
     if let Some(maxelem) = options.maxelem {
-        buf.put_attr_u32(IPSET_ATTR_MAXELEM, maxelem);
+        buf.put_attr_u32_be(IPSET_ATTR_MAXELEM, maxelem);
+    }
+    if tuning {
+        if let Some(bucketsize) = options.bucketsize {
+            buf.put_attr_u8(IPSET_ATTR_BUCKETSIZE, bucketsize);
+        }
+        if let Some(initval) = options.initval {
+            buf.put_attr_u32_be(IPSET_ATTR_INITVAL, initval);
+        }
     }
     if let Some(timeout) = options.timeout {
         // Timeout must be in network byte order with NLA_F_NET_BYTEORDER flag
         buf.put_attr_u32_be(IPSET_ATTR_TIMEOUT, timeout);
     }
 
+    let mut cadt_flags = 0;
+    if options.counters {
+        cadt_flags |= IPSET_FLAG_WITH_COUNTERS;
+    }
+    if options.comment {
+        cadt_flags |= IPSET_FLAG_WITH_COMMENT;
+    }
+    if options.skbinfo {
+        cadt_flags |= IPSET_FLAG_WITH_SKBINFO;
+    }
+    if options.forceadd {
+        cadt_flags |= IPSET_FLAG_WITH_FORCEADD;
+    }
+    if cadt_flags != 0 {
+        buf.put_attr_u32_be(IPSET_ATTR_CADT_FLAGS, cadt_flags);
+    }
+    for (attr_type, payload) in &options.extra_attrs {
+        buf.put_attr_bytes(*attr_type, payload);
+    }
+
     buf.end_nested(data_offset);
     buf.finalize_nlmsg();
 
     let socket = NetlinkSocket::new()?;
     let mut recv_buf = [0u8; BUFF_SZ];
-    let recv_len = socket.send_recv(buf.as_slice(), &mut recv_buf)?;
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
 
     if recv_len < NlMsgHdr::SIZE {
-        return Err(IpSetError::ProtocolError);
+        return Err(RipsetError::ProtocolError);
     }
 
     if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
@@ -302,216 +1649,1830 @@ pub fn ipset_create(setname: &str, options: &IpSetCreateOptions) -> Result<()> {
             return Ok(());
         }
         match -error {
-            libc::EEXIST => return Err(IpSetError::ElementExists),
-            _ => return Err(IpSetError::NetlinkError(-error)),
+            libc::EEXIST => return Err(RipsetError::EntryExists),
+            _ => return Err(ipset_errno_error(-error, &recv_buf[..recv_len])),
+        }
+    }
+
+    Err(RipsetError::ProtocolError)
+}
+
+/// Destroy an ipset.
+///
+/// Returns [`RipsetError::SetInUse`] with the number of references if the set
+/// is still used, see [`ipset_references`].
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset to destroy
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_destroy;
+///
+/// ipset_destroy("myset").unwrap();
+/// ```
+pub fn ipset_destroy(setname: &str) -> Result<()> {
+    block_on(ipset_destroy_async(setname))
+}
+
+/// [`ipset_destroy`] for async code.
+pub(crate) async fn ipset_destroy_async(setname: &str) -> Result<()> {
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    match ipset_set_command(IPSET_CMD_DESTROY, Some(setname), None).await {
+        Err(e) if e.errno() == Some(libc::IPSET_ERR_BUSY) => {
+            match ipset_references_async(setname).await {
+                Ok(1) => Err(RipsetError::SetInUse(
+                    setname.to_string(),
+                    "1 reference".to_string(),
+                )),
+                Ok(n) if n > 1 => Err(RipsetError::SetInUse(
+                    setname.to_string(),
+                    format!("{n} references"),
+                )),
+                _ => Err(e),
+            }
         }
+        result => result,
     }
+}
+
+/// Destroy all ipsets.
+///
+/// Fails without destroying anything if any set is still referenced, e.g.
+/// by an iptables rule or a list:set.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_destroy_all;
+///
+/// ipset_destroy_all().unwrap();
+/// ```
+pub fn ipset_destroy_all() -> Result<()> {
+    block_on(ipset_destroy_all_async())
+}
+
+/// [`ipset_destroy_all`] for async code.
+pub(crate) async fn ipset_destroy_all_async() -> Result<()> {
+    ipset_set_command(IPSET_CMD_DESTROY, None, None).await
+}
+
+/// Flush (remove all elements from) an ipset.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset to flush
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_flush;
+///
+/// ipset_flush("myset").unwrap();
+/// ```
+pub fn ipset_flush(setname: &str) -> Result<()> {
+    block_on(ipset_flush_async(setname))
+}
+
+/// [`ipset_flush`] for async code.
+pub(crate) async fn ipset_flush_async(setname: &str) -> Result<()> {
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    ipset_set_command(IPSET_CMD_FLUSH, Some(setname), None).await
+}
+
+/// Flush (remove all elements from) all ipsets.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_flush_all;
+///
+/// ipset_flush_all().unwrap();
+/// ```
+pub fn ipset_flush_all() -> Result<()> {
+    block_on(ipset_flush_all_async())
+}
+
+/// [`ipset_flush_all`] for async code.
+pub(crate) async fn ipset_flush_all_async() -> Result<()> {
+    ipset_set_command(IPSET_CMD_FLUSH, None, None).await
+}
+
+/// Swap two ipsets.
+///
+/// The kernel exchanges the names of the sets in one step, so rules
+/// referencing either name switch to the other set's contents atomically.
+/// The sets must be of compatible types.
+///
+/// # Arguments
+///
+/// * `setname1` - The name of the first ipset
+/// * `setname2` - The name of the second ipset
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_swap;
+///
+/// // Replace the live blocklist with the freshly filled one
+/// ipset_swap("blocklist", "blocklist_new").unwrap();
+/// ```
+pub fn ipset_swap(setname1: &str, setname2: &str) -> Result<()> {
+    block_on(ipset_swap_async(setname1, setname2))
+}
+
+/// [`ipset_swap`] for async code.
+pub(crate) async fn ipset_swap_async(setname1: &str, setname2: &str) -> Result<()> {
+    for setname in [setname1, setname2] {
+        if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+            return Err(RipsetError::InvalidSetName(setname.to_string()));
+        }
+    }
+
+    ipset_set_command(IPSET_CMD_SWAP, Some(setname1), Some(setname2)).await
+}
+
+/// Replace the contents of an ipset atomically.
+///
+/// The entries are added to a temporary set created with `options`, which
+/// is then swapped with `setname` and destroyed along with the old entries.
+/// Rules using the set see either the old or the new entries, never a mix,
+/// which makes this the way to refresh a live blocklist. The set is created
+/// first if it doesn't exist yet.
+///
+/// The temporary set is named after `setname` with a `.tmp` suffix, and a
+/// leftover of an interrupted replacement is destroyed. The options must
+/// describe a set type compatible with the live set, see [`ipset_swap`].
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset to replace
+/// * `entries` - The new entries of the set
+/// * `options` - Options of the new set
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{IpSetCreateOptions, ipset_replace};
+///
+/// let blocklist: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// ipset_replace("blocklist", blocklist, &IpSetCreateOptions::default()).unwrap();
+/// ```
+pub fn ipset_replace<I, E>(setname: &str, entries: I, options: &IpSetCreateOptions) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(ipset_replace_async(setname, entries, options))
+}
+
+/// [`ipset_replace`] for async code.
+pub(crate) async fn ipset_replace_async<I, E>(
+    setname: &str,
+    entries: I,
+    options: &IpSetCreateOptions,
+) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+    if !ipset_exists_async(setname).await? {
+        ipset_create_async(setname, options).await?;
+    }
+
+    let temp = ipset_temp_name(setname);
+    match ipset_destroy_async(&temp).await {
+        Ok(()) | Err(RipsetError::SetNotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+    ipset_create_async(&temp, options).await?;
+
+    let mut result = ipset_add_many_async(&temp, entries).await;
+    if result.is_ok() {
+        result = ipset_swap_async(setname, &temp).await;
+    }
+    // After the swap, the temporary set holds the old entries
+    let destroyed = ipset_destroy_async(&temp).await;
+    result.and(destroyed)
+}
+
+/// Name of the temporary set used by [`ipset_replace`].
+fn ipset_temp_name(setname: &str) -> String {
+    const SUFFIX: &str = ".tmp";
+    let mut end = setname.len().min(IPSET_MAXNAMELEN - 1 - SUFFIX.len());
+    while !setname.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &setname[..end], SUFFIX)
+}
+
+/// Send a command that operates on a whole set. Without a set name the
+/// kernel applies the command to all sets. `setname2` names the other set of
+/// a swap.
+async fn ipset_set_command(cmd: u8, setname: Option<&str>, setname2: Option<&str>) -> Result<()> {
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+
+    buf.put_nlmsghdr(ipset_msg_type(cmd), NLM_F_REQUEST | NLM_F_ACK, 0);
+    buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
+
+    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+    if let Some(setname) = setname {
+        buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+    }
+    if let Some(setname2) = setname2 {
+        buf.put_attr_str(IPSET_ATTR_SETNAME2, setname2);
+    }
+
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
+
+    if recv_len < NlMsgHdr::SIZE {
+        return Err(RipsetError::ProtocolError);
+    }
+
+    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
+        if error == 0 {
+            return Ok(());
+        }
+        match (-error, setname, setname2) {
+            (libc::ENOENT, Some(setname), _) => {
+                return Err(RipsetError::SetNotFound(setname.to_string()));
+            }
+            (libc::IPSET_ERR_EXIST_SETNAME2, _, Some(setname2)) => {
+                return Err(RipsetError::SetNotFound(setname2.to_string()));
+            }
+            _ => return Err(ipset_errno_error(-error, &recv_buf[..recv_len])),
+        }
+    }
+
+    Err(RipsetError::ProtocolError)
+}
+
+/// A netlink socket kept open for a series of ipset operations.
+///
+/// The `ipset_*` functions open a socket for every call. A session keeps one
+/// socket and its sequence counter instead, which saves the setup cost when
+/// making many changes, for example from a DNS-driven updater.
+///
+/// Requests are built in a buffer reused by each thread, so adding,
+/// deleting and testing address entries allocates no memory.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::IpsetSession;
+///
+/// let session = IpsetSession::new().unwrap();
+/// for addr in ["192.168.1.1", "192.168.1.2"] {
+///     let addr: IpAddr = addr.parse().unwrap();
+///     session.add_exist("myset", addr).unwrap();
+/// }
+/// ```
+pub struct IpsetSession {
+    inner: IpsetSessionInner,
+    /// Keeps the session on one thread at a time: answers are matched to
+    /// requests by sequence number, so threads sharing the socket would
+    /// discard each other's answers.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl IpsetSession {
+    /// Open the netlink socket of a new session.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inner: IpsetSessionInner::new()?,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// Open a session managing the ipsets of the network namespace `netns`,
+    /// such as `/var/run/netns/<name>`. See [`with_netns`](crate::with_netns).
+    pub fn in_netns(netns: impl AsRef<Path>) -> Result<Self> {
+        Self::in_netns_fd(File::open(netns)?.as_fd())
+    }
+
+    /// Open a session managing the ipsets of the network namespace referred
+    /// to by the file descriptor `netns`.
+    pub fn in_netns_fd(netns: BorrowedFd<'_>) -> Result<Self> {
+        Ok(Self {
+            inner: IpsetSessionInner::in_netns_fd(netns)?,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// The socket of the session, for [`AsyncIpsetSession`](crate::AsyncIpsetSession)
+    /// which shares it between tasks behind a lock.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn into_inner(self) -> IpsetSessionInner {
+        self.inner
+    }
+
+    /// Add an entry to an ipset, like [`ipset_add`].
+    pub fn add<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.inner.add_async(setname, entry))
+    }
+
+    /// Add or refresh an entry of an ipset, like [`ipset_add_exist`].
+    pub fn add_exist<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.inner.add_exist_async(setname, entry))
+    }
+
+    /// Add many entries to an ipset, like [`ipset_add_many`].
+    pub fn add_many<I, E>(&self, setname: &str, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        block_on(self.inner.add_many_async(setname, entries))
+    }
+
+    /// Test many entries of an ipset, like [`ipset_test_many`].
+    pub fn test_many<I, E>(&self, setname: &str, entries: I) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        block_on(self.inner.test_many_async(setname, entries))
+    }
+
+    /// Delete many entries from an ipset, like [`ipset_del_many`].
+    pub fn del_many<I, E>(&self, setname: &str, entries: I) -> Result<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        block_on(self.inner.del_many_async(setname, entries))
+    }
+
+    /// Delete an entry from an ipset, like [`ipset_del`].
+    pub fn del<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.inner.del_async(setname, entry))
+    }
+
+    /// Test if an entry exists in an ipset, like [`ipset_test`].
+    pub fn test<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<bool> {
+        block_on(self.inner.test_async(setname, entry))
+    }
+
+    /// Start queueing requests that are sent without waiting for each
+    /// answer, see [`IpsetPipeline`].
+    pub fn pipeline(&self) -> IpsetPipeline<'_> {
+        self.inner.pipeline()
+    }
+}
+
+/// The socket of an [`IpsetSession`], which async sessions share between tasks
+/// behind a lock.
+pub(crate) struct IpsetSessionInner {
+    socket: NetlinkSocket,
+    seq: AtomicU32,
+    netns: Option<OwnedFd>,
+}
+
+impl IpsetSessionInner {
+    /// Open the netlink socket of a new session.
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Self {
+            socket: NetlinkSocket::new()?,
+            seq: AtomicU32::new(0),
+            netns: None,
+        })
+    }
+
+    /// Open a session managing the ipsets of the network namespace referred
+    /// to by the file descriptor `netns`.
+    fn in_netns_fd(netns: BorrowedFd<'_>) -> Result<Self> {
+        let netns = netns.try_clone_to_owned()?;
+        Ok(Self {
+            socket: with_netns_fd(netns.as_fd(), NetlinkSocket::new)?,
+            seq: AtomicU32::new(0),
+            netns: Some(netns),
+        })
+    }
+
+    /// Make the socket of the session wait on the tokio reactor, for
+    /// [`AsyncIpsetSession`](crate::AsyncIpsetSession).
+    #[cfg(feature = "tokio")]
+    pub(crate) fn register(&mut self) -> Result<()> {
+        Ok(self.socket.register()?)
+    }
+
+    /// Run `fut` in the network namespace of the session, so that the
+    /// requests it makes on other sockets reach the same sets.
+    async fn enter<F: Future>(&self, fut: F) -> F::Output {
+        match &self.netns {
+            Some(netns) => with_netns_fd_async(netns.as_fd(), fut).await,
+            None => fut.await,
+        }
+    }
+
+    fn next_seq(&self) -> u32 {
+        self.seq.fetch_add(1, Ordering::Relaxed).wrapping_add(1)
+    }
+
+    /// See [`IpsetSession::pipeline`].
+    fn pipeline(&self) -> IpsetPipeline<'_> {
+        IpsetPipeline {
+            session: self,
+            buf: MsgBuffer::new(BUFF_SZ),
+            first_seq: 0,
+            requests: Vec::new(),
+            setnames: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// [`IpsetSession::add`] for async code.
+    pub(crate) async fn add_async<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<()> {
+        let entry = entry.into();
+        self.enter(ipset_operate(
+            self,
+            setname,
+            &entry,
+            IPSET_CMD_ADD,
+            NLM_F_EXCL,
+        ))
+        .await
+    }
+
+    /// [`IpsetSession::add_exist`] for async code.
+    pub(crate) async fn add_exist_async<E: Into<Entry>>(
+        &self,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        let entry = entry.into();
+        self.enter(ipset_operate(self, setname, &entry, IPSET_CMD_ADD, 0))
+            .await
+    }
+
+    /// [`IpsetSession::add_many`] for async code.
+    pub(crate) async fn add_many_async<I, E>(&self, setname: &str, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let entries = entries.into_iter().map(Into::into);
+        self.enter(ipset_operate_many(self, setname, entries, IPSET_CMD_ADD, 0))
+            .await
+    }
+
+    /// [`IpsetSession::test_many`] for async code.
+    pub(crate) async fn test_many_async<I, E>(&self, setname: &str, entries: I) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let entries = entries.into_iter().map(Into::into);
+        self.enter(ipset_test_entries(self, setname, entries)).await
+    }
+
+    /// [`IpsetSession::del_many`] for async code.
+    pub(crate) async fn del_many_async<I, E>(
+        &self,
+        setname: &str,
+        entries: I,
+    ) -> Result<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        let entries = entries.into_iter().map(Into::into);
+        self.enter(ipset_del_entries(self, setname, entries)).await
+    }
+
+    /// [`IpsetSession::del`] for async code.
+    pub(crate) async fn del_async<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<()> {
+        let entry = entry.into();
+        self.enter(ipset_operate(self, setname, &entry, IPSET_CMD_DEL, 0))
+            .await
+    }
+
+    /// [`IpsetSession::test`] for async code.
+    pub(crate) async fn test_async<E: Into<Entry>>(&self, setname: &str, entry: E) -> Result<bool> {
+        let entry = entry.into();
+        match self
+            .enter(ipset_operate(self, setname, &entry, IPSET_CMD_TEST, 0))
+            .await
+        {
+            Ok(()) => Ok(true),
+            Err(RipsetError::EntryNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Requests queued on an [`IpsetSession`], sent without waiting for the
+/// answer to each.
+///
+/// ipset has no batches: every add, delete or test is a request of its own
+/// that the kernel acknowledges. A pipeline sends up to 128 of them
+/// together and then matches the acknowledgements to the requests by their
+/// sequence number, so a series of changes costs a round trip per group
+/// instead of one per change. Requests can mix commands and sets, and
+/// unlike [`IpsetSession::add_many`] each gets a result of its own.
+///
+/// The requests queued since the last group was sent go out with
+/// [`finish`](IpsetPipeline::finish); dropping the pipeline discards them.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::IpsetSession;
+///
+/// let session = IpsetSession::new()?;
+/// let mut pipeline = session.pipeline();
+/// for addr in ["192.168.1.1", "192.168.1.2"] {
+///     let addr: IpAddr = addr.parse().unwrap();
+///     pipeline.add_exist("allow", addr)?;
+///     pipeline.del("deny", addr)?;
+/// }
+/// for (i, result) in pipeline.finish()?.into_iter().enumerate() {
+///     if let Err(e) = result {
+///         eprintln!("request {i} failed: {e}");
+///     }
+/// }
+/// # Ok::<(), ripset::RipsetError>(())
+/// ```
+pub struct IpsetPipeline<'a> {
+    session: &'a IpsetSessionInner,
+    buf: MsgBuffer,
+    /// Sequence number of the first request in `buf`
+    first_seq: u32,
+    /// What each message in `buf` was sent for, in sequence order
+    requests: Vec<PipelinedRequest>,
+    /// Names of the sets the requests are about
+    setnames: Vec<String>,
+    /// Result of each queued request
+    results: Vec<Result<()>>,
+}
+
+/// A netlink request sent for a queued request of an [`IpsetPipeline`],
+/// which may take several when an entry is expanded.
+struct PipelinedRequest {
+    /// Index of the queued request
+    index: usize,
+    /// Index of the set name in `setnames`
+    setname: usize,
+    cmd: u8,
+}
+
+impl IpsetPipeline<'_> {
+    /// Number of queued requests.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Whether nothing has been queued.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    /// Queue the addition of an entry, like [`IpsetSession::add`].
+    ///
+    /// Fails only if a group of requests could not be exchanged with the
+    /// kernel; the result of the request is returned by
+    /// [`finish`](IpsetPipeline::finish).
+    pub fn add<E: Into<Entry>>(&mut self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.queue(setname, &entry.into(), IPSET_CMD_ADD, NLM_F_EXCL))
+    }
+
+    /// Queue the addition or refresh of an entry, like
+    /// [`IpsetSession::add_exist`].
+    pub fn add_exist<E: Into<Entry>>(&mut self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.queue(setname, &entry.into(), IPSET_CMD_ADD, 0))
+    }
+
+    /// Queue the deletion of an entry, like [`IpsetSession::del`].
+    pub fn del<E: Into<Entry>>(&mut self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.queue(setname, &entry.into(), IPSET_CMD_DEL, 0))
+    }
+
+    /// Queue the test of an entry. Its result is
+    /// [`RipsetError::EntryNotFound`] if the entry isn't in the set.
+    pub fn test<E: Into<Entry>>(&mut self, setname: &str, entry: E) -> Result<()> {
+        block_on(self.queue(setname, &entry.into(), IPSET_CMD_TEST, 0))
+    }
+
+    /// Send the remaining requests and return the result of every queued
+    /// request, in the order they were queued.
+    pub fn finish(self) -> Result<Vec<Result<()>>> {
+        block_on(self.finish_async())
+    }
+
+    /// [`IpsetPipeline::finish`] for async code.
+    async fn finish_async(mut self) -> Result<Vec<Result<()>>> {
+        self.send().await?;
+        Ok(self.results)
+    }
+
+    async fn queue(&mut self, setname: &str, entry: &Entry, cmd: u8, flags: u16) -> Result<()> {
+        let index = self.results.len();
+        if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+            self.results
+                .push(Err(RipsetError::InvalidSetName(setname.to_string())));
+            return Ok(());
+        }
+        let entries = match ipset_adt_entries(entry, cmd) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.results.push(Err(e));
+                return Ok(());
+            }
+        };
+        self.results.push(Ok(()));
+
+        // Requests are mostly about a few sets
+        let setname = match self.setnames.iter().rposition(|name| name == setname) {
+            Some(i) => i,
+            None => {
+                self.setnames.push(setname.to_string());
+                self.setnames.len() - 1
+            }
+        };
+        for entry in entries.iter() {
+            if self.requests.is_empty() {
+                self.first_seq = self.session.seq.load(Ordering::Relaxed).wrapping_add(1);
+            }
+            let start = self.buf.len();
+            self.buf.put_nlmsghdr(
+                ipset_msg_type(cmd),
+                NLM_F_REQUEST | NLM_F_ACK | flags,
+                self.session.next_seq(),
+            );
+            self.buf.put_nfgenmsg(ipset_entry_family(entry), 0, 0);
+            self.buf
+                .put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+            self.buf
+                .put_attr_str(IPSET_ATTR_SETNAME, &self.setnames[setname]);
+            put_adt_data(&mut self.buf, entry, 0);
+            self.buf.finalize_nlmsg_at(start);
+            self.requests.push(PipelinedRequest {
+                index,
+                setname,
+                cmd,
+            });
+
+            if self.requests.len() == IPSET_PIPELINE_WINDOW {
+                self.send().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the requests in the buffer and record their answers.
+    async fn send(&mut self) -> Result<()> {
+        if self.requests.is_empty() {
+            return Ok(());
+        }
+        let session = self.session;
+        let result = session.enter(self.exchange()).await;
+        self.buf.clear();
+        self.requests.clear();
+        result
+    }
+
+    async fn exchange(&mut self) -> Result<()> {
+        let socket = &self.session.socket;
+        socket.send(self.buf.as_slice())?;
+
+        // Errors echo the failed request, of which only the start is needed
+        let mut recv_buf = [0u8; BUFF_SZ];
+        let mut answered = 0;
+        while answered < self.requests.len() {
+            let recv_len = socket.recv_async(&mut recv_buf).await?;
+            if recv_len < NlMsgHdr::SIZE {
+                return Err(RipsetError::ProtocolError);
+            }
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+            // Skip answers left over from earlier requests
+            let Some(request) = self
+                .requests
+                .get(hdr.nlmsg_seq.wrapping_sub(self.first_seq) as usize)
+            else {
+                continue;
+            };
+            answered += 1;
+
+            let result = match parse_nlmsg_error(&recv_buf[..recv_len]) {
+                Some(0) => continue,
+                Some(error) => ipset_adt_error(
+                    -error,
+                    &self.setnames[request.setname],
+                    request.cmd,
+                    &recv_buf[..recv_len],
+                ),
+                None => RipsetError::ProtocolError,
+            };
+            // An expanded entry fails with the first of its requests
+            if self.results[request.index].is_ok() {
+                self.results[request.index] = Err(result);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Add an IP address to an ipset.
+///
+/// Returns [`RipsetError::EntryExists`] if the entry is already in the set;
+/// use [`ipset_add_exist`] to re-add it instead.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entry` - The entry to add (can be created from IpAddr, IpEntry, or a set name for list:set)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_add;
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// ipset_add("myset", addr).unwrap();
+/// ```
+pub fn ipset_add<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    block_on(ipset_add_async(setname, entry))
+}
+
+/// [`ipset_add`] for async code.
+pub(crate) async fn ipset_add_async<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    IpsetSessionInner::new()?.add_async(setname, entry).await
+}
+
+/// Add an entry to an ipset, updating it if it is already present
+/// (`ipset add -exist`).
+///
+/// An existing entry never leaves the set: its timeout is reset to the
+/// given (or the set's default) value and its extensions are replaced.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entry` - The entry to add or refresh
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{IpEntry, ipset_add_exist};
+///
+/// let entry = IpEntry::with_timeout("192.168.1.1".parse().unwrap(), 300);
+/// ipset_add_exist("recently_seen", entry).unwrap();
+/// ```
+pub fn ipset_add_exist<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    block_on(ipset_add_exist_async(setname, entry))
+}
+
+/// [`ipset_add_exist`] for async code.
+pub(crate) async fn ipset_add_exist_async<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    IpsetSessionInner::new()?
+        .add_exist_async(setname, entry)
+        .await
+}
+
+/// Make sure an entry is in an ipset, adding it if it is missing.
+///
+/// Unlike [`ipset_add_exist`], an entry already in the set is left as it
+/// is, so its timeout keeps running. Each CIDR block of a range entry is
+/// added if missing.
+///
+/// Returns whether anything was added.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entry` - The entry that must be in the set
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_ensure_entry;
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// if ipset_ensure_entry("myset", addr).unwrap() {
+///     println!("added {addr}");
+/// }
+/// ```
+pub fn ipset_ensure_entry<E: Into<Entry>>(setname: &str, entry: E) -> Result<bool> {
+    block_on(ipset_ensure_entry_async(setname, entry))
+}
+
+/// [`ipset_ensure_entry`] for async code.
+pub(crate) async fn ipset_ensure_entry_async<E: Into<Entry>>(
+    setname: &str,
+    entry: E,
+) -> Result<bool> {
+    let session = IpsetSessionInner::new()?;
+    let mut added = false;
+    let entry = entry.into();
+    for block in ipset_adt_entries(&entry, IPSET_CMD_ADD)?.iter() {
+        match session.add_async(setname, block.clone()).await {
+            Ok(()) => added = true,
+            Err(RipsetError::EntryExists) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(added)
+}
+
+/// Add many entries to an ipset at once.
+///
+/// The entries are packed into as few netlink messages as possible, and
+/// several messages are sent together. This is much faster than calling
+/// [`ipset_add`] for each entry, e.g. when importing a large blocklist.
+///
+/// Entries already in the set are refreshed as by [`ipset_add_exist`]. The
+/// import isn't atomic: if an entry is refused, the first error is returned
+/// and the other entries may or may not have been added.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entries` - The entries to add
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_add_many;
+///
+/// let addrs: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// ipset_add_many("myset", addrs).unwrap();
+/// ```
+pub fn ipset_add_many<I, E>(setname: &str, entries: I) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(ipset_add_many_async(setname, entries))
+}
+
+/// [`ipset_add_many`] for async code.
+pub(crate) async fn ipset_add_many_async<I, E>(setname: &str, entries: I) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    IpsetSessionInner::new()?
+        .add_many_async(setname, entries)
+        .await
+}
+
+/// Delete an IP address from an ipset.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entry` - The entry to delete (can be created from IpAddr, IpEntry, or a set name for list:set)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_del;
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// ipset_del("myset", addr).unwrap();
+/// ```
+pub fn ipset_del<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    block_on(ipset_del_async(setname, entry))
+}
+
+/// [`ipset_del`] for async code.
+pub(crate) async fn ipset_del_async<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    IpsetSessionInner::new()?.del_async(setname, entry).await
+}
+
+/// Delete many entries from an ipset at once.
+///
+/// The entries are packed into as few netlink messages as possible, like
+/// [`ipset_add_many`]. An entry that can't be deleted, for example because
+/// it already timed out, doesn't stop the others.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entries` - The entries to delete
+///
+/// # Returns
+///
+/// The entries that weren't deleted, as their index in `entries` along with
+/// the reason, such as [`RipsetError::EntryNotFound`]. Errors concerning
+/// the whole set, like [`RipsetError::SetNotFound`], are returned as `Err`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_del_many;
+///
+/// let expired: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// for (index, error) in ipset_del_many("myset", expired.clone()).unwrap() {
+///     eprintln!("{} not deleted: {}", expired[index], error);
+/// }
+/// ```
+pub fn ipset_del_many<I, E>(setname: &str, entries: I) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(ipset_del_many_async(setname, entries))
+}
+
+/// [`ipset_del_many`] for async code.
+pub(crate) async fn ipset_del_many_async<I, E>(
+    setname: &str,
+    entries: I,
+) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    IpsetSessionInner::new()?
+        .del_many_async(setname, entries)
+        .await
+}
+
+/// Test if an IP address exists in an ipset.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entry` - The entry to test (can be created from IpAddr, IpEntry, or a set name for list:set)
+///
+/// # Returns
+///
+/// * `Ok(true)` - The IP address exists in the set
+/// * `Ok(false)` - The IP address does not exist in the set
+/// * `Err(_)` - An error occurred
+///
+/// Addresses covered by a `nomatch` entry of a hash:net set are reported as
+/// not in the set. Testing an entry with `nomatch` set reports whether the
+/// address hits such an exception entry instead.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_test;
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// let exists = ipset_test("myset", addr).unwrap();
+/// ```
+pub fn ipset_test<E: Into<Entry>>(setname: &str, entry: E) -> Result<bool> {
+    block_on(ipset_test_async(setname, entry))
+}
+
+/// [`ipset_test`] for async code.
+pub(crate) async fn ipset_test_async<E: Into<Entry>>(setname: &str, entry: E) -> Result<bool> {
+    IpsetSessionInner::new()?.test_async(setname, entry).await
+}
+
+/// Test many entries of an ipset at once.
+///
+/// The tests are sent in groups over one socket instead of waiting for the
+/// answer to each, which is much faster than calling [`ipset_test`] for
+/// each entry.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entries` - The entries to test
+///
+/// # Returns
+///
+/// Whether each entry is in the set, in the order of `entries`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_test_many;
+///
+/// let addrs: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// let found = ipset_test_many("myset", addrs.iter().copied()).unwrap();
+/// for (addr, found) in addrs.iter().zip(found) {
+///     println!("{}: {}", addr, found);
+/// }
+/// ```
+pub fn ipset_test_many<I, E>(setname: &str, entries: I) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(ipset_test_many_async(setname, entries))
+}
+
+/// [`ipset_test_many`] for async code.
+pub(crate) async fn ipset_test_many_async<I, E>(setname: &str, entries: I) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    IpsetSessionInner::new()?
+        .test_many_async(setname, entries)
+        .await
+}
+
+/// Test if an entry exists in an ipset, with additional match options.
+///
+/// Counter comparisons require a set created with counters. The kernel only
+/// evaluates them for packet matches, so the counters of the matching entry
+/// are read back from the set and compared here. That entry is the one with
+/// the same element, port, protocol, MAC, mark and interface included; an
+/// address tested against a hash:net set matches its longest prefix.
+///
+/// Reading the counters back dumps the whole set, so with a `packets` or
+/// `bytes` condition each call costs time proportional to the size of the
+/// set. Without one, this is a single request like [`ipset_test`]. To
+/// check many entries of a large set, list it once with
+/// [`ipset_list_entries`] instead.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `entry` - The entry to test
+/// * `options` - Match options
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{CounterMatch, IpSetTestOptions, ipset_test_with_options};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// let opts = IpSetTestOptions {
+///     packets: Some(CounterMatch::Gt(1000)),
+///     ..Default::default()
+/// };
+/// let heavy_hitter = ipset_test_with_options("counted", addr, &opts).unwrap();
+/// ```
+pub fn ipset_test_with_options<E: Into<Entry>>(
+    setname: &str,
+    entry: E,
+    options: &IpSetTestOptions,
+) -> Result<bool> {
+    block_on(ipset_test_with_options_async(setname, entry, options))
+}
+
+/// [`ipset_test_with_options`] for async code.
+pub(crate) async fn ipset_test_with_options_async<E: Into<Entry>>(
+    setname: &str,
+    entry: E,
+    options: &IpSetTestOptions,
+) -> Result<bool> {
+    let entry = entry.into();
+    // Only counter conditions need the set to be dumped
+    if options.packets.is_none() && options.bytes.is_none() {
+        return ipset_test_async(setname, entry).await;
+    }
+
+    let key = match entry {
+        Entry::Ip(ip) => ip,
+        Entry::Concat(parts) => concat_to_ip_entry(&parts)?,
+        _ => {
+            return Err(RipsetError::InvalidOption(
+                "counter matching requires an IP entry".to_string(),
+            ));
+        }
+    };
+    // The kernel adds the counters of a tested entry to the stored ones
+    let key = ipset_element_key(&key);
+    if !ipset_test_async(setname, key.clone()).await? {
+        return Ok(false);
+    }
+    let entries = ipset_list_entries_async(setname).await?;
+    let (packets, bytes) = match find_matching_entry(&entries, &key) {
+        Some(IpEntry {
+            packets: Some(packets),
+            bytes: Some(bytes),
+            ..
+        }) => (*packets, *bytes),
+        _ => {
+            return Err(RipsetError::InvalidOption(format!(
+                "{setname} has no counters"
+            )));
+        }
+    };
+
+    Ok(options.packets.is_none_or(|m| m.matches(packets))
+        && options.bytes.is_none_or(|m| m.matches(bytes)))
+}
+
+/// Test if an IP address is in an ipset and return the entry that matched.
+///
+/// For hash:net sets this is the longest prefix covering the address, which
+/// helps with debugging overlapping prefixes. For other set types it is the
+/// entry of the address itself.
+///
+/// Only `hash:ip`, `hash:net` and `bitmap:ip` sets are supported. An address
+/// alone does not tell apart the entries of types such as `hash:ip,port`, so
+/// other sets fail with [`RipsetError::InvalidOption`].
+///
+/// The matching entry is found by dumping the set when the address is in
+/// it, which costs time proportional to the size of the set.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+/// * `addr` - The IP address to look up
+///
+/// # Returns
+///
+/// * `Ok(Some(entry))` - The address is in the set, matched by `entry`
+/// * `Ok(None)` - The address is not in the set
+/// * `Err(_)` - An error occurred
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::ipset_test_match;
+///
+/// let addr: IpAddr = "10.1.2.3".parse().unwrap();
+/// if let Some(entry) = ipset_test_match("mynets", addr).unwrap() {
+///     println!("matched {}/{}", entry.addr, entry.cidr.unwrap_or(32));
+/// }
+/// ```
+pub fn ipset_test_match(setname: &str, addr: IpAddr) -> Result<Option<IpEntry>> {
+    block_on(ipset_test_match_async(setname, addr))
+}
+
+/// [`ipset_test_match`] for async code.
+pub(crate) async fn ipset_test_match_async(setname: &str, addr: IpAddr) -> Result<Option<IpEntry>> {
+    let info = ipset_info_async(setname).await?;
+    if !matches!(
+        info.type_name.as_str(),
+        "hash:ip" | "hash:net" | "bitmap:ip"
+    ) {
+        return Err(RipsetError::InvalidOption(format!(
+            "cannot match an address against the entries of a {} set",
+            info.type_name
+        )));
+    }
+    if !ipset_test_async(setname, addr).await? {
+        return Ok(None);
+    }
+    let entries = ipset_list_entries_async(setname).await?;
+    Ok(find_matching_entry(&entries, &IpEntry::new(addr)).cloned())
+}
+
+/// Find the most specific listed entry matching an element key, as built by
+/// [`ipset_element_key`].
+///
+/// The fields besides the address must be equal. A key with a prefix length
+/// only matches that very network, like the kernel tests it, while a bare
+/// address matches the longest listed prefix covering it.
+fn find_matching_entry<'a>(entries: &'a [Entry], key: &IpEntry) -> Option<&'a IpEntry> {
+    entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Ip(ip) if !ip.nomatch => Some(ip),
+            _ => None,
+        })
+        .filter(|ip| {
+            let stored = ipset_element_key(ip);
+            let addr_matches = match (key.cidr, stored.cidr) {
+                (Some(_), _) => stored.addr == key.addr && stored.cidr == key.cidr,
+                (None, Some(cidr)) => prefix_contains(stored.addr, cidr, key.addr),
+                (None, None) => stored.addr == key.addr,
+            };
+            addr_matches
+                && stored.port == key.port
+                && stored.proto == key.proto
+                && stored.mac == key.mac
+                && stored.mark == key.mark
+                && stored.iface == key.iface
+        })
+        .max_by_key(|ip| ip.cidr.unwrap_or(u8::MAX))
+}
+
+/// Whether `addr` lies within the network `net/cidr`.
+fn prefix_contains(net: IpAddr, cidr: u8, addr: IpAddr) -> bool {
+    match (net, addr) {
+        (IpAddr::V4(net), IpAddr::V4(addr)) => {
+            let mask = u32::MAX.checked_shl(32 - cidr.min(32) as u32).unwrap_or(0);
+            u32::from(net) & mask == u32::from(addr) & mask
+        }
+        (IpAddr::V6(net), IpAddr::V6(addr)) => {
+            let mask = u128::MAX
+                .checked_shl(128 - cidr.min(128) as u32)
+                .unwrap_or(0);
+            u128::from(net) & mask == u128::from(addr) & mask
+        }
+        _ => false,
+    }
+}
+
+/// List all IP addresses in an ipset.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+///
+/// # Returns
+///
+/// A vector of IP addresses currently in the set.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_list;
+///
+/// let ips = ipset_list("myset").unwrap();
+/// for ip in ips {
+///     println!("{}", ip);
+/// }
+/// ```
+pub fn ipset_list(setname: &str) -> Result<Vec<IpAddr>> {
+    block_on(ipset_list_async(setname))
+}
 
-    Err(IpSetError::ProtocolError)
+/// [`ipset_list`] for async code.
+pub(crate) async fn ipset_list_async(setname: &str) -> Result<Vec<IpAddr>> {
+    let entries = ipset_list_entries_async(setname).await?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Entry::Ip(ip) => Some(ip.addr),
+            _ => None,
+        })
+        .collect())
 }
 
-/// Destroy an ipset.
+/// List all entries in an ipset.
+///
+/// Unlike [`ipset_list`], this also returns non-IP members (such as the
+/// set names of a `list:set`) and per-entry data like the remaining timeout,
+/// bound MAC addresses, packet/byte counters, comments and skbinfo values.
 ///
 /// # Arguments
 ///
-/// * `setname` - The name of the ipset to destroy
+/// * `setname` - The name of the ipset
 ///
 /// # Example
 ///
 /// ```no_run
-/// use ruhop_ipset::ipset_destroy;
+/// use ripset::{Entry, ipset_list_entries};
 ///
-/// ipset_destroy("myset").unwrap();
+/// for entry in ipset_list_entries("mylist").unwrap() {
+///     if let Entry::SetName(name) = entry {
+///         println!("{}", name);
+///     }
+/// }
 /// ```
-pub fn ipset_destroy(setname: &str) -> Result<()> {
-    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
-    }
-
-    let mut buf = MsgBuffer::new(BUFF_SZ);
-
-    buf.put_nlmsghdr(
-        ipset_msg_type(IPSET_CMD_DESTROY),
-        NLM_F_REQUEST | NLM_F_ACK,
-        0,
-    );
-    buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
-
-    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, IPSET_PROTOCOL);
-    buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
-
-    buf.finalize_nlmsg();
-
-    let socket = NetlinkSocket::new()?;
-    let mut recv_buf = [0u8; BUFF_SZ];
-    let recv_len = socket.send_recv(buf.as_slice(), &mut recv_buf)?;
+pub fn ipset_list_entries(setname: &str) -> Result<Vec<Entry>> {
+    block_on(ipset_list_entries_async(setname))
+}
 
-    if recv_len < NlMsgHdr::SIZE {
-        return Err(IpSetError::ProtocolError);
+/// [`ipset_list_entries`] for async code.
+pub(crate) async fn ipset_list_entries_async(setname: &str) -> Result<Vec<Entry>> {
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
     }
 
-    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-        if error == 0 {
-            return Ok(());
-        }
-        match -error {
-            libc::ENOENT => return Err(IpSetError::SetNotFound(setname.to_string())),
-            libc::EBUSY => return Err(IpSetError::NetlinkError(-error)), // Set is in use
-            _ => return Err(IpSetError::NetlinkError(-error)),
+    dump_retry_async(move || async move {
+        let mut iter = ipset_list_open(setname).await?;
+        let mut entries = Vec::new();
+        while let Some(entry) = iter.next_async().await {
+            entries.push(entry?);
         }
-    }
-
-    Err(IpSetError::ProtocolError)
+        Ok(entries)
+    })
+    .await
 }
 
-/// Flush (remove all elements from) an ipset.
+/// Iterate over the entries of an ipset as they are received.
+///
+/// Unlike [`ipset_list_entries`], entries are decoded from one dump
+/// message at a time instead of being collected first, so going through a
+/// set of millions of entries takes little memory.
+///
+/// A missing set is reported by this function. Errors during the dump end
+/// the iteration with an error item; this includes a socket overflow
+/// (`ENOBUFS`), which can't be recovered from without returning entries
+/// twice. Later dumps get a larger receive buffer, so listing again from
+/// the start usually succeeds.
 ///
 /// # Arguments
 ///
-/// * `setname` - The name of the ipset to flush
+/// * `setname` - The name of the ipset
 ///
 /// # Example
 ///
 /// ```no_run
-/// use ruhop_ipset::ipset_flush;
+/// use ripset::{Entry, ipset_list_iter};
 ///
-/// ipset_flush("myset").unwrap();
+/// let mut v6 = 0;
+/// for entry in ipset_list_iter("blocklist").unwrap() {
+///     if let Entry::Ip(ip) = entry.unwrap() {
+///         v6 += usize::from(ip.addr.is_ipv6());
+///     }
+/// }
+/// println!("{v6} IPv6 entries");
 /// ```
-pub fn ipset_flush(setname: &str) -> Result<()> {
+pub fn ipset_list_iter(setname: &str) -> Result<IpSetListIter> {
     if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
     }
 
-    let mut buf = MsgBuffer::new(BUFF_SZ);
-
-    buf.put_nlmsghdr(
-        ipset_msg_type(IPSET_CMD_FLUSH),
-        NLM_F_REQUEST | NLM_F_ACK,
-        0,
-    );
-    buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
-
-    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, IPSET_PROTOCOL);
-    buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
-
-    buf.finalize_nlmsg();
+    block_on(dump_retry_async(move || ipset_list_open(setname)))
+}
 
-    let socket = NetlinkSocket::new()?;
-    let mut recv_buf = [0u8; BUFF_SZ];
-    let recv_len = socket.send_recv(buf.as_slice(), &mut recv_buf)?;
+/// Start a LIST dump of `setname` and decode its first message, which
+/// tells whether the set exists.
+async fn ipset_list_open(setname: &str) -> Result<IpSetListIter> {
+    let dump = DumpStream::new(ipset_list_request(setname, 0).as_slice())?;
+    let mut iter = IpSetListIter {
+        setname: setname.to_string(),
+        dump: Some(dump),
+        entries: Vec::new().into_iter(),
+    };
+    iter.fill().await?;
+    Ok(iter)
+}
 
-    if recv_len < NlMsgHdr::SIZE {
-        return Err(IpSetError::ProtocolError);
-    }
+/// Iterator over the entries of an ipset, returned by [`ipset_list_iter`].
+pub struct IpSetListIter {
+    setname: String,
+    /// The dump, until it is over or failed.
+    dump: Option<DumpStream>,
+    /// Entries decoded from the last message.
+    entries: std::vec::IntoIter<Entry>,
+}
 
-    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-        if error == 0 {
+impl IpSetListIter {
+    /// Decode the entries of the next message of the dump.
+    async fn fill(&mut self) -> Result<()> {
+        let Some(dump) = &mut self.dump else {
             return Ok(());
+        };
+        let message = match dump.next_message_async().await {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                self.dump = None;
+                return Ok(());
+            }
+            Err(e) => {
+                self.dump = None;
+                return Err(e.into());
+            }
+        };
+
+        let mut entries = Vec::new();
+        match parse_nlmsg_error(message) {
+            Some(0) => {}
+            Some(error) => {
+                let error = ipset_list_error(-error, message, &self.setname);
+                self.dump = None;
+                return Err(error);
+            }
+            None => parse_ipset_list_attrs(
+                message
+                    .get(NlMsgHdr::SIZE + NfGenMsg::SIZE..)
+                    .unwrap_or_default(),
+                &mut entries,
+            ),
         }
-        match -error {
-            libc::ENOENT => return Err(IpSetError::SetNotFound(setname.to_string())),
-            _ => return Err(IpSetError::NetlinkError(-error)),
+        self.entries = entries.into_iter();
+        Ok(())
+    }
+
+    /// [`Iterator::next`] for async code.
+    async fn next_async(&mut self) -> Option<Result<Entry>> {
+        loop {
+            if let Some(entry) = self.entries.next() {
+                return Some(Ok(entry));
+            }
+            self.dump.as_ref()?;
+            if let Err(e) = self.fill().await {
+                return Some(Err(e));
+            }
         }
     }
+}
 
-    Err(IpSetError::ProtocolError)
+impl Iterator for IpSetListIter {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        block_on(async { self.next_async().await.transpose() }).transpose()
+    }
 }
 
-/// Add an IP address to an ipset.
+/// Zero the packet and byte counters of every entry of an ipset.
+///
+/// The kernel has no command to reset counters, so the entries are listed
+/// and added again with zero counters, keeping their remaining timeout,
+/// comment and skbinfo. Packets matched between the listing and the reset
+/// are not counted, and an entry deleted in between is added back.
+///
+/// Returns the entries with their counters before the reset. Entries
+/// without an address, such as `bitmap:port` and `hash:mac` entries, keep
+/// their counters. Sets without counters are left unchanged.
 ///
 /// # Arguments
 ///
 /// * `setname` - The name of the ipset
-/// * `entry` - The IP entry to add (can be created from IpAddr)
 ///
 /// # Example
 ///
 /// ```no_run
-/// use std::net::IpAddr;
-/// use ruhop_ipset::ipset_add;
+/// use ripset::{Entry, ipset_reset_counters};
 ///
-/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
-/// ipset_add("myset", addr).unwrap();
+/// for entry in ipset_reset_counters("accounting").unwrap() {
+///     if let Entry::Ip(ip) = entry {
+///         println!("{}: {} bytes", ip.addr, ip.bytes.unwrap_or(0));
+///     }
+/// }
 /// ```
-pub fn ipset_add<E: Into<IpEntry>>(setname: &str, entry: E) -> Result<()> {
-    ipset_operate(setname, &entry.into(), IPSET_CMD_ADD)
+pub fn ipset_reset_counters(setname: &str) -> Result<Vec<Entry>> {
+    block_on(ipset_reset_counters_async(setname))
 }
 
-/// Delete an IP address from an ipset.
+/// [`ipset_reset_counters`] for async code.
+pub(crate) async fn ipset_reset_counters_async(setname: &str) -> Result<Vec<Entry>> {
+    let entries = ipset_list_entries_async(setname).await?;
+    let reset: Vec<Entry> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Ip(ip) => zeroed_counters(ip),
+            _ => None,
+        })
+        .collect();
+    if !reset.is_empty() {
+        ipset_add_many_async(setname, reset).await?;
+    }
+    Ok(entries)
+}
+
+/// Zero the packet and byte counters of one entry of an ipset.
+///
+/// The entry is looked up in a listing of the set, which costs as much as
+/// [`ipset_list_entries`], and added again with zero counters like
+/// [`ipset_reset_counters`] does. Only the element matters in `entry`: its
+/// timeout, comment and other extensions are ignored.
+///
+/// Fails with [`RipsetError::EntryNotFound`] if the entry is not in the
+/// set.
 ///
 /// # Arguments
 ///
 /// * `setname` - The name of the ipset
-/// * `entry` - The IP entry to delete (can be created from IpAddr)
+/// * `entry` - The entry whose counters are reset
 ///
 /// # Example
 ///
 /// ```no_run
 /// use std::net::IpAddr;
-/// use ruhop_ipset::ipset_del;
+/// use ripset::ipset_reset_entry_counters;
 ///
 /// let addr: IpAddr = "192.168.1.1".parse().unwrap();
-/// ipset_del("myset", addr).unwrap();
+/// ipset_reset_entry_counters("accounting", addr).unwrap();
 /// ```
-pub fn ipset_del<E: Into<IpEntry>>(setname: &str, entry: E) -> Result<()> {
-    ipset_operate(setname, &entry.into(), IPSET_CMD_DEL)
+pub fn ipset_reset_entry_counters<E: Into<Entry>>(setname: &str, entry: E) -> Result<()> {
+    block_on(ipset_reset_entry_counters_async(setname, entry))
 }
 
-/// Test if an IP address exists in an ipset.
+/// [`ipset_reset_entry_counters`] for async code.
+pub(crate) async fn ipset_reset_entry_counters_async<E: Into<Entry>>(
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    let key = match entry.into() {
+        Entry::Ip(ip) => ip,
+        Entry::Concat(parts) => concat_to_ip_entry(&parts)?,
+        _ => {
+            return Err(RipsetError::InvalidOption(
+                "only address entries can have their counters reset".to_string(),
+            ));
+        }
+    };
+    let key = ipset_element_key(&key);
+
+    let stored = ipset_list_entries_async(setname)
+        .await?
+        .into_iter()
+        .find_map(|entry| match entry {
+            Entry::Ip(ip) if ipset_element_key(&ip) == key => Some(ip),
+            _ => None,
+        })
+        .ok_or(RipsetError::EntryNotFound)?;
+    match zeroed_counters(&stored) {
+        Some(entry) => ipset_add_exist_async(setname, entry).await,
+        None => Ok(()),
+    }
+}
+
+/// An entry with zero counters, or `None` if it has no counters.
+fn zeroed_counters(ip: &IpEntry) -> Option<Entry> {
+    ip.packets?;
+    let mut ip = ip.clone();
+    ip.packets = Some(0);
+    ip.bytes = Some(0);
+    Some(Entry::Ip(ip))
+}
+
+/// The element an entry stands for, without its extensions, to compare a
+/// given entry with a listed one.
+fn ipset_element_key(ip: &IpEntry) -> IpEntry {
+    let mut key = IpEntry::new(ip.addr);
+    let bits = if ip.addr.is_ipv4() { 32 } else { 128 };
+    key.cidr = ip.cidr.filter(|&cidr| cidr != bits);
+    key.port = ip.port;
+    // The kernel reports the protocol a port defaults to
+    key.proto = ip.proto.or(ip.port.map(|_| libc::IPPROTO_TCP as u8));
+    key.mac = ip.mac;
+    key.mark = ip.mark;
+    key.iface = ip.iface.clone();
+    key
+}
+
+/// Information about an ipset, as shown by `ipset list -t`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpSetInfo {
+    /// Set name
+    pub name: String,
+    /// Set type name, e.g. `hash:ip`
+    pub type_name: String,
+    /// Set type revision
+    pub revision: u8,
+    /// Address family (`None` for family-independent types like list:set)
+    pub family: Option<IpSetFamily>,
+    /// Default entry timeout in seconds
+    pub timeout: Option<u32>,
+    /// Hash table size (hash types)
+    pub hashsize: Option<u32>,
+    /// Maximal number of elements (hash types)
+    pub maxelem: Option<u32>,
+    /// Maximal number of elements per bucket (hash types)
+    pub bucketsize: Option<u8>,
+    /// Hash seed (hash types)
+    pub initval: Option<u32>,
+    /// Address range (bitmap types)
+    pub range: Option<IpSetRange>,
+    /// Maximal number of member sets (list:set)
+    pub size: Option<u32>,
+    /// Entries carry packet and byte counters
+    pub counters: bool,
+    /// Entries may carry a comment
+    pub comment: bool,
+    /// Entries may carry skbinfo values
+    pub skbinfo: bool,
+    /// A full hash set evicts entries on add
+    pub forceadd: bool,
+    /// Number of references (e.g. from iptables rules or list:set sets)
+    pub references: u32,
+    /// Memory used by the set in bytes
+    pub memsize: u32,
+    /// Number of entries in the set
+    pub entries: u32,
+}
+
+impl IpSetInfo {
+    /// Options that create a set with the same header, e.g. to recreate it
+    /// elsewhere. The size of a list:set is not carried over.
+    pub fn create_options(&self) -> Result<IpSetCreateOptions> {
+        Ok(IpSetCreateOptions {
+            set_type: self.type_name.parse()?,
+            family: self.family.unwrap_or(IpSetFamily::Inet),
+            hashsize: self.hashsize,
+            maxelem: self.maxelem,
+            bucketsize: self.bucketsize,
+            initval: self.initval,
+            timeout: self.timeout,
+            range: self.range,
+            counters: self.counters,
+            comment: self.comment,
+            skbinfo: self.skbinfo,
+            forceadd: self.forceadd,
+            extra_attrs: Vec::new(),
+        })
+    }
+}
+
+/// Query the header of an ipset.
 ///
 /// # Arguments
 ///
 /// * `setname` - The name of the ipset
-/// * `entry` - The IP entry to test (can be created from IpAddr)
 ///
-/// # Returns
+/// # Example
 ///
-/// * `Ok(true)` - The IP address exists in the set
-/// * `Ok(false)` - The IP address does not exist in the set
-/// * `Err(_)` - An error occurred
+/// ```no_run
+/// use ripset::ipset_info;
+///
+/// let info = ipset_info("myset").unwrap();
+/// println!("{} ({}): {} entries", info.name, info.type_name, info.entries);
+/// ```
+pub fn ipset_info(setname: &str) -> Result<IpSetInfo> {
+    block_on(ipset_info_async(setname))
+}
+
+/// [`ipset_info`] for async code.
+pub(crate) async fn ipset_info_async(setname: &str) -> Result<IpSetInfo> {
+    if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    let info = dump_retry_async(move || async move {
+        let mut info = None;
+        ipset_list_dump(setname, IPSET_FLAG_LIST_HEADER, |attrs| {
+            if info.is_none() {
+                info = parse_ipset_header_attrs(attrs);
+            }
+        })
+        .await?;
+        Ok(info)
+    })
+    .await?;
+    info.ok_or(RipsetError::ProtocolError)
+}
+
+/// List the headers of all ipsets, in the order the kernel keeps them.
 ///
 /// # Example
 ///
 /// ```no_run
-/// use std::net::IpAddr;
-/// use ruhop_ipset::ipset_test;
+/// use ripset::ipset_list_sets;
 ///
-/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
-/// let exists = ipset_test("myset", addr).unwrap();
+/// for info in ipset_list_sets().unwrap() {
+///     println!("{} ({}): {} entries", info.name, info.type_name, info.entries);
+/// }
+/// ```
+pub fn ipset_list_sets() -> Result<Vec<IpSetInfo>> {
+    block_on(ipset_list_sets_async())
+}
+
+/// [`ipset_list_sets`] for async code.
+pub(crate) async fn ipset_list_sets_async() -> Result<Vec<IpSetInfo>> {
+    dump_retry_async(|| async {
+        let mut sets = Vec::new();
+        ipset_list_dump("", IPSET_FLAG_LIST_HEADER, |attrs| {
+            sets.extend(parse_ipset_header_attrs(attrs));
+        })
+        .await?;
+        Ok(sets)
+    })
+    .await
+}
+
+/// Check whether an ipset exists.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_exists;
+///
+/// if !ipset_exists("myset").unwrap() {
+///     println!("myset is missing");
+/// }
 /// ```
-pub fn ipset_test<E: Into<IpEntry>>(setname: &str, entry: E) -> Result<bool> {
-    match ipset_operate(setname, &entry.into(), IPSET_CMD_TEST) {
-        Ok(()) => Ok(true),
-        Err(IpSetError::ElementNotFound) => Ok(false),
+pub fn ipset_exists(setname: &str) -> Result<bool> {
+    block_on(ipset_exists_async(setname))
+}
+
+/// [`ipset_exists`] for async code.
+pub(crate) async fn ipset_exists_async(setname: &str) -> Result<bool> {
+    match ipset_info_async(setname).await {
+        Ok(_) => Ok(true),
+        Err(RipsetError::SetNotFound(_)) => Ok(false),
         Err(e) => Err(e),
     }
 }
 
-/// List all IP addresses in an ipset.
+/// Count the references to an ipset.
+///
+/// iptables rules using the set and list:set sets holding it each count as
+/// one; the set can't be destroyed until there are none left.
 ///
 /// # Arguments
 ///
 /// * `setname` - The name of the ipset
 ///
-/// # Returns
+/// # Example
 ///
-/// A vector of IP addresses currently in the set.
+/// ```no_run
+/// use ripset::ipset_references;
+///
+/// println!("myset is used {} times", ipset_references("myset").unwrap());
+/// ```
+pub fn ipset_references(setname: &str) -> Result<u32> {
+    block_on(ipset_references_async(setname))
+}
+
+/// [`ipset_references`] for async code.
+pub(crate) async fn ipset_references_async(setname: &str) -> Result<u32> {
+    Ok(ipset_info_async(setname).await?.references)
+}
+
+/// Count the entries of an ipset.
+///
+/// The count is read from the set header, so the entries themselves are
+/// not dumped unless the kernel is too old to report it.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
 ///
 /// # Example
 ///
 /// ```no_run
-/// use linux_ipsets::ipset_list;
+/// use ripset::ipset_count;
 ///
-/// let ips = ipset_list("myset").unwrap();
-/// for ip in ips {
-///     println!("{}", ip);
-/// }
+/// println!("myset has {} entries", ipset_count("myset").unwrap());
 /// ```
-pub fn ipset_list(setname: &str) -> Result<Vec<IpAddr>> {
+pub fn ipset_count(setname: &str) -> Result<u32> {
+    block_on(ipset_count_async(setname))
+}
+
+/// [`ipset_count`] for async code.
+pub(crate) async fn ipset_count_async(setname: &str) -> Result<u32> {
     if setname.is_empty() || setname.len() >= IPSET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    let count = dump_retry_async(move || async move {
+        let mut count = None;
+        ipset_list_dump(setname, IPSET_FLAG_LIST_HEADER, |attrs| {
+            count = count.or_else(|| parse_ipset_header_elements(attrs));
+        })
+        .await?;
+        Ok(count)
+    })
+    .await?;
+    if let Some(count) = count {
+        return Ok(count);
+    }
+
+    // Older kernels don't report the element count in the header
+    dump_retry_async(move || async move {
+        let mut count = 0;
+        ipset_list_dump(setname, 0, |attrs| {
+            for (attr_type, payload) in NlAttrIter::new(attrs) {
+                if attr_type == IPSET_ATTR_ADT {
+                    count += NlAttrIter::new(payload)
+                        .filter(|&(elem_type, _)| elem_type == IPSET_ATTR_DATA)
+                        .count() as u32;
+                }
+            }
+        })
+        .await?;
+        Ok(count)
+    })
+    .await
+}
+
+/// Dump a set with the LIST command, passing the attributes of each
+/// response message to `handle`. An empty `setname` dumps all sets.
+///
+/// Fails with `ENOBUFS` when the socket overflowed; the dump must then be
+/// made again from the start, see [`dump_retry_async`].
+async fn ipset_list_dump(setname: &str, flags: u32, mut handle: impl FnMut(&[u8])) -> Result<()> {
+    let mut dump = DumpStream::new(ipset_list_request(setname, flags).as_slice())?;
+    while let Some(message) = dump.next_message_async().await? {
+        match parse_nlmsg_error(message) {
+            Some(0) => {}
+            Some(error) => return Err(ipset_list_error(-error, message, setname)),
+            None => handle(
+                message
+                    .get(NlMsgHdr::SIZE + NfGenMsg::SIZE..)
+                    .unwrap_or_default(),
+            ),
+        }
     }
+    Ok(())
+}
 
+/// Build a LIST dump request for `setname`, or for all sets if empty.
+fn ipset_list_request(setname: &str, flags: u32) -> MsgBuffer {
     let mut buf = MsgBuffer::new(BUFF_SZ);
 
     // Build LIST request with DUMP flag
@@ -522,148 +3483,213 @@ pub fn ipset_list(setname: &str) -> Result<Vec<IpAddr>> {
     );
     buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
 
-    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, IPSET_PROTOCOL);
-    buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+    buf.put_attr_u8(IPSET_ATTR_PROTOCOL, protocol_version());
+    if !setname.is_empty() {
+        buf.put_attr_str(IPSET_ATTR_SETNAME, setname);
+    }
+    if flags != 0 {
+        buf.put_attr_u32_be(IPSET_ATTR_FLAGS, flags);
+    }
 
     buf.finalize_nlmsg();
+    buf
+}
 
-    let socket = NetlinkSocket::new()?;
-    socket.send(buf.as_slice())?;
-
-    let mut result = Vec::new();
-    let mut recv_buf = [0u8; 8192]; // Larger buffer for dump responses
-
-    loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
-        if recv_len < NlMsgHdr::SIZE {
-            break;
-        }
-
-        // Process all messages in the buffer
-        let mut offset = 0;
-        while offset + NlMsgHdr::SIZE <= recv_len {
-            let hdr: NlMsgHdr =
-                unsafe { std::ptr::read_unaligned(recv_buf[offset..].as_ptr() as *const NlMsgHdr) };
-
-            if hdr.nlmsg_len as usize > recv_len - offset {
-                break;
-            }
-
-            // Check for NLMSG_DONE
-            if is_nlmsg_done(&recv_buf[offset..]) {
-                return Ok(result);
-            }
-
-            // Check for error
-            if let Some(error) =
-                parse_nlmsg_error(&recv_buf[offset..offset + hdr.nlmsg_len as usize])
-            {
-                if error != 0 {
-                    match -error {
-                        libc::ENOENT => return Err(IpSetError::SetNotFound(setname.to_string())),
-                        _ => return Err(IpSetError::NetlinkError(-error)),
-                    }
-                }
-            } else {
-                // Parse the message for IP addresses
-                let msg_end = offset + hdr.nlmsg_len as usize;
-                let attr_start = offset + NlMsgHdr::SIZE + NfGenMsg::SIZE;
-                parse_ipset_list_attrs(&recv_buf[attr_start..msg_end], &mut result);
-            }
-
-            offset += nla_align(hdr.nlmsg_len as usize);
-        }
+/// The error for an error answer `errno` to a LIST request.
+fn ipset_list_error(errno: i32, message: &[u8], setname: &str) -> RipsetError {
+    match errno {
+        libc::ENOENT => RipsetError::SetNotFound(setname.to_string()),
+        _ => ipset_errno_error(errno, message),
     }
-
-    Ok(result)
 }
 
-/// Parse attributes from ipset LIST response to extract IP addresses.
-fn parse_ipset_list_attrs(data: &[u8], result: &mut Vec<IpAddr>) {
-    let mut offset = 0;
-
-    while offset + NlAttr::SIZE <= data.len() {
-        let attr_len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
-        let attr_type = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]);
-
-        if attr_len < NlAttr::SIZE || offset + attr_len > data.len() {
-            break;
+/// Parse the header attributes of an ipset LIST response.
+fn parse_ipset_header_attrs(data: &[u8]) -> Option<IpSetInfo> {
+    let mut name = None;
+    let mut type_name = None;
+    let mut revision = 0;
+    let mut family = None;
+    let mut header = None;
+
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            IPSET_ATTR_SETNAME => name = nla_get_str(payload),
+            IPSET_ATTR_TYPENAME => type_name = nla_get_str(payload),
+            IPSET_ATTR_REVISION => revision = payload.first().copied().unwrap_or(0),
+            IPSET_ATTR_FAMILY => family = payload.first().copied().and_then(IpSetFamily::from_u8),
+            IPSET_ATTR_DATA => header = Some(payload),
+            _ => {}
         }
+    }
 
-        let attr_type_masked = attr_type & !NLA_F_NESTED;
+    let mut info = IpSetInfo {
+        name: name?,
+        type_name: type_name?,
+        revision,
+        family,
+        timeout: None,
+        hashsize: None,
+        maxelem: None,
+        bucketsize: None,
+        initval: None,
+        range: None,
+        size: None,
+        counters: false,
+        comment: false,
+        skbinfo: false,
+        forceadd: false,
+        references: 0,
+        memsize: 0,
+        entries: 0,
+    };
 
-        // IPSET_ATTR_ADT contains the element list
-        if attr_type_masked == IPSET_ATTR_ADT && (attr_type & NLA_F_NESTED) != 0 {
-            parse_ipset_adt_attrs(&data[offset + NlAttr::SIZE..offset + attr_len], result);
+    let mut ip_from = None;
+    let mut ip_to = None;
+    let mut cidr = None;
+    let mut port_from = None;
+    let mut port_to = None;
+    for (attr_type, payload) in NlAttrIter::new(header?) {
+        match attr_type {
+            IPSET_ATTR_PORT => port_from = nla_get_u16_be(payload),
+            IPSET_ATTR_PORT_TO => port_to = nla_get_u16_be(payload),
+            IPSET_ATTR_IP => ip_from = parse_ipset_ip_attr(payload),
+            IPSET_ATTR_IP_TO => ip_to = parse_ipset_ip_attr(payload),
+            IPSET_ATTR_CIDR => cidr = payload.first().copied(),
+            IPSET_ATTR_TIMEOUT => info.timeout = nla_get_u32_be(payload),
+            IPSET_ATTR_HASHSIZE => info.hashsize = nla_get_u32_be(payload),
+            IPSET_ATTR_MAXELEM => info.maxelem = nla_get_u32_be(payload),
+            IPSET_ATTR_BUCKETSIZE => info.bucketsize = payload.first().copied(),
+            IPSET_ATTR_INITVAL => info.initval = nla_get_u32_be(payload),
+            IPSET_ATTR_SIZE => info.size = nla_get_u32_be(payload),
+            IPSET_ATTR_CADT_FLAGS => {
+                let flags = nla_get_u32_be(payload).unwrap_or(0);
+                info.counters = flags & IPSET_FLAG_WITH_COUNTERS != 0;
+                info.comment = flags & IPSET_FLAG_WITH_COMMENT != 0;
+                info.skbinfo = flags & IPSET_FLAG_WITH_SKBINFO != 0;
+                info.forceadd = flags & IPSET_FLAG_WITH_FORCEADD != 0;
+            }
+            IPSET_ATTR_REFERENCES => info.references = nla_get_u32_be(payload).unwrap_or(0),
+            IPSET_ATTR_MEMSIZE => info.memsize = nla_get_u32_be(payload).unwrap_or(0),
+            IPSET_ATTR_ELEMENTS => info.entries = nla_get_u32_be(payload).unwrap_or(0),
+            _ => {}
         }
-
-        offset += nla_align(attr_len);
     }
-}
 
-/// Parse ADT (element list) attributes.
-fn parse_ipset_adt_attrs(data: &[u8], result: &mut Vec<IpAddr>) {
-    let mut offset = 0;
+    info.range = match (ip_from, ip_to, cidr) {
+        (Some(IpAddr::V4(from)), Some(IpAddr::V4(to)), _) => Some(IpSetRange::FromTo(from, to)),
+        (Some(IpAddr::V4(addr)), None, Some(cidr)) => Some(IpSetRange::Cidr(addr, cidr)),
+        _ => port_from
+            .zip(port_to)
+            .map(|(from, to)| IpSetRange::Ports(from, to)),
+    };
 
-    while offset + NlAttr::SIZE <= data.len() {
-        let attr_len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
-        let attr_type = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]);
+    Some(info)
+}
 
-        if attr_len < NlAttr::SIZE || offset + attr_len > data.len() {
-            break;
-        }
+/// Read the element count from the header of an ipset LIST response.
+fn parse_ipset_header_elements(data: &[u8]) -> Option<u32> {
+    let (_, header) = NlAttrIter::new(data).find(|&(attr_type, _)| attr_type == IPSET_ATTR_DATA)?;
+    NlAttrIter::new(header)
+        .find(|&(attr_type, _)| attr_type == IPSET_ATTR_ELEMENTS)
+        .and_then(|(_, payload)| nla_get_u32_be(payload))
+}
 
-        // Each element is nested under IPSET_ATTR_DATA
-        if (attr_type & NLA_F_NESTED) != 0 {
-            parse_ipset_data_attrs(&data[offset + NlAttr::SIZE..offset + attr_len], result);
+/// Parse attributes from ipset LIST response to extract set entries.
+fn parse_ipset_list_attrs(data: &[u8], result: &mut Vec<Entry>) {
+    // IPSET_ATTR_ADT contains the element list, each element nested under IPSET_ATTR_DATA
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        if attr_type == IPSET_ATTR_ADT {
+            result.extend(
+                NlAttrIter::new(payload)
+                    .filter(|&(elem_type, _)| elem_type == IPSET_ATTR_DATA)
+                    .filter_map(|(_, elem)| parse_ipset_data_attrs(elem)),
+            );
         }
-
-        offset += nla_align(attr_len);
     }
 }
 
-/// Parse DATA attributes to extract IP address.
-fn parse_ipset_data_attrs(data: &[u8], result: &mut Vec<IpAddr>) {
-    let mut offset = 0;
-
-    while offset + NlAttr::SIZE <= data.len() {
-        let attr_len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
-        let attr_type = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]);
-
-        if attr_len < NlAttr::SIZE || offset + attr_len > data.len() {
-            break;
-        }
-
-        let attr_type_masked = attr_type & !NLA_F_NESTED;
-
-        // IPSET_ATTR_IP contains the IP address (nested)
-        if attr_type_masked == IPSET_ATTR_IP
-            && (attr_type & NLA_F_NESTED) != 0
-            && let Some(addr) = parse_ipset_ip_attr(&data[offset + NlAttr::SIZE..offset + attr_len])
-        {
-            result.push(addr);
+/// Parse DATA attributes of a single element.
+fn parse_ipset_data_attrs(data: &[u8]) -> Option<Entry> {
+    let mut addr = None;
+    let mut cidr = None;
+    let mut cadt_flags = 0;
+    let mut port = None;
+    let mut proto = None;
+    let mut timeout = None;
+    let mut mac = None;
+    let mut mark = None;
+    let mut iface = None;
+    let mut name = None;
+    let mut packets = None;
+    let mut bytes = None;
+    let mut comment = None;
+    let mut skbmark = None;
+    let mut skbprio = None;
+    let mut skbqueue = None;
+
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            IPSET_ATTR_IP => addr = parse_ipset_ip_attr(payload),
+            IPSET_ATTR_CIDR => cidr = payload.first().copied(),
+            IPSET_ATTR_CADT_FLAGS => cadt_flags = nla_get_u32_be(payload).unwrap_or(0),
+            IPSET_ATTR_TIMEOUT => timeout = nla_get_u32_be(payload),
+            IPSET_ATTR_PORT => port = nla_get_u16_be(payload),
+            IPSET_ATTR_PROTO => proto = payload.first().copied(),
+            IPSET_ATTR_ETHER => {
+                mac = payload
+                    .get(..6)
+                    .and_then(|octets| <[u8; 6]>::try_from(octets).ok())
+                    .map(MacAddr)
+            }
+            IPSET_ATTR_MARK => mark = nla_get_u32_be(payload),
+            IPSET_ATTR_IFACE => iface = nla_get_str(payload),
+            IPSET_ATTR_NAME => name = nla_get_str(payload),
+            IPSET_ATTR_PACKETS => packets = nla_get_u64_be(payload),
+            IPSET_ATTR_BYTES => bytes = nla_get_u64_be(payload),
+            IPSET_ATTR_COMMENT => comment = nla_get_str(payload),
+            IPSET_ATTR_SKBMARK => {
+                skbmark = nla_get_u64_be(payload).map(|v| ((v >> 32) as u32, v as u32))
+            }
+            IPSET_ATTR_SKBPRIO => skbprio = nla_get_u32_be(payload),
+            IPSET_ATTR_SKBQUEUE => skbqueue = nla_get_u16_be(payload),
+            _ => {}
         }
+    }
 
-        offset += nla_align(attr_len);
+    if let Some(name) = name {
+        return Some(Entry::SetName(name));
+    }
+    if let (None, Some(port)) = (addr, port) {
+        return Some(Entry::Port {
+            port,
+            port_to: None,
+        });
     }
+    if let (None, Some(mac)) = (addr, mac) {
+        return Some(Entry::Mac(mac));
+    }
+
+    let mut entry = IpEntry::new(addr?);
+    entry.cidr = cidr;
+    entry.port = port;
+    entry.proto = proto;
+    entry.nomatch = cadt_flags & IPSET_FLAG_NOMATCH != 0;
+    entry.timeout = timeout;
+    entry.mac = mac;
+    entry.mark = mark;
+    entry.iface = iface;
+    entry.packets = packets;
+    entry.bytes = bytes;
+    entry.comment = comment;
+    entry.skbmark = skbmark;
+    entry.skbprio = skbprio;
+    entry.skbqueue = skbqueue;
+    Some(Entry::Ip(entry))
 }
 
 /// Parse IP attribute to extract the actual IP address.
 fn parse_ipset_ip_attr(data: &[u8]) -> Option<IpAddr> {
-    if data.len() < NlAttr::SIZE {
-        return None;
-    }
-
-    let attr_len = u16::from_ne_bytes([data[0], data[1]]) as usize;
-    let attr_type = u16::from_ne_bytes([data[2], data[3]])
-        & !NLA_F_NESTED
-        & !crate::netlink::NLA_F_NET_BYTEORDER;
-
-    if attr_len < NlAttr::SIZE {
-        return None;
-    }
-
-    let payload = &data[NlAttr::SIZE..attr_len.min(data.len())];
+    let (attr_type, payload) = NlAttrIter::new(data).next()?;
 
     match attr_type {
         IPSET_ATTR_IPADDR_IPV4 if payload.len() >= 4 => {
@@ -681,6 +3707,7 @@ fn parse_ipset_ip_attr(data: &[u8]) -> Option<IpAddr> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::netlink::{NLA_F_NESTED, NLA_F_NET_BYTEORDER};
 
     #[test]
     fn test_ipset_msg_type() {
@@ -689,6 +3716,126 @@ mod tests {
         assert_eq!(ipset_msg_type(IPSET_CMD_TEST), (6 << 8) | 11);
     }
 
+    #[test]
+    fn test_adt_data_round_trip() {
+        let mut entry = IpEntry::new("10.1.2.3".parse().unwrap());
+        entry.port = Some(443);
+        entry.proto = Some(libc::IPPROTO_UDP as u8);
+        entry.mark = Some(0x1122_3344);
+        entry.timeout = Some(0x0001_0203);
+        entry.packets = Some(0x0102_0304_0506_0708);
+        entry.bytes = Some(u64::MAX - 1);
+        entry.skbmark = Some((0xdead_beef, 0xffff_0000));
+        entry.skbprio = Some(0x0001_0002);
+        entry.skbqueue = Some(0x0102);
+        entry.comment = Some("round trip".to_string());
+
+        let mut buf = MsgBuffer::new(256);
+        put_adt_data(&mut buf, &Entry::Ip(entry.clone()), 7);
+        let (attr_type, data) = NlAttrIter::new(buf.as_slice()).next().unwrap();
+        assert_eq!(attr_type, IPSET_ATTR_DATA);
+        assert_eq!(parse_ipset_data_attrs(data), Some(Entry::Ip(entry)));
+
+        // The kernel wants the values in network byte order and flagged so
+        let raw = |wanted: u16| {
+            let mut offset = 0;
+            while offset < data.len() {
+                let attr_len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
+                let attr_type = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]);
+                if attr_type & !(NLA_F_NESTED | NLA_F_NET_BYTEORDER) == wanted {
+                    return (attr_type, &data[offset + 4..offset + attr_len]);
+                }
+                offset += attr_len.next_multiple_of(4);
+            }
+            panic!("attribute {wanted} missing");
+        };
+        assert_eq!(
+            raw(IPSET_ATTR_PORT),
+            (IPSET_ATTR_PORT | NLA_F_NET_BYTEORDER, &[1, 187][..])
+        );
+        assert_eq!(raw(IPSET_ATTR_MARK).1, [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(
+            raw(IPSET_ATTR_SKBMARK).1,
+            [0xde, 0xad, 0xbe, 0xef, 0xff, 0xff, 0, 0]
+        );
+        let (ip_type, ip) = raw(IPSET_ATTR_IP);
+        assert_eq!(ip_type & NLA_F_NESTED, NLA_F_NESTED);
+        assert_eq!(&ip[4..8], [10, 1, 2, 3]);
+        // The line number is the one value in host byte order
+        assert_eq!(
+            raw(IPSET_ATTR_LINENO),
+            (IPSET_ATTR_LINENO, &7u32.to_ne_bytes()[..])
+        );
+    }
+
+    #[test]
+    fn test_range_to_cidrs() {
+        let v4 = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert_eq!(
+            range_to_cidrs(v4("10.0.0.0"), v4("10.0.0.255")).unwrap(),
+            vec![(v4("10.0.0.0"), 24)]
+        );
+        assert_eq!(
+            range_to_cidrs(v4("10.0.0.1"), v4("10.0.0.6")).unwrap(),
+            vec![
+                (v4("10.0.0.1"), 32),
+                (v4("10.0.0.2"), 31),
+                (v4("10.0.0.4"), 31),
+                (v4("10.0.0.6"), 32),
+            ]
+        );
+        assert_eq!(
+            range_to_cidrs(v4("0.0.0.0"), v4("255.255.255.255")).unwrap(),
+            vec![(v4("0.0.0.0"), 0)]
+        );
+        assert_eq!(
+            range_to_cidrs(v4("2001:db8::"), v4("2001:db8::1:ffff")).unwrap(),
+            vec![(v4("2001:db8::"), 111)]
+        );
+        assert!(range_to_cidrs(v4("10.0.0.2"), v4("10.0.0.1")).is_err());
+        assert!(range_to_cidrs(v4("10.0.0.1"), v4("::1")).is_err());
+    }
+
+    #[test]
+    fn test_fallback_revision() {
+        let options = IpSetCreateOptions::default();
+        assert_eq!(IpSetType::HashIp.fallback_revision(&options), 0);
+        assert_eq!(IpSetType::HashNet.fallback_revision(&options), 2);
+
+        let options = IpSetCreateOptions {
+            counters: true,
+            comment: true,
+            ..Default::default()
+        };
+        assert_eq!(IpSetType::HashIp.fallback_revision(&options), 2);
+        assert_eq!(IpSetType::HashNetPort.fallback_revision(&options), 5);
+        assert_eq!(IpSetType::HashIpMark.fallback_revision(&options), 0);
+
+        // skbinfo needs the usual revision, leaving nothing to fall back to
+        let options = IpSetCreateOptions {
+            skbinfo: true,
+            ..Default::default()
+        };
+        for set_type in [
+            IpSetType::HashIp,
+            IpSetType::HashNetIface,
+            IpSetType::ListSet,
+        ] {
+            assert_eq!(set_type.fallback_revision(&options), set_type.revision());
+        }
+    }
+
+    #[test]
+    fn test_ipset_temp_name() {
+        assert_eq!(ipset_temp_name("blocklist"), "blocklist.tmp");
+        let long_name = "a".repeat(IPSET_MAXNAMELEN - 1);
+        assert_eq!(ipset_temp_name(&long_name).len(), IPSET_MAXNAMELEN - 1);
+        // Truncation keeps whole characters
+        let wide = "é".repeat(15);
+        assert_eq!(ipset_temp_name(&wide), format!("{}.tmp", "é".repeat(13)));
+    }
+
     #[test]
     fn test_invalid_setname() {
         let addr: IpAddr = "192.168.1.1".parse().unwrap();
@@ -696,14 +3843,14 @@ mod tests {
         // Empty name
         assert!(matches!(
             ipset_add("", addr),
-            Err(IpSetError::InvalidSetName(_))
+            Err(RipsetError::InvalidSetName(_))
         ));
 
         // Name too long
         let long_name = "a".repeat(IPSET_MAXNAMELEN);
         assert!(matches!(
             ipset_add(&long_name, addr),
-            Err(IpSetError::InvalidSetName(_))
+            Err(RipsetError::InvalidSetName(_))
         ));
     }
 
@@ -3,10 +3,12 @@
 //! This crate provides functions to add, check, and remove IP addresses
 //! from Linux ipset and nftables sets using the netlink protocol.
 //!
-//! On non-Linux platforms, all operations return `Err(IpSetError::UnsupportedPlatform)`.
+//! On non-Linux platforms, all operations return `Err(RipsetError::UnsupportedPlatform)`.
 
 #[cfg(target_os = "linux")]
-mod netlink;
+pub mod netlink;
+#[cfg(target_os = "linux")]
+pub use netlink::{with_deadline, with_netns, with_netns_fd, with_timeout};
 
 #[cfg(target_os = "linux")]
 pub mod ipset;
@@ -15,14 +17,28 @@ pub mod nftset;
 
 #[cfg(target_os = "linux")]
 pub use ipset::{
-    IpSetCreateOptions, IpSetFamily, IpSetType, ipset_add, ipset_create, ipset_del, ipset_destroy,
-    ipset_flush, ipset_list, ipset_test,
+    CounterMatch, IpSetCreateOptions, IpSetFamily, IpSetInfo, IpSetListIter, IpSetProtocol,
+    IpSetRange, IpSetTestOptions, IpSetType, IpSetTypeRevisions, IpsetPipeline, IpsetSession,
+    ipset_add, ipset_add_exist, ipset_add_many, ipset_capabilities, ipset_count, ipset_create,
+    ipset_create_exist, ipset_del, ipset_del_many, ipset_destroy, ipset_destroy_all,
+    ipset_ensure_entry, ipset_ensure_set, ipset_exists, ipset_flush, ipset_flush_all, ipset_info,
+    ipset_list, ipset_list_entries, ipset_list_iter, ipset_list_sets, ipset_protocol,
+    ipset_references, ipset_replace, ipset_reset_counters, ipset_reset_entry_counters,
+    ipset_supported_types, ipset_swap, ipset_test, ipset_test_many, ipset_test_match,
+    ipset_test_with_options, ipset_type_revisions,
 };
 #[cfg(target_os = "linux")]
 pub use nftset::{
-    NftSetCreateOptions, NftSetType, nftset_add, nftset_create_set, nftset_create_table,
-    nftset_del, nftset_delete_set, nftset_delete_table, nftset_list, nftset_list_tables,
-    nftset_test,
+    NftEvent, NftMapConflict, NftMonitor, NftOwnedTable, NftSession, NftSetCreateOptions,
+    NftSetElement, NftSetInfo, NftSetListIter, NftSetPolicy, NftSetReference, NftSetSummary,
+    NftSetType, NftTransaction, nft_ensure_drop_rule, nftset_add, nftset_add_many,
+    nftset_capabilities, nftset_create_set, nftset_create_table, nftset_create_table_owned,
+    nftset_del, nftset_del_many, nftset_delete_set, nftset_delete_table, nftset_ensure_entry,
+    nftset_ensure_set, nftset_ensure_table, nftset_exists, nftset_flush, nftset_flush_table,
+    nftset_info, nftset_list, nftset_list_elements, nftset_list_entries, nftset_list_iter,
+    nftset_list_sets, nftset_list_tables, nftset_list_tables_all, nftset_map_add,
+    nftset_map_add_with, nftset_map_list, nftset_references, nftset_rename, nftset_reset_counters,
+    nftset_reset_entry_counters, nftset_swap, nftset_table_owner, nftset_test, nftset_test_many,
 };
 
 // Stub implementations for non-Linux platforms
@@ -31,12 +47,56 @@ mod stub;
 #[cfg(not(target_os = "linux"))]
 pub use stub::*;
 
+#[cfg(feature = "tokio")]
+pub mod aio;
+#[cfg(feature = "tokio")]
+pub use aio::*;
+
+pub mod pool;
+pub use pool::{PooledSession, SessionPool};
+
+pub mod backend;
+pub use backend::{
+    BackendKind, BackendSupport, DryRunBackend, DualStackSet, IpsetBackend, NftBackend, SetBackend,
+    SetDiff, SetOperation, SyncSummary, copy_set, detect_backend, diff_entries, diff_set,
+    migrate_ipset_to_nft, set_difference, set_intersection, set_union, sync_set,
+};
+
+pub mod cidr;
+pub use cidr::aggregate;
+
+pub mod find;
+pub use find::find_ip;
+
+pub mod save;
+pub use save::{ipset_restore, ipset_save, ipset_save_all};
+
+pub mod snapshot;
+pub use snapshot::{SetRef, SetSnapshot, restore, snapshot};
+
+pub mod stats;
+pub use stats::{SetStats, set_stats};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "python")]
+mod python;
+
+#[cfg(feature = "ipnet")]
+mod net;
+#[cfg(feature = "ipnet")]
+pub use net::{ipset_list_nets, nftset_list_nets};
+
+use std::fmt;
 use std::net::IpAddr;
+use std::ops::Deref;
+use std::str::FromStr;
 use thiserror::Error;
 
 /// Error type for ipset/nftset operations.
 #[derive(Error, Debug)]
-pub enum IpSetError {
+pub enum RipsetError {
     #[error("Invalid set name: {0}")]
     InvalidSetName(String),
 
@@ -44,23 +104,62 @@ pub enum IpSetError {
     InvalidAddressFamily,
 
     #[error("Socket error: {0}")]
-    SocketError(#[from] std::io::Error),
+    SocketError(#[source] std::io::Error),
 
     #[error("Netlink error: {0}")]
     NetlinkError(i32),
 
+    #[error("Netlink error: {code} ({ext_ack})")]
+    KernelError { code: i32, ext_ack: ExtAck },
+
     #[error("Set not found: {0}")]
     SetNotFound(String),
 
-    #[error("Element not found")]
-    ElementNotFound,
+    #[error("Set {0} is in use by {1}")]
+    SetInUse(String, String),
 
-    #[error("Element already exists")]
-    ElementExists,
+    #[error("Set {0} exists with a different {1}")]
+    SetMismatch(String, String),
+
+    #[error("Entry not found")]
+    EntryNotFound,
+
+    #[error("Entry already exists")]
+    EntryExists,
+
+    #[error("Set {0} is full")]
+    SetFull(String),
+
+    #[error("Permission denied (CAP_NET_ADMIN is required)")]
+    PermissionDenied,
+
+    #[error("Type mismatch: {0}")]
+    TypeMismatch(String),
+
+    #[error("Not supported by the kernel: {0}")]
+    KernelUnsupported(String),
+
+    #[error("Invalid IP address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Invalid MAC address: {0}")]
+    InvalidMacAddress(String),
+
+    #[error("Invalid option: {0}")]
+    InvalidOption(String),
 
     #[error("Invalid table name: {0}")]
     InvalidTableName(String),
 
+    #[error("Table {0} is owned by netlink port {1}")]
+    TableOwned(String, u32),
+
+    #[error("Operation timed out")]
+    Timeout,
+
+    #[error("Notifications were lost, the monitored state must be read again")]
+    EventsLost,
+
     #[error("Send/receive error")]
     SendRecvError,
 
@@ -71,26 +170,431 @@ pub enum IpSetError {
     UnsupportedPlatform,
 }
 
-pub type Result<T> = std::result::Result<T, IpSetError>;
+impl From<std::io::Error> for RipsetError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::TimedOut => RipsetError::Timeout,
+            _ => RipsetError::SocketError(error),
+        }
+    }
+}
+
+impl RipsetError {
+    /// The error code the kernel answered with, for the errors that are not
+    /// translated into a more specific variant.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            RipsetError::NetlinkError(code) | RipsetError::KernelError { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// Former name of [`RipsetError`], kept for existing code.
+pub type IpSetError = RipsetError;
+
+pub type Result<T> = std::result::Result<T, RipsetError>;
+
+/// Details the kernel attached to an error through netlink extended ACKs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtAck {
+    /// Human-readable explanation of the error.
+    pub message: Option<String>,
+    /// Type of the attribute the kernel refused, as numbered by the
+    /// subsystem that received the request.
+    pub attribute: Option<u16>,
+    /// Offset of the refused attribute from the start of the request message.
+    pub offset: Option<u32>,
+}
+
+impl fmt::Display for ExtAck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(message) = &self.message {
+            parts.push(message.clone());
+        }
+        match (self.attribute, self.offset) {
+            (Some(attribute), Some(offset)) => {
+                parts.push(format!("attribute {attribute} at offset {offset}"))
+            }
+            (None, Some(offset)) => parts.push(format!("attribute at offset {offset}")),
+            _ => {}
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Ethernet (MAC) address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{a:02x}:{b:02x}:{c:02x}:{d:02x}:{e:02x}:{g:02x}")
+    }
+}
+
+impl FromStr for MacAddr {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut octets = [0u8; 6];
+        let mut parts = s.split(':');
+        for octet in octets.iter_mut() {
+            let part = parts
+                .next()
+                .filter(|p| p.len() == 2)
+                .ok_or_else(|| RipsetError::InvalidMacAddress(s.to_string()))?;
+            *octet = u8::from_str_radix(part, 16)
+                .map_err(|_| RipsetError::InvalidMacAddress(s.to_string()))?;
+        }
+        if parts.next().is_some() {
+            return Err(RipsetError::InvalidMacAddress(s.to_string()));
+        }
+        Ok(MacAddr(octets))
+    }
+}
+
+/// Serialized as the colon-separated hex string of [`Display`](fmt::Display).
+#[cfg(feature = "serde")]
+impl serde::Serialize for MacAddr {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MacAddr {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> Self {
+        MacAddr(octets)
+    }
+}
+
+/// Name of a set, valid for both ipset and nftables.
+///
+/// Checked on construction: at most [`SetName::MAX_LEN`] bytes, as ipset
+/// allows, starting with a letter, `_` or `.` and continuing with letters,
+/// digits and `_`, `-`, `.` or `/`, as nft identifiers do. A name the kernel
+/// would reject with an opaque `EINVAL` is reported when it is built
+/// instead. Set names deref to `&str`, so they can be passed to every
+/// operation.
+///
+/// # Example
+///
+/// ```
+/// use ripset::SetName;
+///
+/// let name = SetName::new("blocklist_v4").unwrap();
+/// assert_eq!(&*name, "blocklist_v4");
+/// assert!(SetName::new("block list").is_err());
+/// assert!("a_name_longer_than_thirty_one_bytes".parse::<SetName>().is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub struct SetName(String);
+
+impl SetName {
+    /// Longest set name ipset accepts, in bytes.
+    pub const MAX_LEN: usize = 31;
+
+    /// Check a set name.
+    pub fn new(name: impl Into<String>) -> Result<Self> {
+        let name = name.into();
+        let invalid = |reason: &str| Err(RipsetError::InvalidSetName(format!("{name:?} {reason}")));
+        let mut chars = name.chars();
+        match chars.next() {
+            None => return invalid("is empty"),
+            Some(c) if !(c.is_ascii_alphabetic() || c == '_' || c == '.') => {
+                return invalid("must start with a letter, '_' or '.'");
+            }
+            Some(_) => {}
+        }
+        if name.len() > Self::MAX_LEN {
+            return invalid("is longer than 31 bytes");
+        }
+        if !chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/')) {
+            return invalid("may only contain letters, digits, '_', '-', '.' and '/'");
+        }
+        Ok(SetName(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for SetName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SetName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for SetName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for SetName {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<String> for SetName {
+    type Error = RipsetError;
+
+    fn try_from(name: String) -> Result<Self> {
+        Self::new(name)
+    }
+}
+
+impl TryFrom<&str> for SetName {
+    type Error = RipsetError;
+
+    fn try_from(name: &str) -> Result<Self> {
+        Self::new(name)
+    }
+}
+
+impl From<SetName> for String {
+    fn from(name: SetName) -> Self {
+        name.0
+    }
+}
+
+/// nftables address family a table belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum NftFamily {
+    /// Dual-stack IPv4/IPv6
+    Inet,
+    Ip,
+    Ip6,
+    Arp,
+    /// Frames crossing a bridge; sets are typically keyed by `ether_addr`
+    Bridge,
+    /// Ingress/egress of a network device. Tables and sets need no device;
+    /// only the base chains hooking them to traffic are bound to one.
+    Netdev,
+}
+
+impl NftFamily {
+    /// Every nftables family, in the order `nft list tables` prints them.
+    pub const ALL: [NftFamily; 6] = [
+        NftFamily::Ip,
+        NftFamily::Ip6,
+        NftFamily::Inet,
+        NftFamily::Arp,
+        NftFamily::Bridge,
+        NftFamily::Netdev,
+    ];
+}
+
+impl fmt::Display for NftFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NftFamily::Inet => "inet",
+            NftFamily::Ip => "ip",
+            NftFamily::Ip6 => "ip6",
+            NftFamily::Arp => "arp",
+            NftFamily::Bridge => "bridge",
+            NftFamily::Netdev => "netdev",
+        })
+    }
+}
+
+impl FromStr for NftFamily {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "inet" => Ok(NftFamily::Inet),
+            "ip" | "ipv4" => Ok(NftFamily::Ip),
+            "ip6" | "ipv6" => Ok(NftFamily::Ip6),
+            "arp" => Ok(NftFamily::Arp),
+            "bridge" => Ok(NftFamily::Bridge),
+            "netdev" => Ok(NftFamily::Netdev),
+            _ => Err(RipsetError::InvalidAddressFamily),
+        }
+    }
+}
+
+/// Verdict stored as the value of an nftables verdict map.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum NftVerdict {
+    Accept,
+    Drop,
+    Continue,
+    Return,
+    /// Jump to a chain, coming back on return
+    Jump(String),
+    /// Continue in a chain without coming back
+    Goto(String),
+}
+
+impl fmt::Display for NftVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NftVerdict::Accept => f.write_str("accept"),
+            NftVerdict::Drop => f.write_str("drop"),
+            NftVerdict::Continue => f.write_str("continue"),
+            NftVerdict::Return => f.write_str("return"),
+            NftVerdict::Jump(chain) => write!(f, "jump {chain}"),
+            NftVerdict::Goto(chain) => write!(f, "goto {chain}"),
+        }
+    }
+}
+
+impl FromStr for NftVerdict {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || RipsetError::InvalidOption(format!("invalid verdict: {s}"));
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            ["accept"] => Ok(NftVerdict::Accept),
+            ["drop"] => Ok(NftVerdict::Drop),
+            ["continue"] => Ok(NftVerdict::Continue),
+            ["return"] => Ok(NftVerdict::Return),
+            ["jump", chain] => Ok(NftVerdict::Jump(chain.to_string())),
+            ["goto", chain] => Ok(NftVerdict::Goto(chain.to_string())),
+            _ => Err(invalid()),
+        }
+    }
+}
 
 /// IP address with optional timeout for set operations.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IpEntry {
     pub addr: IpAddr,
+    /// Prefix length for network entries (hash:net sets)
+    pub cidr: Option<u8>,
+    /// Last address of a range entry, which is added or deleted as the
+    /// CIDR blocks covering `addr..=ip_to`
+    pub ip_to: Option<IpAddr>,
+    /// Port (ip,port and net,port sets)
+    pub port: Option<u16>,
+    /// Last port of a port range, which is added or deleted in one call
+    pub port_to: Option<u16>,
+    /// IP protocol number of the port (defaults to TCP when a port is set)
+    pub proto: Option<u8>,
+    /// Timeout in seconds; when listing, the remaining time before the entry expires
     pub timeout: Option<u32>,
+    /// MAC address bound to the IP (bitmap:ip,mac sets)
+    pub mac: Option<MacAddr>,
+    /// Packet mark (hash:ip,mark sets)
+    pub mark: Option<u32>,
+    /// Interface name (hash:net,iface sets)
+    pub iface: Option<String>,
+    /// Packet counter (sets created with counters)
+    pub packets: Option<u64>,
+    /// Byte counter (sets created with counters)
+    pub bytes: Option<u64>,
+    /// Entry comment (sets created with comment support), at most 255 bytes
+    /// without double quotes, newlines or NUL characters
+    pub comment: Option<String>,
+    /// Packet mark and mask to set on matching packets (sets created with skbinfo)
+    pub skbmark: Option<(u32, u32)>,
+    /// Packet priority (tc class) to set on matching packets (sets created with skbinfo)
+    pub skbprio: Option<u32>,
+    /// Hardware queue to set on matching packets (sets created with skbinfo)
+    pub skbqueue: Option<u16>,
+    /// Exception entry: addresses matching it are treated as not in the set (hash:net sets)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub nomatch: bool,
 }
 
 impl IpEntry {
     pub fn new(addr: IpAddr) -> Self {
         Self {
             addr,
+            cidr: None,
+            ip_to: None,
+            port: None,
+            port_to: None,
+            proto: None,
             timeout: None,
+            mac: None,
+            mark: None,
+            iface: None,
+            packets: None,
+            bytes: None,
+            comment: None,
+            skbmark: None,
+            skbprio: None,
+            skbqueue: None,
+            nomatch: false,
+        }
+    }
+
+    pub fn with_cidr(addr: IpAddr, cidr: u8) -> Self {
+        Self {
+            cidr: Some(cidr),
+            ..Self::new(addr)
+        }
+    }
+
+    pub fn with_port(addr: IpAddr, proto: u8, port: u16) -> Self {
+        Self {
+            port: Some(port),
+            proto: Some(proto),
+            ..Self::new(addr)
+        }
+    }
+
+    pub fn with_range(from: IpAddr, to: IpAddr) -> Self {
+        Self {
+            ip_to: Some(to),
+            ..Self::new(from)
         }
     }
 
     pub fn with_timeout(addr: IpAddr, timeout: u32) -> Self {
         Self {
-            addr,
             timeout: Some(timeout),
+            ..Self::new(addr)
+        }
+    }
+
+    pub fn with_mac(addr: IpAddr, mac: MacAddr) -> Self {
+        Self {
+            mac: Some(mac),
+            ..Self::new(addr)
+        }
+    }
+
+    pub fn with_comment(addr: IpAddr, comment: impl Into<String>) -> Self {
+        Self {
+            comment: Some(comment.into()),
+            ..Self::new(addr)
         }
     }
 }
@@ -100,3 +604,399 @@ impl From<IpAddr> for IpEntry {
         Self::new(addr)
     }
 }
+
+/// Formats the entry as `ipset save` writes it: the element, its fields
+/// separated by commas, followed by its extensions, e.g.
+/// `10.0.0.0/24,tcp:443 timeout 300 comment "web"`.
+impl fmt::Display for IpEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.addr)?;
+        match (self.cidr, self.ip_to) {
+            (_, Some(ip_to)) => write!(f, "-{ip_to}")?,
+            (Some(cidr), None) => write!(f, "/{cidr}")?,
+            (None, None) => {}
+        }
+        if let Some(port) = self.port {
+            let proto = self.proto.unwrap_or(6);
+            match proto_name(proto) {
+                Some(name) => write!(f, ",{name}:{port}")?,
+                None => write!(f, ",{proto}:{port}")?,
+            }
+            if let Some(port_to) = self.port_to {
+                write!(f, "-{port_to}")?;
+            }
+        }
+        if let Some(mac) = self.mac {
+            write!(f, ",{mac}")?;
+        }
+        if let Some(mark) = self.mark {
+            write!(f, ",{mark:#x}")?;
+        }
+        if let Some(iface) = &self.iface {
+            write!(f, ",{iface}")?;
+        }
+
+        if let Some(timeout) = self.timeout {
+            write!(f, " timeout {timeout}")?;
+        }
+        if let Some(packets) = self.packets {
+            write!(f, " packets {packets}")?;
+        }
+        if let Some(bytes) = self.bytes {
+            write!(f, " bytes {bytes}")?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, " comment \"{comment}\"")?;
+        }
+        match self.skbmark {
+            Some((mark, u32::MAX)) => write!(f, " skbmark {mark:#x}")?,
+            Some((mark, mask)) => write!(f, " skbmark {mark:#x}/{mask:#x}")?,
+            None => {}
+        }
+        if let Some(prio) = self.skbprio {
+            write!(f, " skbprio {:x}:{:x}", prio >> 16, prio & 0xffff)?;
+        }
+        if let Some(queue) = self.skbqueue {
+            write!(f, " skbqueue {queue}")?;
+        }
+        if self.nomatch {
+            f.write_str(" nomatch")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the syntax of [`Display`](fmt::Display): `<addr>`, `<addr>/<cidr>`
+/// or a `<from>-<to>` address range, optionally followed by a comma and
+/// `[<proto>:]<port>[-<port>]`, a MAC address, a `0x` mark or an interface
+/// name, then by the extensions of `ipset add` (`timeout`, `packets`,
+/// `bytes`, `comment`, `skbmark`, `skbprio`, `skbqueue` and `nomatch`).
+impl FromStr for IpEntry {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || RipsetError::InvalidAddress(s.to_string());
+        let parse_addr = |addr: &str| addr.trim().parse::<IpAddr>().map_err(|_| invalid());
+
+        let s = s.trim();
+        let (element, extensions) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let (addr, field) = match element.split_once(',') {
+            Some((addr, field)) => (addr, Some(field)),
+            None => (element, None),
+        };
+
+        let mut entry = if let Some((addr, cidr)) = addr.split_once('/') {
+            let addr = parse_addr(addr)?;
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let cidr = cidr
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|&cidr| cidr <= max)
+                .ok_or_else(invalid)?;
+            Self::with_cidr(addr, cidr)
+        } else if let Some((from, to)) = addr.split_once('-') {
+            let (from, to) = (parse_addr(from)?, parse_addr(to)?);
+            if from.is_ipv4() != to.is_ipv4() || from > to {
+                return Err(invalid());
+            }
+            Self::with_range(from, to)
+        } else {
+            Self::new(parse_addr(addr)?)
+        };
+
+        match field {
+            None => {}
+            Some(field) if field.parse::<MacAddr>().is_ok() => {
+                entry.mac = field.parse().ok();
+            }
+            Some(field) if field.starts_with("0x") => {
+                entry.mark = Some(u32::from_str_radix(&field[2..], 16).map_err(|_| invalid())?);
+            }
+            Some(field)
+                if field.contains(':') || field.starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                let (proto, port) = match field.split_once(':') {
+                    Some((proto, port)) => (Some(parse_proto(proto).ok_or_else(invalid)?), port),
+                    None => (None, field),
+                };
+                let parse_port = |port: &str| port.trim().parse::<u16>().map_err(|_| invalid());
+                let (port, port_to) = match port.split_once('-') {
+                    Some((from, to)) => (parse_port(from)?, Some(parse_port(to)?)),
+                    None => (parse_port(port)?, None),
+                };
+                if port_to.is_some_and(|to| to < port) {
+                    return Err(invalid());
+                }
+                entry.port = Some(port);
+                entry.port_to = port_to;
+                entry.proto = proto;
+            }
+            // Anything else is an interface name (net,iface sets)
+            Some(field) if !field.is_empty() && field.len() < 16 => {
+                entry.iface = Some(field.to_string());
+            }
+            Some(_) => return Err(invalid()),
+        }
+
+        parse_extensions(&mut entry, extensions)?;
+        Ok(entry)
+    }
+}
+
+/// Parse the `ipset add` extensions following an entry into its fields.
+fn parse_extensions(entry: &mut IpEntry, mut rest: &str) -> Result<()> {
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(());
+        }
+        let (key, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if key == "nomatch" {
+            entry.nomatch = true;
+            rest = tail;
+            continue;
+        }
+
+        let invalid = || RipsetError::InvalidOption(format!("invalid {key}: {}", tail.trim()));
+        let tail = tail.trim_start();
+        let (value, tail) = match tail.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').ok_or_else(invalid)?,
+            None => tail.split_once(char::is_whitespace).unwrap_or((tail, "")),
+        };
+        let hex = |value: &str| {
+            value
+                .strip_prefix("0x")
+                .and_then(|value| u32::from_str_radix(value, 16).ok())
+                .or_else(|| value.parse().ok())
+                .ok_or_else(invalid)
+        };
+        match key {
+            "timeout" => entry.timeout = Some(value.parse().map_err(|_| invalid())?),
+            "packets" => entry.packets = Some(value.parse().map_err(|_| invalid())?),
+            "bytes" => entry.bytes = Some(value.parse().map_err(|_| invalid())?),
+            "comment" => entry.comment = Some(value.to_string()),
+            "skbmark" => {
+                entry.skbmark = Some(match value.split_once('/') {
+                    Some((mark, mask)) => (hex(mark)?, hex(mask)?),
+                    None => (hex(value)?, u32::MAX),
+                })
+            }
+            "skbprio" => {
+                let (major, minor) = value.split_once(':').ok_or_else(invalid)?;
+                let part = |part: &str| u16::from_str_radix(part, 16).map_err(|_| invalid());
+                entry.skbprio = Some((part(major)? as u32) << 16 | part(minor)? as u32);
+            }
+            "skbqueue" => entry.skbqueue = Some(value.parse().map_err(|_| invalid())?),
+            _ => {
+                return Err(RipsetError::InvalidOption(format!(
+                    "unknown entry option: {key}"
+                )));
+            }
+        }
+        rest = tail;
+    }
+}
+
+/// Parse a protocol name or number as used in ip,port set entries.
+fn parse_proto(proto: &str) -> Option<u8> {
+    match proto.trim().to_lowercase().as_str() {
+        "tcp" => Some(6),
+        "udp" => Some(17),
+        "sctp" => Some(132),
+        "udplite" => Some(136),
+        other => other.parse().ok(),
+    }
+}
+
+/// Name of a protocol in ip,port set entries, as ipset prints it.
+fn proto_name(proto: u8) -> Option<&'static str> {
+    match proto {
+        6 => Some("tcp"),
+        17 => Some("udp"),
+        132 => Some("sctp"),
+        136 => Some("udplite"),
+        _ => None,
+    }
+}
+
+/// Optional features a backend supports in the running kernel, see
+/// [`SetBackend::capabilities`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BackendCapabilities {
+    /// Atomically exchanging the contents of two sets
+    pub swap: bool,
+    /// Entries that expire
+    pub timeouts: bool,
+    /// Per-entry packet and byte counters
+    pub counters: bool,
+    /// CIDR blocks and address ranges as entries
+    pub intervals: bool,
+    /// Per-entry comments
+    pub comments: bool,
+    /// Sets of MAC addresses
+    pub mac_keys: bool,
+}
+
+/// An element of a set.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Entry {
+    /// IP address based element
+    Ip(IpEntry),
+    /// Name of another set (member of a list:set)
+    SetName(String),
+    /// Port, or inclusive port range when `port_to` is set (bitmap:port)
+    Port { port: u16, port_to: Option<u16> },
+    /// Ethernet address (hash:mac, or nftables `ether_addr` sets)
+    Mac(MacAddr),
+    /// Packet mark (nftables `mark` sets)
+    Mark(u32),
+    /// Interface name (nftables `ifname` sets)
+    Iface(String),
+    /// Verdict (values of nftables verdict maps)
+    Verdict(NftVerdict),
+    /// Concatenation of several values, e.g. address and port. For ipset the
+    /// first part must be an address and the rest fill in the matching
+    /// `IpEntry` fields (ip,port, ip,mac, ip,mark and net,iface sets).
+    Concat(Vec<Entry>),
+}
+
+impl From<NftVerdict> for Entry {
+    fn from(verdict: NftVerdict) -> Self {
+        Entry::Verdict(verdict)
+    }
+}
+
+impl From<MacAddr> for Entry {
+    fn from(mac: MacAddr) -> Self {
+        Entry::Mac(mac)
+    }
+}
+
+impl From<IpEntry> for Entry {
+    fn from(entry: IpEntry) -> Self {
+        Entry::Ip(entry)
+    }
+}
+
+impl From<IpAddr> for Entry {
+    fn from(addr: IpAddr) -> Self {
+        Entry::Ip(IpEntry::new(addr))
+    }
+}
+
+impl From<u16> for Entry {
+    fn from(port: u16) -> Self {
+        Entry::Port {
+            port,
+            port_to: None,
+        }
+    }
+}
+
+impl From<&str> for Entry {
+    fn from(name: &str) -> Self {
+        Entry::SetName(name.to_string())
+    }
+}
+
+impl From<String> for Entry {
+    fn from(name: String) -> Self {
+        Entry::SetName(name)
+    }
+}
+
+impl From<SetName> for Entry {
+    fn from(name: SetName) -> Self {
+        Entry::SetName(name.0)
+    }
+}
+
+/// Formats the entry in the syntax of ipset and nft: addresses as
+/// [`IpEntry`] formats them, marks in hexadecimal, interface names quoted
+/// and the parts of a concatenation joined with ` . `.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Ip(ip) => write!(f, "{ip}"),
+            Entry::SetName(name) => f.write_str(name),
+            Entry::Port {
+                port,
+                port_to: Some(port_to),
+            } => write!(f, "{port}-{port_to}"),
+            Entry::Port { port, .. } => write!(f, "{port}"),
+            Entry::Mac(mac) => write!(f, "{mac}"),
+            Entry::Mark(mark) => write!(f, "{mark:#x}"),
+            Entry::Iface(iface) => write!(f, "\"{iface}\""),
+            Entry::Verdict(verdict) => write!(f, "{verdict}"),
+            Entry::Concat(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" . ")?;
+                    }
+                    write!(f, "{part}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses the syntax of [`Display`](fmt::Display). Without the type of the
+/// set, values are told apart by their shape: quoted strings are interface
+/// names, `0x` numbers marks, other numbers and number ranges ports, and
+/// bare words that are neither verdicts nor addresses set names.
+impl FromStr for Entry {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.contains(" . ") {
+            return s
+                .split(" . ")
+                .map(str::parse)
+                .collect::<Result<_>>()
+                .map(Entry::Concat);
+        }
+        if let Some(iface) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            return Ok(Entry::Iface(iface.to_string()));
+        }
+        if let Ok(mac) = s.parse::<MacAddr>() {
+            return Ok(Entry::Mac(mac));
+        }
+        if let Some(mark) = s.strip_prefix("0x") {
+            return u32::from_str_radix(mark, 16)
+                .map(Entry::Mark)
+                .map_err(|_| RipsetError::InvalidOption(format!("invalid mark: {s}")));
+        }
+        let ports = match s.split_once('-') {
+            Some((from, to)) => from
+                .parse::<u16>()
+                .ok()
+                .zip(to.parse::<u16>().ok().map(Some)),
+            None => s.parse::<u16>().ok().map(|port| (port, None)),
+        };
+        if let Some((port, port_to)) = ports {
+            return Ok(Entry::Port { port, port_to });
+        }
+        if let Ok(verdict) = s.parse::<NftVerdict>() {
+            return Ok(Entry::Verdict(verdict));
+        }
+        match s.parse::<IpEntry>() {
+            Ok(ip) => Ok(Entry::Ip(ip)),
+            Err(e)
+                if s.split(['/', '-', ','])
+                    .next()
+                    .is_some_and(|addr| addr.parse::<IpAddr>().is_ok()) =>
+            {
+                Err(e)
+            }
+            Err(_) if !s.is_empty() && !s.contains(char::is_whitespace) => {
+                Ok(Entry::SetName(s.to_string()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
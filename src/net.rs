@@ -0,0 +1,156 @@
+//! Interoperability with the network types of the `ipnet` crate.
+//!
+//! `IpNet`, `Ipv4Net` and `Ipv6Net` convert into entries, so they can be
+//! passed to every add, delete and test operation, and
+//! [`ipset_list_nets`] and [`nftset_list_nets`] return the contents of
+//! network sets as `IpNet`s.
+
+use std::net::IpAddr;
+
+use ipnet::{IpNet, Ipv4Net, Ipv4Subnets, Ipv6Net, Ipv6Subnets};
+
+use crate::{Entry, IpEntry, NftFamily, Result};
+
+/// A network becomes a CIDR entry, or a plain address entry when its prefix
+/// covers the whole address. Host bits of the address are cleared.
+impl From<IpNet> for IpEntry {
+    fn from(net: IpNet) -> Self {
+        if net.prefix_len() == net.max_prefix_len() {
+            IpEntry::new(net.addr())
+        } else {
+            IpEntry::with_cidr(net.network(), net.prefix_len())
+        }
+    }
+}
+
+impl From<Ipv4Net> for IpEntry {
+    fn from(net: Ipv4Net) -> Self {
+        IpNet::V4(net).into()
+    }
+}
+
+impl From<Ipv6Net> for IpEntry {
+    fn from(net: Ipv6Net) -> Self {
+        IpNet::V6(net).into()
+    }
+}
+
+impl From<IpNet> for Entry {
+    fn from(net: IpNet) -> Self {
+        Entry::Ip(net.into())
+    }
+}
+
+impl From<Ipv4Net> for Entry {
+    fn from(net: Ipv4Net) -> Self {
+        Entry::Ip(net.into())
+    }
+}
+
+impl From<Ipv6Net> for Entry {
+    fn from(net: Ipv6Net) -> Self {
+        Entry::Ip(net.into())
+    }
+}
+
+impl IpEntry {
+    /// The network of the entry: its CIDR block, or a host network for a
+    /// plain address. Returns `None` for an address range (`ip_to`).
+    pub fn to_ipnet(&self) -> Option<IpNet> {
+        if self.ip_to.is_some() {
+            return None;
+        }
+        let max = if self.addr.is_ipv4() { 32 } else { 128 };
+        IpNet::new(self.addr, self.cidr.unwrap_or(max)).ok()
+    }
+
+    /// The networks covering the entry, several for an address range that
+    /// is not a single CIDR block.
+    fn to_ipnets(&self) -> Vec<IpNet> {
+        match (self.addr, self.ip_to) {
+            (IpAddr::V4(from), Some(IpAddr::V4(to))) => {
+                Ipv4Subnets::new(from, to, 0).map(IpNet::V4).collect()
+            }
+            (IpAddr::V6(from), Some(IpAddr::V6(to))) => {
+                Ipv6Subnets::new(from, to, 0).map(IpNet::V6).collect()
+            }
+            _ => self.to_ipnet().into_iter().collect(),
+        }
+    }
+}
+
+/// The networks of address entries, other entries being skipped.
+pub(crate) fn entry_nets(entries: Vec<Entry>) -> Vec<IpNet> {
+    entries
+        .iter()
+        .flat_map(|entry| match entry {
+            Entry::Ip(ip) => ip.to_ipnets(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// List the networks in an ipset.
+///
+/// Addresses are returned as host networks, and address ranges as the CIDR
+/// blocks covering them. Other fields of the entries, such as ports, are
+/// ignored, as in [`ipset_list`](crate::ipset_list).
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_list_nets;
+///
+/// for net in ipset_list_nets("blocklist").unwrap() {
+///     println!("{net}");
+/// }
+/// ```
+pub fn ipset_list_nets(setname: &str) -> Result<Vec<IpNet>> {
+    crate::ipset_list_entries(setname).map(entry_nets)
+}
+
+/// List the networks in an nftables set.
+///
+/// Ranges of interval sets are returned as the CIDR blocks covering them.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_list_nets};
+///
+/// for net in nftset_list_nets(NftFamily::Inet, "filter", "blocklist").unwrap() {
+///     println!("{net}");
+/// }
+/// ```
+pub fn nftset_list_nets(family: NftFamily, table: &str, setname: &str) -> Result<Vec<IpNet>> {
+    crate::nftset_list_entries(family, table, setname).map(entry_nets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipnet_entries() {
+        let net: IpNet = "10.1.2.3/16".parse().unwrap();
+        let entry = IpEntry::from(net);
+        assert_eq!(entry, IpEntry::with_cidr("10.1.0.0".parse().unwrap(), 16));
+        assert_eq!(entry.to_ipnet(), Some("10.1.0.0/16".parse().unwrap()));
+
+        let host: Ipv6Net = "2001:db8::1/128".parse().unwrap();
+        assert_eq!(
+            Entry::from(host),
+            Entry::from("2001:db8::1".parse::<IpAddr>().unwrap())
+        );
+
+        let range = IpEntry::with_range("10.0.0.0".parse().unwrap(), "10.0.2.255".parse().unwrap());
+        assert_eq!(range.to_ipnet(), None);
+        assert_eq!(
+            entry_nets(vec![range.into(), Entry::from("other")]),
+            vec![
+                "10.0.0.0/23".parse::<IpNet>().unwrap(),
+                "10.0.2.0/24".parse().unwrap()
+            ]
+        );
+    }
+}
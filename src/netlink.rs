@@ -1,17 +1,79 @@
-//! Netlink protocol utilities for ipset/nftset operations.
-
-#![allow(dead_code)]
-
+//! Low-level netfilter netlink protocol, for requests the rest of the crate
+//! doesn't make yet.
+//!
+//! [`MsgBuffer`] builds messages: a netlink header, the netfilter header and
+//! attributes, nested ones included. [`NetlinkSocket`] sends them to the
+//! kernel, in the network namespace and within the deadline set by
+//! [`with_netns`] and [`with_deadline`]: [`NetlinkSocket::ack`] for commands
+//! answered by an acknowledgement, [`NetlinkSocket::dump`] for requests
+//! answered by a series of messages, and raw sends and receives for the
+//! rest. [`NlAttrIter`] and the `nla_get_*` functions read the answers.
+//!
+//! The message types and attributes are those of the kernel headers
+//! `linux/netfilter/ipset/ip_set.h` and `linux/netfilter/nf_tables.h`.
+//!
+//! # Example
+//!
+//! Renaming an ipset, which has no function of its own:
+//!
+//! ```no_run
+//! use ripset::netlink::{
+//!     MsgBuffer, NFNL_SUBSYS_IPSET, NLM_F_ACK, NLM_F_REQUEST, NetlinkSocket, nfnl_msg_type,
+//! };
+//!
+//! const IPSET_CMD_RENAME: u8 = 5;
+//! const IPSET_ATTR_PROTOCOL: u16 = 1;
+//! const IPSET_ATTR_SETNAME: u16 = 2;
+//! const IPSET_ATTR_SETNAME2: u16 = 3;
+//!
+//! let mut msg = MsgBuffer::new(64);
+//! msg.put_nlmsghdr(
+//!     nfnl_msg_type(NFNL_SUBSYS_IPSET, IPSET_CMD_RENAME),
+//!     NLM_F_REQUEST | NLM_F_ACK,
+//!     1,
+//! );
+//! msg.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
+//! msg.put_attr_u8(IPSET_ATTR_PROTOCOL, 6);
+//! msg.put_attr_str(IPSET_ATTR_SETNAME, "oldname");
+//! msg.put_attr_str(IPSET_ATTR_SETNAME2, "newname");
+//! msg.finalize_nlmsg();
+//!
+//! NetlinkSocket::new()?.ack(msg.as_slice())?;
+//! # Ok::<(), ripset::RipsetError>(())
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::future::{Future, poll_fn};
 use std::io;
 use std::mem;
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::time::Duration;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+#[cfg(feature = "tokio")]
+use std::os::unix::io::{FromRawFd, OwnedFd};
+use std::path::Path;
+use std::pin::pin;
+#[cfg(feature = "tracing")]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "tokio")]
+use std::task::ready;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+use tokio::io::unix::AsyncFd;
+
+use crate::{ExtAck, Result, RipsetError};
+
+#[cfg(feature = "tracing")]
+mod trace;
 
 // Compile-time size assertions to ensure struct layouts match kernel expectations
 // These are architecture-independent and will fail compilation if sizes don't match
 const _: () = assert!(mem::size_of::<NlMsgHdr>() == 16);
 const _: () = assert!(mem::size_of::<NfGenMsg>() == 4);
 const _: () = assert!(mem::size_of::<NlAttr>() == 4);
+const _: () = assert!(mem::size_of::<NlMsgErr>() == 20);
 
 // Netlink constants
 pub const NETLINK_NETFILTER: i32 = 12;
@@ -20,10 +82,23 @@ pub const AF_NETLINK: i32 = libc::AF_NETLINK;
 // Netlink message header flags
 pub const NLM_F_REQUEST: u16 = 0x01;
 pub const NLM_F_ACK: u16 = 0x04;
+pub const NLM_F_DUMP_INTR: u16 = 0x10;
 pub const NLM_F_DUMP: u16 = 0x300;
 pub const NLM_F_EXCL: u16 = 0x200;
 pub const NLM_F_CREATE: u16 = 0x400;
 
+// Error message flags
+pub const NLM_F_CAPPED: u16 = 0x100;
+pub const NLM_F_ACK_TLVS: u16 = 0x200;
+
+// Multicast group membership socket option
+pub const NETLINK_ADD_MEMBERSHIP: i32 = 1;
+
+// Extended ACK socket option and attributes
+pub const NETLINK_EXT_ACK: i32 = 11;
+pub const NLMSGERR_ATTR_MSG: u16 = 1;
+pub const NLMSGERR_ATTR_OFFS: u16 = 2;
+
 // Netlink message types
 pub const NLMSG_ERROR: u16 = 0x02;
 pub const NLMSG_DONE: u16 = 0x03;
@@ -37,24 +112,38 @@ pub const NLA_F_NET_BYTEORDER: u16 = 1 << 14;
 pub const NFNL_SUBSYS_IPSET: u8 = 6;
 pub const NFNL_SUBSYS_NFTABLES: u8 = 10;
 
+// Netfilter netlink multicast groups
+pub const NFNLGRP_NFTABLES: u32 = 7;
+
 // Netfilter batch messages
 pub const NFNL_MSG_BATCH_BEGIN: u16 = NLMSG_MIN_TYPE;
 pub const NFNL_MSG_BATCH_END: u16 = NLMSG_MIN_TYPE + 1;
 
+// Netfilter batch attributes
+pub const NFNLA_BATCH_GENID: u16 = 1;
+
 // Alignment macros
 pub const NLMSG_ALIGNTO: usize = 4;
 pub const NLA_ALIGNTO: usize = 4;
 
+/// Round a message length up to the alignment of netlink messages.
 #[inline]
 pub fn nlmsg_align(len: usize) -> usize {
     (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1)
 }
 
+/// Round an attribute length up to the alignment of netlink attributes.
 #[inline]
 pub fn nla_align(len: usize) -> usize {
     (len + NLA_ALIGNTO - 1) & !(NLA_ALIGNTO - 1)
 }
 
+/// Type of a netfilter message: the command `msg` of the subsystem `subsys`,
+/// such as [`NFNL_SUBSYS_IPSET`].
+pub fn nfnl_msg_type(subsys: u8, msg: u8) -> u16 {
+    ((subsys as u16) << 8) | msg as u16
+}
+
 /// Netlink message header (struct nlmsghdr)
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default)]
@@ -103,14 +192,189 @@ pub struct NlMsgErr {
     pub msg: NlMsgHdr,
 }
 
+thread_local! {
+    /// Deadline of the netlink operations running on this thread.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+
+    /// Network namespace of the netlink sockets opened on this thread.
+    static NETNS: Cell<Option<RawFd>> = const { Cell::new(None) };
+
+    /// Whether the netlink sockets opened on this thread are registered with
+    /// the tokio reactor, while an async operation is polled.
+    #[cfg(feature = "tokio")]
+    static REGISTER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `op` with the netlink sockets it opens in the network namespace
+/// `netns`, such as `/var/run/netns/<name>` or `/proc/<pid>/ns/net`.
+///
+/// Only the sockets are created in the namespace; the calling thread stays
+/// where it is. Entering a namespace requires `CAP_SYS_ADMIN`.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{ipset_list, with_netns};
+///
+/// let ips = with_netns("/var/run/netns/foo", || ipset_list("myset")).unwrap();
+/// ```
+pub fn with_netns<T>(netns: impl AsRef<Path>, op: impl FnOnce() -> Result<T>) -> Result<T> {
+    let netns = File::open(netns)?;
+    with_netns_fd(netns.as_fd(), op)
+}
+
+/// Run `op` with the netlink sockets it opens in the network namespace
+/// referred to by the file descriptor `netns`, like [`with_netns`].
+pub fn with_netns_fd<T>(netns: BorrowedFd<'_>, op: impl FnOnce() -> T) -> T {
+    struct Restore(Option<RawFd>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            NETNS.with(|current| current.set(self.0));
+        }
+    }
+
+    let _restore = Restore(NETNS.with(Cell::get));
+    NETNS.with(|current| current.set(Some(netns.as_raw_fd())));
+    op()
+}
+
+/// [`with_netns_fd`] for async code: the sockets `fut` opens whenever it is
+/// polled are created in the network namespace `netns`.
+pub(crate) async fn with_netns_fd_async<F: Future>(netns: BorrowedFd<'_>, fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    poll_fn(|cx| with_netns_fd(netns, || fut.as_mut().poll(cx))).await
+}
+
+/// Run `op` with a deadline on the netlink operations it performs.
+///
+/// Once `deadline` passes, waiting for an answer from the kernel fails with
+/// [`RipsetError::Timeout`], so a kernel that stops answering cannot block the
+/// caller forever. Deadlines nest: an inner call cannot extend the deadline
+/// of an outer one.
+///
+/// A change that times out may still have been applied by the kernel.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::{Duration, Instant};
+/// use ripset::{NftFamily, nftset_list, with_deadline};
+///
+/// let deadline = Instant::now() + Duration::from_secs(5);
+/// let ips = with_deadline(deadline, || nftset_list(NftFamily::Inet, "filter", "myset")).unwrap();
+/// ```
+pub fn with_deadline<T>(deadline: Instant, op: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Instant>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DEADLINE.with(|current| current.set(self.0));
+        }
+    }
+
+    let previous = DEADLINE.with(Cell::get);
+    let _restore = Restore(previous);
+    let deadline = previous.map_or(deadline, |previous| previous.min(deadline));
+    DEADLINE.with(|current| current.set(Some(deadline)));
+    op()
+}
+
+/// Run `op` with a budget of `timeout` for the netlink operations it
+/// performs, like [`with_deadline`].
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use ripset::{RipsetError, ipset_list, with_timeout};
+///
+/// match with_timeout(Duration::from_secs(5), || ipset_list("myset")) {
+///     Ok(ips) => println!("{} entries", ips.len()),
+///     Err(RipsetError::Timeout) => eprintln!("the kernel did not answer in time"),
+///     Err(e) => eprintln!("{e}"),
+/// }
+/// ```
+pub fn with_timeout<T>(timeout: Duration, op: impl FnOnce() -> T) -> T {
+    with_deadline(Instant::now() + timeout, op)
+}
+
 /// A netlink socket for communicating with the kernel.
 pub struct NetlinkSocket {
     fd: RawFd,
+    /// Whether a receive timeout is set on the socket.
+    timeout_armed: AtomicBool,
+    /// Span of the last request sent, which its answers are logged in.
+    #[cfg(feature = "tracing")]
+    span: Mutex<tracing::Span>,
+    /// Registration with the tokio reactor of a non-blocking socket, which
+    /// async operations wait on instead of blocking.
+    #[cfg(feature = "tokio")]
+    reactor: Option<AsyncFd<RawFd>>,
 }
 
 impl NetlinkSocket {
-    /// Create a new netlink socket for netfilter operations.
+    /// Create a new netlink socket for netfilter operations, in the network
+    /// namespace chosen by [`with_netns`] if any.
     pub fn new() -> io::Result<Self> {
+        #[allow(unused_mut)]
+        let mut socket = match NETNS.with(Cell::get) {
+            Some(netns) => Self::open_in(netns)?,
+            None => Self::open()?,
+        };
+        #[cfg(feature = "tokio")]
+        if REGISTER.with(Cell::get) {
+            socket.register()?;
+        }
+        Ok(socket)
+    }
+
+    /// Make the socket non-blocking and register it with the reactor of the
+    /// current tokio runtime, failing outside of one.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn register(&mut self) -> io::Result<()> {
+        if self.reactor.is_none() {
+            self.set_nonblocking()?;
+            self.reactor = Some(crate::aio::reactor(self.fd)?);
+        }
+        Ok(())
+    }
+
+    /// Undo [`register`](Self::register), making the socket blocking again
+    /// for use outside of async code.
+    pub(crate) fn deregister(&mut self) -> io::Result<()> {
+        #[cfg(feature = "tokio")]
+        if self.reactor.take().is_some() {
+            let flags = unsafe { libc::fcntl(self.fd, libc::F_GETFL) };
+            if flags < 0
+                || unsafe { libc::fcntl(self.fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) } < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Create the socket in the network namespace `netns`. A socket belongs
+    /// to the namespace of the thread creating it, so the thread enters the
+    /// namespace for as long as that takes.
+    fn open_in(netns: RawFd) -> io::Result<Self> {
+        let current = File::open("/proc/thread-self/ns/net")?;
+        if unsafe { libc::setns(netns, libc::CLONE_NEWNET) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = Self::open();
+        if unsafe { libc::setns(current.as_raw_fd(), libc::CLONE_NEWNET) } < 0 {
+            // Everything the thread did next would happen in the wrong namespace
+            panic!(
+                "failed to return to the original network namespace: {}",
+                io::Error::last_os_error()
+            );
+        }
+        socket
+    }
+
+    fn open() -> io::Result<Self> {
         let fd = unsafe {
             libc::socket(
                 AF_NETLINK,
@@ -142,11 +406,83 @@ impl NetlinkSocket {
             return Err(io::Error::last_os_error());
         }
 
-        Ok(Self { fd })
+        // Ask for error messages and refused attributes. Kernels before 4.12
+        // reject the option and answer with bare error codes.
+        let enable: i32 = 1;
+        unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_NETLINK,
+                NETLINK_EXT_ACK,
+                &enable as *const i32 as *const libc::c_void,
+                mem::size_of::<i32>() as u32,
+            )
+        };
+
+        Ok(Self {
+            fd,
+            timeout_armed: AtomicBool::new(false),
+            #[cfg(feature = "tracing")]
+            span: Mutex::new(tracing::Span::none()),
+            #[cfg(feature = "tokio")]
+            reactor: None,
+        })
     }
 
     /// Send a netlink message and receive the response.
     pub fn send_recv(&self, msg: &[u8], recv_buf: &mut [u8]) -> io::Result<usize> {
+        block_on(self.send_recv_async(msg, recv_buf))
+    }
+
+    /// [`NetlinkSocket::send_recv`] for async code.
+    pub(crate) async fn send_recv_async(
+        &self,
+        msg: &[u8],
+        recv_buf: &mut [u8],
+    ) -> io::Result<usize> {
+        self.send_retry(msg)?;
+        self.recv_retry_async(recv_buf).await
+    }
+
+    /// Receive the answer to a request, waiting on the tokio reactor if the
+    /// socket is registered with it, and otherwise trying again a few times
+    /// if the receive is interrupted.
+    async fn recv_retry_async(&self, recv_buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "tokio")]
+        if self.reactor.is_some() {
+            return self.recv_async(recv_buf).await;
+        }
+
+        let mut retries = 3;
+        loop {
+            self.arm_timeout()?;
+            let received = self.recv_raw(recv_buf, 0);
+            let received = match received {
+                Ok(received) => received,
+                Err(err) => {
+                    let err = self.recv_error(err);
+                    if retries > 0
+                        && (err.raw_os_error() == Some(libc::EAGAIN)
+                            || err.raw_os_error() == Some(libc::EWOULDBLOCK)
+                            || err.raw_os_error() == Some(libc::EINTR))
+                    {
+                        retries -= 1;
+                        std::thread::sleep(Duration::from_millis(10));
+                        continue;
+                    }
+                    return Err(err);
+                }
+            };
+
+            self.trace(false, &recv_buf[..received]);
+            return Ok(received);
+        }
+    }
+
+    /// Send a request, trying again a few times if the socket is busy.
+    pub(crate) fn send_retry(&self, msg: &[u8]) -> io::Result<()> {
+        self.start_request(msg);
+
         // Destination address
         let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
         addr.nl_family = AF_NETLINK as u16;
@@ -184,41 +520,201 @@ impl NetlinkSocket {
             if sent as usize != msg.len() {
                 return Err(io::Error::other("incomplete send"));
             }
-            break;
+            self.trace(true, msg);
+            return Ok(());
         }
+    }
 
-        // Receive with retry
-        let mut retries = 3;
+    /// Send a netlink message and receive the response carrying its sequence
+    /// number, skipping answers left over from earlier requests.
+    pub fn request(&self, msg: &[u8], seq: u32, recv_buf: &mut [u8]) -> io::Result<usize> {
+        block_on(self.request_async(msg, seq, recv_buf))
+    }
+
+    /// [`NetlinkSocket::request`] for async code.
+    pub(crate) async fn request_async(
+        &self,
+        msg: &[u8],
+        seq: u32,
+        recv_buf: &mut [u8],
+    ) -> io::Result<usize> {
+        self.send_retry(msg)?;
+        self.answer_async(seq, recv_buf).await
+    }
+
+    /// Receive the answer carrying the sequence number `seq` to a request
+    /// already sent, skipping answers left over from earlier requests.
+    pub(crate) async fn answer_async(&self, seq: u32, recv_buf: &mut [u8]) -> io::Result<usize> {
         loop {
-            let received = unsafe {
-                libc::recv(
-                    self.fd,
-                    recv_buf.as_mut_ptr() as *mut libc::c_void,
-                    recv_buf.len(),
-                    0,
-                )
-            };
+            let received = self.recv_retry_async(recv_buf).await?;
+            if received < NlMsgHdr::SIZE {
+                return Ok(received);
+            }
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+            if hdr.nlmsg_seq == seq {
+                return Ok(received);
+            }
+        }
+    }
 
-            if received < 0 {
-                let err = io::Error::last_os_error();
-                if retries > 0
-                    && (err.raw_os_error() == Some(libc::EAGAIN)
-                        || err.raw_os_error() == Some(libc::EWOULDBLOCK)
-                        || err.raw_os_error() == Some(libc::EINTR))
-                {
-                    retries -= 1;
-                    std::thread::sleep(Duration::from_millis(10));
+    /// Send a request asking for an acknowledgement (`NLM_F_ACK`) and wait
+    /// for it, translating an error answer like the rest of the crate.
+    pub fn ack(&self, msg: &[u8]) -> Result<()> {
+        let seq = header(msg).ok_or(RipsetError::ProtocolError)?.nlmsg_seq;
+        let mut recv_buf = [0u8; 8192];
+        let received = self.request(msg, seq, &mut recv_buf)?;
+        match parse_nlmsg_error(&recv_buf[..received]) {
+            Some(0) => Ok(()),
+            Some(error) => Err(errno_error(-error, &recv_buf[..received])),
+            None => Err(RipsetError::ProtocolError),
+        }
+    }
+
+    /// Send a dump request (`NLM_F_DUMP`) and pass the header and attributes
+    /// of every answer to `handle`, until the kernel is done.
+    ///
+    /// Fails with `EINTR` if the state changed while it was being dumped, and
+    /// with `ENOBUFS` if the socket overflowed; the request can then be made
+    /// again, on a socket from [`NetlinkSocket::new_dump`] for the latter.
+    pub fn dump(&self, msg: &[u8], mut handle: impl FnMut(NlMsgHdr, &[u8])) -> Result<()> {
+        self.send(msg)?;
+        let mut recv_buf = vec![0u8; DUMP_BUF_SZ];
+        loop {
+            let received = self.recv_dump(&mut recv_buf)?;
+            for message in NlMsgIter::new(&recv_buf[..received]) {
+                let Some(hdr) = header(message) else {
                     continue;
+                };
+                if hdr.nlmsg_flags & NLM_F_DUMP_INTR != 0 {
+                    return Err(RipsetError::NetlinkError(libc::EINTR));
                 }
-                return Err(err);
+                match hdr.nlmsg_type {
+                    NLMSG_DONE => return Ok(()),
+                    NLMSG_ERROR => match parse_nlmsg_error(message) {
+                        Some(0) | None => {}
+                        Some(error) => return Err(errno_error(-error, message)),
+                    },
+                    _ => handle(
+                        hdr,
+                        message
+                            .get(NlMsgHdr::SIZE + NfGenMsg::SIZE..)
+                            .unwrap_or_default(),
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Create a socket for a dump, with the receive buffer earlier dumps
+    /// were found to need.
+    pub fn new_dump() -> io::Result<Self> {
+        let socket = Self::new()?;
+        let size = DUMP_RCVBUF.load(Ordering::Relaxed);
+        if size != 0 {
+            // A smaller buffer only makes an overflow more likely
+            let _ = socket.set_recv_buffer(size);
+        }
+        Ok(socket)
+    }
+
+    /// Receive the next message of a dump, growing `recv_buf` to fit it so
+    /// that no part of the dump is truncated.
+    ///
+    /// When the socket overflowed and lost messages (`ENOBUFS`), the receive
+    /// buffer of later dump sockets is doubled and the error returned; the
+    /// dump must then be made again, see [`dump_retry`].
+    pub fn recv_dump(&self, recv_buf: &mut Vec<u8>) -> io::Result<usize> {
+        self.arm_timeout()?;
+        let len = self
+            .recv_raw(recv_buf, libc::MSG_PEEK | libc::MSG_TRUNC)
+            .map_err(|e| self.dump_error(self.recv_error(e)))?;
+        if len > recv_buf.len() {
+            recv_buf.resize(len, 0);
+        }
+        self.recv(recv_buf).map_err(|e| self.dump_error(e))
+    }
+
+    /// [`NetlinkSocket::recv_dump`] for async code, waiting for the next
+    /// message on the tokio reactor if the socket is registered with it.
+    pub(crate) async fn recv_dump_async(&self, recv_buf: &mut Vec<u8>) -> io::Result<usize> {
+        #[cfg(feature = "tokio")]
+        if let Some(reactor) = &self.reactor {
+            let len = readable(reactor, || {
+                self.recv_raw(recv_buf, libc::MSG_PEEK | libc::MSG_TRUNC)
+            })
+            .await
+            .map_err(|e| self.dump_error(e))?;
+            if len > recv_buf.len() {
+                recv_buf.resize(len, 0);
             }
+            // The message is waiting, the receive takes it at once
+            let received = self.recv_raw(recv_buf, 0).map_err(|e| self.dump_error(e))?;
+            self.trace(false, &recv_buf[..received]);
+            return Ok(received);
+        }
+        self.recv_dump(recv_buf)
+    }
 
-            return Ok(received as usize);
+    /// Raise the receive buffer of later dump sockets if `err` tells that
+    /// this one overflowed.
+    fn dump_error(&self, err: io::Error) -> io::Error {
+        if err.raw_os_error() == Some(libc::ENOBUFS) {
+            let current = self.recv_buffer().unwrap_or(DUMP_RCVBUF_MIN);
+            let size = current
+                .saturating_mul(2)
+                .clamp(DUMP_RCVBUF_MIN, DUMP_RCVBUF_MAX);
+            DUMP_RCVBUF.fetch_max(size, Ordering::Relaxed);
         }
+        err
+    }
+
+    /// Make the socket receive buffer `len` bytes large.
+    ///
+    /// Tries `SO_RCVBUFFORCE` first, which can exceed the system limit but
+    /// requires `CAP_NET_ADMIN`.
+    pub fn set_recv_buffer(&self, len: usize) -> io::Result<()> {
+        let size = i32::try_from(len).unwrap_or(i32::MAX);
+        for option in [libc::SO_RCVBUFFORCE, libc::SO_RCVBUF] {
+            let ret = unsafe {
+                libc::setsockopt(
+                    self.fd,
+                    libc::SOL_SOCKET,
+                    option,
+                    &size as *const i32 as *const libc::c_void,
+                    mem::size_of::<i32>() as u32,
+                )
+            };
+            if ret == 0 {
+                return Ok(());
+            }
+        }
+        Err(io::Error::last_os_error())
+    }
+
+    /// Size of the socket receive buffer.
+    pub fn recv_buffer(&self) -> io::Result<usize> {
+        let mut size: i32 = 0;
+        let mut len = mem::size_of::<i32>() as u32;
+        let ret = unsafe {
+            libc::getsockopt(
+                self.fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVBUF,
+                &mut size as *mut i32 as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(size.max(0) as usize)
     }
 
     /// Send a netlink message without waiting for response.
     pub fn send(&self, msg: &[u8]) -> io::Result<()> {
+        self.start_request(msg);
+
         let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
         addr.nl_family = AF_NETLINK as u16;
         addr.nl_pid = 0;
@@ -243,26 +739,216 @@ impl NetlinkSocket {
             return Err(io::Error::other("incomplete send"));
         }
 
+        self.trace(true, msg);
+        Ok(())
+    }
+
+    /// Make the socket send buffer large enough for a message of `len` bytes.
+    ///
+    /// Tries `SO_SNDBUFFORCE` first, which can exceed the system limit but
+    /// requires `CAP_NET_ADMIN`.
+    pub fn set_send_buffer(&self, len: usize) -> io::Result<()> {
+        let size = i32::try_from(len).unwrap_or(i32::MAX);
+        for option in [libc::SO_SNDBUFFORCE, libc::SO_SNDBUF] {
+            let ret = unsafe {
+                libc::setsockopt(
+                    self.fd,
+                    libc::SOL_SOCKET,
+                    option,
+                    &size as *const i32 as *const libc::c_void,
+                    mem::size_of::<i32>() as u32,
+                )
+            };
+            if ret == 0 {
+                return Ok(());
+            }
+        }
+        Err(io::Error::last_os_error())
+    }
+
+    /// Subscribe the socket to the notifications of a netlink multicast group.
+    pub fn add_membership(&self, group: u32) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_NETLINK,
+                NETLINK_ADD_MEMBERSHIP,
+                &group as *const u32 as *const libc::c_void,
+                mem::size_of::<u32>() as u32,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Make receives fail with `WouldBlock` instead of waiting for messages.
+    pub fn set_nonblocking(&self) -> io::Result<()> {
+        let flags = unsafe { libc::fcntl(self.fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(self.fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
         Ok(())
     }
 
+    /// Discard the messages waiting on the socket, such as the remaining
+    /// answers to a batch that failed early.
+    pub fn drain(&self) -> io::Result<()> {
+        let mut recv_buf = [0u8; 8192];
+        loop {
+            let received = unsafe {
+                libc::recv(
+                    self.fd,
+                    recv_buf.as_mut_ptr() as *mut libc::c_void,
+                    recv_buf.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            };
+
+            if received < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::EAGAIN) {
+                    return Ok(());
+                }
+                return Err(err);
+            }
+            self.trace(false, &recv_buf[..received as usize]);
+        }
+    }
+
+    /// Port ID the kernel assigned to the socket when it was bound.
+    pub fn portid(&self) -> io::Result<u32> {
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<libc::sockaddr_nl>() as u32;
+
+        let ret = unsafe {
+            libc::getsockname(
+                self.fd,
+                &mut addr as *mut libc::sockaddr_nl as *mut libc::sockaddr,
+                &mut addr_len,
+            )
+        };
+
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(addr.nl_pid)
+    }
+
     /// Receive a netlink message.
     pub fn recv(&self, recv_buf: &mut [u8]) -> io::Result<usize> {
+        self.arm_timeout()?;
+        let received = self.recv_raw(recv_buf, 0).map_err(|e| self.recv_error(e))?;
+        self.trace(false, &recv_buf[..received]);
+        Ok(received)
+    }
+
+    /// [`NetlinkSocket::recv`] for async code, waiting for a message on the
+    /// tokio reactor if the socket is registered with it.
+    pub(crate) async fn recv_async(&self, recv_buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(feature = "tokio")]
+        if let Some(reactor) = &self.reactor {
+            let received = readable(reactor, || self.recv_raw(recv_buf, 0)).await?;
+            self.trace(false, &recv_buf[..received]);
+            return Ok(received);
+        }
+        self.recv(recv_buf)
+    }
+
+    /// A single `recv` call with `flags`, returning the length of the
+    /// message even if it was truncated.
+    fn recv_raw(&self, recv_buf: &mut [u8], flags: i32) -> io::Result<usize> {
         let received = unsafe {
             libc::recv(
                 self.fd,
                 recv_buf.as_mut_ptr() as *mut libc::c_void,
                 recv_buf.len(),
-                0,
+                flags,
             )
         };
-
         if received < 0 {
             return Err(io::Error::last_os_error());
         }
-
         Ok(received as usize)
     }
+
+    /// Open the span of the request in `msg`, which the messages sent and
+    /// received until the next request are logged in.
+    fn start_request(&self, msg: &[u8]) {
+        #[cfg(feature = "tracing")]
+        {
+            *self.span.lock().unwrap_or_else(|e| e.into_inner()) = trace::request_span(msg);
+        }
+        #[cfg(not(feature = "tracing"))]
+        let _ = msg;
+    }
+
+    /// Log the messages of a buffer sent or received in the span of the
+    /// current request.
+    fn trace(&self, sent: bool, buf: &[u8]) {
+        #[cfg(feature = "tracing")]
+        let span = self.span.lock().unwrap_or_else(|e| e.into_inner());
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        trace_messages(sent, buf);
+    }
+
+    /// Limit the next receive to the time left before the deadline of the
+    /// running operation, failing if it has already passed.
+    fn arm_timeout(&self) -> io::Result<()> {
+        // A zero timeval waits forever
+        let tv = match DEADLINE.with(Cell::get) {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                // Round up so that the timeout cannot be zero
+                timeval(left.max(Duration::from_micros(1)))
+            }
+            None if self.timeout_armed.load(Ordering::Relaxed) => libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            None => return Ok(()),
+        };
+        let ret = unsafe {
+            libc::setsockopt(
+                self.fd,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &tv as *const libc::timeval as *const libc::c_void,
+                mem::size_of::<libc::timeval>() as u32,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.timeout_armed
+            .store(tv.tv_sec != 0 || tv.tv_usec != 0, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// The error of a failed receive. The socket blocks, so it only runs out
+    /// of time when the receive timeout of a deadline expires.
+    fn recv_error(&self, err: io::Error) -> io::Error {
+        if err.raw_os_error() == Some(libc::EAGAIN) && DEADLINE.with(Cell::get).is_some() {
+            return io::ErrorKind::TimedOut.into();
+        }
+        err
+    }
+}
+
+/// The `timeval` of a receive timeout, capped at the largest `time_t`
+/// rather than wrapping around, which takes 68 years where it has 32 bits.
+fn timeval(timeout: Duration) -> libc::timeval {
+    libc::timeval {
+        tv_sec: libc::time_t::try_from(timeout.as_secs()).unwrap_or(libc::time_t::MAX),
+        tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+    }
 }
 
 impl AsRawFd for NetlinkSocket {
@@ -273,30 +959,373 @@ impl AsRawFd for NetlinkSocket {
 
 impl Drop for NetlinkSocket {
     fn drop(&mut self) {
+        // Deregister before closing, the descriptor could be reused at once
+        #[cfg(feature = "tokio")]
+        drop(self.reactor.take());
         unsafe { libc::close(self.fd) };
     }
 }
 
+/// Run the non-blocking receive `op` each time the tokio reactor finds the
+/// socket readable, until it stops failing with `WouldBlock`, or the
+/// deadline of the running operation passes.
+#[cfg(feature = "tokio")]
+async fn readable<T>(
+    reactor: &AsyncFd<RawFd>,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut timer: Option<Timer> = None;
+    poll_fn(|cx| {
+        loop {
+            match reactor.poll_read_ready(cx) {
+                Poll::Ready(Ok(mut guard)) => match guard.try_io(|_| op()) {
+                    Ok(result) => return Poll::Ready(result),
+                    // Readiness was cleared, wait for the next message
+                    Err(_would_block) => continue,
+                },
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => break,
+            }
+        }
+        let Some(deadline) = DEADLINE.with(Cell::get) else {
+            return Poll::Pending;
+        };
+        let timer = match &mut timer {
+            Some(timer) => timer,
+            None => match Timer::new() {
+                Ok(new) => timer.insert(new),
+                Err(e) => return Poll::Ready(Err(e)),
+            },
+        };
+        match ready!(timer.poll_expired(cx, deadline)) {
+            Ok(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    })
+    .await
+}
+
+/// A timerfd waking async operations waiting for the kernel at their
+/// deadline, which doesn't need the time driver of the runtime.
+#[cfg(feature = "tokio")]
+struct Timer {
+    fd: AsyncFd<OwnedFd>,
+    /// Deadline the timer is set to expire at.
+    armed: Option<Instant>,
+}
+
+#[cfg(feature = "tokio")]
+impl Timer {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        Ok(Self {
+            fd: crate::aio::reactor(fd)?,
+            armed: None,
+        })
+    }
+
+    /// Ready once `deadline` has passed.
+    fn poll_expired(&mut self, cx: &mut Context<'_>, deadline: Instant) -> Poll<io::Result<()>> {
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Poll::Ready(Ok(()));
+            }
+            if self.armed != Some(deadline) {
+                let spec = libc::itimerspec {
+                    it_interval: libc::timespec {
+                        tv_sec: 0,
+                        tv_nsec: 0,
+                    },
+                    it_value: libc::timespec {
+                        tv_sec: libc::time_t::try_from(left.as_secs()).unwrap_or(libc::time_t::MAX),
+                        tv_nsec: left.subsec_nanos() as _,
+                    },
+                };
+                let ret = unsafe {
+                    libc::timerfd_settime(self.fd.as_raw_fd(), 0, &spec, std::ptr::null_mut())
+                };
+                if ret < 0 {
+                    return Poll::Ready(Err(io::Error::last_os_error()));
+                }
+                self.armed = Some(deadline);
+            }
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            // Consume the expiration; the time left is checked again
+            let mut expirations = [0u8; 8];
+            let _ = guard.try_io(|fd| {
+                let ret = unsafe {
+                    libc::read(
+                        fd.as_raw_fd(),
+                        expirations.as_mut_ptr() as *mut libc::c_void,
+                        expirations.len(),
+                    )
+                };
+                if ret < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+            self.armed = None;
+        }
+    }
+}
+
+/// Initial size of the buffers dumps are received into.
+pub const DUMP_BUF_SZ: usize = 32768;
+
+/// Receive buffer of dump sockets, raised each time a dump overflows.
+static DUMP_RCVBUF: AtomicUsize = AtomicUsize::new(0);
+const DUMP_RCVBUF_MIN: usize = 1 << 20;
+const DUMP_RCVBUF_MAX: usize = 64 << 20;
+
+/// Number of times a dump is made before an overflow is returned.
+const DUMP_MAX_RETRIES: u32 = 8;
+
+/// Run a dump `op` again from the start while its socket overflows
+/// (`ENOBUFS`). Each overflow doubles the receive buffer of the next dump
+/// socket, so large dumps end up fitting.
+pub fn dump_retry<T>(mut op: impl FnMut() -> Result<T>) -> Result<T> {
+    block_on(dump_retry_async(|| std::future::ready(op())))
+}
+
+/// [`dump_retry`] for async code.
+pub(crate) async fn dump_retry_async<T, F>(mut op: impl FnMut() -> F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let mut attempts = 1;
+    loop {
+        match op().await {
+            Err(RipsetError::SocketError(e))
+                if e.raw_os_error() == Some(libc::ENOBUFS) && attempts < DUMP_MAX_RETRIES =>
+            {
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The answers to a dump request, received one datagram at a time so that
+/// memory use doesn't grow with the size of the dump.
+pub struct DumpStream {
+    socket: NetlinkSocket,
+    recv_buf: Vec<u8>,
+    received: usize,
+    offset: usize,
+    done: bool,
+}
+
+impl DumpStream {
+    /// Send the dump request `msg` on a socket from
+    /// [`NetlinkSocket::new_dump`].
+    pub fn new(msg: &[u8]) -> io::Result<Self> {
+        let socket = NetlinkSocket::new_dump()?;
+        socket.send(msg)?;
+        Ok(Self {
+            socket,
+            recv_buf: vec![0u8; DUMP_BUF_SZ],
+            received: 0,
+            offset: 0,
+            done: false,
+        })
+    }
+
+    /// The next message of the dump, header included, or `None` once the
+    /// kernel is done. Error answers are returned like other messages.
+    ///
+    /// Fails with `ENOBUFS` if the socket overflowed, see
+    /// [`NetlinkSocket::recv_dump`].
+    pub fn next_message(&mut self) -> io::Result<Option<&[u8]>> {
+        block_on(self.next_message_async())
+    }
+
+    /// [`DumpStream::next_message`] for async code.
+    pub(crate) async fn next_message_async(&mut self) -> io::Result<Option<&[u8]>> {
+        loop {
+            if self.done {
+                return Ok(None);
+            }
+            if self.offset + NlMsgHdr::SIZE > self.received {
+                self.offset = 0;
+                self.received = 0;
+                self.received = self.socket.recv_dump_async(&mut self.recv_buf).await?;
+                if self.received < NlMsgHdr::SIZE {
+                    self.done = true;
+                }
+                continue;
+            }
+
+            let start = self.offset;
+            let fits = NlMsgHdr::SIZE..=self.received - start;
+            let Some(hdr) = header(&self.recv_buf[start..self.received])
+                .filter(|hdr| fits.contains(&(hdr.nlmsg_len as usize)))
+            else {
+                // Drop the rest of a malformed datagram
+                self.offset = self.received;
+                continue;
+            };
+            let len = hdr.nlmsg_len as usize;
+            self.offset += nlmsg_align(len);
+
+            if hdr.nlmsg_type == NLMSG_DONE {
+                self.done = true;
+                return Ok(None);
+            }
+            return Ok(Some(&self.recv_buf[start..start + len]));
+        }
+    }
+}
+
+/// Run the future of an operation on blocking sockets to completion.
+///
+/// The sockets it opens meanwhile block, so its receives wait in the
+/// kernel and the future never needs to be woken up. It is polled once,
+/// and fails with `WouldBlock` if it waits anyway, for example on a socket
+/// registered with the tokio reactor.
+pub(crate) fn block_on<T, E: From<io::Error>>(
+    fut: impl Future<Output = std::result::Result<T, E>>,
+) -> std::result::Result<T, E> {
+    #[cfg(feature = "tokio")]
+    struct Restore(bool);
+
+    #[cfg(feature = "tokio")]
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            REGISTER.with(|current| current.set(self.0));
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    let _restore = Restore(REGISTER.with(|current| current.replace(false)));
+    match pin!(fut).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "a blocking operation waited for an async socket",
+        )
+        .into()),
+    }
+}
+
+/// The network namespace and deadline an async operation was created with,
+/// which apply to it on whichever thread polls it.
+#[cfg(feature = "tokio")]
+#[derive(Default)]
+pub(crate) struct IoContext {
+    netns: Option<OwnedFd>,
+    deadline: Option<Instant>,
+}
+
+#[cfg(feature = "tokio")]
+impl IoContext {
+    /// The namespace and deadline chosen on the calling thread by
+    /// [`with_netns`] and [`with_deadline`]. The namespace is duplicated,
+    /// the caller may close it before the operation runs.
+    pub(crate) fn current() -> io::Result<Self> {
+        let netns = NETNS
+            .with(Cell::get)
+            .map(|netns| unsafe { BorrowedFd::borrow_raw(netns) }.try_clone_to_owned())
+            .transpose()?;
+        Ok(Self {
+            netns,
+            deadline: DEADLINE.with(Cell::get),
+        })
+    }
+
+    /// Run `op` in the context, with the sockets it opens registered with
+    /// the tokio reactor.
+    pub(crate) fn enter<T>(&self, op: impl FnOnce() -> T) -> T {
+        struct Restore(Option<RawFd>, Option<Instant>, bool);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                NETNS.with(|current| current.set(self.0));
+                DEADLINE.with(|current| current.set(self.1));
+                REGISTER.with(|current| current.set(self.2));
+            }
+        }
+
+        let netns = self.netns.as_ref().map(AsRawFd::as_raw_fd);
+        let _restore = Restore(
+            NETNS.with(|current| current.replace(netns)),
+            DEADLINE.with(|current| current.replace(self.deadline)),
+            REGISTER.with(|current| current.replace(true)),
+        );
+        op()
+    }
+}
+
+thread_local! {
+    /// Buffer the single requests of this thread are built in.
+    static SCRATCH: RefCell<MsgBuffer> = RefCell::new(MsgBuffer::new(SCRATCH_SZ));
+}
+
+/// Initial size of the scratch buffer, and the most it keeps after a
+/// request.
+const SCRATCH_SZ: usize = 8192;
+
+/// Build and send a request in an empty buffer reused by the thread, so
+/// that requests don't allocate. A nested call gets a buffer of its own.
+pub fn with_msg_buffer<T>(op: impl FnOnce(&mut MsgBuffer) -> T) -> T {
+    SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+        Ok(mut buf) => {
+            buf.clear();
+            let result = op(&mut buf);
+            // A rare large request doesn't keep its memory
+            if buf.data.capacity() > SCRATCH_SZ {
+                *buf = MsgBuffer::new(SCRATCH_SZ);
+            }
+            result
+        }
+        Err(_) => op(&mut MsgBuffer::new(SCRATCH_SZ)),
+    })
+}
+
 /// Buffer for building netlink messages.
 pub struct MsgBuffer {
     data: Vec<u8>,
 }
 
 impl MsgBuffer {
+    /// Create an empty buffer with room for `capacity` bytes.
     pub fn new(capacity: usize) -> Self {
         Self {
             data: Vec::with_capacity(capacity),
         }
     }
 
+    /// Length of the message built so far.
     pub fn len(&self) -> usize {
         self.data.len()
     }
 
+    /// Whether nothing has been added yet.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Empty the buffer, keeping its memory for the next message.
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// The message built so far.
     pub fn as_slice(&self) -> &[u8] {
         &self.data
     }
 
+    /// The message built so far, to patch in place.
     pub fn as_mut_slice(&mut self) -> &mut [u8] {
         &mut self.data
     }
@@ -407,6 +1436,16 @@ impl MsgBuffer {
         self.align();
     }
 
+    /// Add a netlink attribute with u16 value in network byte order.
+    /// Sets the NLA_F_NET_BYTEORDER flag on the attribute type.
+    pub fn put_attr_u16_be(&mut self, attr_type: u16, val: u16) {
+        let len = NlAttr::SIZE + 2;
+        self.put_u16(len as u16);
+        self.put_u16(attr_type | NLA_F_NET_BYTEORDER);
+        self.put_u16_be(val);
+        self.align();
+    }
+
     /// Add a netlink attribute with u32 value in network byte order.
     /// Sets the NLA_F_NET_BYTEORDER flag on the attribute type.
     pub fn put_attr_u32_be(&mut self, attr_type: u16, val: u32) {
@@ -494,6 +1533,15 @@ impl MsgBuffer {
     }
 }
 
+/// Log the messages of a buffer sent (`sent` true) or received, when the
+/// `tracing` feature is enabled.
+fn trace_messages(sent: bool, buf: &[u8]) {
+    #[cfg(feature = "tracing")]
+    trace::trace_messages(sent, buf);
+    #[cfg(not(feature = "tracing"))]
+    let _ = (sent, buf);
+}
+
 /// Parse a netlink error response.
 pub fn parse_nlmsg_error(buf: &[u8]) -> Option<i32> {
     if buf.len() < NlMsgHdr::SIZE {
@@ -516,6 +1564,81 @@ pub fn parse_nlmsg_error(buf: &[u8]) -> Option<i32> {
     Some(error)
 }
 
+/// Parse the extended ACK attributes of a netlink error response: the
+/// message explaining the error and the attribute of the request it refused.
+pub fn parse_nlmsg_ext_ack(buf: &[u8]) -> Option<ExtAck> {
+    if buf.len() < NlMsgHdr::SIZE {
+        return None;
+    }
+
+    let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const NlMsgHdr) };
+
+    if hdr.nlmsg_type != NLMSG_ERROR || hdr.nlmsg_flags & NLM_F_ACK_TLVS == 0 {
+        return None;
+    }
+
+    // The error code is followed by the request, echoed in full unless capped
+    let request_at = NlMsgHdr::SIZE + 4;
+    let request_hdr = buf.get(request_at..request_at + NlMsgHdr::SIZE)?;
+    let request_hdr: NlMsgHdr =
+        unsafe { std::ptr::read_unaligned(request_hdr.as_ptr() as *const NlMsgHdr) };
+    let capped = hdr.nlmsg_flags & NLM_F_CAPPED != 0;
+    let request_len = if capped {
+        NlMsgHdr::SIZE
+    } else {
+        request_hdr.nlmsg_len as usize
+    };
+
+    let msg_end = (hdr.nlmsg_len as usize).min(buf.len());
+    let attrs = buf.get(request_at + nlmsg_align(request_len)..msg_end)?;
+    let mut ext_ack = ExtAck::default();
+    for (attr_type, payload) in NlAttrIter::new(attrs) {
+        match attr_type {
+            NLMSGERR_ATTR_MSG => ext_ack.message = nla_get_str(payload),
+            NLMSGERR_ATTR_OFFS => {
+                ext_ack.offset = payload
+                    .get(..4)
+                    .and_then(|bytes| Some(u32::from_ne_bytes(bytes.try_into().ok()?)));
+            }
+            _ => {}
+        }
+    }
+
+    // Look the refused attribute up in the echoed request
+    if let Some(offset) = ext_ack.offset
+        && !capped
+    {
+        let at = request_at + offset as usize;
+        ext_ack.attribute = buf
+            .get(at + 2..(at + 4).min(request_at + request_len))
+            .and_then(|bytes| Some(u16::from_ne_bytes(bytes.try_into().ok()?)))
+            .map(|attr_type| attr_type & !(NLA_F_NESTED | NLA_F_NET_BYTEORDER));
+    }
+
+    (ext_ack.message.is_some() || ext_ack.offset.is_some()).then_some(ext_ack)
+}
+
+/// Translate an error code the kernel answered a request with, for the codes
+/// that mean the same to every netfilter subsystem. `msg` is the error
+/// response, whose extended ACK attributes are kept in the error.
+pub fn errno_error(error: i32, msg: &[u8]) -> RipsetError {
+    let ext_ack = parse_nlmsg_ext_ack(msg);
+    match error {
+        libc::EPERM | libc::EACCES => RipsetError::PermissionDenied,
+        libc::EOPNOTSUPP | libc::EAFNOSUPPORT | libc::EPROTONOSUPPORT => {
+            let reason = io::Error::from_raw_os_error(error).to_string();
+            RipsetError::KernelUnsupported(match ext_ack {
+                Some(ext_ack) => format!("{reason} ({ext_ack})"),
+                None => reason,
+            })
+        }
+        code => match ext_ack {
+            Some(ext_ack) => RipsetError::KernelError { code, ext_ack },
+            None => RipsetError::NetlinkError(code),
+        },
+    }
+}
+
 /// Check if a netlink response is NLMSG_DONE.
 pub fn is_nlmsg_done(buf: &[u8]) -> bool {
     if buf.len() < NlMsgHdr::SIZE {
@@ -535,3 +1658,227 @@ pub fn get_nlmsg_type(buf: &[u8]) -> Option<u16> {
     let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const NlMsgHdr) };
     Some(hdr.nlmsg_type)
 }
+
+/// Iterator over the netlink attributes contained in a buffer.
+///
+/// Yields `(attr_type, payload)` pairs where the type has the
+/// `NLA_F_NESTED` and `NLA_F_NET_BYTEORDER` flags masked off.
+pub struct NlAttrIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> NlAttrIter<'a> {
+    /// Iterate over the attributes in `data`, such as the payload of a
+    /// nested attribute.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for NlAttrIter<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.data;
+        let offset = self.offset;
+
+        if offset + NlAttr::SIZE > data.len() {
+            return None;
+        }
+
+        let attr_len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
+        let attr_type = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]);
+
+        if attr_len < NlAttr::SIZE || offset + attr_len > data.len() {
+            return None;
+        }
+
+        self.offset += nla_align(attr_len);
+
+        Some((
+            attr_type & !(NLA_F_NESTED | NLA_F_NET_BYTEORDER),
+            &data[offset + NlAttr::SIZE..offset + attr_len],
+        ))
+    }
+}
+
+/// Iterator over the netlink messages received in a buffer.
+///
+/// Yields each message whole, header included, and stops at a truncated one.
+pub struct NlMsgIter<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> NlMsgIter<'a> {
+    /// Iterate over the messages in `data`, as filled by a receive.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for NlMsgIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = header(self.data)?.nlmsg_len as usize;
+        if len < NlMsgHdr::SIZE || len > self.data.len() {
+            return None;
+        }
+        let message = &self.data[..len];
+        self.data = self.data.get(nlmsg_align(len)..).unwrap_or_default();
+        Some(message)
+    }
+}
+
+/// Read the header of the netlink message at the start of `buf`.
+pub fn header(buf: &[u8]) -> Option<NlMsgHdr> {
+    if buf.len() < NlMsgHdr::SIZE {
+        return None;
+    }
+    Some(unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const NlMsgHdr) })
+}
+
+/// Read a big-endian u16 attribute payload.
+pub fn nla_get_u16_be(payload: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes(payload.get(..2)?.try_into().ok()?))
+}
+
+/// Read a big-endian u32 attribute payload.
+pub fn nla_get_u32_be(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(payload.get(..4)?.try_into().ok()?))
+}
+
+/// Read a big-endian u64 attribute payload.
+pub fn nla_get_u64_be(payload: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(payload.get(..8)?.try_into().ok()?))
+}
+
+/// Read a null-terminated string attribute payload.
+pub fn nla_get_str(payload: &[u8]) -> Option<String> {
+    let end = payload
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(payload.len());
+    String::from_utf8(payload[..end].to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recv_deadline() {
+        let socket = NetlinkSocket::new().unwrap();
+        let mut recv_buf = [0u8; 64];
+
+        // Nothing is sent, so the receive waits until the deadline
+        let start = Instant::now();
+        let err =
+            with_timeout(Duration::from_millis(50), || socket.recv(&mut recv_buf)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(matches!(RipsetError::from(err), RipsetError::Timeout));
+    }
+
+    #[test]
+    fn test_block_on_pending() {
+        let err = block_on(std::future::pending::<io::Result<()>>()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn test_dump_retry() {
+        let overflow = || RipsetError::from(io::Error::from_raw_os_error(libc::ENOBUFS));
+
+        let mut attempts = 0;
+        let result = dump_retry(|| {
+            attempts += 1;
+            if attempts < 3 {
+                Err(overflow())
+            } else {
+                Ok(attempts)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        // Other errors and overflows past the limit are returned
+        let mut attempts = 0;
+        let result: Result<()> = dump_retry(|| {
+            attempts += 1;
+            Err(RipsetError::NetlinkError(libc::EINTR))
+        });
+        assert!(matches!(
+            result,
+            Err(RipsetError::NetlinkError(libc::EINTR))
+        ));
+        assert_eq!(attempts, 1);
+        let result: Result<()> = dump_retry(|| Err(overflow()));
+        assert!(matches!(result, Err(RipsetError::SocketError(_))));
+    }
+
+    #[test]
+    fn test_timeval() {
+        let tv = timeval(Duration::from_millis(1500));
+        assert_eq!((tv.tv_sec, tv.tv_usec), (1, 500_000));
+
+        // Beyond 2038 with a 32-bit time_t
+        let tv = timeval(Duration::from_secs(1 << 40));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(tv.tv_sec, 1 << 40);
+        assert!(tv.tv_sec > 0);
+        let tv = timeval(Duration::MAX);
+        assert_eq!(tv.tv_sec, libc::time_t::MAX);
+    }
+
+    #[test]
+    fn test_byte_order() {
+        let mut buf = MsgBuffer::new(64);
+        buf.put_nlmsghdr(0x0a0b, NLM_F_REQUEST | NLM_F_ACK, 0x01020304);
+        buf.put_nfgenmsg(libc::AF_INET as u8, 0, 0x0a0b);
+        buf.put_attr_u16_be(1, 443);
+        buf.put_attr_u32_be(2, 0x11223344);
+        buf.put_attr_u64_be(3, 0x1122334455667788);
+        buf.put_attr_u32(4, 0x11223344);
+        let nested = buf.start_nested(5);
+        buf.put_attr_u32_nft(6, 0x11223344);
+        buf.end_nested(nested);
+        buf.finalize_nlmsg();
+        let msg = buf.as_slice();
+
+        // Headers and lengths are in host byte order
+        #[cfg(target_endian = "big")]
+        assert_eq!(&msg[..12], [0, 0, 0, 68, 0x0a, 0x0b, 0, 0x05, 1, 2, 3, 4]);
+        #[cfg(target_endian = "little")]
+        assert_eq!(&msg[..12], [68, 0, 0, 0, 0x0b, 0x0a, 0x05, 0, 4, 3, 2, 1]);
+        // Except the resource ID of the netfilter header
+        assert_eq!(&msg[16..20], [libc::AF_INET as u8, 0, 0x0a, 0x0b]);
+
+        // Values in network byte order are the same everywhere
+        assert_eq!(&msg[24..26], [1, 187]);
+        assert_eq!(&msg[32..36], [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(
+            &msg[40..48],
+            [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88]
+        );
+        assert_eq!(&msg[52..56], 0x11223344u32.to_ne_bytes());
+        assert_eq!(&msg[64..68], [0x11, 0x22, 0x33, 0x44]);
+
+        let hdr = header(msg).unwrap();
+        assert_eq!(hdr.nlmsg_len as usize, msg.len());
+        assert_eq!(hdr.nlmsg_type, 0x0a0b);
+        assert_eq!(hdr.nlmsg_seq, 0x01020304);
+        let attrs: Vec<_> = NlAttrIter::new(&msg[NlMsgHdr::SIZE + NfGenMsg::SIZE..]).collect();
+        assert_eq!(attrs.len(), 5);
+        assert_eq!(nla_get_u16_be(attrs[0].1), Some(443));
+        assert_eq!(nla_get_u32_be(attrs[1].1), Some(0x11223344));
+        assert_eq!(nla_get_u64_be(attrs[2].1), Some(0x1122334455667788));
+        assert_eq!(attrs[3].1, 0x11223344u32.to_ne_bytes());
+        // The flags are masked off the types
+        assert_eq!(attrs[0].0, 1);
+        assert_eq!(attrs[4].0, 5);
+        let (nested_type, nested) = NlAttrIter::new(attrs[4].1).next().unwrap();
+        assert_eq!(nested_type, 6);
+        assert_eq!(nla_get_u32_be(nested), Some(0x11223344));
+    }
+}
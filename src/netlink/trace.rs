@@ -0,0 +1,241 @@
+//! Logging of the netlink traffic through `tracing`.
+//!
+//! Every message sent or received is logged at debug level under the
+//! `ripset::netlink` target with its kind, sequence number and length,
+//! followed by a hex dump of the whole message. Kernel errors name the
+//! request they answer, and each request and its answers share a span. A
+//! batch or a group of requests sent together gets a single span, named
+//! after its first message past the batch header.
+
+use std::fmt::Write;
+
+use super::{
+    NFNL_MSG_BATCH_BEGIN, NFNL_MSG_BATCH_END, NFNL_SUBSYS_IPSET, NFNL_SUBSYS_NFTABLES, NLMSG_DONE,
+    NLMSG_ERROR, NlMsgHdr, header, nlmsg_align,
+};
+
+const IPSET_COMMANDS: [&str; 16] = [
+    "NONE",
+    "PROTOCOL",
+    "CREATE",
+    "DESTROY",
+    "FLUSH",
+    "RENAME",
+    "SWAP",
+    "LIST",
+    "SAVE",
+    "ADD",
+    "DEL",
+    "TEST",
+    "HEADER",
+    "TYPE",
+    "GET_BYNAME",
+    "GET_BYINDEX",
+];
+
+const NFT_MESSAGES: [&str; 17] = [
+    "NEWTABLE",
+    "GETTABLE",
+    "DELTABLE",
+    "NEWCHAIN",
+    "GETCHAIN",
+    "DELCHAIN",
+    "NEWRULE",
+    "GETRULE",
+    "DELRULE",
+    "NEWSET",
+    "GETSET",
+    "DELSET",
+    "NEWSETELEM",
+    "GETSETELEM",
+    "DELSETELEM",
+    "NEWGEN",
+    "GETGEN",
+];
+
+/// Span covering the messages sent in `buf` and the reception of their
+/// answers.
+pub(super) fn request_span(buf: &[u8]) -> tracing::Span {
+    match first_request(buf) {
+        Some((hdr, messages)) => tracing::debug_span!(
+            target: "ripset::netlink",
+            "netlink_request",
+            kind = %message_kind(hdr.nlmsg_type),
+            seq = hdr.nlmsg_seq,
+            messages,
+        ),
+        None => tracing::Span::none(),
+    }
+}
+
+/// The header of the first message of `buf` past a batch header, and the
+/// number of messages in `buf`.
+fn first_request(buf: &[u8]) -> Option<(NlMsgHdr, usize)> {
+    let mut first = None;
+    let mut messages = 0;
+    let mut offset = 0;
+    while let Some(hdr) = header(&buf[offset..]) {
+        let len = hdr.nlmsg_len as usize;
+        if len < NlMsgHdr::SIZE || offset + len > buf.len() {
+            break;
+        }
+        if first.is_none() && hdr.nlmsg_type != NFNL_MSG_BATCH_BEGIN {
+            first = Some(hdr);
+        }
+        messages += 1;
+        offset += nlmsg_align(len);
+    }
+    Some((first.or_else(|| header(buf))?, messages))
+}
+
+/// Log each message of a buffer sent (`sent` true) or received.
+pub(super) fn trace_messages(sent: bool, buf: &[u8]) {
+    let direction = if sent { "send" } else { "recv" };
+    let mut offset = 0;
+    while let Some(hdr) = header(&buf[offset..]) {
+        let len = hdr.nlmsg_len as usize;
+        if len < NlMsgHdr::SIZE || offset + len > buf.len() {
+            break;
+        }
+        let msg = &buf[offset..offset + len];
+
+        match error(msg) {
+            Some((0, request)) => tracing::debug!(
+                target: "ripset::netlink",
+                direction,
+                request = %message_kind(request.nlmsg_type),
+                seq = request.nlmsg_seq,
+                "ack"
+            ),
+            Some((errno, request)) => tracing::debug!(
+                target: "ripset::netlink",
+                direction,
+                request = %message_kind(request.nlmsg_type),
+                seq = request.nlmsg_seq,
+                errno,
+                error = %std::io::Error::from_raw_os_error(errno),
+                "kernel error"
+            ),
+            None => tracing::debug!(
+                target: "ripset::netlink",
+                direction,
+                kind = %message_kind(hdr.nlmsg_type),
+                seq = hdr.nlmsg_seq,
+                flags = format_args!("{:#x}", hdr.nlmsg_flags),
+                len,
+                "message"
+            ),
+        }
+        tracing::debug!(target: "ripset::netlink", payload = %hex(msg));
+
+        offset += nlmsg_align(len);
+    }
+}
+
+/// The errno of an error message (0 for an acknowledgement) and the header
+/// of the request it answers.
+fn error(msg: &[u8]) -> Option<(i32, NlMsgHdr)> {
+    if header(msg)?.nlmsg_type != NLMSG_ERROR {
+        return None;
+    }
+    let error = i32::from_ne_bytes(
+        msg.get(NlMsgHdr::SIZE..NlMsgHdr::SIZE + 4)?
+            .try_into()
+            .ok()?,
+    );
+    Some((-error, header(&msg[NlMsgHdr::SIZE + 4..])?))
+}
+
+/// Name of a message type, such as `ipset ADD` or `nft NEWSETELEM`.
+fn message_kind(msg_type: u16) -> String {
+    let (subsys, cmd) = ((msg_type >> 8) as u8, (msg_type & 0xff) as usize);
+    let name = match msg_type {
+        NLMSG_ERROR => Some("ERROR"),
+        NLMSG_DONE => Some("DONE"),
+        NFNL_MSG_BATCH_BEGIN => Some("BATCH_BEGIN"),
+        NFNL_MSG_BATCH_END => Some("BATCH_END"),
+        _ => None,
+    };
+    match (name, subsys) {
+        (Some(name), _) => name.to_string(),
+        (None, NFNL_SUBSYS_IPSET) => match IPSET_COMMANDS.get(cmd) {
+            Some(name) => format!("ipset {name}"),
+            None => format!("ipset {cmd}"),
+        },
+        (None, NFNL_SUBSYS_NFTABLES) => match NFT_MESSAGES.get(cmd) {
+            Some(name) => format!("nft {name}"),
+            None => format!("nft {cmd}"),
+        },
+        (None, _) => format!("{msg_type:#x}"),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_kind() {
+        assert_eq!(message_kind(0x0609), "ipset ADD");
+        assert_eq!(message_kind(0x0a0c), "nft NEWSETELEM");
+        assert_eq!(message_kind(NLMSG_ERROR), "ERROR");
+        assert_eq!(message_kind(NFNL_MSG_BATCH_BEGIN), "BATCH_BEGIN");
+        assert_eq!(message_kind(0x0a40), "nft 64");
+    }
+
+    #[test]
+    fn test_error_request() {
+        // EINVAL answering ipset ADD with sequence number 7
+        let mut msg = Vec::new();
+        msg.extend_from_slice(&36u32.to_ne_bytes());
+        msg.extend_from_slice(&NLMSG_ERROR.to_ne_bytes());
+        msg.extend_from_slice(&[0; 10]);
+        msg.extend_from_slice(&(-22i32).to_ne_bytes());
+        msg.extend_from_slice(&20u32.to_ne_bytes());
+        msg.extend_from_slice(&0x0609u16.to_ne_bytes());
+        msg.extend_from_slice(&0u16.to_ne_bytes());
+        msg.extend_from_slice(&7u32.to_ne_bytes());
+        msg.extend_from_slice(&0u32.to_ne_bytes());
+
+        let (errno, request) = error(&msg).unwrap();
+        assert_eq!(errno, 22);
+        assert_eq!(message_kind(request.nlmsg_type), "ipset ADD");
+        assert_eq!(request.nlmsg_seq, 7);
+        assert_eq!(hex(&[0x24, 0x00, 0xff]), "2400ff");
+    }
+
+    #[test]
+    fn test_first_request() {
+        let message = |msg_type: u16, seq: u32| {
+            let mut msg = Vec::new();
+            msg.extend_from_slice(&20u32.to_ne_bytes());
+            msg.extend_from_slice(&msg_type.to_ne_bytes());
+            msg.extend_from_slice(&0u16.to_ne_bytes());
+            msg.extend_from_slice(&seq.to_ne_bytes());
+            msg.extend_from_slice(&[0; 8]);
+            msg
+        };
+
+        // A batch is named after its first change
+        let mut batch = message(NFNL_MSG_BATCH_BEGIN, 0);
+        batch.extend(message(0x0a0c, 1));
+        batch.extend(message(0x0a0c, 2));
+        batch.extend(message(NFNL_MSG_BATCH_END, 3));
+        let (hdr, messages) = first_request(&batch).unwrap();
+        assert_eq!(message_kind(hdr.nlmsg_type), "nft NEWSETELEM");
+        assert_eq!(hdr.nlmsg_seq, 1);
+        assert_eq!(messages, 4);
+
+        let (hdr, messages) = first_request(&message(0x060b, 5)).unwrap();
+        assert_eq!((hdr.nlmsg_seq, messages), (5, 1));
+        assert!(first_request(&[0; 8]).is_none());
+    }
+}
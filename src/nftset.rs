@@ -3,28 +3,64 @@
 //! This module provides functions to add, test, and delete IP addresses
 //! from nftables sets using the netlink protocol.
 
+use std::cell::Cell;
+use std::fmt;
+use std::fs::File;
+use std::future::Future;
+use std::marker::PhantomData;
 use std::net::IpAddr;
+use std::os::unix::io::{AsFd, BorrowedFd, OwnedFd};
+use std::path::Path;
+use std::str::FromStr;
 
 use crate::netlink::{
-    MsgBuffer, NFNL_MSG_BATCH_BEGIN, NFNL_MSG_BATCH_END, NFNL_SUBSYS_NFTABLES, NLA_F_NESTED,
-    NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP, NLM_F_REQUEST, NetlinkSocket, NfGenMsg, NlAttr, NlMsgHdr,
-    get_nlmsg_type, is_nlmsg_done, nla_align, parse_nlmsg_error,
+    DUMP_BUF_SZ, DumpStream, MsgBuffer, NFNL_MSG_BATCH_BEGIN, NFNL_MSG_BATCH_END,
+    NFNL_SUBSYS_NFTABLES, NFNLA_BATCH_GENID, NLA_F_NESTED, NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP,
+    NLM_F_DUMP_INTR, NLM_F_EXCL, NLM_F_REQUEST, NetlinkSocket, NfGenMsg, NlAttr, NlAttrIter,
+    NlMsgHdr, block_on, dump_retry_async, errno_error, get_nlmsg_type, header, is_nlmsg_done,
+    nla_align, nla_get_str, nla_get_u32_be, nla_get_u64_be, parse_nlmsg_error, with_msg_buffer,
+    with_netns_fd, with_netns_fd_async,
 };
-use crate::{IpEntry, IpSetError, Result};
+use crate::{
+    BackendCapabilities, Entry, IpEntry, MacAddr, NftFamily, NftVerdict, Result, RipsetError,
+};
+
+mod monitor;
+mod rules;
+pub use monitor::{NftEvent, NftMonitor};
+#[cfg(feature = "tokio")]
+pub(crate) use rules::nft_ensure_drop_rule_async;
+pub(crate) use rules::nftset_references_async;
+pub use rules::{NftSetReference, nft_ensure_drop_rule, nftset_references};
 
 // nftables message types
 const NFT_MSG_NEWTABLE: u16 = 0;
 const NFT_MSG_GETTABLE: u16 = 1;
 const NFT_MSG_DELTABLE: u16 = 2;
+const NFT_MSG_DELRULE: u16 = 8;
 const NFT_MSG_NEWSET: u16 = 9;
 const NFT_MSG_DELSET: u16 = 11;
 const NFT_MSG_GETSET: u16 = 10;
 const NFT_MSG_NEWSETELEM: u16 = 12;
 const NFT_MSG_GETSETELEM: u16 = 13;
 const NFT_MSG_DELSETELEM: u16 = 14;
+const NFT_MSG_NEWGEN: u16 = 15;
+const NFT_MSG_GETGEN: u16 = 16;
+const NFT_MSG_GETSETELEM_RESET: u16 = 33;
+
+// nftables generation attributes
+const NFTA_GEN_ID: u16 = 1;
 
 // nftables table attributes
 const NFTA_TABLE_NAME: u16 = 1;
+const NFTA_TABLE_FLAGS: u16 = 2;
+const NFTA_TABLE_OWNER: u16 = 7;
+
+// nftables table flags
+const NFT_TABLE_F_OWNER: u32 = 0x2;
+
+// nftables rule attributes
+const NFTA_RULE_TABLE: u16 = 1;
 
 // nftables set attributes
 const NFTA_SET_TABLE: u16 = 1;
@@ -32,8 +68,28 @@ const NFTA_SET_NAME: u16 = 2;
 const NFTA_SET_FLAGS: u16 = 3;
 const NFTA_SET_KEY_TYPE: u16 = 4;
 const NFTA_SET_KEY_LEN: u16 = 5;
+const NFTA_SET_DATA_TYPE: u16 = 6;
+const NFTA_SET_DATA_LEN: u16 = 7;
+const NFTA_SET_POLICY: u16 = 8;
+const NFTA_SET_DESC: u16 = 9;
 const NFTA_SET_ID: u16 = 10;
 const NFTA_SET_TIMEOUT: u16 = 11;
+const NFTA_SET_GC_INTERVAL: u16 = 12;
+const NFTA_SET_USERDATA: u16 = 13;
+const NFTA_SET_EXPR: u16 = 17;
+const NFTA_SET_EXPRESSIONS: u16 = 18;
+
+// nftables set description attributes
+const NFTA_SET_DESC_SIZE: u16 = 1;
+const NFTA_SET_DESC_CONCAT: u16 = 2;
+
+// nftables list and set field attributes
+const NFTA_LIST_ELEM: u16 = 1;
+const NFTA_SET_FIELD_LEN: u16 = 1;
+
+// Bits per field in the key type of concatenated sets
+const NFT_TYPE_BITS: u32 = 6;
+const NFT_TYPE_MASK: u32 = (1 << NFT_TYPE_BITS) - 1;
 
 // nftables set element list attributes
 const NFTA_SET_ELEM_LIST_TABLE: u16 = 1;
@@ -42,23 +98,81 @@ const NFTA_SET_ELEM_LIST_ELEMENTS: u16 = 3;
 
 // nftables set element attributes
 const NFTA_SET_ELEM_KEY: u16 = 1;
+const NFTA_SET_ELEM_DATA: u16 = 2;
+const NFTA_SET_ELEM_FLAGS: u16 = 3;
 const NFTA_SET_ELEM_TIMEOUT: u16 = 4;
+const NFTA_SET_ELEM_EXPIRATION: u16 = 5;
+const NFTA_SET_ELEM_EXPR: u16 = 7;
 const NFTA_SET_ELEM_KEY_END: u16 = 10;
+const NFTA_SET_ELEM_EXPRESSIONS: u16 = 11;
+
+// nftables expression attributes
+const NFTA_EXPR_NAME: u16 = 1;
+const NFTA_EXPR_DATA: u16 = 2;
+
+// nftables counter attributes
+const NFTA_COUNTER_BYTES: u16 = 1;
+const NFTA_COUNTER_PACKETS: u16 = 2;
 
 // nftables data attributes
 const NFTA_DATA_VALUE: u16 = 1;
+const NFTA_DATA_VERDICT: u16 = 2;
+
+// nftables verdict attributes
+const NFTA_VERDICT_CODE: u16 = 1;
+const NFTA_VERDICT_CHAIN: u16 = 2;
+
+// Verdict codes (NF_* and NFT_*)
+const NF_DROP: i32 = 0;
+const NF_ACCEPT: i32 = 1;
+const NFT_CONTINUE: i32 = -1;
+const NFT_JUMP: i32 = -3;
+const NFT_GOTO: i32 = -4;
+const NFT_RETURN: i32 = -5;
+
+// Data type of verdict maps
+const NFT_DATA_VERDICT: u32 = 0xffffff00;
 
 // nftables set flags
+const NFT_SET_CONSTANT: u32 = 0x2;
 const NFT_SET_INTERVAL: u32 = 0x4;
+const NFT_SET_MAP: u32 = 0x8;
 const NFT_SET_TIMEOUT: u32 = 0x10;
+const NFT_SET_EVAL: u32 = 0x20;
+const NFT_SET_CONCAT: u32 = 0x80;
+
+// nftables set element flags
+const NFT_SET_ELEM_INTERVAL_END: u32 = 0x1;
+
+// Userdata TLV types written by nft
+const NFTNL_UDATA_SET_MERGE_ELEMENTS: u8 = 2;
+const NFTNL_UDATA_SET_COMMENT: u8 = 7;
+const NFT_COMMENT_MAXLEN: usize = 255;
 
 // Address family constants
 const NFPROTO_INET: u8 = 1;
 const NFPROTO_IPV4: u8 = 2;
+const NFPROTO_ARP: u8 = 3;
+const NFPROTO_NETDEV: u8 = 5;
+const NFPROTO_BRIDGE: u8 = 7;
 const NFPROTO_IPV6: u8 = 10;
 
 const BUFF_SZ: usize = 2048;
+// Elements per NEWSETELEM/DELSETELEM message, small enough for the element
+// list of the largest elements to fit the 64 KiB attribute length limit
+const NFT_MAX_ELEMS_PER_MSG: usize = 128;
+
+/// Number of lookups sent together by [`nftset_test_many`], few enough for
+/// their answers to fit into the socket receive buffer.
+const NFT_TESTS_PER_SEND: usize = 128;
+// Batches larger than this get a socket send buffer to match
+const NFT_BATCH_SNDBUF_MIN: usize = 64 * 1024;
 const NFT_SET_MAXNAMELEN: usize = 256;
+/// Table in which [`nftset_capabilities`] probes set creation.
+const NFT_PROBE_TABLE: &str = "ripset_probe";
+/// Attempts at an operation disturbed by concurrent ruleset changes
+const NFT_MAX_RETRIES: usize = 10;
+const IFNAMSIZ: usize = 16;
 
 use std::sync::atomic::{AtomicU32, Ordering};
 
@@ -75,13 +189,17 @@ fn nft_msg_type(cmd: u16) -> u16 {
     ((NFNL_SUBSYS_NFTABLES as u16) << 8) | cmd
 }
 
-/// Parse nftables family string to protocol number.
-fn parse_nf_family(family: &str) -> Result<u8> {
-    match family.to_lowercase().as_str() {
-        "inet" => Ok(NFPROTO_INET),
-        "ip" | "ipv4" => Ok(NFPROTO_IPV4),
-        "ip6" | "ipv6" => Ok(NFPROTO_IPV6),
-        _ => Err(IpSetError::InvalidAddressFamily),
+impl NftFamily {
+    /// Netfilter protocol number carried in `nfgenmsg`.
+    fn nfproto(self) -> u8 {
+        match self {
+            NftFamily::Inet => NFPROTO_INET,
+            NftFamily::Ip => NFPROTO_IPV4,
+            NftFamily::Ip6 => NFPROTO_IPV6,
+            NftFamily::Arp => NFPROTO_ARP,
+            NftFamily::Bridge => NFPROTO_BRIDGE,
+            NftFamily::Netdev => NFPROTO_NETDEV,
+        }
     }
 }
 
@@ -110,20 +228,122 @@ fn calculate_interval_end(addr: &IpAddr) -> IpAddr {
     }
 }
 
-/// Address type for nftables sets
-#[derive(Clone, Copy, Debug)]
+/// Encode an entry as the key data of an nftables set element.
+///
+/// Concatenated values are each padded to a multiple of 4 bytes, matching
+/// the register layout the kernel uses for concatenated set keys.
+fn nft_key_bytes(entry: &Entry) -> Result<Vec<u8>> {
+    let bytes = match entry {
+        Entry::Ip(ip) => ip_bytes(&ip.addr),
+        Entry::Port {
+            port,
+            port_to: None,
+        } => port.to_be_bytes().to_vec(),
+        Entry::Port { .. } => {
+            return Err(RipsetError::InvalidOption(
+                "port ranges require an interval set".to_string(),
+            ));
+        }
+        Entry::Mac(mac) => mac.0.to_vec(),
+        // Marks are kept in host byte order by nftables
+        Entry::Mark(mark) => mark.to_ne_bytes().to_vec(),
+        Entry::Iface(name) => {
+            if name.is_empty() || name.len() >= IFNAMSIZ {
+                return Err(RipsetError::InvalidOption(format!(
+                    "invalid interface name: {name}"
+                )));
+            }
+            let mut bytes = name.as_bytes().to_vec();
+            bytes.resize(IFNAMSIZ, 0);
+            bytes
+        }
+        Entry::Concat(parts) => {
+            let mut bytes = Vec::new();
+            for part in parts {
+                if matches!(part, Entry::Concat(_)) {
+                    return Err(RipsetError::InvalidOption(
+                        "nested concatenations are not supported".to_string(),
+                    ));
+                }
+                bytes.extend(nft_key_bytes(part)?);
+                bytes.resize(bytes.len().next_multiple_of(4), 0);
+            }
+            bytes
+        }
+        Entry::Verdict(_) => {
+            return Err(RipsetError::InvalidOption(
+                "verdicts can only be values of verdict maps".to_string(),
+            ));
+        }
+        Entry::SetName(_) => {
+            return Err(RipsetError::InvalidOption(
+                "set names cannot be elements of nftables sets".to_string(),
+            ));
+        }
+    };
+    Ok(bytes)
+}
+
+/// Key type for nftables sets
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NftSetType {
     /// IPv4 addresses
     Ipv4Addr,
     /// IPv6 addresses
     Ipv6Addr,
+    /// Ethernet (MAC) addresses
+    EtherAddr,
+    /// Packet marks
+    Mark,
+    /// Interface names
+    Ifname,
+    /// Transport protocol ports
+    InetService,
+    /// Verdicts, the values of verdict maps
+    Verdict,
+    /// Concatenation of several types, e.g. `ipv4_addr . inet_service`
+    Concat(Vec<NftSetType>),
 }
 
 impl NftSetType {
     fn key_type(&self) -> u32 {
         match self {
-            NftSetType::Ipv4Addr => 7, // TYPE_IPADDR
-            NftSetType::Ipv6Addr => 8, // TYPE_IP6ADDR
+            NftSetType::Ipv4Addr => 7,     // TYPE_IPADDR
+            NftSetType::Ipv6Addr => 8,     // TYPE_IP6ADDR
+            NftSetType::EtherAddr => 9,    // TYPE_ETHERADDR
+            NftSetType::InetService => 13, // TYPE_INET_SERVICE
+            NftSetType::Mark => 19,        // TYPE_MARK
+            NftSetType::Ifname => 41,      // TYPE_IFNAME
+            NftSetType::Verdict => NFT_DATA_VERDICT,
+            // Field types are packed into the key type, first field highest
+            NftSetType::Concat(fields) => fields.iter().fold(0, |key_type, field| {
+                key_type << NFT_TYPE_BITS | field.key_type()
+            }),
+        }
+    }
+
+    fn from_key_type(key_type: u32) -> Option<Self> {
+        match key_type {
+            7 => Some(NftSetType::Ipv4Addr),
+            8 => Some(NftSetType::Ipv6Addr),
+            9 => Some(NftSetType::EtherAddr),
+            13 => Some(NftSetType::InetService),
+            19 => Some(NftSetType::Mark),
+            41 => Some(NftSetType::Ifname),
+            NFT_DATA_VERDICT => Some(NftSetType::Verdict),
+            key_type if key_type >> NFT_TYPE_BITS != 0 => {
+                let mut fields = Vec::new();
+                let mut rest = key_type;
+                while rest != 0 {
+                    fields.push(NftSetType::from_key_type(rest & NFT_TYPE_MASK)?);
+                    rest >>= NFT_TYPE_BITS;
+                }
+                fields.reverse();
+                Some(NftSetType::Concat(fields))
+            }
+            _ => None,
         }
     }
 
@@ -131,16 +351,163 @@ impl NftSetType {
         match self {
             NftSetType::Ipv4Addr => 4,
             NftSetType::Ipv6Addr => 16,
+            NftSetType::EtherAddr => 6,
+            NftSetType::Mark => 4,
+            NftSetType::Ifname => IFNAMSIZ as u32,
+            NftSetType::InetService => 2,
+            // The kernel sizes verdict data itself
+            NftSetType::Verdict => 0,
+            // Each field takes a whole number of 32-bit registers
+            NftSetType::Concat(fields) => fields
+                .iter()
+                .map(|field| field.key_len().next_multiple_of(4))
+                .sum(),
+        }
+    }
+
+    /// Decode an element key of this type.
+    fn entry_from_key(&self, key: &[u8]) -> Option<Entry> {
+        match self {
+            NftSetType::Ipv4Addr | NftSetType::Ipv6Addr => {
+                Some(Entry::Ip(IpEntry::new(ip_from_bytes(key)?)))
+            }
+            NftSetType::EtherAddr => Some(Entry::Mac(MacAddr(key.try_into().ok()?))),
+            // Marks are kept in host byte order
+            NftSetType::Mark => Some(Entry::Mark(u32::from_ne_bytes(key.try_into().ok()?))),
+            NftSetType::Ifname => {
+                let len = key.iter().position(|&b| b == 0).unwrap_or(key.len());
+                Some(Entry::Iface(String::from_utf8(key[..len].to_vec()).ok()?))
+            }
+            NftSetType::InetService => Some(Entry::from(u16::from_be_bytes(key.try_into().ok()?))),
+            NftSetType::Verdict => None,
+            NftSetType::Concat(fields) => {
+                let mut parts = Vec::new();
+                let mut rest = key;
+                for field in fields {
+                    let len = field.key_len() as usize;
+                    parts.push(field.entry_from_key(rest.get(..len)?)?);
+                    rest = rest.get(len.next_multiple_of(4)..)?;
+                }
+                Some(Entry::Concat(parts))
+            }
+        }
+    }
+
+    /// Decode the inclusive range `first..=last` of element keys of this
+    /// type, as a single value when both ends are equal.
+    fn range_from_keys(&self, first: &[u8], last: &[u8]) -> Option<Entry> {
+        match self {
+            NftSetType::Ipv4Addr | NftSetType::Ipv6Addr => Some(Entry::Ip(range_entry(
+                ip_from_bytes(first)?,
+                ip_from_bytes(last)?,
+            ))),
+            NftSetType::InetService => {
+                let port = u16::from_be_bytes(first.try_into().ok()?);
+                let port_to = u16::from_be_bytes(last.try_into().ok()?);
+                Some(Entry::Port {
+                    port,
+                    port_to: (port_to != port).then_some(port_to),
+                })
+            }
+            NftSetType::Concat(fields) => {
+                let mut parts = Vec::new();
+                let (mut first, mut last) = (first, last);
+                for field in fields {
+                    let len = field.key_len() as usize;
+                    parts.push(field.range_from_keys(first.get(..len)?, last.get(..len)?)?);
+                    first = first.get(len.next_multiple_of(4)..)?;
+                    last = last.get(len.next_multiple_of(4)..)?;
+                }
+                Some(Entry::Concat(parts))
+            }
+            set_type => set_type.entry_from_key(first),
+        }
+    }
+}
+
+impl fmt::Display for NftSetType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NftSetType::Ipv4Addr => f.write_str("ipv4_addr"),
+            NftSetType::Ipv6Addr => f.write_str("ipv6_addr"),
+            NftSetType::EtherAddr => f.write_str("ether_addr"),
+            NftSetType::Mark => f.write_str("mark"),
+            NftSetType::Ifname => f.write_str("ifname"),
+            NftSetType::InetService => f.write_str("inet_service"),
+            NftSetType::Verdict => f.write_str("verdict"),
+            NftSetType::Concat(fields) => {
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" . ")?;
+                    }
+                    write!(f, "{field}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses the type names of nft, concatenations joined with ` . `.
+impl FromStr for NftSetType {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.contains(" . ") {
+            return s
+                .split(" . ")
+                .map(str::parse)
+                .collect::<Result<_>>()
+                .map(NftSetType::Concat);
+        }
+        match s.trim() {
+            "ipv4_addr" => Ok(NftSetType::Ipv4Addr),
+            "ipv6_addr" => Ok(NftSetType::Ipv6Addr),
+            "ether_addr" => Ok(NftSetType::EtherAddr),
+            "mark" => Ok(NftSetType::Mark),
+            "ifname" => Ok(NftSetType::Ifname),
+            "inet_service" => Ok(NftSetType::InetService),
+            "verdict" => Ok(NftSetType::Verdict),
+            _ => Err(RipsetError::InvalidOption(format!("unknown set type: {s}"))),
         }
     }
 }
 
 /// Options for creating an nftables set
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct NftSetCreateOptions {
     pub set_type: NftSetType,
     pub timeout: Option<u32>,
     pub flags: Option<u32>,
+    /// Store CIDR blocks and address ranges (`interval` flag)
+    pub interval: bool,
+    /// Merge adjacent and overlapping ranges on insertion, like nft's
+    /// `auto-merge` (requires `interval`)
+    pub auto_merge: bool,
+    /// Create a map with values of this type instead of a plain set
+    pub data_type: Option<NftSetType>,
+    /// Lookup strategy the kernel picks the set backend for
+    pub policy: Option<NftSetPolicy>,
+    /// Maximum number of elements
+    pub size: Option<u32>,
+    /// Interval in seconds between garbage collection runs that drop expired
+    /// elements (implies the `timeout` flag)
+    pub gc_interval: Option<u32>,
+    /// Allow rules to add and update elements from the packet path
+    /// (`dynamic` flag)
+    pub dynamic: bool,
+    /// Freeze the set contents once it is bound to a rule (`constant` flag)
+    pub constant: bool,
+    /// Free-form description of the set
+    pub comment: Option<String>,
+    /// Keep packet and byte counters for each element
+    pub counters: bool,
+    /// Raw `NFTA_SET_*` attributes appended to the `NEWSET` message, as
+    /// `(type, payload)` pairs, for kernel options not modeled above. The
+    /// payload is sent as is, so integers must be big-endian.
+    pub extra_attrs: Vec<(u16, Vec<u8>)>,
 }
 
 impl Default for NftSetCreateOptions {
@@ -149,375 +516,1899 @@ impl Default for NftSetCreateOptions {
             set_type: NftSetType::Ipv4Addr,
             timeout: None,
             flags: None,
+            interval: false,
+            auto_merge: false,
+            data_type: None,
+            policy: None,
+            size: None,
+            gc_interval: None,
+            dynamic: false,
+            constant: false,
+            comment: None,
+            counters: false,
+            extra_attrs: Vec::new(),
         }
     }
 }
 
-/// Create an nftables table.
+/// What a message in a batch does, to report its errors like the
+/// single-operation functions.
+#[derive(Clone, Copy)]
+enum NftOpKind {
+    NewTable,
+    DelTable,
+    FlushTable,
+    NewSet,
+    DelSet,
+    FlushSet,
+    NewElem,
+    DelElem,
+    NewChain,
+    NewRule,
+}
+
+impl NftOpKind {
+    /// The kind of an element message, NEWSETELEM or DELSETELEM.
+    fn elements(cmd: u16) -> Self {
+        if cmd == NFT_MSG_NEWSETELEM {
+            NftOpKind::NewElem
+        } else {
+            NftOpKind::DelElem
+        }
+    }
+}
+
+/// A message queued in a transaction, with the table or set it acts on.
+struct NftOp {
+    kind: NftOpKind,
+    family: NftFamily,
+    table: String,
+    name: String,
+}
+
+impl NftOp {
+    fn error(&self, error: i32, msg: &[u8]) -> RipsetError {
+        match (self.kind, error) {
+            (NftOpKind::DelElem, libc::ENOENT) => RipsetError::EntryNotFound,
+            (NftOpKind::NewTable | NftOpKind::NewChain, libc::ENOENT) => errno_error(error, msg),
+            (_, libc::ENOENT) => RipsetError::SetNotFound(self.name.clone()),
+            (_, libc::EEXIST) => RipsetError::EntryExists,
+            // The set already holds as many elements as its size allows
+            (NftOpKind::NewElem, libc::ENFILE) => RipsetError::SetFull(self.name.clone()),
+            (_, error) => errno_error(error, msg),
+        }
+    }
+}
+
+/// A batch of nftables changes applied atomically.
 ///
-/// # Arguments
+/// Changes are queued with the methods below and sent to the kernel as a
+/// single netlink batch by [`commit`](NftTransaction::commit). The kernel
+/// applies either all of them or, if one fails, none, like `nft -f`.
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
-/// * `table` - The table name to create
+/// Elements can be added to tables and sets created earlier in the same
+/// transaction. Unlike [`nftset_add`], adding an element that is already in
+/// the set is not an error, and ranges aren't merged in auto-merge sets.
 ///
 /// # Example
 ///
 /// ```no_run
-/// use ruhop_ipset::nftset::nftset_create_table;
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, NftSetCreateOptions, NftTransaction};
 ///
-/// nftset_create_table("inet", "mytable").unwrap();
+/// let addr: IpAddr = "10.0.0.1".parse().unwrap();
+/// let mut tx = NftTransaction::new();
+/// tx.create_table(NftFamily::Inet, "mytable").unwrap();
+/// tx.create_set(NftFamily::Inet, "mytable", "myset", &NftSetCreateOptions::default())
+///     .unwrap();
+/// tx.add(NftFamily::Inet, "mytable", "myset", addr).unwrap();
+/// tx.commit().unwrap();
 /// ```
-pub fn nftset_create_table(family: &str, table: &str) -> Result<()> {
-    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidTableName(table.to_string()));
+pub struct NftTransaction {
+    buf: MsgBuffer,
+    ops: Vec<NftOp>,
+    /// Offset of the last queued message
+    last_msg: usize,
+    /// Whether the sets used so far are interval sets, including the ones
+    /// created in this transaction
+    interval_sets: Vec<(u8, String, String, bool)>,
+}
+
+impl Default for NftTransaction {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    let nf_family = parse_nf_family(family)?;
+impl NftTransaction {
+    /// Start an empty transaction.
+    pub fn new() -> Self {
+        Self::start(None)
+    }
 
-    let mut buf = MsgBuffer::new(BUFF_SZ);
+    /// Start an empty transaction the kernel refuses with `ERESTART` if the
+    /// ruleset is no longer at generation `genid` when it is committed.
+    fn with_genid(genid: u32) -> Self {
+        Self::start(Some(genid))
+    }
 
-    // Batch begin
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_BEGIN, NLM_F_REQUEST, 0);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg();
+    fn start(genid: Option<u32>) -> Self {
+        let mut buf = MsgBuffer::new(BUFF_SZ);
+        put_batch_begin(&mut buf, genid);
 
-    let msg_start = buf.len();
+        Self {
+            buf,
+            ops: Vec::new(),
+            last_msg: 0,
+            interval_sets: Vec::new(),
+        }
+    }
 
-    // Create table message
-    buf.put_nlmsghdr(
-        nft_msg_type(NFT_MSG_NEWTABLE),
-        NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE,
-        1,
-    );
-    buf.put_nfgenmsg(nf_family, 0, 0);
+    /// Number of queued messages.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
 
-    buf.put_attr_str(NFTA_TABLE_NAME, table);
+    /// Whether nothing has been queued.
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
 
-    buf.finalize_nlmsg_at(msg_start);
+    /// Start the next message and return its offset.
+    fn start_msg(&mut self, cmd: u16, flags: u16, nf_family: u8) -> usize {
+        let msg_start = self.buf.len();
+        self.last_msg = msg_start;
+        let seq = self.ops.len() as u32 + 1;
+        self.buf.put_nlmsghdr(nft_msg_type(cmd), flags, seq);
+        self.buf.put_nfgenmsg(nf_family, 0, 0);
+        msg_start
+    }
 
-    // Batch end
-    let end_start = buf.len();
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_END, NLM_F_REQUEST, 2);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg_at(end_start);
+    /// Queue the creation of a table, see [`nftset_create_table`].
+    pub fn create_table(&mut self, family: NftFamily, table: &str) -> Result<()> {
+        self.put_newtable(family, table, NLM_F_CREATE, 0)
+    }
 
-    let socket = NetlinkSocket::new()?;
-    socket.send(buf.as_slice())?;
+    /// Queue a NEWTABLE message with the given creation and table flags.
+    fn put_newtable(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        flags: u16,
+        table_flags: u32,
+    ) -> Result<()> {
+        validate_table_name(table)?;
+        let nf_family = family.nfproto();
+
+        let msg_start = self.start_msg(NFT_MSG_NEWTABLE, NLM_F_REQUEST | flags, nf_family);
+        self.buf.put_attr_str(NFTA_TABLE_NAME, table);
+        if table_flags != 0 {
+            self.buf.put_attr_u32_be(NFTA_TABLE_FLAGS, table_flags);
+        }
+        self.buf.finalize_nlmsg_at(msg_start);
+
+        self.ops.push(NftOp {
+            kind: NftOpKind::NewTable,
+            family,
+            table: table.to_string(),
+            name: table.to_string(),
+        });
+        Ok(())
+    }
 
-    let mut recv_buf = [0u8; BUFF_SZ];
-    loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
+    /// Queue the deletion of a table, see [`nftset_delete_table`].
+    pub fn delete_table(&mut self, family: NftFamily, table: &str) -> Result<()> {
+        validate_table_name(table)?;
+        let nf_family = family.nfproto();
+
+        let msg_start = self.start_msg(NFT_MSG_DELTABLE, NLM_F_REQUEST, nf_family);
+        self.buf.put_attr_str(NFTA_TABLE_NAME, table);
+        self.buf.finalize_nlmsg_at(msg_start);
+
+        self.ops.push(NftOp {
+            kind: NftOpKind::DelTable,
+            family,
+            table: table.to_string(),
+            name: table.to_string(),
+        });
+        Ok(())
+    }
 
-        if recv_len < NlMsgHdr::SIZE {
-            return Err(IpSetError::ProtocolError);
+    /// Queue the removal of all rules and set elements of a table, see
+    /// [`nftset_flush_table`].
+    ///
+    /// The sets to flush are looked up when the change is queued, so sets
+    /// created later in the transaction are left alone.
+    pub fn flush_table(&mut self, family: NftFamily, table: &str) -> Result<()> {
+        block_on(self.flush_table_async(family, table))
+    }
+
+    /// [`NftTransaction::flush_table`] for async code.
+    async fn flush_table_async(&mut self, family: NftFamily, table: &str) -> Result<()> {
+        validate_table_name(table)?;
+        let nf_family = family.nfproto();
+        let sets = nftset_list_sets_async(family, table)
+            .await
+            .map_err(|e| match e {
+                e if e.errno() == Some(libc::ENOENT) => RipsetError::SetNotFound(table.to_string()),
+                e => e,
+            })?;
+
+        // Deleting rules without a chain empties every chain of the table
+        let msg_start = self.start_msg(NFT_MSG_DELRULE, NLM_F_REQUEST, nf_family);
+        self.buf.put_attr_str(NFTA_RULE_TABLE, table);
+        self.buf.finalize_nlmsg_at(msg_start);
+
+        self.ops.push(NftOp {
+            kind: NftOpKind::FlushTable,
+            family,
+            table: table.to_string(),
+            name: table.to_string(),
+        });
+
+        // Anonymous sets belong to rules and go away with them
+        for set in sets.iter().filter(|set| !set.name.starts_with("__")) {
+            self.flush_set(family, table, &set.name)?;
         }
+        Ok(())
+    }
 
-        if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-            if error == 0 {
-                // Continue
-            } else if -error == libc::EEXIST {
-                return Err(IpSetError::ElementExists);
-            } else {
-                return Err(IpSetError::NetlinkError(-error));
+    /// Queue the creation of a set, see [`nftset_create_set`].
+    pub fn create_set(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        options: &NftSetCreateOptions,
+    ) -> Result<()> {
+        self.put_newset(family, table, setname, options, NLM_F_CREATE)
+    }
+
+    /// Queue a NEWSET message with the given creation flags.
+    fn put_newset(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        options: &NftSetCreateOptions,
+        flags: u16,
+    ) -> Result<()> {
+        validate_table_name(table)?;
+        validate_set_name(setname)?;
+        validate_create_options(options)?;
+        let nf_family = family.nfproto();
+
+        let msg_start = self.start_msg(NFT_MSG_NEWSET, NLM_F_REQUEST | flags, nf_family);
+        put_newset_attrs(&mut self.buf, table, setname, options)?;
+        self.buf.finalize_nlmsg_at(msg_start);
+
+        self.ops.push(NftOp {
+            kind: NftOpKind::NewSet,
+            family,
+            table: table.to_string(),
+            name: table.to_string(),
+        });
+        self.interval_sets.push((
+            nf_family,
+            table.to_string(),
+            setname.to_string(),
+            options.interval,
+        ));
+        Ok(())
+    }
+
+    /// Queue the deletion of a set, see [`nftset_delete_set`].
+    pub fn delete_set(&mut self, family: NftFamily, table: &str, setname: &str) -> Result<()> {
+        self.put_set_msg(family, table, setname, NFT_MSG_DELSET, NftOpKind::DelSet)
+    }
+
+    /// Queue the removal of all elements of a set, see [`nftset_flush`].
+    pub fn flush_set(&mut self, family: NftFamily, table: &str, setname: &str) -> Result<()> {
+        // Deleting elements without an element list flushes the whole set
+        self.put_set_msg(
+            family,
+            table,
+            setname,
+            NFT_MSG_DELSETELEM,
+            NftOpKind::FlushSet,
+        )
+    }
+
+    /// Queue a message naming just a table and a set.
+    fn put_set_msg(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        cmd: u16,
+        kind: NftOpKind,
+    ) -> Result<()> {
+        validate_table_name(table)?;
+        validate_set_name(setname)?;
+        let nf_family = family.nfproto();
+
+        // The set and element list attributes share their numbers
+        let msg_start = self.start_msg(cmd, NLM_F_REQUEST, nf_family);
+        self.buf.put_attr_str(NFTA_SET_TABLE, table);
+        self.buf.put_attr_str(NFTA_SET_NAME, setname);
+        self.buf.finalize_nlmsg_at(msg_start);
+
+        self.ops.push(NftOp {
+            kind,
+            family,
+            table: table.to_string(),
+            name: setname.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Queue the addition of an element, see [`nftset_add`].
+    pub fn add<E: Into<Entry>>(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        self.put_entry(
+            family,
+            table,
+            setname,
+            &entry.into(),
+            None,
+            NFT_MSG_NEWSETELEM,
+        )
+    }
+
+    /// Queue the addition of a map element, see [`nftset_map_add`].
+    pub fn map_add<K, V>(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        mapname: &str,
+        key: K,
+        value: V,
+    ) -> Result<()>
+    where
+        K: Into<Entry>,
+        V: Into<Entry>,
+    {
+        self.put_entry(
+            family,
+            table,
+            mapname,
+            &key.into(),
+            Some(&value.into()),
+            NFT_MSG_NEWSETELEM,
+        )
+    }
+
+    /// Queue the deletion of an element, see [`nftset_del`].
+    pub fn del<E: Into<Entry>>(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        self.put_entry(
+            family,
+            table,
+            setname,
+            &entry.into(),
+            None,
+            NFT_MSG_DELSETELEM,
+        )
+    }
+
+    fn put_entry(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: &Entry,
+        data: Option<&Entry>,
+        cmd: u16,
+    ) -> Result<()> {
+        validate_table_name(table)?;
+        validate_set_name(setname)?;
+        let nf_family = family.nfproto();
+
+        // Sets created in this transaction aren't known to the kernel yet;
+        // the others are looked up once
+        let is_interval = match self
+            .interval_sets
+            .iter()
+            .find(|(f, t, s, _)| (*f, t.as_str(), s.as_str()) == (nf_family, table, setname))
+        {
+            Some(&(.., interval)) => interval,
+            None => {
+                let interval =
+                    block_on(nftset_get_set(family, table, setname)).is_ok_and(|set| set.interval);
+                self.interval_sets.push((
+                    nf_family,
+                    table.to_string(),
+                    setname.to_string(),
+                    interval,
+                ));
+                interval
             }
+        };
+
+        let mut elements = nft_elements(entry, is_interval)?;
+        if let Some(data) = data {
+            // The value belongs to the start of a range
+            elements[0].data = Some(nft_data(data)?);
         }
+        self.put_elements(family, cmd, table, setname, &elements)
+    }
 
-        if is_nlmsg_done(&recv_buf[..recv_len]) {
-            break;
+    /// Queue NEWSETELEM or DELSETELEM messages for `elements`, split so
+    /// that each element list fits in a netlink attribute.
+    fn put_elements(
+        &mut self,
+        family: NftFamily,
+        cmd: u16,
+        table: &str,
+        setname: &str,
+        elements: &[NftElement],
+    ) -> Result<()> {
+        let kind = NftOpKind::elements(cmd);
+        for chunk in elements.chunks(NFT_MAX_ELEMS_PER_MSG) {
+            self.last_msg = self.buf.len();
+            let seq = self.ops.len() as u32 + 1;
+            put_setelem_msg(
+                &mut self.buf,
+                family.nfproto(),
+                cmd,
+                seq,
+                table,
+                setname,
+                chunk,
+            )?;
+            self.ops.push(NftOp {
+                kind,
+                family,
+                table: table.to_string(),
+                name: setname.to_string(),
+            });
         }
+        Ok(())
+    }
 
-        if get_nlmsg_type(&recv_buf[..recv_len]) == Some(crate::netlink::NLMSG_ERROR) {
-            break;
+    /// Send the queued changes to the kernel.
+    ///
+    /// Returns the error of the first change the kernel refused, in which
+    /// case none of the changes are applied. Changes to a table owned by
+    /// another process fail with [`RipsetError::TableOwned`].
+    pub fn commit(self) -> Result<()> {
+        block_on(self.commit_async())
+    }
+
+    /// [`NftTransaction::commit`] for async code.
+    pub(crate) async fn commit_async(self) -> Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
         }
+        self.commit_on(&NetlinkSocket::new()?).await
     }
 
-    Ok(())
-}
+    /// Send the queued changes over `socket`, which the kernel checks
+    /// against the owner of the tables they touch.
+    async fn commit_on(self, socket: &NetlinkSocket) -> Result<()> {
+        self.send_on(socket, true).await
+    }
 
-/// Delete an nftables table.
-///
-/// # Arguments
-///
-/// * `family` - The address family ("inet", "ip", "ip6")
-/// * `table` - The table name to delete
-///
-/// # Example
-///
-/// ```no_run
-/// use ruhop_ipset::nftset::nftset_delete_table;
-///
-/// nftset_delete_table("inet", "mytable").unwrap();
-/// ```
-pub fn nftset_delete_table(family: &str, table: &str) -> Result<()> {
-    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidTableName(table.to_string()));
+    /// Have the kernel validate the queued changes without applying them,
+    /// like `nft --check`.
+    async fn check(self) -> Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        self.send_on(&NetlinkSocket::new()?, false).await
     }
 
-    let nf_family = parse_nf_family(family)?;
+    /// Send the queued changes over `socket`. Without the batch end
+    /// message, the kernel processes the batch and then aborts it.
+    async fn send_on(mut self, socket: &NetlinkSocket, commit: bool) -> Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
 
-    let mut buf = MsgBuffer::new(BUFF_SZ);
+        let ops = &self.ops;
+        let msgs = ops.len() as u32;
+        nft_send_batch(socket, &mut self.buf, self.last_msg, msgs, commit)?;
+        nft_batch_answer(socket, msgs, |seq, error, msg| {
+            let op = (seq as usize).checked_sub(1).and_then(|i| ops.get(i));
+            match op {
+                Some(op) if error == libc::EPERM => nft_owner_error(op, socket),
+                Some(op) => op.error(error, msg),
+                None => errno_error(error, msg),
+            }
+        })
+        .await
+    }
+}
 
-    // Batch begin
+/// Start a batch in `buf`, refused with `ERESTART` unless the ruleset is
+/// still at generation `genid`, if any.
+fn put_batch_begin(buf: &mut MsgBuffer, genid: Option<u32>) {
     buf.put_nlmsghdr(NFNL_MSG_BATCH_BEGIN, NLM_F_REQUEST, 0);
     buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
+    if let Some(genid) = genid {
+        buf.put_attr_u32_be(NFNLA_BATCH_GENID, genid);
+    }
     buf.finalize_nlmsg();
+}
 
-    let msg_start = buf.len();
-
-    // Delete table message
-    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_DELTABLE), NLM_F_REQUEST | NLM_F_ACK, 1);
-    buf.put_nfgenmsg(nf_family, 0, 0);
-
-    buf.put_attr_str(NFTA_TABLE_NAME, table);
-
-    buf.finalize_nlmsg_at(msg_start);
-
+/// Send the batch of `msgs` messages built in `buf`, the last of which
+/// starts at `last_msg`. The batch is committed if `commit` is set, and
+/// aborted otherwise.
+fn nft_send_batch(
+    socket: &NetlinkSocket,
+    buf: &mut MsgBuffer,
+    last_msg: usize,
+    msgs: u32,
+    commit: bool,
+) -> Result<()> {
     // Batch end
-    let end_start = buf.len();
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_END, NLM_F_REQUEST, 2);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg_at(end_start);
+    if commit {
+        let end_start = buf.len();
+        buf.put_nlmsghdr(NFNL_MSG_BATCH_END, NLM_F_REQUEST, msgs + 1);
+        buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
+        buf.finalize_nlmsg_at(end_start);
+    }
 
-    let socket = NetlinkSocket::new()?;
+    // Only the last message asks for an acknowledgement: the kernel
+    // reports errors of the others anyway, and acknowledging every
+    // message of a large batch would overflow the socket
+    let flags_at = last_msg + std::mem::offset_of!(NlMsgHdr, nlmsg_flags);
+    let flags = &mut buf.as_mut_slice()[flags_at..flags_at + 2];
+    let acked = u16::from_ne_bytes([flags[0], flags[1]]) | NLM_F_ACK;
+    flags.copy_from_slice(&acked.to_ne_bytes());
+
+    if buf.len() > NFT_BATCH_SNDBUF_MIN {
+        socket.set_send_buffer(buf.len())?;
+    }
     socket.send(buf.as_slice())?;
+    Ok(())
+}
 
+/// Wait for the kernel to process a batch of `msgs` messages sent by
+/// [`nft_send_batch`].
+///
+/// `error` turns an error answer into the error returned, given the
+/// sequence number of the message it is for and the error number.
+async fn nft_batch_answer(
+    socket: &NetlinkSocket,
+    msgs: u32,
+    error: impl FnOnce(u32, i32, &[u8]) -> RipsetError,
+) -> Result<()> {
     let mut recv_buf = [0u8; BUFF_SZ];
     loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
-
+        let recv_len = socket.recv_async(&mut recv_buf).await?;
         if recv_len < NlMsgHdr::SIZE {
-            return Err(IpSetError::ProtocolError);
+            return Err(RipsetError::ProtocolError);
         }
+        // The sequence number tells which message an answer is for
+        let hdr: NlMsgHdr =
+            unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+        match parse_nlmsg_error(&recv_buf[..recv_len]) {
+            Some(0) if hdr.nlmsg_seq == msgs => return Ok(()),
+            Some(0) | None => {}
+            Some(errno) => return Err(error(hdr.nlmsg_seq, -errno, &recv_buf[..recv_len])),
+        }
+    }
+}
 
-        if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-            if error == 0 {
-                // Continue
-            } else if -error == libc::ENOENT {
-                return Err(IpSetError::SetNotFound(table.to_string()));
-            } else {
-                return Err(IpSetError::NetlinkError(-error));
-            }
+/// Add or delete elements of a set over `socket`, in a batch of their own
+/// built in the buffer of the thread.
+async fn nftset_commit_elements(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    cmd: u16,
+    table: &str,
+    setname: &str,
+    elements: &[NftElement],
+) -> Result<()> {
+    let msgs = with_msg_buffer(|buf| -> Result<u32> {
+        put_batch_begin(buf, None);
+        let (mut last_msg, mut msgs) = (0, 0);
+        for chunk in elements.chunks(NFT_MAX_ELEMS_PER_MSG) {
+            last_msg = buf.len();
+            msgs += 1;
+            put_setelem_msg(buf, family.nfproto(), cmd, msgs, table, setname, chunk)?;
+        }
+        if msgs > 0 {
+            nft_send_batch(socket, buf, last_msg, msgs, true)?;
         }
+        Ok(msgs)
+    })?;
+    if msgs == 0 {
+        return Ok(());
+    }
 
-        if is_nlmsg_done(&recv_buf[..recv_len]) {
-            break;
+    nft_batch_answer(socket, msgs, |_, error, msg| {
+        let op = NftOp {
+            kind: NftOpKind::elements(cmd),
+            family,
+            table: table.to_string(),
+            name: setname.to_string(),
+        };
+        match error {
+            libc::EPERM => nft_owner_error(&op, socket),
+            error => op.error(error, msg),
         }
+    })
+    .await
+}
 
-        if get_nlmsg_type(&recv_buf[..recv_len]) == Some(crate::netlink::NLMSG_ERROR) {
-            break;
+/// Explain an `EPERM` for `op`: unless the caller lacks `CAP_NET_ADMIN`,
+/// another socket owns the table.
+fn nft_owner_error(op: &NftOp, socket: &NetlinkSocket) -> RipsetError {
+    let portid = socket.portid().ok();
+    match nftset_table_owner(op.family, &op.table) {
+        Ok(Some(owner)) if Some(owner) != portid => {
+            RipsetError::TableOwned(op.table.clone(), owner)
         }
+        _ => RipsetError::PermissionDenied,
     }
+}
 
+fn validate_table_name(table: &str) -> Result<()> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
     Ok(())
 }
 
-/// Create an nftables set.
+fn validate_set_name(setname: &str) -> Result<()> {
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+    Ok(())
+}
+
+/// Get the generation ID of the ruleset, which changes with every commit.
+async fn nft_genid() -> Result<u32> {
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETGEN), NLM_F_REQUEST | NLM_F_ACK, 0);
+    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, 0);
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
+
+    if recv_len < NlMsgHdr::SIZE + NfGenMsg::SIZE {
+        return Err(RipsetError::ProtocolError);
+    }
+    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
+        return Err(errno_error(-error, &recv_buf[..recv_len]));
+    }
+    if get_nlmsg_type(&recv_buf[..recv_len]) != Some(nft_msg_type(NFT_MSG_NEWGEN)) {
+        return Err(RipsetError::ProtocolError);
+    }
+
+    let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+    let msg_end = (hdr.nlmsg_len as usize).min(recv_len);
+    NlAttrIter::new(&recv_buf[NlMsgHdr::SIZE + NfGenMsg::SIZE..msg_end])
+        .find(|&(attr_type, _)| attr_type == NFTA_GEN_ID)
+        .and_then(|(_, payload)| nla_get_u32_be(payload))
+        .ok_or(RipsetError::ProtocolError)
+}
+
+/// Run `op` again while it fails because the ruleset changed under it: a dump
+/// interrupted by a commit (`EINTR`), a batch built for an older generation
+/// (`ERESTART`) or one the kernel asks to be replayed (`EAGAIN`).
+async fn nft_retry<T, F>(mut op: impl FnMut() -> F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let mut attempts = 1;
+    loop {
+        match op().await {
+            Err(e)
+                if matches!(e.errno(), Some(libc::EINTR | libc::ERESTART | libc::EAGAIN))
+                    && attempts < NFT_MAX_RETRIES =>
+            {
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// [`nft_retry`] for dumps, which are also made again after their socket
+/// overflowed (`ENOBUFS`).
+async fn nft_dump_retry<T, F>(op: impl FnMut() -> F + Copy) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    nft_retry(move || dump_retry_async(op)).await
+}
+
+/// Create an nftables table.
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
-/// * `table` - The table name
-/// * `setname` - The set name to create
-/// * `options` - Creation options (type, timeout, etc.)
+/// * `family` - The nftables address family
+/// * `table` - The table name to create
 ///
 /// # Example
 ///
 /// ```no_run
-/// use ruhop_ipset::nftset::{nftset_create_set, NftSetCreateOptions, NftSetType};
+/// use ripset::NftFamily;
+/// use ripset::nftset::nftset_create_table;
 ///
-/// let opts = NftSetCreateOptions {
-///     set_type: NftSetType::Ipv4Addr,
-///     timeout: Some(300),
-///     ..Default::default()
-/// };
-/// nftset_create_set("inet", "filter", "myset", &opts).unwrap();
+/// nftset_create_table(NftFamily::Inet, "mytable").unwrap();
 /// ```
-pub fn nftset_create_set(
-    family: &str,
-    table: &str,
-    setname: &str,
-    options: &NftSetCreateOptions,
-) -> Result<()> {
-    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidTableName(table.to_string()));
-    }
-    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
-    }
+pub fn nftset_create_table(family: NftFamily, table: &str) -> Result<()> {
+    block_on(nftset_create_table_async(family, table))
+}
 
-    let nf_family = parse_nf_family(family)?;
+/// [`nftset_create_table`] for async code.
+pub(crate) async fn nftset_create_table_async(family: NftFamily, table: &str) -> Result<()> {
+    let mut tx = NftTransaction::new();
+    tx.create_table(family, table)?;
+    tx.commit_async().await
+}
 
-    let mut buf = MsgBuffer::new(BUFF_SZ);
+/// Make sure an nftables table exists, creating it if it is missing.
+///
+/// Tables have no options to compare: an existing table is left as it is,
+/// sets and chains included. A table owned by another process fails with
+/// [`RipsetError::TableOwned`].
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_ensure_table};
+///
+/// nftset_ensure_table(NftFamily::Inet, "mytable").unwrap();
+/// ```
+pub fn nftset_ensure_table(family: NftFamily, table: &str) -> Result<()> {
+    block_on(nftset_ensure_table_async(family, table))
+}
 
-    // Batch begin
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_BEGIN, NLM_F_REQUEST, 0);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg();
+/// [`nftset_ensure_table`] for async code.
+pub(crate) async fn nftset_ensure_table_async(family: NftFamily, table: &str) -> Result<()> {
+    // Without NLM_F_EXCL the kernel accepts an existing table
+    nftset_create_table_async(family, table).await
+}
 
-    let msg_start = buf.len();
+/// Delete an nftables table.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name to delete
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::NftFamily;
+/// use ripset::nftset::nftset_delete_table;
+///
+/// nftset_delete_table(NftFamily::Inet, "mytable").unwrap();
+/// ```
+pub fn nftset_delete_table(family: NftFamily, table: &str) -> Result<()> {
+    block_on(nftset_delete_table_async(family, table))
+}
 
-    // Create set message
-    buf.put_nlmsghdr(
-        nft_msg_type(NFT_MSG_NEWSET),
-        NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE,
-        1,
-    );
-    buf.put_nfgenmsg(nf_family, 0, 0);
+/// [`nftset_delete_table`] for async code.
+pub(crate) async fn nftset_delete_table_async(family: NftFamily, table: &str) -> Result<()> {
+    let mut tx = NftTransaction::new();
+    tx.delete_table(family, table)?;
+    tx.commit_async().await
+}
 
-    buf.put_attr_str(NFTA_SET_TABLE, table);
-    buf.put_attr_str(NFTA_SET_NAME, setname);
+/// An nftables table owned by this process, see [`nftset_create_table_owned`].
+///
+/// The kernel refuses changes to the table from any other netlink socket, so
+/// they must be committed through [`commit`](NftOwnedTable::commit). Dumps,
+/// such as [`nftset_list`] or [`nftset_test`], still work from anywhere.
+///
+/// Dropping the handle closes its socket, and the kernel deletes the table
+/// along with its chains, rules and sets.
+pub struct NftOwnedTable {
+    socket: NetlinkSocket,
+    family: NftFamily,
+    name: String,
+}
 
-    // Set flags - nftables uses big-endian u32 without NLA_F_NET_BYTEORDER flag
-    let mut flags = options.flags.unwrap_or(0);
-    if options.timeout.is_some() {
-        flags |= NFT_SET_TIMEOUT;
+impl NftOwnedTable {
+    /// The address family of the table.
+    pub fn family(&self) -> NftFamily {
+        self.family
     }
-    buf.put_attr_u32_nft(NFTA_SET_FLAGS, flags);
 
-    // Key type and length - also big-endian without NLA_F_NET_BYTEORDER
-    buf.put_attr_u32_nft(NFTA_SET_KEY_TYPE, options.set_type.key_type());
-    buf.put_attr_u32_nft(NFTA_SET_KEY_LEN, options.set_type.key_len());
+    /// The table name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 
-    // Set ID for transaction tracking (required by kernel)
-    buf.put_attr_u32_nft(NFTA_SET_ID, next_set_id());
+    /// The netlink port ID that owns the table, as reported by
+    /// [`nftset_table_owner`].
+    pub fn owner(&self) -> Result<u32> {
+        Ok(self.socket.portid()?)
+    }
 
-    // Timeout (if specified, in milliseconds)
-    if let Some(timeout) = options.timeout {
-        buf.put_attr_u64_nft(NFTA_SET_TIMEOUT, (timeout as u64) * 1000);
+    /// Send the changes queued in `tx` as the owner of the table.
+    ///
+    /// Changes to other tables are allowed too, as long as they aren't owned
+    /// by someone else.
+    pub fn commit(&self, tx: NftTransaction) -> Result<()> {
+        self.socket.drain()?;
+        block_on(tx.commit_on(&self.socket))
     }
+}
 
-    buf.finalize_nlmsg_at(msg_start);
+/// Create an nftables table that only this process can modify.
+///
+/// The table gets the nftables owner flag: until the returned handle is
+/// dropped, other processes (including `nft`) can list its contents but
+/// fail to modify or delete it, with [`RipsetError::TableOwned`] when they
+/// use this library. Changes from this process go through
+/// [`NftOwnedTable::commit`].
+///
+/// Fails with [`RipsetError::EntryExists`] if the table already exists,
+/// since the flag can't be added to an existing table.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name to create
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, NftSetCreateOptions, NftTransaction, nftset_create_table_owned};
+///
+/// let table = nftset_create_table_owned(NftFamily::Inet, "mytable").unwrap();
+/// let mut tx = NftTransaction::new();
+/// tx.create_set(NftFamily::Inet, "mytable", "myset", &NftSetCreateOptions::default())
+///     .unwrap();
+/// table.commit(tx).unwrap();
+/// ```
+pub fn nftset_create_table_owned(family: NftFamily, table: &str) -> Result<NftOwnedTable> {
+    block_on(nftset_create_table_owned_async(family, table))
+}
 
-    // Batch end
-    let end_start = buf.len();
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_END, NLM_F_REQUEST, 2);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg_at(end_start);
+/// [`nftset_create_table_owned`] for async code. The socket of the table
+/// is made blocking again for [`NftOwnedTable::commit`].
+pub(crate) async fn nftset_create_table_owned_async(
+    family: NftFamily,
+    table: &str,
+) -> Result<NftOwnedTable> {
+    let mut socket = NetlinkSocket::new()?;
+    let mut tx = NftTransaction::new();
+    tx.put_newtable(family, table, NLM_F_CREATE | NLM_F_EXCL, NFT_TABLE_F_OWNER)?;
+    tx.commit_on(&socket).await?;
+    socket.deregister()?;
+    Ok(NftOwnedTable {
+        socket,
+        family,
+        name: table.to_string(),
+    })
+}
 
-    let socket = NetlinkSocket::new()?;
-    socket.send(buf.as_slice())?;
+/// Get the netlink port ID of the process owning a table.
+///
+/// # Returns
+///
+/// `None` if the table was created without the owner flag, for example by
+/// [`nftset_create_table`] or `nft`.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_table_owner};
+///
+/// if let Some(owner) = nftset_table_owner(NftFamily::Inet, "mytable").unwrap() {
+///     println!("mytable is owned by netlink port {}", owner);
+/// }
+/// ```
+pub fn nftset_table_owner(family: NftFamily, table: &str) -> Result<Option<u32>> {
+    block_on(nftset_table_owner_async(family, table))
+}
 
-    let mut recv_buf = [0u8; BUFF_SZ];
-    loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
+/// [`nftset_table_owner`] for async code.
+pub(crate) async fn nftset_table_owner_async(
+    family: NftFamily,
+    table: &str,
+) -> Result<Option<u32>> {
+    validate_table_name(table)?;
+    let nf_family = family.nfproto();
 
-        if recv_len < NlMsgHdr::SIZE {
-            return Err(IpSetError::ProtocolError);
-        }
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETTABLE), NLM_F_REQUEST | NLM_F_ACK, 0);
+    buf.put_nfgenmsg(nf_family, 0, 0);
+    buf.put_attr_str(NFTA_TABLE_NAME, table);
+    buf.finalize_nlmsg();
 
-        if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-            if error == 0 {
-                // Continue
-            } else if -error == libc::EEXIST {
-                return Err(IpSetError::ElementExists);
-            } else if -error == libc::ENOENT {
-                return Err(IpSetError::SetNotFound(table.to_string()));
-            } else {
-                return Err(IpSetError::NetlinkError(-error));
-            }
-        }
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
 
-        if is_nlmsg_done(&recv_buf[..recv_len]) {
-            break;
-        }
+    if recv_len < NlMsgHdr::SIZE + NfGenMsg::SIZE {
+        return Err(RipsetError::ProtocolError);
+    }
+    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
+        return match -error {
+            libc::ENOENT => Err(RipsetError::SetNotFound(table.to_string())),
+            error => Err(errno_error(error, &recv_buf[..recv_len])),
+        };
+    }
+    if get_nlmsg_type(&recv_buf[..recv_len]) != Some(nft_msg_type(NFT_MSG_NEWTABLE)) {
+        return Err(RipsetError::ProtocolError);
+    }
 
-        if get_nlmsg_type(&recv_buf[..recv_len]) == Some(crate::netlink::NLMSG_ERROR) {
-            break;
+    let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+    let msg_end = (hdr.nlmsg_len as usize).min(recv_len);
+    Ok(parse_nftset_table_owner(
+        &recv_buf[NlMsgHdr::SIZE + NfGenMsg::SIZE..msg_end],
+    ))
+}
+
+/// Parse the owner of a table from a NEWTABLE message, if it has one.
+fn parse_nftset_table_owner(data: &[u8]) -> Option<u32> {
+    let mut flags = 0;
+    let mut owner = None;
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_TABLE_FLAGS => flags = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_TABLE_OWNER => owner = nla_get_u32_be(payload),
+            _ => {}
         }
     }
+    owner.filter(|_| flags & NFT_TABLE_F_OWNER != 0)
+}
 
-    Ok(())
+/// Create an nftables set.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name to create
+/// * `options` - Creation options (type, timeout, etc.)
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::NftFamily;
+/// use ripset::nftset::{nftset_create_set, NftSetCreateOptions, NftSetType};
+///
+/// let opts = NftSetCreateOptions {
+///     set_type: NftSetType::Ipv4Addr,
+///     timeout: Some(300),
+///     ..Default::default()
+/// };
+/// nftset_create_set(NftFamily::Inet, "filter", "myset", &opts).unwrap();
+/// ```
+pub fn nftset_create_set(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Result<()> {
+    block_on(nftset_create_set_async(family, table, setname, options))
 }
 
-/// Delete an nftables set.
+/// [`nftset_create_set`] for async code.
+pub(crate) async fn nftset_create_set_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Result<()> {
+    let mut tx = NftTransaction::new();
+    tx.create_set(family, table, setname, options)?;
+    tx.commit_async().await
+}
+
+/// Make sure an nftables set exists with the given options.
+///
+/// The set is created if it is missing. An existing set is left as it is,
+/// elements included, if its key type, value type, interval flag, default
+/// timeout and counters match `options`; otherwise
+/// [`RipsetError::SetMismatch`] names the first property that differs.
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
+/// * `family` - The nftables address family
 /// * `table` - The table name
-/// * `setname` - The set name to delete
+/// * `setname` - The set name
+/// * `options` - Creation options (type, timeout, etc.)
 ///
 /// # Example
 ///
 /// ```no_run
-/// use ruhop_ipset::nftset::nftset_delete_set;
+/// use ripset::{NftFamily, NftSetCreateOptions, nftset_ensure_set};
 ///
-/// nftset_delete_set("inet", "filter", "myset").unwrap();
+/// let opts = NftSetCreateOptions {
+///     timeout: Some(300),
+///     ..Default::default()
+/// };
+/// nftset_ensure_set(NftFamily::Inet, "filter", "recently_seen", &opts).unwrap();
 /// ```
-pub fn nftset_delete_set(family: &str, table: &str, setname: &str) -> Result<()> {
-    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidTableName(table.to_string()));
-    }
-    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
-    }
+pub fn nftset_ensure_set(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Result<()> {
+    block_on(nftset_ensure_set_async(family, table, setname, options))
+}
 
-    let nf_family = parse_nf_family(family)?;
+/// [`nftset_ensure_set`] for async code.
+pub(crate) async fn nftset_ensure_set_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Result<()> {
+    let info = match nftset_info_async(family, table, setname).await {
+        Ok(info) => info,
+        Err(RipsetError::SetNotFound(_)) => {
+            return nftset_create_set_async(family, table, setname, options).await;
+        }
+        Err(e) => return Err(e),
+    };
 
-    let mut buf = MsgBuffer::new(BUFF_SZ);
+    let mismatch = if info.set_type.as_ref() != Some(&options.set_type) {
+        Some("type")
+    } else if info.data_type != options.data_type {
+        Some("value type")
+    } else if info.interval != options.interval {
+        Some("interval flag")
+    } else if info.timeout != options.timeout {
+        Some("timeout")
+    } else if info.counters != options.counters {
+        Some("counters option")
+    } else {
+        None
+    };
+    match mismatch {
+        Some(property) => Err(RipsetError::SetMismatch(
+            setname.to_string(),
+            property.to_string(),
+        )),
+        None => Ok(()),
+    }
+}
 
-    // Batch begin
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_BEGIN, NLM_F_REQUEST, 0);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg();
+/// Ask the kernel which optional features nftables sets support.
+///
+/// Each feature is probed by having the kernel check the creation of a set
+/// using it in a scratch table, like `nft --check`: nothing is created.
+/// Swapping is done in a single transaction and always available, while
+/// element comments are not supported by this library.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family to probe
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_capabilities};
+///
+/// let caps = nftset_capabilities(NftFamily::Inet).unwrap();
+/// println!("timeouts: {}, counters: {}", caps.timeouts, caps.counters);
+/// ```
+pub fn nftset_capabilities(family: NftFamily) -> Result<BackendCapabilities> {
+    block_on(nftset_capabilities_async(family))
+}
 
-    let msg_start = buf.len();
+/// [`nftset_capabilities`] for async code.
+pub(crate) async fn nftset_capabilities_async(family: NftFamily) -> Result<BackendCapabilities> {
+    let probe = async |options: NftSetCreateOptions| {
+        let mut tx = NftTransaction::new();
+        tx.create_table(family, NFT_PROBE_TABLE)?;
+        tx.create_set(family, NFT_PROBE_TABLE, "probe", &options)?;
+        tx.check().await
+    };
+    // Plain sets must work for anything else to matter
+    probe(NftSetCreateOptions::default()).await?;
+    let supports = async |options| probe(options).await.is_ok();
+
+    Ok(BackendCapabilities {
+        swap: true,
+        timeouts: supports(NftSetCreateOptions {
+            timeout: Some(60),
+            ..Default::default()
+        })
+        .await,
+        counters: supports(NftSetCreateOptions {
+            counters: true,
+            ..Default::default()
+        })
+        .await,
+        intervals: supports(NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        })
+        .await,
+        comments: false,
+        mac_keys: supports(NftSetCreateOptions {
+            set_type: NftSetType::EtherAddr,
+            ..Default::default()
+        })
+        .await,
+    })
+}
 
-    // Delete set message
-    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_DELSET), NLM_F_REQUEST | NLM_F_ACK, 1);
-    buf.put_nfgenmsg(nf_family, 0, 0);
+/// Check the combination of set creation options.
+fn validate_create_options(options: &NftSetCreateOptions) -> Result<()> {
+    let has_verdict = match &options.set_type {
+        NftSetType::Concat(fields) => fields.contains(&NftSetType::Verdict),
+        set_type => *set_type == NftSetType::Verdict,
+    };
+    if has_verdict {
+        return Err(RipsetError::InvalidOption(
+            "verdicts can only be values of maps".to_string(),
+        ));
+    }
+    if options.auto_merge && !options.interval {
+        return Err(RipsetError::InvalidOption(
+            "auto-merge requires an interval set".to_string(),
+        ));
+    }
+    if let Some(comment) = &options.comment {
+        // The userdata length is a single byte, including the terminating NUL
+        if comment.len() >= NFT_COMMENT_MAXLEN || comment.contains('\0') {
+            return Err(RipsetError::InvalidOption(format!(
+                "invalid set comment: {comment:?}"
+            )));
+        }
+    }
+    if let NftSetType::Concat(fields) = &options.set_type {
+        // The key type packs each field into 6 bits of a u32
+        if !(2..=5).contains(&fields.len())
+            || fields.iter().any(|f| matches!(f, NftSetType::Concat(_)))
+        {
+            return Err(RipsetError::InvalidOption(
+                "concatenations need 2 to 5 non-concatenated fields".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
 
+/// Write the attributes of a NEWSET message.
+fn put_newset_attrs(
+    buf: &mut MsgBuffer,
+    table: &str,
+    setname: &str,
+    options: &NftSetCreateOptions,
+) -> Result<()> {
     buf.put_attr_str(NFTA_SET_TABLE, table);
     buf.put_attr_str(NFTA_SET_NAME, setname);
 
-    buf.finalize_nlmsg_at(msg_start);
+    // Set flags - nftables uses big-endian u32 without NLA_F_NET_BYTEORDER flag
+    let mut flags = options.flags.unwrap_or(0);
+    if options.timeout.is_some() || options.gc_interval.is_some() {
+        flags |= NFT_SET_TIMEOUT;
+    }
+    if options.interval {
+        flags |= NFT_SET_INTERVAL;
+    }
+    if matches!(options.set_type, NftSetType::Concat(_)) {
+        flags |= NFT_SET_CONCAT;
+    }
+    if options.data_type.is_some() {
+        flags |= NFT_SET_MAP;
+    }
+    if options.dynamic {
+        flags |= NFT_SET_EVAL;
+    }
+    if options.constant {
+        flags |= NFT_SET_CONSTANT;
+    }
+    buf.put_attr_u32_nft(NFTA_SET_FLAGS, flags);
 
-    // Batch end
-    let end_start = buf.len();
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_END, NLM_F_REQUEST, 2);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg_at(end_start);
+    // Key type and length - also big-endian without NLA_F_NET_BYTEORDER
+    buf.put_attr_u32_nft(NFTA_SET_KEY_TYPE, options.set_type.key_type());
+    buf.put_attr_u32_nft(NFTA_SET_KEY_LEN, options.set_type.key_len());
 
-    let socket = NetlinkSocket::new()?;
-    socket.send(buf.as_slice())?;
+    // Value type and length of maps
+    if let Some(data_type) = &options.data_type {
+        buf.put_attr_u32_nft(NFTA_SET_DATA_TYPE, data_type.key_type());
+        buf.put_attr_u32_nft(NFTA_SET_DATA_LEN, data_type.key_len());
+    }
 
-    let mut recv_buf = [0u8; BUFF_SZ];
-    loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
+    // Set ID for transaction tracking (required by kernel)
+    buf.put_attr_u32_nft(NFTA_SET_ID, next_set_id());
 
-        if recv_len < NlMsgHdr::SIZE {
-            return Err(IpSetError::ProtocolError);
-        }
+    // Timeout (if specified, in milliseconds)
+    if let Some(timeout) = options.timeout {
+        buf.put_attr_u64_nft(NFTA_SET_TIMEOUT, (timeout as u64) * 1000);
+    }
 
-        if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-            if error == 0 {
-                // Continue
-            } else if -error == libc::ENOENT {
-                return Err(IpSetError::SetNotFound(setname.to_string()));
-            } else {
-                return Err(IpSetError::NetlinkError(-error));
-            }
-        }
+    // GC interval (if specified, in milliseconds)
+    if let Some(gc_interval) = options.gc_interval {
+        let gc_interval = gc_interval.checked_mul(1000).ok_or_else(|| {
+            RipsetError::InvalidOption(format!("gc-interval too large: {gc_interval}"))
+        })?;
+        buf.put_attr_u32_nft(NFTA_SET_GC_INTERVAL, gc_interval);
+    }
 
-        if is_nlmsg_done(&recv_buf[..recv_len]) {
-            break;
-        }
+    // nft keeps the auto-merge setting and the comment in the set userdata
+    let mut udata = Vec::new();
+    if options.auto_merge {
+        udata.extend([NFTNL_UDATA_SET_MERGE_ELEMENTS, 4]);
+        udata.extend(1u32.to_ne_bytes());
+    }
+    if let Some(comment) = &options.comment {
+        udata.extend([NFTNL_UDATA_SET_COMMENT, comment.len() as u8 + 1]);
+        udata.extend(comment.as_bytes());
+        udata.push(0);
+    }
+    if !udata.is_empty() {
+        buf.put_attr_bytes(NFTA_SET_USERDATA, &udata);
+    }
 
-        if get_nlmsg_type(&recv_buf[..recv_len]) == Some(crate::netlink::NLMSG_ERROR) {
-            break;
+    if let Some(policy) = options.policy {
+        buf.put_attr_u32_nft(NFTA_SET_POLICY, policy.as_u32());
+    }
+
+    // Elements get a copy of the set's counter expression
+    if options.counters {
+        let expr = buf.start_nested(NFTA_SET_EXPR);
+        buf.put_attr_str(NFTA_EXPR_NAME, "counter");
+        buf.end_nested(expr);
+    }
+
+    // The description holds the maximum size and, for concatenations, the
+    // length of each field
+    let concat_fields = match &options.set_type {
+        NftSetType::Concat(fields) => Some(fields),
+        _ => None,
+    };
+    if options.size.is_some() || concat_fields.is_some() {
+        let desc = buf.start_nested(NFTA_SET_DESC);
+        if let Some(size) = options.size {
+            buf.put_attr_u32_nft(NFTA_SET_DESC_SIZE, size);
         }
+        if let Some(fields) = concat_fields {
+            let concat = buf.start_nested(NFTA_SET_DESC_CONCAT);
+            for field in fields {
+                let elem = buf.start_nested(NFTA_LIST_ELEM);
+                buf.put_attr_u32_nft(NFTA_SET_FIELD_LEN, field.key_len());
+                buf.end_nested(elem);
+            }
+            buf.end_nested(concat);
+        }
+        buf.end_nested(desc);
     }
 
+    for (attr_type, payload) in &options.extra_attrs {
+        buf.put_attr_bytes(*attr_type, payload);
+    }
     Ok(())
 }
 
-/// Get the flags of an nftables set.
-fn nftset_get_flags(family: &str, table: &str, setname: &str) -> Result<u32> {
-    let nf_family = parse_nf_family(family)?;
-
-    // Build the GETSET message
-    let mut buf = MsgBuffer::new(BUFF_SZ);
-
+/// Delete an nftables set.
+///
+/// Returns [`RipsetError::SetInUse`] naming the rules that still use the set,
+/// see [`nftset_references`].
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name to delete
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::NftFamily;
+/// use ripset::nftset::nftset_delete_set;
+///
+/// nftset_delete_set(NftFamily::Inet, "filter", "myset").unwrap();
+/// ```
+pub fn nftset_delete_set(family: NftFamily, table: &str, setname: &str) -> Result<()> {
+    block_on(nftset_delete_set_async(family, table, setname))
+}
+
+/// [`nftset_delete_set`] for async code.
+pub(crate) async fn nftset_delete_set_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<()> {
+    let mut tx = NftTransaction::new();
+    tx.delete_set(family, table, setname)?;
+    match tx.commit_async().await {
+        // Say which rules keep the set in use
+        Err(e) if e.errno() == Some(libc::EBUSY) => {
+            match nftset_references_async(family, table, setname).await {
+                Ok(rules) if !rules.is_empty() => {
+                    let rules: Vec<_> = rules.iter().map(ToString::to_string).collect();
+                    Err(RipsetError::SetInUse(setname.to_string(), rules.join(", ")))
+                }
+                _ => Err(e),
+            }
+        }
+        result => result,
+    }
+}
+
+/// Remove all elements from an nftables set.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name to flush
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_flush};
+///
+/// nftset_flush(NftFamily::Inet, "filter", "myset").unwrap();
+/// ```
+pub fn nftset_flush(family: NftFamily, table: &str, setname: &str) -> Result<()> {
+    block_on(nftset_flush_async(family, table, setname))
+}
+
+/// [`nftset_flush`] for async code.
+pub(crate) async fn nftset_flush_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<()> {
+    let mut tx = NftTransaction::new();
+    tx.flush_set(family, table, setname)?;
+    tx.commit_async().await
+}
+
+/// Empty an nftables table without deleting it.
+///
+/// All rules are removed from the chains of the table and all elements from
+/// its sets, in one transaction. The chains and sets themselves are kept, so
+/// unlike deleting and recreating the table, references to them from
+/// elsewhere stay valid.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_flush_table};
+///
+/// nftset_flush_table(NftFamily::Inet, "filter").unwrap();
+/// ```
+pub fn nftset_flush_table(family: NftFamily, table: &str) -> Result<()> {
+    block_on(nftset_flush_table_async(family, table))
+}
+
+/// [`nftset_flush_table`] for async code.
+pub(crate) async fn nftset_flush_table_async(family: NftFamily, table: &str) -> Result<()> {
+    // Sets created after they were listed would be left out
+    nft_retry(move || async move {
+        let mut tx = NftTransaction::with_genid(nft_genid().await?);
+        tx.flush_table_async(family, table).await?;
+        tx.commit_async().await
+    })
+    .await
+}
+
+/// Exchange the elements of two nftables sets atomically.
+///
+/// nftables has no equivalent of `ipset swap`, so both sets are flushed and
+/// refilled with each other's elements in one transaction: rules matching
+/// against either set never see a partial state. The sets keep their names
+/// and properties, and must have the same key and value types. Element
+/// counters restart from zero.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table holding both sets
+/// * `setname1` - The first set
+/// * `setname2` - The second set
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_swap};
+///
+/// // Replace the live blocklist with the freshly filled one
+/// nftset_swap(NftFamily::Inet, "filter", "blocklist", "blocklist_new").unwrap();
+/// ```
+pub fn nftset_swap(family: NftFamily, table: &str, setname1: &str, setname2: &str) -> Result<()> {
+    block_on(nftset_swap_async(family, table, setname1, setname2))
+}
+
+/// [`nftset_swap`] for async code.
+pub(crate) async fn nftset_swap_async(
+    family: NftFamily,
+    table: &str,
+    setname1: &str,
+    setname2: &str,
+) -> Result<()> {
+    validate_table_name(table)?;
+    validate_set_name(setname1)?;
+    validate_set_name(setname2)?;
+
+    // Start over if the sets change between the dumps and the commit
+    nft_retry(move || async move {
+        let genid = nft_genid().await?;
+        let (set1, elements1) = nftset_dump_elements(family, table, setname1).await?;
+        let (set2, elements2) = nftset_dump_elements(family, table, setname2).await?;
+        let layout = |set: &NftSetInfo| {
+            (
+                set.key_type,
+                set.key_len,
+                set.interval,
+                set.map,
+                set.data_type.clone(),
+            )
+        };
+        if layout(&set1) != layout(&set2) {
+            return Err(RipsetError::TypeMismatch(format!(
+                "sets {setname1} and {setname2} have different types"
+            )));
+        }
+
+        let mut tx = NftTransaction::with_genid(genid);
+        tx.flush_set(family, table, setname1)?;
+        tx.flush_set(family, table, setname2)?;
+        tx.put_elements(family, NFT_MSG_NEWSETELEM, table, setname1, &elements2)?;
+        tx.put_elements(family, NFT_MSG_NEWSETELEM, table, setname2, &elements1)?;
+        tx.commit_async().await
+    })
+    .await
+}
+
+/// Rename an nftables set.
+///
+/// nftables can't rename sets, so a set with the new name and the same
+/// properties is created, the elements are copied over and the old set is
+/// deleted, all in one transaction. Nothing else is updated: if a rule still
+/// references the old set, it can't be deleted and the whole rename fails,
+/// leaving both names as they were. Element counters restart from zero.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table holding the set
+/// * `setname` - The current set name
+/// * `newname` - The new set name, which must not exist yet
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_rename};
+///
+/// nftset_rename(NftFamily::Inet, "filter", "blocklist_new", "blocklist").unwrap();
+/// ```
+pub fn nftset_rename(family: NftFamily, table: &str, setname: &str, newname: &str) -> Result<()> {
+    block_on(nftset_rename_async(family, table, setname, newname))
+}
+
+/// [`nftset_rename`] for async code.
+pub(crate) async fn nftset_rename_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    newname: &str,
+) -> Result<()> {
+    validate_table_name(table)?;
+    validate_set_name(setname)?;
+    validate_set_name(newname)?;
+
+    nft_retry(move || async move {
+        let genid = nft_genid().await?;
+        let (set, elements) = nftset_dump_elements(family, table, setname).await?;
+        let options = set.create_options()?;
+
+        // Without NLM_F_EXCL an existing set with the new name would be reused
+        let mut tx = NftTransaction::with_genid(genid);
+        tx.put_newset(family, table, newname, &options, NLM_F_CREATE | NLM_F_EXCL)?;
+        tx.put_elements(family, NFT_MSG_NEWSETELEM, table, newname, &elements)?;
+        tx.delete_set(family, table, setname)?;
+        tx.commit_async().await
+    })
+    .await
+}
+
+/// Check whether an nftables set exists.
+///
+/// A missing table is reported as a missing set.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_exists};
+///
+/// if !nftset_exists(NftFamily::Inet, "filter", "myset").unwrap() {
+///     println!("myset is missing");
+/// }
+/// ```
+pub fn nftset_exists(family: NftFamily, table: &str, setname: &str) -> Result<bool> {
+    block_on(nftset_exists_async(family, table, setname))
+}
+
+/// [`nftset_exists`] for async code.
+pub(crate) async fn nftset_exists_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<bool> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    match nftset_get_flags(family, table, setname).await {
+        Ok(_) => Ok(true),
+        Err(e) if e.errno() == Some(libc::ENOENT) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Lookup strategy of an nftables set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum NftSetPolicy {
+    /// Prefer lookup speed
+    Performance,
+    /// Prefer a small memory footprint
+    Memory,
+}
+
+impl NftSetPolicy {
+    fn as_u32(&self) -> u32 {
+        match self {
+            NftSetPolicy::Performance => 0,
+            NftSetPolicy::Memory => 1,
+        }
+    }
+
+    fn from_u32(policy: u32) -> Option<Self> {
+        match policy {
+            0 => Some(NftSetPolicy::Performance),
+            1 => Some(NftSetPolicy::Memory),
+            _ => None,
+        }
+    }
+}
+
+/// Properties of an nftables set
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NftSetInfo {
+    pub name: String,
+    /// Key type, if it is one of the types known to this library
+    pub set_type: Option<NftSetType>,
+    /// Raw nftables key type identifier
+    pub key_type: u32,
+    /// Key length in bytes
+    pub key_len: u32,
+    /// The set is a map (`map` flag)
+    pub map: bool,
+    /// Value type of a map, if it is one of the types known to this library
+    pub data_type: Option<NftSetType>,
+    /// Raw set flags (`NFT_SET_*`)
+    pub flags: u32,
+    /// The set stores ranges (`interval` flag)
+    pub interval: bool,
+    /// Adjacent and overlapping ranges are merged on insertion
+    pub auto_merge: bool,
+    /// Elements can expire (`timeout` flag)
+    pub with_timeout: bool,
+    /// The set is updated from the packet path (`dynamic` flag)
+    pub dynamic: bool,
+    /// The set cannot be changed after creation (`constant` flag)
+    pub constant: bool,
+    /// Default element timeout in seconds
+    pub timeout: Option<u32>,
+    /// Garbage collection interval in seconds
+    pub gc_interval: Option<u32>,
+    /// Lookup policy, if one was requested
+    pub policy: Option<NftSetPolicy>,
+    /// Maximum number of elements
+    pub size: Option<u32>,
+    /// Number of elements in the set (ranges count once)
+    pub entries: u32,
+    /// Set comment
+    pub comment: Option<String>,
+    /// Elements keep packet and byte counters
+    pub counters: bool,
+}
+
+impl NftSetInfo {
+    /// Options that create a set with the same definition, e.g. to recreate
+    /// it elsewhere.
+    ///
+    /// Fails for sets whose key or value type is not known to this library.
+    pub fn create_options(&self) -> Result<NftSetCreateOptions> {
+        let unsupported =
+            || RipsetError::InvalidOption(format!("unsupported type of set {}", self.name));
+        let set_type = self.set_type.clone().ok_or_else(unsupported)?;
+        let data_type = match (self.map, &self.data_type) {
+            (true, None) => return Err(unsupported()),
+            (_, data_type) => data_type.clone(),
+        };
+        Ok(NftSetCreateOptions {
+            set_type,
+            timeout: self.timeout,
+            flags: Some(self.flags),
+            interval: self.interval,
+            auto_merge: self.auto_merge,
+            data_type,
+            policy: self.policy,
+            size: self.size,
+            gc_interval: self.gc_interval,
+            dynamic: self.dynamic,
+            constant: self.constant,
+            comment: self.comment.clone(),
+            counters: self.counters,
+            extra_attrs: Vec::new(),
+        })
+    }
+}
+
+/// Query the properties of an nftables set.
+///
+/// The element count is taken from a dump of the set, as nftables doesn't
+/// report it with the set itself.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_info};
+///
+/// let info = nftset_info(NftFamily::Inet, "filter", "myset").unwrap();
+/// println!("{}: {} entries", info.name, info.entries);
+/// ```
+pub fn nftset_info(family: NftFamily, table: &str, setname: &str) -> Result<NftSetInfo> {
+    block_on(nftset_info_async(family, table, setname))
+}
+
+/// [`nftset_info`] for async code.
+pub(crate) async fn nftset_info_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<NftSetInfo> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    nft_dump_retry(move || async move {
+        let mut info = nftset_get_set(family, table, setname).await?;
+        nftset_elem_dump(family, table, setname, |attrs| {
+            info.entries += count_nftset_elements(attrs);
+        })
+        .await?;
+        Ok(info)
+    })
+    .await
+}
+
+/// Get the properties of a set with GETSET, without counting its elements.
+async fn nftset_get_set(family: NftFamily, table: &str, setname: &str) -> Result<NftSetInfo> {
+    nftset_get_set_on(&NetlinkSocket::new()?, family, table, setname).await
+}
+
+/// Look up the properties of a set over `socket`.
+async fn nftset_get_set_on(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<NftSetInfo> {
+    nftset_lookup_on(socket, family, table, setname, parse_nftset_info).await
+}
+
+/// Look up a set over `socket`, decoding the attributes of the answer
+/// with `parse`.
+async fn nftset_lookup_on<T>(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    parse: impl FnOnce(&[u8]) -> Option<T>,
+) -> Result<T> {
+    // Skip the acknowledgement of a previous lookup on the same socket
+    socket.drain()?;
+    with_msg_buffer(|buf| {
+        // Build the GETSET message
+        buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETSET), NLM_F_REQUEST | NLM_F_ACK, 0);
+        buf.put_nfgenmsg(family.nfproto(), 0, 0);
+
+        buf.put_attr_str(NFTA_SET_TABLE, table);
+        buf.put_attr_str(NFTA_SET_NAME, setname);
+
+        buf.finalize_nlmsg();
+        socket.send_retry(buf.as_slice())
+    })?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket.recv_async(&mut recv_buf).await?;
+
+    if recv_len < NlMsgHdr::SIZE + NfGenMsg::SIZE {
+        return Err(RipsetError::ProtocolError);
+    }
+
+    if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
+        return match -error {
+            libc::ENOENT => Err(RipsetError::SetNotFound(setname.to_string())),
+            error => Err(errno_error(error, &recv_buf[..recv_len])),
+        };
+    }
+
+    let hdr: NlMsgHdr = unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+    let msg_end = (hdr.nlmsg_len as usize).min(recv_len);
+    parse(&recv_buf[NlMsgHdr::SIZE + NfGenMsg::SIZE..msg_end]).ok_or(RipsetError::ProtocolError)
+}
+
+/// Parse whether a set stores ranges and merges them from a NEWSET
+/// message, without the rest of its properties.
+fn parse_nftset_ranges(data: &[u8]) -> Option<(bool, bool)> {
+    let (mut interval, mut auto_merge) = (false, false);
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_SET_FLAGS => {
+                interval = nla_get_u32_be(payload).is_some_and(|f| f & NFT_SET_INTERVAL != 0)
+            }
+            NFTA_SET_USERDATA => auto_merge = parse_nftset_udata_auto_merge(payload),
+            _ => {}
+        }
+    }
+    Some((interval, auto_merge))
+}
+
+/// Parse a NEWSET message into set properties.
+fn parse_nftset_info(data: &[u8]) -> Option<NftSetInfo> {
+    let mut name = None;
+    let mut key_type = 0;
+    let mut key_len = 0;
+    let mut flags = 0;
+    let mut data_type = None;
+    let mut timeout = None;
+    let mut gc_interval = None;
+    let mut policy = None;
+    let mut size = None;
+    let mut comment = None;
+    let mut auto_merge = false;
+    let mut counters = false;
+
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_SET_NAME => name = nla_get_str(payload),
+            NFTA_SET_KEY_TYPE => key_type = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_SET_KEY_LEN => key_len = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_SET_FLAGS => flags = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_SET_DATA_TYPE => {
+                data_type = nla_get_u32_be(payload).and_then(NftSetType::from_key_type)
+            }
+            // Timeouts are reported in milliseconds
+            NFTA_SET_TIMEOUT => timeout = nla_get_u64_be(payload).map(|ms| (ms / 1000) as u32),
+            NFTA_SET_GC_INTERVAL => gc_interval = nla_get_u32_be(payload).map(|ms| ms / 1000),
+            NFTA_SET_POLICY => policy = nla_get_u32_be(payload).and_then(NftSetPolicy::from_u32),
+            NFTA_SET_DESC => {
+                size = NlAttrIter::new(payload)
+                    .find(|&(desc_type, _)| desc_type == NFTA_SET_DESC_SIZE)
+                    .and_then(|(_, size)| nla_get_u32_be(size))
+            }
+            NFTA_SET_USERDATA => {
+                comment = parse_nftset_udata_comment(payload);
+                auto_merge = parse_nftset_udata_auto_merge(payload);
+            }
+            NFTA_SET_EXPR => counters |= parse_nft_counter(payload).is_some(),
+            NFTA_SET_EXPRESSIONS => {
+                counters |=
+                    NlAttrIter::new(payload).any(|(_, expr)| parse_nft_counter(expr).is_some())
+            }
+            _ => {}
+        }
+    }
+
+    Some(NftSetInfo {
+        name: name?,
+        set_type: NftSetType::from_key_type(key_type),
+        key_type,
+        key_len,
+        map: flags & NFT_SET_MAP != 0,
+        data_type,
+        flags,
+        interval: flags & NFT_SET_INTERVAL != 0,
+        auto_merge,
+        with_timeout: flags & NFT_SET_TIMEOUT != 0,
+        dynamic: flags & NFT_SET_EVAL != 0,
+        constant: flags & NFT_SET_CONSTANT != 0,
+        timeout,
+        gc_interval,
+        policy,
+        size,
+        entries: 0,
+        comment,
+        counters,
+    })
+}
+
+/// Find a value in set userdata, stored as `type, len, value` TLVs.
+fn nftset_udata_get(mut data: &[u8], udata_type: u8) -> Option<&[u8]> {
+    while let [tlv_type, len, rest @ ..] = data {
+        let value = rest.get(..*len as usize)?;
+        if *tlv_type == udata_type {
+            return Some(value);
+        }
+        data = &rest[*len as usize..];
+    }
+    None
+}
+
+/// Extract the comment from set userdata.
+fn parse_nftset_udata_comment(data: &[u8]) -> Option<String> {
+    nftset_udata_get(data, NFTNL_UDATA_SET_COMMENT).and_then(nla_get_str)
+}
+
+/// Check the auto-merge flag in set userdata.
+fn parse_nftset_udata_auto_merge(data: &[u8]) -> bool {
+    nftset_udata_get(data, NFTNL_UDATA_SET_MERGE_ELEMENTS)
+        .and_then(|value| value.try_into().ok())
+        .is_some_and(|value| u32::from_ne_bytes(value) != 0)
+}
+
+/// Count the elements in a NEWSETELEM message, skipping interval end markers.
+fn count_nftset_elements(data: &[u8]) -> u32 {
+    NlAttrIter::new(data)
+        .filter(|&(attr_type, _)| attr_type == NFTA_SET_ELEM_LIST_ELEMENTS)
+        .flat_map(|(_, elems)| NlAttrIter::new(elems))
+        .filter(|&(_, elem)| {
+            let flags = NlAttrIter::new(elem)
+                .find(|&(attr_type, _)| attr_type == NFTA_SET_ELEM_FLAGS)
+                .and_then(|(_, flags)| nla_get_u32_be(flags))
+                .unwrap_or(0);
+            flags & NFT_SET_ELEM_INTERVAL_END == 0
+        })
+        .count() as u32
+}
+
+/// Get the flags of an nftables set.
+async fn nftset_get_flags(family: NftFamily, table: &str, setname: &str) -> Result<u32> {
+    let nf_family = family.nfproto();
+
+    // Build the GETSET message
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+
     buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETSET), NLM_F_REQUEST | NLM_F_ACK, 0);
     buf.put_nfgenmsg(nf_family, 0, 0);
 
@@ -528,17 +2419,19 @@ fn nftset_get_flags(family: &str, table: &str, setname: &str) -> Result<u32> {
 
     let socket = NetlinkSocket::new()?;
     let mut recv_buf = [0u8; BUFF_SZ];
-    let recv_len = socket.send_recv(buf.as_slice(), &mut recv_buf)?;
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
 
     if recv_len < NlMsgHdr::SIZE + NfGenMsg::SIZE {
-        return Err(IpSetError::ProtocolError);
+        return Err(RipsetError::ProtocolError);
     }
 
     // Check for error response
     if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len])
         && error != 0
     {
-        return Err(IpSetError::NetlinkError(-error));
+        return Err(errno_error(-error, &recv_buf[..recv_len]));
     }
 
     // Parse response to find flags
@@ -546,85 +2439,59 @@ fn nftset_get_flags(family: &str, table: &str, setname: &str) -> Result<u32> {
 
     if hdr.nlmsg_type == crate::netlink::NLMSG_ERROR {
         // This is an error response, not set data
-        return Err(IpSetError::SetNotFound(setname.to_string()));
+        return Err(RipsetError::SetNotFound(setname.to_string()));
     }
 
-    // Parse attributes to find NFTA_SET_FLAGS
-    let attr_start = NlMsgHdr::SIZE + NfGenMsg::SIZE;
-    let mut offset = attr_start;
-
-    while offset + 4 <= recv_len {
-        let attr_len = u16::from_ne_bytes([recv_buf[offset], recv_buf[offset + 1]]) as usize;
-        let attr_type =
-            u16::from_ne_bytes([recv_buf[offset + 2], recv_buf[offset + 3]]) & !NLA_F_NESTED;
-
-        if attr_len < 4 {
-            break;
-        }
-
-        if attr_type == NFTA_SET_FLAGS && attr_len >= 8 {
-            let flags = u32::from_ne_bytes([
-                recv_buf[offset + 4],
-                recv_buf[offset + 5],
-                recv_buf[offset + 6],
-                recv_buf[offset + 7],
-            ]);
-            return Ok(flags);
+    // Parse attributes to find NFTA_SET_FLAGS (big-endian)
+    let msg_end = (hdr.nlmsg_len as usize).min(recv_len);
+    for (attr_type, payload) in NlAttrIter::new(&recv_buf[NlMsgHdr::SIZE + NfGenMsg::SIZE..msg_end])
+    {
+        if attr_type == NFTA_SET_FLAGS {
+            return Ok(nla_get_u32_be(payload).unwrap_or(0));
         }
-
-        offset += crate::netlink::nla_align(attr_len);
     }
 
     // Flags not found, assume 0
     Ok(0)
 }
 
-/// Test if an IP exists in an nftables set.
-fn nftset_test_ip_exists(family: &str, table: &str, setname: &str, addr: &IpAddr) -> Result<bool> {
-    let nf_family = parse_nf_family(family)?;
-
-    let addr_bytes: Vec<u8> = match addr {
-        IpAddr::V4(v4) => v4.octets().to_vec(),
-        IpAddr::V6(v6) => v6.octets().to_vec(),
-    };
-
-    // Build GETSETELEM message
-    let mut buf = MsgBuffer::new(BUFF_SZ);
-
-    buf.put_nlmsghdr(
-        nft_msg_type(NFT_MSG_GETSETELEM),
-        NLM_F_REQUEST | NLM_F_ACK,
-        0,
-    );
-    buf.put_nfgenmsg(nf_family, 0, 0);
-
-    buf.put_attr_str(NFTA_SET_ELEM_LIST_TABLE, table);
-    buf.put_attr_str(NFTA_SET_ELEM_LIST_SET, setname);
-
-    // Elements list (nested)
-    let elems_offset = buf.start_nested(NFTA_SET_ELEM_LIST_ELEMENTS);
-
-    // Single element (nested)
-    let elem_offset = buf.start_nested(0); // Type 0 for list item
-
-    // Key (nested)
-    let key_offset = buf.start_nested(NFTA_SET_ELEM_KEY);
-
-    // Data value
-    buf.put_attr_bytes(NFTA_DATA_VALUE, &addr_bytes);
-
-    buf.end_nested(key_offset);
-    buf.end_nested(elem_offset);
-    buf.end_nested(elems_offset);
-
-    buf.finalize_nlmsg();
+/// Test if an element key exists in an nftables set.
+async fn nftset_test_key_exists(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    key: &[u8],
+) -> Result<bool> {
+    nftset_test_key_exists_on(&NetlinkSocket::new()?, family, table, setname, key).await
+}
 
-    let socket = NetlinkSocket::new()?;
+/// Test for a key over `socket`.
+async fn nftset_test_key_exists_on(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    key: &[u8],
+) -> Result<bool> {
+    // Skip the acknowledgement of a previous lookup on the same socket
+    socket.drain()?;
+    with_msg_buffer(|buf| {
+        put_getsetelem_msg(
+            buf,
+            family,
+            table,
+            setname,
+            key,
+            NLM_F_REQUEST | NLM_F_ACK,
+            0,
+        );
+        socket.send_retry(buf.as_slice())
+    })?;
     let mut recv_buf = [0u8; BUFF_SZ];
-    let recv_len = socket.send_recv(buf.as_slice(), &mut recv_buf)?;
+    let recv_len = socket.recv_async(&mut recv_buf).await?;
 
     if recv_len < NlMsgHdr::SIZE {
-        return Err(IpSetError::ProtocolError);
+        return Err(RipsetError::ProtocolError);
     }
 
     // Check for error
@@ -635,7 +2502,7 @@ fn nftset_test_ip_exists(family: &str, table: &str, setname: &str, addr: &IpAddr
         if -error == libc::ENOENT {
             return Ok(false);
         }
-        return Err(IpSetError::NetlinkError(-error));
+        return Err(errno_error(-error, &recv_buf[..recv_len]));
     }
 
     // If we got data back without error, the element exists
@@ -647,346 +2514,2317 @@ fn nftset_test_ip_exists(family: &str, table: &str, setname: &str, addr: &IpAddr
     Ok(false)
 }
 
-/// Internal function to perform nftset element operations.
-fn nftset_operate(
-    family: &str,
+/// Build a GETSETELEM message looking up a single key.
+fn put_getsetelem_msg(
+    buf: &mut MsgBuffer,
+    family: NftFamily,
     table: &str,
     setname: &str,
-    entry: &IpEntry,
-    cmd: u16,
-) -> Result<()> {
-    // Validate names
-    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidTableName(table.to_string()));
+    key: &[u8],
+    flags: u16,
+    seq: u32,
+) {
+    let start = buf.len();
+    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETSETELEM), flags, seq);
+    put_setelem_key(buf, family, table, setname, key);
+    buf.finalize_nlmsg_at(start);
+}
+
+/// Put the body of a request for the element with a single key.
+fn put_setelem_key(buf: &mut MsgBuffer, family: NftFamily, table: &str, setname: &str, key: &[u8]) {
+    buf.put_nfgenmsg(family.nfproto(), 0, 0);
+
+    buf.put_attr_str(NFTA_SET_ELEM_LIST_TABLE, table);
+    buf.put_attr_str(NFTA_SET_ELEM_LIST_SET, setname);
+
+    // Elements list (nested)
+    let elems_offset = buf.start_nested(NFTA_SET_ELEM_LIST_ELEMENTS);
+
+    // Single element (nested)
+    let elem_offset = buf.start_nested(0); // Type 0 for list item
+
+    // Key (nested)
+    let key_offset = buf.start_nested(NFTA_SET_ELEM_KEY);
+
+    // Data value
+    buf.put_attr_bytes(NFTA_DATA_VALUE, key);
+
+    buf.end_nested(key_offset);
+    buf.end_nested(elem_offset);
+    buf.end_nested(elems_offset);
+}
+
+/// Test many keys, sending the lookups in groups instead of waiting for
+/// each answer.
+async fn nftset_test_entries<I>(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = Entry>,
+{
+    validate_table_name(table)?;
+    validate_set_name(setname)?;
+
+    let keys = entries
+        .into_iter()
+        .map(|entry| nft_key_bytes(&entry))
+        .collect::<Result<Vec<_>>>()?;
+    let mut found = vec![false; keys.len()];
+
+    socket.drain()?;
+    for (chunk_index, chunk) in keys.chunks(NFT_TESTS_PER_SEND).enumerate() {
+        let mut buf = MsgBuffer::new(BUFF_SZ);
+        for (i, key) in chunk.iter().enumerate() {
+            // Without an acknowledgement, every lookup gets a single answer
+            put_getsetelem_msg(
+                &mut buf,
+                family,
+                table,
+                setname,
+                key,
+                NLM_F_REQUEST,
+                i as u32 + 1,
+            );
+        }
+        socket.send(buf.as_slice())?;
+
+        let mut recv_buf = [0u8; BUFF_SZ];
+        for _ in 0..chunk.len() {
+            let recv_len = socket.recv_async(&mut recv_buf).await?;
+            if recv_len < NlMsgHdr::SIZE {
+                return Err(RipsetError::ProtocolError);
+            }
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(recv_buf.as_ptr() as *const NlMsgHdr) };
+            let i = (hdr.nlmsg_seq as usize)
+                .checked_sub(1)
+                .filter(|i| *i < chunk.len())
+                .ok_or(RipsetError::ProtocolError)?;
+            match parse_nlmsg_error(&recv_buf[..recv_len]) {
+                Some(error) if -error == libc::ENOENT => {}
+                Some(error) => return Err(errno_error(-error, &recv_buf[..recv_len])),
+                None => {
+                    found[chunk_index * NFT_TESTS_PER_SEND + i] =
+                        get_nlmsg_type(&recv_buf[..recv_len])
+                            == Some(nft_msg_type(NFT_MSG_NEWSETELEM));
+                }
+            }
+        }
     }
-    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
+    Ok(found)
+}
+
+/// A set element as sent to or dumped by the kernel.
+#[derive(Clone)]
+struct NftElement {
+    key: Vec<u8>,
+    /// Last key of a range in a concatenated interval set. Other interval
+    /// sets use a separate element flagged as the interval end instead.
+    key_end: Option<Vec<u8>>,
+    flags: u32,
+    timeout: Option<u32>,
+    /// Remaining time of a dumped element
+    expiration: Option<u32>,
+    /// Packet and byte counts of a dumped element
+    counters: Option<(u64, u64)>,
+    /// Value of the element in a map
+    data: Option<NftData>,
+}
+
+/// Value of a map element.
+#[derive(Clone)]
+enum NftData {
+    Value(Vec<u8>),
+    Verdict(NftVerdict),
+}
+
+impl NftElement {
+    /// An element to send to the kernel.
+    fn new(key: Vec<u8>, flags: u32, timeout: Option<u32>) -> Self {
+        Self {
+            key,
+            key_end: None,
+            flags,
+            timeout,
+            expiration: None,
+            counters: None,
+            data: None,
+        }
     }
+}
 
-    let nf_family = parse_nf_family(family)?;
+/// Build the kernel elements for an entry.
+///
+/// Interval sets store each range as a start element followed by an
+/// element flagged as the (exclusive) interval end, so single addresses,
+/// CIDR blocks and address or port ranges all become such a pair. The end
+/// element is left out when the range reaches the last value.
+///
+/// Concatenations in interval sets are a single element instead, whose key
+/// holds the first value of each field and whose key end holds the last.
+fn nft_elements(entry: &Entry, is_interval: bool) -> Result<Vec<NftElement>> {
+    // Concatenations carry their timeout in the leading address
+    let timeout = match entry {
+        Entry::Ip(ip) => ip.timeout,
+        Entry::Concat(parts) => match parts.first() {
+            Some(Entry::Ip(ip)) => ip.timeout,
+            _ => None,
+        },
+        _ => None,
+    };
 
-    // For ADD operations, check if element already exists
-    if cmd == NFT_MSG_NEWSETELEM {
-        match nftset_test_ip_exists(family, table, setname, &entry.addr) {
-            Ok(true) => return Err(IpSetError::ElementExists),
-            Ok(false) => {}
-            Err(IpSetError::SetNotFound(_)) => {
-                return Err(IpSetError::SetNotFound(setname.to_string()));
+    let ip = match entry {
+        Entry::Ip(ip) => ip,
+        Entry::Port { port, port_to } if is_interval => {
+            let last = port_range_end(*port, *port_to)?;
+            let mut elements = vec![NftElement::new(port.to_be_bytes().to_vec(), 0, None)];
+            if let Some(end) = last.checked_add(1) {
+                elements.push(NftElement::new(
+                    end.to_be_bytes().to_vec(),
+                    NFT_SET_ELEM_INTERVAL_END,
+                    None,
+                ));
             }
-            Err(_) => {} // Continue with add
+            return Ok(elements);
+        }
+        Entry::Concat(parts) if is_interval => {
+            let (first, last) = nft_concat_bounds(parts)?;
+            let mut element = NftElement::new(first, 0, timeout);
+            element.key_end = Some(last);
+            return Ok(vec![element]);
         }
+        _ if is_interval => {
+            return Err(RipsetError::InvalidOption(
+                "interval sets only support address, port and concatenated elements".to_string(),
+            ));
+        }
+        _ => return Ok(vec![NftElement::new(nft_key_bytes(entry)?, 0, timeout)]),
+    };
+
+    let max_cidr = if ip.addr.is_ipv4() { 32 } else { 128 };
+    if ip.cidr.is_some_and(|cidr| cidr > max_cidr) {
+        return Err(RipsetError::InvalidAddress(format!(
+            "{}/{}",
+            ip.addr,
+            ip.cidr.unwrap_or(0)
+        )));
+    }
+    let is_range = ip.ip_to.is_some() || ip.cidr.is_some_and(|cidr| cidr < max_cidr);
+    if !is_interval {
+        if is_range {
+            return Err(RipsetError::InvalidOption(
+                "CIDR and range elements require an interval set".to_string(),
+            ));
+        }
+        return Ok(vec![NftElement::new(nft_key_bytes(entry)?, 0, timeout)]);
     }
 
-    // Get set flags to determine if it's an interval set
-    let set_flags = nftset_get_flags(family, table, setname).unwrap_or(0);
-    let is_interval = (set_flags & NFT_SET_INTERVAL) != 0;
+    let (first, last) = ip_entry_bounds(ip)?;
 
-    let addr_bytes: Vec<u8> = match entry.addr {
+    let mut elements = vec![NftElement::new(ip_bytes(&first), 0, timeout)];
+    let end = calculate_interval_end(&last);
+    if end > last {
+        elements.push(NftElement::new(
+            ip_bytes(&end),
+            NFT_SET_ELEM_INTERVAL_END,
+            None,
+        ));
+    }
+    Ok(elements)
+}
+
+/// Last port of a port entry, checking that ranges aren't reversed.
+fn port_range_end(port: u16, port_to: Option<u16>) -> Result<u16> {
+    match port_to {
+        Some(port_to) if port_to < port => Err(RipsetError::InvalidOption(format!(
+            "invalid port range: {port}-{port_to}"
+        ))),
+        port_to => Ok(port_to.unwrap_or(port)),
+    }
+}
+
+/// First and last key of a concatenation in an interval set, laid out like
+/// [`nft_key_bytes`]. Addresses, CIDR blocks and ports can be ranges; other
+/// fields match a single value.
+fn nft_concat_bounds(parts: &[Entry]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut first = Vec::new();
+    let mut last = Vec::new();
+    for part in parts {
+        let (start, end) = match part {
+            Entry::Ip(ip) => {
+                let (start, end) = ip_entry_bounds(ip)?;
+                (ip_bytes(&start), ip_bytes(&end))
+            }
+            Entry::Port { port, port_to } => {
+                let end = port_range_end(*port, *port_to)?;
+                (port.to_be_bytes().to_vec(), end.to_be_bytes().to_vec())
+            }
+            Entry::Concat(_) => {
+                return Err(RipsetError::InvalidOption(
+                    "nested concatenations are not supported".to_string(),
+                ));
+            }
+            part => {
+                let key = nft_key_bytes(part)?;
+                (key.clone(), key)
+            }
+        };
+        first.extend(start);
+        first.resize(first.len().next_multiple_of(4), 0);
+        last.extend(end);
+        last.resize(last.len().next_multiple_of(4), 0);
+    }
+    Ok((first, last))
+}
+
+/// First and last address covered by an address, CIDR block or range entry.
+fn ip_entry_bounds(ip: &IpEntry) -> Result<(IpAddr, IpAddr)> {
+    match (ip.ip_to, ip.cidr) {
+        (Some(_), Some(_)) => Err(RipsetError::InvalidOption(
+            "an element can't have both a CIDR and a range end".to_string(),
+        )),
+        (Some(to), None) => {
+            if to.is_ipv4() != ip.addr.is_ipv4() || to < ip.addr {
+                return Err(RipsetError::InvalidAddress(format!("{}-{}", ip.addr, to)));
+            }
+            Ok((ip.addr, to))
+        }
+        (None, Some(cidr)) if cidr > if ip.addr.is_ipv4() { 32 } else { 128 } => {
+            Err(RipsetError::InvalidAddress(format!("{}/{}", ip.addr, cidr)))
+        }
+        (None, Some(cidr)) => Ok(prefix_bounds(ip.addr, cidr)),
+        (None, None) => Ok((ip.addr, ip.addr)),
+    }
+}
+
+/// First and last address of a CIDR block.
+fn prefix_bounds(addr: IpAddr, cidr: u8) -> (IpAddr, IpAddr) {
+    match addr {
+        IpAddr::V4(v4) => {
+            let host_mask = u32::MAX.checked_shr(cidr as u32).unwrap_or(0);
+            let first = u32::from(v4) & !host_mask;
+            (
+                IpAddr::V4(first.into()),
+                IpAddr::V4((first | host_mask).into()),
+            )
+        }
+        IpAddr::V6(v6) => {
+            let host_mask = u128::MAX.checked_shr(cidr as u32).unwrap_or(0);
+            let first = u128::from(v6) & !host_mask;
+            (
+                IpAddr::V6(first.into()),
+                IpAddr::V6((first | host_mask).into()),
+            )
+        }
+    }
+}
+
+fn ip_bytes(addr: &IpAddr) -> Vec<u8> {
+    match addr {
         IpAddr::V4(v4) => v4.octets().to_vec(),
         IpAddr::V6(v6) => v6.octets().to_vec(),
-    };
+    }
+}
 
-    // Build the batched netlink message
-    let mut buf = MsgBuffer::new(BUFF_SZ);
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(<[u8; 4]>::try_from(bytes).ok()?.into())),
+        16 => Some(IpAddr::V6(<[u8; 16]>::try_from(bytes).ok()?.into())),
+        _ => None,
+    }
+}
 
-    // Batch begin message
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_BEGIN, NLM_F_REQUEST, 0);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg();
+fn ip_to_u128(addr: IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
 
+fn u128_to_ip(value: u128, v4: bool) -> IpAddr {
+    if v4 {
+        IpAddr::V4((value as u32).into())
+    } else {
+        IpAddr::V6(value.into())
+    }
+}
+
+/// Write a NEWSETELEM or DELSETELEM message for `elements`.
+fn put_setelem_msg(
+    buf: &mut MsgBuffer,
+    nf_family: u8,
+    cmd: u16,
+    seq: u32,
+    table: &str,
+    setname: &str,
+    elements: &[NftElement],
+) -> Result<()> {
     let msg_start = buf.len();
 
     // Main message
     let flags = if cmd == NFT_MSG_NEWSETELEM {
-        NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE
+        NLM_F_REQUEST | NLM_F_CREATE
     } else {
-        NLM_F_REQUEST | NLM_F_ACK
+        NLM_F_REQUEST
     };
 
-    buf.put_nlmsghdr(nft_msg_type(cmd), flags, 1);
+    buf.put_nlmsghdr(nft_msg_type(cmd), flags, seq);
     buf.put_nfgenmsg(nf_family, 0, 0);
 
     buf.put_attr_str(NFTA_SET_ELEM_LIST_TABLE, table);
     buf.put_attr_str(NFTA_SET_ELEM_LIST_SET, setname);
 
-    // Elements list (nested)
-    let elems_offset = buf.start_nested(NFTA_SET_ELEM_LIST_ELEMENTS);
+    // Elements list (nested)
+    let elems_offset = buf.start_nested(NFTA_SET_ELEM_LIST_ELEMENTS);
+
+    for element in elements {
+        // Single element (nested)
+        let elem_offset = buf.start_nested(0); // Type 0 for list item
+
+        // Key (nested)
+        let key_offset = buf.start_nested(NFTA_SET_ELEM_KEY);
+        buf.put_attr_bytes(NFTA_DATA_VALUE, &element.key);
+        buf.end_nested(key_offset);
+
+        if let Some(key_end) = &element.key_end {
+            let key_end_offset = buf.start_nested(NFTA_SET_ELEM_KEY_END);
+            buf.put_attr_bytes(NFTA_DATA_VALUE, key_end);
+            buf.end_nested(key_end_offset);
+        }
+
+        if element.flags != 0 {
+            buf.put_attr_u32_nft(NFTA_SET_ELEM_FLAGS, element.flags);
+        }
+
+        // Map value (nested)
+        if let Some(data) = &element.data {
+            let data_offset = buf.start_nested(NFTA_SET_ELEM_DATA);
+            match data {
+                NftData::Value(value) => buf.put_attr_bytes(NFTA_DATA_VALUE, value),
+                NftData::Verdict(verdict) => put_nft_verdict(buf, verdict)?,
+            }
+            buf.end_nested(data_offset);
+        }
+
+        // Timeout (optional, in milliseconds for nftables). Dumps report it
+        // on interval ends too, but the kernel refuses it there
+        if let Some(timeout) = element
+            .timeout
+            .filter(|_| element.flags & NFT_SET_ELEM_INTERVAL_END == 0)
+        {
+            buf.put_attr_u64_be(NFTA_SET_ELEM_TIMEOUT, (timeout as u64) * 1000);
+        }
+
+        buf.end_nested(elem_offset);
+    }
+
+    buf.end_nested(elems_offset);
+
+    buf.finalize_nlmsg_at(msg_start);
+
+    Ok(())
+}
+
+/// Internal function to perform nftset element operations.
+async fn nftset_operate(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: &Entry,
+    data: Option<&Entry>,
+    cmd: u16,
+) -> Result<()> {
+    // Validate names
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    // Get the set properties to determine if it's an interval set
+    let (is_interval, auto_merge) =
+        nftset_lookup_on(socket, family, table, setname, parse_nftset_ranges)
+            .await
+            .unwrap_or_default();
+
+    if cmd == NFT_MSG_NEWSETELEM
+        && let Entry::Ip(ip) = entry
+        && auto_merge
+    {
+        return nft_retry(move || nftset_merge_add(family, table, setname, ip, data)).await;
+    }
+
+    let mut elements = nft_elements(entry, is_interval)?;
+    if let Some(data) = data {
+        // The value belongs to the start of a range
+        elements[0].data = Some(nft_data(data)?);
+    }
+
+    // For ADD operations, check if element already exists
+    if cmd == NFT_MSG_NEWSETELEM {
+        match nftset_test_key_exists_on(socket, family, table, setname, &elements[0].key).await {
+            Ok(true) => return Err(RipsetError::EntryExists),
+            Ok(false) => {}
+            Err(RipsetError::SetNotFound(_)) => {
+                return Err(RipsetError::SetNotFound(setname.to_string()));
+            }
+            Err(_) => {} // Continue with add
+        }
+    }
+
+    socket.drain()?;
+    nftset_commit_elements(socket, family, cmd, table, setname, &elements).await
+}
+
+/// Encode the value of a map element.
+fn nft_data(data: &Entry) -> Result<NftData> {
+    match data {
+        Entry::Verdict(verdict) => Ok(NftData::Verdict(verdict.clone())),
+        data => Ok(NftData::Value(nft_key_bytes(data)?)),
+    }
+}
+
+/// Add a range to an auto-merge interval set.
+///
+/// Like nft, the ranges overlapping or adjacent to the new one are merged
+/// with it: they are deleted and the merged range added in one batch, which
+/// the kernel refuses with `ERESTART` if the set changed in the meantime.
+async fn nftset_merge_add(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    ip: &IpEntry,
+    data: Option<&Entry>,
+) -> Result<()> {
+    let (first, last) = ip_entry_bounds(ip)?;
+    let (mut lo, mut hi) = (ip_to_u128(first), ip_to_u128(last));
+
+    let genid = nft_genid().await?;
+    let (_, entries) = nftset_dump_entries(family, table, setname).await?;
+    let mut ranges: Vec<(u128, u128, IpEntry)> = entries
+        .into_iter()
+        .filter_map(|(entry, _)| match entry {
+            Entry::Ip(existing) if existing.addr.is_ipv4() == first.is_ipv4() => {
+                let (a, b) = ip_entry_bounds(&existing).ok()?;
+                Some((ip_to_u128(a), ip_to_u128(b), existing))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // Absorb ranges until none touches the merged one
+    let mut absorbed = Vec::new();
+    while let Some(pos) = ranges
+        .iter()
+        .position(|&(a, b, _)| a <= hi.saturating_add(1) && lo <= b.saturating_add(1))
+    {
+        let (a, b, existing) = ranges.swap_remove(pos);
+        lo = lo.min(a);
+        hi = hi.max(b);
+        absorbed.push((a, b, existing));
+    }
+
+    // Already covered by a single range
+    if let [(a, b, _)] = absorbed[..]
+        && (a, b) == (lo, hi)
+    {
+        return Ok(());
+    }
+
+    let v4 = first.is_ipv4();
+    let mut merged = range_entry(u128_to_ip(lo, v4), u128_to_ip(hi, v4));
+    merged.timeout = ip.timeout;
+    let mut elements = nft_elements(&Entry::Ip(merged), true)?;
+    if let Some(data) = data {
+        elements[0].data = Some(nft_data(data)?);
+    }
+    let mut stale = Vec::new();
+    for (_, _, mut existing) in absorbed {
+        existing.timeout = None;
+        stale.extend(nft_elements(&Entry::Ip(existing), true)?);
+    }
+
+    // Delete the absorbed ranges and add the merged one in one batch
+    let mut tx = NftTransaction::with_genid(genid);
+    if !stale.is_empty() {
+        tx.put_elements(family, NFT_MSG_DELSETELEM, table, setname, &stale)?;
+    }
+    tx.put_elements(family, NFT_MSG_NEWSETELEM, table, setname, &elements)?;
+    tx.commit_async().await
+}
+
+/// Add an IP address to an nftables set.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entry` - The entry to add (can be created from IpAddr, IpEntry, MacAddr, or a port)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_add};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// nftset_add(NftFamily::Inet, "filter", "myset", addr).unwrap();
+/// ```
+pub fn nftset_add<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    block_on(nftset_add_async(family, table, setname, entry))
+}
+
+/// [`nftset_add`] for async code.
+pub(crate) async fn nftset_add_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    let entry = entry.into();
+    let socket = NetlinkSocket::new()?;
+    nftset_operate(
+        &socket,
+        family,
+        table,
+        setname,
+        &entry,
+        None,
+        NFT_MSG_NEWSETELEM,
+    )
+    .await
+}
+
+/// Delete an IP address from an nftables set.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entry` - The entry to delete (can be created from IpAddr, IpEntry, MacAddr, or a port)
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_del};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// nftset_del(NftFamily::Inet, "filter", "myset", addr).unwrap();
+/// ```
+pub fn nftset_del<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    block_on(nftset_del_async(family, table, setname, entry))
+}
+
+/// [`nftset_del`] for async code.
+pub(crate) async fn nftset_del_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    let entry = entry.into();
+    let socket = NetlinkSocket::new()?;
+    nftset_operate(
+        &socket,
+        family,
+        table,
+        setname,
+        &entry,
+        None,
+        NFT_MSG_DELSETELEM,
+    )
+    .await
+}
+
+/// Add many entries to an nftables set at once.
+///
+/// The set is looked up once and the entries are packed into as few
+/// netlink messages as possible, sent as a single transaction. This is much
+/// faster than calling [`nftset_add`] for each entry, e.g. when importing a
+/// large blocklist. Entries already in the set are left alone; if one of the
+/// entries is refused, none of them are added.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entries` - The entries to add
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_add_many};
+///
+/// let addrs: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// nftset_add_many(NftFamily::Inet, "filter", "myset", addrs).unwrap();
+/// ```
+pub fn nftset_add_many<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(nftset_add_many_async(family, table, setname, entries))
+}
+
+/// [`nftset_add_many`] for async code.
+pub(crate) async fn nftset_add_many_async<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let entries = entries.into_iter().map(Into::into);
+    let socket = NetlinkSocket::new()?;
+    nftset_operate_many(&socket, family, table, setname, entries, NFT_MSG_NEWSETELEM).await
+}
+
+/// Delete many entries from an nftables set at once.
+///
+/// The set is looked up once and the entries are packed into as few
+/// netlink messages as possible, sent as a single transaction. This is much
+/// faster than calling [`nftset_del`] for each entry. An entry that can't be
+/// deleted, for example because it already timed out, doesn't stop the
+/// others: the kernel refuses the whole transaction, so the missing entries
+/// are looked up and the others deleted with a second one.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entries` - The entries to delete
+///
+/// # Returns
+///
+/// The entries that weren't deleted, as their index in `entries` along with
+/// the reason, such as [`RipsetError::EntryNotFound`]. Errors concerning
+/// the whole set, like [`RipsetError::SetNotFound`], are returned as `Err`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_del_many};
+///
+/// let expired: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// for (index, error) in nftset_del_many(NftFamily::Inet, "filter", "myset", expired.clone()).unwrap() {
+///     eprintln!("{} not deleted: {}", expired[index], error);
+/// }
+/// ```
+pub fn nftset_del_many<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(nftset_del_many_async(family, table, setname, entries))
+}
+
+/// [`nftset_del_many`] for async code.
+pub(crate) async fn nftset_del_many_async<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let entries = entries.into_iter().map(Into::into);
+    let socket = NetlinkSocket::new()?;
+    nftset_del_entries(&socket, family, table, setname, entries).await
+}
+
+/// Add many entries in a single transaction, looking the set up only once.
+async fn nftset_operate_many<I>(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+    cmd: u16,
+) -> Result<()>
+where
+    I: IntoIterator<Item = Entry>,
+{
+    validate_table_name(table)?;
+    validate_set_name(setname)?;
+
+    let set = nftset_get_set_on(socket, family, table, setname).await?;
+    let mut elements = Vec::new();
+    for entry in entries {
+        elements.extend(nft_elements(&entry, set.interval)?);
+    }
+
+    socket.drain()?;
+    nftset_commit_elements(socket, family, cmd, table, setname, &elements).await
+}
+
+/// Delete many entries, reporting those that aren't in the set.
+async fn nftset_del_entries<I>(
+    socket: &NetlinkSocket,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = Entry>,
+{
+    validate_table_name(table)?;
+    validate_set_name(setname)?;
+
+    let set = nftset_get_set_on(socket, family, table, setname).await?;
+    let mut failures = Vec::new();
+    let mut items = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        match nft_elements(&entry, set.interval) {
+            Ok(elements) => items.push((index, elements)),
+            Err(e) => failures.push((index, e)),
+        }
+    }
+
+    let mut attempts = 1;
+    loop {
+        let elements: Vec<NftElement> = items
+            .iter()
+            .flat_map(|(_, elements)| elements.iter().cloned())
+            .collect();
+        let mut tx = NftTransaction::new();
+        tx.put_elements(family, NFT_MSG_DELSETELEM, table, setname, &elements)?;
+        socket.drain()?;
+        match tx.commit_on(socket).await {
+            Err(RipsetError::EntryNotFound) if attempts < NFT_MAX_RETRIES => attempts += 1,
+            Err(e) => return Err(e),
+            Ok(()) => break,
+        }
+
+        // Leave out the entries that are gone, including any that timed out
+        // since the last attempt
+        let mut present = Vec::with_capacity(items.len());
+        for (index, elements) in items {
+            if nftset_test_key_exists_on(socket, family, table, setname, &elements[0].key).await? {
+                present.push((index, elements));
+            } else {
+                failures.push((index, RipsetError::EntryNotFound));
+            }
+        }
+        items = present;
+    }
+
+    failures.sort_by_key(|(index, _)| *index);
+    Ok(failures)
+}
+
+/// Add an element with a value to an nftables map.
+///
+/// The map must have been created with [`NftSetCreateOptions::data_type`]
+/// set. The value is encoded like a key of that type, e.g. an address for
+/// `ipv4_addr` maps or [`Entry::Mark`] for `mark` maps, and verdict maps
+/// take an [`Entry::Verdict`].
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `mapname` - The map name
+/// * `key` - The key of the element
+/// * `value` - The value the key maps to
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, Entry, nftset_map_add};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// nftset_map_add(NftFamily::Inet, "filter", "marks", addr, Entry::Mark(0x10)).unwrap();
+/// ```
+pub fn nftset_map_add<K, V>(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+    key: K,
+    value: V,
+) -> Result<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    block_on(nftset_map_add_async(family, table, mapname, key, value))
+}
+
+/// [`nftset_map_add`] for async code.
+pub(crate) async fn nftset_map_add_async<K, V>(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+    key: K,
+    value: V,
+) -> Result<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    nftset_map_add_with_async(family, table, mapname, key, value, NftMapConflict::Fail).await
+}
+
+/// What [`nftset_map_add_with`] does when the key is already in the map.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NftMapConflict {
+    /// Fail with [`RipsetError::EntryExists`], like [`nftset_map_add`]
+    #[default]
+    Fail,
+    /// Replace the value of the existing element
+    Replace,
+    /// Keep the existing element and its value
+    Ignore,
+}
+
+/// Add an element with a value to an nftables map, choosing what happens if
+/// the key is already there.
+///
+/// The kernel can't update the value of an element in place, so
+/// [`NftMapConflict::Replace`] deletes the old element and adds the new one
+/// in a single batch: packets never see the key missing from the map.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `mapname` - The map name
+/// * `key` - The key of the element
+/// * `value` - The value the key maps to
+/// * `on_conflict` - What to do if the key already exists
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, Entry, NftMapConflict, nftset_map_add_with};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// nftset_map_add_with(
+///     NftFamily::Inet,
+///     "filter",
+///     "marks",
+///     addr,
+///     Entry::Mark(0x20),
+///     NftMapConflict::Replace,
+/// )
+/// .unwrap();
+/// ```
+pub fn nftset_map_add_with<K, V>(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+    key: K,
+    value: V,
+    on_conflict: NftMapConflict,
+) -> Result<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    block_on(nftset_map_add_with_async(
+        family,
+        table,
+        mapname,
+        key,
+        value,
+        on_conflict,
+    ))
+}
+
+/// [`nftset_map_add_with`] for async code.
+pub(crate) async fn nftset_map_add_with_async<K, V>(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+    key: K,
+    value: V,
+    on_conflict: NftMapConflict,
+) -> Result<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    let (key, value) = (key.into(), value.into());
+    if on_conflict == NftMapConflict::Fail {
+        let socket = NetlinkSocket::new()?;
+        return nftset_operate(
+            &socket,
+            family,
+            table,
+            mapname,
+            &key,
+            Some(&value),
+            NFT_MSG_NEWSETELEM,
+        )
+        .await;
+    }
+    validate_table_name(table)?;
+    validate_set_name(mapname)?;
+
+    let map = nftset_get_set(family, table, mapname).await?;
+    let stale = nft_elements(&key, map.interval)?;
+    let mut elements = nft_elements(&key, map.interval)?;
+    // The value belongs to the start of a range
+    elements[0].data = Some(nft_data(&value)?);
+    let (stale, elements) = (&stale, &elements);
+
+    nft_retry(move || async move {
+        // The batch is refused if the element appears or goes away after
+        // it was looked up
+        let genid = nft_genid().await?;
+        let mut tx = NftTransaction::with_genid(genid);
+        if nftset_test_key_exists(family, table, mapname, &stale[0].key).await? {
+            if on_conflict == NftMapConflict::Ignore {
+                return Ok(());
+            }
+            tx.put_elements(family, NFT_MSG_DELSETELEM, table, mapname, stale)?;
+        }
+        tx.put_elements(family, NFT_MSG_NEWSETELEM, table, mapname, elements)?;
+        tx.commit_async().await
+    })
+    .await
+}
+
+/// List the elements of an nftables map as key and value pairs.
+///
+/// Keys are listed as by [`nftset_list_entries`]. Elements are skipped if
+/// their value type is not known to this library.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `mapname` - The map name
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_map_list};
+///
+/// for (key, value) in nftset_map_list(NftFamily::Inet, "filter", "marks").unwrap() {
+///     println!("{key:?} : {value:?}");
+/// }
+/// ```
+pub fn nftset_map_list(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+) -> Result<Vec<(Entry, Entry)>> {
+    block_on(nftset_map_list_async(family, table, mapname))
+}
+
+/// [`nftset_map_list`] for async code.
+pub(crate) async fn nftset_map_list_async(
+    family: NftFamily,
+    table: &str,
+    mapname: &str,
+) -> Result<Vec<(Entry, Entry)>> {
+    let (set, entries) = nftset_dump_entries(family, table, mapname).await?;
+    if !set.map {
+        return Err(RipsetError::InvalidOption(format!(
+            "{mapname} is not a map"
+        )));
+    }
+    // Verdict values carry their own type
+    let data_type = set.data_type.unwrap_or(NftSetType::Verdict);
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|(key, element)| {
+            let value = match element.data? {
+                NftData::Value(value) => data_type.entry_from_key(&value)?,
+                NftData::Verdict(verdict) => Entry::Verdict(verdict),
+            };
+            Some((key, value))
+        })
+        .collect())
+}
+
+/// Test if an IP address exists in an nftables set.
+///
+/// The kernel is asked for the single element (`NFT_MSG_GETSETELEM`), so
+/// the cost doesn't depend on the size of the set. In interval sets, an
+/// address inside a stored range is reported as present.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entry` - The entry to test (can be created from IpAddr, IpEntry, MacAddr, or a port)
+///
+/// # Returns
+///
+/// * `Ok(true)` - The IP address exists in the set
+/// * `Ok(false)` - The IP address does not exist in the set
+/// * `Err(_)` - An error occurred
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_test};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// let exists = nftset_test(NftFamily::Inet, "filter", "myset", addr).unwrap();
+/// ```
+pub fn nftset_test<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<bool> {
+    block_on(nftset_test_async(family, table, setname, entry))
+}
+
+/// [`nftset_test`] for async code.
+pub(crate) async fn nftset_test_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<bool> {
+    let key = nft_key_bytes(&entry.into())?;
+    nftset_test_key_exists(family, table, setname, &key).await
+}
+
+/// Make sure an entry is in an nftables set, adding it if it is missing.
+///
+/// An entry already in the set is left as it is, so its timeout keeps
+/// running. The entry is looked up like [`nftset_test`] does, by its first
+/// value for ranges.
+///
+/// Returns whether the entry was added.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entry` - The entry that must be in the set
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_ensure_entry};
+///
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// if nftset_ensure_entry(NftFamily::Inet, "filter", "myset", addr).unwrap() {
+///     println!("added {addr}");
+/// }
+/// ```
+pub fn nftset_ensure_entry<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<bool> {
+    block_on(nftset_ensure_entry_async(family, table, setname, entry))
+}
+
+/// [`nftset_ensure_entry`] for async code.
+pub(crate) async fn nftset_ensure_entry_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<bool> {
+    let session = NftSessionInner::new()?;
+    let entry = entry.into();
+    if session
+        .test_async(family, table, setname, entry.clone())
+        .await?
+    {
+        return Ok(false);
+    }
+    session.add_async(family, table, setname, entry).await?;
+    Ok(true)
+}
+
+/// Test many entries of an nftables set at once.
+///
+/// The lookups are sent in groups over one socket instead of waiting for
+/// the answer to each, which is much faster than calling [`nftset_test`]
+/// for each entry.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entries` - The entries to test
+///
+/// # Returns
+///
+/// Whether each entry is in the set, in the order of `entries`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_test_many};
+///
+/// let addrs: Vec<IpAddr> = vec!["192.168.1.1".parse().unwrap(), "192.168.1.2".parse().unwrap()];
+/// let found = nftset_test_many(NftFamily::Inet, "filter", "myset", addrs.iter().copied()).unwrap();
+/// for (addr, found) in addrs.iter().zip(found) {
+///     println!("{}: {}", addr, found);
+/// }
+/// ```
+pub fn nftset_test_many<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    block_on(nftset_test_many_async(family, table, setname, entries))
+}
+
+/// [`nftset_test_many`] for async code.
+pub(crate) async fn nftset_test_many_async<I, E>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entries: I,
+) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    let entries = entries.into_iter().map(Into::into);
+    let socket = NetlinkSocket::new()?;
+    nftset_test_entries(&socket, family, table, setname, entries).await
+}
+
+/// A netlink socket kept open for a series of nftables operations.
+///
+/// The `nftset_*` functions open a socket for every call, and adding an
+/// element takes several requests. A session sends all of them over one
+/// socket, which saves the setup cost when making many changes.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, NftSession};
+///
+/// let session = NftSession::new().unwrap();
+/// for addr in ["192.168.1.1", "192.168.1.2"] {
+///     let addr: IpAddr = addr.parse().unwrap();
+///     session.add(NftFamily::Inet, "filter", "myset", addr).unwrap();
+/// }
+/// ```
+pub struct NftSession {
+    inner: NftSessionInner,
+    /// Keeps the session on one thread at a time: threads sharing the socket
+    /// would read the answers to each other's requests.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl NftSession {
+    /// Open the netlink socket of a new session.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inner: NftSessionInner::new()?,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// Open a session managing the tables of the network namespace `netns`,
+    /// such as `/var/run/netns/<name>`. See [`with_netns`](crate::with_netns).
+    pub fn in_netns(netns: impl AsRef<Path>) -> Result<Self> {
+        Self::in_netns_fd(File::open(netns)?.as_fd())
+    }
+
+    /// Open a session managing the tables of the network namespace referred
+    /// to by the file descriptor `netns`.
+    pub fn in_netns_fd(netns: BorrowedFd<'_>) -> Result<Self> {
+        Ok(Self {
+            inner: NftSessionInner::in_netns_fd(netns)?,
+            _not_sync: PhantomData,
+        })
+    }
+
+    /// The socket of the session, for [`AsyncNftSession`](crate::AsyncNftSession)
+    /// which shares it between tasks behind a lock.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn into_inner(self) -> NftSessionInner {
+        self.inner
+    }
+
+    /// Add an entry to an nftables set, like [`nftset_add`].
+    pub fn add<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        block_on(self.inner.add_async(family, table, setname, entry))
+    }
 
-    // Single element (nested)
-    let elem_offset = buf.start_nested(0); // Type 0 for list item
+    /// Add many entries to an nftables set, like [`nftset_add_many`].
+    pub fn add_many<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        block_on(self.inner.add_many_async(family, table, setname, entries))
+    }
 
-    // Key (nested)
-    let key_offset = buf.start_nested(NFTA_SET_ELEM_KEY);
-    buf.put_attr_bytes(NFTA_DATA_VALUE, &addr_bytes);
-    buf.end_nested(key_offset);
+    /// Delete many entries from an nftables set, like [`nftset_del_many`].
+    pub fn del_many<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Result<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        block_on(self.inner.del_many_async(family, table, setname, entries))
+    }
 
-    // For interval sets, add the end key
-    if is_interval {
-        let end_addr = calculate_interval_end(&entry.addr);
-        let end_bytes: Vec<u8> = match end_addr {
-            IpAddr::V4(v4) => v4.octets().to_vec(),
-            IpAddr::V6(v6) => v6.octets().to_vec(),
-        };
+    /// Delete an entry from an nftables set, like [`nftset_del`].
+    pub fn del<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        block_on(self.inner.del_async(family, table, setname, entry))
+    }
 
-        let key_end_offset = buf.start_nested(NFTA_SET_ELEM_KEY_END);
-        buf.put_attr_bytes(NFTA_DATA_VALUE, &end_bytes);
-        buf.end_nested(key_end_offset);
+    /// Test if an entry exists in an nftables set, like [`nftset_test`].
+    pub fn test<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<bool> {
+        block_on(self.inner.test_async(family, table, setname, entry))
     }
 
-    // Timeout (optional, in milliseconds for nftables)
-    if let Some(timeout) = entry.timeout {
-        // nftables uses milliseconds for timeout in netlink
-        buf.put_attr_u64_be(NFTA_SET_ELEM_TIMEOUT, (timeout as u64) * 1000);
+    /// Test many entries of an nftables set, like [`nftset_test_many`].
+    pub fn test_many<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        block_on(self.inner.test_many_async(family, table, setname, entries))
     }
 
-    buf.end_nested(elem_offset);
-    buf.end_nested(elems_offset);
+    /// Send the changes queued in `tx`, like [`NftTransaction::commit`].
+    pub fn commit(&self, tx: NftTransaction) -> Result<()> {
+        block_on(self.inner.commit_async(tx))
+    }
+}
 
-    buf.finalize_nlmsg_at(msg_start);
+/// The socket of an [`NftSession`], which async sessions share between tasks
+/// behind a lock.
+pub(crate) struct NftSessionInner {
+    socket: NetlinkSocket,
+    netns: Option<OwnedFd>,
+}
 
-    // Batch end message
-    let end_start = buf.len();
-    buf.put_nlmsghdr(NFNL_MSG_BATCH_END, NLM_F_REQUEST, 2);
-    buf.put_nfgenmsg(libc::AF_UNSPEC as u8, 0, NFNL_SUBSYS_NFTABLES as u16);
-    buf.finalize_nlmsg_at(end_start);
+impl NftSessionInner {
+    /// Open the netlink socket of a new session.
+    pub(crate) fn new() -> Result<Self> {
+        Ok(Self {
+            socket: NetlinkSocket::new()?,
+            netns: None,
+        })
+    }
 
-    // Send and receive
-    let socket = NetlinkSocket::new()?;
-    socket.send(buf.as_slice())?;
+    /// Open a session managing the tables of the network namespace referred
+    /// to by the file descriptor `netns`.
+    fn in_netns_fd(netns: BorrowedFd<'_>) -> Result<Self> {
+        let netns = netns.try_clone_to_owned()?;
+        Ok(Self {
+            socket: with_netns_fd(netns.as_fd(), NetlinkSocket::new)?,
+            netns: Some(netns),
+        })
+    }
 
-    // Receive all responses
-    let mut recv_buf = [0u8; BUFF_SZ];
-    loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
+    /// Make the socket of the session wait on the tokio reactor, for
+    /// [`AsyncNftSession`](crate::AsyncNftSession).
+    #[cfg(feature = "tokio")]
+    pub(crate) fn register(&mut self) -> Result<()> {
+        Ok(self.socket.register()?)
+    }
 
-        if recv_len < NlMsgHdr::SIZE {
-            return Err(IpSetError::ProtocolError);
+    /// Run `fut` in the network namespace of the session, so that the
+    /// requests it makes on other sockets reach the same tables.
+    async fn enter<F: Future>(&self, fut: F) -> F::Output {
+        match &self.netns {
+            Some(netns) => with_netns_fd_async(netns.as_fd(), fut).await,
+            None => fut.await,
         }
+    }
 
-        // Check for error
-        if let Some(error) = parse_nlmsg_error(&recv_buf[..recv_len]) {
-            if error == 0 {
-                // Continue reading
-            } else {
-                match -error {
-                    libc::ENOENT => {
-                        if cmd == NFT_MSG_DELSETELEM {
-                            return Err(IpSetError::ElementNotFound);
-                        }
-                        return Err(IpSetError::SetNotFound(setname.to_string()));
-                    }
-                    libc::EEXIST => return Err(IpSetError::ElementExists),
-                    _ => return Err(IpSetError::NetlinkError(-error)),
-                }
-            }
-        }
+    /// [`NftSession::add`] for async code.
+    pub(crate) async fn add_async<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        let entry = entry.into();
+        self.enter(nftset_operate(
+            &self.socket,
+            family,
+            table,
+            setname,
+            &entry,
+            None,
+            NFT_MSG_NEWSETELEM,
+        ))
+        .await
+    }
 
-        // Check for NLMSG_DONE
-        if is_nlmsg_done(&recv_buf[..recv_len]) {
-            break;
-        }
+    /// [`NftSession::add_many`] for async code.
+    pub(crate) async fn add_many_async<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        self.enter(nftset_operate_many(
+            &self.socket,
+            family,
+            table,
+            setname,
+            entries.into_iter().map(Into::into),
+            NFT_MSG_NEWSETELEM,
+        ))
+        .await
+    }
 
-        // Check message type to determine if we should continue
-        let msg_type = get_nlmsg_type(&recv_buf[..recv_len]);
-        if msg_type == Some(crate::netlink::NLMSG_ERROR) {
-            // Already handled above
-            break;
-        }
+    /// [`NftSession::del_many`] for async code.
+    pub(crate) async fn del_many_async<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Result<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        self.enter(nftset_del_entries(
+            &self.socket,
+            family,
+            table,
+            setname,
+            entries.into_iter().map(Into::into),
+        ))
+        .await
     }
 
-    Ok(())
+    /// [`NftSession::del`] for async code.
+    pub(crate) async fn del_async<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<()> {
+        let entry = entry.into();
+        self.enter(nftset_operate(
+            &self.socket,
+            family,
+            table,
+            setname,
+            &entry,
+            None,
+            NFT_MSG_DELSETELEM,
+        ))
+        .await
+    }
+
+    /// [`NftSession::test`] for async code.
+    pub(crate) async fn test_async<E: Into<Entry>>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entry: E,
+    ) -> Result<bool> {
+        let key = nft_key_bytes(&entry.into())?;
+        self.enter(nftset_test_key_exists_on(
+            &self.socket,
+            family,
+            table,
+            setname,
+            &key,
+        ))
+        .await
+    }
+
+    /// [`NftSession::test_many`] for async code.
+    pub(crate) async fn test_many_async<I, E>(
+        &self,
+        family: NftFamily,
+        table: &str,
+        setname: &str,
+        entries: I,
+    ) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        self.enter(nftset_test_entries(
+            &self.socket,
+            family,
+            table,
+            setname,
+            entries.into_iter().map(Into::into),
+        ))
+        .await
+    }
+
+    /// [`NftSession::commit`] for async code.
+    pub(crate) async fn commit_async(&self, tx: NftTransaction) -> Result<()> {
+        self.socket.drain()?;
+        self.enter(tx.commit_on(&self.socket)).await
+    }
 }
 
-/// Add an IP address to an nftables set.
+/// List all IP addresses in an nftables set.
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
+/// * `family` - The nftables address family
 /// * `table` - The table name
 /// * `setname` - The set name
-/// * `entry` - The IP entry to add (can be created from IpAddr)
+///
+/// # Returns
+///
+/// A vector of IP addresses currently in the set.
 ///
 /// # Example
 ///
 /// ```no_run
-/// use std::net::IpAddr;
-/// use ruhop_ipset::nftset_add;
+/// use ripset::{NftFamily, nftset_list};
 ///
-/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
-/// nftset_add("inet", "filter", "myset", addr).unwrap();
+/// let ips = nftset_list(NftFamily::Inet, "filter", "myset").unwrap();
+/// for ip in ips {
+///     println!("{}", ip);
+/// }
 /// ```
-pub fn nftset_add<E: Into<IpEntry>>(
-    family: &str,
+pub fn nftset_list(family: NftFamily, table: &str, setname: &str) -> Result<Vec<IpAddr>> {
+    block_on(nftset_list_async(family, table, setname))
+}
+
+/// [`nftset_list`] for async code.
+pub(crate) async fn nftset_list_async(
+    family: NftFamily,
     table: &str,
     setname: &str,
-    entry: E,
-) -> Result<()> {
-    nftset_operate(family, table, setname, &entry.into(), NFT_MSG_NEWSETELEM)
+) -> Result<Vec<IpAddr>> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    nft_dump_retry(move || async move {
+        let mut result = Vec::new();
+        nftset_elem_dump(family, table, setname, |attrs| {
+            parse_nftset_elem_message(attrs, &mut result)
+        })
+        .await?;
+        Ok(result)
+    })
+    .await
 }
 
-/// Delete an IP address from an nftables set.
+/// List the entries of an nftables set.
+///
+/// Unlike [`nftset_list`], ranges in interval sets are returned whole: as a
+/// CIDR block (`cidr`) when they are one, and as an address range (`ip_to`)
+/// otherwise. `timeout` holds the remaining time of expiring elements.
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
+/// * `family` - The nftables address family
 /// * `table` - The table name
 /// * `setname` - The set name
-/// * `entry` - The IP entry to delete (can be created from IpAddr)
 ///
 /// # Example
 ///
 /// ```no_run
-/// use std::net::IpAddr;
-/// use ruhop_ipset::nftset_del;
+/// use ripset::{NftFamily, Entry, nftset_list_entries};
 ///
-/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
-/// nftset_del("inet", "filter", "myset", addr).unwrap();
+/// for entry in nftset_list_entries(NftFamily::Inet, "filter", "blocklist").unwrap() {
+///     if let Entry::Ip(ip) = entry {
+///         println!("{}/{}", ip.addr, ip.cidr.unwrap_or(32));
+///     }
+/// }
 /// ```
-pub fn nftset_del<E: Into<IpEntry>>(
-    family: &str,
+pub fn nftset_list_entries(family: NftFamily, table: &str, setname: &str) -> Result<Vec<Entry>> {
+    block_on(nftset_list_entries_async(family, table, setname))
+}
+
+/// [`nftset_list_entries`] for async code.
+pub(crate) async fn nftset_list_entries_async(
+    family: NftFamily,
     table: &str,
     setname: &str,
-    entry: E,
-) -> Result<()> {
-    nftset_operate(family, table, setname, &entry.into(), NFT_MSG_DELSETELEM)
+) -> Result<Vec<Entry>> {
+    let (_, entries) = nftset_dump_entries(family, table, setname).await?;
+    Ok(entries.into_iter().map(|(entry, _)| entry).collect())
 }
 
-/// Test if an IP address exists in an nftables set.
+/// Iterate over the entries of an nftables set as they are received.
+///
+/// Unlike [`nftset_list_entries`], entries are decoded from one dump
+/// message at a time instead of being collected first, so going through a
+/// set of millions of elements takes little memory. Ranges of interval
+/// sets are put together on the fly, as the kernel dumps the end of each
+/// range right before its start.
+///
+/// A missing table or set is reported by this function. Errors during the
+/// dump end the iteration with an error item; this includes the set being
+/// changed meanwhile (`EINTR`) and a socket overflow (`ENOBUFS`), which
+/// can't be recovered from without returning entries twice. Listing again
+/// from the start usually succeeds.
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
+/// * `family` - The nftables address family
 /// * `table` - The table name
 /// * `setname` - The set name
-/// * `entry` - The IP entry to test (can be created from IpAddr)
 ///
-/// # Returns
+/// # Example
 ///
-/// * `Ok(true)` - The IP address exists in the set
-/// * `Ok(false)` - The IP address does not exist in the set
-/// * `Err(_)` - An error occurred
+/// ```no_run
+/// use ripset::{NftFamily, nftset_list_iter};
+///
+/// let mut count = 0;
+/// for entry in nftset_list_iter(NftFamily::Inet, "filter", "blocklist").unwrap() {
+///     entry.unwrap();
+///     count += 1;
+/// }
+/// println!("{count} entries");
+/// ```
+pub fn nftset_list_iter(family: NftFamily, table: &str, setname: &str) -> Result<NftSetListIter> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    block_on(nft_dump_retry(move || async move {
+        let set = nftset_get_set(family, table, setname).await?;
+        let request = nftset_elem_request(NFT_MSG_GETSETELEM, family, table, setname);
+        let mut iter = NftSetListIter {
+            set_type: set.set_type.clone().unwrap_or(NftSetType::Ipv4Addr),
+            set,
+            dump: Some(DumpStream::new(request.as_slice())?),
+            elements: Vec::new().into_iter(),
+            end: None,
+        };
+        // Failures before the first entry can still be retried
+        iter.fill().await?;
+        Ok(iter)
+    }))
+}
+
+/// Iterator over the entries of an nftables set, returned by
+/// [`nftset_list_iter`].
+pub struct NftSetListIter {
+    set: NftSetInfo,
+    /// Key type of the set, addresses if unknown.
+    set_type: NftSetType,
+    /// The dump, until it is over or failed.
+    dump: Option<DumpStream>,
+    /// Elements decoded from the last message.
+    elements: std::vec::IntoIter<NftElement>,
+    /// End marker waiting for the start of its range.
+    end: Option<NftElement>,
+}
+
+impl NftSetListIter {
+    /// Decode the elements of the next message of the dump.
+    async fn fill(&mut self) -> Result<()> {
+        let Some(dump) = &mut self.dump else {
+            return Ok(());
+        };
+        let elements: Result<Option<Vec<NftElement>>> = match dump.next_message_async().await {
+            Ok(Some(message)) => nftset_elem_attrs(NFT_MSG_GETSETELEM, message, &self.set.name)
+                .map(|attrs| attrs.map(|attrs| message_elements(attrs).collect())),
+            Ok(None) => {
+                self.dump = None;
+                return Ok(());
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        match elements {
+            Ok(elements) => {
+                self.elements = elements.unwrap_or_default().into_iter();
+                Ok(())
+            }
+            Err(e) => {
+                self.dump = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// The entry an element completes, if any.
+    fn decode(&mut self, element: NftElement) -> Option<Entry> {
+        if !split_ranges(&self.set, &self.set_type) {
+            return element_entry(&self.set, &self.set_type, &element);
+        }
+        if element.flags & NFT_SET_ELEM_INTERVAL_END != 0 {
+            self.end = Some(element);
+            return None;
+        }
+        // Ranges are dumped from the highest down, so the end marker before
+        // a start closes its range unless the range is open
+        let end = self.end.take().filter(|end| end.key > element.key);
+        split_range_entry(&self.set_type, &element, end.as_ref())
+    }
+}
+
+impl Iterator for NftSetListIter {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while let Some(element) = self.elements.next() {
+                if let Some(entry) = self.decode(element) {
+                    return Some(Ok(entry));
+                }
+            }
+            self.dump.as_ref()?;
+            if let Err(e) = block_on(self.fill()) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// An element of an nftables set, as listed by [`nftset_list_elements`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NftSetElement {
+    /// The element, as returned by [`nftset_list_entries`]
+    pub entry: Entry,
+    /// Timeout the element was added with, in seconds
+    pub timeout: Option<u32>,
+    /// Remaining time in seconds before the element expires
+    pub expiration: Option<u32>,
+    /// Packets matched (sets created with counters)
+    pub packets: Option<u64>,
+    /// Bytes matched (sets created with counters)
+    pub bytes: Option<u64>,
+}
+
+/// List the elements of an nftables set along with their timeouts and
+/// counters.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
 ///
 /// # Example
 ///
 /// ```no_run
-/// use std::net::IpAddr;
-/// use ruhop_ipset::nftset_test;
+/// use ripset::{NftFamily, nftset_list_elements};
 ///
-/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
-/// let exists = nftset_test("inet", "filter", "myset", addr).unwrap();
+/// for element in nftset_list_elements(NftFamily::Inet, "filter", "banned").unwrap() {
+///     if let Some(expiration) = element.expiration {
+///         println!("{:?} expires in {}s", element.entry, expiration);
+///     }
+/// }
 /// ```
-pub fn nftset_test<E: Into<IpEntry>>(
-    family: &str,
+pub fn nftset_list_elements(
+    family: NftFamily,
     table: &str,
     setname: &str,
-    entry: E,
-) -> Result<bool> {
-    let entry = entry.into();
-    nftset_test_ip_exists(family, table, setname, &entry.addr)
+) -> Result<Vec<NftSetElement>> {
+    block_on(nftset_list_elements_async(family, table, setname))
 }
 
-/// List all IP addresses in an nftables set.
+/// [`nftset_list_elements`] for async code.
+pub(crate) async fn nftset_list_elements_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<Vec<NftSetElement>> {
+    let (set, entries) = nftset_dump_entries(family, table, setname).await?;
+    Ok(set_elements(&set, entries))
+}
+
+/// Zero the packet and byte counters of every element of an nftables set.
+///
+/// Each counter is read and reset at once by the kernel
+/// (`NFT_MSG_GETSETELEM_RESET`, Linux 6.5 or later), so no packet goes
+/// uncounted between two collections. Returns the elements with their
+/// counters before the reset.
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
+/// * `family` - The nftables address family
 /// * `table` - The table name
 /// * `setname` - The set name
 ///
-/// # Returns
+/// # Example
 ///
-/// A vector of IP addresses currently in the set.
+/// ```no_run
+/// use ripset::{NftFamily, nftset_reset_counters};
+///
+/// for element in nftset_reset_counters(NftFamily::Inet, "filter", "accounting").unwrap() {
+///     println!("{}: {} bytes", element.entry, element.bytes.unwrap_or(0));
+/// }
+/// ```
+pub fn nftset_reset_counters(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<Vec<NftSetElement>> {
+    block_on(nftset_reset_counters_async(family, table, setname))
+}
+
+/// [`nftset_reset_counters`] for async code.
+pub(crate) async fn nftset_reset_counters_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<Vec<NftSetElement>> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+    if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    // Not retried: the counters dumped by a failed attempt are already reset
+    let set = nftset_get_set(family, table, setname).await?;
+    let mut elements = Vec::new();
+    nftset_elem_dump_msg(NFT_MSG_GETSETELEM_RESET, family, table, setname, |attrs| {
+        elements.extend(message_elements(attrs));
+    })
+    .await?;
+    let entries = set_entries(&set, elements);
+    Ok(set_elements(&set, entries))
+}
+
+/// Zero the packet and byte counters of one element of an nftables set.
+///
+/// The element is found like [`nftset_test`] finds it, so in interval sets
+/// an address resets the range holding it. Requires Linux 6.5 or later.
+///
+/// Fails with [`RipsetError::EntryNotFound`] if the element is not in the
+/// set.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+/// * `setname` - The set name
+/// * `entry` - The element whose counters are reset
 ///
 /// # Example
 ///
 /// ```no_run
-/// use linux_ipsets::nftset_list;
+/// use std::net::IpAddr;
+/// use ripset::{NftFamily, nftset_reset_entry_counters};
 ///
-/// let ips = nftset_list("inet", "filter", "myset").unwrap();
-/// for ip in ips {
-///     println!("{}", ip);
-/// }
+/// let addr: IpAddr = "192.168.1.1".parse().unwrap();
+/// nftset_reset_entry_counters(NftFamily::Inet, "filter", "accounting", addr).unwrap();
 /// ```
-pub fn nftset_list(family: &str, table: &str, setname: &str) -> Result<Vec<IpAddr>> {
+pub fn nftset_reset_entry_counters<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    block_on(nftset_reset_entry_counters_async(
+        family, table, setname, entry,
+    ))
+}
+
+/// [`nftset_reset_entry_counters`] for async code.
+pub(crate) async fn nftset_reset_entry_counters_async<E: Into<Entry>>(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    entry: E,
+) -> Result<()> {
+    let key = nft_key_bytes(&entry.into())?;
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+    buf.put_nlmsghdr(
+        nft_msg_type(NFT_MSG_GETSETELEM_RESET),
+        NLM_F_REQUEST | NLM_F_ACK,
+        0,
+    );
+    put_setelem_key(&mut buf, family, table, setname, &key);
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
+    match parse_nlmsg_error(&recv_buf[..recv_len]) {
+        None | Some(0) => Ok(()),
+        Some(error) if -error == libc::ENOENT => Err(RipsetError::EntryNotFound),
+        Some(error) => Err(errno_error(-error, &recv_buf[..recv_len])),
+    }
+}
+
+/// The elements of a set, with their state, from its decoded entries.
+fn set_elements(set: &NftSetInfo, entries: Vec<(Entry, NftElement)>) -> Vec<NftSetElement> {
+    entries
+        .into_iter()
+        .map(|(entry, element)| NftSetElement {
+            entry,
+            // Elements without their own timeout use the set default
+            timeout: element.timeout.or(set.timeout),
+            expiration: element.expiration,
+            packets: element.counters.map(|(packets, _)| packets),
+            bytes: element.counters.map(|(_, bytes)| bytes),
+        })
+        .collect()
+}
+
+/// Dump the elements of a set as the kernel stores them.
+async fn nftset_dump_elements(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<(NftSetInfo, Vec<NftElement>)> {
+    nft_dump_retry(move || async move {
+        let set = nftset_get_set(family, table, setname).await?;
+
+        let mut elements = Vec::new();
+        nftset_elem_dump(family, table, setname, |attrs| {
+            elements.extend(message_elements(attrs));
+        })
+        .await?;
+        Ok((set, elements))
+    })
+    .await
+}
+
+/// Dump the entries of a set, each with the element holding its value.
+///
+/// Ranges in interval sets are put back together from their start and end
+/// elements.
+async fn nftset_dump_entries(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<(NftSetInfo, Vec<(Entry, NftElement)>)> {
     if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidTableName(table.to_string()));
+        return Err(RipsetError::InvalidTableName(table.to_string()));
     }
     if setname.is_empty() || setname.len() >= NFT_SET_MAXNAMELEN {
-        return Err(IpSetError::InvalidSetName(setname.to_string()));
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    let (set, elements) = nftset_dump_elements(family, table, setname).await?;
+    let entries = set_entries(&set, elements);
+    Ok((set, entries))
+}
+
+/// Decode the elements of a set into entries, each with the element holding
+/// its value.
+///
+/// Ranges in interval sets are put back together from their start and end
+/// elements.
+fn set_entries(set: &NftSetInfo, mut elements: Vec<NftElement>) -> Vec<(Entry, NftElement)> {
+    // Keys of unknown types are listed if they look like addresses
+    let set_type = set.set_type.clone().unwrap_or(NftSetType::Ipv4Addr);
+    if !split_ranges(set, &set_type) {
+        return elements
+            .into_iter()
+            .filter_map(|element| Some((element_entry(set, &set_type, &element)?, element)))
+            .collect();
+    }
+
+    // The kernel dumps interval sets in tree order: sort the start and end
+    // markers by key (ends first, for adjacent ranges) and pair them up
+    elements.sort_by(|a, b| {
+        let a_start = a.flags & NFT_SET_ELEM_INTERVAL_END == 0;
+        let b_start = b.flags & NFT_SET_ELEM_INTERVAL_END == 0;
+        (&a.key, a_start).cmp(&(&b.key, b_start))
+    });
+
+    let mut entries = Vec::new();
+    let mut elements = elements.into_iter().peekable();
+    while let Some(start) = elements.next() {
+        if start.flags & NFT_SET_ELEM_INTERVAL_END != 0 {
+            continue;
+        }
+        let end = elements.next_if(|e| e.flags & NFT_SET_ELEM_INTERVAL_END != 0);
+        if let Some(entry) = split_range_entry(&set_type, &start, end.as_ref()) {
+            entries.push((entry, start));
+        }
+    }
+    entries
+}
+
+/// Whether the ranges of a set are stored as separate start and end
+/// elements, which is the case for interval sets without concatenations.
+fn split_ranges(set: &NftSetInfo, set_type: &NftSetType) -> bool {
+    set.interval && !matches!(set_type, NftSetType::Concat(_))
+}
+
+/// Decode an element holding a whole value into its entry.
+fn element_entry(set: &NftSetInfo, set_type: &NftSetType, element: &NftElement) -> Option<Entry> {
+    let mut entry = if set.interval {
+        // Concatenated ranges come as single elements holding both ends
+        let last = element.key_end.as_deref().unwrap_or(&element.key);
+        set_type.range_from_keys(&element.key, last)?
+    } else {
+        set_type.entry_from_key(&element.key)?
+    };
+    set_entry_state(&mut entry, element);
+    Some(entry)
+}
+
+/// Decode a range from its start element and the end marker closing it.
+fn split_range_entry(
+    set_type: &NftSetType,
+    start: &NftElement,
+    end: Option<&NftElement>,
+) -> Option<Entry> {
+    let last = match end {
+        // The end marker is the first value past the range
+        Some(end) => key_predecessor(&end.key),
+        // An open range extends to the last value
+        None => vec![0xff; start.key.len()],
+    };
+    let mut entry = set_type.range_from_keys(&start.key, &last)?;
+    set_entry_state(&mut entry, start);
+    Some(entry)
+}
+
+/// Fill in the remaining timeout and the counters of a listed entry.
+fn set_entry_state(entry: &mut Entry, element: &NftElement) {
+    let Some(ip) = entry_address_mut(entry) else {
+        return;
+    };
+    ip.timeout = element.expiration;
+    ip.packets = element.counters.map(|(packets, _)| packets);
+    ip.bytes = element.counters.map(|(_, bytes)| bytes);
+}
+
+/// The address carrying the timeout and counters of an entry: the entry
+/// itself, or the leading address of a concatenation like in ipset entries.
+fn entry_address_mut(entry: &mut Entry) -> Option<&mut IpEntry> {
+    match entry {
+        Entry::Ip(ip) => Some(ip),
+        Entry::Concat(parts) => match parts.first_mut() {
+            Some(Entry::Ip(ip)) => Some(ip),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The key just below `key`, read as a big-endian number.
+fn key_predecessor(key: &[u8]) -> Vec<u8> {
+    let mut key = key.to_vec();
+    for byte in key.iter_mut().rev() {
+        let (value, borrow) = byte.overflowing_sub(1);
+        *byte = value;
+        if !borrow {
+            break;
+        }
+    }
+    key
+}
+
+/// Describe the inclusive range `first..=last` as a single address, a CIDR
+/// block or an address range.
+fn range_entry(first: IpAddr, last: IpAddr) -> IpEntry {
+    let bits = if first.is_ipv4() { 32 } else { 128 };
+    let span = ip_to_u128(last).wrapping_sub(ip_to_u128(first));
+    if span == 0 {
+        IpEntry::new(first)
+    } else if span & span.wrapping_add(1) == 0 && ip_to_u128(first) & span == 0 {
+        IpEntry::with_cidr(first, bits - span.count_ones() as u8)
+    } else {
+        IpEntry::with_range(first, last)
+    }
+}
+
+/// Parse the key, flags, timeouts and value of a listed element.
+fn parse_nftset_element(data: &[u8]) -> Option<NftElement> {
+    let mut key = None;
+    let mut key_end = None;
+    let mut flags = 0;
+    let mut timeout = None;
+    let mut expiration = None;
+    let mut counters = None;
+    let mut elem_data = None;
+
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_SET_ELEM_KEY => {
+                key = NlAttrIter::new(payload)
+                    .find(|&(data_type, _)| data_type == NFTA_DATA_VALUE)
+                    .map(|(_, value)| value.to_vec())
+            }
+            NFTA_SET_ELEM_KEY_END => {
+                key_end = NlAttrIter::new(payload)
+                    .find(|&(data_type, _)| data_type == NFTA_DATA_VALUE)
+                    .map(|(_, value)| value.to_vec())
+            }
+            NFTA_SET_ELEM_DATA => {
+                elem_data =
+                    NlAttrIter::new(payload).find_map(|(data_type, value)| match data_type {
+                        NFTA_DATA_VALUE => Some(NftData::Value(value.to_vec())),
+                        NFTA_DATA_VERDICT => parse_nft_verdict(value).map(NftData::Verdict),
+                        _ => None,
+                    })
+            }
+            NFTA_SET_ELEM_FLAGS => flags = nla_get_u32_be(payload).unwrap_or(0),
+            // Timeouts are in milliseconds
+            NFTA_SET_ELEM_TIMEOUT => timeout = nla_get_u64_be(payload).map(|ms| (ms / 1000) as u32),
+            NFTA_SET_ELEM_EXPIRATION => {
+                expiration = nla_get_u64_be(payload).map(|ms| (ms / 1000) as u32)
+            }
+            NFTA_SET_ELEM_EXPR => counters = parse_nft_counter(payload),
+            NFTA_SET_ELEM_EXPRESSIONS => {
+                counters = NlAttrIter::new(payload).find_map(|(_, expr)| parse_nft_counter(expr))
+            }
+            _ => {}
+        }
+    }
+
+    Some(NftElement {
+        key: key?,
+        key_end,
+        flags,
+        timeout,
+        expiration,
+        counters,
+        data: elem_data,
+    })
+}
+
+/// Parse the packet and byte counts of a counter expression.
+fn parse_nft_counter(data: &[u8]) -> Option<(u64, u64)> {
+    let mut is_counter = false;
+    let mut counters = (0, 0);
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_EXPR_NAME => is_counter = nla_get_str(payload).as_deref() == Some("counter"),
+            NFTA_EXPR_DATA => {
+                for (counter_type, value) in NlAttrIter::new(payload) {
+                    match counter_type {
+                        NFTA_COUNTER_PACKETS => counters.0 = nla_get_u64_be(value).unwrap_or(0),
+                        NFTA_COUNTER_BYTES => counters.1 = nla_get_u64_be(value).unwrap_or(0),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
     }
+    is_counter.then_some(counters)
+}
 
-    let nf_family = parse_nf_family(family)?;
+/// Write a verdict as a nested NFTA_DATA_VERDICT attribute.
+fn put_nft_verdict(buf: &mut MsgBuffer, verdict: &NftVerdict) -> Result<()> {
+    let (code, chain) = match verdict {
+        NftVerdict::Accept => (NF_ACCEPT, None),
+        NftVerdict::Drop => (NF_DROP, None),
+        NftVerdict::Continue => (NFT_CONTINUE, None),
+        NftVerdict::Return => (NFT_RETURN, None),
+        NftVerdict::Jump(chain) => (NFT_JUMP, Some(chain)),
+        NftVerdict::Goto(chain) => (NFT_GOTO, Some(chain)),
+    };
+    if chain.is_some_and(|chain| chain.is_empty() || chain.len() >= NFT_SET_MAXNAMELEN) {
+        return Err(RipsetError::InvalidOption(format!(
+            "invalid verdict: {verdict}"
+        )));
+    }
 
-    // Build GETSETELEM message with DUMP flag
-    let mut buf = MsgBuffer::new(BUFF_SZ);
+    let verdict_offset = buf.start_nested(NFTA_DATA_VERDICT);
+    buf.put_attr_u32_nft(NFTA_VERDICT_CODE, code as u32);
+    if let Some(chain) = chain {
+        buf.put_attr_str(NFTA_VERDICT_CHAIN, chain);
+    }
+    buf.end_nested(verdict_offset);
+    Ok(())
+}
 
-    buf.put_nlmsghdr(
-        nft_msg_type(NFT_MSG_GETSETELEM),
-        NLM_F_REQUEST | NLM_F_DUMP,
-        0,
-    );
-    buf.put_nfgenmsg(nf_family, 0, 0);
+/// Parse a nested NFTA_DATA_VERDICT attribute.
+fn parse_nft_verdict(data: &[u8]) -> Option<NftVerdict> {
+    let mut code = None;
+    let mut chain = None;
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_VERDICT_CODE => code = nla_get_u32_be(payload).map(|code| code as i32),
+            NFTA_VERDICT_CHAIN => chain = nla_get_str(payload),
+            _ => {}
+        }
+    }
 
-    buf.put_attr_str(NFTA_SET_ELEM_LIST_TABLE, table);
-    buf.put_attr_str(NFTA_SET_ELEM_LIST_SET, setname);
+    match code? {
+        NF_ACCEPT => Some(NftVerdict::Accept),
+        NF_DROP => Some(NftVerdict::Drop),
+        NFT_CONTINUE => Some(NftVerdict::Continue),
+        NFT_RETURN => Some(NftVerdict::Return),
+        NFT_JUMP => Some(NftVerdict::Jump(chain?)),
+        NFT_GOTO => Some(NftVerdict::Goto(chain?)),
+        _ => None,
+    }
+}
 
-    buf.finalize_nlmsg();
+/// Dump the elements of a set with GETSETELEM, passing the attributes of
+/// each NEWSETELEM message to `handle`.
+async fn nftset_elem_dump(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    handle: impl FnMut(&[u8]),
+) -> Result<()> {
+    nftset_elem_dump_msg(NFT_MSG_GETSETELEM, family, table, setname, handle).await
+}
 
-    let socket = NetlinkSocket::new()?;
-    socket.send(buf.as_slice())?;
+/// Dump the elements of a set with the given request, GETSETELEM or
+/// GETSETELEM_RESET.
+async fn nftset_elem_dump_msg(
+    msg: u16,
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+    mut handle: impl FnMut(&[u8]),
+) -> Result<()> {
+    let mut dump = DumpStream::new(nftset_elem_request(msg, family, table, setname).as_slice())?;
+    while let Some(message) = dump.next_message_async().await? {
+        if let Some(attrs) = nftset_elem_attrs(msg, message, setname)? {
+            handle(attrs);
+        }
+    }
+    Ok(())
+}
 
-    let mut result = Vec::new();
-    let mut recv_buf = [0u8; 16384]; // Larger buffer for dump responses
+/// Build an element dump request, GETSETELEM or GETSETELEM_RESET.
+fn nftset_elem_request(msg: u16, family: NftFamily, table: &str, setname: &str) -> MsgBuffer {
+    let mut buf = MsgBuffer::new(BUFF_SZ);
 
-    loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
-        if recv_len < NlMsgHdr::SIZE {
-            break;
-        }
+    buf.put_nlmsghdr(nft_msg_type(msg), NLM_F_REQUEST | NLM_F_DUMP, 0);
+    buf.put_nfgenmsg(family.nfproto(), 0, 0);
 
-        // Process all messages in the buffer
-        let mut offset = 0;
-        while offset + NlMsgHdr::SIZE <= recv_len {
-            let hdr: NlMsgHdr =
-                unsafe { std::ptr::read_unaligned(recv_buf[offset..].as_ptr() as *const NlMsgHdr) };
+    buf.put_attr_str(NFTA_SET_ELEM_LIST_TABLE, table);
+    buf.put_attr_str(NFTA_SET_ELEM_LIST_SET, setname);
 
-            if hdr.nlmsg_len as usize > recv_len - offset {
-                break;
-            }
+    buf.finalize_nlmsg();
+    buf
+}
 
-            // Check for NLMSG_DONE
-            if is_nlmsg_done(&recv_buf[offset..]) {
-                return Ok(result);
-            }
+/// The attributes of a NEWSETELEM message answering the element dump
+/// request `msg`, `None` for other messages, or the error an error answer
+/// stands for.
+fn nftset_elem_attrs<'a>(msg: u16, message: &'a [u8], setname: &str) -> Result<Option<&'a [u8]>> {
+    let Some(hdr) = header(message) else {
+        return Ok(None);
+    };
 
-            // Check for error
-            if let Some(error) =
-                parse_nlmsg_error(&recv_buf[offset..offset + hdr.nlmsg_len as usize])
-            {
-                if error != 0 {
-                    match -error {
-                        libc::ENOENT => return Err(IpSetError::SetNotFound(setname.to_string())),
-                        _ => return Err(IpSetError::NetlinkError(-error)),
-                    }
-                }
-            } else {
-                // Check if this is a NEWSETELEM message (response to GETSETELEM dump)
-                let expected_type = nft_msg_type(NFT_MSG_NEWSETELEM);
-                if hdr.nlmsg_type == expected_type {
-                    // Parse the message for IP addresses
-                    let msg_end = offset + hdr.nlmsg_len as usize;
-                    let attr_start = offset + NlMsgHdr::SIZE + NfGenMsg::SIZE;
-                    if attr_start < msg_end {
-                        parse_nftset_elem_message(&recv_buf[attr_start..msg_end], &mut result);
-                    }
-                }
-            }
+    // The ruleset changed while it was being dumped. A resetting dump goes
+    // on, as restarting it would lose the counters already reset.
+    if hdr.nlmsg_flags & NLM_F_DUMP_INTR != 0 && msg != NFT_MSG_GETSETELEM_RESET {
+        return Err(RipsetError::NetlinkError(libc::EINTR));
+    }
 
-            offset += nla_align(hdr.nlmsg_len as usize);
-        }
+    match parse_nlmsg_error(message) {
+        Some(0) => Ok(None),
+        Some(error) if -error == libc::ENOENT => Err(RipsetError::SetNotFound(setname.to_string())),
+        Some(error) => Err(errno_error(-error, message)),
+        None if hdr.nlmsg_type == nft_msg_type(NFT_MSG_NEWSETELEM) => Ok(message
+            .get(NlMsgHdr::SIZE + NfGenMsg::SIZE..)
+            .filter(|attrs| !attrs.is_empty())),
+        None => Ok(None),
     }
+}
 
-    Ok(result)
+/// The elements carried by the attributes of a NEWSETELEM message.
+fn message_elements(attrs: &[u8]) -> impl Iterator<Item = NftElement> + '_ {
+    NlAttrIter::new(attrs)
+        .filter(|&(attr_type, _)| attr_type == NFTA_SET_ELEM_LIST_ELEMENTS)
+        .flat_map(|(_, elems)| NlAttrIter::new(elems))
+        .filter_map(|(_, elem)| parse_nftset_element(elem))
 }
 
 /// Parse a NEWSETELEM message to extract IP addresses.
@@ -1036,28 +4874,21 @@ fn parse_nftset_elements_list(data: &[u8], result: &mut Vec<IpAddr>) {
 }
 
 /// Parse a single element to extract the IP address from its KEY attribute.
+///
+/// Interval end markers are skipped, so interval sets list the first
+/// address of each range.
 fn parse_nftset_single_element(data: &[u8]) -> Option<IpAddr> {
-    let mut offset = 0;
-
-    while offset + NlAttr::SIZE <= data.len() {
-        let attr_len = u16::from_ne_bytes([data[offset], data[offset + 1]]) as usize;
-        let attr_type = u16::from_ne_bytes([data[offset + 2], data[offset + 3]]);
-
-        if attr_len < NlAttr::SIZE || offset + attr_len > data.len() {
-            break;
-        }
-
-        let attr_type_masked = attr_type & !NLA_F_NESTED;
-
-        // NFTA_SET_ELEM_KEY contains the key (IP address)
-        if attr_type_masked == NFTA_SET_ELEM_KEY {
-            return parse_nftset_data_value(&data[offset + NlAttr::SIZE..offset + attr_len]);
-        }
-
-        offset += nla_align(attr_len);
+    let is_end = NlAttrIter::new(data)
+        .find(|&(attr_type, _)| attr_type == NFTA_SET_ELEM_FLAGS)
+        .and_then(|(_, flags)| nla_get_u32_be(flags))
+        .is_some_and(|flags| flags & NFT_SET_ELEM_INTERVAL_END != 0);
+    if is_end {
+        return None;
     }
 
-    None
+    // NFTA_SET_ELEM_KEY contains the key (IP address)
+    let (_, key) = NlAttrIter::new(data).find(|&(attr_type, _)| attr_type == NFTA_SET_ELEM_KEY)?;
+    parse_nftset_data_value(key)
 }
 
 /// Parse NFTA_DATA_VALUE to get the actual IP address bytes.
@@ -1098,7 +4929,7 @@ fn parse_nftset_data_value(data: &[u8]) -> Option<IpAddr> {
 ///
 /// # Arguments
 ///
-/// * `family` - The address family ("inet", "ip", "ip6")
+/// * `family` - The nftables address family
 ///
 /// # Returns
 ///
@@ -1107,15 +4938,25 @@ fn parse_nftset_data_value(data: &[u8]) -> Option<IpAddr> {
 /// # Example
 ///
 /// ```no_run
-/// use linux_ipsets::nftset_list_tables;
+/// use ripset::{NftFamily, nftset_list_tables};
 ///
-/// let tables = nftset_list_tables("inet").unwrap();
+/// let tables = nftset_list_tables(NftFamily::Inet).unwrap();
 /// for table in tables {
 ///     println!("{}", table);
 /// }
 /// ```
-pub fn nftset_list_tables(family: &str) -> Result<Vec<String>> {
-    let nf_family = parse_nf_family(family)?;
+pub fn nftset_list_tables(family: NftFamily) -> Result<Vec<String>> {
+    block_on(nftset_list_tables_async(family))
+}
+
+/// [`nftset_list_tables`] for async code.
+pub(crate) async fn nftset_list_tables_async(family: NftFamily) -> Result<Vec<String>> {
+    nft_dump_retry(move || nftset_dump_tables(family)).await
+}
+
+/// Dump the table names of a family with GETTABLE.
+async fn nftset_dump_tables(family: NftFamily) -> Result<Vec<String>> {
+    let nf_family = family.nfproto();
 
     // Build GETTABLE message with DUMP flag
     let mut buf = MsgBuffer::new(BUFF_SZ);
@@ -1129,14 +4970,14 @@ pub fn nftset_list_tables(family: &str) -> Result<Vec<String>> {
 
     buf.finalize_nlmsg();
 
-    let socket = NetlinkSocket::new()?;
+    let socket = NetlinkSocket::new_dump()?;
     socket.send(buf.as_slice())?;
 
     let mut result = Vec::new();
-    let mut recv_buf = [0u8; 8192];
+    let mut recv_buf = vec![0u8; DUMP_BUF_SZ];
 
     loop {
-        let recv_len = socket.recv(&mut recv_buf)?;
+        let recv_len = socket.recv_dump_async(&mut recv_buf).await?;
         if recv_len < NlMsgHdr::SIZE {
             break;
         }
@@ -1151,6 +4992,11 @@ pub fn nftset_list_tables(family: &str) -> Result<Vec<String>> {
                 break;
             }
 
+            // The ruleset changed while it was being dumped
+            if hdr.nlmsg_flags & NLM_F_DUMP_INTR != 0 {
+                return Err(RipsetError::NetlinkError(libc::EINTR));
+            }
+
             // Check for NLMSG_DONE
             if is_nlmsg_done(&recv_buf[offset..]) {
                 return Ok(result);
@@ -1161,7 +5007,10 @@ pub fn nftset_list_tables(family: &str) -> Result<Vec<String>> {
                 parse_nlmsg_error(&recv_buf[offset..offset + hdr.nlmsg_len as usize])
             {
                 if error != 0 {
-                    return Err(IpSetError::NetlinkError(-error));
+                    return Err(errno_error(
+                        -error,
+                        &recv_buf[offset..offset + hdr.nlmsg_len as usize],
+                    ));
                 }
             } else {
                 // Check if this is a NEWTABLE message (response to GETTABLE dump)
@@ -1185,6 +5034,36 @@ pub fn nftset_list_tables(family: &str) -> Result<Vec<String>> {
     Ok(result)
 }
 
+/// List the tables of every nftables family.
+///
+/// # Returns
+///
+/// `(family, table_name)` pairs for every table the kernel knows about.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::nftset_list_tables_all;
+///
+/// for (family, table) in nftset_list_tables_all().unwrap() {
+///     println!("{} {}", family, table);
+/// }
+/// ```
+pub fn nftset_list_tables_all() -> Result<Vec<(NftFamily, String)>> {
+    block_on(nftset_list_tables_all_async())
+}
+
+/// [`nftset_list_tables_all`] for async code.
+pub(crate) async fn nftset_list_tables_all_async() -> Result<Vec<(NftFamily, String)>> {
+    let mut result = Vec::new();
+    for family in NftFamily::ALL {
+        for table in nftset_list_tables_async(family).await? {
+            result.push((family, table));
+        }
+    }
+    Ok(result)
+}
+
 /// Parse a NEWTABLE message to extract the table name.
 fn parse_nftset_table_name(data: &[u8]) -> Option<String> {
     let mut offset = 0;
@@ -1214,6 +5093,163 @@ fn parse_nftset_table_name(data: &[u8]) -> Option<String> {
     None
 }
 
+/// Name and key type of an nftables set
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NftSetSummary {
+    pub name: String,
+    /// Key type, if it is one of the types known to this library
+    pub set_type: Option<NftSetType>,
+    /// Raw nftables key type identifier (concatenations combine several types)
+    pub key_type: u32,
+    /// Key length in bytes
+    pub key_len: u32,
+    /// The set is a map
+    pub map: bool,
+    /// Value type of a map, if it is one of the types known to this library
+    pub data_type: Option<NftSetType>,
+}
+
+/// List all sets in an nftables table.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table name
+///
+/// # Returns
+///
+/// The name and key type of each set in the table.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_list_sets};
+///
+/// for set in nftset_list_sets(NftFamily::Inet, "filter").unwrap() {
+///     println!("{} ({:?})", set.name, set.set_type);
+/// }
+/// ```
+pub fn nftset_list_sets(family: NftFamily, table: &str) -> Result<Vec<NftSetSummary>> {
+    block_on(nftset_list_sets_async(family, table))
+}
+
+/// [`nftset_list_sets`] for async code.
+pub(crate) async fn nftset_list_sets_async(
+    family: NftFamily,
+    table: &str,
+) -> Result<Vec<NftSetSummary>> {
+    if table.is_empty() || table.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidTableName(table.to_string()));
+    }
+
+    nft_dump_retry(move || nftset_dump_sets(family, table)).await
+}
+
+/// Dump the sets of a table with GETSET.
+async fn nftset_dump_sets(family: NftFamily, table: &str) -> Result<Vec<NftSetSummary>> {
+    let nf_family = family.nfproto();
+
+    // Build GETSET message with DUMP flag, restricted to the table
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+
+    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETSET), NLM_F_REQUEST | NLM_F_DUMP, 0);
+    buf.put_nfgenmsg(nf_family, 0, 0);
+
+    buf.put_attr_str(NFTA_SET_TABLE, table);
+
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new_dump()?;
+    socket.send(buf.as_slice())?;
+
+    let mut result = Vec::new();
+    let mut recv_buf = vec![0u8; DUMP_BUF_SZ];
+
+    loop {
+        let recv_len = socket.recv_dump_async(&mut recv_buf).await?;
+        if recv_len < NlMsgHdr::SIZE {
+            break;
+        }
+
+        // Process all messages in the buffer
+        let mut offset = 0;
+        while offset + NlMsgHdr::SIZE <= recv_len {
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(recv_buf[offset..].as_ptr() as *const NlMsgHdr) };
+
+            if hdr.nlmsg_len as usize > recv_len - offset {
+                break;
+            }
+
+            // The ruleset changed while it was being dumped
+            if hdr.nlmsg_flags & NLM_F_DUMP_INTR != 0 {
+                return Err(RipsetError::NetlinkError(libc::EINTR));
+            }
+
+            // Check for NLMSG_DONE
+            if is_nlmsg_done(&recv_buf[offset..]) {
+                return Ok(result);
+            }
+
+            // Check for error
+            if let Some(error) =
+                parse_nlmsg_error(&recv_buf[offset..offset + hdr.nlmsg_len as usize])
+            {
+                if error != 0 {
+                    return Err(errno_error(
+                        -error,
+                        &recv_buf[offset..offset + hdr.nlmsg_len as usize],
+                    ));
+                }
+            } else if hdr.nlmsg_type == nft_msg_type(NFT_MSG_NEWSET) {
+                let msg_end = offset + hdr.nlmsg_len as usize;
+                let attr_start = offset + NlMsgHdr::SIZE + NfGenMsg::SIZE;
+                if attr_start < msg_end
+                    && let Some(set) = parse_nftset_summary(&recv_buf[attr_start..msg_end])
+                {
+                    result.push(set);
+                }
+            }
+
+            offset += nla_align(hdr.nlmsg_len as usize);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse a NEWSET message to extract the set name and key type.
+fn parse_nftset_summary(data: &[u8]) -> Option<NftSetSummary> {
+    let mut name = None;
+    let mut key_type = 0;
+    let mut key_len = 0;
+    let mut flags = 0;
+    let mut data_type = None;
+
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_SET_NAME => name = nla_get_str(payload),
+            NFTA_SET_KEY_TYPE => key_type = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_SET_KEY_LEN => key_len = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_SET_FLAGS => flags = nla_get_u32_be(payload).unwrap_or(0),
+            NFTA_SET_DATA_TYPE => {
+                data_type = nla_get_u32_be(payload).and_then(NftSetType::from_key_type)
+            }
+            _ => {}
+        }
+    }
+
+    Some(NftSetSummary {
+        name: name?,
+        set_type: NftSetType::from_key_type(key_type),
+        key_type,
+        key_len,
+        map: flags & NFT_SET_MAP != 0,
+        data_type,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1226,13 +5262,93 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_nf_family() {
-        assert_eq!(parse_nf_family("inet").unwrap(), NFPROTO_INET);
-        assert_eq!(parse_nf_family("ip").unwrap(), NFPROTO_IPV4);
-        assert_eq!(parse_nf_family("ipv4").unwrap(), NFPROTO_IPV4);
-        assert_eq!(parse_nf_family("ip6").unwrap(), NFPROTO_IPV6);
-        assert_eq!(parse_nf_family("ipv6").unwrap(), NFPROTO_IPV6);
-        assert!(parse_nf_family("invalid").is_err());
+    fn test_parse_nft_family() {
+        assert_eq!("inet".parse::<NftFamily>().unwrap(), NftFamily::Inet);
+        assert_eq!("ip".parse::<NftFamily>().unwrap(), NftFamily::Ip);
+        assert_eq!("ipv4".parse::<NftFamily>().unwrap(), NftFamily::Ip);
+        assert_eq!("ip6".parse::<NftFamily>().unwrap(), NftFamily::Ip6);
+        assert_eq!("IPv6".parse::<NftFamily>().unwrap(), NftFamily::Ip6);
+        assert!("intet".parse::<NftFamily>().is_err());
+        for family in NftFamily::ALL {
+            assert_eq!(family.to_string().parse::<NftFamily>().unwrap(), family);
+        }
+    }
+
+    #[test]
+    fn test_nft_family_nfproto() {
+        assert_eq!(NftFamily::Inet.nfproto(), NFPROTO_INET);
+        assert_eq!(NftFamily::Ip.nfproto(), NFPROTO_IPV4);
+        assert_eq!(NftFamily::Ip6.nfproto(), NFPROTO_IPV6);
+        assert_eq!(NftFamily::Bridge.nfproto(), NFPROTO_BRIDGE);
+        assert_eq!(NftFamily::Netdev.nfproto(), NFPROTO_NETDEV);
+    }
+
+    #[test]
+    fn test_nft_retry() {
+        let mut attempts = 0;
+        let result = block_on(nft_retry(|| {
+            attempts += 1;
+            std::future::ready(match attempts {
+                1 => Err(RipsetError::NetlinkError(libc::EINTR)),
+                2 => Err(RipsetError::NetlinkError(libc::ERESTART)),
+                _ => Ok(attempts),
+            })
+        }));
+        assert_eq!(result.unwrap(), 3);
+
+        // Other errors are returned at once, and retries are bounded
+        attempts = 0;
+        let result: Result<()> = block_on(nft_retry(|| {
+            attempts += 1;
+            std::future::ready(Err(RipsetError::NetlinkError(libc::ENOENT)))
+        }));
+        assert!(matches!(
+            result,
+            Err(RipsetError::NetlinkError(libc::ENOENT))
+        ));
+        assert_eq!(attempts, 1);
+        attempts = 0;
+        let result: Result<()> = block_on(nft_retry(|| {
+            attempts += 1;
+            std::future::ready(Err(RipsetError::NetlinkError(libc::EINTR)))
+        }));
+        assert!(result.is_err());
+        assert_eq!(attempts, NFT_MAX_RETRIES);
+    }
+
+    #[test]
+    #[ignore]
+    fn test_nft_transaction_stale_genid() {
+        // Requires root
+        const TABLE_NAME: &str = "lnftsets_test_genid";
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+
+        let genid = block_on(nft_genid()).expect("Failed to get the generation ID");
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        assert_ne!(block_on(nft_genid()).unwrap(), genid);
+
+        let mut tx = NftTransaction::with_genid(genid);
+        tx.delete_table(NftFamily::Inet, TABLE_NAME).unwrap();
+        assert!(matches!(
+            tx.commit(),
+            Err(RipsetError::NetlinkError(libc::ERESTART))
+        ));
+
+        nftset_delete_table(NftFamily::Inet, TABLE_NAME).expect("Failed to delete table");
+    }
+
+    #[test]
+    fn test_parse_nftset_table_owner() {
+        let mut buf = MsgBuffer::new(64);
+        buf.put_attr_str(NFTA_TABLE_NAME, "t");
+        buf.put_attr_u32_be(NFTA_TABLE_FLAGS, NFT_TABLE_F_OWNER);
+        buf.put_attr_u32_be(NFTA_TABLE_OWNER, 4242);
+        assert_eq!(parse_nftset_table_owner(buf.as_slice()), Some(4242));
+
+        let mut buf = MsgBuffer::new(64);
+        buf.put_attr_str(NFTA_TABLE_NAME, "t");
+        buf.put_attr_u32_be(NFTA_TABLE_FLAGS, 0);
+        assert_eq!(parse_nftset_table_owner(buf.as_slice()), None);
     }
 
     #[test]
@@ -1250,20 +5366,191 @@ mod tests {
         assert_eq!(v6_end.to_string(), "2001:db8::2");
     }
 
+    #[test]
+    fn test_nft_key_bytes() {
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        assert_eq!(nft_key_bytes(&addr.into()).unwrap(), vec![10, 0, 0, 1]);
+        assert_eq!(nft_key_bytes(&443u16.into()).unwrap(), vec![1, 187]);
+
+        let mac = MacAddr([0, 1, 2, 3, 4, 5]);
+        assert_eq!(nft_key_bytes(&mac.into()).unwrap(), vec![0, 1, 2, 3, 4, 5]);
+
+        let iface = nft_key_bytes(&Entry::Iface("eth0".to_string())).unwrap();
+        assert_eq!(iface.len(), IFNAMSIZ);
+        assert_eq!(&iface[..5], b"eth0\0");
+
+        // Each concatenated value is padded to 4 bytes
+        let concat = Entry::Concat(vec![addr.into(), 443u16.into(), mac.into()]);
+        assert_eq!(
+            nft_key_bytes(&concat).unwrap(),
+            vec![10, 0, 0, 1, 1, 187, 0, 0, 0, 1, 2, 3, 4, 5, 0, 0]
+        );
+
+        assert!(nft_key_bytes(&Entry::SetName("other".to_string())).is_err());
+        assert!(nft_key_bytes(&Entry::Iface("a-very-long-ifname".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_nft_key_byte_order() {
+        // Marks are the one key nftables keeps in host byte order
+        let mark = nft_key_bytes(&Entry::Mark(0x1122_3344)).unwrap();
+        #[cfg(target_endian = "big")]
+        assert_eq!(mark, [0x11, 0x22, 0x33, 0x44]);
+        #[cfg(target_endian = "little")]
+        assert_eq!(mark, [0x44, 0x33, 0x22, 0x11]);
+
+        let addr: IpAddr = "192.0.2.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::8:1".parse().unwrap();
+        let cases = [
+            (NftSetType::Ipv4Addr, Entry::from(addr)),
+            (NftSetType::Ipv6Addr, Entry::from(v6)),
+            (NftSetType::Mark, Entry::Mark(0x1122_3344)),
+            (NftSetType::InetService, Entry::from(0x1f90u16)),
+            (
+                NftSetType::EtherAddr,
+                Entry::Mac(MacAddr([2, 0, 0, 0, 0, 1])),
+            ),
+            (NftSetType::Ifname, Entry::Iface("eth0".to_string())),
+            (
+                NftSetType::Concat(vec![
+                    NftSetType::Ipv4Addr,
+                    NftSetType::Mark,
+                    NftSetType::InetService,
+                ]),
+                Entry::Concat(vec![addr.into(), Entry::Mark(7), 0x1f90u16.into()]),
+            ),
+        ];
+        for (set_type, entry) in cases {
+            let key = nft_key_bytes(&entry).unwrap();
+            assert_eq!(key.len(), set_type.key_len() as usize);
+            assert_eq!(set_type.entry_from_key(&key), Some(entry));
+        }
+
+        // Interval ends are one past the last value, in network byte order
+        let ports = Entry::Port {
+            port: 0x00ff,
+            port_to: Some(0x01ff),
+        };
+        let elements = nft_elements(&ports, true).unwrap();
+        assert_eq!(elements[0].key, [0x00, 0xff]);
+        assert_eq!(elements[1].key, [0x02, 0x00]);
+        let range: IpEntry = "10.0.0.255-10.0.1.255".parse().unwrap();
+        let elements = nft_elements(&Entry::Ip(range), true).unwrap();
+        assert_eq!(elements[0].key, [10, 0, 0, 255]);
+        assert_eq!(elements[1].key, [10, 0, 2, 0]);
+    }
+
+    #[test]
+    fn test_nft_elements_ranges() {
+        let ports = Entry::Port {
+            port: 1000,
+            port_to: Some(2000),
+        };
+        let elements = nft_elements(&ports, true).unwrap();
+        assert_eq!(elements.len(), 2);
+        assert_eq!(elements[0].key, 1000u16.to_be_bytes());
+        assert_eq!(elements[1].key, 2001u16.to_be_bytes());
+        assert_eq!(elements[1].flags, NFT_SET_ELEM_INTERVAL_END);
+
+        // No end marker past the last port
+        let to_max = Entry::Port {
+            port: 1000,
+            port_to: Some(u16::MAX),
+        };
+        assert_eq!(nft_elements(&to_max, true).unwrap().len(), 1);
+
+        // Concatenations carry both ends in one element
+        let from: IpAddr = "10.0.0.5".parse().unwrap();
+        let to: IpAddr = "10.0.0.9".parse().unwrap();
+        let concat = Entry::Concat(vec![IpEntry::with_range(from, to).into(), ports.clone()]);
+        let elements = nft_elements(&concat, true).unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].key, vec![10, 0, 0, 5, 3, 232, 0, 0]);
+        assert_eq!(elements[0].key_end, Some(vec![10, 0, 0, 9, 7, 208, 0, 0]));
+        let set_type = NftSetType::Concat(vec![NftSetType::Ipv4Addr, NftSetType::InetService]);
+        assert_eq!(
+            set_type.range_from_keys(&elements[0].key, elements[0].key_end.as_ref().unwrap()),
+            Some(concat)
+        );
+
+        let reversed = Entry::Port {
+            port: 2000,
+            port_to: Some(1000),
+        };
+        assert!(nft_elements(&reversed, true).is_err());
+        assert!(nft_elements(&ports, false).is_err());
+        assert!(nft_elements(&Entry::Mark(1), true).is_err());
+        assert_eq!(key_predecessor(&[1, 0]), vec![0, 255]);
+    }
+
+    #[test]
+    fn test_nftset_type_concat() {
+        let set_type = NftSetType::Concat(vec![NftSetType::Ipv4Addr, NftSetType::InetService]);
+        assert_eq!(set_type.key_type(), 7 << 6 | 13);
+        assert_eq!(set_type.key_len(), 8);
+        assert_eq!(
+            NftSetType::from_key_type(7 << 6 | 13),
+            Some(set_type.clone())
+        );
+        assert_eq!(set_type.to_string(), "ipv4_addr . inet_service");
+
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        let entry = Entry::Concat(vec![addr.into(), 443u16.into()]);
+        let key = nft_key_bytes(&entry).unwrap();
+        assert_eq!(set_type.entry_from_key(&key), Some(entry));
+
+        // Unknown field types can't be decoded
+        assert_eq!(NftSetType::from_key_type(7 << 6 | 63), None);
+    }
+
+    #[test]
+    fn test_nft_verdict_roundtrip() {
+        for verdict in [
+            NftVerdict::Accept,
+            NftVerdict::Drop,
+            NftVerdict::Return,
+            NftVerdict::Jump("allowed".to_string()),
+            NftVerdict::Goto("blocked".to_string()),
+        ] {
+            let mut buf = MsgBuffer::new(BUFF_SZ);
+            put_nft_verdict(&mut buf, &verdict).unwrap();
+            let (attr_type, payload) = NlAttrIter::new(buf.as_slice()).next().unwrap();
+            assert_eq!(attr_type, NFTA_DATA_VERDICT);
+            assert_eq!(parse_nft_verdict(payload), Some(verdict.clone()));
+            assert_eq!(verdict.to_string().parse::<NftVerdict>().unwrap(), verdict);
+        }
+
+        let mut buf = MsgBuffer::new(BUFF_SZ);
+        assert!(put_nft_verdict(&mut buf, &NftVerdict::Jump(String::new())).is_err());
+        assert!("jump".parse::<NftVerdict>().is_err());
+    }
+
+    #[test]
+    fn test_parse_nftset_udata_comment() {
+        // Key byte order TLV followed by the comment
+        let udata = [0, 4, 1, 0, 0, 0, 7, 6, b'h', b'o', b's', b't', b's', 0];
+        assert_eq!(parse_nftset_udata_comment(&udata).as_deref(), Some("hosts"));
+        assert!(!parse_nftset_udata_auto_merge(&udata));
+        assert!(parse_nftset_udata_auto_merge(&[2, 4, 1, 0, 0, 0]));
+        assert_eq!(parse_nftset_udata_comment(&udata[..6]), None);
+        // Truncated TLV
+        assert_eq!(parse_nftset_udata_comment(&udata[..10]), None);
+    }
+
     #[test]
     fn test_invalid_names() {
         let addr: IpAddr = "192.168.1.1".parse().unwrap();
 
         // Empty table
         assert!(matches!(
-            nftset_add("inet", "", "myset", addr),
-            Err(IpSetError::InvalidTableName(_))
+            nftset_add(NftFamily::Inet, "", "myset", addr),
+            Err(RipsetError::InvalidTableName(_))
         ));
 
         // Empty set name
         assert!(matches!(
-            nftset_add("inet", "filter", "", addr),
-            Err(IpSetError::InvalidSetName(_))
+            nftset_add(NftFamily::Inet, "filter", "", addr),
+            Err(RipsetError::InvalidSetName(_))
         ));
     }
 
@@ -1276,7 +5563,8 @@ mod tests {
         // Requires: sudo nft add table inet filter
         //           sudo nft add set inet filter test_set { type ipv4_addr\; }
         let addr: IpAddr = "10.0.0.1".parse().unwrap();
-        nftset_add("inet", "filter", "test_set", addr).expect("Failed to add IP to nftset");
+        nftset_add(NftFamily::Inet, "filter", "test_set", addr)
+            .expect("Failed to add IP to nftset");
     }
 
     #[test]
@@ -1284,8 +5572,8 @@ mod tests {
     fn test_nftset_test_ipv4() {
         // Requires nftables set setup
         let addr: IpAddr = "10.0.0.1".parse().unwrap();
-        let exists =
-            nftset_test("inet", "filter", "test_set", addr).expect("Failed to test IP in nftset");
+        let exists = nftset_test(NftFamily::Inet, "filter", "test_set", addr)
+            .expect("Failed to test IP in nftset");
         println!("IP exists in set: {}", exists);
     }
 
@@ -1294,7 +5582,8 @@ mod tests {
     fn test_nftset_del_ipv4() {
         // Requires nftables set setup
         let addr: IpAddr = "10.0.0.1".parse().unwrap();
-        nftset_del("inet", "filter", "test_set", addr).expect("Failed to delete IP from nftset");
+        nftset_del(NftFamily::Inet, "filter", "test_set", addr)
+            .expect("Failed to delete IP from nftset");
     }
 
     #[test]
@@ -1302,7 +5591,8 @@ mod tests {
     fn test_nftset_add_ipv6() {
         // Requires: sudo nft add set inet filter test_set6 { type ipv6_addr\; }
         let addr: IpAddr = "2001:db8::1".parse().unwrap();
-        nftset_add("inet", "filter", "test_set6", addr).expect("Failed to add IPv6 to nftset");
+        nftset_add(NftFamily::Inet, "filter", "test_set6", addr)
+            .expect("Failed to add IPv6 to nftset");
     }
 
     #[test]
@@ -1311,7 +5601,7 @@ mod tests {
         // Requires: sudo nft add set inet filter test_set_timeout { type ipv4_addr\; timeout 5m\; }
         let addr: IpAddr = "10.0.0.2".parse().unwrap();
         let entry = IpEntry::with_timeout(addr, 60);
-        nftset_add("inet", "filter", "test_set_timeout", entry)
+        nftset_add(NftFamily::Inet, "filter", "test_set_timeout", entry)
             .expect("Failed to add IP with timeout");
     }
 }
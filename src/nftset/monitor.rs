@@ -0,0 +1,370 @@
+//! Notifications of changes to nftables tables, sets and elements.
+//!
+//! The kernel reports every committed change to the members of the nftables
+//! multicast group, whoever made it. ipset has no such notifications, so its
+//! sets cannot be monitored.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use super::*;
+use crate::netlink::{NFNLGRP_NFTABLES, nlmsg_align};
+
+/// Receive buffer size, enough for the largest batch of notifications
+const MONITOR_BUFF_SZ: usize = 65536;
+
+/// A change to nftables reported by the kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NftEvent {
+    /// A table was created or its flags changed.
+    TableCreated { family: NftFamily, table: String },
+    /// A table was deleted, after the deletion of its sets.
+    TableDeleted { family: NftFamily, table: String },
+    /// A set was created or its properties changed.
+    SetCreated {
+        family: NftFamily,
+        table: String,
+        set: String,
+    },
+    /// A set was deleted along with its elements, which are not reported
+    /// one by one.
+    SetDeleted {
+        family: NftFamily,
+        table: String,
+        set: String,
+    },
+    /// An entry was added to a set.
+    ElementAdded {
+        family: NftFamily,
+        table: String,
+        set: String,
+        entry: Entry,
+    },
+    /// An entry was deleted from a set, or flushed with the others.
+    ElementDeleted {
+        family: NftFamily,
+        table: String,
+        set: String,
+        entry: Entry,
+    },
+    /// A transaction was committed. The events since the previous commit
+    /// are its changes, and `generation` is the new ruleset generation.
+    Committed { generation: u32 },
+}
+
+/// Family, table and name of a set
+type SetKey = (NftFamily, String, String);
+
+/// A subscription to the changes made to nftables.
+///
+/// Iterating over the monitor blocks until the next change; a deadline set
+/// with [`with_timeout`](crate::with_timeout) bounds the wait. Entries are
+/// decoded like [`nftset_list_entries`] does, ranges of interval sets being
+/// reported once their transaction is committed. The values of map
+/// elements are not reported.
+///
+/// When changes come faster than they are read, the kernel drops
+/// notifications and the monitor returns [`RipsetError::EventsLost`]. The
+/// monitor keeps working afterwards, but a mirror of the sets has to be
+/// read again with [`nftset_list_entries`] to catch up.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftEvent, NftMonitor};
+///
+/// for event in NftMonitor::new().unwrap() {
+///     match event.unwrap() {
+///         NftEvent::ElementAdded { set, entry, .. } => println!("{set}: +{entry:?}"),
+///         NftEvent::ElementDeleted { set, entry, .. } => println!("{set}: -{entry:?}"),
+///         _ => {}
+///     }
+/// }
+/// ```
+pub struct NftMonitor {
+    socket: NetlinkSocket,
+    /// Socket looking up the sets elements are reported for
+    lookup: NetlinkSocket,
+    /// Properties of the sets elements were reported for
+    sets: HashMap<SetKey, NftSetInfo>,
+    /// Interval set elements changed by the running transaction. Ranges
+    /// take a start and an end element, paired up on commit.
+    intervals: Vec<(SetKey, bool, Vec<NftElement>)>,
+    events: VecDeque<NftEvent>,
+    recv_buf: Vec<u8>,
+}
+
+impl NftMonitor {
+    /// Subscribe to the nftables changes of the current network namespace,
+    /// or of the one chosen with [`with_netns`](crate::with_netns).
+    pub fn new() -> Result<Self> {
+        let socket = NetlinkSocket::new()?;
+        socket.add_membership(NFNLGRP_NFTABLES)?;
+        Ok(Self {
+            socket,
+            lookup: NetlinkSocket::new()?,
+            sets: HashMap::new(),
+            intervals: Vec::new(),
+            events: VecDeque::new(),
+            recv_buf: vec![0u8; MONITOR_BUFF_SZ],
+        })
+    }
+
+    /// Wait for the next change.
+    pub fn next_event(&mut self) -> Result<NftEvent> {
+        loop {
+            if let Some(event) = self.pop_event() {
+                return Ok(event);
+            }
+            match self.recv() {
+                Ok(len) => self.handle(len)?,
+                Err(e) => return Err(self.recv_failed(e)),
+            }
+        }
+    }
+
+    /// Make receives fail with `WouldBlock` rather than wait for changes.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn set_nonblocking(&self) -> io::Result<()> {
+        self.socket.set_nonblocking()
+    }
+
+    /// Take the next decoded event.
+    pub(crate) fn pop_event(&mut self) -> Option<NftEvent> {
+        self.events.pop_front()
+    }
+
+    /// Receive the next batch of notifications.
+    pub(crate) fn recv(&mut self) -> io::Result<usize> {
+        self.socket.recv(&mut self.recv_buf)
+    }
+
+    /// The error of a failed receive, forgetting the transaction in progress
+    /// when notifications were dropped.
+    pub(crate) fn recv_failed(&mut self, error: io::Error) -> RipsetError {
+        if error.raw_os_error() != Some(libc::ENOBUFS) {
+            return error.into();
+        }
+        self.intervals.clear();
+        // Sets may have been replaced unnoticed
+        self.sets.clear();
+        RipsetError::EventsLost
+    }
+
+    /// Decode the `len` bytes of notifications just received.
+    pub(crate) fn handle(&mut self, len: usize) -> Result<()> {
+        let buf = mem::take(&mut self.recv_buf);
+        let result = self.handle_messages(&buf[..len]);
+        self.recv_buf = buf;
+        result
+    }
+
+    fn handle_messages(&mut self, buf: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        while offset + NlMsgHdr::SIZE <= buf.len() {
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const NlMsgHdr) };
+            let msg_len = hdr.nlmsg_len as usize;
+            if msg_len < NlMsgHdr::SIZE || offset + msg_len > buf.len() {
+                break;
+            }
+
+            if hdr.nlmsg_type >> 8 == NFNL_SUBSYS_NFTABLES as u16
+                && msg_len >= NlMsgHdr::SIZE + NfGenMsg::SIZE
+            {
+                // The family is the first byte of nfgenmsg
+                let nfproto = buf[offset + NlMsgHdr::SIZE];
+                let attrs = &buf[offset + NlMsgHdr::SIZE + NfGenMsg::SIZE..offset + msg_len];
+                self.handle_message(hdr.nlmsg_type & 0xff, nfproto, attrs)?;
+            }
+
+            offset += nlmsg_align(msg_len);
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, msg_type: u16, nfproto: u8, attrs: &[u8]) -> Result<()> {
+        if msg_type == NFT_MSG_NEWGEN {
+            let generation = NlAttrIter::new(attrs)
+                .find(|&(attr_type, _)| attr_type == NFTA_GEN_ID)
+                .and_then(|(_, payload)| nla_get_u32_be(payload))
+                .unwrap_or(0);
+            self.commit(generation);
+            return Ok(());
+        }
+
+        // Tables of other families hold no sets of interest
+        let Some(family) = NftFamily::from_nfproto(nfproto) else {
+            return Ok(());
+        };
+
+        match msg_type {
+            NFT_MSG_NEWTABLE | NFT_MSG_DELTABLE => {
+                let Some(table) = parse_nftset_table_name(attrs) else {
+                    return Ok(());
+                };
+                if msg_type == NFT_MSG_NEWTABLE {
+                    self.events
+                        .push_back(NftEvent::TableCreated { family, table });
+                } else {
+                    self.sets
+                        .retain(|(f, t, _), _| !(*f == family && *t == table));
+                    self.events
+                        .push_back(NftEvent::TableDeleted { family, table });
+                }
+            }
+            NFT_MSG_NEWSET | NFT_MSG_DELSET => {
+                let Some(table) = find_str(attrs, NFTA_SET_TABLE) else {
+                    return Ok(());
+                };
+                if msg_type == NFT_MSG_NEWSET {
+                    let Some(info) = parse_nftset_info(attrs) else {
+                        return Ok(());
+                    };
+                    let set = info.name.clone();
+                    self.sets.insert((family, table.clone(), set.clone()), info);
+                    self.events
+                        .push_back(NftEvent::SetCreated { family, table, set });
+                } else {
+                    let Some(set) = find_str(attrs, NFTA_SET_NAME) else {
+                        return Ok(());
+                    };
+                    self.sets.remove(&(family, table.clone(), set.clone()));
+                    self.events
+                        .push_back(NftEvent::SetDeleted { family, table, set });
+                }
+            }
+            NFT_MSG_NEWSETELEM | NFT_MSG_DELSETELEM => {
+                self.handle_elements(family, msg_type == NFT_MSG_NEWSETELEM, attrs)?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_elements(&mut self, family: NftFamily, added: bool, attrs: &[u8]) -> Result<()> {
+        let (mut table, mut set) = (None, None);
+        let mut elements = Vec::new();
+        for (attr_type, payload) in NlAttrIter::new(attrs) {
+            match attr_type {
+                NFTA_SET_ELEM_LIST_TABLE => table = nla_get_str(payload),
+                NFTA_SET_ELEM_LIST_SET => set = nla_get_str(payload),
+                NFTA_SET_ELEM_LIST_ELEMENTS => elements.extend(
+                    NlAttrIter::new(payload).filter_map(|(_, elem)| parse_nftset_element(elem)),
+                ),
+                _ => {}
+            }
+        }
+        let (Some(table), Some(set)) = (table, set) else {
+            return Ok(());
+        };
+
+        let key = (family, table, set);
+        let Some(info) = self.set_info(&key)? else {
+            return Ok(());
+        };
+
+        // Concatenated ranges come as single elements holding both ends
+        if info.interval && !matches!(info.set_type, Some(NftSetType::Concat(_))) {
+            match self
+                .intervals
+                .iter_mut()
+                .find(|(k, a, _)| *k == key && *a == added)
+            {
+                Some((_, _, pending)) => pending.extend(elements),
+                None => self.intervals.push((key, added, elements)),
+            }
+            return Ok(());
+        }
+
+        for (entry, _) in set_entries(&info, elements) {
+            self.events.push_back(element_event(&key, added, entry));
+        }
+        Ok(())
+    }
+
+    /// Properties of a set, looked up the first time one of its elements is
+    /// reported. `None` if the set is already gone.
+    fn set_info(&mut self, key: &SetKey) -> Result<Option<NftSetInfo>> {
+        if let Some(info) = self.sets.get(key) {
+            return Ok(Some(info.clone()));
+        }
+        let (family, table, set) = key;
+        match block_on(nftset_get_set_on(&self.lookup, *family, table, set)) {
+            Ok(info) => {
+                self.sets.insert(key.clone(), info.clone());
+                Ok(Some(info))
+            }
+            Err(RipsetError::SetNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Report the ranges of the committed transaction, then the commit.
+    fn commit(&mut self, generation: u32) {
+        // Deletions first, as replacing a range deletes it and adds another
+        let mut intervals = mem::take(&mut self.intervals);
+        intervals.sort_by_key(|(_, added, _)| *added);
+        for (key, added, elements) in intervals {
+            // Sets deleted by the transaction are reported as a whole
+            let Some(info) = self.sets.get(&key) else {
+                continue;
+            };
+            for (entry, _) in set_entries(info, elements) {
+                self.events.push_back(element_event(&key, added, entry));
+            }
+        }
+        self.events.push_back(NftEvent::Committed { generation });
+    }
+}
+
+impl Iterator for NftMonitor {
+    type Item = Result<NftEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+impl AsRawFd for NftMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl NftFamily {
+    /// Family of a netfilter protocol number carried in `nfgenmsg`.
+    fn from_nfproto(nfproto: u8) -> Option<Self> {
+        NftFamily::ALL
+            .into_iter()
+            .find(|family| family.nfproto() == nfproto)
+    }
+}
+
+/// The string attribute `attr` of a message.
+fn find_str(attrs: &[u8], attr: u16) -> Option<String> {
+    NlAttrIter::new(attrs)
+        .find(|&(attr_type, _)| attr_type == attr)
+        .and_then(|(_, payload)| nla_get_str(payload))
+}
+
+fn element_event((family, table, set): &SetKey, added: bool, entry: Entry) -> NftEvent {
+    let (family, table, set) = (*family, table.clone(), set.clone());
+    if added {
+        NftEvent::ElementAdded {
+            family,
+            table,
+            set,
+            entry,
+        }
+    } else {
+        NftEvent::ElementDeleted {
+            family,
+            table,
+            set,
+            entry,
+        }
+    }
+}
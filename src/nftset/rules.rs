@@ -0,0 +1,563 @@
+//! Rules matching traffic against nftables sets.
+//!
+//! Only the rules this crate needs are supported; anything more elaborate is
+//! better written with nft itself.
+
+use super::*;
+
+// nftables chain and rule message types
+const NFT_MSG_NEWCHAIN: u16 = 3;
+const NFT_MSG_GETCHAIN: u16 = 4;
+const NFT_MSG_NEWRULE: u16 = 6;
+const NFT_MSG_GETRULE: u16 = 7;
+
+// nftables chain attributes
+const NFTA_CHAIN_TABLE: u16 = 1;
+const NFTA_CHAIN_NAME: u16 = 3;
+const NFTA_CHAIN_HOOK: u16 = 4;
+const NFTA_CHAIN_TYPE: u16 = 7;
+
+// nftables hook attributes
+const NFTA_HOOK_HOOKNUM: u16 = 1;
+const NFTA_HOOK_PRIORITY: u16 = 2;
+
+// nftables rule attributes (NFTA_RULE_TABLE is shared with the table flush)
+const NFTA_RULE_CHAIN: u16 = 2;
+const NFTA_RULE_HANDLE: u16 = 3;
+const NFTA_RULE_EXPRESSIONS: u16 = 4;
+const NFTA_RULE_USERDATA: u16 = 7;
+
+// Expression attributes
+const NFTA_META_DREG: u16 = 1;
+const NFTA_META_KEY: u16 = 2;
+const NFTA_CMP_SREG: u16 = 1;
+const NFTA_CMP_OP: u16 = 2;
+const NFTA_CMP_DATA: u16 = 3;
+const NFTA_PAYLOAD_DREG: u16 = 1;
+const NFTA_PAYLOAD_BASE: u16 = 2;
+const NFTA_PAYLOAD_OFFSET: u16 = 3;
+const NFTA_PAYLOAD_LEN: u16 = 4;
+const NFTA_LOOKUP_SET: u16 = 1;
+const NFTA_LOOKUP_SREG: u16 = 2;
+const NFTA_DYNSET_SET_NAME: u16 = 1;
+const NFTA_OBJREF_SET_NAME: u16 = 3;
+const NFTA_IMMEDIATE_DREG: u16 = 1;
+const NFTA_IMMEDIATE_DATA: u16 = 2;
+
+// Registers, keys and operators used by the expressions
+const NFT_REG_VERDICT: u32 = 0;
+const NFT_REG_1: u32 = 1;
+const NFT_META_PROTOCOL: u32 = 1;
+const NFT_META_NFPROTO: u32 = 15;
+const NFT_CMP_EQ: u32 = 0;
+const NFT_PAYLOAD_NETWORK_HEADER: u32 = 1;
+
+// The prerouting hook of the ip, ip6, inet and bridge families
+const NF_INET_PRE_ROUTING: u32 = 0;
+const NF_IP_PRI_FILTER: i32 = 0;
+
+const NLM_F_APPEND: u16 = 0x800;
+const NFTNL_UDATA_RULE_COMMENT: u8 = 0;
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+
+/// Make sure `chain` drops the packets whose source address is in a set.
+///
+/// This is the nft rule `ip saddr @setname drop` (`ip6 saddr` for IPv6
+/// sets). If the chain doesn't exist, it is created as a filter chain on the
+/// prerouting hook, so forwarded traffic is dropped too. The rule is
+/// appended only if the chain doesn't hold it already, which makes calling
+/// this again harmless.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family: ip, ip6, inet or bridge
+/// * `table` - The table holding the chain and the set
+/// * `chain` - The chain to add the rule to
+/// * `setname` - The set of addresses to drop, of type ipv4_addr or ipv6_addr
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nft_ensure_drop_rule};
+///
+/// nft_ensure_drop_rule(NftFamily::Inet, "filter", "blocklist", "banned").unwrap();
+/// ```
+pub fn nft_ensure_drop_rule(
+    family: NftFamily,
+    table: &str,
+    chain: &str,
+    setname: &str,
+) -> Result<()> {
+    block_on(nft_ensure_drop_rule_async(family, table, chain, setname))
+}
+
+/// [`nft_ensure_drop_rule`] for async code.
+pub(crate) async fn nft_ensure_drop_rule_async(
+    family: NftFamily,
+    table: &str,
+    chain: &str,
+    setname: &str,
+) -> Result<()> {
+    validate_table_name(table)?;
+    validate_set_name(setname)?;
+    if chain.is_empty() || chain.len() >= NFT_SET_MAXNAMELEN {
+        return Err(RipsetError::InvalidOption(format!(
+            "invalid chain name: {chain}"
+        )));
+    }
+
+    let set = nftset_get_set(family, table, setname).await?;
+    let saddr = &saddr_match(family, set.set_type.as_ref(), setname)?;
+    let comment = &format!("ripset drop @{setname}");
+    if comment.len() >= NFT_COMMENT_MAXLEN {
+        return Err(RipsetError::InvalidSetName(setname.to_string()));
+    }
+
+    // The chain must not change between the check and the commit
+    nft_retry(move || async move {
+        let genid = nft_genid().await?;
+        let exists = nft_chain_exists(family, table, chain).await?;
+        if exists
+            && dump_retry_async(move || nft_rule_dump(family, table, Some(chain)))
+                .await?
+                .iter()
+                .any(|rule| rule.comment.as_ref() == Some(comment))
+        {
+            return Ok(());
+        }
+
+        let mut tx = NftTransaction::with_genid(genid);
+        if !exists {
+            tx.put_base_chain(family, table, chain);
+        }
+        tx.put_drop_rule(family, table, chain, setname, saddr, comment)?;
+        tx.commit_async().await
+    })
+    .await
+}
+
+/// A rule using an nftables set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NftSetReference {
+    /// Chain holding the rule
+    pub chain: String,
+    /// Rule handle, as shown by `nft -a list ruleset`
+    pub handle: u64,
+    pub comment: Option<String>,
+}
+
+impl fmt::Display for NftSetReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule {} in chain {}", self.handle, self.chain)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " ({comment})")?;
+        }
+        Ok(())
+    }
+}
+
+/// List the rules using an nftables set.
+///
+/// A set can't be deleted while rules look elements up in it, add elements
+/// to it from the packet path or pick objects from it.
+///
+/// # Arguments
+///
+/// * `family` - The nftables address family
+/// * `table` - The table holding the set
+/// * `setname` - The set name
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, nftset_references};
+///
+/// for rule in nftset_references(NftFamily::Inet, "filter", "banned").unwrap() {
+///     println!("banned is used by {rule}");
+/// }
+/// ```
+pub fn nftset_references(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<Vec<NftSetReference>> {
+    block_on(nftset_references_async(family, table, setname))
+}
+
+/// [`nftset_references`] for async code.
+pub(crate) async fn nftset_references_async(
+    family: NftFamily,
+    table: &str,
+    setname: &str,
+) -> Result<Vec<NftSetReference>> {
+    validate_table_name(table)?;
+    validate_set_name(setname)?;
+    nftset_get_set(family, table, setname).await?;
+
+    let rules = nft_dump_retry(move || nft_rule_dump(family, table, None)).await?;
+    Ok(rules
+        .into_iter()
+        .filter(|rule| rule.sets.iter().any(|set| set == setname))
+        .map(|rule| NftSetReference {
+            chain: rule.chain,
+            handle: rule.handle,
+            comment: rule.comment,
+        })
+        .collect())
+}
+
+/// How a rule loads the source address it looks up in a set.
+#[derive(Debug, PartialEq)]
+struct SaddrMatch {
+    /// Meta key and value the packet must match first
+    dependency: Option<(u32, Vec<u8>)>,
+    /// Offset and length of the address in the network header
+    offset: u32,
+    len: u32,
+}
+
+/// Match the source address of the packets in `family` against a set with
+/// keys of `set_type`.
+fn saddr_match(
+    family: NftFamily,
+    set_type: Option<&NftSetType>,
+    setname: &str,
+) -> Result<SaddrMatch> {
+    let (offset, len, nfproto, ethertype) = match set_type {
+        Some(NftSetType::Ipv4Addr) => (12, 4, NFPROTO_IPV4, ETH_P_IP),
+        Some(NftSetType::Ipv6Addr) => (8, 16, NFPROTO_IPV6, ETH_P_IPV6),
+        _ => {
+            return Err(RipsetError::InvalidOption(format!(
+                "set {setname} doesn't hold IP addresses"
+            )));
+        }
+    };
+    // Like nft, only inet and bridge rules need to check the protocol
+    let dependency = match family {
+        NftFamily::Ip | NftFamily::Ip6 if family.nfproto() == nfproto => None,
+        NftFamily::Inet => Some((NFT_META_NFPROTO, vec![nfproto])),
+        NftFamily::Bridge => Some((NFT_META_PROTOCOL, ethertype.to_be_bytes().to_vec())),
+        NftFamily::Ip | NftFamily::Ip6 => {
+            return Err(RipsetError::InvalidOption(format!(
+                "set {setname} doesn't match the {family} family"
+            )));
+        }
+        NftFamily::Arp | NftFamily::Netdev => {
+            return Err(RipsetError::InvalidOption(format!(
+                "drop rules aren't supported in the {family} family"
+            )));
+        }
+    };
+    Ok(SaddrMatch {
+        dependency,
+        offset,
+        len,
+    })
+}
+
+impl NftTransaction {
+    /// Queue the creation of a filter chain on the prerouting hook.
+    fn put_base_chain(&mut self, family: NftFamily, table: &str, chain: &str) {
+        let msg_start = self.start_msg(
+            NFT_MSG_NEWCHAIN,
+            NLM_F_REQUEST | NLM_F_CREATE,
+            family.nfproto(),
+        );
+        self.buf.put_attr_str(NFTA_CHAIN_TABLE, table);
+        self.buf.put_attr_str(NFTA_CHAIN_NAME, chain);
+        let hook = self.buf.start_nested(NFTA_CHAIN_HOOK);
+        self.buf
+            .put_attr_u32_nft(NFTA_HOOK_HOOKNUM, NF_INET_PRE_ROUTING);
+        self.buf
+            .put_attr_u32_nft(NFTA_HOOK_PRIORITY, NF_IP_PRI_FILTER as u32);
+        self.buf.end_nested(hook);
+        self.buf.put_attr_str(NFTA_CHAIN_TYPE, "filter");
+        self.buf.finalize_nlmsg_at(msg_start);
+
+        self.ops.push(NftOp {
+            kind: NftOpKind::NewChain,
+            family,
+            table: table.to_string(),
+            name: chain.to_string(),
+        });
+    }
+
+    /// Queue a rule dropping the packets whose source address is in a set.
+    fn put_drop_rule(
+        &mut self,
+        family: NftFamily,
+        table: &str,
+        chain: &str,
+        setname: &str,
+        saddr: &SaddrMatch,
+        comment: &str,
+    ) -> Result<()> {
+        let msg_start = self.start_msg(
+            NFT_MSG_NEWRULE,
+            NLM_F_REQUEST | NLM_F_CREATE | NLM_F_APPEND,
+            family.nfproto(),
+        );
+        let buf = &mut self.buf;
+        buf.put_attr_str(NFTA_RULE_TABLE, table);
+        buf.put_attr_str(NFTA_RULE_CHAIN, chain);
+
+        let exprs = buf.start_nested(NFTA_RULE_EXPRESSIONS);
+        if let Some((key, value)) = &saddr.dependency {
+            let data = put_expr_start(buf, "meta");
+            buf.put_attr_u32_nft(NFTA_META_KEY, *key);
+            buf.put_attr_u32_nft(NFTA_META_DREG, NFT_REG_1);
+            put_expr_end(buf, data);
+
+            let data = put_expr_start(buf, "cmp");
+            buf.put_attr_u32_nft(NFTA_CMP_SREG, NFT_REG_1);
+            buf.put_attr_u32_nft(NFTA_CMP_OP, NFT_CMP_EQ);
+            let cmp_data = buf.start_nested(NFTA_CMP_DATA);
+            buf.put_attr_bytes(NFTA_DATA_VALUE, value);
+            buf.end_nested(cmp_data);
+            put_expr_end(buf, data);
+        }
+
+        let data = put_expr_start(buf, "payload");
+        buf.put_attr_u32_nft(NFTA_PAYLOAD_DREG, NFT_REG_1);
+        buf.put_attr_u32_nft(NFTA_PAYLOAD_BASE, NFT_PAYLOAD_NETWORK_HEADER);
+        buf.put_attr_u32_nft(NFTA_PAYLOAD_OFFSET, saddr.offset);
+        buf.put_attr_u32_nft(NFTA_PAYLOAD_LEN, saddr.len);
+        put_expr_end(buf, data);
+
+        let data = put_expr_start(buf, "lookup");
+        buf.put_attr_str(NFTA_LOOKUP_SET, setname);
+        buf.put_attr_u32_nft(NFTA_LOOKUP_SREG, NFT_REG_1);
+        put_expr_end(buf, data);
+
+        let data = put_expr_start(buf, "immediate");
+        buf.put_attr_u32_nft(NFTA_IMMEDIATE_DREG, NFT_REG_VERDICT);
+        let imm_data = buf.start_nested(NFTA_IMMEDIATE_DATA);
+        put_nft_verdict(buf, &NftVerdict::Drop)?;
+        buf.end_nested(imm_data);
+        put_expr_end(buf, data);
+        buf.end_nested(exprs);
+
+        let mut udata = vec![NFTNL_UDATA_RULE_COMMENT, comment.len() as u8 + 1];
+        udata.extend(comment.as_bytes());
+        udata.push(0);
+        buf.put_attr_bytes(NFTA_RULE_USERDATA, &udata);
+        buf.finalize_nlmsg_at(msg_start);
+
+        // Failing lookups are reported against the set
+        self.ops.push(NftOp {
+            kind: NftOpKind::NewRule,
+            family,
+            table: table.to_string(),
+            name: setname.to_string(),
+        });
+        Ok(())
+    }
+}
+
+/// Start an expression list element and its data, returning the offsets to
+/// close.
+fn put_expr_start(buf: &mut MsgBuffer, name: &str) -> (usize, usize) {
+    let elem = buf.start_nested(NFTA_LIST_ELEM);
+    buf.put_attr_str(NFTA_EXPR_NAME, name);
+    (elem, buf.start_nested(NFTA_EXPR_DATA))
+}
+
+fn put_expr_end(buf: &mut MsgBuffer, (elem, data): (usize, usize)) {
+    buf.end_nested(data);
+    buf.end_nested(elem);
+}
+
+/// Check whether a chain exists with GETCHAIN.
+async fn nft_chain_exists(family: NftFamily, table: &str, chain: &str) -> Result<bool> {
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETCHAIN), NLM_F_REQUEST | NLM_F_ACK, 0);
+    buf.put_nfgenmsg(family.nfproto(), 0, 0);
+    buf.put_attr_str(NFTA_CHAIN_TABLE, table);
+    buf.put_attr_str(NFTA_CHAIN_NAME, chain);
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new()?;
+    let mut recv_buf = [0u8; BUFF_SZ];
+    let recv_len = socket
+        .send_recv_async(buf.as_slice(), &mut recv_buf)
+        .await?;
+
+    match parse_nlmsg_error(&recv_buf[..recv_len]) {
+        None => Ok(true),
+        Some(error) if -error == libc::ENOENT => Ok(false),
+        Some(error) => Err(errno_error(-error, &recv_buf[..recv_len])),
+    }
+}
+
+/// A rule as far as this module looks into it.
+struct NftRule {
+    chain: String,
+    handle: u64,
+    comment: Option<String>,
+    /// Named sets used by the expressions
+    sets: Vec<String>,
+}
+
+/// Dump the rules of a table, or of one of its chains, with GETRULE.
+async fn nft_rule_dump(
+    family: NftFamily,
+    table: &str,
+    chain: Option<&str>,
+) -> Result<Vec<NftRule>> {
+    let mut buf = MsgBuffer::new(BUFF_SZ);
+    buf.put_nlmsghdr(nft_msg_type(NFT_MSG_GETRULE), NLM_F_REQUEST | NLM_F_DUMP, 0);
+    buf.put_nfgenmsg(family.nfproto(), 0, 0);
+    buf.put_attr_str(NFTA_RULE_TABLE, table);
+    if let Some(chain) = chain {
+        buf.put_attr_str(NFTA_RULE_CHAIN, chain);
+    }
+    buf.finalize_nlmsg();
+
+    let socket = NetlinkSocket::new_dump()?;
+    socket.send(buf.as_slice())?;
+
+    let mut result = Vec::new();
+    let mut recv_buf = vec![0u8; DUMP_BUF_SZ];
+
+    loop {
+        let recv_len = socket.recv_dump_async(&mut recv_buf).await?;
+        if recv_len < NlMsgHdr::SIZE {
+            break;
+        }
+
+        let mut offset = 0;
+        while offset + NlMsgHdr::SIZE <= recv_len {
+            let hdr: NlMsgHdr =
+                unsafe { std::ptr::read_unaligned(recv_buf[offset..].as_ptr() as *const NlMsgHdr) };
+
+            if hdr.nlmsg_len as usize > recv_len - offset {
+                break;
+            }
+
+            // The ruleset changed while it was being dumped
+            if hdr.nlmsg_flags & NLM_F_DUMP_INTR != 0 {
+                return Err(RipsetError::NetlinkError(libc::EINTR));
+            }
+
+            if is_nlmsg_done(&recv_buf[offset..]) {
+                return Ok(result);
+            }
+
+            let msg_end = offset + hdr.nlmsg_len as usize;
+            match parse_nlmsg_error(&recv_buf[offset..msg_end]) {
+                Some(0) => {}
+                Some(error) => return Err(errno_error(-error, &recv_buf[offset..msg_end])),
+                None if hdr.nlmsg_type == nft_msg_type(NFT_MSG_NEWRULE) => {
+                    let attr_start = offset + NlMsgHdr::SIZE + NfGenMsg::SIZE;
+                    result.push(parse_nft_rule(&recv_buf[attr_start.min(msg_end)..msg_end]));
+                }
+                None => {}
+            }
+
+            offset += nla_align(hdr.nlmsg_len as usize);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse a NEWRULE message.
+fn parse_nft_rule(data: &[u8]) -> NftRule {
+    let mut rule = NftRule {
+        chain: String::new(),
+        handle: 0,
+        comment: None,
+        sets: Vec::new(),
+    };
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_RULE_CHAIN => rule.chain = nla_get_str(payload).unwrap_or_default(),
+            NFTA_RULE_HANDLE => rule.handle = nla_get_u64_be(payload).unwrap_or(0),
+            NFTA_RULE_USERDATA => {
+                rule.comment =
+                    nftset_udata_get(payload, NFTNL_UDATA_RULE_COMMENT).and_then(nla_get_str);
+            }
+            NFTA_RULE_EXPRESSIONS => {
+                rule.sets = NlAttrIter::new(payload)
+                    .filter_map(|(_, expr)| parse_nft_expr_set(expr))
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    rule
+}
+
+/// Get the name of the set an expression uses, if any.
+fn parse_nft_expr_set(data: &[u8]) -> Option<String> {
+    let mut name = None;
+    let mut expr_data = None;
+    for (attr_type, payload) in NlAttrIter::new(data) {
+        match attr_type {
+            NFTA_EXPR_NAME => name = nla_get_str(payload),
+            NFTA_EXPR_DATA => expr_data = Some(payload),
+            _ => {}
+        }
+    }
+    let set_attr = match name?.as_str() {
+        "lookup" => NFTA_LOOKUP_SET,
+        "dynset" => NFTA_DYNSET_SET_NAME,
+        "objref" => NFTA_OBJREF_SET_NAME,
+        _ => return None,
+    };
+    NlAttrIter::new(expr_data?)
+        .find(|&(attr_type, _)| attr_type == set_attr)
+        .and_then(|(_, payload)| nla_get_str(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saddr_match() {
+        let v4 = Some(&NftSetType::Ipv4Addr);
+        let v6 = Some(&NftSetType::Ipv6Addr);
+
+        let ip = saddr_match(NftFamily::Ip, v4, "s").unwrap();
+        assert_eq!((ip.dependency, ip.offset, ip.len), (None, 12, 4));
+        let inet = saddr_match(NftFamily::Inet, v6, "s").unwrap();
+        assert_eq!(
+            inet.dependency,
+            Some((NFT_META_NFPROTO, vec![NFPROTO_IPV6]))
+        );
+        assert_eq!((inet.offset, inet.len), (8, 16));
+        let bridge = saddr_match(NftFamily::Bridge, v4, "s").unwrap();
+        assert_eq!(
+            bridge.dependency,
+            Some((NFT_META_PROTOCOL, vec![0x08, 0x00]))
+        );
+
+        assert!(saddr_match(NftFamily::Ip, v6, "s").is_err());
+        assert!(saddr_match(NftFamily::Netdev, v4, "s").is_err());
+        assert!(saddr_match(NftFamily::Inet, Some(&NftSetType::Mark), "s").is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn test_nft_ensure_drop_rule_once() {
+        // Requires root
+        const TABLE_NAME: &str = "lnftsets_test_drop_rule_once";
+        let _ = nftset_delete_table(NftFamily::Ip, TABLE_NAME);
+        nftset_create_table(NftFamily::Ip, TABLE_NAME).unwrap();
+        nftset_create_set(NftFamily::Ip, TABLE_NAME, "banned", &Default::default()).unwrap();
+
+        assert!(!block_on(nft_chain_exists(NftFamily::Ip, TABLE_NAME, "block")).unwrap());
+        for _ in 0..3 {
+            nft_ensure_drop_rule(NftFamily::Ip, TABLE_NAME, "block", "banned").unwrap();
+        }
+        assert!(block_on(nft_chain_exists(NftFamily::Ip, TABLE_NAME, "block")).unwrap());
+        let rules = block_on(nft_rule_dump(NftFamily::Ip, TABLE_NAME, Some("block"))).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].comment.as_deref(), Some("ripset drop @banned"));
+        assert_eq!(rules[0].sets, vec!["banned".to_string()]);
+
+        nftset_delete_table(NftFamily::Ip, TABLE_NAME).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+//! Sessions shared by the threads of a server.
+//!
+//! An [`IpsetSession`] or [`NftSession`] serves one thread at a time. A
+//! [`SessionPool`] hands each thread a session of its own, reusing the
+//! sockets of the sessions returned by other threads, so concurrent
+//! operations neither open a socket per call nor wait for each other.
+
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{IpsetSession, NftSession, Result};
+
+/// A pool of sessions for concurrent set operations.
+///
+/// [`get`](SessionPool::get) takes an idle session, or opens a new one when
+/// all are in use, and the session goes back to the pool when the returned
+/// guard is dropped. The pool is `Sync`, so it can be shared between threads
+/// by reference or in an `Arc`.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::net::IpAddr;
+/// use ripset::{IpsetSession, SessionPool};
+///
+/// let pool = SessionPool::<IpsetSession>::ipset();
+/// std::thread::scope(|s| {
+///     for i in 1..=4 {
+///         let pool = &pool;
+///         s.spawn(move || {
+///             let addr: IpAddr = format!("10.0.0.{i}").parse().unwrap();
+///             pool.get().unwrap().add_exist("myset", addr).unwrap();
+///         });
+///     }
+/// });
+/// ```
+pub struct SessionPool<S> {
+    idle: Mutex<Vec<S>>,
+    open: Box<dyn Fn() -> Result<S> + Send + Sync>,
+    max_idle: usize,
+}
+
+impl<S> SessionPool<S> {
+    /// Create an empty pool opening sessions with `open`, for example
+    /// `|| IpsetSession::in_netns("/var/run/netns/router")`.
+    ///
+    /// Every returned session is kept, so the pool grows to the largest
+    /// number of sessions used at once.
+    pub fn new(open: impl Fn() -> Result<S> + Send + Sync + 'static) -> Self {
+        Self::with_max_idle(open, usize::MAX)
+    }
+
+    /// Create an empty pool keeping at most `max_idle` sessions when they
+    /// are not in use. Sessions returned to a full pool are closed.
+    pub fn with_max_idle(
+        open: impl Fn() -> Result<S> + Send + Sync + 'static,
+        max_idle: usize,
+    ) -> Self {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            open: Box::new(open),
+            max_idle,
+        }
+    }
+
+    /// Take a session for the calling thread, opening one if none is idle.
+    pub fn get(&self) -> Result<PooledSession<'_, S>> {
+        let session = match self.lock().pop() {
+            Some(session) => session,
+            None => (self.open)()?,
+        };
+        Ok(PooledSession {
+            pool: self,
+            session: Some(session),
+        })
+    }
+
+    /// Number of sessions waiting in the pool.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<S>> {
+        // The list of sessions is consistent even if a holder panicked
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SessionPool<IpsetSession> {
+    /// Create a pool of ipset sessions in the current network namespace.
+    pub fn ipset() -> Self {
+        Self::new(IpsetSession::new)
+    }
+}
+
+impl SessionPool<NftSession> {
+    /// Create a pool of nftables sessions in the current network namespace.
+    pub fn nftables() -> Self {
+        Self::new(NftSession::new)
+    }
+}
+
+impl<S> fmt::Debug for SessionPool<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionPool")
+            .field("idle", &self.idle())
+            .field("max_idle", &self.max_idle)
+            .finish()
+    }
+}
+
+/// A session taken from a [`SessionPool`], returned to it when dropped.
+pub struct PooledSession<'a, S> {
+    pool: &'a SessionPool<S>,
+    session: Option<S>,
+}
+
+impl<S> Deref for PooledSession<'_, S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        self.session.as_ref().expect("session already returned")
+    }
+}
+
+impl<S> Drop for PooledSession<'_, S> {
+    fn drop(&mut self) {
+        // A request interrupted by a panic may have left answers behind
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(session) = self.session.take() {
+            let mut idle = self.pool.lock();
+            if idle.len() < self.pool.max_idle {
+                idle.push(session);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_session_pool_reuse() {
+        static OPENED: AtomicUsize = AtomicUsize::new(0);
+        let pool = SessionPool::with_max_idle(|| Ok(OPENED.fetch_add(1, Ordering::SeqCst)), 1);
+
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        assert_eq!((*first, *second), (0, 1));
+        drop(first);
+        // The pool is full, so the second session is closed
+        drop(second);
+        assert_eq!(pool.idle(), 1);
+
+        assert_eq!(*pool.get().unwrap(), 0);
+        assert_eq!(OPENED.load(Ordering::SeqCst), 2);
+    }
+}
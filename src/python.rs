@@ -0,0 +1,323 @@
+//! Python module, built with the `python` feature.
+//!
+//! The `ripset` extension module exposes the set operations to Python, with
+//! sets created from text options and entries passed as text, in the syntax
+//! of the `ipset` and `nft` tools. Build it with
+//! `maturin build --release`, which enables the feature from
+//! `pyproject.toml`.
+//!
+//! Errors raise `ValueError` for invalid arguments, `PermissionError`
+//! without CAP_NET_ADMIN, and otherwise `ripset.RipsetError` or one of its
+//! subclasses. The interpreter lock is released while the kernel answers.
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyOSError, PyPermissionError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::RipsetError as Error;
+use crate::save::{parse_create_options, parse_entry};
+use crate::{
+    Entry, NftFamily, NftSetCreateOptions, ipset_add, ipset_add_exist, ipset_create, ipset_del,
+    ipset_destroy, ipset_flush, ipset_list_entries, ipset_swap, ipset_test, nftset_add,
+    nftset_create_set, nftset_create_table, nftset_del, nftset_delete_set, nftset_delete_table,
+    nftset_flush, nftset_list_entries, nftset_swap, nftset_test,
+};
+
+create_exception!(ripset, RipsetError, PyOSError, "A set operation failed.");
+create_exception!(
+    ripset,
+    SetNotFoundError,
+    RipsetError,
+    "The set or table does not exist."
+);
+create_exception!(
+    ripset,
+    EntryExistsError,
+    RipsetError,
+    "The entry is already in the set."
+);
+create_exception!(
+    ripset,
+    EntryNotFoundError,
+    RipsetError,
+    "The entry is not in the set."
+);
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> Self {
+        let message = error.to_string();
+        match error {
+            Error::InvalidOption(_)
+            | Error::InvalidAddress(_)
+            | Error::InvalidMacAddress(_)
+            | Error::InvalidAddressFamily
+            | Error::InvalidSetName(_)
+            | Error::InvalidTableName(_) => PyValueError::new_err(message),
+            Error::PermissionDenied => PyPermissionError::new_err(message),
+            Error::SetNotFound(_) => SetNotFoundError::new_err(message),
+            Error::EntryExists => EntryExistsError::new_err(message),
+            Error::EntryNotFound => EntryNotFoundError::new_err(message),
+            _ => RipsetError::new_err(message),
+        }
+    }
+}
+
+/// Create an ipset from its type and options, as in `ipset create`.
+#[pyfunction]
+#[pyo3(name = "ipset_create", signature = (setname, options = "hash:ip"))]
+fn py_ipset_create(py: Python<'_>, setname: &str, options: &str) -> PyResult<()> {
+    let options = parse_create_options(options)?;
+    Ok(py.detach(|| ipset_create(setname, &options))?)
+}
+
+/// Destroy an ipset.
+#[pyfunction]
+#[pyo3(name = "ipset_destroy")]
+fn py_ipset_destroy(py: Python<'_>, setname: &str) -> PyResult<()> {
+    Ok(py.detach(|| ipset_destroy(setname))?)
+}
+
+/// Remove all entries from an ipset.
+#[pyfunction]
+#[pyo3(name = "ipset_flush")]
+fn py_ipset_flush(py: Python<'_>, setname: &str) -> PyResult<()> {
+    Ok(py.detach(|| ipset_flush(setname))?)
+}
+
+/// Exchange the contents of two ipsets.
+#[pyfunction]
+#[pyo3(name = "ipset_swap")]
+fn py_ipset_swap(py: Python<'_>, setname1: &str, setname2: &str) -> PyResult<()> {
+    Ok(py.detach(|| ipset_swap(setname1, setname2))?)
+}
+
+/// Add an entry to an ipset, as in `ipset add`; with `exist`, an entry
+/// already present is updated instead of raising `EntryExistsError`.
+#[pyfunction]
+#[pyo3(name = "ipset_add", signature = (setname, entry, exist = false))]
+fn py_ipset_add(py: Python<'_>, setname: &str, entry: &str, exist: bool) -> PyResult<()> {
+    let entry = parse_entry(entry)?;
+    Ok(py.detach(|| {
+        if exist {
+            ipset_add_exist(setname, entry)
+        } else {
+            ipset_add(setname, entry)
+        }
+    })?)
+}
+
+/// Delete an entry from an ipset.
+#[pyfunction]
+#[pyo3(name = "ipset_del")]
+fn py_ipset_del(py: Python<'_>, setname: &str, entry: &str) -> PyResult<()> {
+    let entry = parse_entry(entry)?;
+    Ok(py.detach(|| ipset_del(setname, entry))?)
+}
+
+/// Test whether an entry is in an ipset.
+#[pyfunction]
+#[pyo3(name = "ipset_test")]
+fn py_ipset_test(py: Python<'_>, setname: &str, entry: &str) -> PyResult<bool> {
+    let entry = parse_entry(entry)?;
+    Ok(py.detach(|| ipset_test(setname, entry))?)
+}
+
+/// List the entries of an ipset, as in `ipset save`.
+#[pyfunction]
+#[pyo3(name = "ipset_list")]
+fn py_ipset_list(py: Python<'_>, setname: &str) -> PyResult<Vec<String>> {
+    let entries = py.detach(|| ipset_list_entries(setname))?;
+    Ok(entries.iter().map(Entry::to_string).collect())
+}
+
+/// Create an nftables table.
+#[pyfunction]
+#[pyo3(name = "nft_create_table")]
+fn py_nft_create_table(py: Python<'_>, family: &str, table: &str) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    Ok(py.detach(|| nftset_create_table(family, table))?)
+}
+
+/// Delete an nftables table with everything in it.
+#[pyfunction]
+#[pyo3(name = "nft_delete_table")]
+fn py_nft_delete_table(py: Python<'_>, family: &str, table: &str) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    Ok(py.detach(|| nftset_delete_table(family, table))?)
+}
+
+/// Create an nftables set keyed by an nft type such as `ipv4_addr`; the
+/// keyword options are `interval`, `counters`, `auto_merge`, `dynamic`,
+/// `timeout`, `size` and `comment`.
+#[pyfunction]
+#[pyo3(
+    name = "nft_create_set",
+    signature = (family, table, setname, set_type = "ipv4_addr", **options)
+)]
+fn py_nft_create_set(
+    py: Python<'_>,
+    family: &str,
+    table: &str,
+    setname: &str,
+    set_type: &str,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    let mut create = NftSetCreateOptions {
+        set_type: set_type.parse()?,
+        ..Default::default()
+    };
+    for (key, value) in options.into_iter().flatten() {
+        match key.extract::<String>()?.as_str() {
+            "interval" => create.interval = value.extract()?,
+            "counters" => create.counters = value.extract()?,
+            "auto_merge" => create.auto_merge = value.extract()?,
+            "dynamic" => create.dynamic = value.extract()?,
+            "timeout" => create.timeout = value.extract()?,
+            "size" => create.size = value.extract()?,
+            "comment" => create.comment = value.extract()?,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown set option: {other}"
+                )));
+            }
+        }
+    }
+    Ok(py.detach(|| nftset_create_set(family, table, setname, &create))?)
+}
+
+/// Delete an nftables set.
+#[pyfunction]
+#[pyo3(name = "nft_delete_set")]
+fn py_nft_delete_set(py: Python<'_>, family: &str, table: &str, setname: &str) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    Ok(py.detach(|| nftset_delete_set(family, table, setname))?)
+}
+
+/// Remove all elements from an nftables set.
+#[pyfunction]
+#[pyo3(name = "nft_flush")]
+fn py_nft_flush(py: Python<'_>, family: &str, table: &str, setname: &str) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    Ok(py.detach(|| nftset_flush(family, table, setname))?)
+}
+
+/// Exchange the elements of two nftables sets of a table.
+#[pyfunction]
+#[pyo3(name = "nft_swap")]
+fn py_nft_swap(
+    py: Python<'_>,
+    family: &str,
+    table: &str,
+    setname1: &str,
+    setname2: &str,
+) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    Ok(py.detach(|| nftset_swap(family, table, setname1, setname2))?)
+}
+
+/// Add an element to an nftables set, written as in nft.
+#[pyfunction]
+#[pyo3(name = "nft_add")]
+fn py_nft_add(
+    py: Python<'_>,
+    family: &str,
+    table: &str,
+    setname: &str,
+    entry: &str,
+) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    let entry: Entry = entry.parse()?;
+    Ok(py.detach(|| nftset_add(family, table, setname, entry))?)
+}
+
+/// Delete an element from an nftables set.
+#[pyfunction]
+#[pyo3(name = "nft_del")]
+fn py_nft_del(
+    py: Python<'_>,
+    family: &str,
+    table: &str,
+    setname: &str,
+    entry: &str,
+) -> PyResult<()> {
+    let family: NftFamily = family.parse()?;
+    let entry: Entry = entry.parse()?;
+    Ok(py.detach(|| nftset_del(family, table, setname, entry))?)
+}
+
+/// Test whether an element is in an nftables set.
+#[pyfunction]
+#[pyo3(name = "nft_test")]
+fn py_nft_test(
+    py: Python<'_>,
+    family: &str,
+    table: &str,
+    setname: &str,
+    entry: &str,
+) -> PyResult<bool> {
+    let family: NftFamily = family.parse()?;
+    let entry: Entry = entry.parse()?;
+    Ok(py.detach(|| nftset_test(family, table, setname, entry))?)
+}
+
+/// List the elements of an nftables set.
+#[pyfunction]
+#[pyo3(name = "nft_list")]
+fn py_nft_list(py: Python<'_>, family: &str, table: &str, setname: &str) -> PyResult<Vec<String>> {
+    let family: NftFamily = family.parse()?;
+    let entries = py.detach(|| nftset_list_entries(family, table, setname))?;
+    Ok(entries.iter().map(Entry::to_string).collect())
+}
+
+/// Manage Linux ipset and nftables sets via netlink.
+#[pymodule]
+fn ripset(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("RipsetError", py.get_type::<RipsetError>())?;
+    m.add("SetNotFoundError", py.get_type::<SetNotFoundError>())?;
+    m.add("EntryExistsError", py.get_type::<EntryExistsError>())?;
+    m.add("EntryNotFoundError", py.get_type::<EntryNotFoundError>())?;
+
+    m.add_function(wrap_pyfunction!(py_ipset_create, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_destroy, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_flush, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_swap, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_add, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_del, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_test, m)?)?;
+    m.add_function(wrap_pyfunction!(py_ipset_list, m)?)?;
+
+    m.add_function(wrap_pyfunction!(py_nft_create_table, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_delete_table, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_create_set, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_delete_set, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_flush, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_swap, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_add, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_del, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_test, m)?)?;
+    m.add_function(wrap_pyfunction!(py_nft_list, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceptions() {
+        Python::initialize();
+        Python::attach(|py| {
+            let error = PyErr::from(Error::SetNotFound("blocklist".to_string()));
+            assert!(error.is_instance_of::<SetNotFoundError>(py));
+            assert!(error.is_instance_of::<RipsetError>(py));
+            assert!(error.is_instance_of::<PyOSError>(py));
+
+            let error = PyErr::from(Error::InvalidAddress("10.0.0.0/99".to_string()));
+            assert!(error.is_instance_of::<PyValueError>(py));
+            assert!(PyErr::from(Error::PermissionDenied).is_instance_of::<PyPermissionError>(py));
+        });
+    }
+}
@@ -0,0 +1,445 @@
+//! The text format of `ipset save` and `ipset restore`.
+//!
+//! Each set is written as a `create` line with its header followed by one
+//! `add` line per entry, the same text `ipset save` prints, so sets managed
+//! through this crate can be loaded on another host with `ipset restore`.
+//! The other way around, [`parse_restore`] reads such files, as blocklists
+//! are often distributed, and [`ipset_restore`] loads them into the kernel.
+
+use std::fmt::Write;
+
+use crate::{
+    Entry, IpEntry, IpSetCreateOptions, IpSetFamily, IpSetInfo, IpSetRange, Result, RipsetError,
+    ipset_add_many, ipset_create_exist, ipset_info, ipset_list_entries, ipset_list_sets,
+};
+
+/// Format a set as `ipset save` does, from its header and entries.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::save::format_set;
+/// use ripset::{ipset_info, ipset_list_entries};
+///
+/// let info = ipset_info("blocklist").unwrap();
+/// let entries = ipset_list_entries("blocklist").unwrap();
+/// print!("{}", format_set(&info, &entries));
+/// ```
+pub fn format_set(info: &IpSetInfo, entries: &[Entry]) -> String {
+    let mut out = create_line(info);
+    for entry in entries {
+        let _ = writeln!(out, "add {} {entry}", info.name);
+    }
+    out
+}
+
+/// The `create` line of a set, options in the order `ipset save` prints
+/// them.
+fn create_line(info: &IpSetInfo) -> String {
+    let mut line = format!("create {} {}", info.name, info.type_name);
+    // hash:mac stores no addresses and takes no family
+    if info.type_name.starts_with("hash:")
+        && info.type_name != "hash:mac"
+        && let Some(family) = info.family
+    {
+        line.push_str(match family {
+            IpSetFamily::Inet => " family inet",
+            IpSetFamily::Inet6 => " family inet6",
+        });
+    }
+    match info.range {
+        Some(IpSetRange::FromTo(from, to)) => {
+            let _ = write!(line, " range {from}-{to}");
+        }
+        Some(IpSetRange::Cidr(addr, cidr)) => {
+            let _ = write!(line, " range {addr}/{cidr}");
+        }
+        Some(IpSetRange::Ports(from, to)) => {
+            let _ = write!(line, " range {from}-{to}");
+        }
+        None => {}
+    }
+    let numbers = [
+        ("hashsize", info.hashsize),
+        ("maxelem", info.maxelem),
+        ("size", info.size),
+        ("timeout", info.timeout),
+    ];
+    for (name, value) in numbers {
+        if let Some(value) = value {
+            let _ = write!(line, " {name} {value}");
+        }
+    }
+    let flags = [
+        ("counters", info.counters),
+        ("comment", info.comment),
+        ("forceadd", info.forceadd),
+        ("skbinfo", info.skbinfo),
+    ];
+    for (name, set) in flags {
+        if set {
+            let _ = write!(line, " {name}");
+        }
+    }
+    if let Some(bucketsize) = info.bucketsize {
+        let _ = write!(line, " bucketsize {bucketsize}");
+    }
+    if let Some(initval) = info.initval {
+        let _ = write!(line, " initval {initval:#010x}");
+    }
+    line.push('\n');
+    line
+}
+
+/// Save an ipset in the format of `ipset save`.
+///
+/// # Arguments
+///
+/// * `setname` - The name of the ipset
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_save;
+///
+/// std::fs::write("blocklist.save", ipset_save("blocklist").unwrap()).unwrap();
+/// ```
+pub fn ipset_save(setname: &str) -> Result<String> {
+    let info = ipset_info(setname)?;
+    let entries = ipset_list_entries(setname)?;
+    Ok(format_set(&info, &entries))
+}
+
+/// Save all ipsets in the format of `ipset save`.
+///
+/// Sets come in the order the kernel keeps them, which is the order they
+/// were created in, so the members of a `list:set` come before it as
+/// `ipset restore` requires. Sets destroyed while saving are left out.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_save_all;
+///
+/// std::fs::write("ipsets.save", ipset_save_all().unwrap()).unwrap();
+/// ```
+pub fn ipset_save_all() -> Result<String> {
+    let mut out = String::new();
+    for info in ipset_list_sets()? {
+        let entries = match ipset_list_entries(&info.name) {
+            Ok(entries) => entries,
+            // Destroyed since the sets were listed
+            Err(RipsetError::SetNotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        out.push_str(&format_set(&info, &entries));
+    }
+    Ok(out)
+}
+
+/// A set read from an `ipset save` file by [`parse_restore`].
+#[derive(Clone, Debug)]
+pub struct SavedSet {
+    /// Set name
+    pub name: String,
+    /// Options of the `create` line, `None` if the file only adds entries
+    /// to the set
+    pub options: Option<IpSetCreateOptions>,
+    /// Entries of the `add` lines
+    pub entries: Vec<Entry>,
+}
+
+/// Parse the `create` and `add` lines of an `ipset save` file.
+///
+/// Sets are returned in the order they first appear. Blank lines and
+/// lines starting with `#` are skipped. The `size` of a `list:set` and the
+/// options `ipset` itself ignores (`probes`, `resize` and `gc`) are
+/// dropped; other commands and unknown options are errors naming the line.
+///
+/// # Example
+///
+/// ```
+/// use ripset::save::parse_restore;
+///
+/// let sets = parse_restore(
+///     "create blocklist hash:net family inet hashsize 1024 maxelem 65536\n\
+///      add blocklist 192.0.2.0/24\n",
+/// )
+/// .unwrap();
+/// assert_eq!(sets[0].name, "blocklist");
+/// assert_eq!(sets[0].entries.len(), 1);
+/// ```
+pub fn parse_restore(text: &str) -> Result<Vec<SavedSet>> {
+    let mut sets: Vec<SavedSet> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid =
+            |e: RipsetError| RipsetError::InvalidOption(format!("line {}: {e}", index + 1));
+
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim_start();
+        let (name, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let rest = rest.trim();
+        if name.is_empty() {
+            return Err(invalid(RipsetError::InvalidSetName(name.to_string())));
+        }
+        match command {
+            "create" => {
+                if sets.iter().any(|set| set.name == name) {
+                    return Err(invalid(RipsetError::InvalidOption(format!(
+                        "set {name} already appeared on an earlier line"
+                    ))));
+                }
+                sets.push(SavedSet {
+                    name: name.to_string(),
+                    options: Some(parse_create_options(rest).map_err(invalid)?),
+                    entries: Vec::new(),
+                });
+            }
+            "add" => {
+                let entry = parse_entry(rest).map_err(invalid)?;
+                // Most files add to the set created last
+                let position = sets.iter().rposition(|set| set.name == name);
+                let set = match position {
+                    Some(position) => &mut sets[position],
+                    None => {
+                        sets.push(SavedSet {
+                            name: name.to_string(),
+                            options: None,
+                            entries: Vec::new(),
+                        });
+                        sets.last_mut().unwrap()
+                    }
+                };
+                set.entries.push(entry);
+            }
+            _ => {
+                return Err(invalid(RipsetError::InvalidOption(format!(
+                    "unsupported command: {command}"
+                ))));
+            }
+        }
+    }
+    Ok(sets)
+}
+
+/// Parse the type and options following the name on a `create` line.
+pub(crate) fn parse_create_options(text: &str) -> Result<IpSetCreateOptions> {
+    let mut words = text.split_whitespace();
+    let mut options = IpSetCreateOptions {
+        set_type: words.next().unwrap_or("").parse()?,
+        ..Default::default()
+    };
+    while let Some(key) = words.next() {
+        match key {
+            "counters" => options.counters = true,
+            "comment" => options.comment = true,
+            "skbinfo" => options.skbinfo = true,
+            "forceadd" => options.forceadd = true,
+            _ => {
+                let value = words.next().unwrap_or("");
+                let invalid = || RipsetError::InvalidOption(format!("invalid {key}: {value}"));
+                let number = |value: &str| value.parse::<u32>().map_err(|_| invalid());
+                match key {
+                    "family" => {
+                        options.family = match value {
+                            "inet" => IpSetFamily::Inet,
+                            "inet6" => IpSetFamily::Inet6,
+                            _ => return Err(invalid()),
+                        }
+                    }
+                    "hashsize" => options.hashsize = Some(number(value)?),
+                    "maxelem" => options.maxelem = Some(number(value)?),
+                    "timeout" => options.timeout = Some(number(value)?),
+                    "bucketsize" => {
+                        options.bucketsize = Some(value.parse().map_err(|_| invalid())?)
+                    }
+                    "initval" => {
+                        let hex = value.strip_prefix("0x").ok_or_else(invalid)?;
+                        options.initval =
+                            Some(u32::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                    }
+                    "range" => options.range = Some(parse_range(value).ok_or_else(invalid)?),
+                    "size" | "probes" | "resize" | "gc" => {}
+                    _ => {
+                        return Err(RipsetError::InvalidOption(format!(
+                            "unsupported create option: {key}"
+                        )));
+                    }
+                }
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Parse the range of a bitmap set: ports or addresses, as a range or a
+/// CIDR block.
+fn parse_range(value: &str) -> Option<IpSetRange> {
+    if let Some((addr, cidr)) = value.split_once('/') {
+        return Some(IpSetRange::Cidr(addr.parse().ok()?, cidr.parse().ok()?));
+    }
+    let (from, to) = value.split_once('-')?;
+    match (from.parse(), to.parse()) {
+        (Ok(from), Ok(to)) => Some(IpSetRange::Ports(from, to)),
+        _ => Some(IpSetRange::FromTo(from.parse().ok()?, to.parse().ok()?)),
+    }
+}
+
+/// Parse the element and options of an `add` line. Options such as
+/// `timeout` and `comment` are only supported on address entries.
+pub(crate) fn parse_entry(text: &str) -> Result<Entry> {
+    if text.contains(char::is_whitespace) {
+        text.parse::<IpEntry>().map(Entry::Ip)
+    } else {
+        text.parse()
+    }
+}
+
+/// Create the sets of an `ipset save` file and add their entries, like
+/// `ipset restore -exist`.
+///
+/// The whole file is parsed before anything is changed, so a syntax error
+/// leaves the sets alone. Sets that already exist with the same options are
+/// kept, and their entries are added with [`ipset_add_many`], which packs
+/// them into as few netlink messages as possible. Loading isn't atomic: if
+/// the kernel refuses a set or an entry, the sets before it are loaded.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::ipset_restore;
+///
+/// let text = std::fs::read_to_string("blocklist.ipset").unwrap();
+/// ipset_restore(&text).unwrap();
+/// ```
+pub fn ipset_restore(text: &str) -> Result<()> {
+    for set in parse_restore(text)? {
+        if let Some(options) = &set.options {
+            ipset_create_exist(&set.name, options)?;
+        }
+        ipset_add_many(&set.name, set.entries)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpEntry;
+
+    fn info(name: &str, type_name: &str) -> IpSetInfo {
+        IpSetInfo {
+            name: name.to_string(),
+            type_name: type_name.to_string(),
+            revision: 0,
+            family: Some(IpSetFamily::Inet),
+            timeout: None,
+            hashsize: None,
+            maxelem: None,
+            bucketsize: None,
+            initval: None,
+            range: None,
+            size: None,
+            counters: false,
+            comment: false,
+            skbinfo: false,
+            forceadd: false,
+            references: 0,
+            memsize: 0,
+            entries: 0,
+        }
+    }
+
+    #[test]
+    fn test_format_set() {
+        let mut hash = info("f2b-sshd", "hash:ip");
+        hash.hashsize = Some(1024);
+        hash.maxelem = Some(65536);
+        hash.timeout = Some(600);
+        hash.comment = true;
+        hash.bucketsize = Some(12);
+        hash.initval = Some(0x1c1d3a5);
+        let mut entry = IpEntry::new("10.0.0.1".parse().unwrap());
+        entry.timeout = Some(59);
+        entry.comment = Some("ssh".to_string());
+        assert_eq!(
+            format_set(&hash, &[entry.into()]),
+            "create f2b-sshd hash:ip family inet hashsize 1024 maxelem 65536 timeout 600 \
+             comment bucketsize 12 initval 0x01c1d3a5\n\
+             add f2b-sshd 10.0.0.1 timeout 59 comment \"ssh\"\n"
+        );
+
+        let mut bitmap = info("ports", "bitmap:port");
+        bitmap.range = Some(IpSetRange::Ports(1, 1024));
+        bitmap.counters = true;
+        assert_eq!(
+            format_set(&bitmap, &[Entry::from(22)]),
+            "create ports bitmap:port range 1-1024 counters\nadd ports 22\n"
+        );
+
+        let mut list = info("all", "list:set");
+        list.family = None;
+        list.size = Some(8);
+        assert_eq!(
+            format_set(&list, &[Entry::from("f2b-sshd")]),
+            "create all list:set size 8\nadd all f2b-sshd\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_restore() {
+        let text = "# blocklist\n\
+            create f2b-sshd hash:ip family inet6 hashsize 1024 maxelem 65536 timeout 600 \
+            comment bucketsize 12 initval 0x01c1d3a5\n\
+            add f2b-sshd 2001:db8::1 timeout 59 comment \"ssh\"\n\
+            \n\
+            create ports bitmap:port range 1-1024 counters\n\
+            add ports 22\n\
+            add f2b-sshd 2001:db8::2\n";
+        let sets = parse_restore(text).unwrap();
+        assert_eq!(sets.len(), 2);
+
+        let options = sets[0].options.as_ref().unwrap();
+        assert_eq!(options.set_type, crate::IpSetType::HashIp);
+        assert_eq!(options.family, IpSetFamily::Inet6);
+        assert_eq!(options.timeout, Some(600));
+        assert_eq!(options.bucketsize, Some(12));
+        assert_eq!(options.initval, Some(0x1c1d3a5));
+        assert!(options.comment && !options.counters);
+        let mut entry = IpEntry::new("2001:db8::1".parse().unwrap());
+        entry.timeout = Some(59);
+        entry.comment = Some("ssh".to_string());
+        assert_eq!(
+            sets[0].entries,
+            [
+                entry.into(),
+                Entry::from("2001:db8::2".parse::<std::net::IpAddr>().unwrap())
+            ]
+        );
+
+        let options = sets[1].options.as_ref().unwrap();
+        assert_eq!(options.range, Some(IpSetRange::Ports(1, 1024)));
+        assert_eq!(sets[1].entries, [Entry::from(22)]);
+
+        // Adding to a set the file doesn't create
+        let sets = parse_restore("add other 10.0.0.0/8").unwrap();
+        assert!(sets[0].options.is_none());
+
+        for (text, error) in [
+            ("create a hash:ip\ncreate a hash:ip", "line 2: "),
+            ("swap a b", "unsupported command: swap"),
+            (
+                "create a hash:ip netmask 24",
+                "unsupported create option: netmask",
+            ),
+            ("create a bitmap:ip range 10.0.0.1", "invalid range"),
+        ] {
+            let message = parse_restore(text).unwrap_err().to_string();
+            assert!(message.contains(error), "{message}");
+        }
+    }
+}
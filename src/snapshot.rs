@@ -0,0 +1,191 @@
+//! Backups of whole sets.
+//!
+//! [`snapshot`] captures the definition and the contents of an ipset or an
+//! nftables set as a [`SetSnapshot`], and [`restore`] brings the set back to
+//! that state, recreating it if it was deleted in the meantime. With the
+//! `serde` feature, snapshots can be written to disk and restored after a
+//! reboot.
+
+use crate::{
+    Entry, IpSetCreateOptions, NftFamily, NftSetCreateOptions, NftTransaction, Result, ipset_info,
+    ipset_list_entries, ipset_replace, nftset_exists, nftset_info, nftset_list_entries,
+    nftset_map_list,
+};
+
+/// A set of either backend.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SetRef {
+    /// An ipset
+    Ipset(String),
+    /// An nftables set or map
+    Nft {
+        family: NftFamily,
+        table: String,
+        name: String,
+    },
+}
+
+impl SetRef {
+    /// An ipset.
+    pub fn ipset(name: &str) -> Self {
+        SetRef::Ipset(name.to_string())
+    }
+
+    /// An nftables set or map.
+    pub fn nft(family: NftFamily, table: &str, name: &str) -> Self {
+        SetRef::Nft {
+            family,
+            table: table.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+/// The definition and contents of a set at one point in time.
+///
+/// Entries keep their remaining timeout, comments and, for ipset, their
+/// packet and byte counters.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SetSnapshot {
+    /// An ipset
+    Ipset {
+        name: String,
+        options: IpSetCreateOptions,
+        entries: Vec<Entry>,
+    },
+    /// An nftables set or map
+    Nft {
+        family: NftFamily,
+        table: String,
+        name: String,
+        options: NftSetCreateOptions,
+        /// Elements, with their value in a map
+        elements: Vec<(Entry, Option<Entry>)>,
+    },
+}
+
+impl SetSnapshot {
+    /// The set the snapshot was taken of.
+    pub fn set(&self) -> SetRef {
+        match self {
+            SetSnapshot::Ipset { name, .. } => SetRef::Ipset(name.clone()),
+            SetSnapshot::Nft {
+                family,
+                table,
+                name,
+                ..
+            } => SetRef::nft(*family, table, name),
+        }
+    }
+
+    /// Number of entries in the snapshot.
+    pub fn len(&self) -> usize {
+        match self {
+            SetSnapshot::Ipset { entries, .. } => entries.len(),
+            SetSnapshot::Nft { elements, .. } => elements.len(),
+        }
+    }
+
+    /// Whether the snapshot holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Capture the definition and the entries of a set.
+///
+/// The definition and the entries are read one after the other, so changes
+/// made to the set in between may be caught only partly. Fails for nftables
+/// sets whose type is not known to this library.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{NftFamily, SetRef, restore, snapshot};
+///
+/// let backup = snapshot(&SetRef::nft(NftFamily::Inet, "filter", "blocklist")).unwrap();
+/// // ... a risky update of the set ...
+/// restore(&backup).unwrap();
+/// ```
+pub fn snapshot(set: &SetRef) -> Result<SetSnapshot> {
+    match set {
+        SetRef::Ipset(name) => Ok(SetSnapshot::Ipset {
+            name: name.clone(),
+            options: ipset_info(name)?.create_options()?,
+            entries: ipset_list_entries(name)?,
+        }),
+        SetRef::Nft {
+            family,
+            table,
+            name,
+        } => {
+            let info = nftset_info(*family, table, name)?;
+            let elements = if info.map {
+                nftset_map_list(*family, table, name)?
+                    .into_iter()
+                    .map(|(key, value)| (key, Some(value)))
+                    .collect()
+            } else {
+                nftset_list_entries(*family, table, name)?
+                    .into_iter()
+                    .map(|entry| (entry, None))
+                    .collect()
+            };
+            Ok(SetSnapshot::Nft {
+                family: *family,
+                table: table.clone(),
+                name: name.clone(),
+                options: info.create_options()?,
+                elements,
+            })
+        }
+    }
+}
+
+/// Bring a set back to the state captured by [`snapshot`].
+///
+/// The entries of the set are replaced by those of the snapshot in a single
+/// step, so the set is never seen half restored, and a set that no longer
+/// exists is created again (with its nftables table if needed).
+///
+/// An ipset is replaced through a temporary set, see [`ipset_replace`],
+/// and takes the options of the snapshot. An existing nftables set keeps its current definition, as the
+/// kernel cannot change it in place, and only its elements are replaced.
+/// Packet and byte counters of nftables elements are not restored.
+pub fn restore(snapshot: &SetSnapshot) -> Result<()> {
+    match snapshot {
+        SetSnapshot::Ipset {
+            name,
+            options,
+            entries,
+        } => ipset_replace(name, entries.iter().cloned(), options),
+        SetSnapshot::Nft {
+            family,
+            table,
+            name,
+            options,
+            elements,
+        } => {
+            let mut tx = NftTransaction::new();
+            if nftset_exists(*family, table, name)? {
+                tx.flush_set(*family, table, name)?;
+            } else {
+                tx.create_table(*family, table)?;
+                tx.create_set(*family, table, name, options)?;
+            }
+            for (entry, value) in elements {
+                match value {
+                    Some(value) => {
+                        tx.map_add(*family, table, name, entry.clone(), value.clone())?
+                    }
+                    None => tx.add(*family, table, name, entry.clone())?,
+                }
+            }
+            tx.commit()
+        }
+    }
+}
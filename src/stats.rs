@@ -0,0 +1,99 @@
+//! Usage figures of a set.
+//!
+//! [`set_stats`] reports how full a set is, how much kernel memory it takes
+//! and how much traffic its entries matched, for capacity monitoring without
+//! the `ipset` and `nft` tools.
+
+use crate::{
+    Entry, Result, SetRef, ipset_info, ipset_list_entries, nftset_info, nftset_list_elements,
+};
+
+/// Usage figures of a set, as returned by [`set_stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SetStats {
+    /// Number of entries (ranges of interval sets count once)
+    pub entries: u32,
+    /// Maximum number of entries, if the set has a limit
+    pub max_entries: Option<u32>,
+    /// Kernel memory used by the set in bytes (ipset only)
+    pub memsize: Option<u32>,
+    /// Packets matched by all entries (sets created with counters)
+    pub packets: Option<u64>,
+    /// Bytes matched by all entries (sets created with counters)
+    pub bytes: Option<u64>,
+}
+
+/// Get the usage figures of a set.
+///
+/// The entry count and memory usage come from the header of the set. For
+/// sets created with counters, the entries are listed to add up their
+/// packet and byte counters, which costs as much as a listing. Counters of
+/// ipset entries without an address, such as `bitmap:port` and `hash:mac`
+/// entries, are not included.
+///
+/// # Example
+///
+/// ```no_run
+/// use ripset::{SetRef, set_stats};
+///
+/// let stats = set_stats(&SetRef::ipset("blocklist")).unwrap();
+/// if let Some(max) = stats.max_entries {
+///     println!("{}% full", u64::from(stats.entries) * 100 / u64::from(max));
+/// }
+/// ```
+pub fn set_stats(set: &SetRef) -> Result<SetStats> {
+    match set {
+        SetRef::Ipset(name) => {
+            let info = ipset_info(name)?;
+            let mut stats = SetStats {
+                entries: info.entries,
+                max_entries: info.maxelem,
+                memsize: Some(info.memsize),
+                ..Default::default()
+            };
+            if info.counters {
+                let entries = ipset_list_entries(name)?;
+                let counters = entries.iter().filter_map(|entry| match entry {
+                    Entry::Ip(ip) => Some((ip.packets, ip.bytes)),
+                    _ => None,
+                });
+                (stats.packets, stats.bytes) = sum_counters(counters);
+            }
+            Ok(stats)
+        }
+        SetRef::Nft {
+            family,
+            table,
+            name,
+        } => {
+            let info = nftset_info(*family, table, name)?;
+            let mut stats = SetStats {
+                entries: info.entries,
+                max_entries: info.size,
+                ..Default::default()
+            };
+            if info.counters {
+                let counters = nftset_list_elements(*family, table, name)?
+                    .into_iter()
+                    .map(|element| (element.packets, element.bytes));
+                (stats.packets, stats.bytes) = sum_counters(counters);
+            }
+            Ok(stats)
+        }
+    }
+}
+
+/// Total packets and bytes of a set with counters, missing counters
+/// counting as zero.
+fn sum_counters(
+    counters: impl Iterator<Item = (Option<u64>, Option<u64>)>,
+) -> (Option<u64>, Option<u64>) {
+    let (packets, bytes) = counters.fold((0u64, 0u64), |(p, b), (packets, bytes)| {
+        (
+            p.saturating_add(packets.unwrap_or(0)),
+            b.saturating_add(bytes.unwrap_or(0)),
+        )
+    });
+    (Some(packets), Some(bytes))
+}
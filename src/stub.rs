@@ -1,23 +1,115 @@
 //! Stub implementations for non-Linux platforms.
 //!
-//! All functions return `Err(IpSetError::UnsupportedPlatform)`.
+//! All functions return `Err(RipsetError::UnsupportedPlatform)`.
 
-use std::net::IpAddr;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+#[cfg(unix)]
+use std::os::fd::BorrowedFd;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use crate::{IpEntry, IpSetError, Result};
+use crate::{BackendCapabilities, Entry, IpEntry, NftFamily, Result, RipsetError};
 
 /// ipset type for hash:ip sets (stub for non-Linux)
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IpSetType {
     /// hash:ip - stores IP addresses
     #[default]
+    #[cfg_attr(feature = "serde", serde(rename = "hash:ip"))]
     HashIp,
     /// hash:net - stores network addresses (CIDR)
+    #[cfg_attr(feature = "serde", serde(rename = "hash:net"))]
     HashNet,
+    /// bitmap:ip - stores IPv4 addresses from a fixed range (requires `range`)
+    #[cfg_attr(feature = "serde", serde(rename = "bitmap:ip"))]
+    BitmapIp,
+    /// bitmap:ip,mac - stores IPv4 and MAC address pairs from a fixed range (requires `range`)
+    #[cfg_attr(feature = "serde", serde(rename = "bitmap:ip,mac"))]
+    BitmapIpMac,
+    /// list:set - stores names of other sets
+    #[cfg_attr(feature = "serde", serde(rename = "list:set"))]
+    ListSet,
+    /// hash:ip,port - stores IP address, protocol and port triples
+    #[cfg_attr(feature = "serde", serde(rename = "hash:ip,port"))]
+    HashIpPort,
+    /// hash:net,port - stores network address, protocol and port triples
+    #[cfg_attr(feature = "serde", serde(rename = "hash:net,port"))]
+    HashNetPort,
+    /// bitmap:port - stores ports from a fixed range (requires a port `range`)
+    #[cfg_attr(feature = "serde", serde(rename = "bitmap:port"))]
+    BitmapPort,
+    /// hash:mac - stores MAC addresses
+    #[cfg_attr(feature = "serde", serde(rename = "hash:mac"))]
+    HashMac,
+    /// hash:ip,mark - stores IP address and packet mark pairs
+    #[cfg_attr(feature = "serde", serde(rename = "hash:ip,mark"))]
+    HashIpMark,
+    /// hash:net,iface - stores network address and interface name pairs
+    #[cfg_attr(feature = "serde", serde(rename = "hash:net,iface"))]
+    HashNetIface,
+}
+
+impl fmt::Display for IpSetType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IpSetType::HashIp => "hash:ip",
+            IpSetType::HashNet => "hash:net",
+            IpSetType::BitmapIp => "bitmap:ip",
+            IpSetType::BitmapIpMac => "bitmap:ip,mac",
+            IpSetType::ListSet => "list:set",
+            IpSetType::HashIpPort => "hash:ip,port",
+            IpSetType::HashNetPort => "hash:net,port",
+            IpSetType::BitmapPort => "bitmap:port",
+            IpSetType::HashMac => "hash:mac",
+            IpSetType::HashIpMark => "hash:ip,mark",
+            IpSetType::HashNetIface => "hash:net,iface",
+        })
+    }
+}
+
+impl FromStr for IpSetType {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        [
+            IpSetType::HashIp,
+            IpSetType::HashNet,
+            IpSetType::BitmapIp,
+            IpSetType::BitmapIpMac,
+            IpSetType::ListSet,
+            IpSetType::HashIpPort,
+            IpSetType::HashNetPort,
+            IpSetType::BitmapPort,
+            IpSetType::HashMac,
+            IpSetType::HashIpMark,
+            IpSetType::HashNetIface,
+        ]
+        .into_iter()
+        .find(|set_type| set_type.to_string() == s)
+        .ok_or_else(|| RipsetError::InvalidOption(format!("unknown set type: {s}")))
+    }
+}
+
+/// Address range for bitmap set types (stub for non-Linux)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IpSetRange {
+    /// Inclusive range from the first to the last address
+    FromTo(Ipv4Addr, Ipv4Addr),
+    /// Network address with prefix length
+    Cidr(Ipv4Addr, u8),
+    /// Inclusive port range (bitmap:port)
+    Ports(u16, u16),
 }
 
 /// Address family for ipset (stub for non-Linux)
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum IpSetFamily {
     /// IPv4 addresses
     #[default]
@@ -28,132 +120,1352 @@ pub enum IpSetFamily {
 
 /// Options for creating an ipset (stub for non-Linux)
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct IpSetCreateOptions {
     pub set_type: IpSetType,
     pub family: IpSetFamily,
+    /// Initial hash table size for hash types (rounded up to a power of two by the kernel)
     pub hashsize: Option<u32>,
+    /// Maximal number of elements for hash types (kernel default is 65536)
     pub maxelem: Option<u32>,
+    /// Maximal number of elements per hash bucket (ignored on kernels older than 5.11)
+    pub bucketsize: Option<u8>,
+    /// Hash seed for reproducible hashing (ignored on kernels older than 5.11)
+    pub initval: Option<u32>,
     pub timeout: Option<u32>,
+    /// Address range, required for bitmap types
+    pub range: Option<IpSetRange>,
+    /// Keep per-entry packet and byte counters
+    pub counters: bool,
+    /// Allow a comment on each entry
+    pub comment: bool,
+    /// Allow skbmark/skbprio/skbqueue values on each entry
+    pub skbinfo: bool,
+    /// Evict a random entry instead of failing when a hash set is full
+    pub forceadd: bool,
+    /// Raw attributes appended to the `IPSET_ATTR_DATA` block of the create
+    /// request
+    pub extra_attrs: Vec<(u16, Vec<u8>)>,
 }
 
-/// Address type for nftables sets (stub for non-Linux)
-#[derive(Clone, Copy, Debug, Default)]
+/// Protocol versions supported by the kernel ipset subsystem (stub for non-Linux)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpSetProtocol {
+    /// Protocol version spoken by the kernel
+    pub version: u8,
+    /// Oldest protocol version accepted by the kernel
+    pub min_version: u8,
+}
+
+/// Information about an ipset (stub for non-Linux)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IpSetInfo {
+    /// Set name
+    pub name: String,
+    /// Set type name, e.g. `hash:ip`
+    pub type_name: String,
+    /// Set type revision
+    pub revision: u8,
+    /// Address family (`None` for family-independent types like list:set)
+    pub family: Option<IpSetFamily>,
+    /// Default entry timeout in seconds
+    pub timeout: Option<u32>,
+    /// Hash table size (hash types)
+    pub hashsize: Option<u32>,
+    /// Maximal number of elements (hash types)
+    pub maxelem: Option<u32>,
+    /// Maximal number of elements per bucket (hash types)
+    pub bucketsize: Option<u8>,
+    /// Hash seed (hash types)
+    pub initval: Option<u32>,
+    /// Address range (bitmap types)
+    pub range: Option<IpSetRange>,
+    /// Maximal number of member sets (list:set)
+    pub size: Option<u32>,
+    /// Entries carry packet and byte counters
+    pub counters: bool,
+    /// Entries may carry a comment
+    pub comment: bool,
+    /// Entries may carry skbinfo values
+    pub skbinfo: bool,
+    /// A full hash set evicts entries on add
+    pub forceadd: bool,
+    /// Number of references (e.g. from iptables rules or list:set sets)
+    pub references: u32,
+    /// Memory used by the set in bytes
+    pub memsize: u32,
+    /// Number of entries in the set
+    pub entries: u32,
+}
+
+impl IpSetInfo {
+    /// Options that create a set with the same header
+    pub fn create_options(&self) -> Result<IpSetCreateOptions> {
+        Ok(IpSetCreateOptions {
+            set_type: self.type_name.parse()?,
+            family: self.family.unwrap_or(IpSetFamily::Inet),
+            hashsize: self.hashsize,
+            maxelem: self.maxelem,
+            bucketsize: self.bucketsize,
+            initval: self.initval,
+            timeout: self.timeout,
+            range: self.range,
+            counters: self.counters,
+            comment: self.comment,
+            skbinfo: self.skbinfo,
+            forceadd: self.forceadd,
+            extra_attrs: Vec::new(),
+        })
+    }
+}
+
+/// Comparison of an entry counter against a threshold (stub for non-Linux)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterMatch {
+    /// Counter equals the value
+    Eq(u64),
+    /// Counter differs from the value
+    Ne(u64),
+    /// Counter is less than the value
+    Lt(u64),
+    /// Counter is greater than the value
+    Gt(u64),
+}
+
+/// Options for testing an entry in an ipset (stub for non-Linux)
+#[derive(Clone, Debug, Default)]
+pub struct IpSetTestOptions {
+    /// Only match if the entry's packet counter satisfies the comparison
+    pub packets: Option<CounterMatch>,
+    /// Only match if the entry's byte counter satisfies the comparison
+    pub bytes: Option<CounterMatch>,
+}
+
+/// Key type for nftables sets (stub for non-Linux)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum NftSetType {
     /// IPv4 addresses
     #[default]
     Ipv4Addr,
     /// IPv6 addresses
     Ipv6Addr,
+    /// Ethernet (MAC) addresses
+    EtherAddr,
+    /// Packet marks
+    Mark,
+    /// Interface names
+    Ifname,
+    /// Transport protocol ports
+    InetService,
+    /// Verdicts, the values of verdict maps
+    Verdict,
+    /// Concatenation of several types, e.g. `ipv4_addr . inet_service`
+    Concat(Vec<NftSetType>),
+}
+
+impl fmt::Display for NftSetType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NftSetType::Ipv4Addr => f.write_str("ipv4_addr"),
+            NftSetType::Ipv6Addr => f.write_str("ipv6_addr"),
+            NftSetType::EtherAddr => f.write_str("ether_addr"),
+            NftSetType::Mark => f.write_str("mark"),
+            NftSetType::Ifname => f.write_str("ifname"),
+            NftSetType::InetService => f.write_str("inet_service"),
+            NftSetType::Verdict => f.write_str("verdict"),
+            NftSetType::Concat(fields) => {
+                for (i, field) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" . ")?;
+                    }
+                    write!(f, "{field}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses the type names of nft, concatenations joined with ` . `.
+impl FromStr for NftSetType {
+    type Err = RipsetError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.contains(" . ") {
+            return s
+                .split(" . ")
+                .map(str::parse)
+                .collect::<Result<_>>()
+                .map(NftSetType::Concat);
+        }
+        match s.trim() {
+            "ipv4_addr" => Ok(NftSetType::Ipv4Addr),
+            "ipv6_addr" => Ok(NftSetType::Ipv6Addr),
+            "ether_addr" => Ok(NftSetType::EtherAddr),
+            "mark" => Ok(NftSetType::Mark),
+            "ifname" => Ok(NftSetType::Ifname),
+            "inet_service" => Ok(NftSetType::InetService),
+            "verdict" => Ok(NftSetType::Verdict),
+            _ => Err(RipsetError::InvalidOption(format!("unknown set type: {s}"))),
+        }
+    }
 }
 
 /// Options for creating an nftables set (stub for non-Linux)
 #[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct NftSetCreateOptions {
     pub set_type: NftSetType,
     pub timeout: Option<u32>,
     pub flags: Option<u32>,
+    pub interval: bool,
+    pub auto_merge: bool,
+    pub data_type: Option<NftSetType>,
+    pub policy: Option<NftSetPolicy>,
+    pub size: Option<u32>,
+    pub gc_interval: Option<u32>,
+    pub dynamic: bool,
+    pub constant: bool,
+    pub comment: Option<String>,
+    pub counters: bool,
+    pub extra_attrs: Vec<(u16, Vec<u8>)>,
+}
+
+/// Run `op` with a deadline on its netlink operations (stub - runs `op`)
+pub fn with_deadline<T>(_deadline: Instant, op: impl FnOnce() -> T) -> T {
+    op()
+}
+
+/// Run `op` with a budget for its netlink operations (stub - runs `op`)
+pub fn with_timeout<T>(_timeout: Duration, op: impl FnOnce() -> T) -> T {
+    op()
+}
+
+/// Run `op` in a network namespace (stub - returns UnsupportedPlatform error)
+pub fn with_netns<T>(_netns: impl AsRef<Path>, _op: impl FnOnce() -> Result<T>) -> Result<T> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Run `op` in a network namespace (stub - runs `op`)
+#[cfg(unix)]
+pub fn with_netns_fd<T>(_netns: BorrowedFd<'_>, op: impl FnOnce() -> T) -> T {
+    op()
 }
 
 // ipset stub functions
 
 /// Create an ipset (stub - returns UnsupportedPlatform error)
 pub fn ipset_create(_setname: &str, _options: &IpSetCreateOptions) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Ask which optional features ipsets support (stub - returns UnsupportedPlatform error)
+pub fn ipset_capabilities() -> Result<BackendCapabilities> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Create an ipset unless an identical one exists (stub - returns UnsupportedPlatform error)
+pub fn ipset_create_exist(_setname: &str, _options: &IpSetCreateOptions) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Make sure an ipset exists with the given options (stub - returns UnsupportedPlatform error)
+pub fn ipset_ensure_set(_setname: &str, _options: &IpSetCreateOptions) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Destroy an ipset (stub - returns UnsupportedPlatform error)
 pub fn ipset_destroy(_setname: &str) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Destroy all ipsets (stub - returns UnsupportedPlatform error)
+pub fn ipset_destroy_all() -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Flush an ipset (stub - returns UnsupportedPlatform error)
 pub fn ipset_flush(_setname: &str) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Flush all ipsets (stub - returns UnsupportedPlatform error)
+pub fn ipset_flush_all() -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Swap two ipsets (stub - returns UnsupportedPlatform error)
+pub fn ipset_swap(_setname1: &str, _setname2: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Replace the contents of an ipset atomically (stub - returns UnsupportedPlatform error)
+pub fn ipset_replace<I, E>(_setname: &str, _entries: I, _options: &IpSetCreateOptions) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Add an IP to an ipset (stub - returns UnsupportedPlatform error)
-pub fn ipset_add<E: Into<IpEntry>>(_setname: &str, _entry: E) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn ipset_add<E: Into<Entry>>(_setname: &str, _entry: E) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Add or refresh an entry in an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_add_exist<E: Into<Entry>>(_setname: &str, _entry: E) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Make sure an entry is in an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_ensure_entry<E: Into<Entry>>(_setname: &str, _entry: E) -> Result<bool> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Add many entries to an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_add_many<I, E>(_setname: &str, _entries: I) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Delete many entries from an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_del_many<I, E>(_setname: &str, _entries: I) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Delete an IP from an ipset (stub - returns UnsupportedPlatform error)
-pub fn ipset_del<E: Into<IpEntry>>(_setname: &str, _entry: E) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn ipset_del<E: Into<Entry>>(_setname: &str, _entry: E) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Test if an IP exists in an ipset (stub - returns UnsupportedPlatform error)
-pub fn ipset_test<E: Into<IpEntry>>(_setname: &str, _entry: E) -> Result<bool> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn ipset_test<E: Into<Entry>>(_setname: &str, _entry: E) -> Result<bool> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// A netlink socket kept open for ipset operations (stub)
+pub struct IpsetSession {
+    _private: (),
+}
+
+impl IpsetSession {
+    /// Open a new session (stub - returns UnsupportedPlatform error)
+    pub fn new() -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Open a session in a network namespace (stub - returns UnsupportedPlatform error)
+    pub fn in_netns(_netns: impl AsRef<Path>) -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Open a session in a network namespace (stub - returns UnsupportedPlatform error)
+    #[cfg(unix)]
+    pub fn in_netns_fd(_netns: BorrowedFd<'_>) -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Add an entry to an ipset (stub - returns UnsupportedPlatform error)
+    pub fn add<E: Into<Entry>>(&self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Add or refresh an entry in an ipset (stub - returns UnsupportedPlatform error)
+    pub fn add_exist<E: Into<Entry>>(&self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Add many entries to an ipset (stub - returns UnsupportedPlatform error)
+    pub fn add_many<I, E>(&self, _setname: &str, _entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Delete many entries from an ipset (stub - returns UnsupportedPlatform error)
+    pub fn del_many<I, E>(&self, _setname: &str, _entries: I) -> Result<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Delete an entry from an ipset (stub - returns UnsupportedPlatform error)
+    pub fn del<E: Into<Entry>>(&self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Test if an entry exists in an ipset (stub - returns UnsupportedPlatform error)
+    pub fn test<E: Into<Entry>>(&self, _setname: &str, _entry: E) -> Result<bool> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Test many entries of an ipset (stub - returns UnsupportedPlatform error)
+    pub fn test_many<I, E>(&self, _setname: &str, _entries: I) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Start a pipeline of requests (stub)
+    pub fn pipeline(&self) -> IpsetPipeline<'_> {
+        IpsetPipeline { _session: self }
+    }
+}
+
+/// Requests queued on an ipset session (stub)
+pub struct IpsetPipeline<'a> {
+    _session: &'a IpsetSession,
+}
+
+impl IpsetPipeline<'_> {
+    /// Number of queued requests (stub - always 0)
+    pub fn len(&self) -> usize {
+        0
+    }
+
+    /// Whether nothing has been queued (stub - always true)
+    pub fn is_empty(&self) -> bool {
+        true
+    }
+
+    /// Queue the addition of an entry (stub - returns UnsupportedPlatform error)
+    pub fn add<E: Into<Entry>>(&mut self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the addition or refresh of an entry (stub - returns UnsupportedPlatform error)
+    pub fn add_exist<E: Into<Entry>>(&mut self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the deletion of an entry (stub - returns UnsupportedPlatform error)
+    pub fn del<E: Into<Entry>>(&mut self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the test of an entry (stub - returns UnsupportedPlatform error)
+    pub fn test<E: Into<Entry>>(&mut self, _setname: &str, _entry: E) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Send the queued requests (stub - returns UnsupportedPlatform error)
+    pub fn finish(self) -> Result<Vec<Result<()>>> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+}
+
+/// Test many entries of an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_test_many<I, E>(_setname: &str, _entries: I) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Test an entry in an ipset with match options (stub - returns UnsupportedPlatform error)
+pub fn ipset_test_with_options<E: Into<Entry>>(
+    _setname: &str,
+    _entry: E,
+    _options: &IpSetTestOptions,
+) -> Result<bool> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Test an IP in an ipset and return the matched entry (stub - returns UnsupportedPlatform error)
+pub fn ipset_test_match(_setname: &str, _addr: IpAddr) -> Result<Option<IpEntry>> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// List all IPs in an ipset (stub - returns UnsupportedPlatform error)
 pub fn ipset_list(_setname: &str) -> Result<Vec<IpAddr>> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// List all entries in an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_list_entries(_setname: &str) -> Result<Vec<Entry>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Iterate over the entries of an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_list_iter(_setname: &str) -> Result<IpSetListIter> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Iterator over the entries of an ipset (stub)
+pub struct IpSetListIter {
+    _private: (),
+}
+
+impl Iterator for IpSetListIter {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        None
+    }
+}
+
+/// Query the header of an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_info(_setname: &str) -> Result<IpSetInfo> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Query the ipset protocol version of the kernel (stub - returns UnsupportedPlatform error)
+pub fn ipset_protocol() -> Result<IpSetProtocol> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Zero the counters of every entry of an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_reset_counters(_setname: &str) -> Result<Vec<Entry>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Zero the counters of one entry of an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_reset_entry_counters<E: Into<Entry>>(_setname: &str, _entry: E) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Count the entries of an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_count(_setname: &str) -> Result<u32> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Count the references to an ipset (stub - returns UnsupportedPlatform error)
+pub fn ipset_references(_setname: &str) -> Result<u32> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// List the headers of all ipsets (stub - returns UnsupportedPlatform error)
+pub fn ipset_list_sets() -> Result<Vec<IpSetInfo>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Check whether an ipset exists (stub - returns UnsupportedPlatform error)
+pub fn ipset_exists(_setname: &str) -> Result<bool> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Range of revisions of a set type supported by the kernel (stub for non-Linux)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpSetTypeRevisions {
+    pub set_type: IpSetType,
+    /// Oldest supported revision
+    pub min_revision: u8,
+    /// Newest supported revision
+    pub max_revision: u8,
+}
+
+/// Query supported revisions of a set type (stub - returns UnsupportedPlatform error)
+pub fn ipset_type_revisions(_set_type: IpSetType) -> Result<Option<IpSetTypeRevisions>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// List the set types supported by the kernel (stub - returns UnsupportedPlatform error)
+pub fn ipset_supported_types() -> Result<Vec<IpSetTypeRevisions>> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 // nftset stub functions
 
+/// A batch of nftables changes applied atomically (stub for non-Linux)
+#[derive(Default)]
+pub struct NftTransaction {
+    len: usize,
+}
+
+impl NftTransaction {
+    /// Start an empty transaction (stub)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of queued messages (stub)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing has been queued (stub)
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Queue the creation of a table (stub - returns UnsupportedPlatform error)
+    pub fn create_table(&mut self, _family: NftFamily, _table: &str) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the deletion of a table (stub - returns UnsupportedPlatform error)
+    pub fn delete_table(&mut self, _family: NftFamily, _table: &str) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the flush of a table (stub - returns UnsupportedPlatform error)
+    pub fn flush_table(&mut self, _family: NftFamily, _table: &str) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the creation of a set (stub - returns UnsupportedPlatform error)
+    pub fn create_set(
+        &mut self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _options: &NftSetCreateOptions,
+    ) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the deletion of a set (stub - returns UnsupportedPlatform error)
+    pub fn delete_set(&mut self, _family: NftFamily, _table: &str, _setname: &str) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the flush of a set (stub - returns UnsupportedPlatform error)
+    pub fn flush_set(&mut self, _family: NftFamily, _table: &str, _setname: &str) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the addition of an element (stub - returns UnsupportedPlatform error)
+    pub fn add<E: Into<Entry>>(
+        &mut self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entry: E,
+    ) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the addition of a map element (stub - returns UnsupportedPlatform error)
+    pub fn map_add<K, V>(
+        &mut self,
+        _family: NftFamily,
+        _table: &str,
+        _mapname: &str,
+        _key: K,
+        _value: V,
+    ) -> Result<()>
+    where
+        K: Into<Entry>,
+        V: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Queue the deletion of an element (stub - returns UnsupportedPlatform error)
+    pub fn del<E: Into<Entry>>(
+        &mut self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entry: E,
+    ) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Send the queued changes (stub - returns UnsupportedPlatform error)
+    pub fn commit(self) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+}
+
+/// Ask which optional features nftables sets support (stub - returns UnsupportedPlatform error)
+pub fn nftset_capabilities(_family: NftFamily) -> Result<BackendCapabilities> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
 /// Create an nftables table (stub - returns UnsupportedPlatform error)
-pub fn nftset_create_table(_family: &str, _table: &str) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn nftset_create_table(_family: NftFamily, _table: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Make sure an nftables table exists (stub - returns UnsupportedPlatform error)
+pub fn nftset_ensure_table(_family: NftFamily, _table: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Delete an nftables table (stub - returns UnsupportedPlatform error)
-pub fn nftset_delete_table(_family: &str, _table: &str) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn nftset_delete_table(_family: NftFamily, _table: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// An nftables table owned by this process (stub)
+pub struct NftOwnedTable {
+    family: NftFamily,
+    name: String,
+}
+
+impl NftOwnedTable {
+    /// The address family of the table (stub)
+    pub fn family(&self) -> NftFamily {
+        self.family
+    }
+
+    /// The table name (stub)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The netlink port ID that owns the table (stub - returns UnsupportedPlatform error)
+    pub fn owner(&self) -> Result<u32> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Send the changes queued in a transaction (stub - returns UnsupportedPlatform error)
+    pub fn commit(&self, _tx: NftTransaction) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+}
+
+/// Create an nftables table owned by this process (stub - returns UnsupportedPlatform error)
+pub fn nftset_create_table_owned(_family: NftFamily, _table: &str) -> Result<NftOwnedTable> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Get the netlink port ID owning a table (stub - returns UnsupportedPlatform error)
+pub fn nftset_table_owner(_family: NftFamily, _table: &str) -> Result<Option<u32>> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Create an nftables set (stub - returns UnsupportedPlatform error)
 pub fn nftset_create_set(
-    _family: &str,
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _options: &NftSetCreateOptions,
+) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Make sure an nftables set exists with the given options (stub - returns UnsupportedPlatform error)
+pub fn nftset_ensure_set(
+    _family: NftFamily,
     _table: &str,
     _setname: &str,
     _options: &NftSetCreateOptions,
 ) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Delete an nftables set (stub - returns UnsupportedPlatform error)
-pub fn nftset_delete_set(_family: &str, _table: &str, _setname: &str) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn nftset_delete_set(_family: NftFamily, _table: &str, _setname: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Lookup strategy of an nftables set (stub for non-Linux)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum NftSetPolicy {
+    /// Prefer lookup speed
+    Performance,
+    /// Prefer a small memory footprint
+    Memory,
+}
+
+/// Properties of an nftables set (stub for non-Linux)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NftSetInfo {
+    pub name: String,
+    /// Key type, if it is one of the types known to this library
+    pub set_type: Option<NftSetType>,
+    /// Raw nftables key type identifier
+    pub key_type: u32,
+    /// Key length in bytes
+    pub key_len: u32,
+    /// The set is a map (`map` flag)
+    pub map: bool,
+    /// Value type of a map, if it is one of the types known to this library
+    pub data_type: Option<NftSetType>,
+    /// Raw set flags (`NFT_SET_*`)
+    pub flags: u32,
+    /// The set stores ranges (`interval` flag)
+    pub interval: bool,
+    /// Adjacent and overlapping ranges are merged on insertion
+    pub auto_merge: bool,
+    /// Elements can expire (`timeout` flag)
+    pub with_timeout: bool,
+    /// The set is updated from the packet path (`dynamic` flag)
+    pub dynamic: bool,
+    /// The set cannot be changed after creation (`constant` flag)
+    pub constant: bool,
+    /// Default element timeout in seconds
+    pub timeout: Option<u32>,
+    /// Garbage collection interval in seconds
+    pub gc_interval: Option<u32>,
+    /// Lookup policy, if one was requested
+    pub policy: Option<NftSetPolicy>,
+    /// Maximum number of elements
+    pub size: Option<u32>,
+    /// Number of elements in the set (ranges count once)
+    pub entries: u32,
+    /// Set comment
+    pub comment: Option<String>,
+    /// Elements keep packet and byte counters
+    pub counters: bool,
+}
+
+impl NftSetInfo {
+    /// Options that create a set with the same definition
+    pub fn create_options(&self) -> Result<NftSetCreateOptions> {
+        let unsupported =
+            || RipsetError::InvalidOption(format!("unsupported type of set {}", self.name));
+        let set_type = self.set_type.clone().ok_or_else(unsupported)?;
+        let data_type = match (self.map, &self.data_type) {
+            (true, None) => return Err(unsupported()),
+            (_, data_type) => data_type.clone(),
+        };
+        Ok(NftSetCreateOptions {
+            set_type,
+            timeout: self.timeout,
+            flags: Some(self.flags),
+            interval: self.interval,
+            auto_merge: self.auto_merge,
+            data_type,
+            policy: self.policy,
+            size: self.size,
+            gc_interval: self.gc_interval,
+            dynamic: self.dynamic,
+            constant: self.constant,
+            comment: self.comment.clone(),
+            counters: self.counters,
+            extra_attrs: Vec::new(),
+        })
+    }
+}
+
+/// Query the properties of an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_info(_family: NftFamily, _table: &str, _setname: &str) -> Result<NftSetInfo> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Flush an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_flush(_family: NftFamily, _table: &str, _setname: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Flush an nftables table (stub - returns UnsupportedPlatform error)
+pub fn nftset_flush_table(_family: NftFamily, _table: &str) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Exchange the elements of two nftables sets (stub - returns UnsupportedPlatform error)
+pub fn nftset_swap(
+    _family: NftFamily,
+    _table: &str,
+    _setname1: &str,
+    _setname2: &str,
+) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Rename an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_rename(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _newname: &str,
+) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Check whether an nftables set exists (stub - returns UnsupportedPlatform error)
+pub fn nftset_exists(_family: NftFamily, _table: &str, _setname: &str) -> Result<bool> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Add an IP to an nftables set (stub - returns UnsupportedPlatform error)
-pub fn nftset_add<E: Into<IpEntry>>(
-    _family: &str,
+pub fn nftset_add<E: Into<Entry>>(
+    _family: NftFamily,
     _table: &str,
     _setname: &str,
     _entry: E,
 ) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Make sure an entry is in an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_ensure_entry<E: Into<Entry>>(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _entry: E,
+) -> Result<bool> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Delete an IP from an nftables set (stub - returns UnsupportedPlatform error)
-pub fn nftset_del<E: Into<IpEntry>>(
-    _family: &str,
+pub fn nftset_del<E: Into<Entry>>(
+    _family: NftFamily,
     _table: &str,
     _setname: &str,
     _entry: E,
 ) -> Result<()> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Add many entries to an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_add_many<I, E>(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _entries: I,
+) -> Result<()>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Delete many entries from an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_del_many<I, E>(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _entries: I,
+) -> Result<Vec<(usize, RipsetError)>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Add an element with a value to an nftables map (stub - returns UnsupportedPlatform error)
+pub fn nftset_map_add<K, V>(
+    _family: NftFamily,
+    _table: &str,
+    _mapname: &str,
+    _key: K,
+    _value: V,
+) -> Result<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// What to do when a map key already exists (stub for non-Linux)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NftMapConflict {
+    /// Fail with EntryExists
+    #[default]
+    Fail,
+    /// Replace the value of the existing element
+    Replace,
+    /// Keep the existing element and its value
+    Ignore,
+}
+
+/// Add an element with a value to an nftables map, choosing what happens if
+/// the key exists (stub - returns UnsupportedPlatform error)
+pub fn nftset_map_add_with<K, V>(
+    _family: NftFamily,
+    _table: &str,
+    _mapname: &str,
+    _key: K,
+    _value: V,
+    _on_conflict: NftMapConflict,
+) -> Result<()>
+where
+    K: Into<Entry>,
+    V: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// List the elements of an nftables map (stub - returns UnsupportedPlatform error)
+pub fn nftset_map_list(
+    _family: NftFamily,
+    _table: &str,
+    _mapname: &str,
+) -> Result<Vec<(Entry, Entry)>> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// Test if an IP exists in an nftables set (stub - returns UnsupportedPlatform error)
-pub fn nftset_test<E: Into<IpEntry>>(
-    _family: &str,
+pub fn nftset_test<E: Into<Entry>>(
+    _family: NftFamily,
     _table: &str,
     _setname: &str,
     _entry: E,
 ) -> Result<bool> {
-    Err(IpSetError::UnsupportedPlatform)
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Test many entries of an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_test_many<I, E>(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _entries: I,
+) -> Result<Vec<bool>>
+where
+    I: IntoIterator<Item = E>,
+    E: Into<Entry>,
+{
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// A netlink socket kept open for nftables operations (stub)
+pub struct NftSession {
+    _private: (),
+}
+
+impl NftSession {
+    /// Open a new session (stub - returns UnsupportedPlatform error)
+    pub fn new() -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Open a session in a network namespace (stub - returns UnsupportedPlatform error)
+    pub fn in_netns(_netns: impl AsRef<Path>) -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Open a session in a network namespace (stub - returns UnsupportedPlatform error)
+    #[cfg(unix)]
+    pub fn in_netns_fd(_netns: BorrowedFd<'_>) -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Add an entry to an nftables set (stub - returns UnsupportedPlatform error)
+    pub fn add<E: Into<Entry>>(
+        &self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entry: E,
+    ) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Add many entries to an nftables set (stub - returns UnsupportedPlatform error)
+    pub fn add_many<I, E>(
+        &self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entries: I,
+    ) -> Result<()>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Delete many entries from an nftables set (stub - returns UnsupportedPlatform error)
+    pub fn del_many<I, E>(
+        &self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entries: I,
+    ) -> Result<Vec<(usize, RipsetError)>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Delete an entry from an nftables set (stub - returns UnsupportedPlatform error)
+    pub fn del<E: Into<Entry>>(
+        &self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entry: E,
+    ) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Test if an entry exists in an nftables set (stub - returns UnsupportedPlatform error)
+    pub fn test<E: Into<Entry>>(
+        &self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entry: E,
+    ) -> Result<bool> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Test many entries of an nftables set (stub - returns UnsupportedPlatform error)
+    pub fn test_many<I, E>(
+        &self,
+        _family: NftFamily,
+        _table: &str,
+        _setname: &str,
+        _entries: I,
+    ) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = E>,
+        E: Into<Entry>,
+    {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Send the changes queued in a transaction (stub - returns UnsupportedPlatform error)
+    pub fn commit(&self, _tx: NftTransaction) -> Result<()> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
 }
 
 /// List all IPs in an nftables set (stub - returns UnsupportedPlatform error)
-pub fn nftset_list(_family: &str, _table: &str, _setname: &str) -> Result<Vec<IpAddr>> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn nftset_list(_family: NftFamily, _table: &str, _setname: &str) -> Result<Vec<IpAddr>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// List entries in an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_list_entries(_family: NftFamily, _table: &str, _setname: &str) -> Result<Vec<Entry>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Iterate over the entries of an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_list_iter(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+) -> Result<NftSetListIter> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Iterator over the entries of an nftables set (stub)
+pub struct NftSetListIter {
+    _private: (),
+}
+
+impl Iterator for NftSetListIter {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        None
+    }
+}
+
+/// An element of an nftables set (stub for non-Linux)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NftSetElement {
+    pub entry: Entry,
+    pub timeout: Option<u32>,
+    pub expiration: Option<u32>,
+    pub packets: Option<u64>,
+    pub bytes: Option<u64>,
+}
+
+/// List elements in an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_list_elements(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+) -> Result<Vec<NftSetElement>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Zero the counters of every element of an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_reset_counters(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+) -> Result<Vec<NftSetElement>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Zero the counters of one element of an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_reset_entry_counters<E: Into<Entry>>(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+    _entry: E,
+) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
 }
 
 /// List all tables in an nftables family (stub - returns UnsupportedPlatform error)
-pub fn nftset_list_tables(_family: &str) -> Result<Vec<String>> {
-    Err(IpSetError::UnsupportedPlatform)
+pub fn nftset_list_tables(_family: NftFamily) -> Result<Vec<String>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Make sure a chain drops traffic from a set (stub - returns UnsupportedPlatform error)
+pub fn nft_ensure_drop_rule(
+    _family: NftFamily,
+    _table: &str,
+    _chain: &str,
+    _setname: &str,
+) -> Result<()> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// List tables across all nftables families (stub - returns UnsupportedPlatform error)
+pub fn nftset_list_tables_all() -> Result<Vec<(NftFamily, String)>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// A rule using an nftables set (stub for non-Linux)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NftSetReference {
+    pub chain: String,
+    pub handle: u64,
+    pub comment: Option<String>,
+}
+
+impl fmt::Display for NftSetReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rule {} in chain {}", self.handle, self.chain)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " ({comment})")?;
+        }
+        Ok(())
+    }
+}
+
+/// List the rules using an nftables set (stub - returns UnsupportedPlatform error)
+pub fn nftset_references(
+    _family: NftFamily,
+    _table: &str,
+    _setname: &str,
+) -> Result<Vec<NftSetReference>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// Name and key type of an nftables set (stub for non-Linux)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NftSetSummary {
+    pub name: String,
+    /// Key type, if it is one of the types known to this library
+    pub set_type: Option<NftSetType>,
+    /// Raw nftables key type identifier (concatenations combine several types)
+    pub key_type: u32,
+    /// Key length in bytes
+    pub key_len: u32,
+    /// The set is a map
+    pub map: bool,
+    /// Value type of a map, if it is one of the types known to this library
+    pub data_type: Option<NftSetType>,
+}
+
+/// List all sets in an nftables table (stub - returns UnsupportedPlatform error)
+pub fn nftset_list_sets(_family: NftFamily, _table: &str) -> Result<Vec<NftSetSummary>> {
+    Err(RipsetError::UnsupportedPlatform)
+}
+
+/// A change to nftables reported by the kernel (stub for non-Linux)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NftEvent {
+    TableCreated {
+        family: NftFamily,
+        table: String,
+    },
+    TableDeleted {
+        family: NftFamily,
+        table: String,
+    },
+    SetCreated {
+        family: NftFamily,
+        table: String,
+        set: String,
+    },
+    SetDeleted {
+        family: NftFamily,
+        table: String,
+        set: String,
+    },
+    ElementAdded {
+        family: NftFamily,
+        table: String,
+        set: String,
+        entry: Entry,
+    },
+    ElementDeleted {
+        family: NftFamily,
+        table: String,
+        set: String,
+        entry: Entry,
+    },
+    Committed {
+        generation: u32,
+    },
+}
+
+/// A subscription to the changes made to nftables (stub)
+pub struct NftMonitor {
+    _private: (),
+}
+
+impl NftMonitor {
+    /// Subscribe to the nftables changes (stub - returns UnsupportedPlatform error)
+    pub fn new() -> Result<Self> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    /// Wait for the next change (stub - returns UnsupportedPlatform error)
+    pub fn next_event(&mut self) -> Result<NftEvent> {
+        Err(RipsetError::UnsupportedPlatform)
+    }
+
+    #[cfg(all(unix, feature = "tokio"))]
+    pub(crate) fn set_nonblocking(&self) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(all(unix, feature = "tokio"))]
+    pub(crate) fn pop_event(&mut self) -> Option<NftEvent> {
+        None
+    }
+
+    #[cfg(all(unix, feature = "tokio"))]
+    pub(crate) fn recv(&mut self) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+
+    #[cfg(all(unix, feature = "tokio"))]
+    pub(crate) fn recv_failed(&mut self, error: std::io::Error) -> RipsetError {
+        error.into()
+    }
+
+    #[cfg(all(unix, feature = "tokio"))]
+    pub(crate) fn handle(&mut self, _len: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Iterator for NftMonitor {
+    type Item = Result<NftEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for NftMonitor {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        -1
+    }
 }
@@ -5,12 +5,30 @@
 //! Run with: sudo ./target/debug/deps/integration_tests-*
 
 use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
 use ripset::{
-    IpEntry, IpSetCreateOptions, IpSetFamily, NftSetCreateOptions, NftSetType, ipset_add,
-    ipset_create, ipset_del, ipset_destroy, ipset_list, ipset_test, nftset_add, nftset_create_set,
-    nftset_create_table, nftset_del, nftset_delete_table, nftset_list, nftset_list_tables,
-    nftset_test,
+    BackendKind, CounterMatch, DryRunBackend, DualStackSet, Entry, IpEntry, IpSetCreateOptions,
+    IpSetFamily, IpSetRange, IpSetTestOptions, IpSetType, IpsetBackend, IpsetSession, MacAddr,
+    NftBackend, NftEvent, NftFamily, NftMapConflict, NftMonitor, NftOwnedTable, NftSession,
+    NftSetCreateOptions, NftSetPolicy, NftSetType, NftTransaction, NftVerdict, RipsetError,
+    SessionPool, SetBackend, SetName, SetOperation, SetRef, SetStats, aggregate, copy_set,
+    detect_backend, diff_set, find_ip, ipset_add, ipset_add_exist, ipset_add_many, ipset_count,
+    ipset_create, ipset_create_exist, ipset_del, ipset_del_many, ipset_destroy, ipset_destroy_all,
+    ipset_ensure_entry, ipset_ensure_set, ipset_exists, ipset_flush_all, ipset_info, ipset_list,
+    ipset_list_entries, ipset_list_iter, ipset_list_sets, ipset_protocol, ipset_references,
+    ipset_replace, ipset_reset_counters, ipset_reset_entry_counters, ipset_restore, ipset_save,
+    ipset_save_all, ipset_supported_types, ipset_swap, ipset_test, ipset_test_many,
+    ipset_test_match, ipset_test_with_options, ipset_type_revisions, migrate_ipset_to_nft,
+    nft_ensure_drop_rule, nftset_add, nftset_add_many, nftset_create_set, nftset_create_table,
+    nftset_create_table_owned, nftset_del, nftset_del_many, nftset_delete_set, nftset_delete_table,
+    nftset_ensure_entry, nftset_ensure_set, nftset_ensure_table, nftset_exists, nftset_flush,
+    nftset_flush_table, nftset_info, nftset_list, nftset_list_elements, nftset_list_entries,
+    nftset_list_iter, nftset_list_sets, nftset_list_tables, nftset_list_tables_all, nftset_map_add,
+    nftset_map_add_with, nftset_map_list, nftset_references, nftset_rename, nftset_reset_counters,
+    nftset_reset_entry_counters, nftset_swap, nftset_table_owner, nftset_test, nftset_test_many,
+    restore, set_difference, set_intersection, set_stats, set_union, snapshot, sync_set,
+    with_deadline, with_netns, with_timeout,
 };
 
 // =====================
@@ -164,6 +182,7 @@ mod ipset_tests {
 
         let result = ipset_add("nonexistent_set_12345", addr);
         assert!(result.is_err(), "Should fail for nonexistent set");
+        assert!(!ipset_exists("nonexistent_set_12345").expect("Failed to check ipset"));
     }
 
     #[test]
@@ -174,6 +193,7 @@ mod ipset_tests {
         let _ = ipset_destroy(SET_NAME);
         let opts = IpSetCreateOptions::default();
         ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+        assert!(ipset_exists(SET_NAME).expect("Failed to check ipset"));
 
         // Initially empty
         let ips = ipset_list(SET_NAME).expect("Failed to list ipset");
@@ -194,280 +214,5399 @@ mod ipset_tests {
         assert!(ips.contains(&addr1), "Set should contain addr1");
         assert!(ips.contains(&addr2), "Set should contain addr2");
         assert!(ips.contains(&addr3), "Set should contain addr3");
+        assert_eq!(ipset_count(SET_NAME).expect("Failed to count ipset"), 3);
 
         // Delete one and verify
         ipset_del(SET_NAME, addr2).expect("Failed to delete IP");
         let ips = ipset_list(SET_NAME).expect("Failed to list ipset");
         assert_eq!(ips.len(), 2, "Set should contain 2 IPs after delete");
         assert!(!ips.contains(&addr2), "Set should not contain addr2");
+        assert_eq!(ipset_count(SET_NAME).expect("Failed to count ipset"), 2);
 
         // Cleanup
         let _ = ipset_destroy(SET_NAME);
     }
-}
 
-// =====================
-// nftset tests
-// =====================
+    #[test]
+    fn test_ipset_bitmap_ip() {
+        const SET_NAME: &str = "lipsets_test_bitmap";
 
-mod nftset_tests {
-    use super::*;
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIp,
+            range: Some(IpSetRange::Cidr("192.168.0.0".parse().unwrap(), 16)),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create bitmap:ip set");
 
-    // Each test uses unique table/set names to avoid race conditions when running in parallel
+        let addr: IpAddr = "192.168.10.1".parse().unwrap();
+        ipset_add(SET_NAME, addr).expect("Failed to add IP");
+        assert!(ipset_test(SET_NAME, addr).expect("Failed to test IP"));
+
+        // Addresses outside the range are rejected by the kernel
+        let outside: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(ipset_add(SET_NAME, outside).is_err());
+
+        let ips = ipset_list(SET_NAME).expect("Failed to list ipset");
+        assert_eq!(ips, vec![addr]);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+
+        // A from-to range works as well
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIp,
+            range: Some(IpSetRange::FromTo(
+                "10.1.0.10".parse().unwrap(),
+                "10.1.0.20".parse().unwrap(),
+            )),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create bitmap:ip set with from-to");
+        let addr: IpAddr = "10.1.0.15".parse().unwrap();
+        ipset_add(SET_NAME, addr).expect("Failed to add IP");
+        assert!(ipset_test(SET_NAME, addr).expect("Failed to test IP"));
+        let _ = ipset_destroy(SET_NAME);
+
+        // Missing range is rejected before talking to the kernel
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIp,
+            ..Default::default()
+        };
+        assert!(matches!(
+            ipset_create(SET_NAME, &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
+    }
 
     #[test]
-    fn test_nftset_add_test_del_ipv4() {
-        const TABLE_NAME: &str = "lnftsets_test_v4";
-        const SET_NAME: &str = "test_set";
+    fn test_ipset_bitmap_ip_mac() {
+        const SET_NAME: &str = "lipsets_test_ipmac";
 
         // Setup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
-        nftset_create_table("inet", TABLE_NAME).expect("Failed to create table");
-        let opts = NftSetCreateOptions::default();
-        nftset_create_set("inet", TABLE_NAME, SET_NAME, &opts).expect("Failed to create set");
-
-        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIpMac,
+            range: Some(IpSetRange::Cidr("192.168.1.0".parse().unwrap(), 24)),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create bitmap:ip,mac set");
 
-        // Test that IP is not in set
-        let exists = nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IP");
-        assert!(!exists, "IP should not exist initially");
+        let addr: IpAddr = "192.168.1.10".parse().unwrap();
+        let mac: MacAddr = "00:11:22:aa:bb:cc".parse().unwrap();
+        assert_eq!(mac.to_string(), "00:11:22:aa:bb:cc");
 
-        // Add IP to set
-        nftset_add("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to add IP");
+        ipset_add(SET_NAME, IpEntry::with_mac(addr, mac)).expect("Failed to add IP,MAC");
 
-        // Test that IP is now in set
-        let exists =
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IP after add");
-        assert!(exists, "IP should exist after add");
+        // The binding matches only with the right MAC
+        assert!(ipset_test(SET_NAME, IpEntry::with_mac(addr, mac)).expect("Failed to test"));
+        let other: MacAddr = "00:11:22:aa:bb:cd".parse().unwrap();
+        assert!(!ipset_test(SET_NAME, IpEntry::with_mac(addr, other)).expect("Failed to test"));
 
-        // Delete IP from set
-        nftset_del("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to delete IP");
+        ipset_del(SET_NAME, addr).expect("Failed to delete IP");
+        assert!(!ipset_test(SET_NAME, IpEntry::with_mac(addr, mac)).expect("Failed to test"));
 
-        // Test that IP is no longer in set
-        let exists =
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IP after del");
-        assert!(!exists, "IP should not exist after delete");
+        assert!("00:11:22:aa:bb".parse::<MacAddr>().is_err());
+        assert!("00:11:22:aa:bb:cc:dd".parse::<MacAddr>().is_err());
 
         // Cleanup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
+        let _ = ipset_destroy(SET_NAME);
     }
 
     #[test]
-    fn test_nftset_add_test_del_ipv6() {
-        const TABLE_NAME: &str = "lnftsets_test_v6";
-        const SET_NAME: &str = "test_set";
+    fn test_ipset_list_set() {
+        const LIST_NAME: &str = "lipsets_test_lset";
+        const MEMBER1: &str = "lipsets_test_lset_m1";
+        const MEMBER2: &str = "lipsets_test_lset_m2";
 
         // Setup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
-        nftset_create_table("inet", TABLE_NAME).expect("Failed to create table");
-        let opts = NftSetCreateOptions {
-            set_type: NftSetType::Ipv6Addr,
+        let _ = ipset_destroy(LIST_NAME);
+        let _ = ipset_destroy(MEMBER1);
+        let _ = ipset_destroy(MEMBER2);
+        ipset_create(MEMBER1, &IpSetCreateOptions::default()).expect("Failed to create member1");
+        ipset_create(MEMBER2, &IpSetCreateOptions::default()).expect("Failed to create member2");
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::ListSet,
             ..Default::default()
         };
-        nftset_create_set("inet", TABLE_NAME, SET_NAME, &opts).expect("Failed to create set6");
+        ipset_create(LIST_NAME, &opts).expect("Failed to create list:set");
 
-        let addr: IpAddr = "2001:db8::1".parse().unwrap();
+        // Add member sets by name
+        ipset_add(LIST_NAME, MEMBER1).expect("Failed to add member1");
+        ipset_add(LIST_NAME, MEMBER2).expect("Failed to add member2");
+        assert!(ipset_test(LIST_NAME, MEMBER1).expect("Failed to test member1"));
 
-        // Test that IP is not in set
-        let exists = nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IPv6");
-        assert!(!exists, "IPv6 should not exist initially");
+        // Adding a set that does not exist fails
+        assert!(ipset_add(LIST_NAME, "lipsets_test_lset_none").is_err());
 
-        // Add IP to set
-        nftset_add("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to add IPv6");
+        let entries = ipset_list_entries(LIST_NAME).expect("Failed to list list:set");
+        assert_eq!(
+            entries,
+            vec![Entry::from(MEMBER1), Entry::from(MEMBER2)],
+            "list:set should contain both members in order"
+        );
 
-        // Test that IP is now in set
-        let exists =
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IPv6 after add");
-        assert!(exists, "IPv6 should exist after add");
+        // IP-only listing skips set names
+        assert!(ipset_list(LIST_NAME).expect("Failed to list").is_empty());
 
-        // Delete IP from set
-        nftset_del("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to delete IPv6");
+        ipset_del(LIST_NAME, MEMBER1).expect("Failed to delete member1");
+        assert!(!ipset_test(LIST_NAME, MEMBER1).expect("Failed to test member1"));
 
-        // Test that IP is no longer in set
-        let exists =
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IPv6 after del");
-        assert!(!exists, "IPv6 should not exist after delete");
+        // Cleanup
+        let _ = ipset_destroy(LIST_NAME);
+        let _ = ipset_destroy(MEMBER1);
+        let _ = ipset_destroy(MEMBER2);
+    }
+
+    #[test]
+    fn test_ipset_references() {
+        const LIST_NAME: &str = "lipsets_test_refs_lset";
+        const MEMBER: &str = "lipsets_test_refs_m";
+
+        // Setup
+        let _ = ipset_destroy(LIST_NAME);
+        let _ = ipset_destroy(MEMBER);
+        ipset_create(MEMBER, &IpSetCreateOptions::default()).expect("Failed to create member");
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::ListSet,
+            ..Default::default()
+        };
+        ipset_create(LIST_NAME, &opts).expect("Failed to create list:set");
+        assert_eq!(
+            ipset_references(MEMBER).expect("Failed to get references"),
+            0
+        );
+
+        // Membership in a list:set holds a reference
+        ipset_add(LIST_NAME, MEMBER).expect("Failed to add member");
+        assert_eq!(
+            ipset_references(MEMBER).expect("Failed to get references"),
+            1
+        );
+        assert!(matches!(
+            ipset_destroy(MEMBER),
+            Err(RipsetError::SetInUse(name, by)) if name == MEMBER && by == "1 reference"
+        ));
 
         // Cleanup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
+        let _ = ipset_destroy(LIST_NAME);
+        let _ = ipset_destroy(MEMBER);
     }
 
     #[test]
-    fn test_nftset_with_timeout() {
-        const TABLE_NAME: &str = "lnftsets_test_timeout";
-        const SET_NAME: &str = "test_set";
+    fn test_ipset_counters() {
+        const SET_NAME: &str = "lipsets_test_counters";
 
         // Setup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
-        nftset_create_table("inet", TABLE_NAME).expect("Failed to create table");
-        let opts = NftSetCreateOptions {
-            timeout: Some(300),
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            counters: true,
             ..Default::default()
         };
-        nftset_create_set("inet", TABLE_NAME, SET_NAME, &opts)
-            .expect("Failed to create set with timeout");
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with counters");
 
-        let addr: IpAddr = "10.0.0.2".parse().unwrap();
-        let entry = IpEntry::with_timeout(addr, 60);
+        let addr: IpAddr = "10.0.0.5".parse().unwrap();
+        let mut entry = IpEntry::new(addr);
+        entry.packets = Some(5);
+        entry.bytes = Some(420);
+        ipset_add(SET_NAME, entry).expect("Failed to add IP with counters");
 
-        // Add IP with timeout
-        nftset_add("inet", TABLE_NAME, SET_NAME, entry).expect("Failed to add IP with timeout");
+        let other: IpAddr = "10.0.0.6".parse().unwrap();
+        ipset_add(SET_NAME, other).expect("Failed to add IP");
 
-        // Test that IP is in set
-        let exists = nftset_test("inet", TABLE_NAME, SET_NAME, addr).expect("Failed to test IP");
-        assert!(exists, "IP should exist after add with timeout");
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert_eq!(entries.len(), 2);
+        for entry in entries {
+            let Entry::Ip(entry) = entry else {
+                panic!("Unexpected entry {entry:?}");
+            };
+            if entry.addr == addr {
+                assert_eq!(entry.packets, Some(5));
+                assert_eq!(entry.bytes, Some(420));
+            } else {
+                assert_eq!(entry.packets, Some(0));
+                assert_eq!(entry.bytes, Some(0));
+            }
+        }
+
+        // Sets without counters report none
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+        ipset_add(SET_NAME, addr).expect("Failed to add IP");
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert!(matches!(&entries[..], [Entry::Ip(e)] if e.packets.is_none()));
 
         // Cleanup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
+        let _ = ipset_destroy(SET_NAME);
     }
 
     #[test]
-    fn test_nftset_multiple_ips() {
-        const TABLE_NAME: &str = "lnftsets_test_multi";
-        const SET_NAME: &str = "test_set";
+    fn test_ipset_reset_counters() {
+        const SET_NAME: &str = "lipsets_test_reset_counters";
 
         // Setup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
-        nftset_create_table("inet", TABLE_NAME).expect("Failed to create table");
-        let opts = NftSetCreateOptions::default();
-        nftset_create_set("inet", TABLE_NAME, SET_NAME, &opts).expect("Failed to create set");
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            counters: true,
+            comment: true,
+            timeout: Some(0),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
 
-        let addrs: Vec<IpAddr> = vec![
-            "10.0.0.10".parse().unwrap(),
-            "10.0.0.11".parse().unwrap(),
-            "10.0.0.12".parse().unwrap(),
-        ];
+        let addr: IpAddr = "10.0.0.5".parse().unwrap();
+        let mut entry = IpEntry::with_timeout(addr, 600);
+        entry.packets = Some(5);
+        entry.bytes = Some(420);
+        entry.comment = Some("kept".to_string());
+        ipset_add(SET_NAME, entry).expect("Failed to add");
+        let mut other = IpEntry::new("10.0.0.6".parse().unwrap());
+        other.packets = Some(7);
+        ipset_add(SET_NAME, other.clone()).expect("Failed to add");
 
-        // Add all IPs
-        for addr in &addrs {
-            nftset_add("inet", TABLE_NAME, SET_NAME, *addr).expect("Failed to add IP");
-        }
+        // The counters before the reset are returned
+        let mut before: Vec<_> = ipset_reset_counters(SET_NAME)
+            .expect("Failed to reset counters")
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Ip(ip) => Some((ip.addr, ip.packets)),
+                _ => None,
+            })
+            .collect();
+        before.sort();
+        assert_eq!(before, [(addr, Some(5)), (other.addr, Some(7))]);
 
-        // Test all IPs exist
-        for addr in &addrs {
-            let exists =
-                nftset_test("inet", TABLE_NAME, SET_NAME, *addr).expect("Failed to test IP");
-            assert!(exists, "IP {} should exist", addr);
+        for entry in ipset_list_entries(SET_NAME).expect("Failed to list") {
+            let Entry::Ip(entry) = entry else {
+                panic!("Unexpected entry {entry:?}");
+            };
+            assert_eq!((entry.packets, entry.bytes), (Some(0), Some(0)));
+            if entry.addr == addr {
+                assert_eq!(entry.comment.as_deref(), Some("kept"));
+                assert!(entry.timeout.is_some_and(|t| t > 500));
+            }
         }
 
-        // Delete all IPs
-        for addr in &addrs {
-            nftset_del("inet", TABLE_NAME, SET_NAME, *addr).expect("Failed to delete IP");
-        }
+        // A single entry, given without its extensions
+        let mut entry = IpEntry::new(addr);
+        entry.packets = Some(3);
+        ipset_add_exist(SET_NAME, entry).expect("Failed to refresh");
+        ipset_reset_entry_counters(SET_NAME, addr).expect("Failed to reset entry counters");
+        let matched = ipset_test_match(SET_NAME, addr)
+            .expect("Failed to test")
+            .expect("Entry is missing");
+        assert_eq!(matched.packets, Some(0));
+        assert!(matches!(
+            ipset_reset_entry_counters(SET_NAME, "10.0.0.7".parse::<IpAddr>().unwrap()),
+            Err(RipsetError::EntryNotFound)
+        ));
 
-        // Test all IPs are gone
-        for addr in &addrs {
-            let exists =
-                nftset_test("inet", TABLE_NAME, SET_NAME, *addr).expect("Failed to test IP");
-            assert!(!exists, "IP {} should not exist after delete", addr);
-        }
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_comment() {
+        const SET_NAME: &str = "lipsets_test_comment";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            comment: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with comments");
+
+        let addr: IpAddr = "10.0.0.7".parse().unwrap();
+        ipset_add(SET_NAME, IpEntry::with_comment(addr, "abuse report #42"))
+            .expect("Failed to add IP with comment");
+
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert_eq!(
+            entries,
+            vec![Entry::Ip(IpEntry::with_comment(addr, "abuse report #42"))]
+        );
 
         // Cleanup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
+        let _ = ipset_destroy(SET_NAME);
     }
 
     #[test]
-    fn test_nftset_nonexistent_set() {
-        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+    fn test_ipset_skbinfo() {
+        const SET_NAME: &str = "lipsets_test_skbinfo";
 
-        let result = nftset_add("inet", "nonexistent_table", "nonexistent_set", addr);
-        assert!(result.is_err(), "Should fail for nonexistent set");
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            skbinfo: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with skbinfo");
+
+        let addr: IpAddr = "10.0.0.8".parse().unwrap();
+        let mut entry = IpEntry::new(addr);
+        entry.skbmark = Some((0x10, 0xff));
+        entry.skbprio = Some(0x0001_0002);
+        entry.skbqueue = Some(3);
+        ipset_add(SET_NAME, entry.clone()).expect("Failed to add IP with skbinfo");
+
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert_eq!(entries, vec![Entry::Ip(entry)]);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
     }
 
     #[test]
-    fn test_nftset_list() {
-        const TABLE_NAME: &str = "lnftsets_test_list";
-        const SET_NAME: &str = "test_set";
+    fn test_ipset_forceadd() {
+        const SET_NAME: &str = "lipsets_test_forceadd";
 
         // Setup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
-        nftset_create_table("inet", TABLE_NAME).expect("Failed to create table");
-        let opts = NftSetCreateOptions::default();
-        nftset_create_set("inet", TABLE_NAME, SET_NAME, &opts).expect("Failed to create set");
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            forceadd: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with forceadd");
 
-        // Initially empty
-        let ips = nftset_list("inet", TABLE_NAME, SET_NAME).expect("Failed to list nftset");
-        assert!(ips.is_empty(), "Set should be empty initially");
+        let addr: IpAddr = "10.0.0.9".parse().unwrap();
+        ipset_add(SET_NAME, addr).expect("Failed to add IP");
+        assert!(ipset_test(SET_NAME, addr).expect("Failed to test IP"));
 
-        // Add some IPs
-        let addr1: IpAddr = "10.0.0.1".parse().unwrap();
-        let addr2: IpAddr = "10.0.0.2".parse().unwrap();
-        let addr3: IpAddr = "10.0.0.3".parse().unwrap();
+        // forceadd is only meaningful for hash types
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIp,
+            range: Some(IpSetRange::Cidr("10.0.0.0".parse().unwrap(), 24)),
+            forceadd: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            ipset_create("lipsets_test_forceadd_bm", &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
 
-        nftset_add("inet", TABLE_NAME, SET_NAME, addr1).expect("Failed to add IP");
-        nftset_add("inet", TABLE_NAME, SET_NAME, addr2).expect("Failed to add IP");
-        nftset_add("inet", TABLE_NAME, SET_NAME, addr3).expect("Failed to add IP");
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
 
-        // Verify with nftset_test
-        assert!(
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr1).unwrap(),
-            "addr1 should exist via test"
-        );
-        assert!(
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr2).unwrap(),
-            "addr2 should exist via test"
-        );
-        assert!(
-            nftset_test("inet", TABLE_NAME, SET_NAME, addr3).unwrap(),
-            "addr3 should exist via test"
-        );
+    #[test]
+    fn test_ipset_hashsize_maxelem() {
+        const SET_NAME: &str = "lipsets_test_maxelem";
 
-        // List should now contain all three
-        let ips = nftset_list("inet", TABLE_NAME, SET_NAME).expect("Failed to list nftset");
-        assert_eq!(ips.len(), 3, "Set should contain 3 IPs");
-        assert!(ips.contains(&addr1), "Set should contain addr1");
-        assert!(ips.contains(&addr2), "Set should contain addr2");
-        assert!(ips.contains(&addr3), "Set should contain addr3");
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            hashsize: Some(1024),
+            maxelem: Some(2),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with maxelem");
 
-        // Delete one and verify
-        nftset_del("inet", TABLE_NAME, SET_NAME, addr2).expect("Failed to delete IP");
-        let ips = nftset_list("inet", TABLE_NAME, SET_NAME).expect("Failed to list nftset");
-        assert_eq!(ips.len(), 2, "Set should contain 2 IPs after delete");
-        assert!(!ips.contains(&addr2), "Set should not contain addr2");
+        ipset_add(SET_NAME, "10.0.1.1".parse::<IpAddr>().unwrap()).expect("Failed to add IP");
+        ipset_add(SET_NAME, "10.0.1.2".parse::<IpAddr>().unwrap()).expect("Failed to add IP");
+        // The set is full
+        assert!(matches!(
+            ipset_add(SET_NAME, "10.0.1.3".parse::<IpAddr>().unwrap()),
+            Err(RipsetError::SetFull(_))
+        ));
+
+        // Size options only apply to hash types
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::ListSet,
+            maxelem: Some(2),
+            ..Default::default()
+        };
+        assert!(matches!(
+            ipset_create("lipsets_test_maxelem_ls", &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
 
         // Cleanup
-        let _ = nftset_delete_table("inet", TABLE_NAME);
+        let _ = ipset_destroy(SET_NAME);
     }
 
     #[test]
-    fn test_nftset_list_tables() {
-        const TABLE_NAME1: &str = "lnftsets_test_tables_1";
-        const TABLE_NAME2: &str = "lnftsets_test_tables_2";
+    fn test_ipset_bucketsize_initval() {
+        const SET_NAME: &str = "lipsets_test_bucketsize";
 
-        // Setup - ensure clean state
-        let _ = nftset_delete_table("inet", TABLE_NAME1);
-        let _ = nftset_delete_table("inet", TABLE_NAME2);
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            bucketsize: Some(4),
+            initval: Some(0x1234_5678),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with bucketsize");
 
-        // Create two tables
-        nftset_create_table("inet", TABLE_NAME1).expect("Failed to create table1");
-        nftset_create_table("inet", TABLE_NAME2).expect("Failed to create table2");
+        let addr: IpAddr = "10.0.2.1".parse().unwrap();
+        ipset_add(SET_NAME, addr).expect("Failed to add IP");
+        assert!(ipset_test(SET_NAME, addr).expect("Failed to test IP"));
 
-        // List tables
-        let tables = nftset_list_tables("inet").expect("Failed to list tables");
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
 
-        // Should contain both tables we created
-        assert!(
-            tables.contains(&TABLE_NAME1.to_string()),
-            "Should contain table1"
-        );
-        assert!(
-            tables.contains(&TABLE_NAME2.to_string()),
-            "Should contain table2"
-        );
+    #[test]
+    fn test_ipset_extra_attrs() {
+        const SET_NAME: &str = "lipsets_test_extra_attrs";
+        // IPSET_ATTR_MAXELEM in network byte order
+        const IPSET_ATTR_MAXELEM: u16 = 19 | (1 << 14);
 
-        // Delete one and verify
-        nftset_delete_table("inet", TABLE_NAME1).expect("Failed to delete table1");
-        let tables = nftset_list_tables("inet").expect("Failed to list tables after delete");
-        assert!(
-            !tables.contains(&TABLE_NAME1.to_string()),
-            "Should not contain deleted table1"
-        );
-        assert!(
-            tables.contains(&TABLE_NAME2.to_string()),
-            "Should still contain table2"
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            extra_attrs: vec![(IPSET_ATTR_MAXELEM, 3u32.to_be_bytes().to_vec())],
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with extra attributes");
+        assert_eq!(
+            ipset_info(SET_NAME).expect("Failed to query info").maxelem,
+            Some(3)
         );
 
+        // The kernel validates the raw attributes like modeled ones
+        let opts = IpSetCreateOptions {
+            extra_attrs: vec![(IPSET_ATTR_MAXELEM, vec![1])],
+            ..Default::default()
+        };
+        assert!(ipset_create("lipsets_test_extra_bad", &opts).is_err());
+
         // Cleanup
-        let _ = nftset_delete_table("inet", TABLE_NAME2);
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_nomatch() {
+        const SET_NAME: &str = "lipsets_test_nomatch";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create hash:net ipset");
+
+        // Block 10.0.0.0/8 except 10.1.2.0/24
+        ipset_add(SET_NAME, IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 8))
+            .expect("Failed to add network");
+        let mut exception = IpEntry::with_cidr("10.1.2.0".parse().unwrap(), 24);
+        exception.nomatch = true;
+        ipset_add(SET_NAME, exception.clone()).expect("Failed to add nomatch network");
+
+        let blocked: IpAddr = "10.2.0.1".parse().unwrap();
+        let excepted: IpAddr = "10.1.2.5".parse().unwrap();
+        assert!(ipset_test(SET_NAME, blocked).expect("Failed to test IP"));
+        assert!(!ipset_test(SET_NAME, excepted).expect("Failed to test IP"));
+
+        // Testing with nomatch reports hits on the exception entry
+        let mut probe = IpEntry::new(excepted);
+        probe.nomatch = true;
+        assert!(ipset_test(SET_NAME, probe).expect("Failed to test IP"));
+        let mut probe = IpEntry::new(blocked);
+        probe.nomatch = true;
+        assert!(!ipset_test(SET_NAME, probe).expect("Failed to test IP"));
+
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert!(entries.contains(&Entry::Ip(exception)));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_list_remaining_timeout() {
+        const SET_NAME: &str = "lipsets_test_list_timeout";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            timeout: Some(600),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with timeout");
+
+        let addr: IpAddr = "10.0.3.1".parse().unwrap();
+        ipset_add(SET_NAME, IpEntry::with_timeout(addr, 300)).expect("Failed to add IP");
+
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert_eq!(entries.len(), 1);
+        let Entry::Ip(entry) = &entries[0] else {
+            panic!("Expected an IP entry");
+        };
+        assert_eq!(entry.addr, addr);
+        let remaining = entry.timeout.expect("Expected a remaining timeout");
+        assert!(remaining > 0 && remaining <= 300);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_add_exist() {
+        const SET_NAME: &str = "lipsets_test_add_exist";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            timeout: Some(600),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with timeout");
+
+        let addr: IpAddr = "10.0.4.1".parse().unwrap();
+        ipset_add(SET_NAME, IpEntry::with_timeout(addr, 10)).expect("Failed to add IP");
+
+        // A plain add reports the duplicate
+        assert!(matches!(
+            ipset_add(SET_NAME, IpEntry::with_timeout(addr, 500)),
+            Err(RipsetError::EntryExists)
+        ));
+
+        // Re-adding with -exist semantics refreshes the timeout
+        ipset_add_exist(SET_NAME, IpEntry::with_timeout(addr, 500)).expect("Failed to refresh IP");
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        let Some(Entry::Ip(entry)) = entries.first() else {
+            panic!("Expected an IP entry");
+        };
+        assert!(entry.timeout.expect("Expected a remaining timeout") > 10);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_add_many() {
+        const SET_NAME: &str = "lipsets_test_add_many";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+
+        // Enough entries for many requests and several sends
+        let mut entries: Vec<Entry> = (0..50_000u32)
+            .map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i)).into())
+            .collect();
+        // Duplicates are refreshed and ranges expanded
+        entries.push("10.0.0.1".parse::<IpAddr>().unwrap().into());
+        let range: IpEntry = "10.1.0.0-10.1.0.3".parse().unwrap();
+        entries.push(range.into());
+        ipset_add_many(SET_NAME, entries).expect("Failed to add entries");
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 50_004);
+        assert!(ipset_test(SET_NAME, "10.0.195.79".parse::<IpAddr>().unwrap()).unwrap());
+        assert!(ipset_test(SET_NAME, "10.1.0.2".parse::<IpAddr>().unwrap()).unwrap());
+
+        let addr: IpAddr = "10.2.0.1".parse().unwrap();
+        assert!(matches!(
+            ipset_add_many("lipsets_test_add_many_missing", [addr]),
+            Err(RipsetError::SetNotFound(_))
+        ));
+        ipset_add_many(SET_NAME, Vec::<IpAddr>::new()).expect("Failed to add nothing");
+
+        // Refused entries don't disturb the following requests
+        let session = IpsetSession::new().expect("Failed to open session");
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert!(session.add_many(SET_NAME, [addr, v6, v6]).is_err());
+        assert!(session.test(SET_NAME, addr).unwrap());
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_del_many() {
+        const SET_NAME: &str = "lipsets_test_del_many";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+
+        // Enough entries for many requests and several sends
+        let addrs: Vec<IpAddr> = (0..20_000u32)
+            .map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i)))
+            .collect();
+        ipset_add_many(SET_NAME, addrs.clone()).expect("Failed to add entries");
+        let failures = ipset_del_many(SET_NAME, addrs[2..].to_vec()).expect("Failed to delete");
+        assert!(failures.is_empty());
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 2);
+
+        // Missing entries are reported and the others deleted, even after
+        // a refused entry of the same request
+        let missing: IpAddr = "192.168.0.1".parse().unwrap();
+        let range: IpEntry = "10.1.0.0-10.1.0.3".parse().unwrap();
+        let entries: Vec<Entry> = vec![
+            missing.into(),
+            addrs[0].into(),
+            addrs[5].into(),
+            range.into(),
+            addrs[1].into(),
+        ];
+        let failures = ipset_del_many(SET_NAME, entries).expect("Failed to delete");
+        assert!(matches!(
+            failures.as_slice(),
+            [
+                (0, RipsetError::EntryNotFound),
+                (2, RipsetError::EntryNotFound),
+                (3, RipsetError::EntryNotFound)
+            ]
+        ));
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 0);
+
+        assert!(matches!(
+            ipset_del_many("lipsets_test_del_many_missing", [missing]),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_test_many() {
+        const SET_NAME: &str = "lipsets_test_test_many";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+
+        // Enough addresses for several sends, every third one in the set
+        let addrs: Vec<IpAddr> = (0..1000u32)
+            .map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i)))
+            .collect();
+        ipset_add_many(SET_NAME, addrs.iter().step_by(3).copied()).expect("Failed to add");
+
+        let found = ipset_test_many(SET_NAME, addrs.iter().copied()).expect("Failed to test");
+        let expected: Vec<bool> = (0..addrs.len()).map(|i| i % 3 == 0).collect();
+        assert_eq!(found, expected);
+
+        // Ranges can't be tested
+        let range: IpEntry = "10.1.0.0-10.1.0.3".parse().unwrap();
+        assert!(matches!(
+            ipset_test_many(SET_NAME, [range]),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_session() {
+        const SET_NAME: &str = "lipsets_test_session";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+
+        let session = IpsetSession::new().expect("Failed to open session");
+        let addrs: Vec<IpAddr> = (1..=50)
+            .map(|i| format!("10.0.1.{}", i).parse().unwrap())
+            .collect();
+        for addr in &addrs {
+            session.add(SET_NAME, *addr).expect("Failed to add IP");
+        }
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 50);
+
+        // Errors don't disturb the following requests
+        assert!(matches!(
+            session.add(SET_NAME, addrs[0]),
+            Err(RipsetError::EntryExists)
+        ));
+        session
+            .add_exist(SET_NAME, addrs[0])
+            .expect("Failed to re-add IP");
+        assert!(session.test(SET_NAME, addrs[0]).unwrap());
+        session
+            .del(SET_NAME, addrs[0])
+            .expect("Failed to delete IP");
+        assert!(!session.test(SET_NAME, addrs[0]).unwrap());
+        assert!(matches!(
+            session.add("lipsets_test_session_missing", addrs[0]),
+            Err(RipsetError::SetNotFound(_))
+        ));
+        assert!(session.test(SET_NAME, addrs[1]).unwrap());
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_pipeline() {
+        const SET_NAME: &str = "lipsets_test_pipeline";
+        const NET_SET_NAME: &str = "lipsets_test_pipeline_net";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let _ = ipset_destroy(NET_SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(NET_SET_NAME, &opts).expect("Failed to create hash:net set");
+
+        let session = IpsetSession::new().expect("Failed to open session");
+        let addrs: Vec<IpAddr> = (0..1000u32)
+            .map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a02_0000 + i)))
+            .collect();
+
+        // Enough requests for several groups, one of them failing
+        let mut pipeline = session.pipeline();
+        for addr in &addrs {
+            pipeline.add(SET_NAME, *addr).expect("Failed to queue add");
+        }
+        pipeline
+            .add(SET_NAME, addrs[0])
+            .expect("Failed to queue add");
+        assert_eq!(pipeline.len(), addrs.len() + 1);
+        let results = pipeline.finish().expect("Failed to send pipeline");
+        assert_eq!(results.len(), addrs.len() + 1);
+        assert!(results[..addrs.len()].iter().all(Result::is_ok));
+        assert!(matches!(
+            results[addrs.len()],
+            Err(RipsetError::EntryExists)
+        ));
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 1000);
+
+        // Commands and sets can be mixed, each request gets its own result
+        let range: IpEntry = "10.3.0.0-10.3.1.127".parse().unwrap();
+        let mut pipeline = session.pipeline();
+        pipeline.del(SET_NAME, addrs[1]).unwrap();
+        pipeline.test(SET_NAME, addrs[1]).unwrap();
+        pipeline.test(SET_NAME, addrs[2]).unwrap();
+        pipeline.add(NET_SET_NAME, range).unwrap();
+        pipeline
+            .add("lipsets_test_pipeline_missing", addrs[0])
+            .unwrap();
+        pipeline.add_exist(SET_NAME, addrs[0]).unwrap();
+        pipeline.del("", addrs[0]).unwrap();
+        pipeline.del(SET_NAME, addrs[1]).unwrap();
+        let results = pipeline.finish().expect("Failed to send pipeline");
+        assert_eq!(results.len(), 8);
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(RipsetError::EntryNotFound)));
+        assert!(results[2].is_ok());
+        assert!(results[3].is_ok());
+        assert!(matches!(results[4], Err(RipsetError::SetNotFound(_))));
+        assert!(results[5].is_ok());
+        assert!(matches!(results[6], Err(RipsetError::InvalidSetName(_))));
+        // Like ipset_del, deleting a missing entry isn't an error
+        assert!(results[7].is_ok());
+
+        // The range was added as the two blocks covering it
+        assert_eq!(ipset_list(NET_SET_NAME).unwrap().len(), 2);
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 999);
+
+        // Queued requests are dropped with the pipeline
+        let mut pipeline = session.pipeline();
+        pipeline.del(SET_NAME, addrs[0]).unwrap();
+        drop(pipeline);
+        assert!(session.test(SET_NAME, addrs[0]).unwrap());
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+        let _ = ipset_destroy(NET_SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_session_pool() {
+        const SET_NAME: &str = "lipsets_test_session_pool";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+
+        let pool = SessionPool::<IpsetSession>::ipset();
+        std::thread::scope(|s| {
+            for thread in 0..4 {
+                let pool = &pool;
+                s.spawn(move || {
+                    for i in 1..=25 {
+                        let addr: IpAddr = format!("10.1.{thread}.{i}").parse().unwrap();
+                        let session = pool.get().expect("Failed to get session");
+                        session.add(SET_NAME, addr).expect("Failed to add IP");
+                        assert!(session.test(SET_NAME, addr).unwrap());
+                    }
+                });
+            }
+        });
+        assert_eq!(ipset_count(SET_NAME).unwrap(), 100);
+        // Sessions are reused rather than opened for every operation
+        assert!((1..=4).contains(&pool.idle()));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_deadline() {
+        const SET_NAME: &str = "lipsets_test_deadline";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create set");
+        let addr: IpAddr = "10.0.2.1".parse().unwrap();
+
+        // A generous budget doesn't get in the way
+        with_timeout(Duration::from_secs(10), || ipset_add(SET_NAME, addr))
+            .expect("Failed to add IP");
+
+        // Nothing is sent once the deadline has passed
+        let session = IpsetSession::new().expect("Failed to open session");
+        let deadline = Instant::now();
+        assert!(matches!(
+            with_deadline(deadline, || ipset_list(SET_NAME)),
+            Err(RipsetError::Timeout)
+        ));
+        assert!(matches!(
+            with_deadline(deadline, || session.test(SET_NAME, addr)),
+            Err(RipsetError::Timeout)
+        ));
+
+        // Inner deadlines can't extend outer ones
+        assert!(matches!(
+            with_deadline(deadline, || {
+                with_timeout(Duration::from_secs(10), || ipset_list(SET_NAME))
+            }),
+            Err(RipsetError::Timeout)
+        ));
+
+        // A session used under a deadline keeps working without one
+        with_timeout(Duration::from_millis(100), || session.test(SET_NAME, addr))
+            .expect("Failed to test IP");
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(session.test(SET_NAME, addr).unwrap());
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_test_counter_thresholds() {
+        const SET_NAME: &str = "lipsets_test_counter_match";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            counters: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset with counters");
+
+        let mut heavy = IpEntry::with_cidr("10.0.5.0".parse().unwrap(), 24);
+        heavy.packets = Some(5000);
+        heavy.bytes = Some(4_000_000);
+        ipset_add(SET_NAME, heavy).expect("Failed to add network");
+        let mut light = IpEntry::new("10.0.6.1".parse().unwrap());
+        light.packets = Some(3);
+        light.bytes = Some(180);
+        ipset_add(SET_NAME, light).expect("Failed to add IP");
+
+        let opts = IpSetTestOptions {
+            packets: Some(CounterMatch::Gt(1000)),
+            ..Default::default()
+        };
+        let heavy_addr: IpAddr = "10.0.5.7".parse().unwrap();
+        let light_addr: IpAddr = "10.0.6.1".parse().unwrap();
+        let missing_addr: IpAddr = "10.0.7.1".parse().unwrap();
+        assert!(ipset_test_with_options(SET_NAME, heavy_addr, &opts).expect("Failed to test IP"));
+        assert!(!ipset_test_with_options(SET_NAME, light_addr, &opts).expect("Failed to test IP"));
+        assert!(
+            !ipset_test_with_options(SET_NAME, missing_addr, &opts).expect("Failed to test IP")
+        );
+
+        let opts = IpSetTestOptions {
+            packets: Some(CounterMatch::Lt(10)),
+            bytes: Some(CounterMatch::Eq(180)),
+        };
+        assert!(ipset_test_with_options(SET_NAME, light_addr, &opts).expect("Failed to test IP"));
+
+        // Without a counter condition the entry is only tested
+        let opts = IpSetTestOptions::default();
+        assert!(ipset_test_with_options(SET_NAME, light_addr, &opts).expect("Failed to test IP"));
+        assert!(
+            !ipset_test_with_options(SET_NAME, missing_addr, &opts).expect("Failed to test IP")
+        );
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_test_counter_thresholds_by_port() {
+        const SET_NAME: &str = "lipsets_test_counter_port";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashIpPort,
+            counters: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create hash:ip,port ipset");
+
+        let addr: IpAddr = "10.0.8.1".parse().unwrap();
+        let mut https = IpEntry::new(addr);
+        https.port = Some(443);
+        https.packets = Some(5000);
+        https.bytes = Some(4_000_000);
+        ipset_add(SET_NAME, https.clone()).expect("Failed to add entry");
+        let mut ssh = IpEntry::new(addr);
+        ssh.port = Some(22);
+        ssh.packets = Some(3);
+        ssh.bytes = Some(180);
+        ipset_add(SET_NAME, ssh.clone()).expect("Failed to add entry");
+
+        let opts = IpSetTestOptions {
+            packets: Some(CounterMatch::Gt(1000)),
+            ..Default::default()
+        };
+        assert!(
+            ipset_test_with_options(SET_NAME, https.clone(), &opts).expect("Failed to test entry")
+        );
+        assert!(
+            !ipset_test_with_options(SET_NAME, ssh.clone(), &opts).expect("Failed to test entry")
+        );
+        let opts = IpSetTestOptions {
+            packets: Some(CounterMatch::Eq(3)),
+            bytes: Some(CounterMatch::Eq(180)),
+        };
+        assert!(ipset_test_with_options(SET_NAME, ssh, &opts).expect("Failed to test entry"));
+
+        assert!(matches!(
+            ipset_test_match(SET_NAME, addr),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_create_exist() {
+        const SET_NAME: &str = "lipsets_test_create_exist";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions::default();
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+
+        // A plain create reports the existing set
+        assert!(matches!(
+            ipset_create(SET_NAME, &opts),
+            Err(RipsetError::EntryExists)
+        ));
+
+        // With -exist semantics an identical set is accepted
+        ipset_create_exist(SET_NAME, &opts).expect("Failed to create existing ipset");
+
+        // But a set of a different type is still an error
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        assert!(ipset_create_exist(SET_NAME, &opts).is_err());
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_ensure() {
+        const SET_NAME: &str = "lipsets_test_ensure";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            timeout: Some(300),
+            ..Default::default()
+        };
+
+        // Created when missing, kept with its entries afterwards
+        ipset_ensure_set(SET_NAME, &opts).expect("Failed to ensure ipset");
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(ipset_ensure_entry(SET_NAME, addr).expect("Failed to ensure entry"));
+        assert!(!ipset_ensure_entry(SET_NAME, addr).expect("Failed to ensure entry"));
+        ipset_ensure_set(SET_NAME, &opts).expect("Failed to ensure existing ipset");
+        assert!(ipset_test(SET_NAME, addr).expect("Failed to test IP"));
+
+        // Ranges are added block by block
+        let range = IpEntry::with_range("10.0.0.0".parse().unwrap(), "10.0.0.3".parse().unwrap());
+        assert!(ipset_ensure_entry(SET_NAME, range.clone()).expect("Failed to ensure entry"));
+        assert!(!ipset_ensure_entry(SET_NAME, range).expect("Failed to ensure entry"));
+
+        // Differing options are reported
+        let other = IpSetCreateOptions {
+            timeout: Some(60),
+            ..opts.clone()
+        };
+        assert!(matches!(
+            ipset_ensure_set(SET_NAME, &other),
+            Err(RipsetError::SetMismatch(name, property)) if name == SET_NAME && property == "timeout"
+        ));
+        let other = IpSetCreateOptions {
+            set_type: IpSetType::HashIp,
+            ..opts
+        };
+        assert!(matches!(
+            ipset_ensure_set(SET_NAME, &other),
+            Err(RipsetError::SetMismatch(_, property)) if property == "type"
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_info() {
+        const SET_NAME: &str = "lipsets_test_info";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            family: IpSetFamily::Inet6,
+            timeout: Some(120),
+            hashsize: Some(2048),
+            maxelem: Some(1000),
+            counters: true,
+            comment: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+        ipset_add(SET_NAME, "2001:db8::1".parse::<IpAddr>().unwrap()).expect("Failed to add IP");
+
+        let info = ipset_info(SET_NAME).expect("Failed to query ipset info");
+        assert_eq!(info.name, SET_NAME);
+        assert_eq!(info.type_name, "hash:net");
+        assert_eq!(info.family, Some(IpSetFamily::Inet6));
+        assert_eq!(info.timeout, Some(120));
+        assert_eq!(info.hashsize, Some(2048));
+        assert_eq!(info.maxelem, Some(1000));
+        assert!(info.counters && info.comment);
+        assert!(!info.skbinfo && !info.forceadd);
+        assert_eq!(info.references, 0);
+        assert_eq!(info.entries, 1);
+        assert!(info.memsize > 0);
+
+        let range = IpSetRange::FromTo("10.0.0.0".parse().unwrap(), "10.0.0.255".parse().unwrap());
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIp,
+            range: Some(range),
+            ..Default::default()
+        };
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &opts).expect("Failed to create bitmap ipset");
+        let info = ipset_info(SET_NAME).expect("Failed to query ipset info");
+        assert_eq!(info.type_name, "bitmap:ip");
+        assert_eq!(info.range, Some(range));
+        assert_eq!(info.entries, 0);
+
+        assert!(matches!(
+            ipset_info("lipsets_test_info_missing"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    #[ignore = "flushes and destroys every ipset on the host; run alone with --ignored"]
+    fn test_ipset_flush_destroy_all() {
+        const SET_NAMES: [&str; 2] = ["lipsets_test_all_a", "lipsets_test_all_b"];
+
+        // Setup
+        let addr: IpAddr = "10.0.8.1".parse().unwrap();
+        for name in SET_NAMES {
+            let _ = ipset_destroy(name);
+            ipset_create(name, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+            ipset_add(name, addr).expect("Failed to add IP");
+        }
+
+        ipset_flush_all().expect("Failed to flush all ipsets");
+        for name in SET_NAMES {
+            assert!(ipset_list(name).expect("Failed to list ipset").is_empty());
+        }
+
+        ipset_destroy_all().expect("Failed to destroy all ipsets");
+        for name in SET_NAMES {
+            assert!(matches!(ipset_list(name), Err(RipsetError::SetNotFound(_))));
+        }
+    }
+
+    #[test]
+    fn test_ipset_swap() {
+        const SET_A: &str = "lipsets_test_swap_a";
+        const SET_B: &str = "lipsets_test_swap_b";
+        const SET_NET: &str = "lipsets_test_swap_net";
+
+        // Setup
+        let addr_a: IpAddr = "10.0.9.1".parse().unwrap();
+        let addr_b: IpAddr = "10.0.9.2".parse().unwrap();
+        for (name, addr) in [(SET_A, addr_a), (SET_B, addr_b)] {
+            let _ = ipset_destroy(name);
+            ipset_create(name, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+            ipset_add(name, addr).expect("Failed to add IP");
+        }
+        let _ = ipset_destroy(SET_NET);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(SET_NET, &opts).expect("Failed to create ipset");
+
+        ipset_swap(SET_A, SET_B).expect("Failed to swap ipsets");
+        assert_eq!(ipset_list(SET_A).unwrap(), vec![addr_b]);
+        assert_eq!(ipset_list(SET_B).unwrap(), vec![addr_a]);
+
+        assert!(matches!(
+            ipset_swap(SET_A, SET_NET),
+            Err(RipsetError::TypeMismatch(_))
+        ));
+        assert!(matches!(
+            ipset_swap(SET_A, "lipsets_test_swap_missing"),
+            Err(RipsetError::SetNotFound(name)) if name == "lipsets_test_swap_missing"
+        ));
+
+        // Cleanup
+        for name in [SET_A, SET_B, SET_NET] {
+            let _ = ipset_destroy(name);
+        }
+    }
+
+    #[test]
+    fn test_ipset_replace() {
+        const SET_NAME: &str = "lipsets_test_replace";
+        const LIST_NAME: &str = "lipsets_test_replace_lset";
+        let addr = |s: &str| -> IpAddr { s.parse().unwrap() };
+        let sorted = |mut addrs: Vec<IpAddr>| {
+            addrs.sort();
+            addrs
+        };
+
+        // Setup
+        let _ = ipset_destroy(LIST_NAME);
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions::default();
+
+        // The set is created if needed
+        ipset_replace(SET_NAME, [addr("10.0.0.1"), addr("10.0.0.2")], &opts)
+            .expect("Failed to create set");
+        assert_eq!(
+            sorted(ipset_list(SET_NAME).unwrap()),
+            vec![addr("10.0.0.1"), addr("10.0.0.2")]
+        );
+
+        // References to the live set are kept
+        let list_opts = IpSetCreateOptions {
+            set_type: IpSetType::ListSet,
+            ..Default::default()
+        };
+        ipset_create(LIST_NAME, &list_opts).expect("Failed to create list:set");
+        ipset_add(LIST_NAME, SET_NAME).expect("Failed to add member");
+
+        ipset_replace(SET_NAME, [addr("10.0.0.2"), addr("10.0.0.3")], &opts)
+            .expect("Failed to replace set");
+        assert_eq!(
+            sorted(ipset_list(SET_NAME).unwrap()),
+            vec![addr("10.0.0.2"), addr("10.0.0.3")]
+        );
+        assert_eq!(ipset_references(SET_NAME).unwrap(), 1);
+        assert!(!ipset_exists("lipsets_test_replace.tmp").unwrap());
+
+        // A failed fill leaves the live set alone
+        let v6 = addr("2001:db8::1");
+        assert!(ipset_replace(SET_NAME, [addr("10.0.0.4"), v6], &opts).is_err());
+        assert_eq!(
+            sorted(ipset_list(SET_NAME).unwrap()),
+            vec![addr("10.0.0.2"), addr("10.0.0.3")]
+        );
+        assert!(!ipset_exists("lipsets_test_replace.tmp").unwrap());
+
+        // Cleanup
+        let _ = ipset_destroy(LIST_NAME);
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_protocol() {
+        let protocol = ipset_protocol().expect("Failed to query ipset protocol");
+        assert!(protocol.version >= 6);
+        assert!(protocol.min_version <= protocol.version);
+    }
+
+    #[test]
+    fn test_ipset_supported_types() {
+        let revisions = ipset_type_revisions(IpSetType::HashNet)
+            .expect("Failed to query type revisions")
+            .expect("hash:net is not supported");
+        assert!(revisions.min_revision <= revisions.max_revision);
+        // The revision ripset creates hash:net sets with
+        assert!(revisions.max_revision >= 6);
+
+        let supported = ipset_supported_types().expect("Failed to query supported types");
+        assert!(supported.contains(&revisions));
+        assert!(supported.iter().any(|r| r.set_type == IpSetType::HashIp));
+    }
+
+    #[test]
+    fn test_ipset_test_match() {
+        const SET_NAME: &str = "lipsets_test_match";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create hash:net ipset");
+
+        let wide = IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 8);
+        let narrow = IpEntry::with_cidr("10.9.9.0".parse().unwrap(), 24);
+        ipset_add(SET_NAME, wide.clone()).expect("Failed to add network");
+        ipset_add(SET_NAME, narrow.clone()).expect("Failed to add network");
+
+        let matched =
+            ipset_test_match(SET_NAME, "10.9.9.9".parse().unwrap()).expect("Failed to test IP");
+        assert_eq!(matched, Some(narrow));
+        let matched =
+            ipset_test_match(SET_NAME, "10.8.0.1".parse().unwrap()).expect("Failed to test IP");
+        assert_eq!(matched, Some(wide));
+        let matched =
+            ipset_test_match(SET_NAME, "192.168.0.1".parse().unwrap()).expect("Failed to test IP");
+        assert_eq!(matched, None);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_range_entries() {
+        const SET_NAME: &str = "lipsets_test_range";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+
+        let range: IpEntry = "10.0.9.1-10.0.9.6".parse().expect("Failed to parse range");
+        ipset_add(SET_NAME, range.clone()).expect("Failed to add range");
+        let mut addrs = ipset_list(SET_NAME).expect("Failed to list ipset");
+        addrs.sort();
+        let expected: Vec<IpAddr> = (1..=6)
+            .map(|i| format!("10.0.9.{i}").parse().unwrap())
+            .collect();
+        assert_eq!(addrs, expected);
+
+        // Ranges can't be tested, only added or deleted
+        assert!(matches!(
+            ipset_test(SET_NAME, range.clone()),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        ipset_del(SET_NAME, range).expect("Failed to delete range");
+        assert!(
+            ipset_list(SET_NAME)
+                .expect("Failed to list ipset")
+                .is_empty()
+        );
+
+        // hash:net keeps the CIDR blocks covering the range
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create hash:net ipset");
+        ipset_add(
+            SET_NAME,
+            "10.0.10.0-10.0.11.127".parse::<IpEntry>().unwrap(),
+        )
+        .expect("Failed to add range");
+        assert!(ipset_test(SET_NAME, "10.0.11.100".parse::<IpAddr>().unwrap()).unwrap());
+        assert!(!ipset_test(SET_NAME, "10.0.11.200".parse::<IpAddr>().unwrap()).unwrap());
+        assert_eq!(ipset_list(SET_NAME).expect("Failed to list ipset").len(), 2);
+
+        assert!("10.0.0.2-10.0.0.1".parse::<IpEntry>().is_err());
+        assert!("10.0.0.0/33".parse::<IpEntry>().is_err());
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_save_syntax_round_trip() {
+        const SET_NAME: &str = "lipsets_test_save_syntax";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNetPort,
+            timeout: Some(600),
+            counters: true,
+            comment: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+
+        let lines = [
+            "10.0.0.0/24,tcp:443 timeout 0 packets 5 bytes 300 comment \"web server\"",
+            "10.0.1.0/28,udp:53 timeout 0 packets 0 bytes 0 comment \"dns\"",
+        ];
+        for line in lines {
+            let entry: IpEntry = line.parse().expect("Failed to parse entry");
+            assert_eq!(entry.to_string(), line);
+            ipset_add(SET_NAME, entry).expect("Failed to add entry");
+        }
+
+        let mut listed: Vec<String> = ipset_list_entries(SET_NAME)
+            .expect("Failed to list entries")
+            .iter()
+            .map(Entry::to_string)
+            .collect();
+        listed.sort();
+        assert_eq!(listed, lines);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_save() {
+        const SET1: &str = "lipsets_test_save1";
+        const SET2: &str = "lipsets_test_save2";
+
+        // Setup
+        let _ = ipset_destroy(SET1);
+        let _ = ipset_destroy(SET2);
+        let opts = IpSetCreateOptions {
+            hashsize: Some(1024),
+            maxelem: Some(1000),
+            comment: true,
+            ..Default::default()
+        };
+        ipset_create(SET1, &opts).expect("Failed to create ipset");
+        let mut entry = IpEntry::new("10.0.0.1".parse().unwrap());
+        entry.comment = Some("host".to_string());
+        ipset_add(SET1, entry).expect("Failed to add entry");
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapPort,
+            range: Some(IpSetRange::Ports(1, 1024)),
+            ..Default::default()
+        };
+        ipset_create(SET2, &opts).expect("Failed to create ipset");
+        ipset_add(SET2, 22u16).expect("Failed to add port");
+
+        let saved = ipset_save(SET1).expect("Failed to save");
+        let lines: Vec<&str> = saved.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(&format!(
+            "create {SET1} hash:ip family inet hashsize 1024 maxelem 1000 comment"
+        )));
+        assert_eq!(lines[1], format!("add {SET1} 10.0.0.1 comment \"host\""));
+
+        // Saved comments read back, and those that couldn't are refused
+        let restored = ripset::save::parse_restore(&saved).expect("Failed to parse save");
+        assert_eq!(
+            restored[0].entries,
+            [Entry::Ip(IpEntry::with_comment(
+                "10.0.0.1".parse().unwrap(),
+                "host"
+            ))]
+        );
+        for comment in ["say \"hi\"", "two\nlines", "nul\0", &"x".repeat(256)] {
+            let entry = IpEntry::with_comment("10.0.0.2".parse().unwrap(), comment);
+            assert!(matches!(
+                ipset_add(SET1, entry),
+                Err(RipsetError::InvalidOption(_))
+            ));
+        }
+        let longest = IpEntry::with_comment("10.0.0.3".parse().unwrap(), "x".repeat(255));
+        ipset_add(SET1, longest.clone()).expect("Failed to add entry");
+        let saved = ipset_save(SET1).expect("Failed to save");
+        let restored = ripset::save::parse_restore(&saved).expect("Failed to parse save");
+        assert!(restored[0].entries.contains(&Entry::Ip(longest)));
+
+        // All sets, in creation order
+        let names: Vec<String> = ipset_list_sets()
+            .expect("Failed to list sets")
+            .into_iter()
+            .map(|info| info.name)
+            .filter(|name| name == SET1 || name == SET2)
+            .collect();
+        assert_eq!(names, [SET1, SET2]);
+        let saved = ipset_save_all().expect("Failed to save all");
+        let ours = saved.find(&format!("create {SET1} ")).unwrap();
+        assert!(saved[ours..].contains(&format!(
+            "create {SET2} bitmap:port range 1-1024\nadd {SET2} 22\n"
+        )));
+
+        // Cleanup
+        let _ = ipset_destroy(SET1);
+        let _ = ipset_destroy(SET2);
+    }
+
+    #[test]
+    fn test_ipset_restore() {
+        const SET_NAME: &str = "lipsets_test_restore";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let mut text = format!(
+            "# generated blocklist\n\
+             create {SET_NAME} hash:net family inet hashsize 1024 maxelem 65536 comment\n"
+        );
+        for i in 0..200 {
+            text.push_str(&format!(
+                "add {SET_NAME} 10.{i}.0.0/16 comment \"block {i}\"\n"
+            ));
+        }
+        ipset_restore(&text).expect("Failed to restore");
+        assert_eq!(ipset_count(SET_NAME).expect("Failed to count"), 200);
+
+        // Loading the file again keeps the set and its entries
+        ipset_restore(&text).expect("Failed to restore again");
+        let saved = ipset_save(SET_NAME).expect("Failed to save");
+        assert_eq!(saved.lines().count(), 201);
+        assert!(saved.contains(&format!("add {SET_NAME} 10.7.0.0/16 comment \"block 7\"\n")));
+
+        // A syntax error changes nothing
+        let bad = format!("add {SET_NAME} 10.255.0.0/16\nadd {SET_NAME} not-an-address timeout");
+        assert!(ipset_restore(&bad).is_err());
+        assert_eq!(ipset_count(SET_NAME).expect("Failed to count"), 200);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_netlink_raw_requests() {
+        use ripset::netlink::{
+            MsgBuffer, NFNL_SUBSYS_IPSET, NLM_F_ACK, NLM_F_DUMP, NLM_F_REQUEST, NetlinkSocket,
+            NlAttrIter, nfnl_msg_type, nla_get_str,
+        };
+
+        const SET_NAME: &str = "lipsets_test_raw";
+        const RENAMED: &str = "lipsets_test_raw_renamed";
+        const IPSET_CMD_RENAME: u8 = 5;
+        const IPSET_CMD_LIST: u8 = 7;
+        const IPSET_ATTR_PROTOCOL: u16 = 1;
+        const IPSET_ATTR_SETNAME: u16 = 2;
+        const IPSET_ATTR_SETNAME2: u16 = 3;
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let _ = ipset_destroy(RENAMED);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+
+        let request = |cmd: u8, flags: u16, names: &[&str]| {
+            let mut msg = MsgBuffer::new(128);
+            msg.put_nlmsghdr(nfnl_msg_type(NFNL_SUBSYS_IPSET, cmd), flags, 7);
+            msg.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
+            msg.put_attr_u8(IPSET_ATTR_PROTOCOL, 6);
+            for (attr, name) in [IPSET_ATTR_SETNAME, IPSET_ATTR_SETNAME2]
+                .into_iter()
+                .zip(names)
+            {
+                msg.put_attr_str(attr, name);
+            }
+            msg.finalize_nlmsg();
+            msg
+        };
+
+        let socket = NetlinkSocket::new().expect("Failed to open socket");
+        let rename = request(
+            IPSET_CMD_RENAME,
+            NLM_F_REQUEST | NLM_F_ACK,
+            &[SET_NAME, RENAMED],
+        );
+        socket.ack(rename.as_slice()).expect("Failed to rename");
+        assert!(!ipset_exists(SET_NAME).unwrap());
+        assert!(ipset_exists(RENAMED).unwrap());
+        assert!(matches!(
+            socket.ack(rename.as_slice()),
+            Err(RipsetError::NetlinkError(libc::ENOENT))
+        ));
+
+        let list = request(
+            IPSET_CMD_LIST,
+            NLM_F_REQUEST | NLM_F_ACK | NLM_F_DUMP,
+            &[RENAMED],
+        );
+        let mut names = Vec::new();
+        socket
+            .dump(list.as_slice(), |_, attrs| {
+                names.extend(
+                    NlAttrIter::new(attrs)
+                        .filter(|&(attr, _)| attr == IPSET_ATTR_SETNAME)
+                        .filter_map(|(_, payload)| nla_get_str(payload)),
+                );
+            })
+            .expect("Failed to dump");
+        assert_eq!(names, vec![RENAMED]);
+
+        // Cleanup
+        let _ = ipset_destroy(RENAMED);
+    }
+
+    #[test]
+    fn test_set_name() {
+        let name = SetName::new("lipsets_test_set_name").expect("Failed to check name");
+        let member = SetName::new("lipsets_test_set_member").expect("Failed to check name");
+
+        // Setup
+        let _ = ipset_destroy(&name);
+        let _ = ipset_destroy(&member);
+        ipset_create(&member, &IpSetCreateOptions::default()).expect("Failed to create member");
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::ListSet,
+            ..Default::default()
+        };
+        ipset_create(&name, &opts).expect("Failed to create list:set");
+        ipset_add(&name, member.clone()).expect("Failed to add member");
+        assert_eq!(
+            ipset_list_entries(&name).expect("Failed to list members"),
+            vec![Entry::from(member.clone())]
+        );
+
+        for invalid in [
+            "",
+            "1st",
+            "with space",
+            "quote'",
+            "a_name_longer_than_thirty_one_bytes",
+        ] {
+            match SetName::new(invalid) {
+                Err(RipsetError::InvalidSetName(message)) => assert!(message.contains(invalid)),
+                other => panic!("{invalid:?} was accepted: {other:?}"),
+            }
+        }
+        assert!("_ok.v4/a-b".parse::<SetName>().is_ok());
+
+        // Cleanup
+        let _ = ipset_destroy(&name);
+        let _ = ipset_destroy(&member);
+    }
+
+    #[test]
+    fn test_entry_native_syntax() {
+        let entry: IpEntry = "10.0.0.1,aa:bb:cc:dd:ee:ff skbmark 0x10 skbprio 1:a nomatch"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            entry.mac,
+            Some(MacAddr([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]))
+        );
+        assert_eq!(entry.skbmark, Some((0x10, u32::MAX)));
+        assert_eq!(entry.skbprio, Some(0x1000a));
+        assert!(entry.nomatch);
+        let entry: IpEntry = "10.0.0.0/8,eth0".parse().unwrap();
+        assert_eq!(entry.iface.as_deref(), Some("eth0"));
+        let entry: IpEntry = "192.168.0.1,0x2a".parse().unwrap();
+        assert_eq!(entry.mark, Some(0x2a));
+        assert!("10.0.0.1 timeout soon".parse::<IpEntry>().is_err());
+        assert!(
+            "10.0.0.1 comment \"unterminated"
+                .parse::<IpEntry>()
+                .is_err()
+        );
+        assert!("10.0.0.1 expires 5".parse::<IpEntry>().is_err());
+
+        for text in [
+            "10.0.0.0/24,sctp:80-90",
+            "aa:bb:cc:dd:ee:ff",
+            "0x10",
+            "8080-8090",
+            "\"eth0\"",
+            "jump allow",
+            "blocklist",
+            "10.0.0.1 . \"eth0\" . 443",
+        ] {
+            let entry: Entry = text.parse().expect("Failed to parse entry");
+            assert_eq!(entry.to_string(), text);
+        }
+        assert_eq!(
+            "10.0.0.1 . 443".parse::<Entry>().unwrap(),
+            Entry::Concat(vec![
+                "10.0.0.1".parse::<IpAddr>().unwrap().into(),
+                Entry::from(443),
+            ])
+        );
+        assert!("10.0.0.0/33".parse::<Entry>().is_err());
+
+        assert_eq!(
+            "hash:ip,mark".parse::<IpSetType>().unwrap(),
+            IpSetType::HashIpMark
+        );
+        assert!("hash:foo".parse::<IpSetType>().is_err());
+        let set_type: NftSetType = "ipv4_addr . inet_service".parse().unwrap();
+        assert_eq!(set_type.to_string(), "ipv4_addr . inet_service");
+    }
+
+    #[test]
+    fn test_ipset_port_ranges() {
+        const SET_NAME: &str = "lipsets_test_ports";
+
+        // hash:ip,port
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashIpPort,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create hash:ip,port ipset");
+
+        let range: IpEntry = "10.0.12.1,tcp:8000-8010"
+            .parse()
+            .expect("Failed to parse entry");
+        ipset_add(SET_NAME, range.clone()).expect("Failed to add port range");
+        assert_eq!(
+            ipset_list(SET_NAME).expect("Failed to list ipset").len(),
+            11
+        );
+        let addr: IpAddr = "10.0.12.1".parse().unwrap();
+        assert!(ipset_test(SET_NAME, IpEntry::with_port(addr, 6, 8005)).unwrap());
+        assert!(!ipset_test(SET_NAME, IpEntry::with_port(addr, 17, 8005)).unwrap());
+        assert!(matches!(
+            ipset_test(SET_NAME, range.clone()),
+            Err(RipsetError::InvalidOption(_))
+        ));
+        ipset_del(SET_NAME, range).expect("Failed to delete port range");
+        assert!(
+            ipset_list(SET_NAME)
+                .expect("Failed to list ipset")
+                .is_empty()
+        );
+
+        // hash:net,port
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNetPort,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create hash:net,port ipset");
+        let entry: IpEntry = "10.0.13.0/24,udp:53".parse().unwrap();
+        ipset_add(SET_NAME, entry.clone()).expect("Failed to add entry");
+        let probe = IpEntry::with_port("10.0.13.9".parse().unwrap(), 17, 53);
+        assert!(ipset_test(SET_NAME, probe).unwrap());
+        assert_eq!(
+            ipset_list_entries(SET_NAME).expect("Failed to list ipset"),
+            vec![Entry::Ip(entry)]
+        );
+
+        // bitmap:port
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapPort,
+            range: Some(IpSetRange::Ports(0, 1024)),
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create bitmap:port ipset");
+        let range = Entry::Port {
+            port: 80,
+            port_to: Some(90),
+        };
+        ipset_add(SET_NAME, range).expect("Failed to add port range");
+        assert!(ipset_test(SET_NAME, 85u16).unwrap());
+        assert!(!ipset_test(SET_NAME, 91u16).unwrap());
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert_eq!(entries.len(), 11);
+        assert!(entries.contains(&Entry::from(80u16)));
+        let info = ipset_info(SET_NAME).expect("Failed to query ipset info");
+        assert_eq!(info.range, Some(IpSetRange::Ports(0, 1024)));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_typed_entries() {
+        const SET_NAME: &str = "lipsets_test_typed";
+        let addr: IpAddr = "10.0.14.1".parse().unwrap();
+
+        // Some kernels are built without hash:mac or hash:ip,mark
+        let create = |set_type| {
+            let _ = ipset_destroy(SET_NAME);
+            let opts = IpSetCreateOptions {
+                set_type,
+                ..Default::default()
+            };
+            match ipset_create(SET_NAME, &opts) {
+                Ok(()) => true,
+                Err(RipsetError::KernelUnsupported(_)) => {
+                    eprintln!("Skipping {set_type:?}: not supported by the kernel");
+                    false
+                }
+                Err(e) => panic!("Failed to create {set_type:?} ipset: {e}"),
+            }
+        };
+
+        if create(IpSetType::HashMac) {
+            let mac: MacAddr = "00:11:22:33:44:55".parse().unwrap();
+            ipset_add(SET_NAME, mac).expect("Failed to add MAC");
+            assert!(ipset_test(SET_NAME, mac).unwrap());
+            assert!(!ipset_test(SET_NAME, MacAddr([0, 0x11, 0x22, 0x33, 0x44, 0x66])).unwrap());
+            assert_eq!(
+                ipset_list_entries(SET_NAME).expect("Failed to list ipset"),
+                vec![Entry::Mac(mac)]
+            );
+        }
+
+        if create(IpSetType::HashIpMark) {
+            let entry = Entry::Concat(vec![addr.into(), Entry::Mark(0x10)]);
+            ipset_add(SET_NAME, entry.clone()).expect("Failed to add ip,mark");
+            assert!(ipset_test(SET_NAME, entry).unwrap());
+            let other = Entry::Concat(vec![addr.into(), Entry::Mark(0x20)]);
+            assert!(!ipset_test(SET_NAME, other).unwrap());
+            let mut expected = IpEntry::new(addr);
+            expected.mark = Some(0x10);
+            assert_eq!(
+                ipset_list_entries(SET_NAME).expect("Failed to list ipset"),
+                vec![Entry::Ip(expected)]
+            );
+        }
+
+        assert!(create(IpSetType::HashNetIface));
+        let net = IpEntry::with_cidr("10.0.14.0".parse().unwrap(), 24);
+        let entry = Entry::Concat(vec![net.into(), Entry::Iface("lo".to_string())]);
+        ipset_add(SET_NAME, entry).expect("Failed to add net,iface");
+        let probe = Entry::Concat(vec![addr.into(), Entry::Iface("lo".to_string())]);
+        assert!(ipset_test(SET_NAME, probe).unwrap());
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list ipset");
+        assert!(matches!(&entries[..], [Entry::Ip(e)] if e.iface.as_deref() == Some("lo")));
+
+        // Values that don't follow an address are rejected
+        assert!(matches!(
+            ipset_add(SET_NAME, Entry::Mark(1)),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_netlink_recv_dump() {
+        use ripset::netlink::{
+            MsgBuffer, NFNL_SUBSYS_IPSET, NLM_F_DUMP, NLM_F_REQUEST, NetlinkSocket, header,
+            nfnl_msg_type,
+        };
+        const SET_NAME: &str = "lipsets_test_recv_dump";
+
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+
+        // ipset list, with protocol version 6
+        let mut msg = MsgBuffer::new(64);
+        msg.put_nlmsghdr(
+            nfnl_msg_type(NFNL_SUBSYS_IPSET, 7),
+            NLM_F_REQUEST | NLM_F_DUMP,
+            1,
+        );
+        msg.put_nfgenmsg(libc::AF_INET as u8, 0, 0);
+        msg.put_attr_u8(1, 6);
+        msg.put_attr_str(2, SET_NAME);
+        msg.finalize_nlmsg();
+
+        // The buffer grows to hold the whole message
+        let socket = NetlinkSocket::new_dump().unwrap();
+        socket.send(msg.as_slice()).unwrap();
+        let mut recv_buf = vec![0u8; 16];
+        let received = socket.recv_dump(&mut recv_buf).unwrap();
+        assert!(received > 16);
+        assert_eq!(header(&recv_buf).unwrap().nlmsg_len as usize, received);
+
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_ipset_list_large() {
+        const SET_NAME: &str = "lipsets_test_list_large";
+        const COUNT: u32 = 200_000;
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            maxelem: Some(COUNT),
+            comment: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+        let entries = (0..COUNT).map(|i| {
+            let mut entry = IpEntry::new(IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i)));
+            entry.comment = Some(format!("entry number {i}"));
+            entry
+        });
+        ipset_add_many(SET_NAME, entries).expect("Failed to add entries");
+
+        // The dump spans many messages, none of which may be lost
+        let listed = ipset_list_entries(SET_NAME).expect("Failed to list entries");
+        assert_eq!(listed.len(), COUNT as usize);
+        assert_eq!(ipset_count(SET_NAME).unwrap(), COUNT);
+
+        // Streaming gives the same entries, one message at a time
+        let mut iterated = 0;
+        for (entry, listed) in ipset_list_iter(SET_NAME).unwrap().zip(&listed) {
+            assert_eq!(&entry.expect("Failed to iterate entries"), listed);
+            iterated += 1;
+        }
+        assert_eq!(iterated, COUNT);
+        assert!(matches!(
+            ipset_list_iter("lipsets_test_no_such_set"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+}
+
+// =====================
+// nftset tests
+// =====================
+
+mod nftset_tests {
+    use super::*;
+
+    // Each test uses unique table/set names to avoid race conditions when running in parallel
+
+    #[test]
+    fn test_nftset_add_test_del_ipv4() {
+        const TABLE_NAME: &str = "lnftsets_test_v4";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // Test that IP is not in set
+        let exists =
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to test IP");
+        assert!(!exists, "IP should not exist initially");
+
+        // Add IP to set
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to add IP");
+
+        // Test that IP is now in set
+        let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .expect("Failed to test IP after add");
+        assert!(exists, "IP should exist after add");
+
+        // Delete IP from set
+        nftset_del(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to delete IP");
+
+        // Test that IP is no longer in set
+        let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .expect("Failed to test IP after del");
+        assert!(!exists, "IP should not exist after delete");
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_add_test_del_ipv6() {
+        const TABLE_NAME: &str = "lnftsets_test_v6";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::Ipv6Addr,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set6");
+
+        let addr: IpAddr = "2001:db8::1".parse().unwrap();
+
+        // Test that IP is not in set
+        let exists =
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to test IPv6");
+        assert!(!exists, "IPv6 should not exist initially");
+
+        // Add IP to set
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to add IPv6");
+
+        // Test that IP is now in set
+        let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .expect("Failed to test IPv6 after add");
+        assert!(exists, "IPv6 should exist after add");
+
+        // Delete IP from set
+        nftset_del(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to delete IPv6");
+
+        // Test that IP is no longer in set
+        let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .expect("Failed to test IPv6 after del");
+        assert!(!exists, "IPv6 should not exist after delete");
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_ext_ack() {
+        const TABLE_NAME: &str = "lnftsets_test_ext_ack";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+
+        // The kernel points at the element it refused
+        let addr: IpAddr = "2001:db8::1".parse().unwrap();
+        match nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr) {
+            Err(RipsetError::KernelError { code, ext_ack }) => {
+                assert_eq!(code, 22); // EINVAL
+                assert!(ext_ack.offset.is_some());
+                assert!(ext_ack.attribute.is_some());
+            }
+            other => panic!("expected a kernel error, got {other:?}"),
+        }
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_monitor() {
+        const TABLE_NAME: &str = "lnftsets_test_monitor";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        let mut monitor = NftMonitor::new().expect("Failed to subscribe");
+
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "hosts",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "nets", &opts)
+            .expect("Failed to create interval set");
+        let addr: IpAddr = "10.0.4.1".parse().unwrap();
+        let net = IpEntry::with_cidr("10.4.0.0".parse().unwrap(), 16);
+        nftset_add(NftFamily::Inet, TABLE_NAME, "hosts", addr).expect("Failed to add IP");
+        nftset_add(NftFamily::Inet, TABLE_NAME, "nets", net.clone()).expect("Failed to add net");
+        nftset_del(NftFamily::Inet, TABLE_NAME, "hosts", addr).expect("Failed to delete IP");
+        nftset_delete_table(NftFamily::Inet, TABLE_NAME).expect("Failed to delete table");
+
+        // Other tests change nftables at the same time
+        let mut events = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !events
+            .iter()
+            .any(|e| matches!(e, NftEvent::TableDeleted { .. }))
+        {
+            let event = with_deadline(deadline, || monitor.next_event()).expect("No event");
+            let table = match &event {
+                NftEvent::TableCreated { table, .. }
+                | NftEvent::TableDeleted { table, .. }
+                | NftEvent::SetCreated { table, .. }
+                | NftEvent::SetDeleted { table, .. }
+                | NftEvent::ElementAdded { table, .. }
+                | NftEvent::ElementDeleted { table, .. } => table,
+                NftEvent::Committed { .. } => continue,
+            };
+            if table == TABLE_NAME {
+                events.push(event);
+            }
+        }
+
+        let family = NftFamily::Inet;
+        let table = TABLE_NAME.to_string();
+        let set = |name: &str| name.to_string();
+        assert_eq!(
+            events,
+            vec![
+                NftEvent::TableCreated {
+                    family,
+                    table: table.clone()
+                },
+                NftEvent::SetCreated {
+                    family,
+                    table: table.clone(),
+                    set: set("hosts")
+                },
+                NftEvent::SetCreated {
+                    family,
+                    table: table.clone(),
+                    set: set("nets")
+                },
+                NftEvent::ElementAdded {
+                    family,
+                    table: table.clone(),
+                    set: set("hosts"),
+                    entry: Entry::from(addr),
+                },
+                NftEvent::ElementAdded {
+                    family,
+                    table: table.clone(),
+                    set: set("nets"),
+                    entry: Entry::Ip(net),
+                },
+                NftEvent::ElementDeleted {
+                    family,
+                    table: table.clone(),
+                    set: set("hosts"),
+                    entry: Entry::from(addr),
+                },
+                NftEvent::SetDeleted {
+                    family,
+                    table: table.clone(),
+                    set: set("hosts")
+                },
+                NftEvent::SetDeleted {
+                    family,
+                    table: table.clone(),
+                    set: set("nets")
+                },
+                NftEvent::TableDeleted { family, table },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nftset_with_timeout() {
+        const TABLE_NAME: &str = "lnftsets_test_timeout";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            timeout: Some(300),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set with timeout");
+
+        let addr: IpAddr = "10.0.0.2".parse().unwrap();
+        let entry = IpEntry::with_timeout(addr, 60);
+
+        // Add IP with timeout
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, entry)
+            .expect("Failed to add IP with timeout");
+
+        // Test that IP is in set
+        let exists =
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to test IP");
+        assert!(exists, "IP should exist after add with timeout");
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_multiple_ips() {
+        const TABLE_NAME: &str = "lnftsets_test_multi";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let addrs: Vec<IpAddr> = vec![
+            "10.0.0.10".parse().unwrap(),
+            "10.0.0.11".parse().unwrap(),
+            "10.0.0.12".parse().unwrap(),
+        ];
+
+        // Add all IPs
+        for addr in &addrs {
+            nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, *addr).expect("Failed to add IP");
+        }
+
+        // Test all IPs exist
+        for addr in &addrs {
+            let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, *addr)
+                .expect("Failed to test IP");
+            assert!(exists, "IP {} should exist", addr);
+        }
+
+        // Delete all IPs
+        for addr in &addrs {
+            nftset_del(NftFamily::Inet, TABLE_NAME, SET_NAME, *addr).expect("Failed to delete IP");
+        }
+
+        // Test all IPs are gone
+        for addr in &addrs {
+            let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, *addr)
+                .expect("Failed to test IP");
+            assert!(!exists, "IP {} should not exist after delete", addr);
+        }
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_nonexistent_set() {
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let result = nftset_add(
+            NftFamily::Inet,
+            "nonexistent_table",
+            "nonexistent_set",
+            addr,
+        );
+        assert!(result.is_err(), "Should fail for nonexistent set");
+        let exists = nftset_exists(NftFamily::Inet, "nonexistent_table", "nonexistent_set")
+            .expect("Failed to check nftset");
+        assert!(!exists);
+    }
+
+    #[test]
+    fn test_nftset_list() {
+        const TABLE_NAME: &str = "lnftsets_test_list";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+        assert!(
+            nftset_exists(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to check nftset")
+        );
+        assert!(
+            !nftset_exists(NftFamily::Inet, TABLE_NAME, "missing_set")
+                .expect("Failed to check nftset")
+        );
+
+        // Initially empty
+        let ips =
+            nftset_list(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to list nftset");
+        assert!(ips.is_empty(), "Set should be empty initially");
+
+        // Add some IPs
+        let addr1: IpAddr = "10.0.0.1".parse().unwrap();
+        let addr2: IpAddr = "10.0.0.2".parse().unwrap();
+        let addr3: IpAddr = "10.0.0.3".parse().unwrap();
+
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1).expect("Failed to add IP");
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr2).expect("Failed to add IP");
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr3).expect("Failed to add IP");
+
+        // Verify with nftset_test
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1).unwrap(),
+            "addr1 should exist via test"
+        );
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr2).unwrap(),
+            "addr2 should exist via test"
+        );
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr3).unwrap(),
+            "addr3 should exist via test"
+        );
+
+        // List should now contain all three
+        let ips =
+            nftset_list(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to list nftset");
+        assert_eq!(ips.len(), 3, "Set should contain 3 IPs");
+        assert!(ips.contains(&addr1), "Set should contain addr1");
+        assert!(ips.contains(&addr2), "Set should contain addr2");
+        assert!(ips.contains(&addr3), "Set should contain addr3");
+
+        // Delete one and verify
+        nftset_del(NftFamily::Inet, TABLE_NAME, SET_NAME, addr2).expect("Failed to delete IP");
+        let ips =
+            nftset_list(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to list nftset");
+        assert_eq!(ips.len(), 2, "Set should contain 2 IPs after delete");
+        assert!(!ips.contains(&addr2), "Set should not contain addr2");
+
+        // Flush removes the rest in one go
+        nftset_flush(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to flush nftset");
+        let ips =
+            nftset_list(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to list nftset");
+        assert!(ips.is_empty(), "Set should be empty after flush");
+        assert!(matches!(
+            nftset_flush(NftFamily::Inet, TABLE_NAME, "missing_set"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_list_tables() {
+        const TABLE_NAME1: &str = "lnftsets_test_tables_1";
+        const TABLE_NAME2: &str = "lnftsets_test_tables_2";
+
+        // Setup - ensure clean state
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME1);
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME2);
+
+        // Create two tables
+        nftset_create_table(NftFamily::Inet, TABLE_NAME1).expect("Failed to create table1");
+        nftset_create_table(NftFamily::Inet, TABLE_NAME2).expect("Failed to create table2");
+
+        // List tables
+        let tables = nftset_list_tables(NftFamily::Inet).expect("Failed to list tables");
+
+        // Should contain both tables we created
+        assert!(
+            tables.contains(&TABLE_NAME1.to_string()),
+            "Should contain table1"
+        );
+        assert!(
+            tables.contains(&TABLE_NAME2.to_string()),
+            "Should contain table2"
+        );
+
+        // Delete one and verify
+        nftset_delete_table(NftFamily::Inet, TABLE_NAME1).expect("Failed to delete table1");
+        let tables =
+            nftset_list_tables(NftFamily::Inet).expect("Failed to list tables after delete");
+        assert!(
+            !tables.contains(&TABLE_NAME1.to_string()),
+            "Should not contain deleted table1"
+        );
+        assert!(
+            tables.contains(&TABLE_NAME2.to_string()),
+            "Should still contain table2"
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME2);
+    }
+
+    #[test]
+    fn test_nftset_list_tables_all() {
+        const TABLE_NAME: &str = "lnftsets_test_tables_all";
+
+        // Setup - ensure clean state
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        let _ = nftset_delete_table(NftFamily::Ip6, TABLE_NAME);
+
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create inet table");
+        nftset_create_table(NftFamily::Ip6, TABLE_NAME).expect("Failed to create ip6 table");
+
+        let tables = nftset_list_tables_all().expect("Failed to list tables");
+        assert!(tables.contains(&(NftFamily::Inet, TABLE_NAME.to_string())));
+        assert!(tables.contains(&(NftFamily::Ip6, TABLE_NAME.to_string())));
+        assert!(!tables.contains(&(NftFamily::Ip, TABLE_NAME.to_string())));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        let _ = nftset_delete_table(NftFamily::Ip6, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_list_iter() {
+        const TABLE_NAME: &str = "lnftsets_test_list_iter";
+
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "nets", &opts)
+            .expect("Failed to create interval set");
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "hosts",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+
+        // Adjacent ranges, a lone address, an open range and enough ranges
+        // to span many messages
+        let mut nets: Vec<Entry> = ["10.0.0.0/24", "10.0.1.0/24", "172.16.0.1", "240.0.0.0/4"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        nets.extend((0..5000u32).map(|i| {
+            let addr = std::net::Ipv4Addr::from(0x6400_0000 + (i << 4));
+            Entry::from(IpEntry::with_cidr(IpAddr::from(addr), 29))
+        }));
+        nftset_add_many(NftFamily::Inet, TABLE_NAME, "nets", nets.clone())
+            .expect("Failed to add nets");
+        let hosts = (0..5000u32).map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a01_0000 + i)));
+        nftset_add_many(NftFamily::Inet, TABLE_NAME, "hosts", hosts).expect("Failed to add hosts");
+
+        for setname in ["nets", "hosts"] {
+            let mut iterated: Vec<Entry> = nftset_list_iter(NftFamily::Inet, TABLE_NAME, setname)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .expect("Failed to iterate entries");
+            let mut listed = nftset_list_entries(NftFamily::Inet, TABLE_NAME, setname).unwrap();
+            assert_eq!(iterated.len(), 5000 + if setname == "nets" { 4 } else { 0 });
+            iterated.sort_by_key(Entry::to_string);
+            listed.sort_by_key(Entry::to_string);
+            assert_eq!(iterated, listed);
+        }
+        let mut nets: Vec<String> = nets.iter().map(Entry::to_string).collect();
+        let mut iterated: Vec<String> = nftset_list_iter(NftFamily::Inet, TABLE_NAME, "nets")
+            .unwrap()
+            .map(|entry| entry.unwrap().to_string())
+            .collect();
+        nets.sort();
+        iterated.sort();
+        assert_eq!(iterated, nets);
+
+        assert!(matches!(
+            nftset_list_iter(NftFamily::Inet, TABLE_NAME, "missing"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_netdev_family() {
+        const TABLE_NAME: &str = "lnftsets_test_netdev";
+        const SET_NAME: &str = "ingress_block";
+
+        let _ = nftset_delete_table(NftFamily::Netdev, TABLE_NAME);
+        nftset_create_table(NftFamily::Netdev, TABLE_NAME).expect("Failed to create netdev table");
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::Ipv4Addr,
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Netdev, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create netdev set");
+
+        let net = IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 8);
+        nftset_add(NftFamily::Netdev, TABLE_NAME, SET_NAME, net).expect("Failed to add network");
+        let addr: IpAddr = "10.1.2.3".parse().unwrap();
+        assert!(nftset_test(NftFamily::Netdev, TABLE_NAME, SET_NAME, addr).unwrap());
+        let tables = nftset_list_tables(NftFamily::Netdev).expect("Failed to list tables");
+        assert!(tables.contains(&TABLE_NAME.to_string()));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Netdev, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_bridge_family() {
+        const TABLE_NAME: &str = "lnftsets_test_bridge";
+        const SET_NAME: &str = "macs";
+
+        let _ = nftset_delete_table(NftFamily::Bridge, TABLE_NAME);
+        nftset_create_table(NftFamily::Bridge, TABLE_NAME).expect("Failed to create bridge table");
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::EtherAddr,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Bridge, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create bridge set");
+
+        let mac: MacAddr = "00:11:22:33:44:55".parse().unwrap();
+        nftset_add(NftFamily::Bridge, TABLE_NAME, SET_NAME, mac).expect("Failed to add MAC");
+        assert!(nftset_test(NftFamily::Bridge, TABLE_NAME, SET_NAME, mac).unwrap());
+        let entries = nftset_list_entries(NftFamily::Bridge, TABLE_NAME, SET_NAME)
+            .expect("Failed to list entries");
+        assert_eq!(entries, vec![Entry::Mac(mac)]);
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Bridge, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_list_sets() {
+        const TABLE_NAME: &str = "lnftsets_test_list_sets";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        assert!(
+            nftset_list_sets(NftFamily::Inet, TABLE_NAME)
+                .expect("Failed to list sets")
+                .is_empty()
+        );
+
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "set_v4", &opts)
+            .expect("Failed to create set");
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::Ipv6Addr,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "set_v6", &opts)
+            .expect("Failed to create set");
+
+        let mut sets = nftset_list_sets(NftFamily::Inet, TABLE_NAME).expect("Failed to list sets");
+        sets.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].name, "set_v4");
+        assert_eq!(sets[0].set_type, Some(NftSetType::Ipv4Addr));
+        assert_eq!(sets[0].key_len, 4);
+        assert_eq!(sets[1].name, "set_v6");
+        assert_eq!(sets[1].set_type, Some(NftSetType::Ipv6Addr));
+        assert_eq!(sets[1].key_len, 16);
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        assert!(nftset_list_sets(NftFamily::Inet, TABLE_NAME).is_err());
+    }
+
+    #[test]
+    fn test_nftset_info() {
+        const TABLE_NAME: &str = "lnftsets_test_info";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            timeout: Some(300),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+        for addr in ["10.0.0.1", "10.0.0.2"] {
+            let addr: IpAddr = addr.parse().unwrap();
+            nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to add IP");
+        }
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert_eq!(info.name, SET_NAME);
+        assert_eq!(info.set_type, Some(NftSetType::Ipv4Addr));
+        assert_eq!(info.key_len, 4);
+        assert!(info.with_timeout);
+        assert!(!info.interval);
+        assert!(!info.map);
+        assert!(!info.dynamic);
+        assert_eq!(info.timeout, Some(300));
+        assert_eq!(info.entries, 2);
+        assert_eq!(info.comment, None);
+
+        assert!(matches!(
+            nftset_info(NftFamily::Inet, TABLE_NAME, "missing_set"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_interval() {
+        const TABLE_NAME: &str = "lnftsets_test_interval";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let net: IpAddr = "10.1.0.0".parse().unwrap();
+        let single: IpAddr = "10.2.0.1".parse().unwrap();
+        let from: IpAddr = "10.3.0.5".parse().unwrap();
+        let to: IpAddr = "10.3.0.9".parse().unwrap();
+        nftset_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            IpEntry::with_cidr(net, 24),
+        )
+        .expect("Failed to add CIDR");
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, single).expect("Failed to add IP");
+        nftset_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            IpEntry::with_range(from, to),
+        )
+        .expect("Failed to add range");
+
+        let inside: IpAddr = "10.1.0.200".parse().unwrap();
+        let outside: IpAddr = "10.3.0.10".parse().unwrap();
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, inside).expect("Failed to test")
+        );
+        assert!(
+            !nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, outside).expect("Failed to test")
+        );
+
+        let entries = nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to list entries");
+        assert_eq!(
+            entries,
+            vec![
+                Entry::Ip(IpEntry::with_cidr(net, 24)),
+                Entry::Ip(IpEntry::new(single)),
+                Entry::Ip(IpEntry::with_range(from, to)),
+            ]
+        );
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert!(info.interval);
+        assert!(!info.auto_merge);
+        assert_eq!(info.entries, 3);
+
+        nftset_del(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            IpEntry::with_cidr(net, 24),
+        )
+        .expect("Failed to delete CIDR");
+        assert!(
+            !nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, inside).expect("Failed to test")
+        );
+
+        // CIDR elements need an interval set
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "plain_set",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+        assert!(matches!(
+            nftset_add(
+                NftFamily::Inet,
+                TABLE_NAME,
+                "plain_set",
+                IpEntry::with_cidr(net, 24)
+            ),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_ether_addr() {
+        const TABLE_NAME: &str = "lnftsets_test_ether";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::EtherAddr,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let mac: MacAddr = "00:11:22:33:44:55".parse().unwrap();
+        let other: MacAddr = "00:11:22:33:44:56".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, mac).expect("Failed to add MAC");
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, mac).expect("Failed to test"));
+        assert!(
+            !nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, other).expect("Failed to test")
+        );
+
+        let entries = nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to list entries");
+        assert_eq!(entries, vec![Entry::Mac(mac)]);
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert_eq!(info.set_type, Some(NftSetType::EtherAddr));
+        assert_eq!(info.key_len, 6);
+
+        nftset_del(NftFamily::Inet, TABLE_NAME, SET_NAME, mac).expect("Failed to delete MAC");
+        assert!(!nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, mac).expect("Failed to test"));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_mark_and_ifname() {
+        const TABLE_NAME: &str = "lnftsets_test_meta";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        for (set_name, set_type) in [("marks", NftSetType::Mark), ("ifaces", NftSetType::Ifname)] {
+            let opts = NftSetCreateOptions {
+                set_type,
+                ..Default::default()
+            };
+            nftset_create_set(NftFamily::Inet, TABLE_NAME, set_name, &opts)
+                .expect("Failed to create set");
+        }
+
+        nftset_add(NftFamily::Inet, TABLE_NAME, "marks", Entry::Mark(0x10))
+            .expect("Failed to add mark");
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, "marks", Entry::Mark(0x10))
+                .expect("Failed to test")
+        );
+        assert!(
+            !nftset_test(NftFamily::Inet, TABLE_NAME, "marks", Entry::Mark(0x11))
+                .expect("Failed to test")
+        );
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "marks")
+                .expect("Failed to list entries"),
+            vec![Entry::Mark(0x10)]
+        );
+
+        let eth0 = Entry::Iface("eth0".to_string());
+        nftset_add(NftFamily::Inet, TABLE_NAME, "ifaces", eth0.clone())
+            .expect("Failed to add iface");
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, "ifaces", eth0.clone())
+                .expect("Failed to test")
+        );
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "ifaces")
+                .expect("Failed to list entries"),
+            vec![eth0.clone()]
+        );
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, "ifaces").expect("Failed to query set info");
+        assert_eq!(info.set_type, Some(NftSetType::Ifname));
+        assert_eq!(info.key_len, 16);
+
+        nftset_del(NftFamily::Inet, TABLE_NAME, "ifaces", eth0.clone())
+            .expect("Failed to delete iface");
+        assert!(!nftset_test(NftFamily::Inet, TABLE_NAME, "ifaces", eth0).expect("Failed to test"));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_concat() {
+        const TABLE_NAME: &str = "lnftsets_test_concat";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let set_type = NftSetType::Concat(vec![
+            NftSetType::Ipv4Addr,
+            NftSetType::Ipv4Addr,
+            NftSetType::InetService,
+        ]);
+        let opts = NftSetCreateOptions {
+            set_type: set_type.clone(),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let src: IpAddr = "10.0.0.1".parse().unwrap();
+        let dst: IpAddr = "10.0.0.2".parse().unwrap();
+        let entry = Entry::Concat(vec![src.into(), dst.into(), 22u16.into()]);
+        let other = Entry::Concat(vec![src.into(), dst.into(), 23u16.into()]);
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, entry.clone())
+            .expect("Failed to add element");
+        assert!(
+            nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, entry.clone())
+                .expect("Failed to test")
+        );
+        assert!(
+            !nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, other).expect("Failed to test")
+        );
+
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET_NAME)
+                .expect("Failed to list entries"),
+            vec![entry.clone()]
+        );
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert_eq!(info.set_type, Some(set_type));
+        assert_eq!(info.key_len, 12);
+
+        nftset_del(NftFamily::Inet, TABLE_NAME, SET_NAME, entry.clone())
+            .expect("Failed to delete element");
+        assert!(
+            !nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, entry).expect("Failed to test")
+        );
+
+        // A concatenation needs at least two fields
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::Concat(vec![NftSetType::Ipv4Addr]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            nftset_create_set(NftFamily::Inet, TABLE_NAME, "bad_set", &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_explicit_ranges() {
+        const TABLE_NAME: &str = "lnftsets_test_ranges";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let ports_opts = NftSetCreateOptions {
+            set_type: NftSetType::InetService,
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "ports", &ports_opts)
+            .expect("Failed to create port set");
+        let concat_opts = NftSetCreateOptions {
+            set_type: NftSetType::Concat(vec![NftSetType::Ipv4Addr, NftSetType::InetService]),
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "svc", &concat_opts)
+            .expect("Failed to create concatenated set");
+
+        // Port ranges in a plain interval set
+        let ports = Entry::Port {
+            port: 1000,
+            port_to: Some(2000),
+        };
+        nftset_add(NftFamily::Inet, TABLE_NAME, "ports", ports.clone())
+            .expect("Failed to add port range");
+        nftset_add(NftFamily::Inet, TABLE_NAME, "ports", 22u16).expect("Failed to add port");
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "ports")
+                .expect("Failed to list entries"),
+            vec![Entry::from(22u16), ports.clone()]
+        );
+        nftset_del(NftFamily::Inet, TABLE_NAME, "ports", ports.clone())
+            .expect("Failed to delete port range");
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "ports")
+                .expect("Failed to list entries"),
+            vec![Entry::from(22u16)]
+        );
+
+        // Address and port ranges in a concatenation use key and key end
+        let from: IpAddr = "10.0.0.5".parse().unwrap();
+        let to: IpAddr = "10.0.0.9".parse().unwrap();
+        let range = Entry::Concat(vec![IpEntry::with_range(from, to).into(), ports.clone()]);
+        let net = Entry::Concat(vec![
+            IpEntry::with_cidr("192.168.0.0".parse().unwrap(), 24).into(),
+            443u16.into(),
+        ]);
+        nftset_add(NftFamily::Inet, TABLE_NAME, "svc", range.clone())
+            .expect("Failed to add concatenated range");
+        nftset_add(NftFamily::Inet, TABLE_NAME, "svc", net.clone())
+            .expect("Failed to add concatenated network");
+        let entries = nftset_list_entries(NftFamily::Inet, TABLE_NAME, "svc")
+            .expect("Failed to list entries");
+        assert_eq!(entries.len(), 2);
+        assert!(entries.contains(&range) && entries.contains(&net));
+
+        nftset_del(NftFamily::Inet, TABLE_NAME, "svc", range)
+            .expect("Failed to delete concatenated range");
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "svc")
+                .expect("Failed to list entries"),
+            vec![net]
+        );
+
+        // Ranges need an interval set
+        let plain_opts = NftSetCreateOptions {
+            set_type: NftSetType::InetService,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "plain", &plain_opts)
+            .expect("Failed to create port set");
+        assert!(matches!(
+            nftset_add(NftFamily::Inet, TABLE_NAME, "plain", ports),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_map() {
+        const TABLE_NAME: &str = "lnftsets_test_map";
+        const MAP_NAME: &str = "test_map";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            data_type: Some(NftSetType::Mark),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, MAP_NAME, &opts)
+            .expect("Failed to create map");
+
+        let addr1: IpAddr = "10.0.0.1".parse().unwrap();
+        let addr2: IpAddr = "10.0.0.2".parse().unwrap();
+        nftset_map_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            MAP_NAME,
+            addr1,
+            Entry::Mark(0x10),
+        )
+        .expect("Failed to add element");
+        nftset_map_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            MAP_NAME,
+            addr2,
+            Entry::Mark(0x20),
+        )
+        .expect("Failed to add element");
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, MAP_NAME, addr1).expect("Failed to test"));
+
+        let mut elements =
+            nftset_map_list(NftFamily::Inet, TABLE_NAME, MAP_NAME).expect("Failed to list map");
+        elements.sort_by_key(|(_, value)| format!("{value:?}"));
+        assert_eq!(
+            elements,
+            vec![
+                (Entry::from(addr1), Entry::Mark(0x10)),
+                (Entry::from(addr2), Entry::Mark(0x20)),
+            ]
+        );
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, MAP_NAME).expect("Failed to query map info");
+        assert!(info.map);
+        assert_eq!(info.data_type, Some(NftSetType::Mark));
+
+        nftset_del(NftFamily::Inet, TABLE_NAME, MAP_NAME, addr1).expect("Failed to delete element");
+        assert_eq!(
+            nftset_map_list(NftFamily::Inet, TABLE_NAME, MAP_NAME).expect("Failed to list map"),
+            vec![(Entry::from(addr2), Entry::Mark(0x20))]
+        );
+
+        // Plain sets are not maps
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "plain_set",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+        assert!(matches!(
+            nftset_map_list(NftFamily::Inet, TABLE_NAME, "plain_set"),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_map_conflict() {
+        const TABLE_NAME: &str = "lnftsets_test_map_conflict";
+        const MAP_NAME: &str = "marks";
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            data_type: Some(NftSetType::Mark),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, MAP_NAME, &opts)
+            .expect("Failed to create map");
+        let add = |value, on_conflict| {
+            nftset_map_add_with(
+                NftFamily::Inet,
+                TABLE_NAME,
+                MAP_NAME,
+                addr,
+                Entry::Mark(value),
+                on_conflict,
+            )
+        };
+        let list =
+            || nftset_map_list(NftFamily::Inet, TABLE_NAME, MAP_NAME).expect("Failed to list");
+
+        // Every mode adds a missing key
+        add(0x10, NftMapConflict::Replace).expect("Failed to add element");
+        assert_eq!(list(), vec![(Entry::from(addr), Entry::Mark(0x10))]);
+
+        assert!(matches!(
+            add(0x20, NftMapConflict::Fail),
+            Err(RipsetError::EntryExists)
+        ));
+        add(0x20, NftMapConflict::Ignore).expect("Failed to ignore existing element");
+        assert_eq!(list(), vec![(Entry::from(addr), Entry::Mark(0x10))]);
+
+        add(0x20, NftMapConflict::Replace).expect("Failed to replace element");
+        assert_eq!(list(), vec![(Entry::from(addr), Entry::Mark(0x20))]);
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_add_many() {
+        const TABLE_NAME: &str = "lnftsets_test_add_many";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let addrs: Vec<IpAddr> = (0..100_000u32)
+            .map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i)))
+            .collect();
+        nftset_add_many(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs.clone())
+            .expect("Failed to add entries");
+        assert_eq!(
+            nftset_list(NftFamily::Inet, TABLE_NAME, SET_NAME)
+                .unwrap()
+                .len(),
+            100_000
+        );
+
+        // Existing entries are left alone
+        nftset_add_many(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[..10].to_vec())
+            .expect("Failed to re-add entries");
+        assert!(matches!(
+            nftset_add_many(NftFamily::Inet, TABLE_NAME, "missing", addrs[..10].to_vec()),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_test_many() {
+        const TABLE_NAME: &str = "lnftsets_test_test_many";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        // Enough addresses for several sends, every third one in the set
+        let addrs: Vec<IpAddr> = (0..1000u32)
+            .map(|i| IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + i)))
+            .collect();
+        nftset_add_many(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            addrs.iter().step_by(3).copied(),
+        )
+        .expect("Failed to add");
+
+        let found = nftset_test_many(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs.iter().copied())
+            .expect("Failed to test");
+        let expected: Vec<bool> = (0..addrs.len()).map(|i| i % 3 == 0).collect();
+        assert_eq!(found, expected);
+
+        // The session keeps working after a pipelined test
+        let session = NftSession::new().expect("Failed to open session");
+        assert_eq!(
+            session
+                .test_many(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[..4].to_vec())
+                .unwrap(),
+            vec![true, false, false, true]
+        );
+        session
+            .del(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[0])
+            .expect("Failed to delete IP");
+        assert!(
+            !session
+                .test(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[0])
+                .unwrap()
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_session() {
+        const TABLE_NAME: &str = "lnftsets_test_session";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions::default();
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let session = NftSession::new().expect("Failed to open session");
+        let addrs: Vec<IpAddr> = (1..=50)
+            .map(|i| format!("10.0.1.{}", i).parse().unwrap())
+            .collect();
+        for addr in &addrs {
+            session
+                .add(NftFamily::Inet, TABLE_NAME, SET_NAME, *addr)
+                .expect("Failed to add IP");
+        }
+        assert_eq!(
+            nftset_list(NftFamily::Inet, TABLE_NAME, SET_NAME)
+                .unwrap()
+                .len(),
+            50
+        );
+
+        // Errors don't disturb the following requests
+        assert!(matches!(
+            session.add(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[0]),
+            Err(RipsetError::EntryExists)
+        ));
+        let mut tx = NftTransaction::new();
+        tx.add(NftFamily::Inet, TABLE_NAME, "missing", addrs[0])
+            .unwrap();
+        tx.add(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[0])
+            .unwrap();
+        assert!(session.commit(tx).is_err());
+        session
+            .del(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[0])
+            .expect("Failed to delete IP");
+        assert!(
+            !session
+                .test(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[0])
+                .unwrap()
+        );
+        assert!(
+            session
+                .test(NftFamily::Inet, TABLE_NAME, SET_NAME, addrs[1])
+                .unwrap()
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_verdict_map() {
+        const TABLE_NAME: &str = "lnftsets_test_vmap";
+        const MAP_NAME: &str = "test_vmap";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            data_type: Some(NftSetType::Verdict),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, MAP_NAME, &opts)
+            .expect("Failed to create map");
+
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        nftset_map_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            MAP_NAME,
+            addr,
+            NftVerdict::Drop,
+        )
+        .expect("Failed to add element");
+        assert_eq!(
+            nftset_map_list(NftFamily::Inet, TABLE_NAME, MAP_NAME).expect("Failed to list map"),
+            vec![(Entry::from(addr), Entry::Verdict(NftVerdict::Drop))]
+        );
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, MAP_NAME).expect("Failed to query map info");
+        assert_eq!(info.data_type, Some(NftSetType::Verdict));
+
+        // Verdicts are values, not keys
+        let opts = NftSetCreateOptions {
+            set_type: NftSetType::Verdict,
+            ..Default::default()
+        };
+        assert!(matches!(
+            nftset_create_set(NftFamily::Inet, TABLE_NAME, "bad_set", &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
+        assert!(matches!(
+            nftset_add(NftFamily::Inet, TABLE_NAME, MAP_NAME, NftVerdict::Accept),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_auto_merge() {
+        const TABLE_NAME: &str = "lnftsets_test_merge";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            auto_merge: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let net: IpAddr = "10.0.0.0".parse().unwrap();
+        let upper: IpAddr = "10.0.0.128".parse().unwrap();
+        let inside: IpAddr = "10.0.0.5".parse().unwrap();
+        for entry in [
+            IpEntry::with_cidr(net, 25),
+            IpEntry::with_cidr(upper, 25),
+            IpEntry::new(inside),
+        ] {
+            nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, entry)
+                .expect("Failed to add element");
+        }
+
+        // Adjacent halves are merged and contained addresses are ignored
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET_NAME)
+                .expect("Failed to list entries"),
+            vec![Entry::Ip(IpEntry::with_cidr(net, 24))]
+        );
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert!(info.auto_merge);
+        assert_eq!(info.entries, 1);
+
+        // Without the interval flag auto-merge is rejected
+        let opts = NftSetCreateOptions {
+            auto_merge: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            nftset_create_set(NftFamily::Inet, TABLE_NAME, "bad_set", &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_policy_and_size() {
+        const TABLE_NAME: &str = "lnftsets_test_size";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            policy: Some(NftSetPolicy::Memory),
+            size: Some(2),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert_eq!(info.policy, Some(NftSetPolicy::Memory));
+        assert_eq!(info.size, Some(2));
+
+        // The kernel refuses elements beyond the size
+        for addr in ["10.0.0.1", "10.0.0.2"] {
+            let addr: IpAddr = addr.parse().unwrap();
+            nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to add IP");
+        }
+        let addr: IpAddr = "10.0.0.3".parse().unwrap();
+        assert!(matches!(
+            nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr),
+            Err(RipsetError::SetFull(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_extra_attrs() {
+        const TABLE_NAME: &str = "lnftsets_test_extra_attrs";
+        const SET_NAME: &str = "test_set";
+        const NFTA_SET_TIMEOUT: u16 = 11;
+        const NFT_SET_TIMEOUT: u32 = 0x10;
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        // Default element timeout in milliseconds, as a big-endian u64
+        let opts = NftSetCreateOptions {
+            flags: Some(NFT_SET_TIMEOUT),
+            extra_attrs: vec![(NFTA_SET_TIMEOUT, 30_000u64.to_be_bytes().to_vec())],
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set with extra attributes");
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert_eq!(info.timeout, Some(30));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_gc_interval() {
+        const TABLE_NAME: &str = "lnftsets_test_gc";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            gc_interval: Some(5),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert!(info.with_timeout);
+        assert_eq!(info.gc_interval, Some(5));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_dynamic_and_constant() {
+        const TABLE_NAME: &str = "lnftsets_test_flags";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+
+        let opts = NftSetCreateOptions {
+            dynamic: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "dynamic_set", &opts)
+            .expect("Failed to create dynamic set");
+        let info = nftset_info(NftFamily::Inet, TABLE_NAME, "dynamic_set")
+            .expect("Failed to query set info");
+        assert!(info.dynamic);
+        assert!(!info.constant);
+
+        let opts = NftSetCreateOptions {
+            constant: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "constant_set", &opts)
+            .expect("Failed to create constant set");
+        let info = nftset_info(NftFamily::Inet, TABLE_NAME, "constant_set")
+            .expect("Failed to query set info");
+        assert!(info.constant);
+        assert!(!info.dynamic);
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_comment() {
+        const TABLE_NAME: &str = "lnftsets_test_comment";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            auto_merge: true,
+            comment: Some("blocked networks".to_string()),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert_eq!(info.comment.as_deref(), Some("blocked networks"));
+        assert!(info.auto_merge);
+
+        // Comments must fit the set userdata
+        let opts = NftSetCreateOptions {
+            comment: Some("x".repeat(300)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            nftset_create_set(NftFamily::Inet, TABLE_NAME, "long_comment", &opts),
+            Err(RipsetError::InvalidOption(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_list_elements() {
+        const TABLE_NAME: &str = "lnftsets_test_elements";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            timeout: Some(600),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let addr1: IpAddr = "10.0.0.1".parse().unwrap();
+        let addr2: IpAddr = "10.0.0.2".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1).expect("Failed to add IP");
+        let mut entry = IpEntry::new(addr2);
+        entry.timeout = Some(60);
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, entry).expect("Failed to add IP");
+
+        let elements = nftset_list_elements(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to list elements");
+        assert_eq!(elements.len(), 2);
+        for element in elements {
+            let Entry::Ip(ip) = &element.entry else {
+                panic!("Unexpected entry: {:?}", element.entry);
+            };
+            assert_eq!(element.packets, None);
+            let expected = if ip.addr == addr1 { 600 } else { 60 };
+            assert_eq!(element.timeout, Some(expected));
+            let expiration = element.expiration.expect("Missing expiration");
+            assert!(expiration <= expected && expiration + 10 >= expected);
+        }
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_counters() {
+        const TABLE_NAME: &str = "lnftsets_test_counters";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            counters: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let info =
+            nftset_info(NftFamily::Inet, TABLE_NAME, SET_NAME).expect("Failed to query set info");
+        assert!(info.counters);
+
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to add IP");
+
+        let elements = nftset_list_elements(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to list elements");
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].packets, Some(0));
+        assert_eq!(elements[0].bytes, Some(0));
+
+        let entries = nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to list entries");
+        let [Entry::Ip(entry)] = &entries[..] else {
+            panic!("Unexpected entries: {entries:?}");
+        };
+        assert_eq!(entry.addr, addr);
+        assert_eq!((entry.packets, entry.bytes), (Some(0), Some(0)));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_reset_counters() {
+        const TABLE_NAME: &str = "lnftsets_test_reset_counters";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            counters: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to add IP");
+
+        let elements = nftset_reset_counters(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to reset counters");
+        assert!(matches!(
+            &elements[..],
+            [element] if matches!(&element.entry, Entry::Ip(ip) if ip.addr == addr)
+        ));
+        assert_eq!((elements[0].packets, elements[0].bytes), (Some(0), Some(0)));
+
+        nftset_reset_entry_counters(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .expect("Failed to reset entry counters");
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        assert!(matches!(
+            nftset_reset_entry_counters(NftFamily::Inet, TABLE_NAME, SET_NAME, other),
+            Err(RipsetError::EntryNotFound)
+        ));
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr).expect("Failed to test"));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_add_aggregated() {
+        const TABLE_NAME: &str = "lnftsets_test_aggregate";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        // Overlapping networks, which an interval set refuses as they are
+        let nets = [("10.0.0.0", 24), ("10.0.0.128", 25), ("10.0.1.0", 24)]
+            .map(|(addr, cidr)| IpEntry::with_cidr(addr.parse().unwrap(), cidr));
+        nftset_add_many(NftFamily::Inet, TABLE_NAME, SET_NAME, aggregate(nets))
+            .expect("Failed to add aggregated networks");
+
+        let entries = nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to list entries");
+        assert_eq!(
+            entries,
+            [Entry::from(IpEntry::with_cidr(
+                "10.0.0.0".parse().unwrap(),
+                23
+            ))]
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_test_interval() {
+        const TABLE_NAME: &str = "lnftsets_test_lookup";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .expect("Failed to create set");
+
+        let net: IpAddr = "10.0.0.0".parse().unwrap();
+        nftset_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            IpEntry::with_cidr(net, 24),
+        )
+        .expect("Failed to add CIDR");
+
+        for (addr, expected) in [
+            ("10.0.0.0", true),
+            ("10.0.0.128", true),
+            ("10.0.0.255", true),
+            ("10.0.1.0", false),
+            ("9.255.255.255", false),
+        ] {
+            let addr: IpAddr = addr.parse().unwrap();
+            let exists = nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+                .expect("Failed to test IP");
+            assert_eq!(exists, expected, "{addr}");
+        }
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_transaction() {
+        const TABLE_NAME: &str = "lnftsets_test_tx";
+        const SET_NAME: &str = "test_set";
+
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+
+        let addr1: IpAddr = "10.0.0.1".parse().unwrap();
+        let addr2: IpAddr = "10.0.0.2".parse().unwrap();
+        let addr3: IpAddr = "10.0.0.3".parse().unwrap();
+
+        // Table, set and elements are created together
+        let mut tx = NftTransaction::new();
+        tx.create_table(NftFamily::Inet, TABLE_NAME).unwrap();
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        tx.create_set(NftFamily::Inet, TABLE_NAME, SET_NAME, &opts)
+            .unwrap();
+        tx.add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1)
+            .unwrap();
+        tx.add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            IpEntry::with_cidr(addr2, 31),
+        )
+        .unwrap();
+        assert_eq!(tx.len(), 4);
+        tx.commit().expect("Failed to commit transaction");
+
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1).unwrap());
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr3).unwrap());
+
+        // A failing change rolls back the whole transaction
+        let mut tx = NftTransaction::new();
+        tx.del(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1)
+            .unwrap();
+        tx.del(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            "10.9.9.9".parse::<IpAddr>().unwrap(),
+        )
+        .unwrap();
+        assert!(matches!(tx.commit(), Err(RipsetError::EntryNotFound)));
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1).unwrap());
+
+        // Errors are reported for the change that failed
+        let mut tx = NftTransaction::new();
+        tx.flush_set(NftFamily::Inet, TABLE_NAME, SET_NAME).unwrap();
+        tx.delete_set(NftFamily::Inet, TABLE_NAME, "missing")
+            .unwrap();
+        assert!(matches!(tx.commit(), Err(RipsetError::SetNotFound(name)) if name == "missing"));
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr1).unwrap());
+
+        let mut tx = NftTransaction::new();
+        tx.flush_set(NftFamily::Inet, TABLE_NAME, SET_NAME).unwrap();
+        tx.delete_table(NftFamily::Inet, TABLE_NAME).unwrap();
+        tx.commit().expect("Failed to commit transaction");
+        assert!(!nftset_exists(NftFamily::Inet, TABLE_NAME, SET_NAME).unwrap());
+
+        // Nothing to do
+        assert!(NftTransaction::new().commit().is_ok());
+    }
+
+    #[test]
+    fn test_nftset_swap() {
+        const TABLE_NAME: &str = "lnftsets_test_swap";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        for name in ["live", "next"] {
+            nftset_create_set(NftFamily::Inet, TABLE_NAME, name, &opts)
+                .expect("Failed to create set");
+        }
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "plain",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+
+        // Enough elements to take several messages
+        let live: IpAddr = "192.168.0.1".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, "live", live).expect("Failed to add IP");
+        let mut tx = NftTransaction::new();
+        for i in 0..300u32 {
+            let addr = IpAddr::from((0x0a00_0000 + i * 4).to_be_bytes());
+            tx.add(NftFamily::Inet, TABLE_NAME, "next", addr).unwrap();
+        }
+        tx.commit().expect("Failed to fill set");
+
+        nftset_swap(NftFamily::Inet, TABLE_NAME, "live", "next").expect("Failed to swap sets");
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "live")
+                .unwrap()
+                .len(),
+            300
+        );
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "next").unwrap(),
+            vec![Entry::from(live)]
+        );
+        assert!(!nftset_test(NftFamily::Inet, TABLE_NAME, "live", live).unwrap());
+        let addr: IpAddr = "10.0.4.172".parse().unwrap();
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, "live", addr).unwrap());
+
+        assert!(matches!(
+            nftset_swap(NftFamily::Inet, TABLE_NAME, "live", "plain"),
+            Err(RipsetError::TypeMismatch(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_ensure() {
+        const TABLE_NAME: &str = "lnftsets_test_ensure";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_ensure_table(NftFamily::Inet, TABLE_NAME).expect("Failed to ensure table");
+        nftset_ensure_table(NftFamily::Inet, TABLE_NAME).expect("Failed to ensure table");
+
+        let opts = NftSetCreateOptions {
+            timeout: Some(300),
+            ..Default::default()
+        };
+        nftset_ensure_set(NftFamily::Inet, TABLE_NAME, "set1", &opts)
+            .expect("Failed to ensure set");
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(
+            nftset_ensure_entry(NftFamily::Inet, TABLE_NAME, "set1", addr)
+                .expect("Failed to ensure entry")
+        );
+        assert!(
+            !nftset_ensure_entry(NftFamily::Inet, TABLE_NAME, "set1", addr)
+                .expect("Failed to ensure entry")
+        );
+
+        // The existing set and its elements are kept
+        nftset_ensure_set(NftFamily::Inet, TABLE_NAME, "set1", &opts)
+            .expect("Failed to ensure set");
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, "set1", addr).expect("Failed to test"));
+
+        // Differing options are reported
+        let other = NftSetCreateOptions {
+            set_type: NftSetType::Ipv6Addr,
+            ..opts.clone()
+        };
+        assert!(matches!(
+            nftset_ensure_set(NftFamily::Inet, TABLE_NAME, "set1", &other),
+            Err(RipsetError::SetMismatch(name, property)) if name == "set1" && property == "type"
+        ));
+        let other = NftSetCreateOptions {
+            interval: true,
+            ..opts
+        };
+        assert!(matches!(
+            nftset_ensure_set(NftFamily::Inet, TABLE_NAME, "set1", &other),
+            Err(RipsetError::SetMismatch(_, property)) if property == "interval flag"
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_rename() {
+        const TABLE_NAME: &str = "lnftsets_test_rename";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            auto_merge: true,
+            timeout: Some(600),
+            size: Some(1024),
+            comment: Some("renamed".to_string()),
+            counters: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "old", &opts).expect("Failed to create set");
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "taken",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+        let entry = IpEntry::with_cidr("10.1.0.0".parse().unwrap(), 16);
+        nftset_add(NftFamily::Inet, TABLE_NAME, "old", entry.clone()).expect("Failed to add range");
+
+        nftset_rename(NftFamily::Inet, TABLE_NAME, "old", "new").expect("Failed to rename set");
+        assert!(!nftset_exists(NftFamily::Inet, TABLE_NAME, "old").unwrap());
+        let info = nftset_info(NftFamily::Inet, TABLE_NAME, "new").unwrap();
+        assert_eq!(info.name, "new");
+        assert!(info.interval && info.auto_merge && info.counters);
+        assert_eq!(info.timeout, Some(600));
+        assert_eq!(info.size, Some(1024));
+        assert_eq!(info.comment.as_deref(), Some("renamed"));
+        assert_eq!(info.entries, 1);
+        let addr: IpAddr = "10.1.2.3".parse().unwrap();
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, "new", addr).unwrap());
+
+        // An existing set is never reused, and nothing changes on failure
+        assert!(matches!(
+            nftset_rename(NftFamily::Inet, TABLE_NAME, "new", "taken"),
+            Err(RipsetError::EntryExists)
+        ));
+        assert!(nftset_exists(NftFamily::Inet, TABLE_NAME, "new").unwrap());
+        assert!(!nftset_test(NftFamily::Inet, TABLE_NAME, "taken", addr).unwrap());
+
+        assert!(matches!(
+            nftset_rename(NftFamily::Inet, TABLE_NAME, "missing", "other"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_flush_table() {
+        const TABLE_NAME: &str = "lnftsets_test_flush_table";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        for name in ["first", "second"] {
+            nftset_create_set(
+                NftFamily::Inet,
+                TABLE_NAME,
+                name,
+                &NftSetCreateOptions::default(),
+            )
+            .expect("Failed to create set");
+            nftset_add(NftFamily::Inet, TABLE_NAME, name, addr).expect("Failed to add IP");
+        }
+
+        nftset_flush_table(NftFamily::Inet, TABLE_NAME).expect("Failed to flush table");
+        for name in ["first", "second"] {
+            assert!(nftset_exists(NftFamily::Inet, TABLE_NAME, name).unwrap());
+            assert!(
+                nftset_list(NftFamily::Inet, TABLE_NAME, name)
+                    .unwrap()
+                    .is_empty()
+            );
+        }
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        assert!(matches!(
+            nftset_flush_table(NftFamily::Inet, TABLE_NAME),
+            Err(RipsetError::SetNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_nftset_del_many() {
+        const TABLE_NAME: &str = "lnftsets_test_del_many";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "addrs",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "nets", &opts)
+            .expect("Failed to create set");
+
+        // Enough elements to take several messages
+        let addrs: Vec<IpAddr> = (0..300u32)
+            .map(|i| IpAddr::from((0x0a00_0000 + i).to_be_bytes()))
+            .collect();
+        let mut tx = NftTransaction::new();
+        for &addr in &addrs {
+            tx.add(NftFamily::Inet, TABLE_NAME, "addrs", addr).unwrap();
+        }
+        tx.commit().expect("Failed to fill set");
+
+        let failures = nftset_del_many(NftFamily::Inet, TABLE_NAME, "addrs", addrs[2..].to_vec())
+            .expect("Failed to delete IPs");
+        assert!(failures.is_empty());
+        let mut remaining = nftset_list(NftFamily::Inet, TABLE_NAME, "addrs").unwrap();
+        remaining.sort();
+        assert_eq!(remaining, vec![addrs[0], addrs[1]]);
+
+        // Missing elements are reported and the others deleted
+        let missing: IpAddr = "192.168.0.1".parse().unwrap();
+        let failures = nftset_del_many(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "addrs",
+            [missing, addrs[0], addrs[2]],
+        )
+        .expect("Failed to delete IPs");
+        assert!(matches!(
+            failures.as_slice(),
+            [
+                (0, RipsetError::EntryNotFound),
+                (2, RipsetError::EntryNotFound)
+            ]
+        ));
+        assert_eq!(
+            nftset_list(NftFamily::Inet, TABLE_NAME, "addrs").unwrap(),
+            vec![addrs[1]]
+        );
+
+        let nets = [
+            IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 16),
+            IpEntry::with_cidr("10.2.0.0".parse().unwrap(), 16),
+        ];
+        for net in &nets {
+            nftset_add(NftFamily::Inet, TABLE_NAME, "nets", net.clone())
+                .expect("Failed to add range");
+        }
+        let failures = nftset_del_many(NftFamily::Inet, TABLE_NAME, "nets", nets)
+            .expect("Failed to delete ranges");
+        assert!(failures.is_empty());
+        assert!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "nets")
+                .unwrap()
+                .is_empty()
+        );
+
+        assert!(matches!(
+            nftset_del_many(NftFamily::Inet, TABLE_NAME, "missing", [missing]),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nft_ensure_drop_rule() {
+        const TABLE_NAME: &str = "lnftsets_test_drop_rule";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let v6 = NftSetCreateOptions {
+            set_type: NftSetType::Ipv6Addr,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "banned", &Default::default())
+            .expect("Failed to create set");
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "banned6", &v6)
+            .expect("Failed to create set");
+
+        // Creates the chain, then finds the rule already there
+        for _ in 0..2 {
+            nft_ensure_drop_rule(NftFamily::Inet, TABLE_NAME, "block", "banned")
+                .expect("Failed to add drop rule");
+        }
+        nft_ensure_drop_rule(NftFamily::Inet, TABLE_NAME, "block", "banned6")
+            .expect("Failed to add IPv6 drop rule");
+
+        // The rules keep the sets in use until the table is flushed
+        let refs = nftset_references(NftFamily::Inet, TABLE_NAME, "banned")
+            .expect("Failed to list references");
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs[0].chain, "block");
+        assert_eq!(refs[0].comment.as_deref(), Some("ripset drop @banned"));
+        assert!(matches!(
+            nftset_delete_set(NftFamily::Inet, TABLE_NAME, "banned"),
+            Err(RipsetError::SetInUse(name, by)) if name == "banned" && by == refs[0].to_string()
+        ));
+        nftset_flush_table(NftFamily::Inet, TABLE_NAME).expect("Failed to flush table");
+        assert!(
+            nftset_references(NftFamily::Inet, TABLE_NAME, "banned6")
+                .expect("Failed to list references")
+                .is_empty()
+        );
+        nftset_delete_set(NftFamily::Inet, TABLE_NAME, "banned").expect("Failed to delete set");
+
+        assert!(matches!(
+            nft_ensure_drop_rule(NftFamily::Inet, TABLE_NAME, "block", "banned"),
+            Err(RipsetError::SetNotFound(_))
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nftset_table_owner() {
+        const TABLE_NAME: &str = "lnftsets_test_owner";
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        let table: NftOwnedTable = nftset_create_table_owned(NftFamily::Inet, TABLE_NAME)
+            .expect("Failed to create owned table");
+        let owner = table.owner().expect("Failed to get owner");
+        assert_eq!(
+            nftset_table_owner(NftFamily::Inet, TABLE_NAME).expect("Failed to get table owner"),
+            Some(owner)
+        );
+
+        // The owner commits through its handle
+        let mut tx = NftTransaction::new();
+        tx.create_set(NftFamily::Inet, TABLE_NAME, "myset", &Default::default())
+            .expect("Failed to queue set creation");
+        tx.add(NftFamily::Inet, TABLE_NAME, "myset", addr)
+            .expect("Failed to queue add");
+        table.commit(tx).expect("Failed to commit as owner");
+
+        // Others can read the table but not change it
+        assert!(nftset_test(NftFamily::Inet, TABLE_NAME, "myset", addr).expect("Failed to test"));
+        assert!(matches!(
+            nftset_add(NftFamily::Inet, TABLE_NAME, "myset", "10.0.0.2".parse::<IpAddr>().unwrap()),
+            Err(RipsetError::TableOwned(name, port)) if name == TABLE_NAME && port == owner
+        ));
+        assert!(matches!(
+            nftset_delete_table(NftFamily::Inet, TABLE_NAME),
+            Err(RipsetError::TableOwned(_, port)) if port == owner
+        ));
+        assert!(matches!(
+            nftset_create_table_owned(NftFamily::Inet, TABLE_NAME),
+            Err(RipsetError::TableOwned(_, port)) if port == owner
+        ));
+
+        // A failed batch doesn't confuse the next one
+        let mut tx = NftTransaction::new();
+        tx.del(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "myset",
+            "10.0.0.9".parse::<IpAddr>().unwrap(),
+        )
+        .expect("Failed to queue delete");
+        tx.flush_set(NftFamily::Inet, TABLE_NAME, "none")
+            .expect("Failed to queue flush");
+        assert!(matches!(table.commit(tx), Err(RipsetError::EntryNotFound)));
+        let mut tx = NftTransaction::new();
+        tx.del(NftFamily::Inet, TABLE_NAME, "myset", addr)
+            .expect("Failed to queue delete");
+        table.commit(tx).expect("Failed to commit after an error");
+
+        // The kernel deletes the table with its owner
+        drop(table);
+        assert!(
+            !nftset_list_tables(NftFamily::Inet)
+                .expect("Failed to list tables")
+                .contains(&TABLE_NAME.to_string())
+        );
+
+        // Tables created without the flag have no owner, and can't get one
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        assert_eq!(
+            nftset_table_owner(NftFamily::Inet, TABLE_NAME).expect("Failed to get table owner"),
+            None
+        );
+        assert!(matches!(
+            nftset_create_table_owned(NftFamily::Inet, TABLE_NAME),
+            Err(RipsetError::EntryExists)
+        ));
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+}
+
+// =====================
+// SetBackend tests
+// =====================
+
+mod backend_tests {
+    use super::*;
+
+    /// Run the same operations through either backend.
+    fn exercise_backend(backend: &dyn SetBackend, set1: &str, set2: &str) {
+        let addr1: IpAddr = "10.0.0.1".parse().unwrap();
+        let addr2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        backend.create(set1).expect("Failed to create set1");
+        backend.create(set2).expect("Failed to create set2");
+
+        backend.add(set1, addr1.into()).expect("Failed to add");
+        backend.add(set2, addr2.into()).expect("Failed to add");
+        assert!(backend.test(set1, addr1.into()).expect("Failed to test"));
+        assert!(!backend.test(set1, addr2.into()).expect("Failed to test"));
+
+        backend.swap(set1, set2).expect("Failed to swap");
+        assert_eq!(
+            backend.list(set1).expect("Failed to list"),
+            vec![Entry::from(addr2)]
+        );
+        assert_eq!(
+            backend.list(set2).expect("Failed to list"),
+            vec![Entry::from(addr1)]
+        );
+
+        backend.del(set1, addr2.into()).expect("Failed to delete");
+        assert!(backend.list(set1).expect("Failed to list").is_empty());
+
+        backend.flush(set2).expect("Failed to flush");
+        assert!(backend.list(set2).expect("Failed to list").is_empty());
+
+        backend.destroy(set1).expect("Failed to destroy set1");
+        backend.destroy(set2).expect("Failed to destroy set2");
+        assert!(backend.list(set1).is_err());
+    }
+
+    /// Sync a set through either backend.
+    fn exercise_sync(backend: &dyn SetBackend, setname: &str) {
+        let addr = |s: &str| -> Entry { s.parse::<IpAddr>().unwrap().into() };
+
+        backend.create(setname).expect("Failed to create set");
+        backend
+            .add(setname, addr("10.0.0.1"))
+            .expect("Failed to add");
+        backend
+            .add(setname, addr("10.0.0.2"))
+            .expect("Failed to add");
+
+        let desired = vec![addr("10.0.0.2"), addr("10.0.0.3"), addr("10.0.0.3")];
+        let diff = diff_set(backend, setname, desired.clone()).expect("Failed to diff");
+        assert_eq!(diff.added, vec![addr("10.0.0.3")]);
+        assert_eq!(diff.removed, vec![addr("10.0.0.1")]);
+        assert_eq!(diff.unchanged, vec![addr("10.0.0.2")]);
+        // Nothing applied yet
+        assert_eq!(backend.list(setname).expect("Failed to list").len(), 2);
+
+        let summary = sync_set(backend, setname, desired).expect("Failed to sync");
+        assert_eq!(summary.added, vec![addr("10.0.0.3")]);
+        assert_eq!(summary.removed, vec![addr("10.0.0.1")]);
+
+        let mut entries = backend.list(setname).expect("Failed to list");
+        entries.sort_by_key(|e| format!("{e:?}"));
+        assert_eq!(entries, vec![addr("10.0.0.2"), addr("10.0.0.3")]);
+
+        // Already in sync
+        let summary = sync_set(backend, setname, vec![addr("10.0.0.3"), addr("10.0.0.2")])
+            .expect("Failed to sync");
+        assert!(summary.is_empty());
+
+        let summary = sync_set(backend, setname, Vec::<Entry>::new()).expect("Failed to sync");
+        assert_eq!(summary.removed.len(), 2);
+        assert!(backend.list(setname).expect("Failed to list").is_empty());
+
+        backend.destroy(setname).expect("Failed to destroy set");
+        assert!(sync_set(backend, setname, vec![addr("10.0.0.1")]).is_err());
+    }
+
+    #[test]
+    fn test_netns() {
+        const NETNS: &str = "lripset_test_netns";
+        const NETNS_PATH: &str = "/var/run/netns/lripset_test_netns";
+        const SET_NAME: &str = "lipsets_test_netns";
+        const TABLE_NAME: &str = "lnftsets_test_netns";
+
+        // Setup
+        let ip_netns = |command: &str| {
+            std::process::Command::new("ip")
+                .args(["netns", command, NETNS])
+                .output()
+                .expect("Failed to run ip netns")
+                .status
+        };
+        ip_netns("delete");
+        assert!(ip_netns("add").success());
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // ipset
+        with_netns(NETNS_PATH, || {
+            ipset_create(SET_NAME, &IpSetCreateOptions::default())
+        })
+        .expect("Failed to create set in netns");
+        assert!(!ipset_exists(SET_NAME).unwrap());
+        let session = IpsetSession::in_netns(NETNS_PATH).expect("Failed to open session");
+        session.add(SET_NAME, addr).expect("Failed to add IP");
+        assert!(session.test(SET_NAME, addr).unwrap());
+        assert_eq!(
+            with_netns(NETNS_PATH, || ipset_list(SET_NAME)).unwrap(),
+            vec![addr]
+        );
+
+        // nftables
+        with_netns(NETNS_PATH, || {
+            nftset_create_table(NftFamily::Inet, TABLE_NAME)?;
+            nftset_create_set(
+                NftFamily::Inet,
+                TABLE_NAME,
+                SET_NAME,
+                &NftSetCreateOptions::default(),
+            )
+        })
+        .expect("Failed to create set in netns");
+        assert!(!nftset_exists(NftFamily::Inet, TABLE_NAME, SET_NAME).unwrap());
+        let session = NftSession::in_netns(NETNS_PATH).expect("Failed to open session");
+        session
+            .add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .expect("Failed to add IP");
+        assert!(
+            session
+                .test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+                .unwrap()
+        );
+        assert_eq!(
+            with_netns(NETNS_PATH, || nftset_list(
+                NftFamily::Inet,
+                TABLE_NAME,
+                SET_NAME
+            ))
+            .unwrap(),
+            vec![addr]
+        );
+
+        assert!(
+            with_netns("/var/run/netns/lripset_test_missing", || ipset_list(
+                SET_NAME
+            ))
+            .is_err()
+        );
+
+        // Cleanup
+        ip_netns("delete");
+    }
+
+    #[test]
+    fn test_detect_backend() {
+        let support = detect_backend();
+        assert!(support.nftables);
+        assert!(support.ipset);
+        assert_eq!(support.recommended(), Some(BackendKind::Nftables));
+    }
+
+    #[test]
+    fn test_backend_capabilities() {
+        let caps = IpsetBackend::new()
+            .capabilities()
+            .expect("Failed to query ipset capabilities");
+        assert!(caps.swap && caps.timeouts && caps.intervals);
+
+        let caps = NftBackend::new(NftFamily::Inet, "filter")
+            .capabilities()
+            .expect("Failed to query nftables capabilities");
+        assert!(caps.swap && caps.timeouts && caps.intervals && caps.mac_keys);
+        assert!(!caps.comments);
+
+        // Probing leaves nothing behind
+        let tables = nftset_list_tables(NftFamily::Inet).expect("Failed to list tables");
+        assert!(!tables.iter().any(|table| table == "ripset_probe"));
+    }
+
+    #[test]
+    fn test_ipset_backend() {
+        const SET1: &str = "lipsets_test_backend1";
+        const SET2: &str = "lipsets_test_backend2";
+
+        // Setup
+        let _ = ipset_destroy(SET1);
+        let _ = ipset_destroy(SET2);
+
+        exercise_backend(&IpsetBackend::new(), SET1, SET2);
+    }
+
+    #[test]
+    fn test_ipset_sync_set() {
+        const SET_NAME: &str = "lipsets_test_sync";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+
+        exercise_sync(&IpsetBackend::new(), SET_NAME);
+    }
+
+    #[test]
+    fn test_dry_run_backend() {
+        const SET_NAME: &str = "lipsets_test_dry_run";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let ipset = IpsetBackend::new();
+        ipset.create(SET_NAME).expect("Failed to create set");
+        ipset
+            .add(SET_NAME, "10.0.5.1".parse::<IpAddr>().unwrap().into())
+            .expect("Failed to add IP");
+
+        // The preview reads the set but leaves it alone
+        let dry_run = DryRunBackend::new(ipset);
+        let desired: Vec<IpAddr> = vec!["10.0.5.2".parse().unwrap()];
+        let summary = sync_set(&dry_run, SET_NAME, desired).expect("Failed to preview");
+        assert_eq!(summary.added.len(), 1);
+        assert_eq!(summary.removed.len(), 1);
+        assert_eq!(
+            dry_run.operations(),
+            vec![
+                SetOperation::Del {
+                    setname: SET_NAME.to_string(),
+                    entry: "10.0.5.1".parse::<IpAddr>().unwrap().into(),
+                },
+                SetOperation::Add {
+                    setname: SET_NAME.to_string(),
+                    entry: "10.0.5.2".parse::<IpAddr>().unwrap().into(),
+                },
+            ]
+        );
+        assert_eq!(
+            dry_run.commands(),
+            vec![
+                format!("ipset del {SET_NAME} 10.0.5.1"),
+                format!("ipset add {SET_NAME} 10.0.5.2"),
+            ]
+        );
+        assert_eq!(
+            ipset_list(SET_NAME).unwrap(),
+            vec!["10.0.5.1".parse::<IpAddr>().unwrap()]
+        );
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_copy_set() {
+        const SET1: &str = "lipsets_test_copy1";
+        const SET2: &str = "lipsets_test_copy2";
+        const TABLE_NAME: &str = "lnftsets_test_copy";
+
+        // Setup
+        let _ = ipset_destroy(SET1);
+        let _ = ipset_destroy(SET2);
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+
+        let ipset = IpsetBackend::new();
+        let nft = NftBackend::new(NftFamily::Inet, TABLE_NAME);
+        // Enough entries to be copied in several chunks
+        let addrs: Vec<Entry> = (0..3000u32)
+            .map(|i| IpAddr::V4((0x0a00_0100 + i).into()).into())
+            .collect();
+        ipset.create(SET1).expect("Failed to create ipset");
+        ipset.create(SET2).expect("Failed to create ipset");
+        nft.create("set1").expect("Failed to create nft set");
+        ipset
+            .add_many(SET1, addrs.clone())
+            .expect("Failed to add entries");
+
+        // ipset to nftables and back
+        assert_eq!(
+            copy_set(&ipset, SET1, &nft, "set1").expect("Failed to copy"),
+            3000
+        );
+        assert_eq!(nft.list("set1").expect("Failed to list").len(), 3000);
+        assert_eq!(
+            copy_set(&nft, "set1", &ipset, SET2).expect("Failed to copy"),
+            3000
+        );
+        assert!(
+            diff_set(&ipset, SET2, addrs)
+                .expect("Failed to diff")
+                .is_empty()
+        );
+
+        // Copying again keeps the existing entries
+        assert_eq!(
+            copy_set(&ipset, SET1, &ipset, SET2).expect("Failed to copy"),
+            3000
+        );
+        assert!(copy_set(&ipset, "lipsets_test_copy_missing", &ipset, SET2).is_err());
+
+        // Cleanup
+        let _ = ipset_destroy(SET1);
+        let _ = ipset_destroy(SET2);
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_migrate_ipset_to_nft() {
+        const SET1: &str = "lipsets_test_migrate1";
+        const SET2: &str = "lipsets_test_migrate2";
+        const SET3: &str = "lipsets_test_migrate3";
+        const TABLE_NAME: &str = "lnftsets_test_migrate";
+
+        // Setup
+        for set in [SET1, SET2, SET3] {
+            let _ = ipset_destroy(set);
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+
+        // Addresses with timeouts and counters
+        let opts = IpSetCreateOptions {
+            timeout: Some(600),
+            counters: true,
+            ..Default::default()
+        };
+        ipset_create(SET1, &opts).expect("Failed to create ipset");
+        for i in 1..=10 {
+            let addr: IpAddr = format!("10.0.2.{i}").parse().unwrap();
+            ipset_add(SET1, addr).expect("Failed to add");
+        }
+        assert_eq!(
+            migrate_ipset_to_nft(SET1, NftFamily::Inet, TABLE_NAME).expect("Failed to migrate"),
+            10
+        );
+        let info = nftset_info(NftFamily::Inet, TABLE_NAME, SET1).expect("Failed to get info");
+        assert_eq!(info.set_type, Some(NftSetType::Ipv4Addr));
+        assert_eq!(info.timeout, Some(600));
+        let entries =
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET1).expect("Failed to list");
+        assert_eq!(entries.len(), 10);
+        assert!(
+            entries
+                .iter()
+                .all(|e| matches!(e, Entry::Ip(ip) if ip.timeout.is_some()))
+        );
+
+        // Migrating again merges into the existing set
+        assert_eq!(
+            migrate_ipset_to_nft(SET1, NftFamily::Inet, TABLE_NAME).expect("Failed to migrate"),
+            10
+        );
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET1)
+                .expect("Failed to list")
+                .len(),
+            10
+        );
+
+        // Address and port pairs become a concatenation
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashIpPort,
+            timeout: Some(600),
+            ..Default::default()
+        };
+        ipset_create(SET2, &opts).expect("Failed to create ipset");
+        let mut entry = IpEntry::new("10.0.2.1".parse().unwrap());
+        entry.port = Some(443);
+        ipset_add(SET2, entry).expect("Failed to add");
+        migrate_ipset_to_nft(SET2, NftFamily::Inet, TABLE_NAME).expect("Failed to migrate");
+        let addr: IpAddr = "10.0.2.1".parse().unwrap();
+        assert!(
+            nftset_test(
+                NftFamily::Inet,
+                TABLE_NAME,
+                SET2,
+                Entry::Concat(vec![addr.into(), 443u16.into()])
+            )
+            .expect("Failed to test")
+        );
+        // The timeout is kept in the leading address
+        let entries =
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, SET2).expect("Failed to list");
+        assert!(matches!(
+            &entries[..],
+            [Entry::Concat(parts)] if matches!(&parts[0], Entry::Ip(ip) if ip.timeout.is_some())
+        ));
+
+        // Set types nftables has no equivalent for
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::ListSet,
+            ..Default::default()
+        };
+        ipset_create(SET3, &opts).expect("Failed to create ipset");
+        assert!(matches!(
+            migrate_ipset_to_nft(SET3, NftFamily::Inet, TABLE_NAME),
+            Err(RipsetError::InvalidOption(_))
+        ));
+        assert!(!nftset_exists(NftFamily::Inet, TABLE_NAME, SET3).expect("Failed to check"));
+
+        // Cleanup
+        for set in [SET1, SET2, SET3] {
+            let _ = ipset_destroy(set);
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nft_sync_set() {
+        const TABLE_NAME: &str = "lnftsets_test_sync";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+
+        exercise_sync(&NftBackend::new(NftFamily::Inet, TABLE_NAME), "set1");
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_nft_backend() {
+        const TABLE_NAME: &str = "lnftsets_test_backend";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+
+        exercise_backend(
+            &NftBackend::new(NftFamily::Inet, TABLE_NAME),
+            "set1",
+            "set2",
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        const SET_A: &str = "lipsets_test_algebra_a";
+        const SET_B: &str = "lipsets_test_algebra_b";
+        const DST: &str = "lipsets_test_algebra_dst";
+        const TABLE_NAME: &str = "lnftsets_test_algebra";
+
+        // Setup
+        for set in [SET_A, SET_B, DST] {
+            let _ = ipset_destroy(set);
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+
+        let ipset = IpsetBackend::new();
+        let nft =
+            NftBackend::new(NftFamily::Inet, TABLE_NAME).with_create_options(NftSetCreateOptions {
+                interval: true,
+                ..Default::default()
+            });
+        let addr = |s: &str| Entry::from(s.parse::<IpAddr>().unwrap());
+        for set in [SET_A, SET_B, DST] {
+            ipset.create(set).expect("Failed to create ipset");
+        }
+        ipset
+            .add_many(
+                SET_A,
+                vec![addr("10.0.0.1"), addr("10.0.0.2"), addr("10.0.0.3")],
+            )
+            .expect("Failed to add");
+        ipset
+            .add_many(SET_B, vec![addr("10.0.0.3"), addr("10.0.0.4")])
+            .expect("Failed to add");
+        nft.create("b").expect("Failed to create nft set");
+        nft.add(
+            "b",
+            IpEntry::with_cidr("10.0.0.2".parse().unwrap(), 31).into(),
+        )
+        .expect("Failed to add");
+
+        let sorted = |backend: &dyn SetBackend, set: &str| {
+            let mut entries: Vec<String> = backend
+                .list(set)
+                .expect("Failed to list")
+                .iter()
+                .map(Entry::to_string)
+                .collect();
+            entries.sort();
+            entries
+        };
+
+        set_union(&ipset, SET_A, &ipset, SET_B, &ipset, DST).expect("Failed to unite");
+        assert_eq!(
+            sorted(&ipset, DST),
+            ["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.4"]
+        );
+
+        // Addresses covered by the network in the nft set are in both
+        let summary =
+            set_intersection(&ipset, SET_A, &nft, "b", &ipset, DST).expect("Failed to intersect");
+        assert_eq!(summary.removed.len(), 2);
+        assert_eq!(sorted(&ipset, DST), ["10.0.0.2", "10.0.0.3"]);
+
+        // The destination may be one of the operands
+        set_difference(&ipset, SET_A, &nft, "b", &ipset, SET_A).expect("Failed to subtract");
+        assert_eq!(sorted(&ipset, SET_A), ["10.0.0.1"]);
+        // Also when named through another backend
+        let summary = set_union(&ipset, SET_A, &ipset, SET_B, &IpsetBackend::new(), SET_A)
+            .expect("Failed to unite");
+        assert_eq!(summary.added.len(), 2);
+        assert_eq!(sorted(&ipset, SET_A), ["10.0.0.1", "10.0.0.3", "10.0.0.4"]);
+
+        // Cleanup
+        for set in [SET_A, SET_B, DST] {
+            let _ = ipset_destroy(set);
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_dual_stack_set() {
+        const BASE: &str = "lipsets_test_dual";
+        const TABLE_NAME: &str = "lnftsets_test_dual";
+
+        fn exercise<B: SetBackend>(set: &DualStackSet<B>) {
+            let v4: IpAddr = "192.0.2.1".parse().unwrap();
+            let v6: IpAddr = "2001:db8::1".parse().unwrap();
+            set.create().expect("Failed to create sets");
+            set.add(v4).expect("Failed to add");
+            set.add_many([v6]).expect("Failed to add");
+            assert!(set.test(v4).expect("Failed to test"));
+            assert!(set.test(v6).expect("Failed to test"));
+            assert_eq!(set.list().expect("Failed to list").len(), 2);
+
+            let (backend, name) = set.v6();
+            assert_eq!(backend.list(name).expect("Failed to list").len(), 1);
+
+            let other: IpAddr = "2001:db8::2".parse().unwrap();
+            let summary = set.sync([v4, other]).expect("Failed to sync");
+            assert_eq!((summary.added.len(), summary.removed.len()), (1, 1));
+            assert!(!set.test(v6).expect("Failed to test"));
+
+            set.del(v4).expect("Failed to delete");
+            assert!(!set.test(v4).expect("Failed to test"));
+            set.destroy().expect("Failed to destroy sets");
+        }
+
+        // Setup
+        for suffix in ["_v4", "_v6"] {
+            let _ = ipset_destroy(&format!("{BASE}{suffix}"));
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+
+        exercise(&DualStackSet::ipset(BASE, IpSetCreateOptions::default()));
+
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let options = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        let nft = DualStackSet::nft(NftFamily::Inet, TABLE_NAME, "dual", options);
+        exercise(&nft);
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_find_ip() {
+        const NET_SET: &str = "lipsets_test_find_net";
+        const IP_SET: &str = "lipsets_test_find_ip";
+        const BITMAP_SET: &str = "lipsets_test_find_bitmap";
+        const TABLE_NAME: &str = "lnftsets_test_find";
+
+        // Setup
+        for set in [NET_SET, IP_SET, BITMAP_SET] {
+            let _ = ipset_destroy(set);
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+
+        let addr: IpAddr = "198.51.100.77".parse().unwrap();
+        let net = IpEntry::with_cidr("198.51.100.0".parse().unwrap(), 24);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(NET_SET, &opts).expect("Failed to create ipset");
+        ipset_add(NET_SET, net.clone()).expect("Failed to add");
+        ipset_create(IP_SET, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+        ipset_add(IP_SET, "198.51.100.78".parse::<IpAddr>().unwrap()).expect("Failed to add");
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::BitmapIp,
+            range: Some(IpSetRange::Cidr("198.51.100.0".parse().unwrap(), 24)),
+            ..Default::default()
+        };
+        ipset_create(BITMAP_SET, &opts).expect("Failed to create ipset");
+        ipset_add(BITMAP_SET, addr).expect("Failed to add");
+
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "nets", &opts).expect("Failed to create");
+        nftset_add(NftFamily::Inet, TABLE_NAME, "nets", net).expect("Failed to add");
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "other", &Default::default())
+            .expect("Failed to create");
+
+        // Other tests may run at the same time, so only our sets are checked
+        let found = find_ip(addr, true).expect("Failed to search");
+        assert!(found.contains(&SetRef::ipset(NET_SET)));
+        assert!(found.contains(&SetRef::ipset(BITMAP_SET)));
+        assert!(!found.contains(&SetRef::ipset(IP_SET)));
+        assert!(found.contains(&SetRef::nft(NftFamily::Inet, TABLE_NAME, "nets")));
+        assert!(!found.contains(&SetRef::nft(NftFamily::Inet, TABLE_NAME, "other")));
+
+        let found = find_ip(addr, false).expect("Failed to search");
+        assert!(found.contains(&SetRef::ipset(NET_SET)));
+        assert!(found.iter().all(|set| matches!(set, SetRef::Ipset(_))));
+
+        // Cleanup
+        for set in [NET_SET, IP_SET, BITMAP_SET] {
+            let _ = ipset_destroy(set);
+        }
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_set_stats() {
+        const SET_NAME: &str = "lipsets_test_stats";
+        const TABLE_NAME: &str = "lnftsets_test_stats";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+
+        let opts = IpSetCreateOptions {
+            maxelem: Some(1000),
+            counters: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+        for (addr, packets) in [("10.0.0.1", 5), ("10.0.0.2", 7)] {
+            let mut entry = IpEntry::new(addr.parse().unwrap());
+            entry.packets = Some(packets);
+            entry.bytes = Some(packets * 100);
+            ipset_add(SET_NAME, entry).expect("Failed to add");
+        }
+
+        let stats = set_stats(&SetRef::ipset(SET_NAME)).expect("Failed to get stats");
+        assert_eq!(stats.entries, 2);
+        assert_eq!(stats.max_entries, Some(1000));
+        assert!(stats.memsize.is_some_and(|memsize| memsize > 0));
+        assert_eq!(stats.packets, Some(12));
+        assert_eq!(stats.bytes, Some(1200));
+
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            size: Some(100),
+            counters: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "set1", &opts).expect("Failed to create");
+        nftset_add(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "set1",
+            "10.0.0.1".parse::<IpAddr>().unwrap(),
+        )
+        .expect("Failed to add");
+
+        let set = SetRef::nft(NftFamily::Inet, TABLE_NAME, "set1");
+        let stats = set_stats(&set).expect("Failed to get stats");
+        assert_eq!(
+            stats,
+            SetStats {
+                entries: 1,
+                max_entries: Some(100),
+                memsize: None,
+                packets: Some(0),
+                bytes: Some(0),
+            }
+        );
+
+        // Sets without counters
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "set2", &Default::default())
+            .expect("Failed to create");
+        let stats = set_stats(&SetRef::nft(NftFamily::Inet, TABLE_NAME, "set2"))
+            .expect("Failed to get stats");
+        assert_eq!((stats.entries, stats.packets), (0, None));
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+
+    #[test]
+    fn test_ipset_snapshot_restore() {
+        const SET_NAME: &str = "lipsets_test_snapshot";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            maxelem: Some(1000),
+            comment: true,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+        let mut net = IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 24);
+        net.comment = Some("office".to_string());
+        ipset_add(SET_NAME, net.clone()).expect("Failed to add entry");
+
+        let backup = snapshot(&SetRef::ipset(SET_NAME)).expect("Failed to take snapshot");
+        assert_eq!(backup.set(), SetRef::ipset(SET_NAME));
+        assert_eq!(backup.len(), 1);
+
+        // Changes are rolled back
+        ipset_del(SET_NAME, net.clone()).expect("Failed to delete entry");
+        ipset_add(SET_NAME, "10.1.0.1".parse::<IpAddr>().unwrap()).expect("Failed to add IP");
+        restore(&backup).expect("Failed to restore");
+        assert_eq!(
+            ipset_list_entries(SET_NAME).expect("Failed to list"),
+            vec![Entry::Ip(net.clone())]
+        );
+
+        // A destroyed set is created again with its options
+        ipset_destroy(SET_NAME).expect("Failed to destroy");
+        restore(&backup).expect("Failed to restore");
+        let info = ipset_info(SET_NAME).expect("Failed to query info");
+        assert_eq!(info.type_name, "hash:net");
+        assert_eq!(info.maxelem, Some(1000));
+        assert_eq!(info.entries, 1);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_nftset_snapshot_restore() {
+        const TABLE_NAME: &str = "lnftsets_test_snapshot";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            comment: Some("snapshot test".to_string()),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "set", &opts).expect("Failed to create set");
+        let net = IpEntry::with_cidr("10.0.0.0".parse().unwrap(), 24);
+        nftset_add(NftFamily::Inet, TABLE_NAME, "set", net.clone()).expect("Failed to add");
+        let opts = NftSetCreateOptions {
+            data_type: Some(NftSetType::Mark),
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "map", &opts).expect("Failed to create map");
+        let addr: IpAddr = "10.0.0.1".parse().unwrap();
+        nftset_map_add(NftFamily::Inet, TABLE_NAME, "map", addr, Entry::Mark(7))
+            .expect("Failed to add map element");
+
+        let set_backup = snapshot(&SetRef::nft(NftFamily::Inet, TABLE_NAME, "set"))
+            .expect("Failed to take snapshot");
+        let map_backup = snapshot(&SetRef::nft(NftFamily::Inet, TABLE_NAME, "map"))
+            .expect("Failed to take snapshot");
+
+        // Changes are rolled back
+        nftset_flush(NftFamily::Inet, TABLE_NAME, "set").expect("Failed to flush");
+        let other: IpAddr = "10.1.0.1".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, "set", other).expect("Failed to add");
+        restore(&set_backup).expect("Failed to restore");
+        assert_eq!(
+            nftset_list_entries(NftFamily::Inet, TABLE_NAME, "set").expect("Failed to list"),
+            vec![Entry::Ip(net)]
+        );
+
+        // A deleted table and map are created again
+        nftset_delete_table(NftFamily::Inet, TABLE_NAME).expect("Failed to delete table");
+        restore(&map_backup).expect("Failed to restore");
+        assert_eq!(
+            nftset_map_list(NftFamily::Inet, TABLE_NAME, "map").expect("Failed to list"),
+            vec![(Entry::from(addr), Entry::Mark(7))]
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+}
+
+// =====================
+// async tests
+// =====================
+
+#[cfg(feature = "tokio")]
+mod aio_tests {
+    use super::*;
+    use ripset::{
+        AsyncIpsetSession, AsyncNftMonitor, AsyncNftSession, ipset_add_async, ipset_create_async,
+        ipset_destroy_async, ipset_list_async, ipset_test_async, nftset_create_set_async,
+        nftset_create_table_async, nftset_delete_table_async, nftset_list_async,
+    };
+
+    #[tokio::test]
+    async fn test_ipset_async() {
+        const SET_NAME: &str = "lipsets_test_async";
+
+        // Setup
+        let _ = ipset_destroy_async(SET_NAME).await;
+        ipset_create_async(SET_NAME, &IpSetCreateOptions::default())
+            .await
+            .expect("Failed to create set");
+
+        let addr: IpAddr = "10.0.3.1".parse().unwrap();
+        ipset_add_async(SET_NAME, addr)
+            .await
+            .expect("Failed to add IP");
+        assert!(ipset_test_async(SET_NAME, addr).await.unwrap());
+        assert!(matches!(
+            ipset_add_async(SET_NAME, addr).await,
+            Err(RipsetError::EntryExists)
+        ));
+
+        let session = AsyncIpsetSession::new().expect("Failed to open session");
+        let addrs: Vec<IpAddr> = (2..=5)
+            .map(|i| format!("10.0.3.{i}").parse().unwrap())
+            .collect();
+        session
+            .add_many(SET_NAME, addrs.clone())
+            .await
+            .expect("Failed to add IPs");
+        // Operations can run concurrently on clones of a session
+        let (found, missing) = tokio::join!(
+            session.clone().test(SET_NAME, addrs[0]),
+            session.test(SET_NAME, "10.0.3.9".parse::<IpAddr>().unwrap())
+        );
+        assert!(found.unwrap());
+        assert!(!missing.unwrap());
+        session
+            .del(SET_NAME, addr)
+            .await
+            .expect("Failed to delete IP");
+
+        let mut ips = ipset_list_async(SET_NAME).await.expect("Failed to list");
+        ips.sort();
+        assert_eq!(ips, addrs);
+
+        // Cleanup
+        let _ = ipset_destroy_async(SET_NAME).await;
+    }
+
+    #[test]
+    fn test_async_outside_runtime() {
+        use std::future::Future;
+        use std::pin::pin;
+        use std::task::{Context, Poll, Waker};
+
+        let mut op = pin!(ipset_test_async(
+            "lipsets_test_async",
+            "10.0.3.1".parse::<IpAddr>().unwrap()
+        ));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert!(matches!(
+            op.as_mut().poll(&mut cx),
+            Poll::Ready(Err(RipsetError::SocketError(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_nftset_async() {
+        const TABLE_NAME: &str = "lnftsets_test_async";
+        const SET_NAME: &str = "test_set";
+
+        // Setup
+        let _ = nftset_delete_table_async(NftFamily::Inet, TABLE_NAME).await;
+        nftset_create_table_async(NftFamily::Inet, TABLE_NAME)
+            .await
+            .expect("Failed to create table");
+        nftset_create_set_async(
+            NftFamily::Inet,
+            TABLE_NAME,
+            SET_NAME,
+            &NftSetCreateOptions::default(),
+        )
+        .await
+        .expect("Failed to create set");
+
+        let session = AsyncNftSession::new().expect("Failed to open session");
+        let addr: IpAddr = "10.0.3.1".parse().unwrap();
+        session
+            .add(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+            .await
+            .expect("Failed to add IP");
+        assert!(
+            session
+                .test(NftFamily::Inet, TABLE_NAME, SET_NAME, addr)
+                .await
+                .unwrap()
+        );
+
+        let mut tx = NftTransaction::new();
+        tx.flush_set(NftFamily::Inet, TABLE_NAME, SET_NAME)
+            .expect("Failed to queue flush");
+        session.commit(tx).await.expect("Failed to commit");
+        assert!(
+            nftset_list_async(NftFamily::Inet, TABLE_NAME, SET_NAME)
+                .await
+                .expect("Failed to list")
+                .is_empty()
+        );
+
+        // Cleanup
+        let _ = nftset_delete_table_async(NftFamily::Inet, TABLE_NAME).await;
+    }
+
+    #[tokio::test]
+    async fn test_nftset_monitor_async() {
+        const TABLE_NAME: &str = "lnftsets_test_monitor_async";
+
+        // Setup
+        let _ = nftset_delete_table_async(NftFamily::Inet, TABLE_NAME).await;
+        let mut monitor = AsyncNftMonitor::new().expect("Failed to subscribe");
+
+        nftset_create_table_async(NftFamily::Inet, TABLE_NAME)
+            .await
+            .expect("Failed to create table");
+        nftset_create_set_async(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "hosts",
+            &NftSetCreateOptions::default(),
+        )
+        .await
+        .expect("Failed to create set");
+        let addr: IpAddr = "10.0.4.2".parse().unwrap();
+        AsyncNftSession::new()
+            .expect("Failed to open session")
+            .add(NftFamily::Inet, TABLE_NAME, "hosts", addr)
+            .await
+            .expect("Failed to add IP");
+
+        // Other tests change nftables at the same time
+        let added = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match monitor.next_event().await.expect("Failed to monitor") {
+                    NftEvent::ElementAdded { table, entry, .. } if table == TABLE_NAME => {
+                        return entry;
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .expect("No event");
+        assert_eq!(added, Entry::from(addr));
+
+        // Cleanup
+        let _ = nftset_delete_table_async(NftFamily::Inet, TABLE_NAME).await;
+    }
+}
+
+// =====================
+// serde tests
+// =====================
+
+#[cfg(feature = "serde")]
+mod serde_tests {
+    use super::*;
+
+    #[test]
+    fn test_serde_ipset_from_config() {
+        const SET_NAME: &str = "lipsets_test_serde";
+
+        let opts: IpSetCreateOptions = serde_json::from_str(
+            r#"{"set_type": "hash:net", "family": "inet6", "timeout": 300, "comment": true}"#,
+        )
+        .expect("Failed to parse options");
+        assert_eq!(opts.set_type, IpSetType::HashNet);
+        assert_eq!(opts.family, IpSetFamily::Inet6);
+        assert_eq!(opts.timeout, Some(300));
+        assert!(opts.comment && !opts.counters);
+
+        let entry: IpEntry =
+            serde_json::from_str(r#"{"addr": "2001:db8::", "cidr": 32, "comment": "lab"}"#)
+                .expect("Failed to parse entry");
+        assert_eq!(
+            entry,
+            IpEntry {
+                cidr: Some(32),
+                comment: Some("lab".to_string()),
+                ..IpEntry::new("2001:db8::".parse().unwrap())
+            }
+        );
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+        ipset_add(SET_NAME, entry).expect("Failed to add entry");
+
+        // Listed entries and set headers survive a round trip
+        let entries = ipset_list_entries(SET_NAME).expect("Failed to list entries");
+        let json = serde_json::to_string(&entries).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<Entry>>(&json).unwrap(), entries);
+        let info = ipset_info(SET_NAME).expect("Failed to query ipset info");
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["family"], "inet6");
+        assert_eq!(
+            serde_json::from_value::<ripset::IpSetInfo>(json).unwrap(),
+            info
+        );
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_serde_nftset_from_config() {
+        let opts: NftSetCreateOptions = serde_json::from_str(
+            r#"{"set_type": {"concat": ["ipv4_addr", "inet_service"]}, "policy": "memory"}"#,
+        )
+        .expect("Failed to parse options");
+        assert_eq!(
+            opts.set_type,
+            NftSetType::Concat(vec![NftSetType::Ipv4Addr, NftSetType::InetService])
+        );
+        assert_eq!(opts.policy, Some(NftSetPolicy::Memory));
+        assert!(!opts.interval && opts.timeout.is_none());
+
+        let entry = Entry::Concat(vec![
+            Entry::Mac("00:11:22:33:44:55".parse().unwrap()),
+            Entry::Verdict(NftVerdict::Jump("allow".to_string())),
+        ]);
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(
+            json,
+            r#"{"concat":[{"mac":"00:11:22:33:44:55"},{"verdict":{"jump":"allow"}}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Entry>(&json).unwrap(), entry);
+        assert_eq!(
+            serde_json::from_str::<NftFamily>(r#""bridge""#).unwrap(),
+            NftFamily::Bridge
+        );
+        assert!(serde_json::from_str::<MacAddr>(r#""00:11""#).is_err());
+        assert!(serde_json::from_str::<SetName>(r#""block list""#).is_err());
+    }
+}
+
+// =====================
+// ipnet tests
+// =====================
+
+#[cfg(feature = "ipnet")]
+mod ipnet_tests {
+    use super::*;
+    use ipnet::{IpNet, Ipv4Net};
+    use ripset::{ipset_list_nets, nftset_list_nets};
+
+    #[test]
+    fn test_ipset_ipnet() {
+        const SET_NAME: &str = "lipsets_test_ipnet";
+
+        // Setup
+        let _ = ipset_destroy(SET_NAME);
+        let opts = IpSetCreateOptions {
+            set_type: IpSetType::HashNet,
+            ..Default::default()
+        };
+        ipset_create(SET_NAME, &opts).expect("Failed to create ipset");
+
+        let net: Ipv4Net = "10.8.0.0/16".parse().unwrap();
+        ipset_add(SET_NAME, net).expect("Failed to add network");
+        ipset_add(SET_NAME, "10.9.0.1/32".parse::<IpNet>().unwrap()).expect("Failed to add host");
+        assert!(ipset_test(SET_NAME, net).unwrap());
+        assert!(ipset_test(SET_NAME, "10.8.1.1".parse::<IpAddr>().unwrap()).unwrap());
+
+        let mut nets = ipset_list_nets(SET_NAME).expect("Failed to list networks");
+        nets.sort();
+        assert_eq!(nets, vec![IpNet::V4(net), "10.9.0.1/32".parse().unwrap()]);
+
+        ipset_del(SET_NAME, net).expect("Failed to delete network");
+        assert_eq!(ipset_list_nets(SET_NAME).unwrap().len(), 1);
+
+        // Cleanup
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_nftset_ipnet() {
+        const TABLE_NAME: &str = "test_ipnet_table";
+
+        // Setup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        let opts = NftSetCreateOptions {
+            interval: true,
+            ..Default::default()
+        };
+        nftset_create_set(NftFamily::Inet, TABLE_NAME, "nets", &opts)
+            .expect("Failed to create set");
+
+        let net: Ipv4Net = "192.168.0.0/24".parse().unwrap();
+        nftset_add(NftFamily::Inet, TABLE_NAME, "nets", net).expect("Failed to add network");
+        let range = IpEntry::with_range("10.0.0.0".parse().unwrap(), "10.0.2.255".parse().unwrap());
+        nftset_add(NftFamily::Inet, TABLE_NAME, "nets", range).expect("Failed to add range");
+        assert!(
+            nftset_test(
+                NftFamily::Inet,
+                TABLE_NAME,
+                "nets",
+                "192.168.0.7".parse::<IpAddr>().unwrap()
+            )
+            .unwrap()
+        );
+
+        let mut nets =
+            nftset_list_nets(NftFamily::Inet, TABLE_NAME, "nets").expect("Failed to list networks");
+        nets.sort();
+        let expected: Vec<IpNet> = ["10.0.0.0/23", "10.0.2.0/24", "192.168.0.0/24"]
+            .iter()
+            .map(|net| net.parse().unwrap())
+            .collect();
+        assert_eq!(nets, expected);
+
+        // Cleanup
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+    }
+}
+
+#[cfg(feature = "ffi")]
+mod ffi_tests {
+    use super::*;
+    use ripset::ffi::*;
+    use std::ffi::{CStr, c_char, c_void};
+
+    unsafe extern "C" fn collect(entry: *const c_char, ctx: *mut c_void) {
+        let entries = unsafe { &mut *(ctx as *mut Vec<String>) };
+        entries.push(
+            unsafe { CStr::from_ptr(entry) }
+                .to_str()
+                .unwrap()
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn test_ffi_ipset() {
+        let set = c"lipsets_test_ffi";
+        let _ = ipset_destroy("lipsets_test_ffi");
+
+        unsafe {
+            assert_eq!(ripset_ipset_create(set.as_ptr(), c"hash:net".as_ptr()), 0);
+            assert_eq!(ripset_ipset_add(set.as_ptr(), c"10.1.0.0/16".as_ptr()), 0);
+            assert_eq!(ripset_ipset_add(set.as_ptr(), c"10.2.0.1".as_ptr()), 0);
+            assert_eq!(
+                ripset_ipset_add(set.as_ptr(), c"10.2.0.1".as_ptr()),
+                RipsetErrorCode::EntryExists as i32
+            );
+            assert_eq!(ripset_ipset_test(set.as_ptr(), c"10.1.2.3".as_ptr()), 1);
+            assert_eq!(ripset_ipset_test(set.as_ptr(), c"10.3.0.1".as_ptr()), 0);
+
+            let mut entries: Vec<String> = Vec::new();
+            let ctx = &mut entries as *mut Vec<String> as *mut c_void;
+            assert_eq!(ripset_ipset_list(set.as_ptr(), Some(collect), ctx), 2);
+            entries.sort();
+            assert_eq!(entries, ["10.1.0.0/16", "10.2.0.1/32"]);
+
+            assert_eq!(ripset_ipset_del(set.as_ptr(), c"10.2.0.1".as_ptr()), 0);
+            assert_eq!(ripset_ipset_destroy(set.as_ptr()), 0);
+            assert_eq!(
+                ripset_ipset_flush(set.as_ptr()),
+                RipsetErrorCode::SetNotFound as i32
+            );
+            assert!(!ripset_last_error().is_null());
+        }
+    }
+
+    #[test]
+    fn test_ffi_nftset() {
+        const TABLE_NAME: &str = "test_ffi_table";
+        let (family, table, set) = (c"inet", c"test_ffi_table", c"nets");
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+
+        unsafe {
+            assert_eq!(ripset_nft_create_table(family.as_ptr(), table.as_ptr()), 0);
+            let code = ripset_nft_create_set(
+                family.as_ptr(),
+                table.as_ptr(),
+                set.as_ptr(),
+                c"ipv4_addr".as_ptr(),
+                RIPSET_NFT_INTERVAL | RIPSET_NFT_AUTO_MERGE,
+                0,
+            );
+            assert_eq!(code, 0);
+            for entry in [c"192.168.0.0/24", c"10.0.0.1"] {
+                let code = ripset_nft_add(
+                    family.as_ptr(),
+                    table.as_ptr(),
+                    set.as_ptr(),
+                    entry.as_ptr(),
+                );
+                assert_eq!(code, 0);
+            }
+            let test = |entry: &CStr| {
+                ripset_nft_test(
+                    family.as_ptr(),
+                    table.as_ptr(),
+                    set.as_ptr(),
+                    entry.as_ptr(),
+                )
+            };
+            assert_eq!(test(c"192.168.0.9"), 1);
+            assert_eq!(test(c"10.0.0.2"), 0);
+
+            let mut entries: Vec<String> = Vec::new();
+            let ctx = &mut entries as *mut Vec<String> as *mut c_void;
+            let count = ripset_nft_list(
+                family.as_ptr(),
+                table.as_ptr(),
+                set.as_ptr(),
+                Some(collect),
+                ctx,
+            );
+            assert_eq!(count, 2);
+
+            let code = ripset_nft_del(
+                family.as_ptr(),
+                table.as_ptr(),
+                set.as_ptr(),
+                c"10.0.0.1".as_ptr(),
+            );
+            assert_eq!(code, 0);
+            assert_eq!(
+                ripset_nft_flush(family.as_ptr(), table.as_ptr(), set.as_ptr()),
+                0
+            );
+            assert_eq!(
+                ripset_nft_delete_set(family.as_ptr(), table.as_ptr(), set.as_ptr()),
+                0
+            );
+            assert_eq!(ripset_nft_delete_table(family.as_ptr(), table.as_ptr()), 0);
+        }
+    }
+}
+
+// =====================
+// Allocation tests
+// =====================
+
+mod alloc_tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    /// The system allocator, counting the allocations of each thread.
+    struct Counting;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+
+    /// Number of allocations `op` makes on this thread.
+    fn allocations(op: impl FnOnce()) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        op();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn test_ipset_session_no_allocations() {
+        const SET_NAME: &str = "lipsets_test_no_alloc";
+
+        let _ = ipset_destroy(SET_NAME);
+        ipset_create(SET_NAME, &IpSetCreateOptions::default()).expect("Failed to create ipset");
+        let session = IpsetSession::new().unwrap();
+        let addr: IpAddr = "10.9.0.1".parse().unwrap();
+
+        // The first requests set up the thread's buffer and the protocol
+        session.add(SET_NAME, addr).unwrap();
+        session.del(SET_NAME, addr).unwrap();
+
+        let count = allocations(|| {
+            for _ in 0..100 {
+                session.add(SET_NAME, addr).unwrap();
+                assert!(session.test(SET_NAME, addr).unwrap());
+                session.del(SET_NAME, addr).unwrap();
+            }
+        });
+        assert_eq!(count, 0);
+        let count = allocations(|| {
+            ipset_add(SET_NAME, addr).unwrap();
+            ipset_del(SET_NAME, addr).unwrap();
+        });
+        assert_eq!(count, 0);
+
+        let _ = ipset_destroy(SET_NAME);
+    }
+
+    #[test]
+    fn test_nftset_session_allocations() {
+        const TABLE_NAME: &str = "lnftsets_test_alloc";
+
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
+        nftset_create_table(NftFamily::Inet, TABLE_NAME).expect("Failed to create table");
+        nftset_create_set(
+            NftFamily::Inet,
+            TABLE_NAME,
+            "hosts",
+            &NftSetCreateOptions::default(),
+        )
+        .expect("Failed to create set");
+        let session = NftSession::new().unwrap();
+        let addr: IpAddr = "10.9.0.1".parse().unwrap();
+        session
+            .add(NftFamily::Inet, TABLE_NAME, "hosts", addr)
+            .unwrap();
+        session
+            .del(NftFamily::Inet, TABLE_NAME, "hosts", addr)
+            .unwrap();
+
+        let count = allocations(|| {
+            session
+                .add(NftFamily::Inet, TABLE_NAME, "hosts", addr)
+                .unwrap();
+            session
+                .del(NftFamily::Inet, TABLE_NAME, "hosts", addr)
+                .unwrap();
+        });
+        // Only the elements built from the entries are allocated
+        assert!(count <= 4, "{count} allocations");
+
+        let _ = nftset_delete_table(NftFamily::Inet, TABLE_NAME);
     }
 }